@@ -20,8 +20,30 @@ typedef struct ytflow_database ytflow_database;
 namespace ytflow_core {
 #endif // __cplusplus
 
+/**
+ * Version of the C ABI exposed by this library, bumped whenever a function signature or the
+ * layout of a buffer it takes or returns changes in an incompatible way. Most `ytflow_app_*`
+ * functions exchange CBOR buffers, but some do not: `ytflow_app_cbor_from_json` takes JSON,
+ * `ytflow_app_cidr_list_compile` takes plain text and `ytflow_app_share_link_encode_qr*`
+ * return luma bitmaps.
+ */
+#define YTFLOW_APP_ABI_VERSION 1
+
 typedef struct ytflow_runtime ytflow_runtime;
 
+/**
+ * Called with each CBOR-encoded item during a streamed profile export. The buffer is
+ * only valid during the call. Return `false` to abort the export.
+ */
+typedef bool (*ytflow_profile_export_callback)(void *ctx, const uint8_t *item, uintptr_t item_len);
+
+/**
+ * Called with the raw fd (or `SOCKET` on Windows) of every outbound socket right after it
+ * is created, before it is bound or connected. `kind` is 1 for TCP, 2 for UDP and 3 for ICMP.
+ * Return 0 on success, or an OS error code to abort the connection attempt.
+ */
+typedef int32_t (*ytflow_socket_hook)(void *ctx, uint64_t socket, uint8_t kind, bool is_ipv6);
+
 typedef struct ytflow_result_content {
   void *_0;
   uintptr_t _1;
@@ -43,17 +65,26 @@ typedef struct ytflow_result {
 extern "C" {
 #endif // __cplusplus
 
+uint32_t ytflow_app_abi_version(void);
+
 const char *ytflow_get_version(void);
 
 struct ytflow_result ytflow_app_cbor_to_json(const uint8_t *cbor, uintptr_t cbor_len);
 
 struct ytflow_result ytflow_app_cbor_from_json(const char *json);
 
+struct ytflow_result ytflow_app_cidr_list_compile(const uint8_t *text, uintptr_t text_len);
+
 struct ytflow_result ytflow_plugin_verify(const char *plugin,
                                           uint16_t plugin_version,
                                           const uint8_t *param,
                                           uintptr_t param_len);
 
+struct ytflow_result ytflow_profile_estimate_handshakes(uint32_t profile_id,
+                                                        const ytflow_connection *conn);
+
+struct ytflow_result ytflow_plugin_schemas(void);
+
 #if defined(_WIN32)
 struct ytflow_result ytflow_db_new_win32(const uint16_t *path, uintptr_t len);
 #endif
@@ -62,12 +93,26 @@ struct ytflow_result ytflow_db_new_win32(const uint16_t *path, uintptr_t len);
 struct ytflow_result ytflow_db_new_unix(const uint8_t *path, uintptr_t len);
 #endif
 
+#if defined(_WIN32)
+struct ytflow_result ytflow_db_recover_win32(const uint16_t *path, uintptr_t len);
+#endif
+
+#if defined(__unix__)
+struct ytflow_result ytflow_db_recover_unix(const uint8_t *path, uintptr_t len);
+#endif
+
 struct ytflow_result ytflow_db_free(ytflow_database *db);
 
 struct ytflow_result ytflow_db_conn_new(const ytflow_database *db);
 
 struct ytflow_result ytflow_db_conn_free(ytflow_connection *conn);
 
+struct ytflow_result ytflow_db_integrity_check(const ytflow_connection *conn);
+
+struct ytflow_result ytflow_db_vacuum(const ytflow_connection *conn);
+
+struct ytflow_result ytflow_db_wal_checkpoint(const ytflow_connection *conn);
+
 struct ytflow_result ytflow_profiles_get_all(const ytflow_connection *conn);
 
 struct ytflow_result ytflow_plugins_get_by_profile(uint32_t profile_id,
@@ -75,6 +120,14 @@ struct ytflow_result ytflow_plugins_get_by_profile(uint32_t profile_id,
 
 struct ytflow_result ytflow_plugins_get_entry(uint32_t profile_id, const ytflow_connection *conn);
 
+/**
+ * Like [`ytflow_plugins_get_by_profile`], with descriptions in the locale best matching
+ * `locale`.
+ */
+struct ytflow_result ytflow_plugins_get_localized_by_profile(uint32_t profile_id,
+                                                             const char *locale,
+                                                             const ytflow_connection *conn);
+
 struct ytflow_result ytflow_profile_create(const char *name,
                                            const char *locale,
                                            const ytflow_connection *conn);
@@ -84,12 +137,92 @@ struct ytflow_result ytflow_profile_update(uint32_t profile_id,
                                            const char *locale,
                                            const ytflow_connection *conn);
 
+struct ytflow_result ytflow_profile_set_locked(uint32_t profile_id,
+                                              bool locked,
+                                              const ytflow_connection *conn);
+
 struct ytflow_result ytflow_profile_delete(uint32_t profile_id, const ytflow_connection *conn);
 
+/**
+ * Copy a profile with its plugins, entry plugins and translations under a new name.
+ */
+struct ytflow_result ytflow_profile_duplicate(uint32_t profile_id,
+                                              const char *name,
+                                              ytflow_connection *conn);
+
+struct ytflow_result ytflow_profile_snapshots_get(uint32_t profile_id,
+                                                  const ytflow_connection *conn);
+
+struct ytflow_result ytflow_profile_snapshot_create(uint32_t profile_id,
+                                                    const char *name,
+                                                    const ytflow_connection *conn);
+
+/**
+ * Roll the plugins and entry plugins of a profile back to a snapshot.
+ */
+struct ytflow_result ytflow_profile_snapshot_restore(uint32_t snapshot_id, ytflow_connection *conn);
+
+struct ytflow_result ytflow_profile_snapshot_rename(uint32_t snapshot_id,
+                                                    const char *name,
+                                                    const ytflow_connection *conn);
+
+struct ytflow_result ytflow_profile_snapshot_delete(uint32_t snapshot_id,
+                                                    const ytflow_connection *conn);
+
+/**
+ * Get a profile with its name and description in the locale best matching `locale`.
+ */
+struct ytflow_result ytflow_profile_get_localized(uint32_t profile_id,
+                                                  const char *locale,
+                                                  const ytflow_connection *conn);
+
+struct ytflow_result ytflow_profile_translations_get(uint32_t profile_id,
+                                                     const ytflow_connection *conn);
+
+struct ytflow_result ytflow_profile_translation_upsert(uint32_t profile_id,
+                                                       const char *locale,
+                                                       const char *name,
+                                                       const char *desc,
+                                                       const ytflow_connection *conn);
+
+struct ytflow_result ytflow_profile_translation_delete(uint32_t profile_id,
+                                                       const char *locale,
+                                                       const ytflow_connection *conn);
+
 struct ytflow_result ytflow_profile_export_toml(uint32_t profile_id, const ytflow_connection *conn);
 
+/**
+ * Export a profile on top of a previously exported TOML document, preserving its comments and
+ * formatting where the profile has not changed.
+ */
+struct ytflow_result ytflow_profile_export_toml_with_base(uint32_t profile_id,
+                                                          const uint8_t *base,
+                                                          uintptr_t base_len,
+                                                          const ytflow_connection *conn);
+
+struct ytflow_result ytflow_profile_export_cbor_streamed(uint32_t profile_id,
+                                                         const ytflow_connection *conn,
+                                                         ytflow_profile_export_callback callback,
+                                                         void *ctx);
+
 struct ytflow_result ytflow_profile_parse_toml(const uint8_t *toml, uintptr_t toml_len);
 
+/**
+ * Export a profile as a bundle signed with one of the signing keys on this device.
+ */
+struct ytflow_result ytflow_profile_export_signed_bundle(uint32_t profile_id,
+                                                         uint32_t signing_key_id,
+                                                         const ytflow_connection *conn);
+
+/**
+ * Verify a signed profile bundle and parse the profile inside. Fails if the bundle has been
+ * tampered with. When `signer` of the result is null, the key is not trusted and the user should
+ * be shown `fingerprint` and asked whether to trust the publisher before importing.
+ */
+struct ytflow_result ytflow_profile_verify_bundle(const uint8_t *bundle,
+                                                  uintptr_t bundle_len,
+                                                  const ytflow_connection *conn);
+
 struct ytflow_result ytflow_plugin_create(uint32_t profile_id,
                                           const char *name,
                                           const char *desc,
@@ -111,6 +244,18 @@ struct ytflow_result ytflow_plugin_update(uint32_t plugin_id,
 
 struct ytflow_result ytflow_plugin_delete(uint32_t plugin_id, const ytflow_connection *conn);
 
+struct ytflow_result ytflow_plugin_translations_get(uint32_t plugin_id,
+                                                    const ytflow_connection *conn);
+
+struct ytflow_result ytflow_plugin_translation_upsert(uint32_t plugin_id,
+                                                      const char *locale,
+                                                      const char *desc,
+                                                      const ytflow_connection *conn);
+
+struct ytflow_result ytflow_plugin_translation_delete(uint32_t plugin_id,
+                                                      const char *locale,
+                                                      const ytflow_connection *conn);
+
 struct ytflow_result ytflow_plugin_set_as_entry(uint32_t plugin_id,
                                                 uint32_t profile_id,
                                                 const ytflow_connection *conn);
@@ -214,6 +359,143 @@ struct ytflow_result ytflow_resource_github_release_update_retrieved_by_resource
                                                                                     const char *release_title,
                                                                                     const ytflow_connection *conn);
 
+struct ytflow_result ytflow_resource_create_with_maxmind_permalink(const char *key,
+                                                                   const char *type,
+                                                                   const char *local_file,
+                                                                   const char *edition_id,
+                                                                   const char *account_id,
+                                                                   const char *license_key,
+                                                                   ytflow_connection *conn);
+
+struct ytflow_result ytflow_resource_maxmind_permalink_query_by_resource_id(uint32_t resource_id,
+                                                                            const ytflow_connection *conn);
+
+struct ytflow_result ytflow_resource_maxmind_permalink_update_retrieved_by_resource_id(uint32_t resource_id,
+                                                                                       const char *last_modified,
+                                                                                       const ytflow_connection *conn);
+
+/**
+ * Returns the permalink to download the latest release of `edition_id` from. The request
+ * must be authenticated with the account ID and license key using HTTP basic auth.
+ */
+struct ytflow_result ytflow_resource_maxmind_permalink_url(const char *edition_id);
+
+/**
+ * Extracts the `.mmdb` database from a downloaded tar.gz archive.
+ */
+struct ytflow_result ytflow_resource_maxmind_extract_mmdb(const uint8_t *archive,
+                                                          uintptr_t archive_len);
+
+struct ytflow_result ytflow_traffic_quota_get_all(const ytflow_connection *conn);
+
+struct ytflow_result ytflow_traffic_quota_create_for_proxy(uint32_t proxy_id,
+                                                           uint64_t bytes_limit,
+                                                           uint8_t warn_percent,
+                                                           uint8_t reset_day,
+                                                           const ytflow_connection *conn);
+
+struct ytflow_result ytflow_traffic_quota_create_for_proxy_group(uint32_t proxy_group_id,
+                                                                 uint64_t bytes_limit,
+                                                                 uint8_t warn_percent,
+                                                                 uint8_t reset_day,
+                                                                 const ytflow_connection *conn);
+
+struct ytflow_result ytflow_traffic_quota_update(uint32_t traffic_quota_id,
+                                                 uint64_t bytes_limit,
+                                                 uint8_t warn_percent,
+                                                 uint8_t reset_day,
+                                                 const ytflow_connection *conn);
+
+struct ytflow_result ytflow_traffic_quota_reset_usage(uint32_t traffic_quota_id,
+                                                      const ytflow_connection *conn);
+
+struct ytflow_result ytflow_traffic_quota_delete(uint32_t traffic_quota_id,
+                                                 const ytflow_connection *conn);
+
+struct ytflow_result ytflow_signing_key_get_all(const ytflow_connection *conn);
+
+struct ytflow_result ytflow_signing_key_generate(const char *name, const ytflow_connection *conn);
+
+struct ytflow_result ytflow_signing_key_delete(uint32_t signing_key_id,
+                                               const ytflow_connection *conn);
+
+struct ytflow_result ytflow_trusted_key_get_all(const ytflow_connection *conn);
+
+struct ytflow_result ytflow_trusted_key_create(const char *name,
+                                               const uint8_t *public_key,
+                                               uintptr_t public_key_len,
+                                               const ytflow_connection *conn);
+
+struct ytflow_result ytflow_trusted_key_delete(uint32_t trusted_key_id,
+                                               const ytflow_connection *conn);
+
+struct ytflow_result ytflow_inbound_user_get_all_by_plugin(uint32_t plugin_id,
+                                                           const ytflow_connection *conn);
+
+/**
+ * A `max_connections` of 0 means unlimited.
+ */
+struct ytflow_result ytflow_inbound_user_create(uint32_t plugin_id,
+                                                const char *name,
+                                                const uint8_t *credential,
+                                                uintptr_t credential_len,
+                                                uint32_t max_connections,
+                                                const ytflow_connection *conn);
+
+/**
+ * A `max_connections` of 0 means unlimited.
+ */
+struct ytflow_result ytflow_inbound_user_update(uint32_t inbound_user_id,
+                                                const char *name,
+                                                const uint8_t *credential,
+                                                uintptr_t credential_len,
+                                                uint32_t max_connections,
+                                                const ytflow_connection *conn);
+
+struct ytflow_result ytflow_inbound_user_set_enabled(uint32_t inbound_user_id,
+                                                     bool enabled,
+                                                     const ytflow_connection *conn);
+
+struct ytflow_result ytflow_inbound_user_reset_usage(uint32_t inbound_user_id,
+                                                     const ytflow_connection *conn);
+
+struct ytflow_result ytflow_inbound_user_delete(uint32_t inbound_user_id,
+                                                const ytflow_connection *conn);
+
+/**
+ * Entries left in the journal by a previous run that crashed. Call before
+ * connecting, revert the changes in reverse order and remove each entry.
+ */
+struct ytflow_result ytflow_system_journal_get_all(const ytflow_connection *conn);
+
+/**
+ * Record a system change before applying it. `kind` is one of `route`, `dns`
+ * and `system_proxy`.
+ */
+struct ytflow_result ytflow_system_journal_record(const char *kind,
+                                                  const char *target,
+                                                  const uint8_t *undo,
+                                                  uintptr_t undo_len,
+                                                  const ytflow_connection *conn);
+
+/**
+ * Remove an entry after the change has been reverted.
+ */
+struct ytflow_result ytflow_system_journal_remove(uint32_t entry_id,
+                                                  const ytflow_connection *conn);
+
+struct ytflow_result ytflow_traffic_stat_get_daily(uint32_t days, const ytflow_connection *conn);
+
+struct ytflow_result ytflow_traffic_stat_get_by_profile(uint32_t profile_id,
+                                                        uint32_t days,
+                                                        const ytflow_connection *conn);
+
+struct ytflow_result ytflow_traffic_stat_get_by_proxy(uint32_t proxy_id,
+                                                      uint32_t days,
+                                                      const ytflow_connection *conn);
+
+struct ytflow_result ytflow_traffic_stat_prune(uint32_t keep_days, const ytflow_connection *conn);
+
 void ytflow_result_free(struct ytflow_result *result);
 
 struct ytflow_result ytflow_buffer_free(void *ptr, uintptr_t metadata);
@@ -230,10 +512,28 @@ struct ytflow_result ytflow_runtime_new(void);
 
 struct ytflow_result ytflow_runtime_free(struct ytflow_runtime *runtime);
 
+/**
+ * Install a process-wide hook for outbound sockets, e.g. to call `VpnService.protect()` on
+ * Android. Pass a null `callback` to remove the hook. `ctx` must stay valid until the hook
+ * is replaced or removed.
+ */
+struct ytflow_result ytflow_runtime_set_socket_hook(ytflow_socket_hook callback, void *ctx);
+
 struct ytflow_result ytflow_app_share_link_decode(const char *link);
 
 struct ytflow_result ytflow_app_share_link_encode(const uint8_t *proxy, uintptr_t proxy_len);
 
+/**
+ * Returns a square 8-bit luma bitmap. The width is the square root of the buffer length.
+ */
+struct ytflow_result ytflow_app_share_link_encode_qr(const uint8_t *proxy,
+                                                     uintptr_t proxy_len,
+                                                     uint32_t scale);
+
+struct ytflow_result ytflow_app_share_link_encode_qr_by_proxy_group(uint32_t proxy_group_id,
+                                                                    uint32_t scale,
+                                                                    const ytflow_connection *conn);
+
 struct ytflow_result ytflow_app_subscription_userinfo_header_decode(const char *header);
 
 struct ytflow_result ytflow_app_subscription_decode(const uint8_t *subscription,
@@ -244,6 +544,54 @@ struct ytflow_result ytflow_app_subscription_decode_with_format(const uint8_t *s
                                                                 uintptr_t subscription_len,
                                                                 const char *format);
 
+struct ytflow_result ytflow_app_subscription_metadata_headers_decode(const uint8_t *headers,
+                                                                     uintptr_t headers_len);
+
+struct ytflow_result ytflow_app_subscription_metadata_save(uint32_t proxy_group_id,
+                                                           const uint8_t *metadata,
+                                                           uintptr_t metadata_len,
+                                                           const ytflow_connection *conn);
+
+#if defined(YTFLOW_SYNC)
+/**
+ * Sync a profile or a proxy group with its snapshot on the remote endpoint. Blocks until the
+ * requests complete.
+ */
+struct ytflow_result ytflow_sync_item(const uint8_t *item,
+                                      uintptr_t item_len,
+                                      const uint8_t *endpoint,
+                                      uintptr_t endpoint_len,
+                                      const char *device_id,
+                                      ytflow_connection *conn);
+#endif
+
+#if defined(YTFLOW_SYNC)
+/**
+ * Resolve a conflict reported by `ytflow_sync_item` by keeping either the local item or the
+ * remote snapshot.
+ */
+struct ytflow_result ytflow_sync_resolve_conflict(const uint8_t *item,
+                                                  uintptr_t item_len,
+                                                  const uint8_t *endpoint,
+                                                  uintptr_t endpoint_len,
+                                                  const char *device_id,
+                                                  bool keep_local,
+                                                  ytflow_connection *conn);
+#endif
+
+#if defined(YTFLOW_SYNC)
+/**
+ * Import a profile pulled by `ytflow_sync_item` or `ytflow_sync_resolve_conflict` over the local
+ * profile. The pull is only recorded once this succeeds.
+ */
+struct ytflow_result ytflow_sync_apply_pulled_profile(uint32_t profile_id,
+                                                      const uint8_t *content,
+                                                      uintptr_t content_len,
+                                                      const uint8_t *endpoint,
+                                                      uintptr_t endpoint_len,
+                                                      ytflow_connection *conn);
+#endif
+
 #ifdef __cplusplus
 } // extern "C"
 #endif // __cplusplus
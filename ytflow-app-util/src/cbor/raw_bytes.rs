@@ -3,12 +3,35 @@ use cbor4ii::core::Value as CborValue;
 
 use super::{CborUtilError, CborUtilResult};
 
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+/// zstd frames expanding beyond this size are kept compressed.
+const MAX_ZSTD_DECOMPRESSED_SIZE: usize = 64 * 1024 * 1024;
+
+fn try_decompress_zstd_text(bytes: &[u8]) -> Option<String> {
+    if !bytes.starts_with(&ZSTD_MAGIC) {
+        return None;
+    }
+    let buf = zstd::bulk::decompress(bytes, MAX_ZSTD_DECOMPRESSED_SIZE).ok()?;
+    String::from_utf8(buf).ok()
+}
+
 /// Map CBOR bytes to string or base64 encoded string for
-/// later converting back.
+/// later converting back. Bytes of zstd compressed text are
+/// mapped to the decompressed text.
 pub fn escape_cbor_buf(val: &mut CborValue) {
     match val {
         CborValue::Bytes(bytes) => {
             let bytes = std::mem::take(bytes);
+            if let Some(text) = try_decompress_zstd_text(&bytes) {
+                *val = CborValue::Map(vec![
+                    (
+                        CborValue::Text("__byte_repr".into()),
+                        CborValue::Text("zstd".into()),
+                    ),
+                    (CborValue::Text("data".into()), CborValue::Text(text)),
+                ]);
+                return;
+            }
             *val = match String::from_utf8(bytes) {
                 Ok(str) => CborValue::Map(vec![
                     (
@@ -73,6 +96,10 @@ pub fn unescape_cbor_buf(val: &mut CborValue) -> CborUtilResult<()> {
                 (Some(repr), Some(buf)) if repr == "base64" => BASE64_STANDARD
                     .decode(std::mem::take(buf).into_bytes())
                     .map_err(|_| CborUtilError::InvalidByteRepr("base64"))?,
+                (Some(repr), Some(buf)) if repr == "zstd" => {
+                    zstd::bulk::compress(buf.as_bytes(), 0)
+                        .map_err(|_| CborUtilError::InvalidByteRepr("zstd"))?
+                }
                 (Some(_), None) => return Err(CborUtilError::MissingData),
                 (Some(repr), _) => return Err(CborUtilError::UnknownByteRepr(repr.clone())),
                 (None, _) => {
@@ -239,4 +266,45 @@ mod tests {
             Err(CborUtilError::UnexpectedByteReprKey("unexpected".into()))
         );
     }
+
+    #[test]
+    fn test_escape_cbor_buf_zstd() {
+        use CborValue::*;
+        let compressed = zstd::bulk::compress(b"DOMAIN-SUFFIX,a.com,direct", 0).unwrap();
+        let mut val = Map(vec![(Text("zstd".into()), Bytes(compressed))]);
+        escape_cbor_buf(&mut val);
+        assert_eq!(
+            val,
+            Map(vec![(
+                Text("zstd".into()),
+                Map(vec![
+                    (Text("__byte_repr".into()), Text("zstd".into())),
+                    (
+                        Text("data".into()),
+                        Text("DOMAIN-SUFFIX,a.com,direct".into())
+                    ),
+                ]),
+            )])
+        );
+    }
+
+    #[test]
+    fn test_unescape_cbor_buf_zstd() {
+        use CborValue::*;
+        let mut val = Map(vec![
+            (Text("__byte_repr".into()), Text("zstd".into())),
+            (
+                Text("data".into()),
+                Text("DOMAIN-SUFFIX,a.com,direct".into()),
+            ),
+        ]);
+        unescape_cbor_buf(&mut val).unwrap();
+        let Bytes(compressed) = val else {
+            panic!("expect bytes, got {:?}", val);
+        };
+        assert_eq!(
+            zstd::bulk::decompress(&compressed, 1024).unwrap(),
+            b"DOMAIN-SUFFIX,a.com,direct"
+        );
+    }
 }
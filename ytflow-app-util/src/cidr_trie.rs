@@ -0,0 +1,278 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::net::IpAddr;
+
+use thiserror::Error;
+use ytflow::plugin::rule_dispatcher::cidr_trie::{DEFAULT_TAG, EMPTY, LEAF_FLAG, MAGIC, VERSION};
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum CompileCidrListError {
+    #[error("invalid CIDR at line {0}")]
+    InvalidCidr(usize),
+    #[error("tag too long at line {0}")]
+    TagTooLong(usize),
+    #[error("too many tags")]
+    TooManyTags,
+    #[error("too many nodes")]
+    TooManyNodes,
+}
+
+pub type CompileCidrListResult<T> = Result<T, CompileCidrListError>;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Child {
+    Empty,
+    Leaf(u16),
+    Node(usize),
+}
+
+struct TrieBuilder {
+    nodes: Vec<[Child; 2]>,
+}
+
+impl TrieBuilder {
+    fn new() -> Self {
+        Self {
+            nodes: vec![[Child::Empty; 2]],
+        }
+    }
+
+    fn fill_empty(&mut self, node: usize, tag: u16) {
+        for bit in 0..2 {
+            match self.nodes[node][bit] {
+                Child::Empty => self.nodes[node][bit] = Child::Leaf(tag),
+                Child::Leaf(_) => {}
+                Child::Node(n) => self.fill_empty(n, tag),
+            }
+        }
+    }
+
+    /// Prefixes must be inserted from the shortest to the longest without
+    /// duplicates, so that a longer prefix overrides the shorter ones
+    /// containing it.
+    fn insert(&mut self, bits: u128, len: u8, tag: u16) {
+        if len == 0 {
+            self.fill_empty(0, tag);
+            return;
+        }
+        let mut node = 0;
+        for depth in 0..len {
+            let bit = ((bits >> (127 - depth)) & 1) as usize;
+            if depth == len - 1 {
+                match self.nodes[node][bit] {
+                    // A leaf here is inherited from a shorter prefix.
+                    Child::Empty | Child::Leaf(_) => self.nodes[node][bit] = Child::Leaf(tag),
+                    Child::Node(n) => self.fill_empty(n, tag),
+                }
+                return;
+            }
+            node = match self.nodes[node][bit] {
+                Child::Node(n) => n,
+                child => {
+                    let new_node = self.nodes.len();
+                    let inherited = match child {
+                        Child::Leaf(t) => Child::Leaf(t),
+                        _ => Child::Empty,
+                    };
+                    self.nodes.push([inherited; 2]);
+                    self.nodes[node][bit] = Child::Node(new_node);
+                    new_node
+                }
+            };
+        }
+    }
+
+    /// Merge subtrees that resolve to the same tag for every address.
+    fn aggregate(&mut self, node: usize) -> Child {
+        for bit in 0..2 {
+            if let Child::Node(n) = self.nodes[node][bit] {
+                self.nodes[node][bit] = self.aggregate(n);
+            }
+        }
+        match self.nodes[node] {
+            [Child::Leaf(a), Child::Leaf(b)] if a == b => Child::Leaf(a),
+            [Child::Empty, Child::Empty] => Child::Empty,
+            _ => Child::Node(node),
+        }
+    }
+
+    fn serialize(mut self, out: &mut Vec<u8>) -> CompileCidrListResult<()> {
+        if self.nodes == [[Child::Empty; 2]] {
+            out.extend_from_slice(&0u32.to_le_bytes());
+            return Ok(());
+        }
+        for bit in 0..2 {
+            if let Child::Node(n) = self.nodes[0][bit] {
+                self.nodes[0][bit] = self.aggregate(n);
+            }
+        }
+        // Renumber reachable nodes in pre-order, so that children always come
+        // after their parents.
+        let mut order = vec![];
+        let mut new_idx = BTreeMap::new();
+        let mut stack = vec![0];
+        while let Some(node) = stack.pop() {
+            new_idx.insert(node, order.len());
+            order.push(node);
+            for bit in (0..2).rev() {
+                if let Child::Node(n) = self.nodes[node][bit] {
+                    stack.push(n);
+                }
+            }
+        }
+        if order.len() >= LEAF_FLAG as usize {
+            return Err(CompileCidrListError::TooManyNodes);
+        }
+        out.extend_from_slice(&(order.len() as u32).to_le_bytes());
+        for node in order {
+            for child in self.nodes[node] {
+                let r = match child {
+                    Child::Empty => EMPTY,
+                    Child::Leaf(t) => LEAF_FLAG | t as u32,
+                    Child::Node(n) => new_idx[&n] as u32,
+                };
+                out.extend_from_slice(&r.to_le_bytes());
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_cidr(s: &str) -> Option<(IpAddr, u8)> {
+    let (addr, len) = match s.split_once('/') {
+        Some((addr, len)) => (addr.parse::<IpAddr>().ok()?, Some(len.parse::<u8>().ok()?)),
+        None => (s.parse::<IpAddr>().ok()?, None),
+    };
+    let max_len = if addr.is_ipv4() { 32 } else { 128 };
+    let len = len.unwrap_or(max_len);
+    (len <= max_len).then_some((addr, len))
+}
+
+/// Compile a plain CIDR list, one `CIDR[,tag]` per line, into the binary trie
+/// format accepted by `rule-dispatcher` as a `cidr-trie` resource. Overlapping
+/// prefixes resolve to the longest one, and adjacent prefixes with the same
+/// tag are aggregated.
+pub fn compile_cidr_list(text: &str) -> CompileCidrListResult<Vec<u8>> {
+    let mut tags: Vec<&str> = vec![];
+    let mut v4 = vec![];
+    let mut v6 = vec![];
+    for (line_no, line) in text.lines().enumerate().map(|(i, l)| (i + 1, l.trim())) {
+        if line.is_empty() || line.starts_with(['#', ';']) {
+            continue;
+        }
+        let (cidr, tag) = line
+            .split_once(',')
+            .map(|(c, t)| (c.trim(), t.trim()))
+            .unwrap_or((line, DEFAULT_TAG));
+        if tag.len() > u8::MAX as usize {
+            return Err(CompileCidrListError::TagTooLong(line_no));
+        }
+        let tag_idx = match tags.iter().position(|t| *t == tag) {
+            Some(idx) => idx,
+            None => {
+                tags.push(tag);
+                tags.len() - 1
+            }
+        };
+        let tag_idx = u16::try_from(tag_idx).map_err(|_| CompileCidrListError::TooManyTags)?;
+        match parse_cidr(cidr).ok_or(CompileCidrListError::InvalidCidr(line_no))? {
+            (IpAddr::V4(ip), len) => v4.push(((u32::from(ip) as u128) << 96, len, tag_idx)),
+            (IpAddr::V6(ip), len) => v6.push((u128::from(ip), len, tag_idx)),
+        }
+    }
+
+    let mut out = Vec::from(MAGIC);
+    out.extend_from_slice(&[VERSION, 0, 0, 0]);
+    out.extend_from_slice(&(tags.len() as u16).to_le_bytes());
+    for tag in &tags {
+        out.push(tag.len() as u8);
+        out.extend_from_slice(tag.as_bytes());
+    }
+    for mut prefixes in [v4, v6] {
+        // Stable, so that the first one of duplicated prefixes wins.
+        prefixes.sort_by_key(|(_, len, _)| *len);
+        let mut seen = BTreeSet::new();
+        let mut builder = TrieBuilder::new();
+        for (bits, len, tag) in prefixes {
+            let mask = u128::MAX.checked_shl(128 - len as u32).unwrap_or(0);
+            if seen.insert((bits & mask, len)) {
+                builder.insert(bits, len, tag);
+            }
+        }
+        builder.serialize(&mut out)?;
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use ytflow::plugin::rule_dispatcher::cidr_trie::CidrTrie;
+
+    use super::*;
+
+    #[test]
+    fn test_compile_cidr_list_lookup() {
+        let text = "
+            # comment
+            10.0.0.0/8
+            10.1.0.0/16,other
+            192.168.1.1
+            2001:db8::/32,v6
+        ";
+        let data = compile_cidr_list(text).unwrap();
+        let trie = CidrTrie::parse(&data[..]).unwrap();
+        assert_eq!(trie.tags(), ["default", "other", "v6"]);
+        assert_eq!(trie.lookup_v4(Ipv4Addr::new(10, 2, 3, 4)), Some(0));
+        assert_eq!(trie.lookup_v4(Ipv4Addr::new(10, 1, 3, 4)), Some(1));
+        assert_eq!(trie.lookup_v4(Ipv4Addr::new(192, 168, 1, 1)), Some(0));
+        assert_eq!(trie.lookup_v4(Ipv4Addr::new(192, 168, 1, 2)), None);
+        assert_eq!(trie.lookup_v4(Ipv4Addr::new(11, 0, 0, 0)), None);
+        assert_eq!(
+            trie.lookup_v6("2001:db8:1::1".parse::<Ipv6Addr>().unwrap()),
+            Some(2)
+        );
+        assert_eq!(trie.lookup_v6(Ipv6Addr::LOCALHOST), None);
+    }
+
+    #[test]
+    fn test_compile_cidr_list_aggregate() {
+        let split = compile_cidr_list("10.0.0.0/9\n10.128.0.0/9").unwrap();
+        let whole = compile_cidr_list("10.0.0.0/8").unwrap();
+        assert_eq!(split, whole);
+    }
+
+    #[test]
+    fn test_compile_cidr_list_default_route() {
+        let data = compile_cidr_list("0.0.0.0/0\n1.0.0.0/8,one").unwrap();
+        let trie = CidrTrie::parse(&data[..]).unwrap();
+        assert_eq!(trie.lookup_v4(Ipv4Addr::new(8, 8, 8, 8)), Some(0));
+        assert_eq!(trie.lookup_v4(Ipv4Addr::new(1, 1, 1, 1)), Some(1));
+    }
+
+    #[test]
+    fn test_compile_cidr_list_duplicate() {
+        let data = compile_cidr_list("10.0.0.0/8\n1.0.0.0/8,one\n10.1.2.3/8,two").unwrap();
+        let trie = CidrTrie::parse(&data[..]).unwrap();
+        assert_eq!(trie.lookup_v4(Ipv4Addr::new(10, 0, 0, 1)), Some(0));
+    }
+
+    #[test]
+    fn test_compile_cidr_list_empty() {
+        let data = compile_cidr_list("").unwrap();
+        let trie = CidrTrie::parse(&data[..]).unwrap();
+        assert_eq!(trie.lookup_v4(Ipv4Addr::new(8, 8, 8, 8)), None);
+    }
+
+    #[test]
+    fn test_compile_cidr_list_invalid() {
+        assert_eq!(
+            compile_cidr_list("10.0.0.0/8\n10.0.0.0/33"),
+            Err(CompileCidrListError::InvalidCidr(2))
+        );
+        assert_eq!(
+            compile_cidr_list("not an ip"),
+            Err(CompileCidrListError::InvalidCidr(1))
+        );
+    }
+}
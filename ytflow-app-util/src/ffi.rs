@@ -1,5 +1,6 @@
 #![allow(clippy::missing_safety_doc)]
 pub mod cbor;
+pub mod cidr_trie;
 pub mod config;
 pub mod data;
 pub mod error;
@@ -8,39 +9,79 @@ pub mod proxy;
 pub mod runtime;
 pub mod share_link;
 pub mod subscription;
+#[cfg(feature = "sync")]
+pub mod sync;
 
 pub mod exports {
-    pub use super::ytflow_get_version;
     use super::*;
+    pub use super::{ytflow_app_abi_version, ytflow_get_version};
     pub use cbor::{ytflow_app_cbor_from_json, ytflow_app_cbor_to_json};
-    pub use config::ytflow_plugin_verify;
-    #[cfg(unix)]
-    pub use data::ytflow_db_new_unix;
-    #[cfg(windows)]
-    pub use data::ytflow_db_new_win32;
+    pub use cidr_trie::ytflow_app_cidr_list_compile;
+    pub use config::{
+        ytflow_plugin_schemas, ytflow_plugin_verify, ytflow_profile_estimate_handshakes,
+    };
     pub use data::{
-        ytflow_db_conn_free, ytflow_db_conn_new, ytflow_db_free, ytflow_plugin_create,
-        ytflow_plugin_delete, ytflow_plugin_update, ytflow_plugins_get_by_profile,
-        ytflow_plugins_get_entry, ytflow_profile_create, ytflow_profile_delete,
-        ytflow_profile_update, ytflow_profiles_get_all, ytflow_proxy_create, ytflow_proxy_delete,
+        ytflow_db_conn_free, ytflow_db_conn_new, ytflow_db_free, ytflow_db_integrity_check,
+        ytflow_db_vacuum, ytflow_db_wal_checkpoint, ytflow_plugin_create, ytflow_plugin_delete,
+        ytflow_plugin_update, ytflow_plugins_get_by_profile, ytflow_plugins_get_entry,
+        ytflow_profile_create, ytflow_profile_delete, ytflow_profile_export_cbor_streamed,
+        ytflow_profile_export_signed_bundle, ytflow_profile_export_toml_with_base,
+        ytflow_profile_set_locked, ytflow_profile_update, ytflow_profile_verify_bundle,
+        ytflow_profiles_get_all, ytflow_proxy_create, ytflow_proxy_delete,
         ytflow_proxy_get_by_proxy_group, ytflow_proxy_group_create, ytflow_proxy_group_delete,
         ytflow_proxy_group_get_all, ytflow_proxy_group_get_by_id, ytflow_proxy_group_rename,
         ytflow_proxy_reorder, ytflow_proxy_update, ytflow_resource_create_with_github_release,
-        ytflow_resource_create_with_url, ytflow_resource_delete, ytflow_resource_get_all,
+        ytflow_resource_create_with_maxmind_permalink, ytflow_resource_create_with_url,
+        ytflow_resource_delete, ytflow_resource_get_all,
         ytflow_resource_github_release_query_by_resource_id,
         ytflow_resource_github_release_update_retrieved_by_resource_id,
-        ytflow_resource_url_query_by_resource_id,
-        ytflow_resource_url_update_retrieved_by_resource_id,
+        ytflow_resource_maxmind_extract_mmdb,
+        ytflow_resource_maxmind_permalink_query_by_resource_id,
+        ytflow_resource_maxmind_permalink_update_retrieved_by_resource_id,
+        ytflow_resource_maxmind_permalink_url, ytflow_resource_url_query_by_resource_id,
+        ytflow_resource_url_update_retrieved_by_resource_id, ytflow_signing_key_delete,
+        ytflow_signing_key_generate, ytflow_signing_key_get_all, ytflow_system_journal_get_all,
+        ytflow_system_journal_record, ytflow_system_journal_remove,
+        ytflow_traffic_quota_create_for_proxy, ytflow_traffic_quota_create_for_proxy_group,
+        ytflow_traffic_quota_delete, ytflow_traffic_quota_get_all,
+        ytflow_traffic_quota_reset_usage, ytflow_traffic_quota_update,
+        ytflow_traffic_stat_get_by_profile, ytflow_traffic_stat_get_by_proxy,
+        ytflow_traffic_stat_get_daily, ytflow_traffic_stat_prune, ytflow_trusted_key_create,
+        ytflow_trusted_key_delete, ytflow_trusted_key_get_all,
     };
+    #[cfg(unix)]
+    pub use data::{ytflow_db_new_unix, ytflow_db_recover_unix};
+    #[cfg(windows)]
+    pub use data::{ytflow_db_new_win32, ytflow_db_recover_win32};
     pub use error::ytflow_result_free;
     pub use interop::ytflow_buffer_free;
     pub use proxy::{ytflow_app_proxy_data_proxy_analyze, ytflow_app_proxy_data_proxy_compose_v1};
-    pub use runtime::{ytflow_runtime_free, ytflow_runtime_new};
-    pub use share_link::{ytflow_app_share_link_decode, ytflow_app_share_link_encode};
+    pub use runtime::{ytflow_runtime_free, ytflow_runtime_new, ytflow_runtime_set_socket_hook};
+    pub use share_link::{
+        ytflow_app_share_link_decode, ytflow_app_share_link_encode,
+        ytflow_app_share_link_encode_qr, ytflow_app_share_link_encode_qr_by_proxy_group,
+    };
     pub use subscription::{
         ytflow_app_subscription_decode, ytflow_app_subscription_decode_with_format,
+        ytflow_app_subscription_metadata_headers_decode, ytflow_app_subscription_metadata_save,
         ytflow_app_subscription_userinfo_header_decode,
     };
+    #[cfg(feature = "sync")]
+    pub use sync::{
+        ytflow_sync_apply_pulled_profile, ytflow_sync_item, ytflow_sync_resolve_conflict,
+    };
+}
+
+/// Version of the C ABI exposed by this library, bumped whenever a function signature or the
+/// layout of a buffer it takes or returns changes in an incompatible way. Most `ytflow_app_*`
+/// functions exchange CBOR buffers, but some do not: `ytflow_app_cbor_from_json` takes JSON,
+/// `ytflow_app_cidr_list_compile` takes plain text and `ytflow_app_share_link_encode_qr*`
+/// return luma bitmaps.
+pub const YTFLOW_APP_ABI_VERSION: u32 = 1;
+
+#[no_mangle]
+pub extern "C" fn ytflow_app_abi_version() -> u32 {
+    YTFLOW_APP_ABI_VERSION
 }
 
 #[no_mangle]
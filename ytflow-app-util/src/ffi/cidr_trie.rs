@@ -0,0 +1,14 @@
+use super::error::ytflow_result;
+use super::interop::serialize_byte_buffer;
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_app_cidr_list_compile(
+    text: *const u8,
+    text_len: usize,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(move || {
+        let text = std::slice::from_raw_parts(text, text_len);
+        let text = String::from_utf8_lossy(text);
+        crate::cidr_trie::compile_cidr_list(&text).map(|t| serialize_byte_buffer(t))
+    })
+}
@@ -1,10 +1,14 @@
 use std::ffi::CStr;
 use std::os::raw::c_char;
+use std::panic::AssertUnwindSafe;
 
-use ytflow::config::verify::verify_plugin;
+use ytflow::config::migration::migrate_plugins;
+use ytflow::config::schema::builtin_plugin_schemas;
+use ytflow::config::verify::{estimate_chains, verify_plugin};
 use ytflow::config::Plugin;
+use ytflow::data::{Connection as ytflow_connection, Plugin as DataPlugin};
 
-use super::error::ytflow_result;
+use super::error::{ytflow_result, InvalidCborError};
 use super::interop::serialize_buffer;
 
 #[no_mangle]
@@ -26,3 +30,25 @@ pub unsafe extern "C" fn ytflow_plugin_verify(
         verify_plugin(&plugin).map(|v| serialize_buffer(&v))
     })
 }
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_profile_estimate_handshakes(
+    profile_id: u32,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let conn = unsafe { &*conn };
+        DataPlugin::query_all_by_profile(profile_id.into(), conn).map(|plugins| {
+            let mut plugins: Vec<Plugin> = plugins.into_iter().map(From::from).collect();
+            migrate_plugins(&mut plugins);
+            serialize_buffer(&estimate_chains(&plugins))
+        })
+    }))
+}
+
+#[no_mangle]
+pub extern "C" fn ytflow_plugin_schemas() -> ytflow_result {
+    ytflow_result::catch_result_unwind(|| {
+        Ok::<_, InvalidCborError>(serialize_buffer(&builtin_plugin_schemas()))
+    })
+}
@@ -1,19 +1,34 @@
 use std::ffi::CStr;
-use std::os::raw::c_char;
+use std::os::raw::{c_char, c_void};
 use std::panic::AssertUnwindSafe;
 use std::ptr::null_mut;
 
-#[allow(non_camel_case_types)]
-use ytflow::data::{Connection as ytflow_connection, Database as ytflow_database};
 use ytflow::data::{
-    DataError, Plugin, Profile, Proxy, ProxyGroup, ProxySubscription, Resource,
-    ResourceGitHubRelease, ResourceUrl,
+    maintenance, DataError, InboundUser, Plugin, PluginTranslation, Profile, ProfileSnapshot,
+    ProfileTranslation, Proxy, ProxyGroup, ProxyInput, ProxySubscription, Resource,
+    ResourceGitHubRelease, ResourceMaxmindPermalink, ResourceUrl, SigningKey, SystemJournalEntry,
+    SystemMutationKind, TrafficQuota, TrafficStat, TrustedKey,
 };
+#[allow(non_camel_case_types)]
+use ytflow::data::{Connection as ytflow_connection, Database as ytflow_database};
 
-use crate::profile::{export_profile_toml, parse_profile_toml};
+use crate::profile::{
+    export_profile_cbor_streamed, export_profile_toml, export_profile_toml_with_base,
+    export_signed_profile_bundle, generate_signing_key, parse_profile_toml, verify_profile_bundle,
+};
 
 use super::error::ytflow_result;
-use super::interop::{serialize_buffer, serialize_string_buffer};
+use super::interop::{serialize_buffer, serialize_byte_buffer, serialize_string_buffer};
+
+fn open_db(path: impl AsRef<std::path::Path>) -> Result<(*mut c_void, usize), DataError> {
+    ytflow::data::Database::open(path).map(|db| (Box::into_raw(Box::new(db)) as *mut _, 0))
+}
+
+/// Move a corrupted database file aside and salvage readable rows into a
+/// fresh one. Returns the full [`ytflow::data::RecoveryReport`].
+fn recover_db(path: impl AsRef<std::path::Path>) -> Result<(*mut c_void, usize), DataError> {
+    ytflow::data::Database::recover(path).map(|report| serialize_buffer(&report))
+}
 
 #[no_mangle]
 #[cfg(windows)]
@@ -22,7 +37,7 @@ pub unsafe extern "C" fn ytflow_db_new_win32(path: *const u16, len: usize) -> yt
     use std::os::windows::ffi::OsStringExt;
     ytflow_result::catch_result_unwind(move || {
         let path = unsafe { OsString::from_wide(std::slice::from_raw_parts(path, len)) };
-        ytflow::data::Database::open(path).map(|db| (Box::into_raw(Box::new(db)) as *mut _, 0))
+        open_db(path)
     })
 }
 
@@ -33,7 +48,29 @@ pub unsafe extern "C" fn ytflow_db_new_unix(path: *const u8, len: usize) -> ytfl
     use std::os::unix::ffi::OsStrExt;
     ytflow_result::catch_result_unwind(move || {
         let path = unsafe { OsStr::from_bytes(std::slice::from_raw_parts(path, len)) };
-        ytflow::data::Database::open(path).map(|db| (Box::into_raw(Box::new(db)) as *mut _, 0))
+        open_db(path)
+    })
+}
+
+#[no_mangle]
+#[cfg(windows)]
+pub unsafe extern "C" fn ytflow_db_recover_win32(path: *const u16, len: usize) -> ytflow_result {
+    use std::ffi::OsString;
+    use std::os::windows::ffi::OsStringExt;
+    ytflow_result::catch_result_unwind(move || {
+        let path = unsafe { OsString::from_wide(std::slice::from_raw_parts(path, len)) };
+        recover_db(path)
+    })
+}
+
+#[no_mangle]
+#[cfg(unix)]
+pub unsafe extern "C" fn ytflow_db_recover_unix(path: *const u8, len: usize) -> ytflow_result {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    ytflow_result::catch_result_unwind(move || {
+        let path = unsafe { OsStr::from_bytes(std::slice::from_raw_parts(path, len)) };
+        recover_db(path)
     })
 }
 
@@ -62,6 +99,32 @@ pub unsafe extern "C" fn ytflow_db_conn_free(conn: *mut ytflow_connection) -> yt
     }))
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_db_integrity_check(
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let conn = unsafe { &*conn };
+        maintenance::integrity_check(conn).map(|m| serialize_buffer(&m))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_db_vacuum(conn: *const ytflow_connection) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let conn = unsafe { &*conn };
+        maintenance::vacuum(conn).map(|()| (null_mut(), 0))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_db_wal_checkpoint(conn: *const ytflow_connection) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let conn = unsafe { &*conn };
+        maintenance::wal_checkpoint(conn).map(|c| serialize_buffer(&c))
+    }))
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn ytflow_profiles_get_all(conn: *const ytflow_connection) -> ytflow_result {
     ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
@@ -92,6 +155,31 @@ pub unsafe extern "C" fn ytflow_plugins_get_entry(
     }))
 }
 
+/// Like [`ytflow_plugins_get_by_profile`], with descriptions in the locale best matching
+/// `locale`.
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_plugins_get_localized_by_profile(
+    profile_id: u32,
+    locale: *const c_char,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let locale = unsafe { CStr::from_ptr(locale) };
+        let conn = unsafe { &*conn };
+        let profile = Profile::query_by_id(profile_id as _, conn)?
+            .ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+        let mut plugins = Plugin::query_all_by_profile(profile.id, conn)?;
+        PluginTranslation::localize(
+            &mut plugins,
+            profile.id,
+            &profile.locale,
+            &locale.to_string_lossy(),
+            conn,
+        )
+        .map(|()| serialize_buffer(&plugins))
+    }))
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn ytflow_profile_create(
     name: *const c_char,
@@ -133,315 +221,318 @@ pub unsafe extern "C" fn ytflow_profile_update(
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn ytflow_profile_delete(
+pub unsafe extern "C" fn ytflow_profile_set_locked(
     profile_id: u32,
+    locked: bool,
     conn: *const ytflow_connection,
 ) -> ytflow_result {
     ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
         let conn = unsafe { &*conn };
-        Profile::delete(profile_id, conn).map(|()| (null_mut(), 0))
+        Profile::set_locked(profile_id, locked, conn).map(|()| (null_mut(), 0))
     }))
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn ytflow_profile_export_toml(
+pub unsafe extern "C" fn ytflow_profile_delete(
     profile_id: u32,
     conn: *const ytflow_connection,
 ) -> ytflow_result {
     ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
         let conn = unsafe { &*conn };
-        export_profile_toml(profile_id.into(), conn)
-            .map(|p| p.map(serialize_string_buffer).unwrap_or((null_mut(), 0)))
+        Profile::delete(profile_id, conn).map(|()| (null_mut(), 0))
     }))
 }
 
+/// Copy a profile with its plugins, entry plugins and translations under a new name.
 #[no_mangle]
-pub unsafe extern "C" fn ytflow_profile_parse_toml(
-    toml: *const u8,
-    toml_len: usize,
+pub unsafe extern "C" fn ytflow_profile_duplicate(
+    profile_id: u32,
+    name: *const c_char,
+    conn: *mut ytflow_connection,
 ) -> ytflow_result {
     ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
-        let toml = unsafe { std::slice::from_raw_parts(toml, toml_len) };
-        parse_profile_toml(toml).map(|p| serialize_buffer(&p))
+        let name = unsafe { CStr::from_ptr(name) };
+        let conn = unsafe { &mut *conn };
+        Profile::duplicate(profile_id, name.to_string_lossy().into_owned(), conn)
+            .map(|id| (id as _, 0))
     }))
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn ytflow_plugin_create(
+pub unsafe extern "C" fn ytflow_profile_snapshots_get(
     profile_id: u32,
-    name: *const c_char,
-    desc: *const c_char,
-    plugin: *const c_char,
-    plugin_version: u16,
-    param: *const u8,
-    param_len: usize,
     conn: *const ytflow_connection,
 ) -> ytflow_result {
     ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
-        let name = unsafe { CStr::from_ptr(name) };
-        let desc = unsafe { CStr::from_ptr(desc) };
-        let plugin = unsafe { CStr::from_ptr(plugin) };
         let conn = unsafe { &*conn };
-        Plugin::create(
-            profile_id.into(),
-            name.to_string_lossy().into_owned(),
-            desc.to_string_lossy().into_owned(),
-            plugin.to_string_lossy().into_owned(),
-            plugin_version,
-            unsafe { std::slice::from_raw_parts(param, param_len).to_vec() },
-            conn,
-        )
-        .map(|id| (id as _, 0))
+        ProfileSnapshot::query_all_by_profile(profile_id.into(), conn).map(|s| serialize_buffer(&s))
     }))
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn ytflow_plugin_update(
-    plugin_id: u32,
+pub unsafe extern "C" fn ytflow_profile_snapshot_create(
     profile_id: u32,
     name: *const c_char,
-    desc: *const c_char,
-    plugin: *const c_char,
-    plugin_version: u16,
-    param: *const u8,
-    param_len: usize,
     conn: *const ytflow_connection,
 ) -> ytflow_result {
     ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
         let name = unsafe { CStr::from_ptr(name) };
-        let desc = unsafe { CStr::from_ptr(desc) };
-        let plugin = unsafe { CStr::from_ptr(plugin) };
         let conn = unsafe { &*conn };
-        Plugin::update(
-            plugin_id,
-            profile_id.into(),
-            name.to_string_lossy().into_owned(),
-            desc.to_string_lossy().into_owned(),
-            plugin.to_string_lossy().into_owned(),
-            plugin_version,
-            unsafe { std::slice::from_raw_parts(param, param_len).to_vec() },
-            conn,
-        )
-        .map(|()| (null_mut(), 0))
+        ProfileSnapshot::create(profile_id.into(), name.to_string_lossy().into_owned(), conn)
+            .map(|id| (id as _, 0))
     }))
 }
 
+/// Roll the plugins and entry plugins of a profile back to a snapshot.
 #[no_mangle]
-pub unsafe extern "C" fn ytflow_plugin_delete(
-    plugin_id: u32,
-    conn: *const ytflow_connection,
+pub unsafe extern "C" fn ytflow_profile_snapshot_restore(
+    snapshot_id: u32,
+    conn: *mut ytflow_connection,
 ) -> ytflow_result {
     ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
-        let conn = unsafe { &*conn };
-        Plugin::delete(plugin_id, conn).map(|()| (null_mut(), 0))
+        let conn = unsafe { &mut *conn };
+        ProfileSnapshot::restore(snapshot_id, conn).map(|()| (null_mut(), 0))
     }))
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn ytflow_plugin_set_as_entry(
-    plugin_id: u32,
-    profile_id: u32,
+pub unsafe extern "C" fn ytflow_profile_snapshot_rename(
+    snapshot_id: u32,
+    name: *const c_char,
     conn: *const ytflow_connection,
 ) -> ytflow_result {
     ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let name = unsafe { CStr::from_ptr(name) };
         let conn = unsafe { &*conn };
-        Plugin::set_as_entry(profile_id.into(), plugin_id.into(), conn).map(|()| (null_mut(), 0))
+        ProfileSnapshot::rename(snapshot_id, name.to_string_lossy().into_owned(), conn)
+            .map(|()| (null_mut(), 0))
     }))
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn ytflow_plugin_unset_as_entry(
-    plugin_id: u32,
-    profile_id: u32,
+pub unsafe extern "C" fn ytflow_profile_snapshot_delete(
+    snapshot_id: u32,
     conn: *const ytflow_connection,
 ) -> ytflow_result {
     ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
         let conn = unsafe { &*conn };
-        Plugin::unset_as_entry(profile_id.into(), plugin_id.into(), conn).map(|()| (null_mut(), 0))
+        ProfileSnapshot::delete(snapshot_id, conn).map(|()| (null_mut(), 0))
     }))
 }
 
+/// Get a profile with its name and description in the locale best matching `locale`.
 #[no_mangle]
-pub unsafe extern "C" fn ytflow_proxy_group_get_all(
+pub unsafe extern "C" fn ytflow_profile_get_localized(
+    profile_id: u32,
+    locale: *const c_char,
     conn: *const ytflow_connection,
 ) -> ytflow_result {
     ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let locale = unsafe { CStr::from_ptr(locale) };
         let conn = unsafe { &*conn };
-        ProxyGroup::query_all(conn).map(|p| serialize_buffer(&p))
+        let profile = Profile::query_by_id(profile_id as _, conn)?
+            .ok_or(rusqlite::Error::QueryReturnedNoRows)?;
+        ProfileTranslation::localize(profile, &locale.to_string_lossy(), conn)
+            .map(|p| serialize_buffer(&p))
     }))
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn ytflow_proxy_group_get_by_id(
-    proxy_group_id: u32,
+pub unsafe extern "C" fn ytflow_profile_translations_get(
+    profile_id: u32,
     conn: *const ytflow_connection,
 ) -> ytflow_result {
     ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
         let conn = unsafe { &*conn };
-        ProxyGroup::query_by_id(proxy_group_id as usize, conn).map(|p| serialize_buffer(&p))
+        ProfileTranslation::query_all_by_profile(profile_id.into(), conn)
+            .map(|t| serialize_buffer(&t))
     }))
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn ytflow_proxy_group_create(
+pub unsafe extern "C" fn ytflow_profile_translation_upsert(
+    profile_id: u32,
+    locale: *const c_char,
     name: *const c_char,
-    r#type: *const c_char,
+    desc: *const c_char,
     conn: *const ytflow_connection,
 ) -> ytflow_result {
     ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let locale = unsafe { CStr::from_ptr(locale) };
         let name = unsafe { CStr::from_ptr(name) };
-        let r#type = unsafe { CStr::from_ptr(r#type) };
+        let desc = unsafe { CStr::from_ptr(desc) };
         let conn = unsafe { &*conn };
-        ProxyGroup::create(
+        ProfileTranslation::upsert(
+            profile_id.into(),
+            locale.to_string_lossy().into_owned(),
             name.to_string_lossy().into_owned(),
-            r#type.to_string_lossy().into_owned(),
+            desc.to_string_lossy().into_owned(),
             conn,
         )
-        .map(|id| (id as _, 0))
+        .map(|()| (null_mut(), 0))
     }))
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn ytflow_proxy_group_create_subscription(
-    name: *const c_char,
-    format: *const c_char,
-    url: *const c_char,
-    conn: *mut ytflow_connection,
+pub unsafe extern "C" fn ytflow_profile_translation_delete(
+    profile_id: u32,
+    locale: *const c_char,
+    conn: *const ytflow_connection,
 ) -> ytflow_result {
     ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
-        let name = unsafe { CStr::from_ptr(name) };
-        let format = unsafe { CStr::from_ptr(format) };
-        let url = unsafe { CStr::from_ptr(url) };
-        let conn = unsafe { &mut *conn };
-        ProxyGroup::create_subscription(
-            name.to_string_lossy().into_owned(),
-            format.to_string_lossy().into_owned(),
-            url.to_string_lossy().into_owned(),
-            conn,
-        )
-        .map(|id| (id as _, 0))
+        let locale = unsafe { CStr::from_ptr(locale) };
+        let conn = unsafe { &*conn };
+        ProfileTranslation::delete(profile_id.into(), &locale.to_string_lossy(), conn)
+            .map(|()| (null_mut(), 0))
     }))
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn ytflow_proxy_group_rename(
-    proxy_group_id: u32,
-    name: *const c_char,
+pub unsafe extern "C" fn ytflow_profile_export_toml(
+    profile_id: u32,
     conn: *const ytflow_connection,
 ) -> ytflow_result {
     ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
-        let name = unsafe { CStr::from_ptr(name) };
         let conn = unsafe { &*conn };
-        ProxyGroup::rename(proxy_group_id, name.to_string_lossy().into_owned(), conn)
-            .map(|()| (null_mut(), 0))
+        export_profile_toml(profile_id.into(), conn)
+            .map(|p| p.map(serialize_string_buffer).unwrap_or((null_mut(), 0)))
     }))
 }
 
+/// Export a profile on top of a previously exported TOML document, preserving its comments and
+/// formatting where the profile has not changed.
 #[no_mangle]
-pub unsafe extern "C" fn ytflow_proxy_group_delete(
-    proxy_group_id: u32,
+pub unsafe extern "C" fn ytflow_profile_export_toml_with_base(
+    profile_id: u32,
+    base: *const u8,
+    base_len: usize,
     conn: *const ytflow_connection,
 ) -> ytflow_result {
     ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
         let conn = unsafe { &*conn };
-        ProxyGroup::delete(proxy_group_id, conn).map(|()| (null_mut(), 0))
+        let base = unsafe { std::slice::from_raw_parts(base, base_len) };
+        export_profile_toml_with_base(profile_id.into(), base, conn)
+            .map(|p| p.map(serialize_string_buffer).unwrap_or((null_mut(), 0)))
     }))
 }
 
+/// Called with each CBOR-encoded item during a streamed profile export. The buffer is
+/// only valid during the call. Return `false` to abort the export.
+#[allow(non_camel_case_types)]
+pub type ytflow_profile_export_callback =
+    unsafe extern "C" fn(ctx: *mut c_void, item: *const u8, item_len: usize) -> bool;
+
 #[no_mangle]
-pub unsafe extern "C" fn ytflow_proxy_subscription_query_by_proxy_group_id(
-    proxy_group_id: u32,
+pub unsafe extern "C" fn ytflow_profile_export_cbor_streamed(
+    profile_id: u32,
     conn: *const ytflow_connection,
+    callback: ytflow_profile_export_callback,
+    ctx: *mut c_void,
 ) -> ytflow_result {
     ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
         let conn = unsafe { &*conn };
-        ProxySubscription::query_by_proxy_group_id(proxy_group_id, conn)
-            .map(|p| serialize_buffer(&p))
+        export_profile_cbor_streamed(profile_id.into(), conn, |item| unsafe {
+            callback(ctx, item.as_ptr(), item.len())
+        })
+        .map(|completed| serialize_buffer(&completed))
     }))
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn ytflow_proxy_subscription_update_retrieved_by_proxy_group_id(
-    proxy_group_id: u32,
-    upload_bytes_used: *const u64,
-    download_bytes_used: *const u64,
-    bytes_total: *const u64,
-    expires_at: *const c_char,
-    conn: *const ytflow_connection,
+pub unsafe extern "C" fn ytflow_profile_parse_toml(
+    toml: *const u8,
+    toml_len: usize,
 ) -> ytflow_result {
     ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
-        let upload_bytes_used = unsafe { upload_bytes_used.as_ref().copied() };
-        let download_bytes_used = unsafe { download_bytes_used.as_ref().copied() };
-        let bytes_total = unsafe { bytes_total.as_ref().copied() };
-        let expires_at = if expires_at.is_null() {
-            None
-        } else {
-            Some(unsafe { CStr::from_ptr(expires_at).to_string_lossy().into_owned() })
-        };
-        let conn = unsafe { &*conn };
-        ProxySubscription::update_retrieved_by_proxy_group_id(
-            proxy_group_id,
-            upload_bytes_used,
-            download_bytes_used,
-            bytes_total,
-            expires_at,
-            conn,
-        )
-        .map(|()| (null_mut(), 0))
+        let toml = unsafe { std::slice::from_raw_parts(toml, toml_len) };
+        parse_profile_toml(toml).map(|p| serialize_buffer(&p))
     }))
 }
 
+/// Export a profile as a bundle signed with one of the signing keys on this device.
 #[no_mangle]
-pub unsafe extern "C" fn ytflow_proxy_get_by_proxy_group(
-    proxy_group_id: u32,
+pub unsafe extern "C" fn ytflow_profile_export_signed_bundle(
+    profile_id: u32,
+    signing_key_id: u32,
     conn: *const ytflow_connection,
 ) -> ytflow_result {
     ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
         let conn = unsafe { &*conn };
-        Proxy::query_all_by_group(proxy_group_id.into(), conn).map(|p| serialize_buffer(&p))
+        export_signed_profile_bundle(profile_id.into(), signing_key_id.into(), conn)
+            .map(|b| b.map(serialize_byte_buffer).unwrap_or((null_mut(), 0)))
     }))
 }
 
+/// Verify a signed profile bundle and parse the profile inside. Fails if the bundle has been
+/// tampered with. When `signer` of the result is null, the key is not trusted and the user should
+/// be shown `fingerprint` and asked whether to trust the publisher before importing.
 #[no_mangle]
-pub unsafe extern "C" fn ytflow_proxy_create(
-    proxy_group_id: u32,
-    name: *const c_char,
-    proxy: *const u8,
-    proxy_len: usize,
-    proxy_version: u16,
+pub unsafe extern "C" fn ytflow_profile_verify_bundle(
+    bundle: *const u8,
+    bundle_len: usize,
     conn: *const ytflow_connection,
 ) -> ytflow_result {
     ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
-        let name = unsafe { CStr::from_ptr(name) };
         let conn = unsafe { &*conn };
-        Proxy::create(
-            proxy_group_id.into(),
-            name.to_string_lossy().into_owned(),
-            unsafe { std::slice::from_raw_parts(proxy, proxy_len).to_vec() },
-            proxy_version,
-            conn,
-        )
-        .map(|id| (id as _, 0))
+        let bundle = unsafe { std::slice::from_raw_parts(bundle, bundle_len) };
+        verify_profile_bundle(bundle, conn).map(|b| serialize_buffer(&b))
     }))
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn ytflow_proxy_update(
-    proxy_id: u32,
-    name: *const c_char,
-    proxy: *const u8,
-    proxy_len: usize,
-    proxy_version: u16,
+pub unsafe extern "C" fn ytflow_plugin_create(
+    profile_id: u32,
+    name: *const c_char,
+    desc: *const c_char,
+    plugin: *const c_char,
+    plugin_version: u16,
+    param: *const u8,
+    param_len: usize,
     conn: *const ytflow_connection,
 ) -> ytflow_result {
     ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
         let name = unsafe { CStr::from_ptr(name) };
+        let desc = unsafe { CStr::from_ptr(desc) };
+        let plugin = unsafe { CStr::from_ptr(plugin) };
         let conn = unsafe { &*conn };
-        Proxy::update(
-            proxy_id,
+        Plugin::create(
+            profile_id.into(),
             name.to_string_lossy().into_owned(),
-            unsafe { std::slice::from_raw_parts(proxy, proxy_len).to_vec() },
-            proxy_version,
+            desc.to_string_lossy().into_owned(),
+            plugin.to_string_lossy().into_owned(),
+            plugin_version,
+            unsafe { std::slice::from_raw_parts(param, param_len).to_vec() },
+            conn,
+        )
+        .map(|id| (id as _, 0))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_plugin_update(
+    plugin_id: u32,
+    profile_id: u32,
+    name: *const c_char,
+    desc: *const c_char,
+    plugin: *const c_char,
+    plugin_version: u16,
+    param: *const u8,
+    param_len: usize,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let name = unsafe { CStr::from_ptr(name) };
+        let desc = unsafe { CStr::from_ptr(desc) };
+        let plugin = unsafe { CStr::from_ptr(plugin) };
+        let conn = unsafe { &*conn };
+        Plugin::update(
+            plugin_id,
+            profile_id.into(),
+            name.to_string_lossy().into_owned(),
+            desc.to_string_lossy().into_owned(),
+            plugin.to_string_lossy().into_owned(),
+            plugin_version,
+            unsafe { std::slice::from_raw_parts(param, param_len).to_vec() },
             conn,
         )
         .map(|()| (null_mut(), 0))
@@ -449,31 +540,42 @@ pub unsafe extern "C" fn ytflow_proxy_update(
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn ytflow_proxy_delete(
-    proxy_id: u32,
+pub unsafe extern "C" fn ytflow_plugin_delete(
+    plugin_id: u32,
     conn: *const ytflow_connection,
 ) -> ytflow_result {
     ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
         let conn = unsafe { &*conn };
-        Proxy::delete(proxy_id, conn).map(|()| (null_mut(), 0))
+        Plugin::delete(plugin_id, conn).map(|()| (null_mut(), 0))
     }))
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn ytflow_proxy_reorder(
-    proxy_group_id: u32,
-    range_start_order: i32,
-    range_end_order: i32,
-    moves: i32,
-    conn: *mut ytflow_connection,
+pub unsafe extern "C" fn ytflow_plugin_translations_get(
+    plugin_id: u32,
+    conn: *const ytflow_connection,
 ) -> ytflow_result {
     ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
-        let conn = unsafe { &mut *conn };
-        Proxy::reorder(
-            proxy_group_id.into(),
-            range_start_order,
-            range_end_order,
-            moves,
+        let conn = unsafe { &*conn };
+        PluginTranslation::query_all_by_plugin(plugin_id.into(), conn).map(|t| serialize_buffer(&t))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_plugin_translation_upsert(
+    plugin_id: u32,
+    locale: *const c_char,
+    desc: *const c_char,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let locale = unsafe { CStr::from_ptr(locale) };
+        let desc = unsafe { CStr::from_ptr(desc) };
+        let conn = unsafe { &*conn };
+        PluginTranslation::upsert(
+            plugin_id.into(),
+            locale.to_string_lossy().into_owned(),
+            desc.to_string_lossy().into_owned(),
             conn,
         )
         .map(|()| (null_mut(), 0))
@@ -481,67 +583,77 @@ pub unsafe extern "C" fn ytflow_proxy_reorder(
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn ytflow_proxy_batch_update_by_group(
-    proxy_group_id: u32,
-    new_proxies_buf: *const u8,
-    new_proxies_buf_len: usize,
-    conn: *mut ytflow_connection,
+pub unsafe extern "C" fn ytflow_plugin_translation_delete(
+    plugin_id: u32,
+    locale: *const c_char,
+    conn: *const ytflow_connection,
 ) -> ytflow_result {
     ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
-        let new_proxies_buf = if new_proxies_buf_len == 0 {
-            &[][..]
-        } else {
-            unsafe { std::slice::from_raw_parts(new_proxies_buf, new_proxies_buf_len) }
-        };
-        let new_proxies =
-            cbor4ii::serde::from_slice(new_proxies_buf).map_err(|_| DataError::InvalidData {
-                domain: "proxies update",
-                field: "proxies_buf",
-            })?;
-        let conn = unsafe { &mut *conn };
-        Proxy::batch_update_by_group(proxy_group_id.into(), new_proxies, conn)
+        let locale = unsafe { CStr::from_ptr(locale) };
+        let conn = unsafe { &*conn };
+        PluginTranslation::delete(plugin_id.into(), &locale.to_string_lossy(), conn)
             .map(|()| (null_mut(), 0))
     }))
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn ytflow_resource_get_all(conn: *const ytflow_connection) -> ytflow_result {
+pub unsafe extern "C" fn ytflow_plugin_set_as_entry(
+    plugin_id: u32,
+    profile_id: u32,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
     ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
         let conn = unsafe { &*conn };
-        Resource::query_all(conn).map(|r| serialize_buffer(&r))
+        Plugin::set_as_entry(profile_id.into(), plugin_id.into(), conn).map(|()| (null_mut(), 0))
     }))
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn ytflow_resource_delete(
-    resource_id: u32,
+pub unsafe extern "C" fn ytflow_plugin_unset_as_entry(
+    plugin_id: u32,
+    profile_id: u32,
     conn: *const ytflow_connection,
 ) -> ytflow_result {
     ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
         let conn = unsafe { &*conn };
-        Resource::delete(resource_id, conn).map(|()| (null_mut(), 0))
+        Plugin::unset_as_entry(profile_id.into(), plugin_id.into(), conn).map(|()| (null_mut(), 0))
     }))
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn ytflow_resource_create_with_url(
-    key: *const c_char,
+pub unsafe extern "C" fn ytflow_proxy_group_get_all(
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let conn = unsafe { &*conn };
+        ProxyGroup::query_all(conn).map(|p| serialize_buffer(&p))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_proxy_group_get_by_id(
+    proxy_group_id: u32,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let conn = unsafe { &*conn };
+        ProxyGroup::query_by_id(proxy_group_id as usize, conn).map(|p| serialize_buffer(&p))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_proxy_group_create(
+    name: *const c_char,
     r#type: *const c_char,
-    local_file: *const c_char,
-    url: *const c_char,
-    conn: *mut ytflow_connection,
+    conn: *const ytflow_connection,
 ) -> ytflow_result {
     ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
-        let key = unsafe { CStr::from_ptr(key) };
+        let name = unsafe { CStr::from_ptr(name) };
         let r#type = unsafe { CStr::from_ptr(r#type) };
-        let local_file = unsafe { CStr::from_ptr(local_file) };
-        let url = unsafe { CStr::from_ptr(url) };
-        let conn = unsafe { &mut *conn };
-        Resource::create_with_url(
-            key.to_string_lossy().into_owned(),
+        let conn = unsafe { &*conn };
+        ProxyGroup::create(
+            name.to_string_lossy().into_owned(),
             r#type.to_string_lossy().into_owned(),
-            local_file.to_string_lossy().into_owned(),
-            url.to_string_lossy().into_owned(),
             conn,
         )
         .map(|id| (id as _, 0))
@@ -549,30 +661,21 @@ pub unsafe extern "C" fn ytflow_resource_create_with_url(
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn ytflow_resource_create_with_github_release(
-    key: *const c_char,
-    r#type: *const c_char,
-    local_file: *const c_char,
-    github_username: *const c_char,
-    github_repo: *const c_char,
-    asset_name: *const c_char,
+pub unsafe extern "C" fn ytflow_proxy_group_create_subscription(
+    name: *const c_char,
+    format: *const c_char,
+    url: *const c_char,
     conn: *mut ytflow_connection,
 ) -> ytflow_result {
     ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
-        let key = unsafe { CStr::from_ptr(key) };
-        let r#type = unsafe { CStr::from_ptr(r#type) };
-        let local_file = unsafe { CStr::from_ptr(local_file) };
-        let github_username = unsafe { CStr::from_ptr(github_username) };
-        let github_repo = unsafe { CStr::from_ptr(github_repo) };
-        let asset_name = unsafe { CStr::from_ptr(asset_name) };
+        let name = unsafe { CStr::from_ptr(name) };
+        let format = unsafe { CStr::from_ptr(format) };
+        let url = unsafe { CStr::from_ptr(url) };
         let conn = unsafe { &mut *conn };
-        Resource::create_with_github_release(
-            key.to_string_lossy().into_owned(),
-            r#type.to_string_lossy().into_owned(),
-            local_file.to_string_lossy().into_owned(),
-            github_username.to_string_lossy().into_owned(),
-            github_repo.to_string_lossy().into_owned(),
-            asset_name.to_string_lossy().into_owned(),
+        ProxyGroup::create_subscription(
+            name.to_string_lossy().into_owned(),
+            format.to_string_lossy().into_owned(),
+            url.to_string_lossy().into_owned(),
             conn,
         )
         .map(|id| (id as _, 0))
@@ -580,72 +683,346 @@ pub unsafe extern "C" fn ytflow_resource_create_with_github_release(
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn ytflow_resource_url_query_by_resource_id(
-    resource_id: u32,
+pub unsafe extern "C" fn ytflow_proxy_group_rename(
+    proxy_group_id: u32,
+    name: *const c_char,
     conn: *const ytflow_connection,
 ) -> ytflow_result {
     ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let name = unsafe { CStr::from_ptr(name) };
         let conn = unsafe { &*conn };
-        ResourceUrl::query_by_resource_id(resource_id, conn).map(|r| serialize_buffer(&r))
+        ProxyGroup::rename(proxy_group_id, name.to_string_lossy().into_owned(), conn)
+            .map(|()| (null_mut(), 0))
     }))
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn ytflow_resource_url_update_retrieved_by_resource_id(
-    resource_id: u32,
-    etag: *const c_char,
-    last_modified: *const c_char,
+pub unsafe extern "C" fn ytflow_proxy_group_delete(
+    proxy_group_id: u32,
     conn: *const ytflow_connection,
 ) -> ytflow_result {
     ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
-        let etag = if etag.is_null() {
-            None
-        } else {
-            Some(
-                unsafe { CStr::from_ptr(etag) }
-                    .to_string_lossy()
-                    .into_owned(),
-            )
-        };
-        let last_modified = if last_modified.is_null() {
-            None
-        } else {
-            Some({
-                unsafe { CStr::from_ptr(last_modified) }
-                    .to_string_lossy()
-                    .into_owned()
-            })
-        };
         let conn = unsafe { &*conn };
-        ResourceUrl::update_retrieved_by_resource_id(resource_id, etag, last_modified, conn)
-            .map(|()| (null_mut(), 0))
+        ProxyGroup::delete(proxy_group_id, conn).map(|()| (null_mut(), 0))
     }))
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn ytflow_resource_github_release_query_by_resource_id(
-    resource_id: u32,
+pub unsafe extern "C" fn ytflow_proxy_subscription_query_by_proxy_group_id(
+    proxy_group_id: u32,
     conn: *const ytflow_connection,
 ) -> ytflow_result {
     ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
         let conn = unsafe { &*conn };
-        ResourceGitHubRelease::query_by_resource_id(resource_id, conn).map(|r| serialize_buffer(&r))
+        ProxySubscription::query_by_proxy_group_id(proxy_group_id, conn)
+            .map(|p| serialize_buffer(&p))
     }))
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn ytflow_resource_github_release_update_retrieved_by_resource_id(
-    resource_id: u32,
-    git_tag: *const c_char,
-    release_title: *const c_char,
+pub unsafe extern "C" fn ytflow_proxy_subscription_update_retrieved_by_proxy_group_id(
+    proxy_group_id: u32,
+    upload_bytes_used: *const u64,
+    download_bytes_used: *const u64,
+    bytes_total: *const u64,
+    expires_at: *const c_char,
     conn: *const ytflow_connection,
 ) -> ytflow_result {
     ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
-        let git_tag = unsafe { CStr::from_ptr(git_tag) };
-        let release_title = unsafe { CStr::from_ptr(release_title) };
-        let conn = unsafe { &*conn };
-        ResourceGitHubRelease::update_retrieved_by_resource_id(
-            resource_id,
+        let upload_bytes_used = unsafe { upload_bytes_used.as_ref().copied() };
+        let download_bytes_used = unsafe { download_bytes_used.as_ref().copied() };
+        let bytes_total = unsafe { bytes_total.as_ref().copied() };
+        let expires_at = if expires_at.is_null() {
+            None
+        } else {
+            Some(unsafe { CStr::from_ptr(expires_at).to_string_lossy().into_owned() })
+        };
+        let conn = unsafe { &*conn };
+        ProxySubscription::update_retrieved_by_proxy_group_id(
+            proxy_group_id,
+            upload_bytes_used,
+            download_bytes_used,
+            bytes_total,
+            expires_at,
+            conn,
+        )
+        .map(|()| (null_mut(), 0))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_proxy_get_by_proxy_group(
+    proxy_group_id: u32,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let conn = unsafe { &*conn };
+        Proxy::query_all_by_group(proxy_group_id.into(), conn).map(|p| serialize_buffer(&p))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_proxy_create(
+    proxy_group_id: u32,
+    name: *const c_char,
+    proxy: *const u8,
+    proxy_len: usize,
+    proxy_version: u16,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let name = unsafe { CStr::from_ptr(name) };
+        let conn = unsafe { &*conn };
+        Proxy::create(
+            proxy_group_id.into(),
+            name.to_string_lossy().into_owned(),
+            unsafe { std::slice::from_raw_parts(proxy, proxy_len).to_vec() },
+            proxy_version,
+            conn,
+        )
+        .map(|id| (id as _, 0))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_proxy_update(
+    proxy_id: u32,
+    name: *const c_char,
+    proxy: *const u8,
+    proxy_len: usize,
+    proxy_version: u16,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let name = unsafe { CStr::from_ptr(name) };
+        let conn = unsafe { &*conn };
+        Proxy::update(
+            proxy_id,
+            name.to_string_lossy().into_owned(),
+            unsafe { std::slice::from_raw_parts(proxy, proxy_len).to_vec() },
+            proxy_version,
+            conn,
+        )
+        .map(|()| (null_mut(), 0))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_proxy_delete(
+    proxy_id: u32,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let conn = unsafe { &*conn };
+        Proxy::delete(proxy_id, conn).map(|()| (null_mut(), 0))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_proxy_reorder(
+    proxy_group_id: u32,
+    range_start_order: i32,
+    range_end_order: i32,
+    moves: i32,
+    conn: *mut ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let conn = unsafe { &mut *conn };
+        Proxy::reorder(
+            proxy_group_id.into(),
+            range_start_order,
+            range_end_order,
+            moves,
+            conn,
+        )
+        .map(|()| (null_mut(), 0))
+    }))
+}
+
+fn is_credential_rotation(old: &Proxy, new: &ProxyInput) -> bool {
+    let analyze = |name: &str, proxy: &[u8], version| {
+        crate::proxy::data::analyze_data_proxy(name.into(), proxy, version).ok()
+    };
+    let (Some(old), Some(new)) = (
+        analyze(&old.name, &old.proxy, old.proxy_version),
+        analyze(&new.name, &new.proxy, new.proxy_version),
+    ) else {
+        return false;
+    };
+    old.differs_only_in_credentials(&new)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_proxy_batch_update_by_group(
+    proxy_group_id: u32,
+    new_proxies_buf: *const u8,
+    new_proxies_buf_len: usize,
+    conn: *mut ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let new_proxies_buf = if new_proxies_buf_len == 0 {
+            &[][..]
+        } else {
+            unsafe { std::slice::from_raw_parts(new_proxies_buf, new_proxies_buf_len) }
+        };
+        let new_proxies =
+            cbor4ii::serde::from_slice(new_proxies_buf).map_err(|_| DataError::InvalidData {
+                domain: "proxies update",
+                field: "proxies_buf",
+            })?;
+        let conn = unsafe { &mut *conn };
+        Proxy::batch_update_by_group_with_rotation(
+            proxy_group_id.into(),
+            new_proxies,
+            is_credential_rotation,
+            conn,
+        )
+        .map(|rotated| serialize_buffer(&rotated))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_resource_get_all(conn: *const ytflow_connection) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let conn = unsafe { &*conn };
+        Resource::query_all(conn).map(|r| serialize_buffer(&r))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_resource_delete(
+    resource_id: u32,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let conn = unsafe { &*conn };
+        Resource::delete(resource_id, conn).map(|()| (null_mut(), 0))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_resource_create_with_url(
+    key: *const c_char,
+    r#type: *const c_char,
+    local_file: *const c_char,
+    url: *const c_char,
+    conn: *mut ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let key = unsafe { CStr::from_ptr(key) };
+        let r#type = unsafe { CStr::from_ptr(r#type) };
+        let local_file = unsafe { CStr::from_ptr(local_file) };
+        let url = unsafe { CStr::from_ptr(url) };
+        let conn = unsafe { &mut *conn };
+        Resource::create_with_url(
+            key.to_string_lossy().into_owned(),
+            r#type.to_string_lossy().into_owned(),
+            local_file.to_string_lossy().into_owned(),
+            url.to_string_lossy().into_owned(),
+            conn,
+        )
+        .map(|id| (id as _, 0))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_resource_create_with_github_release(
+    key: *const c_char,
+    r#type: *const c_char,
+    local_file: *const c_char,
+    github_username: *const c_char,
+    github_repo: *const c_char,
+    asset_name: *const c_char,
+    conn: *mut ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let key = unsafe { CStr::from_ptr(key) };
+        let r#type = unsafe { CStr::from_ptr(r#type) };
+        let local_file = unsafe { CStr::from_ptr(local_file) };
+        let github_username = unsafe { CStr::from_ptr(github_username) };
+        let github_repo = unsafe { CStr::from_ptr(github_repo) };
+        let asset_name = unsafe { CStr::from_ptr(asset_name) };
+        let conn = unsafe { &mut *conn };
+        Resource::create_with_github_release(
+            key.to_string_lossy().into_owned(),
+            r#type.to_string_lossy().into_owned(),
+            local_file.to_string_lossy().into_owned(),
+            github_username.to_string_lossy().into_owned(),
+            github_repo.to_string_lossy().into_owned(),
+            asset_name.to_string_lossy().into_owned(),
+            conn,
+        )
+        .map(|id| (id as _, 0))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_resource_url_query_by_resource_id(
+    resource_id: u32,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let conn = unsafe { &*conn };
+        ResourceUrl::query_by_resource_id(resource_id, conn).map(|r| serialize_buffer(&r))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_resource_url_update_retrieved_by_resource_id(
+    resource_id: u32,
+    etag: *const c_char,
+    last_modified: *const c_char,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let etag = if etag.is_null() {
+            None
+        } else {
+            Some(
+                unsafe { CStr::from_ptr(etag) }
+                    .to_string_lossy()
+                    .into_owned(),
+            )
+        };
+        let last_modified = if last_modified.is_null() {
+            None
+        } else {
+            Some({
+                unsafe { CStr::from_ptr(last_modified) }
+                    .to_string_lossy()
+                    .into_owned()
+            })
+        };
+        let conn = unsafe { &*conn };
+        ResourceUrl::update_retrieved_by_resource_id(resource_id, etag, last_modified, conn)
+            .map(|()| (null_mut(), 0))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_resource_github_release_query_by_resource_id(
+    resource_id: u32,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let conn = unsafe { &*conn };
+        ResourceGitHubRelease::query_by_resource_id(resource_id, conn).map(|r| serialize_buffer(&r))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_resource_github_release_update_retrieved_by_resource_id(
+    resource_id: u32,
+    git_tag: *const c_char,
+    release_title: *const c_char,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let git_tag = unsafe { CStr::from_ptr(git_tag) };
+        let release_title = unsafe { CStr::from_ptr(release_title) };
+        let conn = unsafe { &*conn };
+        ResourceGitHubRelease::update_retrieved_by_resource_id(
+            resource_id,
             git_tag.to_string_lossy().into_owned(),
             release_title.to_string_lossy().into_owned(),
             conn,
@@ -653,3 +1030,452 @@ pub unsafe extern "C" fn ytflow_resource_github_release_update_retrieved_by_reso
         .map(|()| (null_mut(), 0))
     }))
 }
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_resource_create_with_maxmind_permalink(
+    key: *const c_char,
+    r#type: *const c_char,
+    local_file: *const c_char,
+    edition_id: *const c_char,
+    account_id: *const c_char,
+    license_key: *const c_char,
+    conn: *mut ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let key = unsafe { CStr::from_ptr(key) };
+        let r#type = unsafe { CStr::from_ptr(r#type) };
+        let local_file = unsafe { CStr::from_ptr(local_file) };
+        let edition_id = unsafe { CStr::from_ptr(edition_id) };
+        let account_id = unsafe { CStr::from_ptr(account_id) };
+        let license_key = unsafe { CStr::from_ptr(license_key) };
+        let conn = unsafe { &mut *conn };
+        Resource::create_with_maxmind_permalink(
+            key.to_string_lossy().into_owned(),
+            r#type.to_string_lossy().into_owned(),
+            local_file.to_string_lossy().into_owned(),
+            edition_id.to_string_lossy().into_owned(),
+            account_id.to_string_lossy().into_owned(),
+            license_key.to_string_lossy().into_owned(),
+            conn,
+        )
+        .map(|id| (id as _, 0))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_resource_maxmind_permalink_query_by_resource_id(
+    resource_id: u32,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let conn = unsafe { &*conn };
+        ResourceMaxmindPermalink::query_by_resource_id(resource_id, conn)
+            .map(|r| serialize_buffer(&r))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_resource_maxmind_permalink_update_retrieved_by_resource_id(
+    resource_id: u32,
+    last_modified: *const c_char,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let last_modified = if last_modified.is_null() {
+            None
+        } else {
+            Some(
+                unsafe { CStr::from_ptr(last_modified) }
+                    .to_string_lossy()
+                    .into_owned(),
+            )
+        };
+        let conn = unsafe { &*conn };
+        ResourceMaxmindPermalink::update_retrieved_by_resource_id(resource_id, last_modified, conn)
+            .map(|()| (null_mut(), 0))
+    }))
+}
+
+/// Returns the permalink to download the latest release of `edition_id` from. The request
+/// must be authenticated with the account ID and license key using HTTP basic auth.
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_resource_maxmind_permalink_url(
+    edition_id: *const c_char,
+) -> ytflow_result {
+    ytflow_result::catch_ptr_unwind(AssertUnwindSafe(move || {
+        let edition_id = unsafe { CStr::from_ptr(edition_id) };
+        serialize_string_buffer(ytflow::resource::maxmind::permalink_url(
+            &edition_id.to_string_lossy(),
+        ))
+    }))
+}
+
+/// Extracts the `.mmdb` database from a downloaded tar.gz archive.
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_resource_maxmind_extract_mmdb(
+    archive: *const u8,
+    archive_len: usize,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let archive = unsafe { std::slice::from_raw_parts(archive, archive_len) };
+        ytflow::resource::maxmind::extract_mmdb(archive).map(serialize_byte_buffer)
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_traffic_quota_get_all(
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let conn = unsafe { &*conn };
+        TrafficQuota::query_all(conn).map(|q| serialize_buffer(&q))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_traffic_quota_create_for_proxy(
+    proxy_id: u32,
+    bytes_limit: u64,
+    warn_percent: u8,
+    reset_day: u8,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let conn = unsafe { &*conn };
+        TrafficQuota::create_for_proxy(proxy_id.into(), bytes_limit, warn_percent, reset_day, conn)
+            .map(|id| (id as _, 0))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_traffic_quota_create_for_proxy_group(
+    proxy_group_id: u32,
+    bytes_limit: u64,
+    warn_percent: u8,
+    reset_day: u8,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let conn = unsafe { &*conn };
+        TrafficQuota::create_for_proxy_group(
+            proxy_group_id.into(),
+            bytes_limit,
+            warn_percent,
+            reset_day,
+            conn,
+        )
+        .map(|id| (id as _, 0))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_traffic_quota_update(
+    traffic_quota_id: u32,
+    bytes_limit: u64,
+    warn_percent: u8,
+    reset_day: u8,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let conn = unsafe { &*conn };
+        TrafficQuota::update(traffic_quota_id, bytes_limit, warn_percent, reset_day, conn)
+            .map(|()| (null_mut(), 0))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_traffic_quota_reset_usage(
+    traffic_quota_id: u32,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let conn = unsafe { &*conn };
+        TrafficQuota::reset_usage(traffic_quota_id, conn).map(|()| (null_mut(), 0))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_traffic_quota_delete(
+    traffic_quota_id: u32,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let conn = unsafe { &*conn };
+        TrafficQuota::delete(traffic_quota_id, conn).map(|()| (null_mut(), 0))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_signing_key_get_all(
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let conn = unsafe { &*conn };
+        SigningKey::query_all(conn).map(|k| serialize_buffer(&k))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_signing_key_generate(
+    name: *const c_char,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let name = unsafe { CStr::from_ptr(name) };
+        let conn = unsafe { &*conn };
+        generate_signing_key(name.to_string_lossy().into_owned(), conn).map(|id| (id as _, 0))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_signing_key_delete(
+    signing_key_id: u32,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let conn = unsafe { &*conn };
+        SigningKey::delete(signing_key_id, conn).map(|()| (null_mut(), 0))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_trusted_key_get_all(
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let conn = unsafe { &*conn };
+        TrustedKey::query_all(conn).map(|k| serialize_buffer(&k))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_trusted_key_create(
+    name: *const c_char,
+    public_key: *const u8,
+    public_key_len: usize,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let name = unsafe { CStr::from_ptr(name) };
+        let public_key = unsafe { std::slice::from_raw_parts(public_key, public_key_len) };
+        let conn = unsafe { &*conn };
+        TrustedKey::create(name.to_string_lossy().into_owned(), public_key, conn)
+            .map(|id| (id as _, 0))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_trusted_key_delete(
+    trusted_key_id: u32,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let conn = unsafe { &*conn };
+        TrustedKey::delete(trusted_key_id, conn).map(|()| (null_mut(), 0))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_inbound_user_get_all_by_plugin(
+    plugin_id: u32,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let conn = unsafe { &*conn };
+        InboundUser::query_all_by_plugin(plugin_id.into(), conn).map(|u| serialize_buffer(&u))
+    }))
+}
+
+/// A `max_connections` of 0 means unlimited.
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_inbound_user_create(
+    plugin_id: u32,
+    name: *const c_char,
+    credential: *const u8,
+    credential_len: usize,
+    max_connections: u32,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let name = unsafe { CStr::from_ptr(name) };
+        let credential = unsafe { std::slice::from_raw_parts(credential, credential_len) };
+        let conn = unsafe { &*conn };
+        InboundUser::create(
+            plugin_id.into(),
+            name.to_string_lossy().into_owned(),
+            credential,
+            (max_connections != 0).then_some(max_connections),
+            conn,
+        )
+        .map(|id| (id as _, 0))
+    }))
+}
+
+/// A `max_connections` of 0 means unlimited.
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_inbound_user_update(
+    inbound_user_id: u32,
+    name: *const c_char,
+    credential: *const u8,
+    credential_len: usize,
+    max_connections: u32,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let name = unsafe { CStr::from_ptr(name) };
+        let credential = unsafe { std::slice::from_raw_parts(credential, credential_len) };
+        let conn = unsafe { &*conn };
+        InboundUser::update(
+            inbound_user_id,
+            name.to_string_lossy().into_owned(),
+            credential,
+            (max_connections != 0).then_some(max_connections),
+            conn,
+        )
+        .map(|()| (null_mut(), 0))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_inbound_user_set_enabled(
+    inbound_user_id: u32,
+    enabled: bool,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let conn = unsafe { &*conn };
+        InboundUser::set_enabled(inbound_user_id, enabled, conn).map(|()| (null_mut(), 0))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_inbound_user_reset_usage(
+    inbound_user_id: u32,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let conn = unsafe { &*conn };
+        InboundUser::reset_usage(inbound_user_id, conn).map(|()| (null_mut(), 0))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_inbound_user_delete(
+    inbound_user_id: u32,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let conn = unsafe { &*conn };
+        InboundUser::delete(inbound_user_id, conn).map(|()| (null_mut(), 0))
+    }))
+}
+
+/// Entries left in the journal by a previous run that crashed. Call before
+/// connecting, revert the changes in reverse order and remove each entry.
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_system_journal_get_all(
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let conn = unsafe { &*conn };
+        SystemJournalEntry::query_all(conn).map(|e| serialize_buffer(&e))
+    }))
+}
+
+/// Record a system change before applying it. `kind` is one of `route`, `dns`
+/// and `system_proxy`.
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_system_journal_record(
+    kind: *const c_char,
+    target: *const c_char,
+    undo: *const u8,
+    undo_len: usize,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let kind = unsafe { CStr::from_ptr(kind) };
+        let target = unsafe { CStr::from_ptr(target) };
+        let undo = unsafe { std::slice::from_raw_parts(undo, undo_len) };
+        let conn = unsafe { &*conn };
+        let kind = kind
+            .to_str()
+            .ok()
+            .and_then(SystemMutationKind::parse)
+            .ok_or(DataError::InvalidData {
+                domain: "system_journal",
+                field: "kind",
+            })?;
+        SystemJournalEntry::record(kind, target.to_string_lossy().into_owned(), undo, conn)
+            .map(|id| (id as _, 0))
+    }))
+}
+
+/// Remove an entry after the change has been reverted.
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_system_journal_remove(
+    entry_id: u32,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let conn = unsafe { &*conn };
+        SystemJournalEntry::delete(entry_id, conn).map(|()| (null_mut(), 0))
+    }))
+}
+
+/// Dates covering the last `days` days, including today.
+fn traffic_stat_range(days: u32) -> (chrono::NaiveDate, chrono::NaiveDate) {
+    let to = chrono::Local::now().date_naive();
+    let from = to - chrono::Days::new(days.saturating_sub(1) as u64);
+    (from, to)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_traffic_stat_get_daily(
+    days: u32,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let conn = unsafe { &*conn };
+        let (from, to) = traffic_stat_range(days);
+        TrafficStat::query_daily(from, to, conn).map(|s| serialize_buffer(&s))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_traffic_stat_get_by_profile(
+    profile_id: u32,
+    days: u32,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let conn = unsafe { &*conn };
+        let (from, to) = traffic_stat_range(days);
+        TrafficStat::query_by_profile(profile_id.into(), from, to, conn)
+            .map(|s| serialize_buffer(&s))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_traffic_stat_get_by_proxy(
+    proxy_id: u32,
+    days: u32,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let conn = unsafe { &*conn };
+        let (from, to) = traffic_stat_range(days);
+        TrafficStat::query_by_proxy(proxy_id.into(), from, to, conn).map(|s| serialize_buffer(&s))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_traffic_stat_prune(
+    keep_days: u32,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let conn = unsafe { &*conn };
+        let (from, _) = traffic_stat_range(keep_days);
+        TrafficStat::delete_before(from, conn).map(|n| (n as _, 0))
+    }))
+}
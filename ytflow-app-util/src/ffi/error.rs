@@ -7,8 +7,9 @@ use std::ptr::null_mut;
 
 use ytflow::config::ConfigError;
 use ytflow::data::DataError;
+use ytflow::resource::ResourceError;
 
-use crate::{cbor, profile, proxy, share_link, subscription};
+use crate::{cbor, cidr_trie, profile, proxy, share_link, subscription};
 
 #[repr(C)]
 #[derive(Clone, Copy)]
@@ -120,6 +121,22 @@ impl ToFfiError for DataError {
             InvalidData { domain, field } => {
                 ErrorDesc::e2(BASE_CODE + 3, domain.to_string(), field.to_string())
             }
+            Io(r) => ErrorDesc::e1(BASE_CODE + 4, r.to_string()),
+            ProfileLocked { profile_id } => ErrorDesc::e1(BASE_CODE + 5, profile_id.to_string()),
+        }
+    }
+}
+
+impl ToFfiError for ResourceError {
+    fn from(self) -> ErrorDesc {
+        use ResourceError::*;
+        const BASE_CODE: u32 = 0x8000_3000;
+        match self {
+            NotFound => ErrorDesc::e0(BASE_CODE + 1),
+            IoError(r) => ErrorDesc::e1(BASE_CODE + 2, r.to_string()),
+            DataError(r) => ToFfiError::from(r),
+            NotLoaded => ErrorDesc::e0(BASE_CODE + 3),
+            InvalidData => ErrorDesc::e0(BASE_CODE + 4),
         }
     }
 }
@@ -152,6 +169,11 @@ impl ToFfiError for ConfigError {
             TooManyPlugin { plugin, r#type } => {
                 ErrorDesc::e2(BASE_CODE + 7, plugin, r#type.to_string())
             }
+            MigrateParam {
+                plugin,
+                from_version,
+                reason,
+            } => ErrorDesc::e3(BASE_CODE + 8, plugin, from_version.to_string(), reason),
         }
     }
 }
@@ -207,6 +229,7 @@ impl ToFfiError for share_link::EncodeError {
             TooManyLegs => ErrorDesc::e0(BASE_CODE + 1),
             InvalidEncoding(c) => ErrorDesc::e1(BASE_CODE + 2, c.into()),
             UnsupportedComponent(c) => ErrorDesc::e1(BASE_CODE + 3, c.into()),
+            TooLongForQrCode => ErrorDesc::e0(BASE_CODE + 4),
         }
     }
 }
@@ -251,6 +274,50 @@ impl ToFfiError for profile::ParseTomlProfileError {
     }
 }
 
+impl ToFfiError for profile::ProfileBundleError {
+    fn from(self) -> ErrorDesc {
+        use profile::ProfileBundleError::*;
+        const BASE_CODE: u32 = 0x8001_1900;
+        match self {
+            InvalidEncoding => ErrorDesc::e0(BASE_CODE + 1),
+            UnknownVersion(v) => ErrorDesc::e1(BASE_CODE + 2, v.to_string()),
+            BadSignature => ErrorDesc::e0(BASE_CODE + 3),
+            NoSigningKey(id) => ErrorDesc::e1(BASE_CODE + 4, id.to_string()),
+            Data(e) => ToFfiError::from(e),
+            Parse(e) => ToFfiError::from(e),
+        }
+    }
+}
+
+impl ToFfiError for cidr_trie::CompileCidrListError {
+    fn from(self) -> ErrorDesc {
+        use cidr_trie::CompileCidrListError::*;
+        const BASE_CODE: u32 = 0x8001_1800;
+        match self {
+            InvalidCidr(l) => ErrorDesc::e1(BASE_CODE + 1, l.to_string()),
+            TagTooLong(l) => ErrorDesc::e1(BASE_CODE + 2, l.to_string()),
+            TooManyTags => ErrorDesc::e0(BASE_CODE + 3),
+            TooManyNodes => ErrorDesc::e0(BASE_CODE + 4),
+        }
+    }
+}
+
+#[cfg(feature = "sync")]
+impl ToFfiError for crate::sync::SyncError {
+    fn from(self) -> ErrorDesc {
+        use crate::sync::SyncError::*;
+        const BASE_CODE: u32 = 0x8001_1A00;
+        match self {
+            InvalidEndpoint => ErrorDesc::e0(BASE_CODE + 1),
+            Transport(e) => ErrorDesc::e1(BASE_CODE + 2, e),
+            UnexpectedStatus(s) => ErrorDesc::e1(BASE_CODE + 3, s.to_string()),
+            RemoteChanged => ErrorDesc::e0(BASE_CODE + 4),
+            InvalidSnapshot => ErrorDesc::e0(BASE_CODE + 5),
+            Data(e) => ToFfiError::from(e),
+        }
+    }
+}
+
 pub(super) struct InvalidCborError;
 
 impl Display for InvalidCborError {
@@ -1,5 +1,9 @@
+use std::ffi::c_void;
+use std::io;
+use std::sync::Arc;
 use std::{panic::AssertUnwindSafe, ptr::null_mut};
 
+use ytflow::plugin::socket_hook::{set_socket_hook, RawSocket, SocketKind};
 use ytflow::tokio::runtime::{Builder as TokioRuntimeBuilder, Runtime as TokioRuntime};
 
 use super::error::ytflow_result;
@@ -32,3 +36,42 @@ pub unsafe extern "C" fn ytflow_runtime_free(runtime: *mut ytflow_runtime) -> yt
         (null_mut(), 0)
     }))
 }
+
+/// Called with the raw fd (or `SOCKET` on Windows) of every outbound socket right after it
+/// is created, before it is bound or connected. `kind` is 1 for TCP, 2 for UDP and 3 for ICMP.
+/// Return 0 on success, or an OS error code to abort the connection attempt.
+#[allow(non_camel_case_types)]
+pub type ytflow_socket_hook =
+    unsafe extern "C" fn(ctx: *mut c_void, socket: u64, kind: u8, is_ipv6: bool) -> i32;
+
+struct SocketHookCtx(*mut c_void);
+
+// The embedder is responsible for making `ctx` usable from any thread.
+unsafe impl Send for SocketHookCtx {}
+unsafe impl Sync for SocketHookCtx {}
+
+/// Install a process-wide hook for outbound sockets, e.g. to call `VpnService.protect()` on
+/// Android. Pass a null `callback` to remove the hook. `ctx` must stay valid until the hook
+/// is replaced or removed.
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_runtime_set_socket_hook(
+    callback: Option<ytflow_socket_hook>,
+    ctx: *mut c_void,
+) -> ytflow_result {
+    ytflow_result::catch_ptr_unwind(AssertUnwindSafe(move || {
+        let Some(callback) = callback else {
+            set_socket_hook(None);
+            return (null_mut(), 0);
+        };
+        let ctx = SocketHookCtx(ctx);
+        set_socket_hook(Some(Arc::new(
+            move |socket: RawSocket, kind: SocketKind, is_ipv6: bool| match unsafe {
+                callback(ctx.0, socket as u64, kind as u8, is_ipv6)
+            } {
+                0 => Ok(()),
+                code => Err(io::Error::from_raw_os_error(code)),
+            },
+        )));
+        (null_mut(), 0)
+    }))
+}
@@ -2,9 +2,13 @@ use std::ffi::CStr;
 use std::os::raw::c_char;
 use std::panic::AssertUnwindSafe;
 
-use super::interop::serialize_buffer;
+use ytflow::data::{Connection as ytflow_connection, Proxy};
+
+use super::interop::{serialize_buffer, serialize_byte_buffer};
 use super::{error::ytflow_result, interop::serialize_string_buffer};
-use crate::share_link::{decode_share_link, encode_share_link};
+use crate::share_link::{
+    decode_share_link, encode_proxy_group_qr, encode_share_link, encode_share_link_qr,
+};
 
 #[no_mangle]
 pub unsafe extern "C" fn ytflow_app_share_link_decode(link: *const c_char) -> ytflow_result {
@@ -26,3 +30,31 @@ pub unsafe extern "C" fn ytflow_app_share_link_encode(
             .map_err(ytflow_result::from)
     }))
 }
+
+/// Returns a square 8-bit luma bitmap. The width is the square root of the buffer length.
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_app_share_link_encode_qr(
+    proxy: *const u8,
+    proxy_len: usize,
+    scale: u32,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let proxy = super::proxy::deserialize_proxy_cbor(proxy, proxy_len)?;
+        encode_share_link_qr(&proxy, scale)
+            .map(|qr| serialize_byte_buffer(qr.luma.into_vec()))
+            .map_err(ytflow_result::from)
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_app_share_link_encode_qr_by_proxy_group(
+    proxy_group_id: u32,
+    scale: u32,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let conn = unsafe { &*conn };
+        Proxy::query_all_by_group(proxy_group_id.into(), conn)
+            .map(|p| serialize_buffer(&encode_proxy_group_qr(&p, scale)))
+    }))
+}
@@ -1,10 +1,15 @@
+use std::collections::BTreeMap;
 use std::ffi::CStr;
 use std::os::raw::c_char;
 use std::panic::AssertUnwindSafe;
+use std::ptr::null_mut;
+
+#[allow(non_camel_case_types)]
+use ytflow::data::{Connection as ytflow_connection, DataError};
 
 use crate::subscription::{
     decode_subscription, decode_subscription_with_format, DecodeError, SubscriptionFormat,
-    SubscriptionUserInfo,
+    SubscriptionMetadata, SubscriptionUserInfo,
 };
 
 use super::error::{ytflow_result, InvalidCborError};
@@ -48,3 +53,40 @@ pub unsafe extern "C" fn ytflow_app_subscription_decode_with_format(
         decode_subscription_with_format(subscription, format).map(|s| serialize_buffer(&s))
     }))
 }
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_app_subscription_metadata_headers_decode(
+    headers: *const u8,
+    headers_len: usize,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let headers = std::slice::from_raw_parts(headers, headers_len);
+        let headers: BTreeMap<String, String> =
+            cbor4ii::serde::from_slice(headers).map_err(|_| InvalidCborError)?;
+        let metadata = SubscriptionMetadata::decode_headers(
+            headers.iter().map(|(k, v)| (k.as_str(), v.as_str())),
+        );
+        Ok::<_, InvalidCborError>(serialize_buffer(&metadata))
+    }))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_app_subscription_metadata_save(
+    proxy_group_id: u32,
+    metadata: *const u8,
+    metadata_len: usize,
+    conn: *const ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let metadata = std::slice::from_raw_parts(metadata, metadata_len);
+        let metadata: SubscriptionMetadata =
+            cbor4ii::serde::from_slice(metadata).map_err(|_| DataError::InvalidData {
+                domain: "subscription metadata",
+                field: "metadata",
+            })?;
+        let conn = unsafe { &*conn };
+        metadata
+            .save(proxy_group_id, conn)
+            .map(|()| (null_mut(), 0))
+    }))
+}
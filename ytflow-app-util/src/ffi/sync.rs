@@ -0,0 +1,95 @@
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::panic::AssertUnwindSafe;
+use std::ptr::null_mut;
+
+#[allow(non_camel_case_types)]
+use ytflow::data::Connection as ytflow_connection;
+
+use crate::sync::{apply_pulled_profile, resolve_conflict, sync_item, SyncEndpoint, SyncItem};
+
+use super::error::{ytflow_result, InvalidCborError};
+use super::interop::serialize_buffer;
+
+unsafe fn decode_args(
+    item: *const u8,
+    item_len: usize,
+    endpoint: *const u8,
+    endpoint_len: usize,
+) -> Result<(SyncItem, SyncEndpoint), InvalidCborError> {
+    let item = std::slice::from_raw_parts(item, item_len);
+    let endpoint = std::slice::from_raw_parts(endpoint, endpoint_len);
+    Ok((
+        cbor4ii::serde::from_slice(item).map_err(|_| InvalidCborError)?,
+        cbor4ii::serde::from_slice(endpoint).map_err(|_| InvalidCborError)?,
+    ))
+}
+
+/// Sync a profile or a proxy group with its snapshot on the remote endpoint. Blocks until the
+/// requests complete.
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_sync_item(
+    item: *const u8,
+    item_len: usize,
+    endpoint: *const u8,
+    endpoint_len: usize,
+    device_id: *const c_char,
+    conn: *mut ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let (item, endpoint) = decode_args(item, item_len, endpoint, endpoint_len)?;
+        let device_id = unsafe { CStr::from_ptr(device_id) }.to_string_lossy();
+        let conn = unsafe { &mut *conn };
+        let backend = endpoint.connect()?;
+        sync_item(&*backend, item, &device_id, conn)
+            .map(|o| serialize_buffer(&o))
+            .map_err(ytflow_result::from)
+    }))
+}
+
+/// Resolve a conflict reported by `ytflow_sync_item` by keeping either the local item or the
+/// remote snapshot.
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_sync_resolve_conflict(
+    item: *const u8,
+    item_len: usize,
+    endpoint: *const u8,
+    endpoint_len: usize,
+    device_id: *const c_char,
+    keep_local: bool,
+    conn: *mut ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let (item, endpoint) = decode_args(item, item_len, endpoint, endpoint_len)?;
+        let device_id = unsafe { CStr::from_ptr(device_id) }.to_string_lossy();
+        let conn = unsafe { &mut *conn };
+        let backend = endpoint.connect()?;
+        resolve_conflict(&*backend, item, &device_id, keep_local, conn)
+            .map(|o| serialize_buffer(&o))
+            .map_err(ytflow_result::from)
+    }))
+}
+
+/// Import a profile pulled by `ytflow_sync_item` or `ytflow_sync_resolve_conflict` over the local
+/// profile. The pull is only recorded once this succeeds.
+#[no_mangle]
+pub unsafe extern "C" fn ytflow_sync_apply_pulled_profile(
+    profile_id: u32,
+    content: *const u8,
+    content_len: usize,
+    endpoint: *const u8,
+    endpoint_len: usize,
+    conn: *mut ytflow_connection,
+) -> ytflow_result {
+    ytflow_result::catch_result_unwind(AssertUnwindSafe(move || {
+        let content = unsafe { std::slice::from_raw_parts(content, content_len) };
+        let endpoint = unsafe { std::slice::from_raw_parts(endpoint, endpoint_len) };
+        let endpoint: SyncEndpoint =
+            cbor4ii::serde::from_slice(endpoint).map_err(|_| InvalidCborError)?;
+        let conn = unsafe { &mut *conn };
+        let backend = endpoint.connect()?;
+        apply_pulled_profile(&*backend, profile_id, content, conn)
+            .map(|()| (null_mut(), 0))
+            .map_err(ytflow_result::from)
+    }))
+}
@@ -1,9 +1,12 @@
 #![cfg_attr(feature = "ffi", feature(ptr_metadata))]
 
 pub mod cbor;
+pub mod cidr_trie;
 #[cfg(feature = "ffi")]
 pub mod ffi;
 pub mod profile;
 pub mod proxy;
 pub mod share_link;
 pub mod subscription;
+#[cfg(feature = "sync")]
+pub mod sync;
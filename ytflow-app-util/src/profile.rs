@@ -1,8 +1,16 @@
+mod bundle;
 mod export;
 mod import;
 
-pub use export::export_profile_toml;
+pub use bundle::{
+    export_signed_profile_bundle, generate_signing_key, key_fingerprint, verify_profile_bundle,
+    ProfileBundleError, ProfileBundleResult, VerifiedProfileBundle,
+};
+pub use export::{
+    export_profile_cbor_streamed, export_profile_toml, export_profile_toml_with_base,
+    ProfileExportItem,
+};
 pub use import::{
     parse_profile_toml, ParseTomlProfileError, ParseTomlProfileResult, ParsedTomlPlugin,
-    ParsedTomlProfile,
+    ParsedTomlProfile, ParsedTomlProfileTranslation,
 };
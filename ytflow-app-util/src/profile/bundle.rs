@@ -0,0 +1,198 @@
+use ed25519_dalek::{Signature, Signer, SigningKey as Ed25519SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use thiserror::Error;
+
+use ytflow::data::{
+    Connection as DbConnection, DataError, DataResult, ProfileId, SigningKey, SigningKeyId,
+    TrustedKey,
+};
+
+use super::export::export_profile_toml;
+use super::import::{parse_profile_toml, ParseTomlProfileError, ParsedTomlProfile};
+
+const BUNDLE_VERSION: u8 = 1;
+/// Prepended to the profile before signing, so that a bundle signature cannot be passed off as
+/// a signature over anything else made with the same key.
+const SIGNATURE_CONTEXT: &[u8] = b"ytflow profile bundle v1\0";
+
+#[derive(Debug, Error)]
+pub enum ProfileBundleError {
+    #[error("invalid profile bundle")]
+    InvalidEncoding,
+    #[error("unknown profile bundle version {0}")]
+    UnknownVersion(u8),
+    #[error("the signature does not match the profile bundle")]
+    BadSignature,
+    #[error("signing key {0} does not exist")]
+    NoSigningKey(SigningKeyId),
+    #[error(transparent)]
+    Data(#[from] DataError),
+    #[error(transparent)]
+    Parse(#[from] ParseTomlProfileError),
+}
+
+pub type ProfileBundleResult<T> = Result<T, ProfileBundleError>;
+
+#[derive(Serialize, Deserialize)]
+struct RawProfileBundle {
+    version: u8,
+    /// The profile exported as TOML.
+    profile: ByteBuf,
+    public_key: ByteBuf,
+    signature: ByteBuf,
+}
+
+/// A profile bundle whose signature has been checked against the public key it carries.
+#[derive(Debug, Clone, Serialize)]
+pub struct VerifiedProfileBundle {
+    #[serde(with = "serde_bytes")]
+    pub public_key: [u8; 32],
+    /// The public key in a form for users to compare against the one published out of band.
+    pub fingerprint: String,
+    /// The trusted publisher owning the key. `None` if the key is not trusted yet, in which case
+    /// the user must be asked before importing the profile.
+    pub signer: Option<TrustedKey>,
+    pub profile: ParsedTomlProfile,
+}
+
+fn signed_message(profile: &[u8]) -> Vec<u8> {
+    let mut msg = Vec::with_capacity(SIGNATURE_CONTEXT.len() + profile.len());
+    msg.extend_from_slice(SIGNATURE_CONTEXT);
+    msg.extend_from_slice(profile);
+    msg
+}
+
+/// Format a public key as groups of four hex digits.
+pub fn key_fingerprint(public_key: &[u8; 32]) -> String {
+    hex::encode(public_key)
+        .as_bytes()
+        .chunks(4)
+        .map(|c| std::str::from_utf8(c).expect("hex digits must be ASCII"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Generate a new ed25519 key pair for signing profile bundles and store it in the database.
+pub fn generate_signing_key(name: String, conn: &DbConnection) -> DataResult<u32> {
+    let key = Ed25519SigningKey::generate(&mut rand::rngs::OsRng);
+    SigningKey::create(name, key.to_bytes(), key.verifying_key().to_bytes(), conn)
+}
+
+pub fn export_signed_profile_bundle(
+    profile_id: ProfileId,
+    signing_key_id: SigningKeyId,
+    conn: &DbConnection,
+) -> ProfileBundleResult<Option<Vec<u8>>> {
+    let signing_key = SigningKey::query_by_id(signing_key_id.0, conn)?
+        .ok_or(ProfileBundleError::NoSigningKey(signing_key_id))?;
+    let Some(profile) = export_profile_toml(profile_id, conn)? else {
+        return Ok(None);
+    };
+    let key = Ed25519SigningKey::from_bytes(&signing_key.secret_key);
+    let signature = key.sign(&signed_message(profile.as_bytes()));
+    let bundle = RawProfileBundle {
+        version: BUNDLE_VERSION,
+        profile: ByteBuf::from(profile.into_bytes()),
+        public_key: ByteBuf::from(key.verifying_key().to_bytes().to_vec()),
+        signature: ByteBuf::from(signature.to_bytes().to_vec()),
+    };
+    Ok(Some(
+        cbor4ii::serde::to_vec(vec![], &bundle).expect("Could not serialize profile bundle"),
+    ))
+}
+
+/// Check the signature of a profile bundle and parse the profile inside. A bundle that has been
+/// tampered with is rejected; whether the signer is trusted is left for the caller to act on.
+pub fn verify_profile_bundle(
+    bundle: &[u8],
+    conn: &DbConnection,
+) -> ProfileBundleResult<VerifiedProfileBundle> {
+    let bundle: RawProfileBundle =
+        cbor4ii::serde::from_slice(bundle).map_err(|_| ProfileBundleError::InvalidEncoding)?;
+    if bundle.version != BUNDLE_VERSION {
+        return Err(ProfileBundleError::UnknownVersion(bundle.version));
+    }
+    let public_key = <[u8; 32]>::try_from(&bundle.public_key[..])
+        .map_err(|_| ProfileBundleError::InvalidEncoding)?;
+    let signature = <[u8; 64]>::try_from(&bundle.signature[..])
+        .map_err(|_| ProfileBundleError::InvalidEncoding)?;
+    let verifying_key =
+        VerifyingKey::from_bytes(&public_key).map_err(|_| ProfileBundleError::BadSignature)?;
+    verifying_key
+        .verify(
+            &signed_message(&bundle.profile),
+            &Signature::from_bytes(&signature),
+        )
+        .map_err(|_| ProfileBundleError::BadSignature)?;
+
+    let profile = parse_profile_toml(&bundle.profile)?;
+    let signer = TrustedKey::query_by_public_key(&public_key, conn)?;
+    Ok(VerifiedProfileBundle {
+        public_key,
+        fingerprint: key_fingerprint(&public_key),
+        signer,
+        profile,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ytflow::data::{Database, Profile};
+
+    fn setup() -> (DbConnection, ProfileId, SigningKeyId) {
+        let db = Database::connect_temp().unwrap();
+        let profile_id = Profile::create("test".into(), "en-US".into(), &db)
+            .unwrap()
+            .into();
+        let key_id = generate_signing_key("me".into(), &db).unwrap().into();
+        (db, profile_id, key_id)
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let (db, profile_id, key_id) = setup();
+        let bundle = export_signed_profile_bundle(profile_id, key_id, &db)
+            .unwrap()
+            .unwrap();
+
+        let verified = verify_profile_bundle(&bundle, &db).unwrap();
+        let signing_key = SigningKey::query_by_id(key_id.0, &db).unwrap().unwrap();
+        assert_eq!(verified.public_key, signing_key.public_key);
+        assert_eq!(verified.profile.name.as_deref(), Some("test"));
+        assert!(verified.signer.is_none());
+
+        TrustedKey::create("publisher".into(), &signing_key.public_key, &db).unwrap();
+        let verified = verify_profile_bundle(&bundle, &db).unwrap();
+        assert_eq!(verified.signer.unwrap().name, "publisher");
+    }
+
+    #[test]
+    fn test_reject_tampered() {
+        let (db, profile_id, key_id) = setup();
+        let bundle = export_signed_profile_bundle(profile_id, key_id, &db)
+            .unwrap()
+            .unwrap();
+        let mut raw: RawProfileBundle = cbor4ii::serde::from_slice(&bundle).unwrap();
+        let toml = String::from_utf8(raw.profile.into_vec()).unwrap();
+        raw.profile = ByteBuf::from(toml.replace("\"test\"", "\"evil\"").into_bytes());
+        let tampered = cbor4ii::serde::to_vec(vec![], &raw).unwrap();
+
+        assert!(matches!(
+            verify_profile_bundle(&tampered, &db),
+            Err(ProfileBundleError::BadSignature)
+        ));
+    }
+
+    #[test]
+    fn test_key_fingerprint() {
+        let mut key = [0u8; 32];
+        key[0] = 0xab;
+        key[1] = 0xcd;
+        let fingerprint = key_fingerprint(&key);
+        assert!(fingerprint.starts_with("abcd 0000 "));
+        assert_eq!(fingerprint.len(), 16 * 4 + 15);
+    }
+}
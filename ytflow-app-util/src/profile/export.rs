@@ -1,13 +1,17 @@
 use cbor4ii::core::Value as CborValue;
 use chrono::{Datelike, Timelike};
 use rusqlite::Error as SqError;
+use serde::Serialize;
 use toml_edit::{
     Date as TomlDate, Datetime as TomlDatetime, DocumentMut, InlineTable, Item as TomlItem, Table,
-    Time as TomlTime, Value as TomlValue,
+    TableLike, Time as TomlTime, Value as TomlValue,
 };
 
-use ytflow::data::{Connection as DbConnection, DataResult, ProfileId};
+use ytflow::data::{
+    Connection as DbConnection, DataResult, PluginTranslation, ProfileId, ProfileTranslation,
+};
 
+use super::import::{parse_plugin_desc, try_decode_toml_item, try_decode_toml_value};
 use crate::cbor::escape_cbor_buf;
 
 fn encode_naive_datetime(dt: chrono::NaiveDateTime) -> TomlDatetime {
@@ -87,21 +91,28 @@ fn encode_cbor_buf(buf: &[u8]) -> TomlValue {
     }
 }
 
-pub fn export_profile_toml(
-    profile_id: ProfileId,
-    conn: &DbConnection,
-) -> DataResult<Option<String>> {
-    let profile = ytflow::data::Profile::query_by_id(profile_id.0 as _, conn)?
-        .ok_or(SqError::QueryReturnedNoRows)?;
-    let entry_plugin_names = ytflow::data::Plugin::query_entry_by_profile(profile_id, conn)?
-        .into_iter()
-        .map(|p| p.name);
-    let plugins = ytflow::data::Plugin::query_all_by_profile(profile_id, conn)?;
-
-    let mut doc = DocumentMut::new();
-    doc.insert("version", TomlItem::Value(1i64.into()));
+fn encode_plugin_desc(desc: &str) -> String {
+    let mut decor = desc
+        .trim()
+        .lines()
+        .map(|l| {
+            if l.is_empty() {
+                "\n#".into()
+            } else {
+                format!("\n# {}", l.trim())
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("");
+    decor.push_str("\n");
+    decor
+}
 
-    let metadata_table: Table = [
+fn encode_profile_fields(
+    profile: ytflow::data::Profile,
+    entry_plugin_names: Vec<String>,
+) -> [(&'static str, TomlValue); 5] {
+    [
         ("name", TomlValue::from(profile.name)),
         (
             "permanent_id",
@@ -114,43 +125,130 @@ pub fn export_profile_toml(
         ),
         (
             "entry_plugins",
-            TomlValue::Array(entry_plugin_names.map(TomlValue::from).collect()),
+            TomlValue::Array(
+                entry_plugin_names
+                    .into_iter()
+                    .map(TomlValue::from)
+                    .collect(),
+            ),
         ),
     ]
-    .into_iter()
-    .collect();
-    doc.insert("profile", TomlItem::Table(metadata_table));
+}
+
+/// Encode translations as `translations.<locale> = ...` lines. Absent if
+/// there is no translation.
+fn encode_translations<'a>(
+    translations: impl Iterator<Item = (&'a str, TomlValue)>,
+) -> Option<TomlValue> {
+    let mut table: InlineTable = translations.collect();
+    if table.is_empty() {
+        return None;
+    }
+    table.set_dotted(true);
+    Some(TomlValue::InlineTable(table))
+}
+
+fn encode_profile_translations(translations: &[ProfileTranslation]) -> Option<TomlValue> {
+    encode_translations(translations.iter().map(|t| {
+        let mut entry = InlineTable::new();
+        entry.insert("name", t.name.as_str().into());
+        if !t.desc.is_empty() {
+            entry.insert("desc", t.desc.as_str().into());
+        }
+        (t.locale.as_str(), TomlValue::InlineTable(entry))
+    }))
+}
 
-    let plugin_tables = plugins.into_iter().map(|p| {
-        let mut table: Table = [
-            ("plugin", TomlValue::from(p.plugin)),
-            ("plugin_version", TomlValue::from(p.plugin_version as i64)),
-            ("param", encode_cbor_buf(&p.param).into()),
-            ("updated_at", encode_naive_datetime(p.updated_at).into()),
-        ]
+fn encode_plugin_translations(
+    p: &ytflow::data::Plugin,
+    translations: &[PluginTranslation],
+) -> Option<TomlValue> {
+    encode_translations(
+        translations
+            .iter()
+            .filter(|t| t.plugin_id == p.id)
+            .map(|t| (t.locale.as_str(), TomlValue::from(t.desc.as_str()))),
+    )
+}
+
+fn encode_plugin_fields(p: &ytflow::data::Plugin) -> [(&'static str, TomlValue); 4] {
+    [
+        ("plugin", TomlValue::from(p.plugin.as_str())),
+        ("plugin_version", TomlValue::from(p.plugin_version as i64)),
+        ("param", encode_cbor_buf(&p.param)),
+        ("updated_at", encode_naive_datetime(p.updated_at).into()),
+    ]
+}
+
+fn encode_plugin_table(p: &ytflow::data::Plugin, translations: &[PluginTranslation]) -> Table {
+    let mut table: Table = encode_plugin_fields(p).into_iter().collect();
+    if let Some(translations) = encode_plugin_translations(p, translations) {
+        table.insert("translations", TomlItem::Value(translations));
+    }
+    table.decor_mut().set_prefix(encode_plugin_desc(&p.desc));
+    table
+}
+
+struct ProfileForExport {
+    profile: ytflow::data::Profile,
+    entry_plugin_names: Vec<String>,
+    plugins: Vec<ytflow::data::Plugin>,
+    translations: Vec<ProfileTranslation>,
+    plugin_translations: Vec<PluginTranslation>,
+}
+
+fn query_profile_for_export(
+    profile_id: ProfileId,
+    conn: &DbConnection,
+) -> DataResult<ProfileForExport> {
+    let profile = ytflow::data::Profile::query_by_id(profile_id.0 as _, conn)?
+        .ok_or(SqError::QueryReturnedNoRows)?;
+    let entry_plugin_names = ytflow::data::Plugin::query_entry_by_profile(profile_id, conn)?
         .into_iter()
+        .map(|p| p.name)
         .collect();
-        let mut decor = p
-            .desc
-            .trim()
-            .lines()
-            .map(|l| {
-                if l.is_empty() {
-                    "\n#".into()
-                } else {
-                    format!("\n# {}", l.trim())
-                }
-            })
-            .collect::<Vec<_>>()
-            .join("");
-        decor.push_str("\n");
-        table.decor_mut().set_prefix(decor);
-        (p.name, table)
-    });
+    let plugins = ytflow::data::Plugin::query_all_by_profile(profile_id, conn)?;
+    let translations = ProfileTranslation::query_all_by_profile(profile_id, conn)?;
+    let plugin_translations = PluginTranslation::query_all_by_profile(profile_id, conn)?;
+    Ok(ProfileForExport {
+        profile,
+        entry_plugin_names,
+        plugins,
+        translations,
+        plugin_translations,
+    })
+}
+
+pub fn export_profile_toml(
+    profile_id: ProfileId,
+    conn: &DbConnection,
+) -> DataResult<Option<String>> {
+    let ProfileForExport {
+        profile,
+        entry_plugin_names,
+        plugins,
+        translations,
+        plugin_translations,
+    } = query_profile_for_export(profile_id, conn)?;
+
+    let mut doc = DocumentMut::new();
+    doc.insert("version", TomlItem::Value(1i64.into()));
+
+    let mut metadata_table: Table = encode_profile_fields(profile, entry_plugin_names)
+        .into_iter()
+        .collect();
+    if let Some(translations) = encode_profile_translations(&translations) {
+        metadata_table.insert("translations", TomlItem::Value(translations));
+    }
+    doc.insert("profile", TomlItem::Table(metadata_table));
+
     let mut plugin_table = Table::new();
     plugin_table.set_implicit(true);
-    for (key, table) in plugin_tables {
-        plugin_table.insert(&key, TomlItem::Table(table));
+    for p in &plugins {
+        plugin_table.insert(
+            &p.name,
+            TomlItem::Table(encode_plugin_table(p, &plugin_translations)),
+        );
     }
     doc.insert("plugins", TomlItem::Table(plugin_table));
 
@@ -158,6 +256,210 @@ pub fn export_profile_toml(
     Ok(Some(toml_str))
 }
 
+fn is_same_value(old: &TomlItem, new: &TomlValue) -> bool {
+    if let (Some(old), Some(new)) = (old.as_datetime(), new.as_datetime()) {
+        return old == new;
+    }
+    let old = try_decode_toml_item(old);
+    old.is_some() && old == try_decode_toml_value(new)
+}
+
+/// Replace `old` with `new` unless they are equivalent, keeping the comments
+/// and whitespace around the old value.
+fn merge_value(old: &mut TomlItem, mut new: TomlValue) {
+    if is_same_value(old, &new) {
+        return;
+    }
+    if let Some(old) = old.as_value() {
+        *new.decor_mut() = old.decor().clone();
+    }
+    *old = TomlItem::Value(new);
+}
+
+fn merge_table_value(table: &mut dyn TableLike, key: &str, new: TomlValue) {
+    match table.get_mut(key) {
+        Some(old) => merge_value(old, new),
+        None => {
+            table.insert(key, TomlItem::Value(new));
+        }
+    }
+}
+
+fn merge_translations(table: &mut dyn TableLike, new: Option<TomlValue>) {
+    match new {
+        Some(new) => merge_table_value(table, "translations", new),
+        None => {
+            table.remove("translations");
+        }
+    }
+}
+
+/// Merge a plugin param key by key, so that comments on the keys left
+/// untouched survive.
+fn merge_param(table: &mut Table, new: TomlValue) {
+    let Some(old) = table.get_mut("param") else {
+        table.insert("param", TomlItem::Value(new));
+        return;
+    };
+    if is_same_value(old, &new) {
+        return;
+    }
+    let new_table = match new {
+        TomlValue::InlineTable(t) if old.is_table_like() => t,
+        new => return merge_value(old, new),
+    };
+    let old_table = old.as_table_like_mut().unwrap();
+    let stale_keys: Vec<_> = old_table
+        .iter()
+        .map(|(k, _)| k.to_string())
+        .filter(|k| !new_table.contains_key(k))
+        .collect();
+    for key in stale_keys {
+        old_table.remove(&key);
+    }
+    for (key, value) in new_table.iter() {
+        merge_table_value(old_table, key, value.clone());
+    }
+}
+
+fn merge_plugin_table(
+    table: &mut Table,
+    p: &ytflow::data::Plugin,
+    translations: &[PluginTranslation],
+) {
+    let old_desc = table
+        .decor()
+        .prefix()
+        .and_then(|p| p.as_str())
+        .map(parse_plugin_desc)
+        .unwrap_or_default();
+    let new_desc = encode_plugin_desc(&p.desc);
+    if old_desc != parse_plugin_desc(&new_desc) {
+        table.decor_mut().set_prefix(new_desc);
+    }
+    for (key, value) in encode_plugin_fields(p) {
+        if key == "param" {
+            merge_param(table, value);
+        } else {
+            merge_table_value(table, key, value);
+        }
+    }
+    merge_translations(table, encode_plugin_translations(p, translations));
+}
+
+/// Export a profile on top of `base`, a TOML document previously exported or
+/// imported. Only values that have changed since are rewritten, so that key
+/// ordering, formatting and user-added comments are preserved and the
+/// resulting diff stays minimal. New plugins are appended to the end.
+///
+/// Falls back to [`export_profile_toml`] if `base` is not a valid profile
+/// document.
+pub fn export_profile_toml_with_base(
+    profile_id: ProfileId,
+    base: &[u8],
+    conn: &DbConnection,
+) -> DataResult<Option<String>> {
+    let Some(mut doc) = std::str::from_utf8(base)
+        .ok()
+        .and_then(|b| b.parse::<DocumentMut>().ok())
+        .filter(|d| d.get("version").and_then(|v| v.as_integer()) == Some(1))
+    else {
+        return export_profile_toml(profile_id, conn);
+    };
+    let ProfileForExport {
+        profile,
+        entry_plugin_names,
+        plugins,
+        translations,
+        plugin_translations,
+    } = query_profile_for_export(profile_id, conn)?;
+
+    if !doc.get("profile").is_some_and(|p| p.is_table_like()) {
+        doc.insert("profile", TomlItem::Table(Table::new()));
+    }
+    let profile_table = doc["profile"].as_table_like_mut().unwrap();
+    for (key, value) in encode_profile_fields(profile, entry_plugin_names) {
+        merge_table_value(profile_table, key, value);
+    }
+    merge_translations(profile_table, encode_profile_translations(&translations));
+
+    if !doc.get("plugins").is_some_and(|p| p.is_table()) {
+        let mut plugin_table = Table::new();
+        plugin_table.set_implicit(true);
+        doc.insert("plugins", TomlItem::Table(plugin_table));
+    }
+    let plugin_table = doc["plugins"].as_table_mut().unwrap();
+    let stale_plugins: Vec<_> = plugin_table
+        .iter()
+        .map(|(k, _)| k.to_string())
+        .filter(|k| !plugins.iter().any(|p| &p.name == k))
+        .collect();
+    for name in stale_plugins {
+        plugin_table.remove(&name);
+    }
+    for p in &plugins {
+        match plugin_table.get_mut(&p.name).and_then(|t| t.as_table_mut()) {
+            Some(table) => merge_plugin_table(table, p, &plugin_translations),
+            None => {
+                plugin_table.insert(
+                    &p.name,
+                    TomlItem::Table(encode_plugin_table(p, &plugin_translations)),
+                );
+            }
+        }
+    }
+
+    Ok(Some(doc.to_string()))
+}
+
+/// An item yielded by [`export_profile_cbor_streamed`]. The profile metadata
+/// always comes first, followed by each plugin of the profile.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ProfileExportItem {
+    Profile {
+        profile: ytflow::data::Profile,
+        entry_plugins: Vec<String>,
+    },
+    Plugin(ytflow::data::Plugin),
+}
+
+/// Export a profile as a sequence of CBOR-encoded [`ProfileExportItem`]s,
+/// without materializing all plugins in memory at once. `on_item` may return
+/// `false` to abort the export, in which case `Ok(false)` is returned.
+pub fn export_profile_cbor_streamed(
+    profile_id: ProfileId,
+    conn: &DbConnection,
+    mut on_item: impl FnMut(&[u8]) -> bool,
+) -> DataResult<bool> {
+    let profile = ytflow::data::Profile::query_by_id(profile_id.0 as _, conn)?
+        .ok_or(SqError::QueryReturnedNoRows)?;
+    let entry_plugins = ytflow::data::Plugin::query_entry_by_profile(profile_id, conn)?
+        .into_iter()
+        .map(|p| p.name)
+        .collect();
+
+    let mut buf = Vec::with_capacity(4096);
+    let mut emit = |item: &ProfileExportItem| {
+        buf.clear();
+        buf = cbor4ii::serde::to_vec(std::mem::take(&mut buf), item)
+            .expect("Could not serialize export item into CBOR");
+        on_item(&buf)
+    };
+    if !emit(&ProfileExportItem::Profile {
+        profile,
+        entry_plugins,
+    }) {
+        return Ok(false);
+    }
+    let mut completed = true;
+    ytflow::data::Plugin::for_each_by_profile(profile_id, conn, |plugin| {
+        completed = emit(&ProfileExportItem::Plugin(plugin));
+        completed
+    })?;
+    Ok(completed)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -165,7 +467,7 @@ mod tests {
     use ciborium::cbor;
 
     use serde_bytes::Bytes;
-    use ytflow::data::{Database, Plugin, Profile};
+    use ytflow::data::{Database, Plugin, PluginTranslation, Profile, ProfileTranslation};
 
     use crate::cbor::to_cbor;
 
@@ -375,4 +677,205 @@ updated_at = 2024-04-27T09:43:17.191
             "{toml}"
         );
     }
+
+    #[test]
+    fn test_export_profile_toml_with_base() {
+        let db = Database::connect_temp().unwrap();
+        let profile_id = Profile::create("test".into(), "en-US".into(), &db)
+            .unwrap()
+            .into();
+        let plugin_a_id = Plugin::create(
+            profile_id,
+            "a".into(),
+            "Plugin A".into(),
+            "null".into(),
+            0,
+            to_cbor(cbor!(null)).into_vec(),
+            &db,
+        )
+        .unwrap();
+        let plugin_b_id = Plugin::create(
+            profile_id,
+            "b".into(),
+            "Plugin B".into(),
+            "redirect".into(),
+            0,
+            to_cbor(cbor!({ "next" => "a.tcp", "port" => 1 })).into_vec(),
+            &db,
+        )
+        .unwrap();
+        let exported = export_profile_toml(profile_id, &db).unwrap().unwrap();
+        let base = exported
+            .replace("param.next", "# Keep me\nparam.next")
+            .replace("param.port = 1", "param.port = 1 # and me")
+            + "\n# Trailing comment\n";
+
+        let unchanged = export_profile_toml_with_base(profile_id, base.as_bytes(), &db)
+            .unwrap()
+            .unwrap();
+        assert_eq!(unchanged, base);
+
+        Plugin::update(
+            plugin_b_id,
+            profile_id,
+            "b".into(),
+            "Plugin B".into(),
+            "redirect".into(),
+            0,
+            to_cbor(cbor!({ "next" => "a.tcp", "port" => 2 })).into_vec(),
+            &db,
+        )
+        .unwrap();
+        Plugin::delete(plugin_a_id, &db).unwrap();
+        Plugin::create(
+            profile_id,
+            "c".into(),
+            "Plugin C".into(),
+            "null".into(),
+            0,
+            to_cbor(cbor!(null)).into_vec(),
+            &db,
+        )
+        .unwrap();
+        let merged = export_profile_toml_with_base(profile_id, base.as_bytes(), &db)
+            .unwrap()
+            .unwrap();
+        assert!(
+            merged.contains("# Keep me\nparam.next = \"a.tcp\""),
+            "{merged}"
+        );
+        assert!(merged.contains("param.port = 2 # and me"), "{merged}");
+        assert!(merged.contains("# Trailing comment"), "{merged}");
+        assert!(!merged.contains("[plugins.a]"), "{merged}");
+        assert!(
+            merged.find("[plugins.b]").unwrap() < merged.find("# Plugin C\n[plugins.c]").unwrap(),
+            "{merged}"
+        );
+        let parsed = crate::profile::parse_profile_toml(merged.as_bytes()).unwrap();
+        assert_eq!(parsed.plugins.len(), 2);
+        assert_eq!(parsed.plugins[0].plugin.desc, "Plugin B");
+    }
+
+    #[test]
+    fn test_export_profile_toml_translations() {
+        let db = Database::connect_temp().unwrap();
+        let profile_id = Profile::create("test".into(), "en-US".into(), &db)
+            .unwrap()
+            .into();
+        let plugin_id = Plugin::create(
+            profile_id,
+            "a".into(),
+            "Plugin A".into(),
+            "null".into(),
+            0,
+            to_cbor(cbor!(null)).into_vec(),
+            &db,
+        )
+        .unwrap();
+        ProfileTranslation::upsert(profile_id, "zh-CN".into(), "测试".into(), "".into(), &db)
+            .unwrap();
+        PluginTranslation::upsert(plugin_id.into(), "zh-CN".into(), "插件 A".into(), &db).unwrap();
+
+        let exported = export_profile_toml(profile_id, &db).unwrap().unwrap();
+        assert!(
+            exported.contains(r#"translations.zh-CN = { name = "测试" }"#),
+            "{exported}"
+        );
+        assert!(
+            exported.contains(r#"translations.zh-CN = "插件 A""#),
+            "{exported}"
+        );
+        let parsed = crate::profile::parse_profile_toml(exported.as_bytes()).unwrap();
+        assert_eq!(parsed.translations["zh-CN"].name, "测试");
+        assert_eq!(parsed.plugins[0].translations["zh-CN"], "插件 A");
+
+        let unchanged = export_profile_toml_with_base(profile_id, exported.as_bytes(), &db)
+            .unwrap()
+            .unwrap();
+        assert_eq!(unchanged, exported);
+
+        PluginTranslation::delete(plugin_id.into(), "zh-CN", &db).unwrap();
+        let merged = export_profile_toml_with_base(profile_id, exported.as_bytes(), &db)
+            .unwrap()
+            .unwrap();
+        assert_eq!(merged.matches("translations.zh-CN").count(), 1, "{merged}");
+    }
+
+    #[test]
+    fn test_export_profile_toml_with_invalid_base() {
+        let db = Database::connect_temp().unwrap();
+        let profile_id = Profile::create("test".into(), "en-US".into(), &db)
+            .unwrap()
+            .into();
+        assert_eq!(
+            export_profile_toml_with_base(profile_id, b"version = [", &db).unwrap(),
+            export_profile_toml(profile_id, &db).unwrap(),
+        );
+    }
+
+    #[test]
+    fn test_export_profile_cbor_streamed() {
+        let db = Database::connect_temp().unwrap();
+        let profile_id = Profile::create("test".into(), "en-US".into(), &db)
+            .unwrap()
+            .into();
+        for name in ["a", "b", "c"] {
+            Plugin::create(
+                profile_id,
+                name.into(),
+                "".into(),
+                "null".into(),
+                0,
+                to_cbor(cbor!(null)).into_vec(),
+                &db,
+            )
+            .unwrap();
+        }
+
+        let mut items = vec![];
+        let completed = export_profile_cbor_streamed(profile_id, &db, |buf| {
+            let item: CborValue = cbor4ii::serde::from_slice(buf).unwrap();
+            items.push(item);
+            true
+        })
+        .unwrap();
+        assert!(completed);
+        assert_eq!(items.len(), 4);
+        let item_type = |item: &CborValue| match item {
+            CborValue::Map(kvs) => kvs.iter().find_map(|(k, v)| match (k, v) {
+                (CborValue::Text(k), CborValue::Text(v)) if k == "type" => Some(v.clone()),
+                _ => None,
+            }),
+            _ => None,
+        };
+        assert_eq!(item_type(&items[0]).as_deref(), Some("profile"));
+        assert_eq!(item_type(&items[3]).as_deref(), Some("plugin"));
+    }
+
+    #[test]
+    fn test_export_profile_cbor_streamed_abort() {
+        let db = Database::connect_temp().unwrap();
+        let profile_id = Profile::create("test".into(), "en-US".into(), &db)
+            .unwrap()
+            .into();
+        Plugin::create(
+            profile_id,
+            "a".into(),
+            "".into(),
+            "null".into(),
+            0,
+            to_cbor(cbor!(null)).into_vec(),
+            &db,
+        )
+        .unwrap();
+
+        let mut count = 0;
+        let completed = export_profile_cbor_streamed(profile_id, &db, |_| {
+            count += 1;
+            false
+        })
+        .unwrap();
+        assert!(!completed);
+        assert_eq!(count, 1);
+    }
 }
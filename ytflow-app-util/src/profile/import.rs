@@ -1,4 +1,7 @@
-use std::{collections::BTreeSet, str::FromStr};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    str::FromStr,
+};
 
 use cbor4ii::core::Value as CborValue;
 use chrono::{DateTime, Local, NaiveDateTime};
@@ -31,14 +34,24 @@ pub struct ParsedTomlProfile {
     pub name: Option<String>,
     pub locale: Option<String>,
     pub created_at: Option<NaiveDateTime>,
+    /// Profile names and descriptions in other locales, keyed by locale.
+    pub translations: BTreeMap<String, ParsedTomlProfileTranslation>,
     pub plugins: Vec<ParsedTomlPlugin>,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ParsedTomlProfileTranslation {
+    pub name: String,
+    pub desc: String,
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct ParsedTomlPlugin {
     #[serde(flatten)]
-    plugin: Plugin,
-    is_entry: bool,
+    pub plugin: Plugin,
+    pub is_entry: bool,
+    /// Plugin descriptions in other locales, keyed by locale.
+    pub translations: BTreeMap<String, String>,
 }
 
 fn transform_date_time(date_time: &TomlDatetime) -> Option<NaiveDateTime> {
@@ -51,61 +64,93 @@ fn transform_date_time(date_time: &TomlDatetime) -> Option<NaiveDateTime> {
     }
 }
 
-fn parse_plugin_param(value: &TomlItem) -> Option<ByteBuf> {
+pub(super) fn try_decode_toml_value(value: &TomlValue) -> Option<CborValue> {
     use TomlValue::*;
-    fn try_decode_toml_value(value: &TomlValue) -> Option<CborValue> {
-        Some(match value {
-            String(s) => CborValue::Text(s.clone().into_value()),
-            Integer(i) => CborValue::Integer(i.clone().into_value() as _),
-            Float(f) => CborValue::Float(f.clone().into_value()),
-            Boolean(b) => CborValue::Bool(b.clone().into_value()),
-            Datetime(_) => return None,
-            Array(arr) => CborValue::Array(
-                arr.iter()
-                    .map(try_decode_toml_value)
-                    .collect::<Option<_>>()?,
-            ),
-            InlineTable(t) => {
-                if t.get("__toml_repr").and_then(|v| v.as_str()) == Some("null") {
-                    return Some(CborValue::Null);
-                }
-                CborValue::Map(
-                    t.iter()
-                        .map(|(k, v)| Some((CborValue::Text(k.into()), try_decode_toml_value(v)?)))
-                        .collect::<Option<Vec<_>>>()?,
-                )
+    Some(match value {
+        String(s) => CborValue::Text(s.clone().into_value()),
+        Integer(i) => CborValue::Integer(i.clone().into_value() as _),
+        Float(f) => CborValue::Float(f.clone().into_value()),
+        Boolean(b) => CborValue::Bool(b.clone().into_value()),
+        Datetime(_) => return None,
+        Array(arr) => CborValue::Array(
+            arr.iter()
+                .map(try_decode_toml_value)
+                .collect::<Option<_>>()?,
+        ),
+        InlineTable(t) => {
+            if t.get("__toml_repr").and_then(|v| v.as_str()) == Some("null") {
+                return Some(CborValue::Null);
             }
-        })
-    }
-    fn try_decode_toml_item(item: &TomlItem) -> Option<CborValue> {
-        Some(match item {
-            TomlItem::Value(v) => try_decode_toml_value(v)?,
-            TomlItem::Table(t) => CborValue::Map(
+            CborValue::Map(
                 t.iter()
-                    .map(|(k, v)| Some((CborValue::Text(k.into()), try_decode_toml_item(v)?)))
+                    .map(|(k, v)| Some((CborValue::Text(k.into()), try_decode_toml_value(v)?)))
                     .collect::<Option<Vec<_>>>()?,
-            ),
-            TomlItem::ArrayOfTables(a) => CborValue::Array(
-                a.iter()
-                    .map(|t| {
-                        Some(CborValue::Map(
-                            t.iter()
-                                .map(|(k, v)| {
-                                    Some((CborValue::Text(k.into()), try_decode_toml_item(v)?))
-                                })
-                                .collect::<Option<Vec<_>>>()?,
-                        ))
-                    })
-                    .collect::<Option<Vec<_>>>()?,
-            ),
-            TomlItem::None => return None,
-        })
-    }
+            )
+        }
+    })
+}
+
+/// Decode a TOML item into CBOR, leaving escaped byte strings as they are.
+pub(super) fn try_decode_toml_item(item: &TomlItem) -> Option<CborValue> {
+    Some(match item {
+        TomlItem::Value(v) => try_decode_toml_value(v)?,
+        TomlItem::Table(t) => CborValue::Map(
+            t.iter()
+                .map(|(k, v)| Some((CborValue::Text(k.into()), try_decode_toml_item(v)?)))
+                .collect::<Option<Vec<_>>>()?,
+        ),
+        TomlItem::ArrayOfTables(a) => CborValue::Array(
+            a.iter()
+                .map(|t| {
+                    Some(CborValue::Map(
+                        t.iter()
+                            .map(|(k, v)| {
+                                Some((CborValue::Text(k.into()), try_decode_toml_item(v)?))
+                            })
+                            .collect::<Option<Vec<_>>>()?,
+                    ))
+                })
+                .collect::<Option<Vec<_>>>()?,
+        ),
+        TomlItem::None => return None,
+    })
+}
+
+pub(super) fn parse_plugin_param(value: &TomlItem) -> Option<ByteBuf> {
     let mut value = try_decode_toml_item(value)?;
     unescape_cbor_buf(&mut value).ok()?;
     Some(ByteBuf::from(cbor4ii::serde::to_vec(vec![], &value).ok()?))
 }
 
+/// Extract a plugin description from the comment lines preceding its table.
+pub(super) fn parse_plugin_desc(decor_prefix: &str) -> String {
+    decor_prefix
+        .lines()
+        .filter_map(|l| l.trim_start().strip_prefix('#'))
+        .map(|l| l.trim())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Parse a `translations` table at `path`, where each key is a locale.
+fn parse_translations<T>(
+    item: Option<&TomlItem>,
+    path: &str,
+    mut parse_entry: impl FnMut(&TomlItem, &str) -> ParseTomlProfileResult<T>,
+) -> ParseTomlProfileResult<BTreeMap<String, T>> {
+    let Some(item) = item else {
+        return Ok(BTreeMap::new());
+    };
+    item.as_table_like()
+        .ok_or_else(|| ParseTomlProfileError::InvalidValue(path.into()))?
+        .iter()
+        .map(|(locale, entry)| {
+            let entry_path = format!("{}.{}", path, locale);
+            Ok((locale.to_owned(), parse_entry(entry, &entry_path)?))
+        })
+        .collect()
+}
+
 pub fn parse_profile_toml(toml: &[u8]) -> ParseTomlProfileResult<ParsedTomlProfile> {
     let toml = String::from_utf8_lossy(toml);
     let doc = toml_edit::ImDocument::parse(&*toml)?;
@@ -152,6 +197,33 @@ pub fn parse_profile_toml(toml: &[u8]) -> ParseTomlProfileResult<ParsedTomlProfi
         .map(|v| v.as_str())
         .collect::<Option<BTreeSet<&str>>>()
         .ok_or_else(|| ParseTomlProfileError::InvalidValue("entry_plugins".into()))?;
+    let translations = parse_translations(
+        profile_table.get("translations"),
+        "translations",
+        |entry, path| {
+            let entry = entry
+                .as_table_like()
+                .ok_or_else(|| ParseTomlProfileError::InvalidValue(path.into()))?;
+            let name = entry
+                .get("name")
+                .ok_or_else(|| ParseTomlProfileError::MissingInfo(format!("{}.name", path)))?
+                .as_str()
+                .ok_or_else(|| ParseTomlProfileError::InvalidValue(format!("{}.name", path)))?;
+            let desc = entry
+                .get("desc")
+                .map(|v| {
+                    v.as_str().ok_or_else(|| {
+                        ParseTomlProfileError::InvalidValue(format!("{}.desc", path))
+                    })
+                })
+                .transpose()?
+                .unwrap_or_default();
+            Ok(ParsedTomlProfileTranslation {
+                name: name.to_owned(),
+                desc: desc.to_owned(),
+            })
+        },
+    )?;
 
     let empty_plugin_table = Table::default();
     let plugins = doc
@@ -172,12 +244,8 @@ pub fn parse_profile_toml(toml: &[u8]) -> ParseTomlProfileResult<ParsedTomlProfi
                 .decor()
                 .prefix()
                 .and_then(|p| Some(unsafe { toml.get_unchecked(p.span()?) }))
-                .unwrap_or_default()
-                .lines()
-                .filter_map(|l| l.trim_start().strip_prefix('#'))
-                .map(|l| l.trim())
-                .collect::<Vec<_>>()
-                .join("\n");
+                .map(parse_plugin_desc)
+                .unwrap_or_default();
             let plugin = plugin_table
                 .get("plugin")
                 .ok_or_else(|| {
@@ -212,17 +280,28 @@ pub fn parse_profile_toml(toml: &[u8]) -> ParseTomlProfileResult<ParsedTomlProfi
                 .transpose()?
                 .and_then(transform_date_time)
                 .unwrap_or_else(|| Local::now().naive_local());
+            let translations = parse_translations(
+                plugin_table.get("translations"),
+                &format!("plugins.{}.translations", name),
+                |entry, path| {
+                    entry
+                        .as_str()
+                        .map(Into::into)
+                        .ok_or_else(|| ParseTomlProfileError::InvalidValue(path.into()))
+                },
+            )?;
             Ok(ParsedTomlPlugin {
                 plugin: Plugin {
                     id: Default::default(),
                     name: name.to_owned(),
-                    desc: desc.to_owned(),
+                    desc,
                     plugin: plugin.to_owned(),
                     plugin_version,
                     param,
                     updated_at,
                 },
                 is_entry: entry_plugins.remove(name),
+                translations,
             })
         })
         .collect::<ParseTomlProfileResult<Vec<_>>>()?;
@@ -236,6 +315,7 @@ pub fn parse_profile_toml(toml: &[u8]) -> ParseTomlProfileResult<ParsedTomlProfi
         name: name.map(Into::into),
         locale: locale.map(Into::into),
         created_at,
+        translations,
         plugins,
     })
 }
@@ -514,6 +594,45 @@ updated_at = 2024-04-27T09:43:17.191
         );
     }
 
+    #[test]
+    fn test_parse_profile_toml_translations() {
+        let toml = r#"version = 1
+[profile]
+entry_plugins = []
+translations.zh-CN = { name = "测试", desc = "描述" }
+translations.ja.name = "テスト"
+
+# Null
+[plugins.null]
+plugin = "null"
+plugin_version = 0
+param = { __toml_repr = "null" }
+translations.zh-CN = "空"
+"#;
+        let parsed = parse_profile_toml(toml.as_bytes()).unwrap();
+        assert_eq!(
+            parsed.translations["zh-CN"],
+            ParsedTomlProfileTranslation {
+                name: "测试".into(),
+                desc: "描述".into(),
+            }
+        );
+        assert_eq!(parsed.translations["ja"].name, "テスト");
+        assert_eq!(parsed.translations["ja"].desc, "");
+        assert_eq!(parsed.plugins[0].translations["zh-CN"], "空");
+
+        let toml = r#"version = 1
+[profile]
+entry_plugins = []
+translations.zh-CN = { desc = "描述" }
+"#;
+        let err = parse_profile_toml(toml.as_bytes()).unwrap_err();
+        assert!(
+            matches!(err, ParseTomlProfileError::MissingInfo(ref f) if f == "translations.zh-CN.name"),
+            "{err}"
+        );
+    }
+
     #[test]
     fn test_parse_profile_toml_minimal_profile() {
         let toml = br#"version = 1
@@ -21,3 +21,67 @@ pub struct ProxyLeg {
     pub obfs: Option<obfs::ProxyObfsType>,
     pub tls: Option<tls::ProxyTlsLayer>,
 }
+
+impl Proxy {
+    /// Whether `other` describes the same proxy with only passwords or UUIDs
+    /// changed, as is the case when a provider rotates credentials.
+    pub fn differs_only_in_credentials(&self, other: &Proxy) -> bool {
+        self != other && self.without_credentials() == other.without_credentials()
+    }
+
+    fn without_credentials(&self) -> Proxy {
+        let mut proxy = self.clone();
+        for leg in &mut proxy.legs {
+            match &mut leg.protocol {
+                protocol::ProxyProtocolType::Shadowsocks(p) => p.password.clear(),
+                protocol::ProxyProtocolType::Trojan(p) => p.password.clear(),
+                protocol::ProxyProtocolType::Http(p) => p.password.clear(),
+                protocol::ProxyProtocolType::Socks5(p) => p.password.clear(),
+                protocol::ProxyProtocolType::VMess(p) => p.user_id = uuid::Uuid::nil(),
+            }
+        }
+        proxy
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_bytes::ByteBuf;
+    use ytflow::flow::HostName;
+
+    use super::*;
+    use crate::proxy::protocol::{ProxyProtocolType, TrojanProxy};
+
+    fn trojan(password: &str, port: u16) -> Proxy {
+        Proxy {
+            name: "p".into(),
+            legs: vec![ProxyLeg {
+                protocol: ProxyProtocolType::Trojan(TrojanProxy {
+                    password: ByteBuf::from(password),
+                }),
+                dest: DestinationAddr {
+                    host: HostName::DomainName("a.co".into()),
+                    port,
+                },
+                obfs: None,
+                tls: None,
+            }],
+            udp_supported: false,
+        }
+    }
+
+    #[test]
+    fn test_differs_only_in_credentials() {
+        assert!(trojan("a", 443).differs_only_in_credentials(&trojan("b", 443)));
+    }
+
+    #[test]
+    fn test_differs_only_in_credentials_identical() {
+        assert!(!trojan("a", 443).differs_only_in_credentials(&trojan("a", 443)));
+    }
+
+    #[test]
+    fn test_differs_only_in_credentials_other_fields() {
+        assert!(!trojan("a", 443).differs_only_in_credentials(&trojan("b", 8443)));
+    }
+}
@@ -51,6 +51,10 @@ fn encode_obfs(
             param: to_cbor(cbor!({
                 "host" => &*http.host,
                 "path" => &*http.path,
+                "hosts" => &http.extra_hosts,
+                "paths" => &http.extra_paths,
+                "rotation" => http.rotation,
+                "headers" => &http.headers,
                 "next" => next,
             })),
         },
@@ -70,6 +74,9 @@ fn encode_obfs(
             param: to_cbor(cbor!({
                 "host" => ws.host.as_deref(),
                 "path" => &*ws.path,
+                "hosts" => &ws.extra_hosts,
+                "paths" => &ws.extra_paths,
+                "rotation" => ws.rotation,
                 "headers" => &ws.headers,
                 "next" => next,
             })),
@@ -139,6 +146,7 @@ fn encode_protocol(
             param: to_cbor(cbor!({
                 "user" => &socks5.username,
                 "pass" => &socks5.password,
+                "version" => socks5.version,
                 "tcp_next" => tcp_next,
                 "udp_next" => udp_next,
             })),
@@ -18,9 +18,11 @@ mod tests {
     use super::super::analyze_data_proxy;
     use super::super::compose_data_proxy_v1 as compose_data_proxy;
     use crate::proxy::data::ComposeError;
-    use crate::proxy::obfs::{HttpObfsObfs, ProxyObfsType, TlsObfsObfs, WebSocketObfs};
+    use crate::proxy::obfs::{
+        HttpObfsObfs, ObfsRotation, ProxyObfsType, TlsObfsObfs, WebSocketObfs,
+    };
     use crate::proxy::protocol::{
-        ProxyProtocolType, ShadowsocksProxy, Socks5Proxy, TrojanProxy, VMessProxy,
+        ProxyProtocolType, ShadowsocksProxy, Socks5Proxy, SocksVersion, TrojanProxy, VMessProxy,
     };
     use crate::proxy::{Proxy, ProxyLeg};
 
@@ -174,6 +176,7 @@ mod tests {
                 obfs: Some(ProxyObfsType::HttpObfs(HttpObfsObfs {
                     host: "obfs.example.com".into(),
                     path: "/obfs".into(),
+                    ..Default::default()
                 })),
                 tls: None,
             }],
@@ -211,6 +214,7 @@ mod tests {
                 obfs: Some(ProxyObfsType::HttpObfs(HttpObfsObfs {
                     host: "obfs.example.com".into(),
                     path: "/obfs".into(),
+                    ..Default::default()
                 })),
                 tls: Some(Default::default()),
             }],
@@ -250,6 +254,7 @@ mod tests {
                     obfs: Some(ProxyObfsType::HttpObfs(HttpObfsObfs {
                         host: "obfs.example.com".into(),
                         path: "/obfs".into(),
+                        ..Default::default()
                     })),
                     tls: Some(Default::default()),
                 },
@@ -271,6 +276,7 @@ mod tests {
                     obfs: Some(ProxyObfsType::HttpObfs(HttpObfsObfs {
                         host: "obfs.example.com".into(),
                         path: "/obfs".into(),
+                        ..Default::default()
                     })),
                     tls: None,
                 },
@@ -343,6 +349,10 @@ mod tests {
                     obfs: Some(ProxyObfsType::HttpObfs(HttpObfsObfs {
                         host: "obfs.example.com".into(),
                         path: "/obfs".into(),
+                        extra_hosts: vec!["obfs2.example.com".into()],
+                        rotation: ObfsRotation::Random,
+                        headers: [("X-Header".into(), "value".into())].into_iter().collect(),
+                        ..Default::default()
                     })),
                     tls: None,
                 },
@@ -362,7 +372,9 @@ mod tests {
                     obfs: Some(ProxyObfsType::WebSocket(WebSocketObfs {
                         host: None,
                         path: "/path".into(),
+                        extra_paths: vec!["/path2".into(), "/path3".into()],
                         headers: [("X-Header".into(), "value".into())].into_iter().collect(),
+                        ..Default::default()
                     })),
                     tls: None,
                 },
@@ -370,6 +382,7 @@ mod tests {
                     protocol: ProxyProtocolType::Socks5(Socks5Proxy {
                         username: ByteBuf::from("username"),
                         password: ByteBuf::from("password"),
+                        version: SocksVersion::Socks5,
                     }),
                     dest: dest.clone(),
                     obfs: None,
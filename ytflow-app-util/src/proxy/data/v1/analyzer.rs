@@ -12,9 +12,10 @@ use ytflow::plugin::dyn_outbound::config::v1::{
 use ytflow::plugin::shadowsocks::SupportedCipher;
 
 use crate::proxy::data::{AnalyzeError, AnalyzeResult};
-use crate::proxy::obfs::{HttpObfsObfs, ProxyObfsType, TlsObfsObfs, WebSocketObfs};
+use crate::proxy::obfs::{HttpObfsObfs, ObfsRotation, ProxyObfsType, TlsObfsObfs, WebSocketObfs};
 use crate::proxy::protocol::{
-    HttpProxy, ProxyProtocolType, ShadowsocksProxy, Socks5Proxy, TrojanProxy, VMessProxy,
+    HttpProxy, ProxyProtocolType, ShadowsocksProxy, Socks5Proxy, SocksVersion, TrojanProxy,
+    VMessProxy,
 };
 use crate::proxy::tls::ProxyTlsLayer;
 use crate::proxy::{Proxy, ProxyLeg};
@@ -178,12 +179,15 @@ impl<'p> Analyzer<'p> {
                     udp_next: &'a str,
                     user: ByteBuf,
                     pass: ByteBuf,
+                    #[serde(default)]
+                    version: SocksVersion,
                 }
                 let socks5: Socks5ClientConfig = deserialize_plugin_param(plugin)?;
                 (
                     ProxyProtocolType::Socks5(Socks5Proxy {
                         username: socks5.user,
                         password: socks5.pass,
+                        version: socks5.version,
                     }),
                     socks5.tcp_next,
                     Some(socks5.udp_next),
@@ -304,6 +308,14 @@ impl<'p> Analyzer<'p> {
                 struct HttpObfsClientConfig<'a> {
                     host: String,
                     path: String,
+                    #[serde(default)]
+                    hosts: Vec<String>,
+                    #[serde(default)]
+                    paths: Vec<String>,
+                    #[serde(default)]
+                    rotation: ObfsRotation,
+                    #[serde(default)]
+                    headers: HashMap<String, String>,
                     next: &'a str,
                 }
                 let obfs: HttpObfsClientConfig = deserialize_plugin_param(plugin)?;
@@ -311,6 +323,10 @@ impl<'p> Analyzer<'p> {
                 ProxyObfsType::HttpObfs(HttpObfsObfs {
                     host: obfs.host,
                     path: obfs.path,
+                    extra_hosts: obfs.hosts,
+                    extra_paths: obfs.paths,
+                    rotation: obfs.rotation,
+                    headers: obfs.headers,
                 })
             }
             "tls-obfs-client" => {
@@ -329,6 +345,12 @@ impl<'p> Analyzer<'p> {
                     host: Option<String>,
                     #[serde(default = "default_path")]
                     path: String,
+                    #[serde(default)]
+                    hosts: Vec<String>,
+                    #[serde(default)]
+                    paths: Vec<String>,
+                    #[serde(default)]
+                    rotation: ObfsRotation,
                     headers: HashMap<String, String>,
                     next: &'a str,
                 }
@@ -340,6 +362,9 @@ impl<'p> Analyzer<'p> {
                 ProxyObfsType::WebSocket(WebSocketObfs {
                     host: obfs.host,
                     path: obfs.path,
+                    extra_hosts: obfs.hosts,
+                    extra_paths: obfs.paths,
+                    rotation: obfs.rotation,
                     headers: obfs.headers,
                 })
             }
@@ -8,6 +8,16 @@ pub use http_obfs::HttpObfsObfs;
 pub use tls_obfs::TlsObfsObfs;
 pub use ws::WebSocketObfs;
 
+/// How a Host header or path is picked for each connection when there are
+/// several of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ObfsRotation {
+    #[default]
+    RoundRobin,
+    Random,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ProxyObfsType {
     HttpObfs(http_obfs::HttpObfsObfs),
@@ -1,7 +1,21 @@
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+use super::ObfsRotation;
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub struct HttpObfsObfs {
     pub host: String,
     pub path: String,
+    /// Rotated along with `host`.
+    #[serde(default)]
+    pub extra_hosts: Vec<String>,
+    /// Rotated along with `path`.
+    #[serde(default)]
+    pub extra_paths: Vec<String>,
+    #[serde(default)]
+    pub rotation: ObfsRotation,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
 }
@@ -2,10 +2,20 @@ use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
 
+use super::ObfsRotation;
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct WebSocketObfs {
     pub host: Option<String>,
     pub path: String,
+    /// Rotated along with `host`.
+    #[serde(default)]
+    pub extra_hosts: Vec<String>,
+    /// Rotated along with `path`.
+    #[serde(default)]
+    pub extra_paths: Vec<String>,
+    #[serde(default)]
+    pub rotation: ObfsRotation,
     pub headers: HashMap<String, String>,
 }
 
@@ -14,6 +24,9 @@ impl Default for WebSocketObfs {
         Self {
             host: None,
             path: "/".into(),
+            extra_hosts: vec![],
+            extra_paths: vec![],
+            rotation: ObfsRotation::default(),
             headers: HashMap::new(),
         }
     }
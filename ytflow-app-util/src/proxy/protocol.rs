@@ -8,7 +8,7 @@ mod vmess;
 
 pub use http::HttpProxy;
 pub use shadowsocks::ShadowsocksProxy;
-pub use socks5::Socks5Proxy;
+pub use socks5::{Socks5Proxy, SocksVersion};
 pub use trojan::TrojanProxy;
 pub use vmess::VMessProxy;
 
@@ -36,7 +36,7 @@ impl ProxyProtocolType {
             ProxyProtocolType::Shadowsocks(_) => true,
             ProxyProtocolType::Trojan(_) => true,
             ProxyProtocolType::Http(_) => false,
-            ProxyProtocolType::Socks5(_) => true,
+            ProxyProtocolType::Socks5(socks5) => socks5.version == SocksVersion::Socks5,
             ProxyProtocolType::VMess(_) => true,
         }
     }
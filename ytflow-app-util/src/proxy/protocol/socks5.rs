@@ -1,8 +1,19 @@
 use serde::{Deserialize, Serialize};
 use serde_bytes::ByteBuf;
 
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SocksVersion {
+    #[default]
+    Socks5,
+    Socks4,
+    Socks4a,
+}
+
 #[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Socks5Proxy {
     pub username: ByteBuf,
     pub password: ByteBuf,
+    #[serde(default)]
+    pub version: SocksVersion,
 }
@@ -1,6 +1,7 @@
 mod decode;
 mod encode;
 mod http;
+mod qr;
 pub mod shadowsocks;
 mod socks5;
 mod trojan;
@@ -8,3 +9,6 @@ mod vmess;
 
 pub use decode::{decode_share_link, DecodeError, DecodeResult};
 pub use encode::{encode_share_link, EncodeError, EncodeResult};
+pub use qr::{
+    encode_proxy_group_qr, encode_share_link_qr, render_share_link_qr, ProxyShareQr, QrBitmap,
+};
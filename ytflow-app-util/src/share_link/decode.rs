@@ -52,7 +52,7 @@ pub fn decode_share_link(link: &str) -> Result<Proxy, DecodeError> {
             return Err(DecodeError::UnknownScheme)
         }
         "http" | "https" => HttpProxy::decode_share_link(&url, &mut queries)?,
-        "socks5" => Socks5Proxy::decode_share_link(&url, &mut queries)?,
+        "socks5" | "socks4" | "socks4a" => Socks5Proxy::decode_share_link(&url, &mut queries)?,
         "vmess" => VMessProxy::decode_share_link(&url, &mut queries)?,
         _ => return Err(DecodeError::UnknownScheme),
     };
@@ -14,6 +14,8 @@ pub enum EncodeError {
     InvalidEncoding(&'static str),
     #[error(r#""{0}" cannot be encoded"#)]
     UnsupportedComponent(&'static str),
+    #[error("share link is too long for a QR code")]
+    TooLongForQrCode,
 }
 
 pub type EncodeResult<T> = Result<T, EncodeError>;
@@ -51,7 +53,7 @@ mod tests {
     use ytflow::plugin::vmess::SupportedSecurity;
 
     use crate::proxy::protocol::{
-        HttpProxy, ShadowsocksProxy, Socks5Proxy, TrojanProxy, VMessProxy,
+        HttpProxy, ShadowsocksProxy, Socks5Proxy, SocksVersion, TrojanProxy, VMessProxy,
     };
     use crate::proxy::tls::ProxyTlsLayer;
     use crate::proxy::ProxyLeg;
@@ -170,6 +172,7 @@ mod tests {
                         protocol: ProxyProtocolType::Socks5(Socks5Proxy {
                             username: ByteBuf::from("a/b"),
                             password: ByteBuf::from("p/d"),
+                            version: SocksVersion::Socks5,
                         }),
                         dest: DestinationAddr {
                             host: HostName::DomainName("a.co".into()),
@@ -0,0 +1,137 @@
+use qrcode::{Color, QrCode};
+use serde::Serialize;
+use serde_bytes::ByteBuf;
+
+use ytflow::data::{Proxy as DataProxy, ProxyId};
+
+use super::{encode_share_link, EncodeError, EncodeResult};
+use crate::proxy::data::analyze_data_proxy;
+use crate::proxy::Proxy;
+
+/// Width of the light border around the symbol, in modules, as required by the QR code spec.
+const QUIET_ZONE: usize = 4;
+/// Upper bound of pixels per module. The largest symbol at this scale is already over 5000 pixels
+/// wide; a larger scale would only let callers allocate huge bitmaps.
+const MAX_SCALE: u32 = 32;
+const DARK: u8 = 0;
+const LIGHT: u8 = 255;
+
+/// An 8-bit grayscale bitmap of a QR code. Pixels are stored row by row.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct QrBitmap {
+    pub width: u32,
+    pub luma: ByteBuf,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ProxyShareQr {
+    pub proxy_id: ProxyId,
+    pub name: String,
+    pub link: Option<String>,
+    pub qr: Option<QrBitmap>,
+    pub error: Option<String>,
+}
+
+/// Renders `link` as a square QR code bitmap, where each module spans `scale` x `scale` pixels.
+/// `scale` is clamped to `1..=32`.
+pub fn render_share_link_qr(link: &str, scale: u32) -> EncodeResult<QrBitmap> {
+    let code = QrCode::new(link.as_bytes()).map_err(|_| EncodeError::TooLongForQrCode)?;
+    let scale = scale.clamp(1, MAX_SCALE) as usize;
+    let modules = code.width();
+    let colors = code.to_colors();
+    let width = (modules + QUIET_ZONE * 2) * scale;
+
+    let mut luma = vec![LIGHT; width * width];
+    for (idx, color) in colors.into_iter().enumerate() {
+        if color == Color::Light {
+            continue;
+        }
+        let x0 = (idx % modules + QUIET_ZONE) * scale;
+        let y0 = (idx / modules + QUIET_ZONE) * scale;
+        for row in luma[y0 * width..(y0 + scale) * width].chunks_exact_mut(width) {
+            row[x0..x0 + scale].fill(DARK);
+        }
+    }
+
+    Ok(QrBitmap {
+        width: width as u32,
+        luma: ByteBuf::from(luma),
+    })
+}
+
+pub fn encode_share_link_qr(proxy: &Proxy, scale: u32) -> EncodeResult<QrBitmap> {
+    render_share_link_qr(&encode_share_link(proxy)?, scale)
+}
+
+/// Encodes every proxy in a group into a share link and its QR code. Proxies that cannot be
+/// analyzed or encoded are reported with an error instead of failing the whole group.
+pub fn encode_proxy_group_qr(proxies: &[DataProxy], scale: u32) -> Vec<ProxyShareQr> {
+    proxies
+        .iter()
+        .map(|p| {
+            let res = analyze_data_proxy(p.name.clone(), &p.proxy, p.proxy_version)
+                .map_err(|e| e.to_string())
+                .and_then(|proxy| encode_share_link(&proxy).map_err(|e| e.to_string()))
+                .and_then(|link| {
+                    let qr = render_share_link_qr(&link, scale).map_err(|e| e.to_string())?;
+                    Ok((link, qr))
+                });
+            let (link, qr, error) = match res {
+                Ok((link, qr)) => (Some(link), Some(qr), None),
+                Err(e) => (None, None, Some(e)),
+            };
+            ProxyShareQr {
+                proxy_id: p.id,
+                name: p.name.clone(),
+                link,
+                qr,
+                error,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_share_link_qr() {
+        let qr = render_share_link_qr("trojan://a%2Fb@a.co:10443#c%2Fd", 2).unwrap();
+        let width = qr.width as usize;
+        assert_eq!(qr.luma.len(), width * width);
+        assert_eq!(width % 2, 0);
+        // Quiet zone is light, the top-left finder pattern starts right after it
+        assert!(qr.luma[..QUIET_ZONE * 2 * width]
+            .iter()
+            .all(|&p| p == LIGHT));
+        let finder = (QUIET_ZONE * 2) * width + QUIET_ZONE * 2;
+        assert_eq!(qr.luma[finder], DARK);
+        assert_eq!(qr.luma[finder + 1], DARK);
+        assert_eq!(qr.luma[finder - 1], LIGHT);
+    }
+
+    #[test]
+    fn test_render_share_link_qr_zero_scale() {
+        let qr = render_share_link_qr("ss://", 0).unwrap();
+        assert_eq!(qr.width as usize, 21 + QUIET_ZONE * 2);
+    }
+
+    #[test]
+    fn test_render_share_link_qr_huge_scale() {
+        let qr = render_share_link_qr("ss://", u32::MAX).unwrap();
+        assert_eq!(
+            qr.width as usize,
+            (21 + QUIET_ZONE * 2) * MAX_SCALE as usize
+        );
+    }
+
+    #[test]
+    fn test_render_share_link_qr_too_long() {
+        let link = "a".repeat(8000);
+        assert_eq!(
+            render_share_link_qr(&link, 1),
+            Err(EncodeError::TooLongForQrCode)
+        );
+    }
+}
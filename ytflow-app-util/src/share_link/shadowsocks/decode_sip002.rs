@@ -50,7 +50,11 @@ pub fn decode_shadowsocks_plugin_opts(
                 .filter(|s| !s.is_empty())
                 .unwrap_or("/")
                 .into();
-            ProxyObfsType::HttpObfs(HttpObfsObfs { host, path })
+            ProxyObfsType::HttpObfs(HttpObfsObfs {
+                host,
+                path,
+                ..Default::default()
+            })
         }
         "tls" => ProxyObfsType::TlsObfs(TlsObfsObfs { host }),
         _ => return Err(DecodeError::UnknownValue("obfs")),
@@ -65,16 +69,25 @@ pub fn decode_shadowsocks_plugin_opts(
 }
 
 pub fn decode_sip002(url: &Url, queries: &mut QueryMap) -> DecodeResult<ProxyLeg> {
-    let b64 = {
-        let b64str = percent_decode_str(url.username())
-            .decode_utf8()
-            .map_err(|_| DecodeError::InvalidEncoding)?;
-        BASE64_ENGINE
-            .decode(&*b64str)
-            .map_err(|_| DecodeError::InvalidEncoding)?
+    // AEAD-2022 ciphers put percent-encoded method and password in the userinfo as is.
+    let userinfo: Vec<u8> = match url.password() {
+        Some(password) => {
+            let mut userinfo: Vec<u8> = percent_decode_str(url.username()).collect();
+            userinfo.push(b':');
+            userinfo.extend(percent_decode_str(password));
+            userinfo
+        }
+        None => {
+            let b64str = percent_decode_str(url.username())
+                .decode_utf8()
+                .map_err(|_| DecodeError::InvalidEncoding)?;
+            BASE64_ENGINE
+                .decode(&*b64str)
+                .map_err(|_| DecodeError::InvalidEncoding)?
+        }
     };
     let (cipher, password) = {
-        let mut split = b64.splitn(2, |&b| b == b':');
+        let mut split = userinfo.splitn(2, |&b| b == b':');
         let method = split.next().expect("first split must exist");
         let cipher = parse_supported_cipher(method).ok_or(DecodeError::UnknownValue("method"))?;
         let pass = split.next().ok_or(DecodeError::MissingInfo("password"))?;
@@ -167,6 +180,25 @@ mod tests {
         assert!(queries.is_empty());
     }
     #[test]
+    fn test_decode_sip002_aead_2022() {
+        let url = Url::parse(
+            "ss://2022-blake3-aes-128-gcm:AAAAAAAAAAAAAAAAAAAAAA%3D%3D:AQEBAQEBAQEBAQEBAQEBAQ%3D%3D@a.co:34187",
+        )
+        .unwrap();
+        let mut queries = QueryMap::new();
+        let leg = decode_sip002(&url, &mut queries).unwrap();
+        let ss = match leg.protocol {
+            ProxyProtocolType::Shadowsocks(ss) => ss,
+            p => panic!("unexpected protocol type {:?}", p),
+        };
+        assert_eq!(ss.cipher, SupportedCipher::Blake3Aes128Gcm);
+        assert_eq!(
+            &ss.password,
+            b"AAAAAAAAAAAAAAAAAAAAAA==:AQEBAQEBAQEBAQEBAQEBAQ=="
+        );
+        assert!(queries.is_empty());
+    }
+    #[test]
     fn test_decode_sip002_obfs() {
         let cases = [
             (
@@ -186,6 +218,7 @@ mod tests {
                 ProxyObfsType::HttpObfs(HttpObfsObfs {
                     host: "3.187.225.7".into(),
                     path: "/".into(),
+                    ..Default::default()
                 }),
             ),
             (
@@ -193,6 +226,7 @@ mod tests {
                 ProxyObfsType::HttpObfs(HttpObfsObfs {
                     host: "a.co".into(),
                     path: "/".into(),
+                    ..Default::default()
                 }),
             ),
             (
@@ -200,6 +234,7 @@ mod tests {
                 ProxyObfsType::HttpObfs(HttpObfsObfs {
                     host: "3.187.225.7".into(),
                     path: "/".into(),
+                    ..Default::default()
                 }),
             ),
             (
@@ -207,6 +242,7 @@ mod tests {
                 ProxyObfsType::HttpObfs(HttpObfsObfs {
                     host: "a.co".into(),
                     path: "/bb".into(),
+                    ..Default::default()
                 }),
             ),
             (
@@ -214,6 +250,7 @@ mod tests {
                 ProxyObfsType::HttpObfs(HttpObfsObfs {
                     host: "a.co".into(),
                     path: "/".into(),
+                    ..Default::default()
                 }),
             ),
         ];
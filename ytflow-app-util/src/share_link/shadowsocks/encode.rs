@@ -21,7 +21,14 @@ impl ShadowsocksProxy {
             return Err(EncodeError::UnsupportedComponent("tls"));
         }
         let host = url_encode_host(&leg.dest.host);
-        let username = {
+        let username = if self.cipher.is_aead_2022() {
+            // SIP002 requires AEAD-2022 userinfo to be percent-encoded instead of base64.
+            format!(
+                "{}:{}",
+                self.cipher,
+                percent_encode(&self.password, NON_ALPHANUMERIC),
+            )
+        } else {
             let mut buf = self.cipher.to_string().into_bytes();
             buf.reserve(1 + self.password.len());
             buf.push(b':');
@@ -102,6 +109,35 @@ mod tests {
         );
     }
     #[test]
+    fn test_encode_share_link_aead_2022() {
+        let proxy = Proxy {
+            name: "c/d".into(),
+            legs: vec![ProxyLeg {
+                protocol: ProxyProtocolType::Shadowsocks(ShadowsocksProxy {
+                    cipher: SupportedCipher::Blake3Aes128Gcm,
+                    password: ByteBuf::from(b"AAAAAAAAAAAAAAAAAAAAAA=="),
+                }),
+                dest: DestinationAddr {
+                    host: HostName::DomainName("a.co".into()),
+                    port: 1080,
+                },
+                obfs: None,
+                tls: None,
+            }],
+            udp_supported: true,
+        };
+        let leg = &proxy.legs[0];
+        let ss = match &leg.protocol {
+            ProxyProtocolType::Shadowsocks(p) => p,
+            _ => panic!("unexpected protocol"),
+        };
+        let url = ss.encode_share_link(leg, &proxy).unwrap();
+        assert_eq!(
+            url,
+            "ss://2022-blake3-aes-128-gcm:AAAAAAAAAAAAAAAAAAAAAA%3D%3D@a.co:1080#c%2Fd",
+        );
+    }
+    #[test]
     fn test_encode_share_link_http_obfs() {
         let proxy = Proxy {
             name: "c/d".into(),
@@ -117,6 +153,7 @@ mod tests {
                 obfs: Some(ProxyObfsType::HttpObfs(HttpObfsObfs {
                     host: "obfs.co".into(),
                     path: "/obfs".into(),
+                    ..Default::default()
                 })),
                 tls: None,
             }],
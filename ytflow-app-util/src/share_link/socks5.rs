@@ -8,11 +8,16 @@ use super::decode::{
     extract_name_from_frag, parse_host_transparent, DecodeError, DecodeResult, QueryMap,
 };
 use super::encode::{url_encode_host, EncodeError, EncodeResult};
-use crate::proxy::protocol::{ProxyProtocolType, Socks5Proxy};
+use crate::proxy::protocol::{ProxyProtocolType, Socks5Proxy, SocksVersion};
 use crate::proxy::{Proxy, ProxyLeg};
 
 impl Socks5Proxy {
     pub(super) fn decode_share_link(url: &Url, queries: &mut QueryMap) -> DecodeResult<Proxy> {
+        let version = match url.scheme() {
+            "socks4" => SocksVersion::Socks4,
+            "socks4a" => SocksVersion::Socks4a,
+            _ => SocksVersion::Socks5,
+        };
         let user = percent_decode_str(url.username())
             .decode_utf8()
             .map_err(|_| DecodeError::InvalidEncoding)?
@@ -39,6 +44,7 @@ impl Socks5Proxy {
                 protocol: ProxyProtocolType::Socks5(Socks5Proxy {
                     username: ByteBuf::from(user),
                     password: ByteBuf::from(pass),
+                    version,
                 }),
                 dest,
                 obfs: None,
@@ -58,9 +64,15 @@ impl Socks5Proxy {
         if leg.tls.is_some() {
             return Err(EncodeError::UnsupportedComponent("tls"));
         }
+        let scheme = match self.version {
+            SocksVersion::Socks5 => "socks5",
+            SocksVersion::Socks4 => "socks4",
+            SocksVersion::Socks4a => "socks4a",
+        };
         let host = url_encode_host(&leg.dest.host);
         let mut url = Url::parse(&format!(
-            "socks5://{}:{}#{}",
+            "{}://{}:{}#{}",
+            scheme,
             host,
             leg.dest.port,
             percent_encode(proxy.name.as_bytes(), NON_ALPHANUMERIC),
@@ -71,7 +83,8 @@ impl Socks5Proxy {
             url.set_username(&percent_encode(&self.username, NON_ALPHANUMERIC).to_string())
                 .expect("cannot set username");
         }
-        if !self.password.is_empty() {
+        // SOCKS4 only carries a user ID
+        if !self.password.is_empty() && self.version == SocksVersion::Socks5 {
             url.set_password(Some(
                 &percent_encode(&self.password, NON_ALPHANUMERIC).to_string(),
             ))
@@ -102,6 +115,7 @@ mod tests {
                     protocol: ProxyProtocolType::Socks5(Socks5Proxy {
                         username: ByteBuf::from(b"a/b"),
                         password: ByteBuf::from(b"p/d"),
+                        version: SocksVersion::Socks5,
                     }),
                     dest: DestinationAddr {
                         host: HostName::from_domain_name("a.co".into()).unwrap(),
@@ -115,6 +129,25 @@ mod tests {
         );
     }
     #[test]
+    fn test_decode_share_link_socks4() {
+        for (raw_url, version) in [
+            ("socks4://u@1.2.3.4:1080", SocksVersion::Socks4),
+            ("socks4a://u@a.co:1080", SocksVersion::Socks4a),
+        ] {
+            let url = Url::parse(raw_url).unwrap();
+            let proxy = Socks5Proxy::decode_share_link(&url, &mut QueryMap::new()).unwrap();
+            assert_eq!(
+                proxy.legs[0].protocol,
+                ProxyProtocolType::Socks5(Socks5Proxy {
+                    username: ByteBuf::from(b"u"),
+                    password: ByteBuf::new(),
+                    version,
+                }),
+                "{raw_url}"
+            );
+        }
+    }
+    #[test]
     fn test_decode_share_link_names() {
         let cases = [
             "socks5://a.co:1080?remarks=name.com:2333#name.frag",
@@ -152,6 +185,7 @@ mod tests {
                 protocol: ProxyProtocolType::Socks5(Socks5Proxy {
                     username: ByteBuf::from("a/b"),
                     password: ByteBuf::from("p/d"),
+                    version: SocksVersion::Socks5,
                 }),
                 dest: DestinationAddr {
                     host: HostName::DomainName("a.co".into()),
@@ -173,6 +207,35 @@ mod tests {
         );
     }
     #[test]
+    fn test_encode_share_link_socks4a() {
+        let proxy = Proxy {
+            name: "c/d".into(),
+            legs: vec![ProxyLeg {
+                protocol: ProxyProtocolType::Socks5(Socks5Proxy {
+                    username: ByteBuf::from("a/b"),
+                    password: ByteBuf::from("p/d"),
+                    version: SocksVersion::Socks4a,
+                }),
+                dest: DestinationAddr {
+                    host: HostName::DomainName("a.co".into()),
+                    port: 1080,
+                },
+                obfs: None,
+                tls: None,
+            }],
+            udp_supported: false,
+        };
+        let leg = &proxy.legs[0];
+        let socks5 = match &leg.protocol {
+            ProxyProtocolType::Socks5(p) => p,
+            _ => panic!("unexpected protocol"),
+        };
+        assert_eq!(
+            socks5.encode_share_link(leg, &proxy).unwrap(),
+            "socks4a://a%2Fb@a.co:1080#c%2Fd"
+        );
+    }
+    #[test]
     fn test_encode_share_link_too_many_legs() {
         let proxy = Proxy {
             name: "c/d".into(),
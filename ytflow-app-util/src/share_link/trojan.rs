@@ -8,6 +8,7 @@ use super::decode::{
     extract_name_from_frag, parse_host_transparent, DecodeError, DecodeResult, QueryMap,
 };
 use super::encode::{url_encode_host, EncodeError, EncodeResult};
+use crate::proxy::obfs::{ProxyObfsType, WebSocketObfs};
 use crate::proxy::protocol::{ProxyProtocolType, TrojanProxy};
 use crate::proxy::tls::ProxyTlsLayer;
 use crate::proxy::{Proxy, ProxyLeg};
@@ -33,11 +34,28 @@ impl TrojanProxy {
             .remove("alpn")
             .map(|s| s.split(',').map(|a| a.to_owned()).collect())
             .unwrap_or_default();
+        let obfs = match &*queries.remove("type").unwrap_or_default() {
+            "" | "tcp" => None,
+            "ws" => Some(ProxyObfsType::WebSocket(WebSocketObfs {
+                // The Host header may differ from both the destination and SNI for domain fronting
+                host: queries
+                    .remove("host")
+                    .filter(|s| !s.is_empty())
+                    .map(|s| s.into_owned()),
+                path: queries
+                    .remove("path")
+                    .filter(|s| !s.is_empty())
+                    .map(|s| s.into_owned())
+                    .unwrap_or_else(|| "/".into()),
+                ..Default::default()
+            })),
+            _ => return Err(DecodeError::UnknownValue("type")),
+        };
 
         let leg = ProxyLeg {
             protocol: ProxyProtocolType::Trojan(TrojanProxy { password }),
             dest: DestinationAddr { host, port },
-            obfs: None,
+            obfs,
             tls: Some(ProxyTlsLayer {
                 alpn,
                 sni,
@@ -56,9 +74,11 @@ impl TrojanProxy {
         if proxy.legs.len() != 1 {
             return Err(EncodeError::TooManyLegs);
         }
-        if leg.obfs.is_some() {
-            return Err(EncodeError::UnsupportedComponent("obfs"));
-        }
+        let ws = match &leg.obfs {
+            None => None,
+            Some(ProxyObfsType::WebSocket(ws)) if ws.headers.is_empty() => Some(ws),
+            Some(_) => return Err(EncodeError::UnsupportedComponent("obfs")),
+        };
         let Some(tls) = &leg.tls else {
             return Err(EncodeError::UnsupportedComponent("tls"));
         };
@@ -83,6 +103,13 @@ impl TrojanProxy {
         if !alpn.is_empty() {
             query.append_pair("alpn", &alpn);
         }
+        if let Some(ws) = ws {
+            query.append_pair("type", "ws");
+            if let Some(host) = ws.host.as_ref().filter(|s| !s.is_empty()) {
+                query.append_pair("host", host);
+            }
+            query.append_pair("path", &ws.path);
+        }
         drop(query);
         if url.query() == Some("") {
             url.set_query(None);
@@ -97,7 +124,7 @@ mod tests {
     use ytflow::flow::HostName;
 
     use super::*;
-    use crate::proxy::obfs::ProxyObfsType;
+    use crate::proxy::obfs::{HttpObfsObfs, ProxyObfsType};
     use crate::proxy::tls::ProxyTlsLayer;
 
     #[test]
@@ -287,7 +314,11 @@ mod tests {
                     host: HostName::DomainName("a.co".into()),
                     port: 1080,
                 },
-                obfs: Some(ProxyObfsType::WebSocket(Default::default())),
+                obfs: Some(ProxyObfsType::HttpObfs(HttpObfsObfs {
+                    host: "b.co".into(),
+                    path: "/".into(),
+                    ..Default::default()
+                })),
                 tls: Some(Default::default()),
             }],
             udp_supported: false,
@@ -300,4 +331,67 @@ mod tests {
         let res = trojan.encode_share_link(leg, &proxy);
         assert_eq!(res.unwrap_err(), EncodeError::UnsupportedComponent("obfs"));
     }
+    #[test]
+    fn test_decode_share_link_ws_fronting() {
+        let url = Url::parse(
+            "trojan://pass@front.co:443?sni=front.co&type=ws&host=real.co&path=%2Fws#fronted",
+        )
+        .unwrap();
+        let mut queries = url.query_pairs().collect::<QueryMap>();
+        let proxy = TrojanProxy::decode_share_link(&url, &mut queries).unwrap();
+        let leg = &proxy.legs[0];
+        assert_eq!(
+            leg.obfs,
+            Some(ProxyObfsType::WebSocket(WebSocketObfs {
+                host: Some("real.co".into()),
+                path: "/ws".into(),
+                ..Default::default()
+            }))
+        );
+        assert_eq!(leg.tls.as_ref().unwrap().sni.as_deref(), Some("front.co"));
+        assert!(queries.is_empty());
+    }
+    #[test]
+    fn test_decode_share_link_unknown_type() {
+        let url = Url::parse("trojan://pass@a.co:443?type=grpc").unwrap();
+        let mut queries = url.query_pairs().collect::<QueryMap>();
+        let proxy = TrojanProxy::decode_share_link(&url, &mut queries);
+        assert_eq!(proxy.unwrap_err(), DecodeError::UnknownValue("type"));
+    }
+    #[test]
+    fn test_encode_share_link_ws_fronting() {
+        let proxy = Proxy {
+            name: "fronted".into(),
+            legs: vec![ProxyLeg {
+                protocol: ProxyProtocolType::Trojan(TrojanProxy {
+                    password: ByteBuf::from("pass"),
+                }),
+                dest: DestinationAddr {
+                    host: HostName::DomainName("front.co".into()),
+                    port: 443,
+                },
+                obfs: Some(ProxyObfsType::WebSocket(WebSocketObfs {
+                    host: Some("real.co".into()),
+                    path: "/ws".into(),
+                    ..Default::default()
+                })),
+                tls: Some(ProxyTlsLayer {
+                    alpn: vec![],
+                    sni: Some("front.co".into()),
+                    skip_cert_check: None,
+                }),
+            }],
+            udp_supported: false,
+        };
+        let leg = &proxy.legs[0];
+        let trojan = match &leg.protocol {
+            ProxyProtocolType::Trojan(p) => p,
+            _ => panic!("unexpected protocol"),
+        };
+        let url = trojan.encode_share_link(leg, &proxy).unwrap();
+        assert_eq!(
+            url,
+            "trojan://pass@front.co:443?sni=front.co&type=ws&host=real.co&path=%2Fws#fronted",
+        );
+    }
 }
@@ -106,7 +106,7 @@ pub(crate) fn decode_v2rayn(url: &Url, queries: &mut QueryMap) -> DecodeResult<P
         "ws" => {
             is_ws = true;
             Some(ProxyObfsType::WebSocket(WebSocketObfs {
-                host: obfs_host,
+                host: obfs_host.filter(|s| !s.is_empty()),
                 path: obfs_path
                     .filter(|s| !s.is_empty())
                     .unwrap_or_else(|| "/".into()),
@@ -1,5 +1,6 @@
 mod b64_links;
 mod decode;
+mod metadata;
 mod sip008;
 mod surge_proxy_list;
 mod userinfo;
@@ -7,6 +8,7 @@ mod userinfo;
 use std::ffi::CStr;
 
 pub use decode::{decode_subscription, decode_subscription_with_format, DecodeError, DecodeResult};
+pub use metadata::SubscriptionMetadata;
 use serde::Serialize;
 pub use userinfo::SubscriptionUserInfo;
 
@@ -0,0 +1,254 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use percent_encoding::percent_decode_str;
+use serde::{Deserialize, Serialize};
+use ytflow::data::{Connection as DbConnection, DataResult, ProxySubscription};
+
+use super::SubscriptionUserInfo;
+
+/// Metadata sent along with a subscription by providers following Clash
+/// conventions.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubscriptionMetadata {
+    /// `None` if the provider did not send `subscription-userinfo`, in which
+    /// case the usage stored previously is kept.
+    pub userinfo: Option<SubscriptionUserInfo>,
+    pub update_interval_hours: Option<u32>,
+    pub web_page_url: Option<String>,
+    pub title: Option<String>,
+}
+
+/// Extra fields of [`SubscriptionMetadata`] stored as JSON in the
+/// `additional_info` of a `ProxySubscription`.
+#[derive(Default, Serialize, Deserialize)]
+struct AdditionalInfo {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    web_page_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+}
+
+fn decode_title(value: &str) -> Option<String> {
+    match value.strip_prefix("base64:") {
+        Some(b64) => String::from_utf8(STANDARD.decode(b64.trim()).ok()?).ok(),
+        None => Some(value.to_string()),
+    }
+}
+
+/// Extract the file name from a `Content-Disposition` header, preferring the
+/// RFC 5987 `filename*` form.
+fn decode_content_disposition(value: &str) -> Option<String> {
+    let mut filename = None;
+    for param in value.split(';').skip(1) {
+        let Some((key, value)) = param.split_once('=') else {
+            continue;
+        };
+        match key.trim().to_ascii_lowercase().as_str() {
+            "filename*" => {
+                let value = value.trim();
+                let encoded = value.splitn(3, '\'').nth(2).unwrap_or(value);
+                if let Ok(decoded) = percent_decode_str(encoded).decode_utf8() {
+                    return Some(decoded.into_owned());
+                }
+            }
+            "filename" => {
+                filename = Some(value.trim().trim_matches('"').to_string());
+            }
+            _ => {}
+        }
+    }
+    filename
+}
+
+impl SubscriptionMetadata {
+    /// Collect metadata from HTTP response headers of a subscription.
+    pub fn decode_headers<'a>(headers: impl IntoIterator<Item = (&'a str, &'a str)>) -> Self {
+        let mut ret = Self::default();
+        let mut file_name = None;
+        for (name, value) in headers {
+            let value = value.trim();
+            match name.trim().to_ascii_lowercase().as_str() {
+                "subscription-userinfo" => {
+                    ret.userinfo = Some(SubscriptionUserInfo::decode_header(value));
+                }
+                "profile-update-interval" => {
+                    ret.update_interval_hours = value.parse().ok().filter(|h| *h > 0);
+                }
+                "profile-web-page-url" => {
+                    ret.web_page_url = Some(value.to_string()).filter(|u| !u.is_empty());
+                }
+                "profile-title" => {
+                    ret.title = decode_title(value).filter(|t| !t.is_empty());
+                }
+                "content-disposition" => {
+                    file_name = decode_content_disposition(value).filter(|f| !f.is_empty());
+                }
+                _ => {}
+            }
+        }
+        if ret.title.is_none() {
+            ret.title = file_name;
+        }
+        ret
+    }
+
+    /// Value for the `update_frequency` column of a `ProxySubscription`.
+    pub fn update_frequency(&self) -> Option<String> {
+        self.update_interval_hours.map(|h| format!("{}h", h))
+    }
+
+    /// Value for the `additional_info` column of a `ProxySubscription`.
+    pub fn additional_info(&self) -> Option<String> {
+        self.merge_additional_info(None)
+    }
+
+    /// Overlay the fields present in this metadata onto `existing`, the
+    /// current value of the `additional_info` column.
+    fn merge_additional_info(&self, existing: Option<&str>) -> Option<String> {
+        let mut info: AdditionalInfo = existing
+            .and_then(|s| serde_json::from_str(s).ok())
+            .unwrap_or_default();
+        if let Some(url) = &self.web_page_url {
+            info.web_page_url = Some(url.clone());
+        }
+        if let Some(title) = &self.title {
+            info.title = Some(title.clone());
+        }
+        if info.web_page_url.is_none() && info.title.is_none() {
+            return existing.map(|s| s.to_string());
+        }
+        serde_json::to_string(&info).ok()
+    }
+
+    /// Store the metadata into the subscription of a Proxy Group. Columns
+    /// whose headers were absent keep their current values.
+    pub fn save(&self, proxy_group_id: u32, conn: &DbConnection) -> DataResult<()> {
+        let current = ProxySubscription::query_by_proxy_group_id(proxy_group_id, conn)?;
+        let (upload_bytes_used, download_bytes_used, bytes_total, expires_at) = match &self.userinfo
+        {
+            Some(userinfo) => (
+                userinfo.upload_bytes_used,
+                userinfo.download_bytes_used,
+                userinfo.bytes_total,
+                userinfo
+                    .expires_at
+                    .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string()),
+            ),
+            None => (
+                current.upload_bytes_used,
+                current.download_bytes_used,
+                current.bytes_total,
+                current.expires_at,
+            ),
+        };
+        ProxySubscription::update_retrieved_by_proxy_group_id(
+            proxy_group_id,
+            upload_bytes_used,
+            download_bytes_used,
+            bytes_total,
+            expires_at,
+            conn,
+        )?;
+        ProxySubscription::update_metadata_by_proxy_group_id(
+            proxy_group_id,
+            self.update_frequency().or(current.update_frequency),
+            self.merge_additional_info(current.additional_info.as_deref()),
+            conn,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ytflow::data::{Database, ProxyGroup};
+
+    use super::*;
+
+    #[test]
+    fn test_decode_headers() {
+        let meta = SubscriptionMetadata::decode_headers([
+            ("Content-Type", "text/plain"),
+            (
+                "Subscription-Userinfo",
+                "upload=1; download=2; total=3; expire=",
+            ),
+            ("Profile-Update-Interval", "24"),
+            ("Profile-Web-Page-Url", "https://example.com/user"),
+            ("Profile-Title", "base64:5rWL6K+V"),
+        ]);
+        assert_eq!(
+            meta,
+            SubscriptionMetadata {
+                userinfo: Some(SubscriptionUserInfo {
+                    upload_bytes_used: Some(1),
+                    download_bytes_used: Some(2),
+                    bytes_total: Some(3),
+                    expires_at: None,
+                }),
+                update_interval_hours: Some(24),
+                web_page_url: Some("https://example.com/user".into()),
+                title: Some("测试".into()),
+            }
+        );
+        assert_eq!(meta.update_frequency().as_deref(), Some("24h"));
+        assert_eq!(
+            meta.additional_info().as_deref(),
+            Some(r#"{"web_page_url":"https://example.com/user","title":"测试"}"#)
+        );
+    }
+
+    #[test]
+    fn test_decode_headers_content_disposition() {
+        let meta = SubscriptionMetadata::decode_headers([(
+            "content-disposition",
+            r#"attachment; filename="fallback.yaml"; filename*=UTF-8''%E6%B5%8B%E8%AF%95"#,
+        )]);
+        assert_eq!(meta.title.as_deref(), Some("测试"));
+        let meta = SubscriptionMetadata::decode_headers([
+            ("content-disposition", r#"attachment; filename="sub.yaml""#),
+            ("profile-title", "Provider"),
+        ]);
+        assert_eq!(meta.title.as_deref(), Some("Provider"));
+    }
+
+    #[test]
+    fn test_decode_headers_empty() {
+        let meta = SubscriptionMetadata::decode_headers([("profile-update-interval", "0")]);
+        assert_eq!(meta, SubscriptionMetadata::default());
+        assert_eq!(meta.additional_info(), None);
+    }
+
+    #[test]
+    fn test_save_keeps_absent_columns() {
+        let mut conn = Database::connect_temp().unwrap();
+        let proxy_group_id = ProxyGroup::create_subscription(
+            "sub".into(),
+            "sip008".into(),
+            "https://example.com/sub".into(),
+            &mut conn,
+        )
+        .unwrap();
+        SubscriptionMetadata::decode_headers([
+            ("subscription-userinfo", "upload=1; download=2; total=3"),
+            ("profile-update-interval", "24"),
+            ("profile-web-page-url", "https://example.com/user"),
+            ("profile-title", "Provider"),
+        ])
+        .save(proxy_group_id, &conn)
+        .unwrap();
+
+        SubscriptionMetadata::decode_headers([("profile-title", "Renamed")])
+            .save(proxy_group_id, &conn)
+            .unwrap();
+
+        let sub = ProxySubscription::query_by_proxy_group_id(proxy_group_id, &conn).unwrap();
+        assert_eq!(sub.upload_bytes_used, Some(1));
+        assert_eq!(sub.download_bytes_used, Some(2));
+        assert_eq!(sub.bytes_total, Some(3));
+        assert_eq!(sub.update_frequency.as_deref(), Some("24h"));
+        assert_eq!(
+            sub.additional_info.as_deref(),
+            Some(r#"{"web_page_url":"https://example.com/user","title":"Renamed"}"#)
+        );
+    }
+}
@@ -9,7 +9,8 @@ use ytflow::plugin::vmess::SupportedSecurity;
 use super::decode::DecodeResult;
 use crate::proxy::obfs::{HttpObfsObfs, ProxyObfsType, TlsObfsObfs, WebSocketObfs};
 use crate::proxy::protocol::{
-    HttpProxy, ProxyProtocolType, ShadowsocksProxy, Socks5Proxy, TrojanProxy, VMessProxy,
+    HttpProxy, ProxyProtocolType, ShadowsocksProxy, Socks5Proxy, SocksVersion, TrojanProxy,
+    VMessProxy,
 };
 use crate::proxy::tls::ProxyTlsLayer;
 use crate::proxy::{Proxy, ProxyLeg};
@@ -99,11 +100,13 @@ fn decode_surge_proxy_line(line: &str, parents: &mut BTreeMap<String, String>) -
             host: None,
             path: ws_path.into(),
             headers: ws_headers.collect(),
+            ..Default::default()
         }))
     } else if obfs == Some("http") {
         Some(ProxyObfsType::HttpObfs(HttpObfsObfs {
             host: obfs_host.into(),
             path: obfs_uri.into(),
+            ..Default::default()
         }))
     } else if obfs == Some("tls") {
         Some(ProxyObfsType::TlsObfs(TlsObfsObfs {
@@ -124,7 +127,11 @@ fn decode_surge_proxy_line(line: &str, parents: &mut BTreeMap<String, String>) -
             kv_args.remove("always-use-connect");
             ProxyProtocolType::Http(HttpProxy { username, password })
         }
-        "socks5" | "socks5-tls" => ProxyProtocolType::Socks5(Socks5Proxy { username, password }),
+        "socks5" | "socks5-tls" => ProxyProtocolType::Socks5(Socks5Proxy {
+            username,
+            password,
+            version: SocksVersion::Socks5,
+        }),
         "ss" => {
             udp_supported = udp_relay == "true";
             ProxyProtocolType::Shadowsocks(ShadowsocksProxy {
@@ -274,6 +281,7 @@ mod tests {
                     headers: [("H1".into(), "V1".into()), ("H2".into(), "V2".into())]
                         .into_iter()
                         .collect(),
+                    ..Default::default()
                 }),
             ),
             (
@@ -281,6 +289,7 @@ mod tests {
                 ProxyObfsType::HttpObfs(HttpObfsObfs {
                     host: "b.com".into(),
                     path: "/path".into(),
+                    ..Default::default()
                 }),
             ),
             (
@@ -288,6 +297,7 @@ mod tests {
                 ProxyObfsType::HttpObfs(HttpObfsObfs {
                     host: "a.com".into(),
                     path: "/".into(),
+                    ..Default::default()
                 }),
             ),
             (
@@ -332,6 +342,7 @@ mod tests {
                 ProxyProtocolType::Socks5(Socks5Proxy {
                     username: ByteBuf::from("user"),
                     password: ByteBuf::from("pass"),
+                    version: SocksVersion::Socks5,
                 }),
             ),
             (
@@ -339,6 +350,7 @@ mod tests {
                 ProxyProtocolType::Socks5(Socks5Proxy {
                     username: ByteBuf::default(),
                     password: ByteBuf::default(),
+                    version: SocksVersion::Socks5,
                 }),
             ),
             (
@@ -0,0 +1,90 @@
+//! Keeps profiles and proxy groups consistent across devices by pushing and pulling snapshots
+//! to a WebDAV or S3-compatible endpoint. Each snapshot carries a version vector, so that changes
+//! made on different devices since the last sync are detected as conflicts instead of silently
+//! overwriting each other.
+
+mod backend;
+mod engine;
+mod s3;
+mod vector;
+mod webdav;
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use ytflow::data::DataError;
+
+pub use backend::{Precondition, RemoteObject, SyncBackend};
+pub use engine::{apply_pulled_profile, resolve_conflict, sync_item, SyncItem, SyncOutcome};
+pub use s3::S3Backend;
+pub use vector::{Causality, VersionVector};
+pub use webdav::WebDavBackend;
+
+#[derive(Debug, Error)]
+pub enum SyncError {
+    #[error("invalid sync endpoint")]
+    InvalidEndpoint,
+    #[error("cannot reach the sync endpoint: {0}")]
+    Transport(String),
+    #[error("unexpected HTTP status {0}")]
+    UnexpectedStatus(u16),
+    #[error("the remote snapshot was changed by another device during sync")]
+    RemoteChanged,
+    #[error("invalid remote snapshot")]
+    InvalidSnapshot,
+    #[error(transparent)]
+    Data(#[from] DataError),
+}
+
+pub type SyncResult<T> = Result<T, SyncError>;
+
+/// Where snapshots are stored, as configured by the user.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SyncEndpoint {
+    WebDav {
+        url: String,
+        username: Option<String>,
+        password: Option<String>,
+    },
+    S3 {
+        endpoint: String,
+        region: String,
+        bucket: String,
+        #[serde(default)]
+        prefix: String,
+        access_key_id: String,
+        secret_access_key: String,
+    },
+}
+
+impl SyncEndpoint {
+    pub fn connect(self) -> SyncResult<Box<dyn SyncBackend>> {
+        Ok(match self {
+            SyncEndpoint::WebDav {
+                url,
+                username,
+                password,
+            } => Box::new(WebDavBackend::new(
+                &url,
+                username.as_deref(),
+                password.as_deref(),
+            )?),
+            SyncEndpoint::S3 {
+                endpoint,
+                region,
+                bucket,
+                prefix,
+                access_key_id,
+                secret_access_key,
+            } => Box::new(S3Backend::new(
+                &endpoint,
+                region,
+                &bucket,
+                &prefix,
+                access_key_id,
+                secret_access_key,
+            )?),
+        })
+    }
+}
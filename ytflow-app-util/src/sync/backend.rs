@@ -0,0 +1,73 @@
+use std::io::Read;
+use std::time::Duration;
+
+use super::{SyncError, SyncResult};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Snapshots are small; anything larger is not something we have written.
+const MAX_OBJECT_SIZE: u64 = 16 * 1024 * 1024;
+
+pub struct RemoteObject {
+    pub body: Vec<u8>,
+    pub etag: Option<String>,
+}
+
+/// Condition under which a write to the remote endpoint should take place, so that a snapshot
+/// written by another device between our read and write is never overwritten.
+#[derive(Debug, Clone, Copy)]
+pub enum Precondition<'a> {
+    /// Only create the object if it does not exist yet.
+    Absent,
+    /// Only replace the object if its ETag still matches.
+    Matches(&'a str),
+    /// Write unconditionally, for endpoints that do not report ETags.
+    Any,
+}
+
+pub trait SyncBackend {
+    fn get(&self, key: &str) -> SyncResult<Option<RemoteObject>>;
+    fn put(&self, key: &str, body: &[u8], precondition: Precondition<'_>) -> SyncResult<()>;
+}
+
+pub(super) fn new_agent() -> ureq::Agent {
+    ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build()
+}
+
+pub(super) fn apply_precondition(
+    req: ureq::Request,
+    precondition: Precondition<'_>,
+) -> ureq::Request {
+    match precondition {
+        Precondition::Absent => req.set("If-None-Match", "*"),
+        Precondition::Matches(etag) => req.set("If-Match", etag),
+        Precondition::Any => req,
+    }
+}
+
+/// Turn the response of a GET request into an object. A missing object is not an error.
+pub(super) fn read_object(
+    res: Result<ureq::Response, ureq::Error>,
+) -> SyncResult<Option<RemoteObject>> {
+    let res = match res {
+        Ok(res) => res,
+        Err(ureq::Error::Status(404, _)) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let etag = res.header("ETag").map(str::to_string);
+    let mut body = vec![];
+    res.into_reader()
+        .take(MAX_OBJECT_SIZE)
+        .read_to_end(&mut body)
+        .map_err(|e| SyncError::Transport(e.to_string()))?;
+    Ok(Some(RemoteObject { body, etag }))
+}
+
+impl From<ureq::Error> for SyncError {
+    fn from(e: ureq::Error) -> Self {
+        match e {
+            ureq::Error::Status(412, _) => SyncError::RemoteChanged,
+            ureq::Error::Status(code, _) => SyncError::UnexpectedStatus(code),
+            ureq::Error::Transport(t) => SyncError::Transport(t.to_string()),
+        }
+    }
+}
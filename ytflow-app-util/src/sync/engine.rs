@@ -0,0 +1,625 @@
+use rusqlite::Error as SqError;
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use sha2::{Digest, Sha256};
+
+use ytflow::data::{
+    Connection as DbConnection, DataError, Plugin, PluginId, PluginTranslation, Profile,
+    ProfileTranslation, Proxy, ProxyGroup, ProxyInput, SyncState,
+};
+
+use super::backend::{Precondition, SyncBackend};
+use super::vector::{Causality, VersionVector};
+use super::{SyncError, SyncResult};
+use crate::profile::{export_profile_toml, parse_profile_toml, ParsedTomlProfile};
+
+const SNAPSHOT_VERSION: u8 = 1;
+
+/// A local item to be synced.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncItem {
+    Profile(u32),
+    ProxyGroup(u32),
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum SyncOutcome {
+    UpToDate,
+    Pushed,
+    /// The remote snapshot is newer than the local item. Proxy groups are updated in place.
+    /// Profiles are returned as TOML for the app to import with [`apply_pulled_profile`]; until
+    /// then, the pull is not recorded and is offered again on the next sync.
+    Pulled {
+        content: ByteBuf,
+        applied: bool,
+    },
+    /// Both sides have changed since the last sync. Resolve with [`resolve_conflict`].
+    Conflict {
+        remote_device_id: String,
+        remote_content: ByteBuf,
+    },
+}
+
+/// The object stored on the remote endpoint for each item.
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    version: u8,
+    /// The device that wrote this snapshot.
+    device_id: String,
+    vector: VersionVector,
+    content: ByteBuf,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ProxyGroupContent {
+    name: String,
+    r#type: String,
+    proxies: Vec<SyncedProxy>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SyncedProxy {
+    name: String,
+    proxy: ByteBuf,
+    proxy_version: u16,
+}
+
+struct LocalItem {
+    item: SyncItem,
+    key: String,
+    content: Vec<u8>,
+    hash: [u8; 32],
+    /// The version vector and content hash at the last sync.
+    state: Option<(VersionVector, Option<ByteBuf>)>,
+}
+
+fn content_hash(content: &[u8]) -> [u8; 32] {
+    Sha256::digest(content).into()
+}
+
+fn encode_cbor<T: Serialize>(value: &T) -> Vec<u8> {
+    cbor4ii::serde::to_vec(vec![], value).expect("Could not serialize sync content")
+}
+
+fn not_found() -> DataError {
+    SqError::QueryReturnedNoRows.into()
+}
+
+fn read_content(item: SyncItem, conn: &DbConnection) -> SyncResult<(String, Vec<u8>)> {
+    Ok(match item {
+        SyncItem::Profile(id) => {
+            let profile = Profile::query_by_id(id as _, conn)?.ok_or_else(not_found)?;
+            let toml = export_profile_toml(id.into(), conn)?.unwrap_or_default();
+            (
+                format!("profiles/{}.cbor", hex::encode(profile.permanent_id)),
+                toml.into_bytes(),
+            )
+        }
+        SyncItem::ProxyGroup(id) => {
+            let group = ProxyGroup::query_by_id(id as _, conn)?.ok_or_else(not_found)?;
+            let proxies = Proxy::query_all_by_group(id.into(), conn)?
+                .into_iter()
+                .map(|p| SyncedProxy {
+                    name: p.name,
+                    proxy: p.proxy,
+                    proxy_version: p.proxy_version,
+                })
+                .collect();
+            // Proxy groups have no permanent ID, so they are matched across devices by name.
+            (
+                format!("proxy-groups/{}.cbor", hex::encode(&group.name)),
+                encode_cbor(&ProxyGroupContent {
+                    name: group.name,
+                    r#type: group.r#type,
+                    proxies,
+                }),
+            )
+        }
+    })
+}
+
+impl LocalItem {
+    fn load(item: SyncItem, conn: &DbConnection) -> SyncResult<Self> {
+        let (key, content) = read_content(item, conn)?;
+        let state = SyncState::query_by_item_key(&key, conn)?
+            .and_then(|s| Some((VersionVector::decode(&s.version_vector)?, s.content_hash)));
+        Ok(Self {
+            item,
+            key,
+            hash: content_hash(&content),
+            content,
+            state,
+        })
+    }
+
+    /// Whether the item has been modified locally since the last sync.
+    fn changed(&self) -> bool {
+        match &self.state {
+            None => true,
+            Some((_, Some(hash))) => hash[..] != self.hash,
+            // Pulled by an earlier version that did not record the hash of pulled profiles.
+            Some((_, None)) => false,
+        }
+    }
+
+    fn base_vector(&self) -> VersionVector {
+        self.state
+            .as_ref()
+            .map(|(v, _)| v.clone())
+            .unwrap_or_default()
+    }
+
+    fn save_state(
+        &self,
+        vector: &VersionVector,
+        hash: Option<&[u8; 32]>,
+        conn: &DbConnection,
+    ) -> SyncResult<()> {
+        SyncState::upsert(&self.key, &vector.encode(), hash.map(|h| &h[..]), conn)?;
+        Ok(())
+    }
+
+    fn push(
+        &self,
+        backend: &dyn SyncBackend,
+        device_id: &str,
+        vector: &VersionVector,
+        precondition: Precondition<'_>,
+        conn: &DbConnection,
+    ) -> SyncResult<SyncOutcome> {
+        let snapshot = Snapshot {
+            version: SNAPSHOT_VERSION,
+            device_id: device_id.into(),
+            vector: vector.clone(),
+            content: ByteBuf::from(self.content.clone()),
+        };
+        backend.put(&self.key, &encode_cbor(&snapshot), precondition)?;
+        self.save_state(vector, Some(&self.hash), conn)?;
+        Ok(SyncOutcome::Pushed)
+    }
+
+    fn pull(self, snapshot: Snapshot, conn: &mut DbConnection) -> SyncResult<SyncOutcome> {
+        match self.item {
+            // Nothing is recorded until the app applies the content with
+            // `apply_pulled_profile`. Otherwise the local profile would count as changed on the
+            // next sync and be pushed over the newer snapshot.
+            SyncItem::Profile(_) => Ok(SyncOutcome::Pulled {
+                content: snapshot.content,
+                applied: false,
+            }),
+            SyncItem::ProxyGroup(id) => {
+                let content: ProxyGroupContent = cbor4ii::serde::from_slice(&snapshot.content)
+                    .map_err(|_| SyncError::InvalidSnapshot)?;
+                let proxies = content
+                    .proxies
+                    .into_iter()
+                    .map(|p| ProxyInput {
+                        name: p.name,
+                        proxy: p.proxy,
+                        proxy_version: p.proxy_version,
+                    })
+                    .collect();
+                Proxy::batch_update_by_group(id.into(), proxies, conn)?;
+                let (_, applied) = read_content(self.item, conn)?;
+                self.save_state(&snapshot.vector, Some(&content_hash(&applied)), conn)?;
+                Ok(SyncOutcome::Pulled {
+                    content: snapshot.content,
+                    applied: true,
+                })
+            }
+        }
+    }
+}
+
+fn fetch_snapshot(
+    backend: &dyn SyncBackend,
+    key: &str,
+) -> SyncResult<Option<(Snapshot, Option<String>)>> {
+    let Some(remote) = backend.get(key)? else {
+        return Ok(None);
+    };
+    let snapshot: Snapshot =
+        cbor4ii::serde::from_slice(&remote.body).map_err(|_| SyncError::InvalidSnapshot)?;
+    if snapshot.version != SNAPSHOT_VERSION {
+        return Err(SyncError::InvalidSnapshot);
+    }
+    Ok(Some((snapshot, remote.etag)))
+}
+
+fn replace_precondition(etag: &Option<String>) -> Precondition<'_> {
+    etag.as_deref()
+        .map_or(Precondition::Any, Precondition::Matches)
+}
+
+/// Bring a local item and its remote snapshot up to date with each other, in whichever direction
+/// the version vectors allow.
+pub fn sync_item(
+    backend: &dyn SyncBackend,
+    item: SyncItem,
+    device_id: &str,
+    conn: &mut DbConnection,
+) -> SyncResult<SyncOutcome> {
+    let local = LocalItem::load(item, conn)?;
+    let mut vector = local.base_vector();
+    if local.changed() {
+        vector.increment(device_id);
+    }
+    let Some((snapshot, etag)) = fetch_snapshot(backend, &local.key)? else {
+        return local.push(backend, device_id, &vector, Precondition::Absent, conn);
+    };
+    if content_hash(&snapshot.content) == local.hash {
+        // Both sides ended up with the same content.
+        vector.merge(&snapshot.vector);
+        local.save_state(&vector, Some(&local.hash), conn)?;
+        return Ok(SyncOutcome::UpToDate);
+    }
+    match vector.compare(&snapshot.vector) {
+        Causality::Equal => {
+            local.save_state(&vector, Some(&local.hash), conn)?;
+            Ok(SyncOutcome::UpToDate)
+        }
+        Causality::After => local.push(
+            backend,
+            device_id,
+            &vector,
+            replace_precondition(&etag),
+            conn,
+        ),
+        Causality::Before => local.pull(snapshot, conn),
+        Causality::Concurrent => Ok(SyncOutcome::Conflict {
+            remote_device_id: snapshot.device_id,
+            remote_content: snapshot.content,
+        }),
+    }
+}
+
+/// Settle a conflict reported by [`sync_item`] by keeping either the local item or the remote
+/// snapshot.
+pub fn resolve_conflict(
+    backend: &dyn SyncBackend,
+    item: SyncItem,
+    device_id: &str,
+    keep_local: bool,
+    conn: &mut DbConnection,
+) -> SyncResult<SyncOutcome> {
+    let local = LocalItem::load(item, conn)?;
+    let mut vector = local.base_vector();
+    let Some((snapshot, etag)) = fetch_snapshot(backend, &local.key)? else {
+        vector.increment(device_id);
+        return local.push(backend, device_id, &vector, Precondition::Absent, conn);
+    };
+    if !keep_local {
+        return local.pull(snapshot, conn);
+    }
+    vector.merge(&snapshot.vector);
+    vector.increment(device_id);
+    local.push(
+        backend,
+        device_id,
+        &vector,
+        replace_precondition(&etag),
+        conn,
+    )
+}
+
+/// Import a profile pulled by [`sync_item`] or [`resolve_conflict`] over the local one, keeping
+/// its ID and permanent ID, and record the pull. Fails with [`SyncError::RemoteChanged`] if the
+/// remote snapshot no longer has `content`.
+pub fn apply_pulled_profile(
+    backend: &dyn SyncBackend,
+    profile_id: u32,
+    content: &[u8],
+    conn: &mut DbConnection,
+) -> SyncResult<()> {
+    let item = SyncItem::Profile(profile_id);
+    let local = LocalItem::load(item, conn)?;
+    let Some((snapshot, _)) = fetch_snapshot(backend, &local.key)? else {
+        return Err(SyncError::RemoteChanged);
+    };
+    if content_hash(&snapshot.content) != content_hash(content) {
+        return Err(SyncError::RemoteChanged);
+    }
+    let parsed = parse_profile_toml(content).map_err(|_| SyncError::InvalidSnapshot)?;
+    replace_profile(profile_id, parsed, conn)?;
+    let (_, applied) = read_content(item, conn)?;
+    local.save_state(&snapshot.vector, Some(&content_hash(&applied)), conn)?;
+    Ok(())
+}
+
+/// Replace the name, translations, plugins and entry plugins of a profile in one transaction.
+/// Plugins are matched by name, so that matching plugins keep their IDs.
+fn replace_profile(
+    profile_id: u32,
+    parsed: ParsedTomlProfile,
+    conn: &mut DbConnection,
+) -> SyncResult<()> {
+    let tx = conn.transaction().map_err(DataError::from)?;
+    let profile = Profile::query_by_id(profile_id as _, &tx)?.ok_or_else(not_found)?;
+    Profile::update(
+        profile_id,
+        parsed.name.unwrap_or(profile.name),
+        parsed.locale.unwrap_or(profile.locale),
+        &tx,
+    )?;
+    for translation in ProfileTranslation::query_all_by_profile(profile.id, &tx)? {
+        if !parsed.translations.contains_key(&translation.locale) {
+            ProfileTranslation::delete(profile.id, &translation.locale, &tx)?;
+        }
+    }
+    for (locale, translation) in parsed.translations {
+        ProfileTranslation::upsert(profile.id, locale, translation.name, translation.desc, &tx)?;
+    }
+
+    for entry in Plugin::query_entry_by_profile(profile.id, &tx)? {
+        Plugin::unset_as_entry(profile.id, entry.id, &tx)?;
+    }
+    let existing = Plugin::query_all_by_profile(profile.id, &tx)?;
+    for plugin in existing
+        .iter()
+        .filter(|p| !parsed.plugins.iter().any(|n| n.plugin.name == p.name))
+    {
+        Plugin::delete(plugin.id.0, &tx)?;
+    }
+    for parsed_plugin in parsed.plugins {
+        let plugin = parsed_plugin.plugin;
+        let plugin_id: PluginId = match existing.iter().find(|p| p.name == plugin.name) {
+            Some(p) => {
+                Plugin::update(
+                    p.id.0,
+                    profile.id,
+                    plugin.name,
+                    plugin.desc,
+                    plugin.plugin,
+                    plugin.plugin_version,
+                    plugin.param.into_vec(),
+                    &tx,
+                )?;
+                p.id
+            }
+            None => Plugin::create(
+                profile.id,
+                plugin.name,
+                plugin.desc,
+                plugin.plugin,
+                plugin.plugin_version,
+                plugin.param.into_vec(),
+                &tx,
+            )?
+            .into(),
+        };
+        for translation in PluginTranslation::query_all_by_plugin(plugin_id, &tx)? {
+            if !parsed_plugin.translations.contains_key(&translation.locale) {
+                PluginTranslation::delete(plugin_id, &translation.locale, &tx)?;
+            }
+        }
+        for (locale, desc) in parsed_plugin.translations {
+            PluginTranslation::upsert(plugin_id, locale, desc, &tx)?;
+        }
+        if parsed_plugin.is_entry {
+            Plugin::set_as_entry(profile.id, plugin_id, &tx)?;
+        }
+    }
+    tx.commit().map_err(DataError::from)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    use ytflow::data::Database;
+
+    use super::super::backend::RemoteObject;
+    use super::*;
+
+    #[derive(Default)]
+    struct MemoryBackend {
+        objects: RefCell<HashMap<String, (Vec<u8>, u32)>>,
+    }
+
+    impl SyncBackend for MemoryBackend {
+        fn get(&self, key: &str) -> SyncResult<Option<RemoteObject>> {
+            Ok(self
+                .objects
+                .borrow()
+                .get(key)
+                .map(|(body, rev)| RemoteObject {
+                    body: body.clone(),
+                    etag: Some(rev.to_string()),
+                }))
+        }
+        fn put(&self, key: &str, body: &[u8], precondition: Precondition<'_>) -> SyncResult<()> {
+            let mut objects = self.objects.borrow_mut();
+            let rev = objects.get(key).map(|(_, rev)| *rev);
+            match (precondition, rev) {
+                (Precondition::Absent, Some(_)) => return Err(SyncError::RemoteChanged),
+                (Precondition::Matches(etag), Some(rev)) if etag != rev.to_string() => {
+                    return Err(SyncError::RemoteChanged)
+                }
+                _ => {}
+            }
+            objects.insert(key.into(), (body.to_vec(), rev.unwrap_or(0) + 1));
+            Ok(())
+        }
+    }
+
+    fn create_group(conn: &DbConnection, proxies: &[&str]) -> SyncItem {
+        let id = ProxyGroup::create("group".into(), "manual".into(), conn).unwrap();
+        for name in proxies {
+            Proxy::create(id.into(), name.to_string(), vec![], 0, conn).unwrap();
+        }
+        SyncItem::ProxyGroup(id)
+    }
+
+    fn put_snapshot(
+        backend: &MemoryBackend,
+        key: &str,
+        device_id: &str,
+        vector: VersionVector,
+        content: Vec<u8>,
+    ) {
+        let snapshot = Snapshot {
+            version: SNAPSHOT_VERSION,
+            device_id: device_id.into(),
+            vector,
+            content: ByteBuf::from(content),
+        };
+        backend
+            .put(key, &encode_cbor(&snapshot), Precondition::Any)
+            .unwrap();
+    }
+
+    fn proxy_names(item: SyncItem, conn: &DbConnection) -> Vec<String> {
+        let SyncItem::ProxyGroup(id) = item else {
+            unreachable!()
+        };
+        Proxy::query_all_by_group(id.into(), conn)
+            .unwrap()
+            .into_iter()
+            .map(|p| p.name)
+            .collect()
+    }
+
+    #[test]
+    fn test_push_then_pull() {
+        let backend = MemoryBackend::default();
+        let mut a = Database::connect_temp().unwrap();
+        let mut b = Database::connect_temp().unwrap();
+        let item_a = create_group(&a, &["p1"]);
+        let item_b = create_group(&b, &["p1"]);
+
+        assert!(matches!(
+            sync_item(&backend, item_a, "a", &mut a).unwrap(),
+            SyncOutcome::Pushed
+        ));
+        // Same content on both devices
+        assert!(matches!(
+            sync_item(&backend, item_b, "b", &mut b).unwrap(),
+            SyncOutcome::UpToDate
+        ));
+
+        let SyncItem::ProxyGroup(id) = item_a else {
+            unreachable!()
+        };
+        Proxy::create(id.into(), "p2".into(), vec![], 0, &a).unwrap();
+        assert!(matches!(
+            sync_item(&backend, item_a, "a", &mut a).unwrap(),
+            SyncOutcome::Pushed
+        ));
+        assert!(matches!(
+            sync_item(&backend, item_b, "b", &mut b).unwrap(),
+            SyncOutcome::Pulled { applied: true, .. }
+        ));
+        assert_eq!(proxy_names(item_b, &b), ["p1", "p2"]);
+        assert!(matches!(
+            sync_item(&backend, item_b, "b", &mut b).unwrap(),
+            SyncOutcome::UpToDate
+        ));
+    }
+
+    #[test]
+    fn test_conflict() {
+        let backend = MemoryBackend::default();
+        let mut a = Database::connect_temp().unwrap();
+        let mut b = Database::connect_temp().unwrap();
+        let item_a = create_group(&a, &["p1"]);
+        let item_b = create_group(&b, &["p2"]);
+
+        sync_item(&backend, item_a, "a", &mut a).unwrap();
+        assert!(matches!(
+            sync_item(&backend, item_b, "b", &mut b).unwrap(),
+            SyncOutcome::Conflict { remote_device_id, .. } if remote_device_id == "a"
+        ));
+
+        resolve_conflict(&backend, item_b, "b", true, &mut b).unwrap();
+        assert!(matches!(
+            sync_item(&backend, item_a, "a", &mut a).unwrap(),
+            SyncOutcome::Pulled { .. }
+        ));
+        assert_eq!(proxy_names(item_a, &a), ["p2"]);
+    }
+
+    #[test]
+    fn test_pull_edit_push_profile() {
+        let backend = MemoryBackend::default();
+        let mut conn = Database::connect_temp().unwrap();
+        let id = Profile::create("p".into(), "en-US".into(), &conn).unwrap();
+        let item = SyncItem::Profile(id);
+        assert!(matches!(
+            sync_item(&backend, item, "a", &mut conn).unwrap(),
+            SyncOutcome::Pushed
+        ));
+
+        // Another device renames the profile.
+        Profile::update(id, "q".into(), "en-US".into(), &conn).unwrap();
+        let (key, renamed) = read_content(item, &conn).unwrap();
+        Profile::update(id, "p".into(), "en-US".into(), &conn).unwrap();
+        let mut vector = VersionVector::new();
+        vector.increment("a");
+        vector.increment("b");
+        put_snapshot(&backend, &key, "b", vector, renamed);
+        let SyncOutcome::Pulled {
+            content,
+            applied: false,
+        } = sync_item(&backend, item, "a", &mut conn).unwrap()
+        else {
+            panic!("expected an unapplied pull");
+        };
+
+        // The app imports the pulled profile, which is then in sync.
+        apply_pulled_profile(&backend, id, &content, &mut conn).unwrap();
+        assert_eq!(
+            Profile::query_by_id(id as _, &conn).unwrap().unwrap().name,
+            "q"
+        );
+        assert!(matches!(
+            sync_item(&backend, item, "a", &mut conn).unwrap(),
+            SyncOutcome::UpToDate
+        ));
+
+        // Later edits are pushed.
+        Profile::update(id, "r".into(), "en-US".into(), &conn).unwrap();
+        assert!(matches!(
+            sync_item(&backend, item, "a", &mut conn).unwrap(),
+            SyncOutcome::Pushed
+        ));
+    }
+
+    #[test]
+    fn test_unapplied_profile_pull_is_not_pushed() {
+        let backend = MemoryBackend::default();
+        let mut conn = Database::connect_temp().unwrap();
+        let id = Profile::create("p".into(), "en-US".into(), &conn).unwrap();
+        let item = SyncItem::Profile(id);
+        sync_item(&backend, item, "a", &mut conn).unwrap();
+
+        Profile::update(id, "q".into(), "en-US".into(), &conn).unwrap();
+        let (key, renamed) = read_content(item, &conn).unwrap();
+        Profile::update(id, "p".into(), "en-US".into(), &conn).unwrap();
+        let mut vector = VersionVector::new();
+        vector.increment("a");
+        vector.increment("b");
+        put_snapshot(&backend, &key, "b", vector, renamed.clone());
+
+        // The app never imports the pulled profile, so it is offered again instead of the
+        // stale local profile being pushed over it.
+        for _ in 0..2 {
+            assert!(matches!(
+                sync_item(&backend, item, "a", &mut conn).unwrap(),
+                SyncOutcome::Pulled { applied: false, .. }
+            ));
+        }
+        let (snapshot, _) = fetch_snapshot(&backend, &key).unwrap().unwrap();
+        assert_eq!(snapshot.device_id, "b");
+        assert_eq!(&snapshot.content[..], &renamed[..]);
+
+        // Content that is no longer on the remote is not applied.
+        assert!(matches!(
+            apply_pulled_profile(&backend, id, b"version = 1", &mut conn),
+            Err(SyncError::RemoteChanged)
+        ));
+    }
+}
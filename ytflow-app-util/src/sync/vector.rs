@@ -0,0 +1,104 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// How two version vectors relate to each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Causality {
+    Equal,
+    /// Every change seen by this side has been seen by the other side.
+    Before,
+    /// This side has seen every change seen by the other side, and more.
+    After,
+    /// Both sides have seen changes unknown to the other side.
+    Concurrent,
+}
+
+/// A vector timestamp counting the changes made on each device.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct VersionVector(BTreeMap<String, u64>);
+
+impl VersionVector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, device_id: &str) -> u64 {
+        self.0.get(device_id).copied().unwrap_or(0)
+    }
+
+    /// Record a change made on `device_id`.
+    pub fn increment(&mut self, device_id: &str) {
+        *self.0.entry(device_id.to_string()).or_default() += 1;
+    }
+
+    /// Take the element-wise maximum of both vectors.
+    pub fn merge(&mut self, other: &VersionVector) {
+        for (device_id, &counter) in &other.0 {
+            let entry = self.0.entry(device_id.clone()).or_default();
+            *entry = (*entry).max(counter);
+        }
+    }
+
+    pub fn compare(&self, other: &VersionVector) -> Causality {
+        let mut ret = Causality::Equal;
+        for device_id in self.0.keys().chain(other.0.keys()) {
+            let this = match self.get(device_id).cmp(&other.get(device_id)) {
+                Ordering::Equal => continue,
+                Ordering::Less => Causality::Before,
+                Ordering::Greater => Causality::After,
+            };
+            if ret == Causality::Equal {
+                ret = this;
+            } else if ret != this {
+                return Causality::Concurrent;
+            }
+        }
+        ret
+    }
+
+    pub(super) fn encode(&self) -> Vec<u8> {
+        cbor4ii::serde::to_vec(vec![], self).expect("Could not serialize version vector")
+    }
+
+    pub(super) fn decode(buf: &[u8]) -> Option<Self> {
+        cbor4ii::serde::from_slice(buf).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vector(counters: &[(&str, u64)]) -> VersionVector {
+        VersionVector(counters.iter().map(|(d, c)| (d.to_string(), *c)).collect())
+    }
+
+    #[test]
+    fn test_compare() {
+        let a = vector(&[("a", 1), ("b", 2)]);
+        assert_eq!(a.compare(&a.clone()), Causality::Equal);
+        assert_eq!(a.compare(&vector(&[("a", 1), ("b", 3)])), Causality::Before);
+        assert_eq!(a.compare(&vector(&[("a", 1)])), Causality::After);
+        assert_eq!(
+            a.compare(&vector(&[("a", 2), ("b", 1)])),
+            Causality::Concurrent
+        );
+        assert_eq!(
+            a.compare(&vector(&[("a", 1), ("b", 2), ("c", 1)])),
+            Causality::Before
+        );
+        assert_eq!(VersionVector::new().compare(&a), Causality::Before);
+    }
+
+    #[test]
+    fn test_increment_merge() {
+        let mut a = vector(&[("a", 1), ("b", 2)]);
+        a.merge(&vector(&[("a", 3), ("c", 1)]));
+        a.increment("b");
+        assert_eq!(a, vector(&[("a", 3), ("b", 3), ("c", 1)]));
+        assert_eq!(VersionVector::decode(&a.encode()), Some(a));
+    }
+}
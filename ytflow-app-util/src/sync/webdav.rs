@@ -0,0 +1,85 @@
+use base64::prelude::*;
+
+use super::backend::*;
+use super::{SyncError, SyncResult};
+
+pub struct WebDavBackend {
+    agent: ureq::Agent,
+    /// Always ends with a slash.
+    base_url: String,
+    authorization: Option<String>,
+}
+
+impl WebDavBackend {
+    pub fn new(
+        url: &str,
+        username: Option<&str>,
+        password: Option<&str>,
+    ) -> SyncResult<WebDavBackend> {
+        let mut base_url = url::Url::parse(url)
+            .ok()
+            .filter(|u| matches!(u.scheme(), "http" | "https"))
+            .ok_or(SyncError::InvalidEndpoint)?
+            .to_string();
+        if !base_url.ends_with('/') {
+            base_url.push('/');
+        }
+        let authorization = username.map(|user| {
+            let credentials = format!("{}:{}", user, password.unwrap_or_default());
+            format!("Basic {}", BASE64_STANDARD.encode(credentials))
+        });
+        Ok(WebDavBackend {
+            agent: new_agent(),
+            base_url,
+            authorization,
+        })
+    }
+
+    fn request(&self, method: &str, path: &str) -> ureq::Request {
+        let req = self
+            .agent
+            .request(method, &format!("{}{}", self.base_url, path));
+        match &self.authorization {
+            Some(auth) => req.set("Authorization", auth),
+            None => req,
+        }
+    }
+
+    /// Create the collections leading to `key`, ignoring those that already exist.
+    fn create_collections(&self, key: &str) -> SyncResult<()> {
+        let mut path = String::new();
+        let Some((parent, _)) = key.rsplit_once('/') else {
+            return Ok(());
+        };
+        for segment in parent.split('/') {
+            path.push_str(segment);
+            path.push('/');
+            match self.request("MKCOL", &path).call() {
+                // 405: the collection already exists
+                Ok(_) | Err(ureq::Error::Status(405, _)) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl SyncBackend for WebDavBackend {
+    fn get(&self, key: &str) -> SyncResult<Option<RemoteObject>> {
+        read_object(self.request("GET", key).call())
+    }
+
+    fn put(&self, key: &str, body: &[u8], precondition: Precondition<'_>) -> SyncResult<()> {
+        let put = || apply_precondition(self.request("PUT", key), precondition).send_bytes(body);
+        match put() {
+            Ok(_) => Ok(()),
+            // 409: the parent collection does not exist
+            Err(ureq::Error::Status(409, _)) => {
+                self.create_collections(key)?;
+                put()?;
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+}
@@ -1,17 +1,45 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
+use std::panic::AssertUnwindSafe;
 use std::path::{Path, PathBuf};
-use std::time::Duration;
+use std::process::ExitCode;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::{Duration, Instant, SystemTime};
 
 use anyhow::{Context, Result};
-use clap::{arg, value_parser, ArgMatches};
+use clap::{arg, value_parser, ArgAction, ArgMatches};
 use log::{error, info, warn};
 
+mod failure;
 mod fs_resource_loader;
 
-pub fn main() -> Result<()> {
+use failure::FailureKind;
+
+pub fn main() -> ExitCode {
     let args = get_args();
     init_log(&args);
-    try_main(&args)
+    #[cfg(feature = "tokio-console")]
+    ytflow::log::init_console();
+    let activated = ytflow::plugin::socket::init_activated_sockets();
+    if activated > 0 {
+        info!("Adopted {} sockets from socket activation", activated);
+    }
+    let (kind, fatal) = match std::panic::catch_unwind(AssertUnwindSafe(|| try_main(&args))) {
+        Ok(Ok(())) => return ExitCode::SUCCESS,
+        Ok(Err(e)) => {
+            error!("{:?}", e);
+            (
+                failure::classify(&e),
+                e.chain().map(|e| e.to_string()).collect(),
+            )
+        }
+        Err(payload) => (FailureKind::Panic, vec![failure::panic_message(&*payload)]),
+    };
+    let snapshot_path = args.get_one::<PathBuf>("failure-snapshot");
+    match failure::write_snapshot(snapshot_path.map(|p| p.as_path()), kind, fatal) {
+        Ok(path) => info!("Failure snapshot written to {}", path.display()),
+        Err(e) => warn!("Failed to write failure snapshot: {}", e),
+    }
+    kind.into()
 }
 
 fn get_args() -> ArgMatches {
@@ -26,9 +54,34 @@ fn get_args() -> ArgMatches {
                 .value_parser(value_parser!(PathBuf))
                 .required(false)
         )
+        .arg(
+            arg!(--watch <DIR> "Load the profile from TOML files in this directory instead of the database, and reload plugins when the files change")
+                .value_parser(value_parser!(PathBuf))
+                .required(false)
+        )
         .arg(arg!([PROFILE] "Specify the name of the profile to use"))
         // .arg(arg!(-l --"from-link" <LINK> "Generate a new profile using the provided share link as outbound, and save to the database").required(false))
         .arg(arg!(--"skip-grace" "Start immediately. Do not wait for 3 seconds before YtFlow starts running").required(false))
+        .arg(arg!(--"no-tun" "Do not start TUN related plugins, such as ip-stack. Useful on routers where only listeners are needed. Always on for builds without TUN support").required(false))
+        .arg(arg!(--"count-traffic" "Count traffic passing through each plugin, as reported to frontends. Adds a little overhead to every connection").required(false))
+        .arg(
+            arg!(--"allow-command" <COMMAND> "Allow automation rules of the profile to run this program with exactly these arguments, terminated by `;`. Can be specified multiple times")
+                .num_args(1..)
+                .value_terminator(";")
+                .allow_hyphen_values(true)
+                .action(ArgAction::Append)
+                .required(false)
+        )
+        .arg(
+            arg!(--"failure-snapshot" <PATH> "Where to write a JSON snapshot of errors and the running Profile when YtFlow exits on failure. Defaults to a new ytflow-core-failure-*.json file in the temporary directory")
+                .value_parser(value_parser!(PathBuf))
+                .required(false)
+        )
+        .arg(
+            arg!(--"drain-timeout" <SECS> "How long connections of the previous plugins may keep going after the profile is reloaded. Defaults to 30 seconds")
+                .value_parser(value_parser!(u64))
+                .default_value("30")
+        )
         .arg(arg!(-v --verbose "Turn on verbose logging").required(false))
         .get_matches()
 }
@@ -87,34 +140,16 @@ fn init_resource_loader(args: &ArgMatches) -> Result<fs_resource_loader::FsResou
     Ok(loader)
 }
 
-fn try_main(args: &ArgMatches) -> Result<()> {
-    let db = args
-        .get_one::<PathBuf>("db-path")
-        .map(AsRef::<Path>::as_ref)
-        .map(Path::canonicalize)
-        .transpose()
-        .context("Failed to load database path")?
-        .map(|path| {
-            info!("Connecting to database: {}", path.display());
-            ytflow::data::Database::open(path)
-        })
-        .transpose()
-        .context("Failed to open database")?;
-
-    let conn = if let Some(db) = &db {
-        db.connect().context("Failed to connect to database")?
-    } else {
-        info!("Connecting to database: in-memory");
-        ytflow::data::Database::connect_temp().expect("Could not open in-memory database")
-    };
-
-    let profile_name = args
-        .get_one::<String>("PROFILE")
-        .map(|s| s.as_str())
-        .unwrap_or("default");
-    info!("Selected Profile: {}", profile_name);
+struct ProfilePlugins {
+    entry_plugins: Vec<ytflow::config::Plugin>,
+    all_plugins: Vec<ytflow::config::Plugin>,
+}
 
-    let all_profiles = ytflow::data::Profile::query_all(&conn)
+fn load_profile_from_db(
+    conn: &ytflow::data::Connection,
+    profile_name: &str,
+) -> Result<ProfilePlugins> {
+    let all_profiles = ytflow::data::Profile::query_all(conn)
         .context("Failed to load all Profiles from database")?;
     let profile = all_profiles
         .iter()
@@ -132,17 +167,168 @@ fn try_main(args: &ArgMatches) -> Result<()> {
             anyhow::anyhow!("Profile not found")
         })?;
 
-    let all_plugins: Vec<_> = ytflow::data::Plugin::query_all_by_profile(profile.id, &conn)
+    let all_plugins: Vec<_> = ytflow::data::Plugin::query_all_by_profile(profile.id, conn)
         .context("Failed to load all plugins for selected Profile from database")?
         .into_iter()
         .map(From::from)
         .collect();
-    let entry_plugins: Vec<_> = ytflow::data::Plugin::query_entry_by_profile(profile.id, &conn)
+    let entry_plugins: Vec<_> = ytflow::data::Plugin::query_entry_by_profile(profile.id, conn)
         .context("Failed to load entry plugins for selected Profile from database")?
         .into_iter()
         .map(From::from)
         .collect();
+    Ok(ProfilePlugins {
+        entry_plugins,
+        all_plugins,
+    })
+}
+
+/// Collect modification times of all TOML files in the watched directory.
+fn snapshot_toml_files(dir: &Path) -> Result<BTreeMap<PathBuf, SystemTime>> {
+    let mut files = BTreeMap::new();
+    for entry in std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read directory {}", dir.display()))?
+    {
+        let path = entry?.path();
+        if path.extension().map_or(true, |ext| ext != "toml") || !path.is_file() {
+            continue;
+        }
+        let modified = std::fs::metadata(&path)
+            .and_then(|m| m.modified())
+            .with_context(|| format!("Failed to read metadata of {}", path.display()))?;
+        files.insert(path, modified);
+    }
+    Ok(files)
+}
+
+/// Find the profile in the watched directory. A TOML file matches if the `name`
+/// in it equals the selected profile name, or, when the name is absent, if its
+/// file stem does.
+fn load_profile_from_dir(dir: &Path, profile_name: &str) -> Result<ProfilePlugins> {
+    let mut candidates = vec![];
+    for path in snapshot_toml_files(dir)?.into_keys() {
+        let content = std::fs::read(&path)
+            .with_context(|| format!("Failed to read profile file {}", path.display()))?;
+        let profile = ytflow_app_util::profile::parse_profile_toml(&content)
+            .with_context(|| format!("Failed to parse profile file {}", path.display()))?;
+        let matched = match &profile.name {
+            Some(name) => name == profile_name,
+            None => path.file_stem().map_or(false, |s| s == profile_name),
+        };
+        if matched {
+            candidates.push((path, profile));
+        }
+    }
+    let (path, profile) = match candidates.len() {
+        0 => anyhow::bail!(
+            r#"Cannot find Profile "{}" in {}"#,
+            profile_name,
+            dir.display()
+        ),
+        1 => candidates.pop().unwrap(),
+        _ => anyhow::bail!(
+            r#"Multiple files define Profile "{}": {}"#,
+            profile_name,
+            candidates
+                .iter()
+                .map(|(p, _)| p.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    info!("Loading Profile from {}", path.display());
+
+    let mut entry_plugins = vec![];
+    let mut all_plugins = vec![];
+    for plugin in profile.plugins {
+        let config_plugin: ytflow::config::Plugin = plugin.plugin.into();
+        if plugin.is_entry {
+            entry_plugins.push(config_plugin.clone());
+        }
+        all_plugins.push(config_plugin);
+    }
+    Ok(ProfilePlugins {
+        entry_plugins,
+        all_plugins,
+    })
+}
+
+struct RunningPlugins {
+    // Dropped first, before the responders in the control hub
+    plugin_set: ytflow::config::PluginSet,
+    control_hub: ytflow::control::ControlHub,
+    /// Some listeners could not bind to their addresses.
+    bind_failed: bool,
+    /// None of the entry plugins could bind to their addresses.
+    nothing_bound: bool,
+}
+
+/// The plugin that could not bind to its address.
+fn bind_error_plugin(error: &ytflow::config::LoadError) -> Option<&str> {
+    use std::io::ErrorKind;
+    use ytflow::config::LoadError;
+    match error {
+        LoadError::PortInUse { plugin, .. } => Some(plugin),
+        LoadError::Io { plugin, error }
+            if matches!(
+                error.kind(),
+                ErrorKind::AddrInUse | ErrorKind::AddrNotAvailable
+            ) =>
+        {
+            Some(plugin)
+        }
+        _ => None,
+    }
+}
+
+/// Plugin types that need a TUN device from the platform.
+const TUN_PLUGIN_TYPES: &[&str] = &["ip-stack", "vpn-tun"];
+
+fn is_no_tun(args: &ArgMatches) -> bool {
+    args.get_flag("no-tun") || cfg!(not(feature = "tun"))
+}
+
+fn start_plugins(
+    args: &ArgMatches,
+    plugins: &ProfilePlugins,
+    conn: &ytflow::data::Connection,
+    db: Option<&ytflow::data::Database>,
+    runtime: &ytflow::tokio::runtime::Runtime,
+    reloader: &ytflow::control::ProfileReloader,
+) -> Result<RunningPlugins> {
     use ytflow::config::loader::{ProfileLoadResult, ProfileLoader};
+    let mut all_plugins = plugins.all_plugins.clone();
+    let (migration_reports, migration_errors) = ProfileLoader::migrate_profile(&mut all_plugins);
+    for report in &migration_reports {
+        info!(
+            r#"Migrated plugin "{}" from {} v{} to {} v{}"#,
+            report.name,
+            report.from_plugin,
+            report.from_version,
+            report.to_plugin,
+            report.to_version
+        );
+        if !report.added_plugins.is_empty() {
+            info!("Added plugins: {}", report.added_plugins.join(", "));
+        }
+    }
+    for migration_error in migration_errors {
+        warn!("{}", migration_error);
+        failure::record_error(&migration_error);
+    }
+    let no_tun = is_no_tun(args);
+    let entry_plugins: Vec<_> = all_plugins
+        .iter()
+        .filter(|p| plugins.entry_plugins.iter().any(|e| e.name == p.name))
+        .filter(|p| {
+            let skipped = no_tun && TUN_PLUGIN_TYPES.contains(&&*p.plugin);
+            if skipped {
+                info!(r#"Skipping entry plugin "{}" of type {}"#, p.name, p.plugin);
+            }
+            !skipped
+        })
+        .cloned()
+        .collect();
     let (factory, required_resources, load_errors) =
         ProfileLoader::parse_profile(entry_plugins.iter(), &all_plugins);
     if !load_errors.is_empty() {
@@ -153,14 +339,9 @@ fn try_main(args: &ArgMatches) -> Result<()> {
     }
     for load_error in load_errors {
         warn!("{}", load_error);
+        failure::record_error(&load_error);
     }
 
-    let runtime = ytflow::tokio::runtime::Builder::new_multi_thread()
-        .enable_all()
-        .build()
-        .context("Error initializing Tokio runtime")?;
-    let runtime_enter_guard = runtime.enter();
-
     let resource_registry = if required_resources.is_empty() {
         Box::new(ytflow::resource::EmptyResourceRegistry) as _
     } else {
@@ -170,41 +351,177 @@ fn try_main(args: &ArgMatches) -> Result<()> {
             .collect::<BTreeSet<_>>();
         let resource_len = resource_keys.len();
         let mut loader =
-            ytflow::resource::DbFileResourceLoader::new_with_required_keys(resource_keys, &conn)
-                .context("Loading resource information from database")?;
+            ytflow::resource::DbFileResourceLoader::new_with_required_keys(resource_keys, conn)
+                .context("Loading resource information from database")
+                .context(FailureKind::Resource)?;
         info!("Loading {} resources...", resource_len);
         runtime
-            .block_on(futures::future::join_all(
-                loader.load_required_files(&init_resource_loader(args)?),
-            ))
+            .block_on(futures::future::join_all(loader.load_required_files(
+                &init_resource_loader(args).context(FailureKind::Resource)?,
+            )))
             .into_iter()
             .collect::<Result<Vec<_>, _>>()
-            .context("Loading resource from file system")?;
+            .context("Loading resource from file system")
+            .context(FailureKind::Resource)?;
         info!("Resources loaded");
         Box::new(loader) as _
     };
 
-    if !args.get_flag("skip-grace") {
-        info!("Starting YtFlow in 3 seconds...");
-        std::thread::sleep(Duration::from_secs(3));
-    }
     info!("Starting YtFlow...");
-
     let ProfileLoadResult {
         plugin_set,
         errors: load_errors,
-        ..
-    } = factory.load_all(runtime.handle(), resource_registry, db.as_ref());
+        mut control_hub,
+    } = factory
+        .count_traffic(args.get_flag("count-traffic"))
+        .load_all(runtime.handle(), resource_registry, db);
+    control_hub.set_profile_reloader(reloader.clone());
     if !load_errors.is_empty() {
         warn!(
             "{} errors detected while loading plugins:",
             load_errors.len()
         );
     }
+    let bind_failed_plugins: BTreeSet<_> =
+        load_errors.iter().filter_map(bind_error_plugin).collect();
+    let bind_failed = !bind_failed_plugins.is_empty();
+    let nothing_bound = !entry_plugins.is_empty()
+        && entry_plugins
+            .iter()
+            .all(|p| bind_failed_plugins.contains(p.name.as_str()));
     for load_error in load_errors {
         error!("{}", load_error);
+        failure::record_error(&load_error);
     }
     info!("Plugins loaded");
+    Ok(RunningPlugins {
+        plugin_set,
+        control_hub,
+        bind_failed,
+        nothing_bound,
+    })
+}
+
+/// Shut down a replaced plugin set. Relayed connections outlive their plugin
+/// set, so let them finish on their own for a while instead of cutting them
+/// off.
+fn retire_plugins(
+    old: RunningPlugins,
+    draining: &mut Vec<(ytflow::flow::ConnectionTracker, Instant)>,
+    drain_timeout: Duration,
+) {
+    info!("Shutting down the previous plugins");
+    let connections = old.control_hub.connections().clone();
+    drop(old);
+    if !connections.is_empty() {
+        info!(
+            "Draining {} connections of the previous plugins",
+            connections.len()
+        );
+        draining.push((connections, Instant::now() + drain_timeout));
+    }
+}
+
+/// Look up a profile activated by a schedule.
+fn load_activated_profile(
+    profile_id: ytflow::data::ProfileId,
+    is_watching: bool,
+    conn: &ytflow::data::Connection,
+) -> Result<(String, ProfilePlugins)> {
+    if is_watching {
+        anyhow::bail!("Profiles cannot be activated by schedules while watching files");
+    }
+    let profile = ytflow::data::Profile::query_by_id(profile_id.0 as usize, conn)
+        .context("Failed to load Profile from database")?
+        .with_context(|| format!("Profile {} not found", profile_id))?;
+    let plugins = load_profile_from_db(conn, &profile.name)?;
+    Ok((profile.name, plugins))
+}
+
+fn try_main(args: &ArgMatches) -> Result<()> {
+    ytflow::plugin::automation::set_allowed_commands(
+        args.get_occurrences::<String>("allow-command")
+            .into_iter()
+            .flatten()
+            .filter_map(|mut argv| {
+                let program = PathBuf::from(argv.next()?);
+                Some((program, argv.cloned().collect()))
+            })
+            .collect(),
+    );
+    let db = args
+        .get_one::<PathBuf>("db-path")
+        .map(AsRef::<Path>::as_ref)
+        .map(Path::canonicalize)
+        .transpose()
+        .context("Failed to load database path")
+        .context(FailureKind::Config)?
+        .map(|path| {
+            info!("Connecting to database: {}", path.display());
+            ytflow::data::Database::open(&path).map_err(|e| {
+                if ytflow::data::Database::is_corrupted(&path).unwrap_or(false) {
+                    error!("Database is corrupted. Run `ytflow-edit --recover` to salvage it");
+                }
+                e
+            })
+        })
+        .transpose()
+        .context("Failed to open database")
+        .context(FailureKind::Config)?;
+
+    let conn = if let Some(db) = &db {
+        db.connect()
+            .context("Failed to connect to database")
+            .context(FailureKind::Config)?
+    } else {
+        info!("Connecting to database: in-memory");
+        ytflow::data::Database::connect_temp().expect("Could not open in-memory database")
+    };
+
+    let profile_name = args
+        .get_one::<String>("PROFILE")
+        .map(|s| s.as_str())
+        .unwrap_or("default");
+    info!("Selected Profile: {}", profile_name);
+
+    let watch_dir = args.get_one::<PathBuf>("watch");
+    let plugins = match watch_dir {
+        Some(dir) => {
+            info!("Watching profile files in: {}", dir.display());
+            load_profile_from_dir(dir, profile_name)
+        }
+        None => load_profile_from_db(&conn, profile_name),
+    }
+    .context(FailureKind::Config)?;
+    failure::record_profile(profile_name, &plugins.entry_plugins, &plugins.all_plugins);
+
+    let runtime = ytflow::tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .context("Error initializing Tokio runtime")?;
+    let runtime_enter_guard = runtime.enter();
+
+    if !args.get_flag("skip-grace") {
+        info!("Starting YtFlow in 3 seconds...");
+        std::thread::sleep(Duration::from_secs(3));
+    }
+    let reloader = ytflow::control::ProfileReloader::default();
+    let mut running = Some(start_plugins(
+        args,
+        &plugins,
+        &conn,
+        db.as_ref(),
+        &runtime,
+        &reloader,
+    )?);
+    // Listeners that did bind keep serving. With none of them, a supervisor
+    // may fix a port conflict at startup by waiting for the other process to
+    // go away. Later reloads keep going regardless.
+    if running.as_ref().is_some_and(|r| r.nothing_bound) {
+        return Err(
+            anyhow::anyhow!("No listeners can bind to their addresses").context(FailureKind::Bind)
+        );
+    }
 
     let (ctrlc_tx, ctrlc_rx) = std::sync::mpsc::channel();
     ctrlc::set_handler(move || {
@@ -217,13 +534,133 @@ fn try_main(args: &ArgMatches) -> Result<()> {
         let _ = ctrlc_tx.send(());
     })
     .expect("Error setting Ctrl-C handler");
+    #[cfg(unix)]
+    if let Err(e) = ytflow::control::handle_sighup() {
+        warn!("Failed to set SIGHUP handler: {}", e);
+    }
 
-    ctrlc_rx
-        .recv()
-        .expect("Error waiting for Ctrl-C channel signal");
+    let drain_timeout = Duration::from_secs(*args.get_one::<u64>("drain-timeout").unwrap());
+    // Connections of previous plugin sets, closed when the deadline passes.
+    let mut draining: Vec<(ytflow::flow::ConnectionTracker, Instant)> = vec![];
+
+    let mut profile_name = profile_name.to_string();
+    let mut snapshot = watch_dir.map(|dir| snapshot_toml_files(dir)).transpose()?;
+    loop {
+        match ctrlc_rx.recv_timeout(Duration::from_secs(1)) {
+            Err(RecvTimeoutError::Timeout) => {}
+            Ok(()) => break,
+            Err(RecvTimeoutError::Disconnected) => {
+                panic!("Error waiting for Ctrl-C channel signal")
+            }
+        }
+        let mut next_plugins = None;
+        if let (Some(dir), Some(snapshot)) = (watch_dir, &mut snapshot) {
+            match snapshot_toml_files(dir) {
+                Ok(new_snapshot) if new_snapshot != *snapshot => {
+                    *snapshot = new_snapshot;
+                    info!("Profile files changed, reloading...");
+                    match load_profile_from_dir(dir, &profile_name) {
+                        Ok(p) => next_plugins = Some(p),
+                        Err(e) => {
+                            error!("{:?}", e);
+                            warn!("Keeping the currently running plugins");
+                        }
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => warn!("{:?}", e),
+            }
+        }
+        // Take both, so that one request does not trigger another reload later.
+        let reload_requested = reloader.take_requested();
+        if ytflow::control::take_sighup() || reload_requested {
+            let reloaded = match watch_dir {
+                Some(dir) => {
+                    info!("Reloading profile files...");
+                    load_profile_from_dir(dir, &profile_name)
+                }
+                None => {
+                    info!(r#"Reloading Profile "{}" from database..."#, profile_name);
+                    load_profile_from_db(&conn, &profile_name)
+                }
+            };
+            match reloaded {
+                Ok(p) => next_plugins = Some(p),
+                Err(e) => {
+                    error!("{:?}", e);
+                    warn!("Keeping the currently running plugins");
+                }
+            }
+        }
+        let activation = running
+            .as_ref()
+            .and_then(|r| r.control_hub.scheduler().take_profile_activation());
+        if let Some(profile_id) = activation {
+            info!("Running scheduled activation of Profile {}", profile_id);
+            match load_activated_profile(profile_id, watch_dir.is_some(), &conn) {
+                Ok((name, plugins)) => {
+                    info!(r#"Activating Profile "{}""#, name);
+                    profile_name = name;
+                    next_plugins = Some(plugins);
+                }
+                Err(e) => warn!("{:?}", e),
+            }
+        }
+        let now = Instant::now();
+        draining.retain(|(connections, deadline)| {
+            if connections.is_empty() {
+                return false;
+            }
+            if now < *deadline {
+                return true;
+            }
+            let remaining = connections.list();
+            warn!(
+                "Closing {} connections of the previous plugins",
+                remaining.len()
+            );
+            for connection in remaining {
+                connections.close(connection.id);
+            }
+            false
+        });
+        let Some(plugins) = next_plugins else {
+            continue;
+        };
+        failure::record_profile(&profile_name, &plugins.entry_plugins, &plugins.all_plugins);
+        // Start the new plugins next to the old ones, so that flows of the old
+        // set, including those through the TUN device, keep going until the
+        // new set takes over.
+        let started = match start_plugins(args, &plugins, &conn, db.as_ref(), &runtime, &reloader) {
+            Ok(new) if new.bind_failed && running.is_some() => {
+                // Listeners of the old plugins still hold their addresses.
+                // Release them and try again.
+                drop(new);
+                if let Some(old) = running.take() {
+                    // Flows still held by the old plugins must not leak out
+                    // while they are torn down.
+                    old.control_hub.set_profile_reloading(true);
+                    retire_plugins(old, &mut draining, drain_timeout);
+                }
+                start_plugins(args, &plugins, &conn, db.as_ref(), &runtime, &reloader)
+            }
+            r => r,
+        };
+        match started {
+            Ok(new) => {
+                if let Some(old) = running.replace(new) {
+                    retire_plugins(old, &mut draining, drain_timeout);
+                }
+            }
+            Err(e) => {
+                error!("Failed to reload plugins: {:?}", e);
+                failure::record_error(format_args!("Failed to reload plugins: {:#}", e));
+            }
+        }
+    }
     info!("Shutting down all plugins");
 
-    drop(plugin_set);
+    drop(running);
     info!("Plugins destroyed, shutting down runtime...");
 
     drop(runtime_enter_guard);
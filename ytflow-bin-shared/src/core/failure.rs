@@ -0,0 +1,273 @@
+use std::fmt::{self, Display, Formatter};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+/// Why ytflow-core stopped unexpectedly. Attach one to an [`anyhow::Error`]
+/// as context to have the process exit with the matching code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureKind {
+    /// The profile cannot be found or parsed, or the database is unusable.
+    Config,
+    /// Resources required by the profile cannot be loaded.
+    Resource,
+    /// None of the listeners can bind to their addresses at startup.
+    Bind,
+    /// The main thread panicked.
+    Panic,
+    /// Any other error.
+    Other,
+}
+
+impl FailureKind {
+    /// Exit codes follow `sysexits.h` so that supervisors can tell whether a
+    /// restart may help: only [`FailureKind::Bind`] is worth retrying.
+    pub fn exit_code(self) -> u8 {
+        match self {
+            FailureKind::Config => 78,   // EX_CONFIG
+            FailureKind::Resource => 66, // EX_NOINPUT
+            FailureKind::Bind => 75,     // EX_TEMPFAIL
+            FailureKind::Panic => 70,    // EX_SOFTWARE
+            FailureKind::Other => 1,
+        }
+    }
+}
+
+impl Display for FailureKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FailureKind::Config => "Configuration error",
+            FailureKind::Resource => "Resource error",
+            FailureKind::Bind => "Failed to bind listeners",
+            FailureKind::Panic => "Runtime panic",
+            FailureKind::Other => "Unexpected error",
+        })
+    }
+}
+
+impl From<FailureKind> for ExitCode {
+    fn from(kind: FailureKind) -> Self {
+        ExitCode::from(kind.exit_code())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct PluginSummary {
+    name: String,
+    plugin: String,
+    plugin_version: u16,
+    is_entry: bool,
+}
+
+/// What ytflow-core has been doing, collected along the way for a failure
+/// snapshot.
+struct FailureContext {
+    profile: Option<String>,
+    plugins: Vec<PluginSummary>,
+    errors: Vec<String>,
+}
+
+static CONTEXT: Mutex<FailureContext> = Mutex::new(FailureContext {
+    profile: None,
+    plugins: Vec::new(),
+    errors: Vec::new(),
+});
+
+/// Upper bound of errors kept for a snapshot, in case a long-running process
+/// keeps failing to reload.
+const MAX_RECORDED_ERRORS: usize = 256;
+
+fn with_context<R>(f: impl FnOnce(&mut FailureContext) -> R) -> R {
+    let mut ctx = CONTEXT.lock().unwrap_or_else(|e| e.into_inner());
+    f(&mut ctx)
+}
+
+/// Remember the profile about to be started.
+pub fn record_profile(
+    name: &str,
+    entry_plugins: &[ytflow::config::Plugin],
+    all_plugins: &[ytflow::config::Plugin],
+) {
+    let plugins = all_plugins
+        .iter()
+        .map(|p| PluginSummary {
+            name: p.name.clone(),
+            plugin: p.plugin.clone(),
+            plugin_version: p.plugin_version,
+            is_entry: entry_plugins.iter().any(|e| e.name == p.name),
+        })
+        .collect();
+    with_context(|ctx| {
+        ctx.profile = Some(name.to_string());
+        ctx.plugins = plugins;
+    });
+}
+
+/// Remember a non-fatal error, such as a plugin that failed to load.
+pub fn record_error(error: impl Display) {
+    let error = error.to_string();
+    with_context(|ctx| {
+        if ctx.errors.len() < MAX_RECORDED_ERRORS {
+            ctx.errors.push(error);
+        }
+    });
+}
+
+#[derive(Serialize)]
+struct Versions {
+    ytflow_core: &'static str,
+    os: &'static str,
+    arch: &'static str,
+    tun: bool,
+    netif: bool,
+}
+
+#[derive(Serialize)]
+struct FailureSnapshot<'a> {
+    kind: FailureKind,
+    exit_code: u8,
+    time: String,
+    /// The fatal error and its causes, outermost first.
+    fatal: Vec<String>,
+    /// Errors recorded before the failure.
+    errors: &'a [String],
+    profile: Option<&'a str>,
+    plugins: &'a [PluginSummary],
+    versions: Versions,
+}
+
+/// A new file in the temporary directory to write a failure snapshot to,
+/// unless specified otherwise. The name cannot be guessed in advance, so that
+/// other users cannot plant a file or symlink there.
+fn default_snapshot_path() -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "ytflow-core-failure-{}-{}.json",
+        chrono::Local::now().format("%Y%m%d-%H%M%S"),
+        nanoid::nanoid!(8),
+    ))
+}
+
+/// Write a JSON snapshot of the failure and everything recorded so far.
+/// An explicit `path` is replaced if it exists. Otherwise, a new file only
+/// readable by the current user is created in the temporary directory.
+/// Returns where the snapshot has been written.
+pub fn write_snapshot(
+    path: Option<&Path>,
+    kind: FailureKind,
+    fatal: Vec<String>,
+) -> std::io::Result<PathBuf> {
+    let json = with_context(|ctx| {
+        let snapshot = FailureSnapshot {
+            kind,
+            exit_code: kind.exit_code(),
+            time: chrono::Local::now().to_rfc3339(),
+            fatal,
+            errors: &ctx.errors,
+            profile: ctx.profile.as_deref(),
+            plugins: &ctx.plugins,
+            versions: Versions {
+                ytflow_core: env!("CARGO_PKG_VERSION"),
+                os: std::env::consts::OS,
+                arch: std::env::consts::ARCH,
+                tun: cfg!(feature = "tun"),
+                netif: cfg!(feature = "netif"),
+            },
+        };
+        serde_json::to_vec_pretty(&snapshot)
+    })?;
+    if let Some(path) = path {
+        std::fs::write(path, json)?;
+        return Ok(path.to_owned());
+    }
+    let path = default_snapshot_path();
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(&path)?.write_all(&json)?;
+    Ok(path)
+}
+
+/// Classify a fatal error by the [`FailureKind`] attached as context.
+pub fn classify(error: &anyhow::Error) -> FailureKind {
+    error
+        .downcast_ref::<FailureKind>()
+        .copied()
+        .unwrap_or(FailureKind::Other)
+}
+
+/// Extract the message of a panic payload.
+pub fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic payload".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let error = anyhow::anyhow!("address in use")
+            .context(FailureKind::Bind)
+            .context("Starting plugins");
+        assert_eq!(classify(&error), FailureKind::Bind);
+        let error = anyhow::anyhow!("file not found").context(FailureKind::Resource);
+        assert_eq!(classify(&error), FailureKind::Resource);
+        assert_eq!(classify(&anyhow::anyhow!("oops")), FailureKind::Other);
+    }
+
+    #[test]
+    fn test_exit_code() {
+        let kinds = [
+            FailureKind::Config,
+            FailureKind::Resource,
+            FailureKind::Bind,
+            FailureKind::Panic,
+            FailureKind::Other,
+        ];
+        let codes: Vec<_> = kinds.iter().map(|k| k.exit_code()).collect();
+        assert_eq!(codes, [78, 66, 75, 70, 1]);
+        // Distinct, and never mistaken for success.
+        assert!(codes.iter().all(|&c| c != 0));
+        assert_eq!(
+            codes
+                .iter()
+                .collect::<std::collections::BTreeSet<_>>()
+                .len(),
+            kinds.len()
+        );
+    }
+
+    #[test]
+    fn test_default_snapshot_paths_are_unique() {
+        let path = default_snapshot_path();
+        assert!(path.starts_with(std::env::temp_dir()));
+        assert_ne!(path, default_snapshot_path());
+    }
+
+    #[test]
+    fn test_write_snapshot() {
+        record_error("plugin failed");
+        let path = write_snapshot(None, FailureKind::Config, vec!["bad profile".into()]).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(json["kind"], "config");
+        assert_eq!(json["exit_code"], 78);
+        assert_eq!(json["fatal"][0], "bad profile");
+        assert!(json["errors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|e| e == "plugin failed"));
+    }
+}
@@ -11,30 +11,104 @@ use crossterm::{
 use tui::{backend::CrosstermBackend, Terminal};
 
 mod gen;
+mod resource_update;
 mod views;
-use ytflow::data::{Connection, Database};
+use ytflow::data::{maintenance, Connection, Database};
 
 pub fn main() -> Result<()> {
     let args = get_args();
+    if let Some(("schema", _)) = args.subcommand() {
+        return print_schema();
+    }
     let conn = get_db_conn(&args)?;
-    run_tui(conn)?;
+    if args.get_flag("maintain") {
+        return run_maintenance(&conn);
+    }
+    let resource_root = args
+        .get_one::<PathBuf>("resource-root")
+        .cloned()
+        .unwrap_or_else(|| {
+            std::env::current_dir().expect("Cannot get working directory for resources")
+        });
+    run_tui(conn, resource_root)?;
     Ok(())
 }
 
 fn get_args() -> ArgMatches {
     clap::command!()
         .arg(arg!(<PATH> "Path to the database file").value_parser(value_parser!(PathBuf)))
+        .arg(
+            arg!(--"resource-root" <PATH> "Path to the root of the resource directory. Defaults to the current working directory")
+                .value_parser(value_parser!(PathBuf))
+                .required(false)
+        )
+        .arg(arg!(--maintain "Check database integrity, checkpoint WAL and vacuum the database, then exit"))
+        .arg(arg!(--recover "Move the database file aside and salvage readable rows into a fresh one before opening it"))
+        .subcommand(clap::Command::new("schema").about("Print descriptors of all built-in plugins as JSON, then exit"))
+        .subcommand_negates_reqs(true)
         .get_matches()
 }
 
 fn get_db_conn(args: &ArgMatches) -> Result<Connection> {
     let db_path: &PathBuf = args.get_one("PATH").expect("Cannot get database path");
-    let db = Database::open(db_path).context("Could not prepare database")?;
-    let conn = db.connect().context("Could not connect to database")?;
+    if args.get_flag("recover") {
+        let report = Database::recover(db_path).context("Could not recover database")?;
+        eprintln!(
+            "Database has been recovered. The original file is moved to {}",
+            report.backup_path.display()
+        );
+        for table in &report.tables {
+            eprintln!(
+                "  {}: {} rows salvaged{}",
+                table.name,
+                table.rows,
+                if table.complete { "" } else { " (incomplete)" }
+            );
+        }
+    }
+    let db = Database::open(db_path).map_err(|e| {
+        if Database::is_corrupted(db_path).unwrap_or(false) {
+            eprintln!("Database is corrupted. Run with --recover to salvage it");
+        }
+        e
+    });
+    let conn = db
+        .context("Could not prepare database")?
+        .connect()
+        .context("Could not connect to database")?;
     Ok(conn)
 }
 
-fn run_tui(conn: Connection) -> Result<()> {
+fn print_schema() -> Result<()> {
+    let schemas = ytflow::config::schema::builtin_plugin_schemas();
+    let json = serde_json::to_string_pretty(schemas).context("Could not serialize schema")?;
+    println!("{}", json);
+    Ok(())
+}
+
+fn run_maintenance(conn: &Connection) -> Result<()> {
+    let problems = maintenance::integrity_check(conn).context("Could not check integrity")?;
+    if problems.is_empty() {
+        println!("Integrity check: ok");
+    } else {
+        println!("Integrity check found {} problem(s):", problems.len());
+        for problem in &problems {
+            println!("  {}", problem);
+        }
+    }
+    let checkpoint = maintenance::wal_checkpoint(conn).context("Could not checkpoint WAL")?;
+    println!(
+        "WAL checkpoint: {}/{} frames{}",
+        checkpoint.checkpointed_frames,
+        checkpoint.log_frames,
+        if checkpoint.busy { " (busy)" } else { "" }
+    );
+    maintenance::vacuum(conn).context("Could not vacuum database")?;
+    println!("Vacuum: done");
+    Ok(())
+}
+
+fn run_tui(conn: Connection, resource_root: PathBuf) -> Result<()> {
     let mut stdout = io::stdout();
     execute!(stdout, EnterAlternateScreen, EnableMouseCapture).unwrap();
     let backend = CrosstermBackend::new(stdout);
@@ -45,6 +119,7 @@ fn run_tui(conn: Connection) -> Result<()> {
     let mut ctx = AppContext {
         term: terminal,
         conn,
+        resource_root,
     };
     let res = run_main_loop(&mut ctx);
     let mut terminal = ctx.term;
@@ -62,6 +137,7 @@ fn run_tui(conn: Connection) -> Result<()> {
 pub struct AppContext {
     term: Terminal<CrosstermBackend<io::Stdout>>,
     conn: Connection,
+    resource_root: PathBuf,
 }
 
 fn run_main_loop(ctx: &mut AppContext) -> Result<()> {
@@ -79,6 +155,8 @@ fn run_main_loop(ctx: &mut AppContext) -> Result<()> {
             Some(NavChoice::NewProxyGroupView) => views::run_new_proxy_group_view(ctx)?,
             Some(NavChoice::ProxyGroupView(id)) => views::run_proxy_group_view(ctx, *id)?,
             Some(NavChoice::ProxyTypeView(group_id)) => views::run_proxy_type_view(ctx, *group_id)?,
+            Some(NavChoice::NewResourceView) => views::run_new_resource_view(ctx)?,
+            Some(NavChoice::ResourceView(id)) => views::run_resource_view(ctx, *id)?,
             Some(NavChoice::InputView(req)) => views::run_input_view(ctx, req)?,
             Some(NavChoice::Back) => {
                 nav_choices.pop(); // Pop "Back" out
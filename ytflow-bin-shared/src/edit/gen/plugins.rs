@@ -89,6 +89,16 @@ pub enum PluginType {
         detailed_message = "Match the connection against a list of matchers defined in a resource, and use the handler of the action or fallback handler if there is no match."
     )]
     ListDispatcher,
+    #[strum(
+        props(prefix = "alpn-dispatcher"),
+        detailed_message = "Dispatch TLS connections to different handlers by the ALPN protocols offered in the ClientHello, before TLS is terminated. Each handler needs a tls-server of its own."
+    )]
+    AlpnDispatcher,
+    #[strum(
+        props(prefix = "mixed-listener"),
+        detailed_message = "Detect SOCKS5, HTTP proxy or TLS from the first bytes of a connection, and dispatch to the corresponding handler."
+    )]
+    MixedListener,
     #[strum(
         props(prefix = "forward"),
         detailed_message = "Establish a new connection for each incoming connection, and forward data between them."
@@ -111,6 +121,11 @@ pub enum PluginType {
         detailed_message = "HTTP Proxy client. Use HTTP CONNECT to connect to the proxy server."
     )]
     HttpProxyClient,
+    #[strum(
+        props(prefix = "masque-client"),
+        detailed_message = "MASQUE client. Use CONNECT-UDP for UDP over HTTP/3 when quic_next is set, or over HTTP/2 or HTTP/1.1 otherwise, and HTTP CONNECT for TCP."
+    )]
+    MasqueClient,
     #[strum(props(prefix = "tls-client"), detailed_message = "TLS client stream.")]
     TlsClient,
     #[strum(
@@ -132,6 +147,11 @@ pub enum PluginType {
     TlsObfsClient,
     #[strum(props(prefix = "ws-client"), detailed_message = "WebSocket client.")]
     WsClient,
+    #[strum(
+        props(prefix = "scramble"),
+        detailed_message = "Scramble UDP payloads with XOR or AES-CTR using a shared key."
+    )]
+    Scramble,
     #[strum(
         props(prefix = "redirect"),
         detailed_message = "Change the destination of connections or datagrams."
@@ -147,6 +167,56 @@ pub enum PluginType {
         detailed_message = "A dynamic network interface."
     )]
     Netif,
+    #[strum(
+        props(prefix = "circuit-breaker"),
+        detailed_message = "Stop dialing an upstream for a cool-down period after consecutive connection failures."
+    )]
+    CircuitBreaker,
+    #[strum(
+        props(prefix = "guard"),
+        detailed_message = "Reject outgoing connections to destinations or TLS SNIs not on an allowlist."
+    )]
+    Guard,
+    #[strum(
+        props(prefix = "bonding"),
+        detailed_message = "Send datagrams over multiple UDP outbounds, duplicated or in turn, and drop duplicated replies. Datagrams carry a sequence number, so the remote end must use the same framing."
+    )]
+    Bonding,
+    #[strum(
+        props(prefix = "delay"),
+        detailed_message = "Add latency, packet loss or bandwidth caps. Can be adjusted at runtime for testing."
+    )]
+    Delay,
+    #[strum(
+        props(prefix = "ping-prober"),
+        detailed_message = "Probe targets with ICMP echo or TCP connect. Fail fast when all targets are unreachable."
+    )]
+    PingProber,
+    #[strum(
+        props(prefix = "url-test"),
+        detailed_message = "Probe a group of outbounds with a test URL periodically, and connect through the fastest or the first healthy one."
+    )]
+    UrlTest,
+    #[strum(
+        props(prefix = "stun-keepalive"),
+        detailed_message = "Keep NAT mappings of UDP sessions alive with periodic STUN Binding Requests, and detect external address changes."
+    )]
+    StunKeepalive,
+    #[strum(
+        props(prefix = "memory-watchdog"),
+        detailed_message = "Shrink caches of other plugins when memory usage approaches a threshold or the platform limit."
+    )]
+    MemoryWatchdog,
+    #[strum(
+        props(prefix = "automation"),
+        detailed_message = "Send webhook requests or run allowed local commands when the profile connects, an outbound becomes unhealthy, a subscription is updated or a traffic quota runs out."
+    )]
+    Automation,
+    #[strum(
+        props(prefix = "health-endpoint"),
+        detailed_message = "Answer liveness and readiness probes over HTTP or RESP with the health of designated probers."
+    )]
+    HealthEndpoint,
 }
 
 impl PluginType {
@@ -171,6 +241,7 @@ impl PluginType {
                 PluginType::VpnTun => cbor!({
                     "ipv4" => "192.168.3.1",
                     "ipv6" => Null,
+                    "ipv6_prefixes" => Vec::<()>::new(),
                     "ipv4_route" => ["11.17.0.0/16", "11.16.0.0/24"],
                     "ipv6_route" => Vec::<()>::new(),
                     "dns" => ["11.16.1.1"],
@@ -274,6 +345,19 @@ impl PluginType {
                         "resolver" => "fakeip.resolver",
                     },
                 }),
+                PluginType::AlpnDispatcher => cbor!({
+                    "alpn" => {
+                        "h2" => name.clone() + "-h2-tls-server.tcp",
+                        "http/1.1" => name.clone() + "-ws-tls-server.tcp",
+                    },
+                    "fallback" => name.clone() + "-trojan-tls-server.tcp",
+                }),
+                PluginType::MixedListener => cbor!({
+                    "socks5_next" => name.clone() + "-socks5-server.tcp",
+                    "http_next" => name.clone() + "-http-proxy-server.tcp",
+                    "tls_next" => name.clone() + "-tls-server.tcp",
+                    "fallback" => name.clone() + "-reject.tcp",
+                }),
                 PluginType::Forward => cbor!({
                     "tcp_next" => name.clone() + "-shadowsocks-client.tcp",
                     "udp_next" => name.clone() + "-socket.udp",
@@ -299,6 +383,13 @@ impl PluginType {
                     "user" => Bytes::new(b""),
                     "pass" => Bytes::new(b""),
                 }),
+                PluginType::MasqueClient => cbor!({
+                    "host" => "proxy.example.com",
+                    "udp_path" => "/.well-known/masque/udp/{target_host}/{target_port}/",
+                    "user" => Bytes::new(b""),
+                    "pass" => Bytes::new(b""),
+                    "next" => name.clone() + "-tls.tcp",
+                }),
                 PluginType::TlsClient => cbor!({
                     "alpn" => ["http/1.1"],
                     "sni" => "remove.for.auto.sni.detection.com",
@@ -329,6 +420,11 @@ impl PluginType {
                     "headers" => {},
                     "next" => name.clone() + "-tls.tcp",
                 }),
+                PluginType::Scramble => cbor!({
+                    "method" => "aes-ctr",
+                    "key" => Bytes::new(b"key"),
+                    "next" => name.clone() + "-redirect.udp",
+                }),
                 PluginType::Redirect => cbor!({
                     "dest" => DestinationAddr {
                         host: HostName::DomainName("my.proxy.server.com.".into()),
@@ -344,6 +440,73 @@ impl PluginType {
                     family_preference: FamilyPreference::Ipv4Only,
                     selection: SelectionMode::Manual("eth0".into()),
                     outbound_resolver: None,
+                    probers: vec![],
+                }),
+                PluginType::CircuitBreaker => cbor!({
+                    "failure_threshold" => 5u8,
+                    "cooldown" => 30000u16,
+                    "tcp_next" => name.clone() + "-socket.tcp",
+                    "udp_next" => name.clone() + "-socket.udp",
+                }),
+                PluginType::Guard => cbor!({
+                    "allow" => ["example.com", "*.example.com"],
+                    "tcp_next" => name.clone() + "-socket.tcp",
+                    "udp_next" => name.clone() + "-socket.udp",
+                }),
+                PluginType::Bonding => cbor!({
+                    "mode" => "duplicate",
+                    "nexts" => [name.clone() + "-socket-wifi.udp", name.clone() + "-socket-cellular.udp"],
+                }),
+                PluginType::Delay => cbor!({
+                    "latency" => 0u8,
+                    "tcp_next" => name.clone() + "-socket.tcp",
+                    "udp_next" => name.clone() + "-socket.udp",
+                }),
+                PluginType::PingProber => cbor!({
+                    "targets" => ["1.1.1.1:443", "8.8.8.8:443"],
+                    "method" => "auto",
+                    "interval" => 30000u16,
+                    "timeout" => 3000u16,
+                    "tcp_next" => name.clone() + "-socket.tcp",
+                    "udp_next" => name.clone() + "-socket.udp",
+                }),
+                PluginType::UrlTest => cbor!({
+                    "candidates" => [{
+                        "name" => "Primary",
+                        "tcp_next" => name.clone() + "-primary.tcp",
+                        "udp_next" => name.clone() + "-primary.udp",
+                    }, {
+                        "name" => "Backup",
+                        "tcp_next" => name.clone() + "-backup.tcp",
+                        "udp_next" => name.clone() + "-backup.udp",
+                    }],
+                    "url" => "https://www.gstatic.com/generate_204",
+                    "mode" => "fastest",
+                    "interval" => 300000u32,
+                }),
+                PluginType::StunKeepalive => cbor!({
+                    "server" => DestinationAddr {
+                        host: HostName::DomainName("stun.l.google.com.".into()),
+                        port: 19302,
+                    },
+                    "interval" => 25000u16,
+                    "next" => name.clone() + "-socket.udp",
+                }),
+                PluginType::MemoryWatchdog => cbor!({
+                    "limit_percent" => 80u8,
+                    "interval" => 5000u16,
+                }),
+                PluginType::Automation => cbor!({
+                    "rules" => [{
+                        "on" => ["outbound_unhealthy"],
+                        "webhook" => {
+                            "url" => "https://ntfy.sh/ytflow-example",
+                        },
+                    }],
+                    "tcp_next" => name.clone() + "-socket.tcp",
+                }),
+                PluginType::HealthEndpoint => cbor!({
+                    "probers" => [name.clone() + "-ping-prober"],
                 }),
             }
             .unwrap(),
@@ -351,8 +514,8 @@ impl PluginType {
         Plugin {
             id: None,
             name,
+            plugin_version: ytflow::config::migration::current_plugin_version(&plugin),
             plugin,
-            plugin_version: 0,
             param: param.into_vec(),
         }
     }
@@ -0,0 +1,230 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+
+use ytflow::data::{
+    Connection, Resource, ResourceGitHubRelease, ResourceMaxmindPermalink, ResourceUrl,
+};
+use ytflow::resource::fetch::{FetchResponse, ResourceFetcher};
+use ytflow::resource::maxmind;
+
+pub enum UpdateOutcome {
+    Updated,
+    UpToDate,
+}
+
+#[derive(Deserialize)]
+struct GitHubRelease {
+    tag_name: String,
+    name: Option<String>,
+    assets: Vec<GitHubReleaseAsset>,
+}
+
+#[derive(Deserialize)]
+struct GitHubReleaseAsset {
+    name: String,
+    browser_download_url: String,
+}
+
+pub fn local_file_path(resource_root: &Path, local_file: &str) -> Result<PathBuf> {
+    let local_file = Path::new(local_file);
+    if !local_file
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)))
+    {
+        bail!("Local file must be a relative path inside the resource root")
+    }
+    Ok(resource_root.join(local_file))
+}
+
+fn save_file(path: &Path, content: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).context("Failed to create resource directory")?;
+    }
+    let tmp_path = path.with_extension("download");
+    fs::write(&tmp_path, content).context("Failed to write resource file")?;
+    fs::rename(&tmp_path, path).context("Failed to replace resource file")?;
+    Ok(())
+}
+
+/// Download the latest content of `resource` into the resource root, and record the retrieval
+/// in the database.
+pub fn update_resource(
+    resource: &Resource,
+    resource_root: &Path,
+    conn: &Connection,
+) -> Result<UpdateOutcome> {
+    let path = local_file_path(resource_root, &resource.local_file)?;
+    let runtime = ytflow::tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("Failed to create runtime")?;
+    runtime.block_on(async {
+        let fetcher = ResourceFetcher::new();
+        match &*resource.remote_type {
+            "url" => update_url_resource(&fetcher, resource, &path, conn).await,
+            "github_release" => {
+                update_github_release_resource(&fetcher, resource, &path, conn).await
+            }
+            "maxmind_permalink" => {
+                update_maxmind_permalink_resource(&fetcher, resource, &path, conn).await
+            }
+            t => bail!("Unknown remote type {}", t),
+        }
+    })
+}
+
+async fn update_url_resource(
+    fetcher: &ResourceFetcher,
+    resource: &Resource,
+    path: &Path,
+    conn: &Connection,
+) -> Result<UpdateOutcome> {
+    let url = ResourceUrl::query_by_resource_id(resource.id.0, conn)
+        .context("Failed to query URL of the resource")?
+        .ok_or_else(|| anyhow!("URL of the resource is missing"))?;
+    // Do not bother validating cache if the local file is gone.
+    let (etag, last_modified) = if path.exists() {
+        (url.etag.as_deref(), url.last_modified.as_deref())
+    } else {
+        (None, None)
+    };
+    let res = fetcher
+        .get(&url.url, etag, last_modified)
+        .await
+        .context("Failed to download resource")?;
+    match res {
+        FetchResponse::NotModified => {
+            ResourceUrl::update_retrieved_by_resource_id(
+                resource.id.0,
+                url.etag,
+                url.last_modified,
+                conn,
+            )
+            .context("Failed to update resource")?;
+            Ok(UpdateOutcome::UpToDate)
+        }
+        FetchResponse::Ok {
+            etag,
+            last_modified,
+            body,
+        } => {
+            save_file(path, &body)?;
+            ResourceUrl::update_retrieved_by_resource_id(resource.id.0, etag, last_modified, conn)
+                .context("Failed to update resource")?;
+            Ok(UpdateOutcome::Updated)
+        }
+    }
+}
+
+async fn update_github_release_resource(
+    fetcher: &ResourceFetcher,
+    resource: &Resource,
+    path: &Path,
+    conn: &Connection,
+) -> Result<UpdateOutcome> {
+    let github_release = ResourceGitHubRelease::query_by_resource_id(resource.id.0, conn)
+        .context("Failed to query GitHub release of the resource")?
+        .ok_or_else(|| anyhow!("GitHub release of the resource is missing"))?;
+    let api_url = format!(
+        "https://api.github.com/repos/{}/{}/releases/latest",
+        github_release.github_username, github_release.github_repo
+    );
+    let FetchResponse::Ok { body, .. } = fetcher
+        .get(&api_url, None, None)
+        .await
+        .context("Failed to query the latest release")?
+    else {
+        bail!("Unexpected response from GitHub")
+    };
+    let release: GitHubRelease =
+        serde_json::from_slice(&body).context("Failed to parse the latest release")?;
+    let release_title = release.name.unwrap_or_else(|| release.tag_name.clone());
+    if github_release.git_tag.as_ref() == Some(&release.tag_name) && path.exists() {
+        ResourceGitHubRelease::update_retrieved_by_resource_id(
+            resource.id.0,
+            release.tag_name,
+            release_title,
+            conn,
+        )
+        .context("Failed to update resource")?;
+        return Ok(UpdateOutcome::UpToDate);
+    }
+    let asset = release
+        .assets
+        .iter()
+        .find(|a| a.name == github_release.asset_name)
+        .ok_or_else(|| {
+            anyhow!(
+                "Asset {} is not found in release {}",
+                github_release.asset_name,
+                release.tag_name
+            )
+        })?;
+    let FetchResponse::Ok { body, .. } = fetcher
+        .get(&asset.browser_download_url, None, None)
+        .await
+        .context("Failed to download release asset")?
+    else {
+        bail!("Unexpected response when downloading release asset")
+    };
+    save_file(path, &body)?;
+    ResourceGitHubRelease::update_retrieved_by_resource_id(
+        resource.id.0,
+        release.tag_name,
+        release_title,
+        conn,
+    )
+    .context("Failed to update resource")?;
+    Ok(UpdateOutcome::Updated)
+}
+
+async fn update_maxmind_permalink_resource(
+    fetcher: &ResourceFetcher,
+    resource: &Resource,
+    path: &Path,
+    conn: &Connection,
+) -> Result<UpdateOutcome> {
+    let permalink = ResourceMaxmindPermalink::query_by_resource_id(resource.id.0, conn)
+        .context("Failed to query MaxMind permalink of the resource")?
+        .ok_or_else(|| anyhow!("MaxMind permalink of the resource is missing"))?;
+    let last_modified = permalink.last_modified.as_deref().filter(|_| path.exists());
+    let res = fetcher
+        .get_with_basic_auth(
+            &maxmind::permalink_url(&permalink.edition_id),
+            None,
+            last_modified,
+            Some((&permalink.account_id, &permalink.license_key)),
+        )
+        .await
+        .context("Failed to download MaxMind database")?;
+    match res {
+        FetchResponse::NotModified => {
+            ResourceMaxmindPermalink::update_retrieved_by_resource_id(
+                resource.id.0,
+                permalink.last_modified,
+                conn,
+            )
+            .context("Failed to update resource")?;
+            Ok(UpdateOutcome::UpToDate)
+        }
+        FetchResponse::Ok {
+            last_modified,
+            body,
+            ..
+        } => {
+            let mmdb = maxmind::extract_mmdb(&body)
+                .context("Failed to extract the database from the downloaded archive")?;
+            save_file(path, &mmdb)?;
+            ResourceMaxmindPermalink::update_retrieved_by_resource_id(
+                resource.id.0,
+                last_modified,
+                conn,
+            )
+            .context("Failed to update resource")?;
+            Ok(UpdateOutcome::Updated)
+        }
+    }
+}
@@ -5,21 +5,25 @@ mod input;
 mod main;
 mod new_profile;
 mod new_proxy_group;
+mod new_resource;
 mod plugin_type;
 mod profile;
 mod proxy_group;
 mod proxy_type;
+mod resource;
 mod utils;
 
 pub use input::run_input_view;
 pub use main::run_main_view;
 pub use new_profile::run_new_profile_view;
 pub use new_proxy_group::run_new_proxy_group_view;
+pub use new_resource::run_new_resource_view;
 pub use plugin_type::run_plugin_type_view;
 pub use profile::run_profile_view;
 pub use proxy_group::run_proxy_group_view;
 pub use proxy_type::run_proxy_type_view;
-use ytflow::data::{Plugin, ProfileId, ProxyGroupId};
+pub use resource::run_resource_view;
+use ytflow::data::{Plugin, ProfileId, ProxyGroupId, ResourceId};
 
 const BG: Color = Color::Black;
 const FG: Color = Color::White;
@@ -49,6 +53,8 @@ pub enum NavChoice {
     NewProxyGroupView,
     ProxyGroupView(ProxyGroupId),
     ProxyTypeView(ProxyGroupId),
+    NewResourceView,
+    ResourceView(ResourceId),
     InputView(InputRequest),
     Back,
 }
@@ -3,6 +3,7 @@ use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind};
 use tui::{
     layout::{Constraint, Direction, Layout},
     style::{Color, Style},
+    text::{Span, Spans, Text},
     widgets::{Block, Borders, Paragraph},
 };
 
@@ -16,6 +17,8 @@ pub fn run_input_view(ctx: &mut edit::AppContext, req: &mut InputRequest) -> Res
     let mut input = tui_input::Input::default().with_value(req.initial_value.clone());
     let desc = req.desc.clone() + "\r\n\r\nPress Enter to submit, Esc to go back.";
     let mut has_error = false;
+    // Why the last submission was rejected, shown above the description.
+    let mut action_error: Option<String> = None;
     loop {
         let size = ctx.term.size()?;
         let vchunks = Layout::default()
@@ -51,8 +54,16 @@ pub fn run_input_view(ctx: &mut edit::AppContext, req: &mut InputRequest) -> Res
                 // Move one line down, from the border to the input line
                 edit_chunk.y + 1,
             );
-            let desc = Paragraph::new(&*desc);
-            f.render_widget(desc, desc_chunk);
+            let mut lines = vec![];
+            if let Some(e) = &action_error {
+                lines.push(Spans::from(Span::styled(
+                    e.as_str(),
+                    Style::default().fg(Color::Red),
+                )));
+                lines.push(Spans::default());
+            }
+            lines.extend(Text::raw(&*desc).lines);
+            f.render_widget(Paragraph::new(lines), desc_chunk);
         })?;
 
         let ev = crossterm::event::read().unwrap();
@@ -66,7 +77,10 @@ pub fn run_input_view(ctx: &mut edit::AppContext, req: &mut InputRequest) -> Res
                 code: KeyCode::Enter,
                 kind: KeyEventKind::Press,
                 ..
-            }) if !has_error => break,
+            }) if !has_error => match (req.action)(ctx, input.value().to_string()) {
+                Ok(()) => return Ok(NavChoice::Back),
+                Err(e) => action_error = Some(format!("{:#}", e)),
+            },
             Event::Key(KeyEvent {
                 code: KeyCode::Enter,
                 kind: KeyEventKind::Press,
@@ -82,7 +96,4 @@ pub fn run_input_view(ctx: &mut edit::AppContext, req: &mut InputRequest) -> Res
             }
         }
     }
-
-    (req.action)(ctx, input.value().to_string())?;
-    Ok(NavChoice::Back)
 }
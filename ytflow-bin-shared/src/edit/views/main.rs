@@ -10,16 +10,18 @@ use tui::{
 
 use super::{bg_rev, InputRequest, NavChoice, BG};
 use crate::edit;
-use ytflow::data::{Profile, ProxyGroup};
+use ytflow::data::{Profile, ProxyGroup, Resource};
 
-const CATEGORY_ITEM_COUNT: usize = 3;
+const CATEGORY_ITEM_COUNT: usize = 4;
 const CATEGORY_ITEM_INDEX_PROFILE: usize = 0;
 const CATEGORY_ITEM_INDEX_PROXY_GROUP: usize = 1;
-const CATEGORY_ITEM_INDEX_ABOUT: usize = 2;
+const CATEGORY_ITEM_INDEX_RESOURCE: usize = 2;
+const CATEGORY_ITEM_INDEX_ABOUT: usize = 3;
 
 enum DeleteAction {
     Profile,
     ProxyGroup,
+    Resource,
 }
 
 struct MainViewStates {
@@ -27,6 +29,7 @@ struct MainViewStates {
     category_state: ListState,
     profile_state: ListState,
     proxy_group_state: ListState,
+    resource_state: ListState,
 }
 
 impl Default for MainViewStates {
@@ -38,6 +41,7 @@ impl Default for MainViewStates {
             category_state,
             profile_state: ListState::default(),
             proxy_group_state: ListState::default(),
+            resource_state: ListState::default(),
         }
     }
 }
@@ -60,11 +64,13 @@ fn run_main_view_with_states(
     let mut profiles = Profile::query_all(&ctx.conn).context("Could not query all profiles")?;
     let mut proxy_groups =
         ProxyGroup::query_all(&ctx.conn).context("Could not query all proxy groups")?;
+    let mut resources = Resource::query_all(&ctx.conn).context("Could not query all resources")?;
     let MainViewStates {
         focus_left,
         category_state,
         profile_state,
         proxy_group_state,
+        resource_state,
     } = states;
     if profile_state.selected().is_none() && !profiles.is_empty() {
         profile_state.select(Some(0));
@@ -72,6 +78,9 @@ fn run_main_view_with_states(
     if proxy_group_state.selected().is_none() && !proxy_groups.is_empty() {
         proxy_group_state.select(Some(0));
     }
+    if resource_state.selected().is_none() && !resources.is_empty() {
+        resource_state.select(Some(0));
+    }
     let mut delete_action: Option<DeleteAction> = None;
 
     'main_loop: loop {
@@ -91,6 +100,7 @@ fn run_main_view_with_states(
         let category_list = List::new([
             ListItem::new("Profiles"),
             ListItem::new("Proxy Groups"),
+            ListItem::new("Resources"),
             ListItem::new("About"),
         ])
         .block(
@@ -106,7 +116,13 @@ fn run_main_view_with_states(
                     let items = List::new(
                         profiles
                             .iter()
-                            .map(|p| ListItem::new(p.name.clone()))
+                            .map(|p| {
+                                ListItem::new(if p.locked {
+                                    format!("{} [locked]", p.name)
+                                } else {
+                                    p.name.clone()
+                                })
+                            })
                             .collect::<Vec<_>>(),
                     )
                     .block(Block::default().title("Profiles").borders(Borders::ALL))
@@ -116,7 +132,7 @@ fn run_main_view_with_states(
                         Paragraph::new(if delete_action.is_some() {
                             "y: delete Profile; <any key>: cancel"
                         } else {
-                            "c: Create Profile; d: Delete Profile; F2: Rename Profile; q: Quit"
+                            "c: Create Profile; d: Delete Profile; F2: Rename Profile; l: Lock/Unlock Profile; p: Duplicate Profile; q: Quit"
                         }),
                         status_bar_chunk,
                     );
@@ -140,6 +156,30 @@ fn run_main_view_with_states(
                         status_bar_chunk,
                     );
                 }
+                CATEGORY_ITEM_INDEX_RESOURCE => {
+                    let items = List::new(
+                        resources
+                            .iter()
+                            .map(|r| {
+                                ListItem::new(format!(
+                                    "{}  [{}] {} ({})",
+                                    r.key, r.r#type, r.local_file, r.remote_type
+                                ))
+                            })
+                            .collect::<Vec<_>>(),
+                    )
+                    .block(Block::default().title("Resources").borders(Borders::ALL))
+                    .highlight_style(Style::default().bg(bg_rev(!*focus_left)).fg(BG));
+                    f.render_stateful_widget(items, right_chunk, resource_state);
+                    f.render_widget(
+                        Paragraph::new(if delete_action.is_some() {
+                            "y: delete Resource; <any key>: cancel"
+                        } else {
+                            "c: Create Resource; d: Delete Resource; Enter: Details and Download; q: Quit"
+                        }),
+                        status_bar_chunk,
+                    );
+                }
                 CATEGORY_ITEM_INDEX_ABOUT => {
                     let content = Paragraph::new(
                         r"
@@ -181,6 +221,15 @@ https://github.com/YtFlow/YtFlowCore",
                                 proxy_group_state.select(proxy_groups.len().checked_sub(1));
                             }
                         }
+                        DeleteAction::Resource => {
+                            let idx = resource_state.selected().unwrap();
+                            let resource_id = resources.remove(idx).id;
+                            Resource::delete(resource_id.0, &ctx.conn)
+                                .context("Failed to delete resource")?;
+                            if resources.len() == idx {
+                                resource_state.select(resources.len().checked_sub(1));
+                            }
+                        }
                     }
                 }
                 continue 'main_loop;
@@ -204,6 +253,11 @@ https://github.com/YtFlow/YtFlowCore",
                 {
                     return Ok(NavChoice::NewProxyGroupView);
                 }
+                KeyCode::Char('c')
+                    if category_state.selected() == Some(CATEGORY_ITEM_INDEX_RESOURCE) =>
+                {
+                    return Ok(NavChoice::NewResourceView);
+                }
                 KeyCode::Down if *focus_left => {
                     category_state.select(
                         category_state
@@ -214,7 +268,7 @@ https://github.com/YtFlow/YtFlowCore",
                 KeyCode::Up if *focus_left => {
                     category_state.select(category_state.selected().map(|i| {
                         if i == 0 {
-                            1
+                            CATEGORY_ITEM_COUNT - 1
                         } else {
                             i - 1
                         }
@@ -240,6 +294,13 @@ https://github.com/YtFlow/YtFlowCore",
                         return Ok(NavChoice::ProxyGroupView(proxy_groups[idx].id));
                     }
                 }
+                KeyCode::Enter
+                    if category_state.selected() == Some(CATEGORY_ITEM_INDEX_RESOURCE) =>
+                {
+                    if let Some(idx) = resource_state.selected() {
+                        return Ok(NavChoice::ResourceView(resources[idx].id));
+                    }
+                }
                 KeyCode::Down
                     if !*focus_left
                         && category_state.selected() == Some(CATEGORY_ITEM_INDEX_PROFILE) =>
@@ -281,12 +342,33 @@ https://github.com/YtFlow/YtFlowCore",
                         }
                     }));
                 }
+                KeyCode::Down
+                    if !*focus_left
+                        && category_state.selected() == Some(CATEGORY_ITEM_INDEX_RESOURCE) =>
+                {
+                    resource_state
+                        .select(resource_state.selected().map(|i| (i + 1) % resources.len()));
+                }
+                KeyCode::Up
+                    if !*focus_left
+                        && category_state.selected() == Some(CATEGORY_ITEM_INDEX_RESOURCE) =>
+                {
+                    resource_state.select(resource_state.selected().map(|i| {
+                        if i == 0 {
+                            resources.len() - 1
+                        } else {
+                            i - 1
+                        }
+                    }));
+                }
                 KeyCode::Char('d')
                     if !*focus_left
                         && category_state.selected() == Some(CATEGORY_ITEM_INDEX_PROFILE) =>
                 {
-                    if profile_state.selected().is_some() {
-                        delete_action = Some(DeleteAction::Profile);
+                    if let Some(idx) = profile_state.selected() {
+                        if !profiles[idx].locked {
+                            delete_action = Some(DeleteAction::Profile);
+                        }
                     }
                 }
                 KeyCode::Char('d')
@@ -297,11 +379,30 @@ https://github.com/YtFlow/YtFlowCore",
                         delete_action = Some(DeleteAction::ProxyGroup);
                     }
                 }
-                KeyCode::F(2)
+                KeyCode::Char('d')
+                    if !*focus_left
+                        && category_state.selected() == Some(CATEGORY_ITEM_INDEX_RESOURCE) =>
+                {
+                    if resource_state.selected().is_some() {
+                        delete_action = Some(DeleteAction::Resource);
+                    }
+                }
+                KeyCode::Char('l')
                     if !*focus_left
                         && category_state.selected() == Some(CATEGORY_ITEM_INDEX_PROFILE) =>
                 {
                     if let Some(idx) = profile_state.selected() {
+                        let profile = &mut profiles[idx];
+                        Profile::set_locked(profile.id.0, !profile.locked, &ctx.conn)
+                            .context("Failed to lock or unlock Profile")?;
+                        profile.locked = !profile.locked;
+                    }
+                }
+                KeyCode::F(2)
+                    if !*focus_left
+                        && category_state.selected() == Some(CATEGORY_ITEM_INDEX_PROFILE) =>
+                {
+                    if let Some(idx) = profile_state.selected().filter(|&i| !profiles[i].locked) {
                         let profile = profiles[idx].clone();
                         return Ok(NavChoice::InputView(InputRequest {
                             item: "new Profile name".into(),
@@ -322,6 +423,25 @@ https://github.com/YtFlow/YtFlowCore",
                         }));
                     }
                 }
+                KeyCode::Char('p')
+                    if !*focus_left
+                        && category_state.selected() == Some(CATEGORY_ITEM_INDEX_PROFILE) =>
+                {
+                    if let Some(idx) = profile_state.selected() {
+                        let profile_id = profiles[idx].id;
+                        return Ok(NavChoice::InputView(InputRequest {
+                            item: "name of the Profile copy".into(),
+                            desc: "Enter a name for the copy of the selected Profile. Plugins and entry flags are copied along.".into(),
+                            initial_value: format!("{} (copy)", profiles[idx].name),
+                            max_len: 255,
+                            action: Box::new(move |ctx, name| {
+                                Profile::duplicate(profile_id.0, name, &mut ctx.conn)
+                                    .context("Failed to duplicate Profile")?;
+                                Ok(())
+                            }),
+                        }));
+                    }
+                }
                 KeyCode::F(2)
                     if !*focus_left
                         && category_state.selected() == Some(CATEGORY_ITEM_INDEX_PROXY_GROUP) =>
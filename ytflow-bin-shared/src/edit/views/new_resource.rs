@@ -0,0 +1,226 @@
+use std::cell::Cell;
+
+use anyhow::{bail, Context, Result};
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind};
+use tui::{
+    layout::{Constraint, Direction, Layout},
+    style::Style,
+    widgets::{Block, Borders, List, ListItem, ListState},
+};
+
+use super::{bg_rev, InputRequest, NavChoice, BG};
+use crate::edit;
+use ytflow::data::Resource;
+use ytflow::resource::maxmind::DEFAULT_EDITION_ID;
+use ytflow::resource::{
+    RESOURCE_TYPE_CIDR_LIST, RESOURCE_TYPE_CLASH_RULE_PROVIDER, RESOURCE_TYPE_DOMAIN_LIST,
+    RESOURCE_TYPE_GEOIP_COUNTRY, RESOURCE_TYPE_GEOSITE, RESOURCE_TYPE_QUANX_FILTER,
+    RESOURCE_TYPE_SURGE_DOMAINSET,
+};
+
+thread_local! {
+    static SHOULD_RETURN: Cell<bool> = const { Cell::new(false) };
+}
+
+#[derive(Clone, Copy)]
+enum RemoteType {
+    Url,
+    GitHubRelease,
+    MaxmindPermalink,
+}
+
+const TEMPLATES: [(&str, &str, RemoteType); 13] = [
+    (
+        "GeoIP Country database from URL",
+        RESOURCE_TYPE_GEOIP_COUNTRY,
+        RemoteType::Url,
+    ),
+    (
+        "GeoIP Country database from GitHub Release",
+        RESOURCE_TYPE_GEOIP_COUNTRY,
+        RemoteType::GitHubRelease,
+    ),
+    (
+        "GeoIP Country database from MaxMind",
+        RESOURCE_TYPE_GEOIP_COUNTRY,
+        RemoteType::MaxmindPermalink,
+    ),
+    (
+        "Surge Domain Set from URL",
+        RESOURCE_TYPE_SURGE_DOMAINSET,
+        RemoteType::Url,
+    ),
+    (
+        "Surge Domain Set from GitHub Release",
+        RESOURCE_TYPE_SURGE_DOMAINSET,
+        RemoteType::GitHubRelease,
+    ),
+    (
+        "Quantumult X Filter from URL",
+        RESOURCE_TYPE_QUANX_FILTER,
+        RemoteType::Url,
+    ),
+    (
+        "Quantumult X Filter from GitHub Release",
+        RESOURCE_TYPE_QUANX_FILTER,
+        RemoteType::GitHubRelease,
+    ),
+    (
+        "CIDR List from URL",
+        RESOURCE_TYPE_CIDR_LIST,
+        RemoteType::Url,
+    ),
+    (
+        "CIDR List from GitHub Release",
+        RESOURCE_TYPE_CIDR_LIST,
+        RemoteType::GitHubRelease,
+    ),
+    (
+        "GeoSite database from URL",
+        RESOURCE_TYPE_GEOSITE,
+        RemoteType::Url,
+    ),
+    (
+        "GeoSite database from GitHub Release",
+        RESOURCE_TYPE_GEOSITE,
+        RemoteType::GitHubRelease,
+    ),
+    (
+        "Domain List from URL",
+        RESOURCE_TYPE_DOMAIN_LIST,
+        RemoteType::Url,
+    ),
+    (
+        "Clash Rule Provider from URL",
+        RESOURCE_TYPE_CLASH_RULE_PROVIDER,
+        RemoteType::Url,
+    ),
+];
+
+pub fn run_new_resource_view(ctx: &mut edit::AppContext) -> Result<NavChoice> {
+    if SHOULD_RETURN.with(|c| c.replace(false)) {
+        return Ok(NavChoice::Back);
+    }
+    let mut type_state = ListState::default();
+    type_state.select(Some(0));
+    loop {
+        let size = ctx.term.size()?;
+        let main_chunk = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(0)].as_ref())
+            .split(size)[0];
+        let template_list = List::new(
+            TEMPLATES
+                .iter()
+                .map(|(desc, _, _)| ListItem::new(*desc))
+                .collect::<Vec<_>>(),
+        )
+        .block(
+            Block::default()
+                .title("Choose a type")
+                .borders(Borders::ALL),
+        )
+        .highlight_style(Style::default().bg(bg_rev(true)).fg(BG));
+        ctx.term.draw(|f| {
+            f.render_stateful_widget(template_list, main_chunk, &mut type_state);
+        })?;
+        if let Event::Key(KeyEvent {
+            code,
+            kind: KeyEventKind::Press,
+            ..
+        }) = crossterm::event::read().unwrap()
+        {
+            match (code, type_state.selected()) {
+                (KeyCode::Char('q') | KeyCode::Esc, _) => return Ok(NavChoice::Back),
+                (KeyCode::Down, _) => {
+                    type_state.select(type_state.selected().map(|i| (i + 1) % TEMPLATES.len()));
+                }
+                (KeyCode::Up, _) => {
+                    type_state.select(
+                        type_state
+                            .selected()
+                            .map(|i| i.checked_sub(1).unwrap_or(TEMPLATES.len() - 1)),
+                    );
+                }
+                (KeyCode::Enter, Some(selected_index)) => {
+                    SHOULD_RETURN.with(|c| c.set(true));
+                    let (_, resource_type, remote_type) = TEMPLATES[selected_index];
+                    let (item, desc, initial_value) = match remote_type {
+                        RemoteType::Url => (
+                            "resource URL",
+                            "Enter the URL to download the resource from.",
+                            "https://",
+                        ),
+                        RemoteType::GitHubRelease => (
+                            "GitHub release asset",
+                            "Enter the GitHub release asset in the form of username/repo/asset_name. The asset will be downloaded from the latest release.",
+                            "",
+                        ),
+                        RemoteType::MaxmindPermalink => (
+                            "MaxMind account",
+                            "Enter the MaxMind account ID and license key in the form of account_id/license_key[/edition_id]. The edition defaults to GeoLite2-Country.",
+                            "",
+                        ),
+                    };
+                    return Ok(NavChoice::InputView(InputRequest {
+                        item: item.into(),
+                        desc: desc.into(),
+                        initial_value: initial_value.into(),
+                        max_len: 2048,
+                        action: Box::new(move |ctx, source| {
+                            let key = format!("{}-{}", resource_type, nanoid::nanoid!(5));
+                            let local_file = key.clone();
+                            match remote_type {
+                                RemoteType::Url => Resource::create_with_url(
+                                    key,
+                                    resource_type.into(),
+                                    local_file,
+                                    source,
+                                    &mut ctx.conn,
+                                ),
+                                RemoteType::GitHubRelease => {
+                                    let mut parts = source.trim().splitn(3, '/');
+                                    let (Some(username), Some(repo), Some(asset_name)) =
+                                        (parts.next(), parts.next(), parts.next())
+                                    else {
+                                        bail!("Expect username/repo/asset_name")
+                                    };
+                                    Resource::create_with_github_release(
+                                        key,
+                                        resource_type.into(),
+                                        local_file,
+                                        username.into(),
+                                        repo.into(),
+                                        asset_name.into(),
+                                        &mut ctx.conn,
+                                    )
+                                }
+                                RemoteType::MaxmindPermalink => {
+                                    let mut parts = source.trim().splitn(3, '/');
+                                    let (Some(account_id), Some(license_key)) =
+                                        (parts.next(), parts.next())
+                                    else {
+                                        bail!("Expect account_id/license_key[/edition_id]")
+                                    };
+                                    let edition_id = parts.next().unwrap_or(DEFAULT_EDITION_ID);
+                                    Resource::create_with_maxmind_permalink(
+                                        key,
+                                        resource_type.into(),
+                                        local_file,
+                                        edition_id.into(),
+                                        account_id.into(),
+                                        license_key.into(),
+                                        &mut ctx.conn,
+                                    )
+                                }
+                            }
+                            .context("Failed to create Resource")?;
+                            Ok(())
+                        }),
+                    }));
+                }
+                _ => {}
+            }
+        };
+    }
+}
@@ -10,7 +10,7 @@ use tui::{
 
 use super::{utils::open_editor_for_cbor, InputRequest, NavChoice, BG, FG};
 use crate::edit;
-use ytflow::data::{Plugin, Profile, ProfileId};
+use ytflow::data::{Plugin, Profile, ProfileId, ProfileSnapshot};
 
 pub fn run_profile_view(ctx: &mut edit::AppContext, id: ProfileId) -> Result<NavChoice> {
     let profile = Profile::query_by_id(id.0 as _, &ctx.conn)
@@ -54,6 +54,10 @@ pub fn run_profile_view(ctx: &mut edit::AppContext, id: ProfileId) -> Result<Nav
                     content: profile.name.clone().into(),
                     style: Style::default(),
                 },
+                Span {
+                    content: if profile.locked { " [locked]" } else { "" }.into(),
+                    style: Style::default(),
+                },
                 Span {
                     content: "  ".into(),
                     style: Style::default(),
@@ -91,11 +95,16 @@ pub fn run_profile_view(ctx: &mut edit::AppContext, id: ProfileId) -> Result<Nav
             f.render_stateful_widget(items, main_chunk, &mut plugin_state);
             f.render_widget(
                 match (delete_confirm, plugin_state.selected()) {
+                    _ if profile.locked => Paragraph::new(
+                        "This Profile is locked. Unlock it from the Profile list to make changes.\r\ns: Save snapshot; q: Quit",
+                    ),
                     (true, _) => Paragraph::new("y: Delete Plugin; <any key>: Cancel"),
                     (_, Some(_)) => Paragraph::new(
-                        "Enter: Edit params; c: Create Plugin; d: Delete Plugin; t: Change Plugin type\r\ne: Set/Unset as entry; F2: Rename; i: Edit desc; q: Quit",
+                        "Enter: Edit params; c: Create Plugin; d: Delete Plugin; t: Change Plugin type\r\ne: Set/Unset as entry; F2: Rename; i: Edit desc; s: Save snapshot; r: Restore snapshot; q: Quit",
+                    ),
+                    (_, None) => Paragraph::new(
+                        "c: Create Plugin; Enter: Rename; s: Save snapshot; r: Restore snapshot; q: Quit",
                     ),
-                    (_, None) => Paragraph::new("c: Create Plugin; Enter: Rename, q: Quit"),
                 },
                 status_bar_chunk,
             );
@@ -125,10 +134,57 @@ pub fn run_profile_view(ctx: &mut edit::AppContext, id: ProfileId) -> Result<Nav
         {
             match (code, plugin_state.selected()) {
                 (KeyCode::Char('q') | KeyCode::Esc, _) => break,
+                // Only navigation is allowed on a locked Profile.
+                _ if profile.locked
+                    && !matches!(code, KeyCode::Up | KeyCode::Down | KeyCode::Char('s')) => {}
                 (KeyCode::Char('c'), _) => {
                     return Ok(NavChoice::PluginTypeView(profile.id, None));
                 }
 
+                (KeyCode::Char('s'), _) => {
+                    let profile_id = profile.id;
+                    return Ok(NavChoice::InputView(InputRequest {
+                        item: "Snapshot name".into(),
+                        desc: "Enter a name for a snapshot of the current Plugins. Restore it later to roll back changes.".into(),
+                        initial_value: chrono::Local::now().format("%Y-%m-%d %H:%M").to_string(),
+                        max_len: 255,
+                        action: Box::new(move |ctx, name| {
+                            ProfileSnapshot::create(profile_id, name, &ctx.conn)
+                                .context("Failed to save snapshot")?;
+                            Ok(())
+                        }),
+                    }));
+                }
+                (KeyCode::Char('r'), _) => {
+                    let profile_id = profile.id;
+                    let snapshots = ProfileSnapshot::query_all_by_profile(profile_id, &ctx.conn)
+                        .context("Failed to query snapshots")?;
+                    let Some(latest) = snapshots.last() else {
+                        continue 'main_loop;
+                    };
+                    let names = snapshots
+                        .iter()
+                        .map(|s| s.name.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    return Ok(NavChoice::InputView(InputRequest {
+                        item: "Snapshot to restore".into(),
+                        desc: format!(
+                            "Enter the name of the snapshot to restore. Plugins created since will be deleted.\r\nAvailable snapshots: {}",
+                            names
+                        ),
+                        initial_value: latest.name.clone(),
+                        max_len: 255,
+                        action: Box::new(move |ctx, name| {
+                            let snapshot = ProfileSnapshot::query_by_name(profile_id, &name, &ctx.conn)
+                                .context("Failed to query snapshot")?
+                                .ok_or_else(|| anyhow!("Snapshot {} not found", name))?;
+                            ProfileSnapshot::restore(snapshot.id.0, &mut ctx.conn)
+                                .context("Failed to restore snapshot")?;
+                            Ok(())
+                        }),
+                    }));
+                }
                 (KeyCode::Down, None) => plugin_state.select(plugins.first().map(|_| 0)),
                 (KeyCode::Down, Some(idx)) => plugin_state.select(Some((idx + 1) % plugins.len())),
 
@@ -5,14 +5,19 @@ use serde::{Deserialize, Serialize};
 use serde_bytes::ByteBuf;
 use tui::{
     layout::{Constraint, Direction, Layout},
-    style::Style,
-    text::{Span, Spans},
+    style::{Color, Style},
+    text::{Span, Spans, Text},
     widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
 };
 
-use super::{InputRequest, NavChoice, BG, FG};
+use super::{InputRequest, NavChoice, BG, DIM_FG, FG};
 use crate::edit;
 use ytflow::data::{Proxy, ProxyGroup, ProxyGroupId};
+use ytflow::flow::{DestinationAddr, HostName};
+use ytflow_app_util::proxy::data::{analyze_data_proxy, compose_data_proxy_v1};
+use ytflow_app_util::proxy::protocol::ProxyProtocolType;
+use ytflow_app_util::proxy::Proxy as AppProxy;
+use ytflow_app_util::share_link::decode_share_link;
 
 pub fn run_proxy_group_view(ctx: &mut edit::AppContext, id: ProxyGroupId) -> Result<NavChoice> {
     let proxy_group = ProxyGroup::query_by_id(id.0 as _, &ctx.conn)
@@ -21,6 +26,8 @@ pub fn run_proxy_group_view(ctx: &mut edit::AppContext, id: ProxyGroupId) -> Res
     let mut proxies = Proxy::query_all_by_group(proxy_group.id, &ctx.conn)
         .context("Failed to query all proxies")?;
     let mut delete_confirm = false;
+    // Why the last key press could not be handled, shown in the status bar.
+    let mut error: Option<String> = None;
     let mut action_state = ListState::default();
     let mut proxy_state = ListState::default();
     if !proxies.is_empty() {
@@ -35,7 +42,7 @@ pub fn run_proxy_group_view(ctx: &mut edit::AppContext, id: ProxyGroupId) -> Res
                 [
                     Constraint::Length(2),
                     Constraint::Min(0),
-                    Constraint::Length(2),
+                    Constraint::Length(3),
                 ]
                 .as_ref(),
             )
@@ -72,23 +79,28 @@ pub fn run_proxy_group_view(ctx: &mut edit::AppContext, id: ProxyGroupId) -> Res
                 proxies
                     .iter()
                     .map(|p| {
-                        ListItem::new(&*p.name)
+                        ListItem::new(Spans(vec![
+                            Span::raw(&*p.name),
+                            Span::raw("  "),
+                            Span::styled(summarize_proxy(p), Style::default().fg(DIM_FG)),
+                        ]))
                     })
                     .collect::<Vec<_>>(),
             )
             .block(Block::default().title("Proxies").borders(Borders::ALL))
             .highlight_style(Style::default().bg(FG).fg(BG));
             f.render_stateful_widget(items, main_chunk, &mut proxy_state);
-            f.render_widget(
-                match (delete_confirm, proxy_state.selected()) {
-                    (true, _) => Paragraph::new("y: Delete Proxy; <any key>: Cancel"),
-                    (_, Some(_)) => Paragraph::new(
-                        "Enter: Edit Proxy; c: Create Proxy; d: Delete Plugin\r\n+/-: Reorder; F2: Rename; q: Quit",
-                    ),
-                    (_, None) => Paragraph::new("c: Create Proxy; Enter: Rename, q: Quit"),
-                },
-                status_bar_chunk,
+            let help = match (delete_confirm, proxy_state.selected()) {
+                (true, _) => "y: Delete Proxy; <any key>: Cancel",
+                (_, Some(_)) => "Enter: Edit Proxy; e: Edit Destination; p: Edit Password; d: Delete Proxy\r\nc: Create Proxy; v: Paste Share Link; +/-: Reorder; F2: Rename; q: Quit",
+                (_, None) => "c: Create Proxy; v: Paste Share Link; Enter: Rename, q: Quit",
+            };
+            let mut status = Text::styled(
+                error.as_deref().unwrap_or_default(),
+                Style::default().fg(Color::Red),
             );
+            status.extend(Text::raw(help).lines);
+            f.render_widget(Paragraph::new(status), status_bar_chunk);
         })?;
         if delete_confirm {
             loop {
@@ -112,9 +124,28 @@ pub fn run_proxy_group_view(ctx: &mut edit::AppContext, id: ProxyGroupId) -> Res
             ..
         }) = crossterm::event::read().unwrap()
         {
+            error = None;
             match (code, proxy_state.selected()) {
                 (KeyCode::Char('q') | KeyCode::Esc, _) => break,
                 (KeyCode::Char('c'), _) => return Ok(NavChoice::ProxyTypeView(proxy_group.id)),
+                (KeyCode::Char('v'), _) => {
+                    let proxy_group_id = proxy_group.id;
+                    return Ok(NavChoice::InputView(InputRequest {
+                        item: "share link".into(),
+                        desc: "Paste a share link (ss://, trojan://, vmess://, http://, socks5://) to import as a new Proxy.".into(),
+                        initial_value: String::new(),
+                        max_len: 4096,
+                        action: Box::new(move |ctx, link| {
+                            let proxy = decode_share_link(&link)
+                                .context("Failed to decode share link")?;
+                            let data = compose_data_proxy_v1(&proxy)
+                                .context("Failed to compose proxy from share link")?;
+                            Proxy::create(proxy_group_id, proxy.name, data, 0, &ctx.conn)
+                                .context("Failed to create proxy")?;
+                            Ok(())
+                        }),
+                    }));
+                }
 
                 (KeyCode::Down, None) => proxy_state.select(proxies.first().map(|_| 0)),
                 (KeyCode::Down, Some(idx)) => proxy_state.select(Some((idx + 1) % proxies.len())),
@@ -140,7 +171,11 @@ pub fn run_proxy_group_view(ctx: &mut edit::AppContext, id: ProxyGroupId) -> Res
                 (KeyCode::Enter, Some(idx)) => {
                     let proxy = proxies[idx].clone();
                     if proxy.proxy_version != 0 {
-                        bail!("Proxy version {} is not supported", proxy.proxy_version)
+                        error = Some(format!(
+                            "Proxy version {} is not supported",
+                            proxy.proxy_version
+                        ));
+                        continue 'main_loop;
                     }
                     if let Some(new_proxy_param) = edit_proxy(ctx, &proxy.proxy)? {
                         Proxy::update(
@@ -158,6 +193,69 @@ pub fn run_proxy_group_view(ctx: &mut edit::AppContext, id: ProxyGroupId) -> Res
                 (KeyCode::Char('d'), Some(_)) => {
                     delete_confirm = true;
                 }
+                (KeyCode::Char('e'), Some(idx)) => {
+                    let proxy = proxies[idx].clone();
+                    let analyzed = match analyze_single_leg_proxy(&proxy) {
+                        Ok(analyzed) => analyzed,
+                        Err(e) => {
+                            error = Some(format!("{:#}", e));
+                            continue 'main_loop;
+                        }
+                    };
+                    let desc =
+                        "Enter the new destination of the proxy server in the form of host:port."
+                            .into();
+                    return Ok(NavChoice::InputView(InputRequest {
+                        item: "new Proxy destination".into(),
+                        desc,
+                        initial_value: analyzed.legs[0].dest.to_string(),
+                        max_len: 262,
+                        action: Box::new(move |ctx, dest| {
+                            let mut analyzed = analyzed.clone();
+                            analyzed.legs[0].dest = parse_destination(&dest)?;
+                            update_analyzed_proxy(ctx, &proxy, &analyzed)
+                        }),
+                    }));
+                }
+                (KeyCode::Char('p'), Some(idx)) => {
+                    let proxy = proxies[idx].clone();
+                    let analyzed = match analyze_single_leg_proxy(&proxy) {
+                        Ok(analyzed) => analyzed,
+                        Err(e) => {
+                            error = Some(format!("{:#}", e));
+                            continue 'main_loop;
+                        }
+                    };
+                    let initial_value = match &analyzed.legs[0].protocol {
+                        ProxyProtocolType::Shadowsocks(p) => &p.password,
+                        ProxyProtocolType::Trojan(p) => &p.password,
+                        ProxyProtocolType::Http(p) => &p.password,
+                        ProxyProtocolType::Socks5(p) => &p.password,
+                        ProxyProtocolType::VMess(_) => {
+                            error = Some("VMess proxies have no password. Press Enter to edit the user ID instead.".into());
+                            continue 'main_loop;
+                        }
+                    };
+                    let initial_value = String::from_utf8_lossy(initial_value).into_owned();
+                    return Ok(NavChoice::InputView(InputRequest {
+                        item: "new Proxy password".into(),
+                        desc: "Enter the new password of the proxy server.".into(),
+                        initial_value,
+                        max_len: 1024,
+                        action: Box::new(move |ctx, password| {
+                            let mut analyzed = analyzed.clone();
+                            let password = ByteBuf::from(password.into_bytes());
+                            match &mut analyzed.legs[0].protocol {
+                                ProxyProtocolType::Shadowsocks(p) => p.password = password,
+                                ProxyProtocolType::Trojan(p) => p.password = password,
+                                ProxyProtocolType::Http(p) => p.password = password,
+                                ProxyProtocolType::Socks5(p) => p.password = password,
+                                ProxyProtocolType::VMess(_) => unreachable!(),
+                            }
+                            update_analyzed_proxy(ctx, &proxy, &analyzed)
+                        }),
+                    }));
+                }
                 (KeyCode::F(2), Some(idx)) => {
                     let proxy = proxies[idx].clone();
                     // https://github.com/rust-lang/rustfmt/issues/3135
@@ -221,6 +319,85 @@ pub fn run_proxy_group_view(ctx: &mut edit::AppContext, id: ProxyGroupId) -> Res
     Ok(NavChoice::Back)
 }
 
+fn protocol_name(protocol: &ProxyProtocolType) -> &'static str {
+    match protocol {
+        ProxyProtocolType::Shadowsocks(_) => "Shadowsocks",
+        ProxyProtocolType::Trojan(_) => "Trojan",
+        ProxyProtocolType::Http(_) => "HTTP",
+        ProxyProtocolType::Socks5(_) => "SOCKS5",
+        ProxyProtocolType::VMess(_) => "VMess",
+    }
+}
+
+fn summarize_proxy(proxy: &Proxy) -> String {
+    if proxy.proxy_version != 0 {
+        return format!("(unsupported version {})", proxy.proxy_version);
+    }
+    let Ok(analyzed) = analyze_data_proxy(proxy.name.clone(), &proxy.proxy, proxy.proxy_version)
+    else {
+        return "(custom)".into();
+    };
+    analyzed
+        .legs
+        .iter()
+        .map(|leg| {
+            let mut summary = format!("{} {}", protocol_name(&leg.protocol), leg.dest.to_string());
+            if leg.obfs.is_some() {
+                summary += " +obfs";
+            }
+            if leg.tls.is_some() {
+                summary += " +tls";
+            }
+            summary
+        })
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+fn analyze_single_leg_proxy(proxy: &Proxy) -> Result<AppProxy> {
+    if proxy.proxy_version != 0 {
+        bail!("Proxy version {} is not supported", proxy.proxy_version)
+    }
+    let analyzed = analyze_data_proxy(proxy.name.clone(), &proxy.proxy, proxy.proxy_version)
+        .context("Cannot recognize the proxy. Press Enter to edit the full config instead")?;
+    if analyzed.legs.len() != 1 {
+        bail!("Only proxies with a single leg can be edited in place. Press Enter to edit the full config instead")
+    }
+    Ok(analyzed)
+}
+
+fn update_analyzed_proxy(
+    ctx: &mut edit::AppContext,
+    proxy: &Proxy,
+    analyzed: &AppProxy,
+) -> Result<()> {
+    let data = compose_data_proxy_v1(analyzed).context("Failed to compose proxy")?;
+    Proxy::update(
+        proxy.id.0,
+        proxy.name.clone(),
+        data,
+        proxy.proxy_version,
+        &ctx.conn,
+    )
+    .context("Failed to update Proxy")?;
+    Ok(())
+}
+
+fn parse_destination(dest: &str) -> Result<DestinationAddr> {
+    let (host, port) = dest
+        .trim()
+        .rsplit_once(':')
+        .ok_or_else(|| anyhow!("Missing port in destination"))?;
+    let port = port.parse().context("Invalid port")?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let host = match host.parse() {
+        Ok(ip) => HostName::Ip(ip),
+        Err(_) => HostName::from_domain_name(host.into())
+            .map_err(|h| anyhow!("Invalid host name {}", h))?,
+    };
+    Ok(DestinationAddr { host, port })
+}
+
 #[derive(Serialize, Deserialize)]
 struct EditPlugin {
     pub name: String,
@@ -0,0 +1,149 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::NaiveDateTime;
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind};
+use tui::{
+    layout::{Constraint, Direction, Layout},
+    widgets::{Block, Borders, Paragraph},
+};
+
+use super::NavChoice;
+use crate::edit;
+use crate::edit::resource_update::{local_file_path, update_resource, UpdateOutcome};
+use ytflow::data::{
+    Resource, ResourceGitHubRelease, ResourceId, ResourceMaxmindPermalink, ResourceUrl,
+};
+
+fn describe_freshness(retrieved_at: Option<NaiveDateTime>) -> String {
+    let Some(retrieved_at) = retrieved_at else {
+        return "never retrieved".into();
+    };
+    let age = chrono::Utc::now().naive_utc() - retrieved_at;
+    let age = if age.num_days() > 0 {
+        format!("{} days ago", age.num_days())
+    } else if age.num_hours() > 0 {
+        format!("{} hours ago", age.num_hours())
+    } else {
+        format!("{} minutes ago", age.num_minutes().max(0))
+    };
+    format!(
+        "retrieved at {} UTC ({})",
+        retrieved_at.format("%F %T"),
+        age
+    )
+}
+
+fn describe_resource(ctx: &edit::AppContext, resource: &Resource) -> Result<String> {
+    let local_file = match local_file_path(&ctx.resource_root, &resource.local_file) {
+        Ok(path) if path.exists() => path.display().to_string(),
+        Ok(path) => format!("{} (missing)", path.display()),
+        Err(e) => format!("{} ({})", resource.local_file, e),
+    };
+    let mut desc = format!(
+        "Key: {}\r\nType: {}\r\nLocal file: {}\r\nCreated at: {} UTC\r\nUpdated at: {} UTC\r\n\r\n",
+        resource.key,
+        resource.r#type,
+        local_file,
+        resource.created_at.format("%F %T"),
+        resource.updated_at.format("%F %T"),
+    );
+    match &*resource.remote_type {
+        "url" => {
+            let url = ResourceUrl::query_by_resource_id(resource.id.0, &ctx.conn)
+                .context("Failed to query URL of the resource")?
+                .ok_or_else(|| anyhow!("URL of the resource is missing"))?;
+            desc += &format!(
+                "URL: {}\r\nETag: {}\r\nLast-Modified: {}\r\nFreshness: {}",
+                url.url,
+                url.etag.as_deref().unwrap_or("-"),
+                url.last_modified.as_deref().unwrap_or("-"),
+                describe_freshness(url.retrieved_at),
+            );
+        }
+        "github_release" => {
+            let release = ResourceGitHubRelease::query_by_resource_id(resource.id.0, &ctx.conn)
+                .context("Failed to query GitHub release of the resource")?
+                .ok_or_else(|| anyhow!("GitHub release of the resource is missing"))?;
+            desc += &format!(
+                "GitHub repository: {}/{}\r\nAsset: {}\r\nRelease: {} ({})\r\nFreshness: {}",
+                release.github_username,
+                release.github_repo,
+                release.asset_name,
+                release.release_title.as_deref().unwrap_or("-"),
+                release.git_tag.as_deref().unwrap_or("-"),
+                describe_freshness(release.retrieved_at),
+            );
+        }
+        "maxmind_permalink" => {
+            let permalink =
+                ResourceMaxmindPermalink::query_by_resource_id(resource.id.0, &ctx.conn)
+                    .context("Failed to query MaxMind permalink of the resource")?
+                    .ok_or_else(|| anyhow!("MaxMind permalink of the resource is missing"))?;
+            desc += &format!(
+                "MaxMind edition: {}\r\nAccount ID: {}\r\nLast-Modified: {}\r\nFreshness: {}",
+                permalink.edition_id,
+                permalink.account_id,
+                permalink.last_modified.as_deref().unwrap_or("-"),
+                describe_freshness(permalink.retrieved_at),
+            );
+        }
+        t => desc += &format!("Unknown remote type: {}", t),
+    }
+    Ok(desc)
+}
+
+fn draw(ctx: &mut edit::AppContext, resource: &Resource, desc: &str, status: &str) -> Result<()> {
+    let size = ctx.term.size()?;
+    let vchunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(2)].as_ref())
+        .split(size);
+    let main_chunk = vchunks[0];
+    let status_bar_chunk = vchunks[1];
+
+    ctx.term.draw(|f| {
+        let content = Paragraph::new(desc).block(
+            Block::default()
+                .title(format!("Resource: {}", resource.key))
+                .borders(Borders::ALL),
+        );
+        f.render_widget(content, main_chunk);
+        f.render_widget(
+            Paragraph::new(format!("{}\r\nu: Download/Update; q: Quit", status)),
+            status_bar_chunk,
+        );
+    })?;
+    Ok(())
+}
+
+pub fn run_resource_view(ctx: &mut edit::AppContext, id: ResourceId) -> Result<NavChoice> {
+    let resource = Resource::query_by_id(id.0, &ctx.conn)
+        .context("Could not query selected resource")?
+        .ok_or_else(|| anyhow!("Resource not found"))?;
+    let mut status = String::new();
+
+    loop {
+        let desc = describe_resource(ctx, &resource)?;
+        draw(ctx, &resource, &desc, &status)?;
+
+        if let Event::Key(KeyEvent {
+            code,
+            kind: KeyEventKind::Press,
+            ..
+        }) = crossterm::event::read().unwrap()
+        {
+            match code {
+                KeyCode::Char('q') | KeyCode::Esc => break,
+                KeyCode::Char('u') => {
+                    draw(ctx, &resource, &desc, "Downloading...")?;
+                    status = match update_resource(&resource, &ctx.resource_root, &ctx.conn) {
+                        Ok(UpdateOutcome::Updated) => "Resource updated".into(),
+                        Ok(UpdateOutcome::UpToDate) => "Resource is up to date".into(),
+                        Err(e) => format!("Update failed: {:#}", e),
+                    };
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(NavChoice::Back)
+}
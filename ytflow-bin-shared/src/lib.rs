@@ -16,7 +16,7 @@ fn execute_main<E>(main: impl FnOnce() -> Result<(), E>) {
 
 #[no_mangle]
 pub extern "C" fn ytflow_bin_exec_core() {
-    execute_main(core::main)
+    core::main().exit_process()
 }
 
 #[no_mangle]
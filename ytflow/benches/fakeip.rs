@@ -0,0 +1,28 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use ytflow::data::PluginCache;
+use ytflow::flow::Resolver;
+use ytflow::plugin::fakeip::FakeIp;
+
+fn bench_fakeip(c: &mut Criterion) {
+    let fakeip = FakeIp::new(
+        [11, 17],
+        [0x26, 0x0c, 0x20, 0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        PluginCache::new(0.into(), None),
+    );
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    rt.block_on(fakeip.resolve_ipv4("example.com.".into()))
+        .unwrap();
+
+    c.bench_function("fakeip mapped sync", |b| {
+        b.iter(|| fakeip.try_resolve_ipv4_sync(black_box("example.com.")))
+    });
+    c.bench_function("fakeip mapped async", |b| {
+        b.iter(|| rt.block_on(fakeip.resolve_ipv4(black_box("example.com.").into())))
+    });
+}
+
+criterion_group!(benches, bench_fakeip);
+criterion_main!(benches);
@@ -0,0 +1,8 @@
+//! 64-bit atomics are not available on every target YtFlow runs on, notably 32-bit MIPS
+//! routers. Import them from here instead of `std::sync::atomic`.
+
+#[cfg(target_has_atomic = "64")]
+pub(crate) use std::sync::atomic::{AtomicI64, AtomicU64};
+
+#[cfg(not(target_has_atomic = "64"))]
+pub(crate) use portable_atomic::{AtomicI64, AtomicU64};
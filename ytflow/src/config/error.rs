@@ -34,6 +34,12 @@ pub enum ConfigError {
         plugin: String,
         r#type: &'static str,
     },
+    #[error(r#"cannot migrate param of plugin "{plugin:}" from v{from_version:}: {reason:}"#)]
+    MigrateParam {
+        plugin: String,
+        from_version: u16,
+        reason: String,
+    },
 }
 
 #[derive(Debug, Error)]
@@ -50,6 +56,12 @@ pub enum LoadError {
         plugin: String,
         error: std::io::Error,
     },
+    #[error(r#"plugin "{plugin:}" cannot listen on {addr:}, which is in use by {owner:}. Stop that process, choose another port, or set "reuse_port" or "port_fallback""#)]
+    PortInUse {
+        plugin: String,
+        addr: String,
+        owner: String,
+    },
     #[error(r#"an error occurs while loading the resources for plugin {plugin:}: {error:}"#)]
     Resource {
         plugin: String,
@@ -64,6 +76,20 @@ pub enum LoadError {
     },
     #[error(r#"plugin "{plugin:}" required a database to work"#)]
     DatabaseRequired { plugin: String },
+    #[error(r#"plugin "{plugin:}" cannot select a proxy from group "{group:}": {reason:}"#)]
+    ProxyGroupSelection {
+        plugin: String,
+        group: String,
+        reason: String,
+    },
+    #[error(r#"plugin "{plugin:}" of type "{r#type:}" is not built in. Rebuild with the "{feature:}" feature to use it"#)]
+    NotBuiltIn {
+        plugin: String,
+        r#type: &'static str,
+        feature: &'static str,
+    },
+    #[error("cannot load schedules from database: {0}")]
+    Schedules(crate::data::DataError),
 }
 
 pub type ConfigResult<T> = Result<T, ConfigError>;
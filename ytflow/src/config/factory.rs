@@ -39,6 +39,16 @@ pub struct RequiredResource<'de> {
     pub allowed_types: &'static [&'static str],
 }
 
+/// Access points of a plugin as declared in a profile, regardless of whether
+/// it has been loaded.
+#[derive(Debug, Clone, Serialize)]
+pub struct PluginDeclaration {
+    pub name: String,
+    pub plugin: String,
+    pub requires: Vec<Descriptor<String>>,
+    pub provides: Vec<ProvideDescriptor>,
+}
+
 pub(super) struct ParsedPlugin<'de, F: Factory> {
     pub(super) factory: F,
     pub(super) requires: Vec<DemandDescriptor<'de>>,
@@ -66,8 +76,8 @@ pub(super) fn create_factory_from_plugin(
         r#type: plugin.plugin.clone(),
         version: plugin.plugin_version,
     });
-    // All plugins are using v0 config at this moment;
-    if plugin.plugin_version != 0 {
+    // Outdated versions have to go through `migration::migrate_plugins` first.
+    if plugin.plugin_version != super::migration::current_plugin_version(&plugin.plugin) {
         return no_such_type_err;
     }
     fn box_result<'de, 'f, F: Factory + 'f>(
@@ -93,30 +103,49 @@ pub(super) fn create_factory_from_plugin(
         "null" => box_result(NullFactory::parse(plugin)),
         "ip-stack" => box_result(IpStackFactory::parse(plugin)),
         "socket-listener" => box_result(SocketListenerFactory::parse(plugin)),
+        "tproxy-listener" => box_result(TproxyListenerFactory::parse(plugin)),
         "vpn-tun" => box_result(VpnTunFactory::parse(plugin)),
         "host-resolver" => box_result(HostResolverFactory::parse(plugin)),
         "fake-ip" => box_result(FakeIpFactory::parse(plugin)),
         "system-resolver" => box_result(SystemResolverFactory::parse(plugin)),
         "switch" => box_result(SwitchFactory::parse(plugin)),
         "dns-server" => box_result(DnsServerFactory::parse(plugin)),
+        "dns-hijack" => box_result(DnsHijackFactory::parse(plugin)),
         "socks5-server" => box_result(Socks5ServerFactory::parse(plugin)),
         "http-obfs-server" => box_result(HttpObfsServerFactory::parse(plugin)),
         "resolve-dest" => box_result(ResolveDestFactory::parse(plugin)),
         "simple-dispatcher" => box_result(SimpleDispatcherFactory::parse(plugin)),
         "rule-dispatcher" => box_result(RuleDispatcherFactory::parse(plugin)),
         "list-dispatcher" => box_result(ListDispatcherFactory::parse(plugin)),
+        "alpn-dispatcher" => box_result(AlpnDispatcherFactory::parse(plugin)),
+        "mixed-listener" => box_result(MixedListenerFactory::parse(plugin)),
         "forward" => box_result(ForwardFactory::parse(plugin)),
         "dyn-outbound" => box_result(DynOutboundFactory::parse(plugin)),
         "shadowsocks-client" => box_result(ShadowsocksFactory::parse(plugin)),
         "socks5-client" => box_result(Socks5ClientFactory::parse(plugin)),
+        "http-proxy-server" => box_result(HttpProxyServerFactory::parse(plugin)),
         "http-proxy-client" => box_result(HttpProxyFactory::parse(plugin)),
         "tls-client" => box_result(TlsFactory::parse(plugin)),
+        "quic-client" => box_result(QuicClientFactory::parse(plugin)),
+        "mux-client" => box_result(MuxClientFactory::parse(plugin)),
         "trojan-client" => box_result(TrojanFactory::parse(plugin)),
         "vmess-client" => box_result(VMessClientFactory::parse(plugin)),
         "http-obfs-client" => box_result(HttpObfsClientFactory::parse(plugin)),
         "tls-obfs-client" => box_result(TlsObfsClientFactory::parse(plugin)),
+        "scramble" => box_result(ScrambleFactory::parse(plugin)),
         "ws-client" => box_result(WsClientFactory::parse(plugin)),
+        "masque-client" => box_result(MasqueClientFactory::parse(plugin)),
         "redirect" => box_result(RedirectFactory::parse(plugin)),
+        "circuit-breaker" => box_result(CircuitBreakerFactory::parse(plugin)),
+        "bonding" => box_result(BondingFactory::parse(plugin)),
+        "guard" => box_result(GuardFactory::parse(plugin)),
+        "delay" => box_result(DelayFactory::parse(plugin)),
+        "ping-prober" => box_result(PingProberFactory::parse(plugin)),
+        "url-test" => box_result(UrlTestFactory::parse(plugin)),
+        "stun-keepalive" => box_result(StunKeepaliveFactory::parse(plugin)),
+        "health-endpoint" => box_result(HealthEndpointFactory::parse(plugin)),
+        "memory-watchdog" => box_result(MemoryWatchdogFactory::parse(plugin)),
+        "automation" => box_result(AutomationFactory::parse(plugin)),
         "socket" => box_result(SocketFactory::parse(plugin)),
         "netif" => box_result(NetifFactory::parse(plugin)),
         _ => no_such_type_err,
@@ -141,6 +170,8 @@ pub(super) struct ParseResultCollection<'f> {
     pub(super) factories: BTreeMap<String, Box<dyn Factory + 'f>>,
     pub(super) errors: Vec<ConfigError>,
     pub(super) resources: Vec<RequiredResource<'f>>,
+    #[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+    pub(super) declarations: Vec<PluginDeclaration>,
 }
 
 impl<'de> AccessPointResolver<'de> {
@@ -200,6 +231,18 @@ impl<'de> AccessPointResolver<'de> {
             mut resources,
         } = parsed;
         result_col.resources.append(&mut resources);
+        result_col.declarations.push(PluginDeclaration {
+            name: plugin.name.clone(),
+            plugin: plugin.plugin.clone(),
+            requires: requires
+                .iter()
+                .map(|d| Descriptor {
+                    descriptor: d.descriptor.to_string(),
+                    r#type: d.r#type,
+                })
+                .collect(),
+            provides: provides.clone(),
+        });
         provides
             .into_iter()
             .for_each(|p| self.provide(p, &mut result_col.errors));
@@ -7,7 +7,11 @@ use crate::config::factory::RequiredResource;
 use crate::config::*;
 
 #[cfg(feature = "plugins")]
-pub struct ProfileLoader<'f>(BTreeMap<String, Box<dyn factory::Factory + 'f>>);
+pub struct ProfileLoader<'f>(
+    BTreeMap<String, Box<dyn factory::Factory + 'f>>,
+    Vec<factory::PluginDeclaration>,
+    bool,
+);
 #[cfg(not(feature = "plugins"))]
 pub struct ProfileLoader<'f>(std::marker::PhantomData<&'f ()>);
 
@@ -19,6 +23,14 @@ pub struct ProfileLoadResult {
 }
 
 impl<'f> ProfileLoader<'f> {
+    /// Upgrade plugins of a profile saved with outdated param versions. Must be
+    /// run on `all_plugins` before [`Self::parse_profile`], which only accepts
+    /// the latest versions.
+    pub fn migrate_profile(
+        all_plugins: &mut Vec<Plugin>,
+    ) -> (Vec<migration::MigrationReport>, Vec<ConfigError>) {
+        migration::migrate_plugins(all_plugins)
+    }
     pub fn parse_profile(
         entry_plugins: impl Iterator<Item = &'f Plugin>,
         all_plugins: &'f [Plugin],
@@ -34,11 +46,23 @@ impl<'f> ProfileLoader<'f> {
             all_plugins,
         );
         #[cfg(feature = "plugins")]
-        let res = (Self(res.factories), res.resources, res.errors);
+        let res = (
+            Self(res.factories, res.declarations, false),
+            res.resources,
+            res.errors,
+        );
         #[cfg(not(feature = "plugins"))]
         let res = (Self(Default::default()), res.resources, res.errors);
         res
     }
+    /// Attribute traffic through access points to the plugins providing them,
+    /// as reported by [`crate::control::ControlHub::traffic`]. Off by
+    /// default, since every hop of a chain then costs an extra wrapper.
+    #[cfg(feature = "plugins")]
+    pub fn count_traffic(mut self, enabled: bool) -> Self {
+        self.2 = enabled;
+        self
+    }
     #[cfg(feature = "plugins")]
     pub fn load_all(
         self,
@@ -49,6 +73,8 @@ impl<'f> ProfileLoader<'f> {
         use std::collections::HashMap;
         use std::mem::ManuallyDrop;
 
+        use crate::control::PluginGraph;
+
         let rt_handle_cloned = rt_handle.clone();
         let _enter_guard = rt_handle.enter();
         let mut partial_set = set::PartialPluginSet::new(
@@ -64,9 +90,43 @@ impl<'f> ProfileLoader<'f> {
                 datagram_outbounds: ManuallyDrop::new(HashMap::new()),
                 resolver: ManuallyDrop::new(HashMap::new()),
                 tun: ManuallyDrop::new(HashMap::new()),
+                counted_access_points: vec![],
             },
         );
+        *partial_set.control_hub.plugin_graph_mut() = PluginGraph::new(self.1);
+        partial_set.counting_traffic = self.2;
         partial_set.load_all();
+        if let Some(db) = db {
+            match db
+                .connect()
+                .and_then(|c| crate::data::Schedule::query_all(&c))
+            {
+                Ok(schedules) => {
+                    let scheduler = crate::control::Scheduler::new(
+                        schedules,
+                        chrono::Local::now().naive_local(),
+                    );
+                    let responders = partial_set.control_hub.plugin_responders();
+                    partial_set.control_hub.set_scheduler(scheduler.clone());
+                    partial_set
+                        .fully_constructed
+                        .long_running_tasks
+                        .push(tokio::spawn(scheduler.drive(responders)));
+                }
+                Err(error) => partial_set.errors.push(LoadError::Schedules(error)),
+            }
+        }
+        let errors: Vec<_> = partial_set.errors.iter().map(|e| e.to_string()).collect();
+        partial_set
+            .control_hub
+            .events()
+            .publish(crate::control::events::Event::Connected {
+                errors: errors.len() as u32,
+            });
+        partial_set
+            .control_hub
+            .plugin_graph_mut()
+            .set_errors(errors);
         ProfileLoadResult {
             plugin_set: partial_set.fully_constructed,
             errors: partial_set.errors,
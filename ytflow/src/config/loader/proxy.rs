@@ -148,6 +148,7 @@ impl<'f, I1: IntoIterator<Item = &'f str> + 'f, I2: IntoIterator<Item = &'f str>
                 ),
                 resolver: ManuallyDrop::new(HashMap::new()),
                 tun: ManuallyDrop::new(HashMap::new()),
+                counted_access_points: vec![],
             },
         );
         partial_set.load_all();
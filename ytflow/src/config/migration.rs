@@ -0,0 +1,366 @@
+use std::collections::{HashSet, VecDeque};
+
+use cbor4ii::core::Value as CborValue;
+use serde::Serialize;
+
+use super::{ConfigError, Plugin};
+
+/// Stop following a chain of migrations that does not seem to converge.
+const MAX_MIGRATION_STEPS: usize = 32;
+
+/// Upgrade a plugin from `from_version` of `plugin` to a newer schema. The
+/// first plugin returned replaces the original one and must keep its name.
+/// Any other plugins returned are added to the profile, e.g. when a plugin is
+/// split into two.
+pub type MigrateFn = fn(Plugin) -> Result<Vec<Plugin>, String>;
+
+pub struct PluginMigration {
+    pub plugin: &'static str,
+    pub from_version: u16,
+    pub migrate: MigrateFn,
+}
+
+/// Migrations of built-in plugins. When the param schema of a plugin changes,
+/// register how to upgrade from the previous version here and bump the version
+/// in its schema. Factories only accept the version right after the last
+/// migration registered for the plugin.
+pub static BUILTIN_MIGRATIONS: &[PluginMigration] = &[PluginMigration {
+    plugin: "ip-stack",
+    from_version: 0,
+    migrate: migrate_ip_stack_v0,
+}];
+
+/// The param version of `plugin` accepted by its factory.
+pub fn current_plugin_version(plugin: &str) -> u16 {
+    BUILTIN_MIGRATIONS
+        .iter()
+        .filter(|m| m.plugin == plugin)
+        .map(|m| m.from_version + 1)
+        .max()
+        .unwrap_or(0)
+}
+
+/// ip-stack v0 listed TCP stacks in `tcp.stacks`, which only ever differed in
+/// congestion control. v1 lists the controllers in `tcp.congestion_controls`.
+fn migrate_ip_stack_v0(mut plugin: Plugin) -> Result<Vec<Plugin>, String> {
+    plugin.param = edit_param_fields(&plugin.param, |fields| {
+        let tcp = fields
+            .iter_mut()
+            .find(|(k, _)| matches!(k, CborValue::Text(k) if k == "tcp"));
+        let Some((_, CborValue::Map(tcp))) = tcp else {
+            return Ok(());
+        };
+        for (key, value) in tcp.iter_mut() {
+            if !matches!(key, CborValue::Text(k) if k == "stacks") {
+                continue;
+            }
+            *key = CborValue::Text("congestion_controls".into());
+            let CborValue::Array(stacks) = value else {
+                return Err("tcp.stacks is not an array".into());
+            };
+            for stack in stacks {
+                let congestion = match &*stack {
+                    CborValue::Text(s) if s == "smoltcp" => "none",
+                    CborValue::Text(s) if s == "smoltcp-reno" => "reno",
+                    CborValue::Text(s) if s == "smoltcp-cubic" => "cubic",
+                    _ => return Err(format!("unknown TCP stack {:?}", stack)),
+                };
+                *stack = CborValue::Text(congestion.into());
+            }
+        }
+        Ok(())
+    })?;
+    plugin.plugin_version = 1;
+    Ok(vec![plugin])
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MigrationReport {
+    pub name: String,
+    pub from_plugin: String,
+    pub from_version: u16,
+    pub to_plugin: String,
+    pub to_version: u16,
+    /// Names of plugins added by the migration.
+    pub added_plugins: Vec<String>,
+}
+
+/// Upgrade plugins with outdated params using [`BUILTIN_MIGRATIONS`]. Plugins
+/// failing to migrate, or whose migration would add a plugin with a name
+/// already taken, are kept at the last version reached.
+pub fn migrate_plugins(plugins: &mut Vec<Plugin>) -> (Vec<MigrationReport>, Vec<ConfigError>) {
+    migrate_plugins_with(plugins, BUILTIN_MIGRATIONS)
+}
+
+pub fn migrate_plugins_with(
+    plugins: &mut Vec<Plugin>,
+    migrations: &[PluginMigration],
+) -> (Vec<MigrationReport>, Vec<ConfigError>) {
+    let mut reports = vec![];
+    let mut errors = vec![];
+    let mut names: HashSet<_> = plugins.iter().map(|p| p.name.clone()).collect();
+    let mut queue: VecDeque<_> = std::mem::take(plugins).into();
+    while let Some(mut plugin) = queue.pop_front() {
+        let mut report = MigrationReport {
+            name: plugin.name.clone(),
+            from_plugin: plugin.plugin.clone(),
+            from_version: plugin.plugin_version,
+            to_plugin: String::new(),
+            to_version: 0,
+            added_plugins: vec![],
+        };
+        for _ in 0..MAX_MIGRATION_STEPS {
+            let Some(migration) = migrations
+                .iter()
+                .find(|m| m.plugin == plugin.plugin && m.from_version == plugin.plugin_version)
+            else {
+                break;
+            };
+            let from_version = plugin.plugin_version;
+            let mut migrated = match (migration.migrate)(plugin.clone()) {
+                Ok(migrated) if migrated.first().map(|p| &p.name) != Some(&plugin.name) => {
+                    errors.push(ConfigError::MigrateParam {
+                        plugin: plugin.name.clone(),
+                        from_version,
+                        reason: "the migrated plugin must keep its name".into(),
+                    });
+                    break;
+                }
+                Ok(migrated) => {
+                    let mut added_names = HashSet::new();
+                    let collision = migrated[1..]
+                        .iter()
+                        .map(|p| &p.name)
+                        .find(|n| names.contains(*n) || !added_names.insert(*n))
+                        .cloned();
+                    if let Some(collision) = collision {
+                        errors.push(ConfigError::MigrateParam {
+                            plugin: plugin.name.clone(),
+                            from_version,
+                            reason: format!(
+                                "the added plugin {} collides with an existing one",
+                                collision
+                            ),
+                        });
+                        break;
+                    }
+                    migrated.into_iter()
+                }
+                Err(reason) => {
+                    errors.push(ConfigError::MigrateParam {
+                        plugin: plugin.name.clone(),
+                        from_version,
+                        reason,
+                    });
+                    break;
+                }
+            };
+            plugin = migrated.next().unwrap();
+            for added in migrated {
+                names.insert(added.name.clone());
+                report.added_plugins.push(added.name.clone());
+                queue.push_back(added);
+            }
+        }
+        if plugin.plugin != report.from_plugin
+            || plugin.plugin_version != report.from_version
+            || !report.added_plugins.is_empty()
+        {
+            report.to_plugin = plugin.plugin.clone();
+            report.to_version = plugin.plugin_version;
+            reports.push(report);
+        }
+        plugins.push(plugin);
+    }
+    (reports, errors)
+}
+
+/// Edit the fields of a plugin param that is a CBOR map.
+pub fn edit_param_fields(
+    param: &[u8],
+    edit: impl FnOnce(&mut Vec<(CborValue, CborValue)>) -> Result<(), String>,
+) -> Result<Vec<u8>, String> {
+    let value: CborValue = cbor4ii::serde::from_slice(param).map_err(|e| e.to_string())?;
+    let CborValue::Map(mut fields) = value else {
+        return Err("param is not a map".into());
+    };
+    edit(&mut fields)?;
+    cbor4ii::serde::to_vec(vec![], &CborValue::Map(fields)).map_err(|e| e.to_string())
+}
+
+/// Rename a field of a plugin param, if present.
+pub fn rename_param_field(param: &[u8], from: &str, to: &str) -> Result<Vec<u8>, String> {
+    edit_param_fields(param, |fields| {
+        for (key, _) in fields.iter_mut() {
+            if matches!(key, CborValue::Text(k) if k == from) {
+                *key = CborValue::Text(to.into());
+            }
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plugin(name: &str, plugin: &str, version: u16, param: CborValue) -> Plugin {
+        Plugin {
+            id: None,
+            name: name.into(),
+            plugin: plugin.into(),
+            plugin_version: version,
+            param: cbor4ii::serde::to_vec(vec![], &param).unwrap(),
+        }
+    }
+
+    fn text(s: &str) -> CborValue {
+        CborValue::Text(s.into())
+    }
+
+    fn rename_next(mut p: Plugin) -> Result<Vec<Plugin>, String> {
+        p.param = rename_param_field(&p.param, "next", "tcp_next")?;
+        p.plugin_version = 1;
+        Ok(vec![p])
+    }
+
+    fn split(mut p: Plugin) -> Result<Vec<Plugin>, String> {
+        let helper = plugin(&(p.name.clone() + "-helper"), "null", 0, CborValue::Null);
+        p.plugin_version = 2;
+        Ok(vec![p, helper])
+    }
+
+    fn rename_plugin(mut p: Plugin) -> Result<Vec<Plugin>, String> {
+        p.name += "-renamed";
+        Ok(vec![p])
+    }
+
+    static MIGRATIONS: &[PluginMigration] = &[
+        PluginMigration {
+            plugin: "foo",
+            from_version: 0,
+            migrate: rename_next,
+        },
+        PluginMigration {
+            plugin: "foo",
+            from_version: 1,
+            migrate: split,
+        },
+        PluginMigration {
+            plugin: "bad",
+            from_version: 0,
+            migrate: rename_plugin,
+        },
+    ];
+
+    #[test]
+    fn test_migrate_plugins_chain() {
+        let mut plugins = vec![
+            plugin(
+                "a",
+                "foo",
+                0,
+                CborValue::Map(vec![(text("next"), text("b.tcp"))]),
+            ),
+            plugin("b", "null", 0, CborValue::Null),
+        ];
+        let (reports, errors) = migrate_plugins_with(&mut plugins, MIGRATIONS);
+        assert!(errors.is_empty());
+        assert_eq!(
+            reports,
+            vec![MigrationReport {
+                name: "a".into(),
+                from_plugin: "foo".into(),
+                from_version: 0,
+                to_plugin: "foo".into(),
+                to_version: 2,
+                added_plugins: vec!["a-helper".into()],
+            }]
+        );
+        let names: Vec<_> = plugins.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["a", "b", "a-helper"]);
+        let param: CborValue = cbor4ii::serde::from_slice(&plugins[0].param).unwrap();
+        assert_eq!(
+            param,
+            CborValue::Map(vec![(text("tcp_next"), text("b.tcp"))])
+        );
+    }
+
+    #[test]
+    fn test_migrate_plugins_rejects_collisions() {
+        let mut plugins = vec![
+            plugin("a", "foo", 1, CborValue::Null),
+            plugin("a-helper", "null", 0, CborValue::Null),
+        ];
+        let (reports, errors) = migrate_plugins_with(&mut plugins, MIGRATIONS);
+        assert!(reports.is_empty());
+        assert!(matches!(
+            &errors[..],
+            [ConfigError::MigrateParam { plugin, from_version: 1, .. }] if plugin == "a"
+        ));
+        assert_eq!(plugins.len(), 2);
+        assert_eq!(plugins[0].plugin_version, 1);
+    }
+
+    #[test]
+    fn test_migrate_ip_stack_v0() {
+        let mut plugins = vec![plugin(
+            "tun",
+            "ip-stack",
+            0,
+            CborValue::Map(vec![
+                (text("tun"), text("vpn.tun")),
+                (
+                    text("tcp"),
+                    CborValue::Map(vec![(
+                        text("stacks"),
+                        CborValue::Array(vec![text("smoltcp"), text("smoltcp-cubic")]),
+                    )]),
+                ),
+            ]),
+        )];
+        let (reports, errors) = migrate_plugins(&mut plugins);
+        assert!(errors.is_empty());
+        assert_eq!(reports[0].to_version, current_plugin_version("ip-stack"));
+        assert_eq!(plugins[0].plugin_version, 1);
+        let param: CborValue = cbor4ii::serde::from_slice(&plugins[0].param).unwrap();
+        assert_eq!(
+            param,
+            CborValue::Map(vec![
+                (text("tun"), text("vpn.tun")),
+                (
+                    text("tcp"),
+                    CborValue::Map(vec![(
+                        text("congestion_controls"),
+                        CborValue::Array(vec![text("none"), text("cubic")]),
+                    )]),
+                ),
+            ])
+        );
+
+        let mut plugins = vec![plugin(
+            "tun",
+            "ip-stack",
+            0,
+            CborValue::Map(vec![(
+                text("tcp"),
+                CborValue::Map(vec![(text("stacks"), CborValue::Array(vec![text("lwip")]))]),
+            )]),
+        )];
+        let (_, errors) = migrate_plugins(&mut plugins);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(plugins[0].plugin_version, 0);
+    }
+
+    #[test]
+    fn test_migrate_plugins_rejects_renaming() {
+        let mut plugins = vec![plugin("a", "bad", 0, CborValue::Null)];
+        let (reports, errors) = migrate_plugins_with(&mut plugins, MIGRATIONS);
+        assert!(reports.is_empty());
+        assert!(matches!(
+            &errors[..],
+            [ConfigError::MigrateParam { plugin, from_version: 0, .. }] if plugin == "a"
+        ));
+        assert_eq!(plugins[0].name, "a");
+    }
+}
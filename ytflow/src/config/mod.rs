@@ -2,8 +2,10 @@ mod error;
 pub mod factory;
 mod human_repr;
 pub mod loader;
+pub mod migration;
 mod param;
 pub mod plugin;
+pub mod schema;
 #[cfg(feature = "plugins")]
 mod set;
 pub mod verify;
@@ -1,57 +1,94 @@
+mod alpn_dispatcher;
+mod automation;
+mod bonding;
+mod circuit_breaker;
+mod delay;
+mod dns_hijack;
 mod dns_server;
 mod dyn_outbound;
 mod fakeip;
 mod forward;
+mod guard;
+mod health_endpoint;
 mod host_resolver;
 mod http_obfs;
 mod http_proxy;
 mod ip_stack;
 mod list_dispatcher;
+mod masque_client;
+mod memory_watchdog;
+mod mixed_listener;
+mod mux;
 mod netif;
 mod null;
+mod ping_prober;
+mod quic;
 mod redirect;
 mod reject;
 mod resolve_dest;
 mod rule_dispatcher;
+mod scramble;
 mod shadowsocks;
 mod simple_dispatcher;
 mod socket;
 mod socket_listener;
 mod socks5;
+mod stun_keepalive;
 mod switch;
 mod system_resolver;
 mod tls;
 mod tls_obfs;
+mod tproxy_listener;
 mod trojan;
+mod udp_fallback;
+mod url_test;
 mod vmess;
 mod vpntun;
 mod ws;
 
+pub use alpn_dispatcher::*;
+pub use automation::*;
+pub use bonding::*;
+pub use circuit_breaker::*;
+pub use delay::*;
+pub use dns_hijack::*;
 pub use dns_server::*;
 pub use dyn_outbound::*;
 pub use fakeip::*;
 pub use forward::*;
+pub use guard::*;
+pub use health_endpoint::*;
 pub use host_resolver::*;
 pub use http_obfs::*;
 pub use http_proxy::*;
 pub use ip_stack::*;
 pub use list_dispatcher::ListDispatcherFactory;
+pub use masque_client::*;
+pub use memory_watchdog::*;
+pub use mixed_listener::*;
+pub use mux::*;
 pub use netif::*;
 pub use null::*;
+pub use ping_prober::*;
+pub use quic::*;
 pub use redirect::*;
 pub use reject::*;
 pub use resolve_dest::*;
 pub use rule_dispatcher::RuleDispatcherFactory;
+pub use scramble::*;
 pub use shadowsocks::*;
 pub use simple_dispatcher::*;
 pub use socket::*;
 pub use socket_listener::*;
 pub use socks5::*;
+pub use stun_keepalive::*;
 pub use switch::*;
 pub use system_resolver::*;
 pub use tls::*;
 pub use tls_obfs::*;
+pub use tproxy_listener::*;
 pub use trojan::*;
+pub use url_test::*;
 pub use vmess::*;
 pub use vpntun::*;
 pub use ws::*;
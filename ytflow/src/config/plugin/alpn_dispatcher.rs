@@ -0,0 +1,80 @@
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+
+use crate::config::factory::*;
+use crate::config::*;
+
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+#[derive(Deserialize)]
+pub struct AlpnDispatcherFactory<'a> {
+    #[serde(borrow)]
+    alpn: BTreeMap<&'a str, &'a str>,
+    fallback: &'a str,
+}
+
+impl<'de> AlpnDispatcherFactory<'de> {
+    pub(in super::super) fn parse(plugin: &'de Plugin) -> ConfigResult<ParsedPlugin<'de, Self>> {
+        let Plugin { name, param, .. } = plugin;
+        let config: Self = parse_param(name, param)?;
+        if config.alpn.keys().any(|a| a.is_empty() || a.len() > 255) {
+            return Err(ConfigError::InvalidParam {
+                plugin: name.clone(),
+                field: "alpn",
+            });
+        }
+        Ok(ParsedPlugin {
+            requires: config
+                .alpn
+                .values()
+                .chain(std::iter::once(&config.fallback))
+                .map(|next| Descriptor {
+                    descriptor: *next,
+                    r#type: AccessPointType::STREAM_HANDLER,
+                })
+                .collect(),
+            provides: vec![Descriptor {
+                descriptor: name.to_string() + ".tcp",
+                r#type: AccessPointType::STREAM_HANDLER,
+            }],
+            factory: config,
+            resources: vec![],
+        })
+    }
+}
+
+impl<'de> Factory for AlpnDispatcherFactory<'de> {
+    #[cfg(feature = "plugins")]
+    fn load(&mut self, plugin_name: String, set: &mut PartialPluginSet) -> LoadResult<()> {
+        use crate::plugin::alpn_dispatcher;
+        use crate::plugin::reject::RejectHandler;
+
+        let dispatcher = Arc::new_cyclic(|weak| {
+            set.stream_handlers
+                .insert(plugin_name.clone() + ".tcp", weak.clone() as _);
+            let mut get_next = |descriptor| match set
+                .get_or_create_stream_handler(plugin_name.clone(), descriptor)
+            {
+                Ok(t) => t,
+                Err(e) => {
+                    set.errors.push(e);
+                    Arc::downgrade(&(Arc::new(RejectHandler)))
+                }
+            };
+            let rules = self
+                .alpn
+                .iter()
+                .map(|(alpn, next)| alpn_dispatcher::AlpnRule {
+                    alpn: alpn.to_string(),
+                    next: get_next(*next),
+                })
+                .collect();
+            let fallback = get_next(self.fallback);
+            alpn_dispatcher::AlpnDispatcher { rules, fallback }
+        });
+        set.fully_constructed
+            .stream_handlers
+            .insert(plugin_name + ".tcp", dispatcher);
+        Ok(())
+    }
+}
@@ -0,0 +1,157 @@
+use serde::Deserialize;
+
+use crate::config::factory::*;
+use crate::config::*;
+
+fn default_cooldown() -> u64 {
+    60_000
+}
+
+fn default_body() -> String {
+    "{message}".into()
+}
+
+fn default_content_type() -> String {
+    "text/plain; charset=utf-8".into()
+}
+
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+#[derive(Clone, Deserialize)]
+struct WebhookConfig {
+    url: String,
+    /// Request body, where `{event}` and `{message}` are substituted.
+    #[serde(default = "default_body")]
+    body: String,
+    #[serde(default = "default_content_type")]
+    content_type: String,
+}
+
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+#[derive(Clone, Deserialize)]
+struct CommandConfig {
+    path: String,
+    #[serde(default)]
+    args: Vec<String>,
+}
+
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+#[derive(Clone, Deserialize)]
+struct RuleConfig<'a> {
+    #[serde(borrow)]
+    on: Vec<&'a str>,
+    webhook: Option<WebhookConfig>,
+    command: Option<CommandConfig>,
+    /// Minimum interval between two runs of the rule in milliseconds.
+    #[serde(default = "default_cooldown")]
+    cooldown: u64,
+}
+
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+#[derive(Clone, Deserialize)]
+pub struct AutomationFactory<'a> {
+    #[serde(borrow)]
+    rules: Vec<RuleConfig<'a>>,
+    /// Outbound for webhook requests.
+    tcp_next: &'a str,
+}
+
+impl<'de> AutomationFactory<'de> {
+    pub(in super::super) fn parse(plugin: &'de Plugin) -> ConfigResult<ParsedPlugin<'de, Self>> {
+        let Plugin { name, param, .. } = plugin;
+        let config: Self = parse_param(name, param)?;
+        let invalid = |field| ConfigError::InvalidParam {
+            plugin: name.clone(),
+            field,
+        };
+        for rule in &config.rules {
+            if rule.on.is_empty()
+                || rule
+                    .on
+                    .iter()
+                    .any(|t| !crate::plugin::automation::TRIGGERS.contains(t))
+            {
+                return Err(invalid("rules[].on"));
+            }
+            match (&rule.webhook, &rule.command) {
+                (Some(webhook), None) => {
+                    let url: http::Uri = webhook
+                        .url
+                        .parse()
+                        .map_err(|_| invalid("rules[].webhook.url"))?;
+                    if !matches!(url.scheme_str(), Some("http" | "https")) || url.host().is_none() {
+                        return Err(invalid("rules[].webhook.url"));
+                    }
+                }
+                (None, Some(command)) => {
+                    if command.path.is_empty() {
+                        return Err(invalid("rules[].command.path"));
+                    }
+                }
+                _ => return Err(invalid("rules")),
+            }
+        }
+        Ok(ParsedPlugin {
+            requires: vec![Descriptor {
+                descriptor: config.tcp_next,
+                r#type: AccessPointType::STREAM_OUTBOUND_FACTORY,
+            }],
+            provides: vec![],
+            factory: config,
+            resources: vec![],
+        })
+    }
+}
+
+impl<'de> Factory for AutomationFactory<'de> {
+    #[cfg(feature = "plugins")]
+    fn load(&mut self, plugin_name: String, set: &mut PartialPluginSet) -> LoadResult<()> {
+        use std::time::Duration;
+
+        use crate::plugin::automation;
+        use crate::plugin::null::Null;
+
+        let tcp_next = match set.get_or_create_stream_outbound(plugin_name.clone(), self.tcp_next) {
+            Ok(t) => t,
+            Err(e) => {
+                set.errors.push(e);
+                Arc::downgrade(&(Arc::new(Null) as _))
+            }
+        };
+        let rules = self
+            .rules
+            .iter()
+            .map(|rule| automation::Rule {
+                on: rule.on.iter().map(|t| t.to_string()).collect(),
+                action: match (&rule.webhook, &rule.command) {
+                    (Some(webhook), _) => automation::Action::Webhook {
+                        url: webhook.url.parse().expect("URL has been validated"),
+                        body: webhook.body.clone(),
+                        content_type: webhook.content_type.clone(),
+                    },
+                    (None, Some(command)) => automation::Action::Command {
+                        program: command.path.clone().into(),
+                        args: command.args.clone(),
+                    },
+                    (None, None) => unreachable!("rule has been validated"),
+                },
+                cooldown: Duration::from_millis(rule.cooldown),
+            })
+            .collect();
+        let automation = Arc::new(automation::Automation::new(
+            rules,
+            automation::WebhookClient::new(tcp_next),
+        ));
+        set.control_hub.create_plugin_control(
+            plugin_name,
+            "automation",
+            automation::Responder::new(automation.clone()),
+        );
+        // Subscribe now rather than in the task, so that events published
+        // right after loading, such as `connected`, are not missed.
+        let (_, subscription) = set.control_hub.events().subscribe(u64::MAX);
+        set.fully_constructed
+            .long_running_tasks
+            .push(tokio::spawn(automation.run(subscription)));
+        Ok(())
+    }
+}
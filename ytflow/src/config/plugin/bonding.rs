@@ -0,0 +1,89 @@
+use serde::Deserialize;
+
+use crate::config::factory::*;
+use crate::config::*;
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum BondingModeParam {
+    #[default]
+    Duplicate,
+    Stripe,
+}
+
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+#[derive(Clone, Deserialize)]
+pub struct BondingFactory<'a> {
+    #[serde(default)]
+    mode: BondingModeParam,
+    /// Datagram outbounds over different paths, e.g. sockets bound to
+    /// different network interfaces.
+    nexts: Vec<&'a str>,
+}
+
+impl<'de> BondingFactory<'de> {
+    pub(in super::super) fn parse(plugin: &'de Plugin) -> ConfigResult<ParsedPlugin<'de, Self>> {
+        let Plugin { name, param, .. } = plugin;
+        let config: Self = parse_param(name, param)?;
+        if config.nexts.len() < 2 {
+            return Err(ConfigError::InvalidParam {
+                plugin: name.clone(),
+                field: "nexts",
+            });
+        }
+
+        Ok(ParsedPlugin {
+            requires: config
+                .nexts
+                .iter()
+                .map(|next| Descriptor {
+                    descriptor: *next,
+                    r#type: AccessPointType::DATAGRAM_SESSION_FACTORY,
+                })
+                .collect(),
+            provides: vec![Descriptor {
+                descriptor: name.clone() + ".udp",
+                r#type: AccessPointType::DATAGRAM_SESSION_FACTORY,
+            }],
+            factory: config,
+            resources: vec![],
+        })
+    }
+}
+
+impl<'de> Factory for BondingFactory<'de> {
+    #[cfg(feature = "plugins")]
+    fn load(&mut self, plugin_name: String, set: &mut PartialPluginSet) -> LoadResult<()> {
+        use crate::plugin::bonding;
+        use crate::plugin::null::Null;
+
+        let factory = Arc::new_cyclic(|weak| {
+            set.datagram_outbounds
+                .insert(plugin_name.clone() + ".udp", weak.clone() as _);
+            let nexts = self
+                .nexts
+                .iter()
+                .map(
+                    |next| match set.get_or_create_datagram_outbound(plugin_name.clone(), next) {
+                        Ok(next) => next,
+                        Err(e) => {
+                            set.errors.push(e);
+                            Arc::downgrade(&(Arc::new(Null) as _))
+                        }
+                    },
+                )
+                .collect();
+            bonding::BondingFactory {
+                mode: match self.mode {
+                    BondingModeParam::Duplicate => bonding::BondingMode::Duplicate,
+                    BondingModeParam::Stripe => bonding::BondingMode::Stripe,
+                },
+                nexts,
+            }
+        });
+        set.fully_constructed
+            .datagram_outbounds
+            .insert(plugin_name + ".udp", factory);
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+use serde::Deserialize;
+
+use crate::config::factory::*;
+use crate::config::*;
+
+fn default_failure_threshold() -> u32 {
+    5
+}
+
+fn default_cooldown() -> u64 {
+    30_000
+}
+
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+#[derive(Clone, Deserialize)]
+pub struct CircuitBreakerFactory<'a> {
+    #[serde(default = "default_failure_threshold")]
+    failure_threshold: u32,
+    #[serde(default = "default_cooldown")]
+    cooldown: u64,
+    tcp_next: &'a str,
+    udp_next: &'a str,
+}
+
+impl<'de> CircuitBreakerFactory<'de> {
+    pub(in super::super) fn parse(plugin: &'de Plugin) -> ConfigResult<ParsedPlugin<'de, Self>> {
+        let Plugin { name, param, .. } = plugin;
+        let config: Self = parse_param(name, param)?;
+        if config.failure_threshold == 0 {
+            return Err(ConfigError::InvalidParam {
+                plugin: name.clone(),
+                field: "failure_threshold",
+            });
+        }
+
+        Ok(ParsedPlugin {
+            requires: vec![
+                Descriptor {
+                    descriptor: config.tcp_next,
+                    r#type: AccessPointType::STREAM_OUTBOUND_FACTORY,
+                },
+                Descriptor {
+                    descriptor: config.udp_next,
+                    r#type: AccessPointType::DATAGRAM_SESSION_FACTORY,
+                },
+            ],
+            provides: vec![
+                Descriptor {
+                    descriptor: name.clone() + ".tcp",
+                    r#type: AccessPointType::STREAM_OUTBOUND_FACTORY,
+                },
+                Descriptor {
+                    descriptor: name.clone() + ".udp",
+                    r#type: AccessPointType::DATAGRAM_SESSION_FACTORY,
+                },
+            ],
+            factory: config,
+            resources: vec![],
+        })
+    }
+}
+
+impl<'de> Factory for CircuitBreakerFactory<'de> {
+    #[cfg(feature = "plugins")]
+    fn load(&mut self, plugin_name: String, set: &mut PartialPluginSet) -> LoadResult<()> {
+        use std::time::Duration;
+
+        use crate::plugin::circuit_breaker;
+        use crate::plugin::null::Null;
+
+        let breaker = Arc::new(circuit_breaker::Breaker::new(
+            self.failure_threshold,
+            Duration::from_millis(self.cooldown),
+        ));
+        let tcp_factory = Arc::new_cyclic(|tcp_weak| {
+            set.stream_outbounds
+                .insert(plugin_name.clone() + ".tcp", tcp_weak.clone() as _);
+
+            // Make sure all weak references are inserted into the set before loading any plugins
+            let udp_factory = Arc::new_cyclic(|udp_weak| {
+                set.datagram_outbounds
+                    .insert(plugin_name.clone() + ".udp", udp_weak.clone() as _);
+
+                let next =
+                    match set.get_or_create_datagram_outbound(plugin_name.clone(), self.udp_next) {
+                        Ok(t) => t,
+                        Err(e) => {
+                            set.errors.push(e);
+                            Arc::downgrade(&(Arc::new(Null)))
+                        }
+                    };
+                circuit_breaker::DatagramCircuitBreakerFactory {
+                    breaker: breaker.clone(),
+                    next,
+                }
+            });
+            set.fully_constructed
+                .datagram_outbounds
+                .insert(plugin_name.clone() + ".udp", udp_factory);
+
+            let next = match set.get_or_create_stream_outbound(plugin_name.clone(), self.tcp_next) {
+                Ok(t) => t,
+                Err(e) => {
+                    set.errors.push(e);
+                    Arc::downgrade(&(Arc::new(Null)))
+                }
+            };
+            circuit_breaker::StreamCircuitBreakerFactory {
+                breaker: breaker.clone(),
+                next,
+            }
+        });
+        set.fully_constructed
+            .stream_outbounds
+            .insert(plugin_name.clone() + ".tcp", tcp_factory);
+        set.control_hub.create_plugin_control(
+            plugin_name,
+            "circuit-breaker",
+            circuit_breaker::Responder::new(breaker),
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,110 @@
+use cidr::IpCidr;
+use serde::Deserialize;
+
+use crate::config::factory::*;
+use crate::config::*;
+
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+#[derive(Clone, Deserialize)]
+pub struct DnsHijackFactory<'a> {
+    /// Queries sent to these servers are not intercepted.
+    #[serde(default)]
+    except: Vec<HumanRepr<IpCidr>>,
+    /// DNS over TCP is passed to `tcp_next` untouched if not set.
+    #[serde(default)]
+    dns_tcp: Option<&'a str>,
+    dns_udp: &'a str,
+    tcp_next: &'a str,
+    udp_next: &'a str,
+}
+
+impl<'de> DnsHijackFactory<'de> {
+    pub(in super::super) fn parse(plugin: &'de Plugin) -> ConfigResult<ParsedPlugin<'de, Self>> {
+        let Plugin { name, param, .. } = plugin;
+        let config: Self = parse_param(name, param)?;
+        let mut requires = vec![
+            Descriptor {
+                descriptor: config.dns_udp,
+                r#type: AccessPointType::DATAGRAM_SESSION_HANDLER,
+            },
+            Descriptor {
+                descriptor: config.tcp_next,
+                r#type: AccessPointType::STREAM_HANDLER,
+            },
+            Descriptor {
+                descriptor: config.udp_next,
+                r#type: AccessPointType::DATAGRAM_SESSION_HANDLER,
+            },
+        ];
+        requires.extend(config.dns_tcp.map(|t| Descriptor {
+            descriptor: t,
+            r#type: AccessPointType::STREAM_HANDLER,
+        }));
+        Ok(ParsedPlugin {
+            factory: config,
+            requires,
+            provides: vec![
+                Descriptor {
+                    descriptor: name.to_string() + ".tcp",
+                    r#type: AccessPointType::STREAM_HANDLER,
+                },
+                Descriptor {
+                    descriptor: name.to_string() + ".udp",
+                    r#type: AccessPointType::DATAGRAM_SESSION_HANDLER,
+                },
+            ],
+            resources: vec![],
+        })
+    }
+}
+
+impl<'de> Factory for DnsHijackFactory<'de> {
+    #[cfg(feature = "plugins")]
+    fn load(&mut self, plugin_name: String, set: &mut PartialPluginSet) -> LoadResult<()> {
+        use crate::plugin::dns_hijack::DnsHijack;
+        use crate::plugin::reject::RejectHandler;
+
+        let hijack = Arc::new_cyclic(|weak| {
+            set.stream_handlers
+                .insert(plugin_name.clone() + ".tcp", weak.clone() as _);
+            set.datagram_handlers
+                .insert(plugin_name.clone() + ".udp", weak.clone() as _);
+            let mut get_tcp = |descriptor| match set
+                .get_or_create_stream_handler(plugin_name.clone(), descriptor)
+            {
+                Ok(t) => t,
+                Err(e) => {
+                    set.errors.push(e);
+                    Arc::downgrade(&(Arc::new(RejectHandler) as _))
+                }
+            };
+            let dns_tcp = self.dns_tcp.map(&mut get_tcp);
+            let tcp_next = get_tcp(self.tcp_next);
+            let mut get_udp = |descriptor| match set
+                .get_or_create_datagram_handler(plugin_name.clone(), descriptor)
+            {
+                Ok(u) => u,
+                Err(e) => {
+                    set.errors.push(e);
+                    Arc::downgrade(&(Arc::new(RejectHandler) as _))
+                }
+            };
+            let dns_udp = get_udp(self.dns_udp);
+            let udp_next = get_udp(self.udp_next);
+            DnsHijack {
+                except: self.except.iter().map(|c| c.inner).collect(),
+                dns_tcp,
+                dns_udp,
+                tcp_next,
+                udp_next,
+            }
+        });
+        set.fully_constructed
+            .stream_handlers
+            .insert(plugin_name.clone() + ".tcp", hijack.clone());
+        set.fully_constructed
+            .datagram_handlers
+            .insert(plugin_name + ".udp", hijack);
+        Ok(())
+    }
+}
@@ -6,6 +6,27 @@ use crate::config::factory::*;
 use crate::config::*;
 use crate::data::PluginId;
 
+fn default_query_log_capacity() -> u32 {
+    1000
+}
+
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum QueryLogMode {
+    Full,
+    HashedDomains,
+    AggregateOnly,
+}
+
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+#[derive(Deserialize)]
+struct QueryLogConfig {
+    mode: QueryLogMode,
+    #[serde(default = "default_query_log_capacity")]
+    capacity: u32,
+}
+
 #[cfg_attr(not(feature = "plugins"), allow(dead_code))]
 #[derive(Deserialize)]
 pub struct DnsServerFactory<'a> {
@@ -13,10 +34,17 @@ pub struct DnsServerFactory<'a> {
     concurrency_limit: u32,
     resolver: &'a str,
     ttl: u32,
+    /// Answers are cached for at least this long when set.
+    #[serde(default)]
+    min_ttl: Option<u32>,
+    #[serde(default)]
+    max_ttl: Option<u32>,
     #[serde(borrow)]
     tcp_map_back: HashSet<&'a str>,
     #[serde(borrow)]
     udp_map_back: HashSet<&'a str>,
+    #[serde(default)]
+    query_log: Option<QueryLogConfig>,
     #[serde(skip)]
     plugin_id: Option<PluginId>,
 }
@@ -28,6 +56,12 @@ impl<'de> DnsServerFactory<'de> {
         } = plugin;
         let mut config: Self = parse_param(name, param)?;
         config.plugin_id = *id;
+        if config.min_ttl.unwrap_or(0) > config.max_ttl.unwrap_or(u32::MAX) {
+            return Err(ConfigError::InvalidParam {
+                plugin: name.clone(),
+                field: "min_ttl",
+            });
+        }
         let resolver = config.resolver;
         Ok(ParsedPlugin {
             requires: [Descriptor {
@@ -85,6 +119,14 @@ impl<'de> Factory for DnsServerFactory<'de> {
             Some(db.clone()),
         );
 
+        let query_log = self.query_log.as_ref().map(|c| {
+            let mode = match c.mode {
+                QueryLogMode::Full => dns_server::QueryLogMode::Full,
+                QueryLogMode::HashedDomains => dns_server::QueryLogMode::HashedDomains,
+                QueryLogMode::AggregateOnly => dns_server::QueryLogMode::AggregateOnly,
+            };
+            Arc::new(dns_server::QueryLog::new(mode, c.capacity as usize))
+        });
         let mut err = None;
         let factory = Arc::new_cyclic(|weak| {
             set.datagram_handlers
@@ -95,7 +137,17 @@ impl<'de> Factory for DnsServerFactory<'de> {
                     err = Some(e);
                     Arc::downgrade(&(Arc::new(Null) as _))
                 });
-            dns_server::DnsServer::new(self.concurrency_limit as usize, resolver, self.ttl, cache)
+            dns_server::DnsServer::new(
+                self.concurrency_limit as usize,
+                resolver,
+                self.ttl,
+                dns_server::TtlClamp {
+                    min_ttl: self.min_ttl.unwrap_or(0),
+                    max_ttl: self.max_ttl.unwrap_or(u32::MAX),
+                },
+                cache,
+                query_log.clone(),
+            )
         });
         if let Some(e) = err {
             set.errors.push(e);
@@ -145,6 +197,16 @@ impl<'de> Factory for DnsServerFactory<'de> {
                 .insert(plugin_name.clone() + ".udp_map_back." + next, udp_map_back);
         }
 
+        if let Some(query_log) = query_log {
+            set.control_hub.create_plugin_control(
+                plugin_name.clone(),
+                "dns-server",
+                dns_server::Responder::new(query_log),
+            );
+        }
+        set.control_hub
+            .cache_shrinkers()
+            .register(Arc::downgrade(&factory) as _);
         set.fully_constructed
             .datagram_handlers
             .insert(plugin_name + ".udp", factory.clone());
@@ -76,12 +76,15 @@ impl<'de> Factory for DynOutboundFactory<'de> {
                 plugin: plugin_name.clone(),
             })?
             .clone();
-        let cache = PluginCache::new(
-            self.plugin_id.ok_or_else(|| LoadError::DatabaseRequired {
-                plugin: plugin_name.clone(),
-            })?,
-            Some(db.clone()),
-        );
+        let plugin_id = self.plugin_id.ok_or_else(|| LoadError::DatabaseRequired {
+            plugin: plugin_name.clone(),
+        })?;
+        let cache = PluginCache::new(plugin_id, Some(db.clone()));
+        let profile_id = db
+            .connect()
+            .ok()
+            .and_then(|conn| crate::data::Plugin::query_profile_id(plugin_id, &conn).ok())
+            .flatten();
         let factory = Arc::new_cyclic(|weak| {
             set.stream_outbounds
                 .insert(plugin_name.clone() + ".tcp", weak.clone() as _);
@@ -107,7 +110,15 @@ impl<'de> Factory for DynOutboundFactory<'de> {
             };
 
             // TOO: fixed outbounds
-            dyn_outbound::DynOutbound::new(db, cache.clone(), vec![], tcp_next, udp_next)
+            dyn_outbound::DynOutbound::new(
+                db,
+                cache.clone(),
+                profile_id,
+                vec![],
+                tcp_next,
+                udp_next,
+                set.control_hub.events().clone(),
+            )
         });
 
         // TODO: return errors
@@ -118,6 +129,13 @@ impl<'de> Factory for DynOutboundFactory<'de> {
             "dyn-outbound",
             dyn_outbound::Responder::new(factory.clone()),
         );
+        set.fully_constructed.long_running_tasks.push(tokio::spawn(
+            dyn_outbound::DynOutbound::run_stats_flush(Arc::downgrade(&factory)),
+        ));
+        let (_, subscription) = set.control_hub.events().subscribe(u64::MAX);
+        set.fully_constructed.long_running_tasks.push(tokio::spawn(
+            dyn_outbound::DynOutbound::run_event_listener(Arc::downgrade(&factory), subscription),
+        ));
         set.fully_constructed
             .stream_outbounds
             .insert(plugin_name.clone() + ".tcp", factory.clone());
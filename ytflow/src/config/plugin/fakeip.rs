@@ -55,6 +55,9 @@ impl<'de> Factory for FakeIpFactory<'de> {
             Some(db.clone()),
         );
         let plugin = Arc::new(fakeip::FakeIp::new(self.prefix_v4, self.prefix_v6, cache));
+        set.control_hub
+            .cache_shrinkers()
+            .register(Arc::downgrade(&plugin) as _);
         set.fully_constructed
             .long_running_tasks
             .push(tokio::spawn(fakeip::cache_writer(plugin.clone())));
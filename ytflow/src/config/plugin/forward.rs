@@ -54,6 +54,12 @@ impl<'de> Factory for ForwardFactory<'de> {
         use crate::plugin::null::Null;
 
         let stat = forward::StatHandle::default();
+        let connections = set.control_hub.connections().clone();
+        let name: Arc<str> = plugin_name.as_str().into();
+        // Connections are listed with the plugin providing the outbound.
+        let outbound_name = |descriptor: &str| -> Arc<str> {
+            descriptor.split('.').next().unwrap_or(descriptor).into()
+        };
         let tcp_factory = Arc::new_cyclic(|weak| {
             set.stream_handlers
                 .insert(plugin_name.clone() + ".tcp", weak.clone() as _);
@@ -66,9 +72,12 @@ impl<'de> Factory for ForwardFactory<'de> {
                     }
                 };
             forward::StreamForwardHandler {
+                plugin_name: name.clone(),
                 outbound: tcp_next,
+                outbound_name: outbound_name(self.tcp_next),
                 request_timeout: self.request_timeout,
                 stat: stat.clone(),
+                connections: connections.clone(),
             }
         });
         let udp_factory = Arc::new_cyclic(|weak| {
@@ -83,8 +92,11 @@ impl<'de> Factory for ForwardFactory<'de> {
                     }
                 };
             forward::DatagramForwardHandler {
+                plugin_name: name.clone(),
                 outbound: udp_next,
+                outbound_name: outbound_name(self.udp_next),
                 stat: stat.clone(),
+                connections: connections.clone(),
             }
         });
         set.fully_constructed
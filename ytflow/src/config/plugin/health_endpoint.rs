@@ -0,0 +1,44 @@
+use serde::Deserialize;
+
+use crate::config::factory::*;
+use crate::config::*;
+
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+#[derive(Clone, Deserialize)]
+pub struct HealthEndpointFactory<'a> {
+    /// Names of ping-probers whose failures make the endpoint report not ready.
+    #[serde(borrow, default)]
+    probers: Vec<&'a str>,
+}
+
+impl<'de> HealthEndpointFactory<'de> {
+    pub(in super::super) fn parse(plugin: &'de Plugin) -> ConfigResult<ParsedPlugin<'de, Self>> {
+        let Plugin { name, param, .. } = plugin;
+        let config: Self = parse_param(name, param)?;
+        Ok(ParsedPlugin {
+            factory: config,
+            requires: vec![],
+            provides: vec![Descriptor {
+                descriptor: name.to_string() + ".tcp",
+                r#type: AccessPointType::STREAM_HANDLER,
+            }],
+            resources: vec![],
+        })
+    }
+}
+
+impl<'de> Factory for HealthEndpointFactory<'de> {
+    #[cfg(feature = "plugins")]
+    fn load(&mut self, plugin_name: String, set: &mut PartialPluginSet) -> LoadResult<()> {
+        use crate::plugin::health_endpoint;
+
+        let handler = health_endpoint::HealthEndpointHandler::new(
+            set.control_hub.kill_switch().clone(),
+            self.probers.iter().map(|p| p.to_string()).collect(),
+        );
+        set.fully_constructed
+            .stream_handlers
+            .insert(plugin_name + ".tcp", Arc::new(handler));
+        Ok(())
+    }
+}
@@ -11,12 +11,15 @@ use crate::config::*;
 struct DohSpecConfig<'a> {
     url: &'a str,
     next: &'a str,
+    #[serde(default)]
+    use_get: bool,
 }
 
 #[cfg_attr(not(feature = "plugins"), allow(dead_code))]
 struct DohSpec<'a> {
     url: Uri,
     next: &'a str,
+    use_get: bool,
 }
 
 #[derive(Deserialize)]
@@ -27,6 +30,10 @@ struct HostResolverConfig<'a> {
     udp: Vec<&'a str>,
     #[serde(borrow)]
     tcp: Vec<&'a str>,
+    #[serde(default)]
+    min_ttl: Option<u32>,
+    #[serde(default)]
+    max_ttl: Option<u32>,
 }
 
 #[cfg_attr(not(feature = "plugins"), allow(dead_code))]
@@ -34,12 +41,20 @@ pub struct HostResolverFactory<'a> {
     doh: Vec<DohSpec<'a>>,
     udp: Vec<&'a str>,
     _tcp: Vec<&'a str>,
+    min_ttl: Option<u32>,
+    max_ttl: Option<u32>,
 }
 
 impl<'de> HostResolverFactory<'de> {
     pub(in super::super) fn parse(plugin: &'de Plugin) -> ConfigResult<ParsedPlugin<'de, Self>> {
         let Plugin { name, param, .. } = plugin;
         let config: HostResolverConfig = parse_param(name, param)?;
+        if config.min_ttl.unwrap_or(0) > config.max_ttl.unwrap_or(u32::MAX) {
+            return Err(ConfigError::InvalidParam {
+                plugin: name.clone(),
+                field: "min_ttl",
+            });
+        }
 
         let doh = config
             .doh
@@ -51,7 +66,11 @@ impl<'de> HostResolverFactory<'de> {
                         url.scheme() == Some(&Scheme::HTTPS) || url.scheme() == Some(&Scheme::HTTP)
                     })
                     .filter(|url| url.host().is_some())
-                    .map(|url| DohSpec { url, next: d.next })
+                    .map(|url| DohSpec {
+                        url,
+                        next: d.next,
+                        use_get: d.use_get,
+                    })
             })
             .collect::<Option<Vec<_>>>()
             .ok_or_else(|| ConfigError::InvalidParam {
@@ -80,6 +99,8 @@ impl<'de> HostResolverFactory<'de> {
                 doh,
                 udp: config.udp,
                 _tcp: config.tcp,
+                min_ttl: config.min_ttl,
+                max_ttl: config.max_ttl,
             },
             requires,
             provides: vec![Descriptor {
@@ -94,9 +115,12 @@ impl<'de> HostResolverFactory<'de> {
 impl<'de> Factory for HostResolverFactory<'de> {
     #[cfg(feature = "plugins")]
     fn load(&mut self, plugin_name: String, set: &mut PartialPluginSet) -> LoadResult<()> {
+        use std::time::Duration;
+
         use crate::plugin::host_resolver;
 
         let mut errors = vec![];
+        let mut doh_stats = vec![];
         let factory = Arc::new_cyclic(|weak| {
             set.resolver
                 .insert(plugin_name.to_string() + ".resolver", weak.clone() as _);
@@ -105,19 +129,27 @@ impl<'de> Factory for HostResolverFactory<'de> {
                 .iter()
                 .map(|d| {
                     let next = set.get_or_create_stream_outbound(plugin_name.clone(), d.next);
-                    (d.url.clone(), next)
+                    (d, next)
                 })
-                .filter_map(|(url, next)| match next {
-                    Ok(next) => Some((url, next)),
+                .filter_map(|(d, next)| match next {
+                    Ok(next) => Some((d, next)),
                     Err(e) => {
                         errors.push(e);
                         None
                     }
                 })
-                .map(|(url, next)| {
-                    host_resolver::doh_adapter::DohDatagramAdapterFactory::new(url, next)
+                .map(|(d, next)| {
+                    host_resolver::doh_adapter::DohDatagramAdapterFactory::new(
+                        d.url.clone(),
+                        d.use_get,
+                        next,
+                    )
                 })
                 .collect::<Vec<_>>();
+            doh_stats = doh
+                .iter()
+                .map(|d| (d.url().to_string(), d.stats()))
+                .collect();
             let udp = self
                 .udp
                 .iter()
@@ -129,9 +161,21 @@ impl<'de> Factory for HostResolverFactory<'de> {
                         None
                     }
                 });
-            host_resolver::HostResolver::new(udp, doh)
+            host_resolver::HostResolver::with_ttl_clamp(
+                udp,
+                doh,
+                self.min_ttl.map(|t| Duration::from_secs(t as u64)),
+                self.max_ttl.map(|t| Duration::from_secs(t as u64)),
+            )
         });
         set.errors.extend(errors);
+        if !doh_stats.is_empty() {
+            set.control_hub.create_plugin_control(
+                plugin_name.clone(),
+                "host-resolver",
+                host_resolver::Responder::new(doh_stats),
+            );
+        }
         set.fully_constructed
             .resolver
             .insert(plugin_name + ".resolver", factory);
@@ -1,5 +1,9 @@
+use std::collections::BTreeMap;
+
+use http::HeaderMap;
 use serde::Deserialize;
 
+use super::ws::{parse_headers, RotationConfig};
 use crate::config::factory::*;
 use crate::config::*;
 #[cfg(feature = "plugins")]
@@ -10,11 +14,29 @@ pub struct HttpObfsServerFactory<'a> {
     next: &'a str,
 }
 
-#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
 #[derive(Deserialize)]
-pub struct HttpObfsClientFactory<'a> {
+pub struct HttpObfsClientConfig<'a> {
     host: &'a str,
     path: &'a str,
+    /// Rotated along with `host`.
+    #[serde(borrow, default)]
+    hosts: Vec<&'a str>,
+    /// Rotated along with `path`.
+    #[serde(borrow, default)]
+    paths: Vec<&'a str>,
+    #[serde(default)]
+    rotation: RotationConfig,
+    #[serde(borrow, default)]
+    headers: BTreeMap<&'a str, &'a str>,
+    next: &'a str,
+}
+
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+pub struct HttpObfsClientFactory<'a> {
+    hosts: Vec<&'a str>,
+    paths: Vec<&'a str>,
+    rotation: RotationConfig,
+    headers: HeaderMap,
     next: &'a str,
 }
 
@@ -41,10 +63,24 @@ impl<'de> HttpObfsServerFactory<'de> {
 impl<'de> HttpObfsClientFactory<'de> {
     pub(in super::super) fn parse(plugin: &'de Plugin) -> ConfigResult<ParsedPlugin<'de, Self>> {
         let Plugin { name, param, .. } = plugin;
-        let config: Self = parse_param(name, param)?;
+        let config: HttpObfsClientConfig = parse_param(name, param)?;
         let next = config.next;
+        let (header_host, headers) = parse_headers(name, config.headers)?;
+        if header_host.is_some() {
+            // Use `host` and `hosts` instead
+            return Err(ConfigError::InvalidParam {
+                plugin: name.clone(),
+                field: "headers_header",
+            });
+        }
         Ok(ParsedPlugin {
-            factory: config,
+            factory: HttpObfsClientFactory {
+                hosts: std::iter::once(config.host).chain(config.hosts).collect(),
+                paths: std::iter::once(config.path).chain(config.paths).collect(),
+                rotation: config.rotation,
+                headers,
+                next,
+            },
             requires: vec![Descriptor {
                 descriptor: next,
                 r#type: AccessPointType::STREAM_OUTBOUND_FACTORY,
@@ -99,7 +135,12 @@ impl<'de> Factory for HttpObfsClientFactory<'de> {
                 }
             };
 
-            simple_http::SimpleHttpOutbound::new(self.path.as_bytes(), self.host.as_bytes(), next)
+            simple_http::SimpleHttpOutbound::new(
+                self.rotation.rotate(&self.paths),
+                self.rotation.rotate(&self.hosts),
+                std::mem::take(&mut self.headers),
+                next,
+            )
         });
         set.fully_constructed
             .stream_outbounds
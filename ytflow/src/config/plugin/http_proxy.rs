@@ -3,19 +3,102 @@ use serde_bytes::Bytes;
 
 use crate::config::factory::*;
 use crate::config::*;
+use crate::flow::DEFAULT_HANDSHAKE_TIMEOUT;
+
+fn default_handshake_timeout() -> u64 {
+    DEFAULT_HANDSHAKE_TIMEOUT.as_millis() as u64
+}
 
 #[cfg_attr(not(feature = "plugins"), allow(dead_code))]
 #[derive(Deserialize)]
 pub struct HttpProxyFactory<'a> {
     user: &'a Bytes,
     pass: &'a Bytes,
+    /// In milliseconds.
+    #[serde(default = "default_handshake_timeout")]
+    handshake_timeout: u64,
+    tcp_next: &'a str,
+}
+
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+#[derive(Deserialize)]
+pub struct HttpProxyServerFactory<'a> {
+    #[serde(borrow, default)]
+    user: Option<&'a Bytes>,
+    #[serde(borrow, default)]
+    pass: Option<&'a Bytes>,
     tcp_next: &'a str,
 }
 
+impl<'de> HttpProxyServerFactory<'de> {
+    pub(in super::super) fn parse(plugin: &'de Plugin) -> ConfigResult<ParsedPlugin<'de, Self>> {
+        let Plugin { name, param, .. } = plugin;
+        let config: Self = parse_param(name, param)?;
+        if config.user.is_some() != config.pass.is_some() {
+            return Err(ConfigError::InvalidParam {
+                plugin: name.clone(),
+                field: if config.user.is_some() {
+                    "pass"
+                } else {
+                    "user"
+                },
+            });
+        }
+        let tcp_next = config.tcp_next;
+        Ok(ParsedPlugin {
+            factory: config,
+            requires: vec![Descriptor {
+                descriptor: tcp_next,
+                r#type: AccessPointType::STREAM_HANDLER,
+            }],
+            provides: vec![Descriptor {
+                descriptor: name.to_string() + ".tcp",
+                r#type: AccessPointType::STREAM_HANDLER,
+            }],
+            resources: vec![],
+        })
+    }
+}
+
+impl<'de> Factory for HttpProxyServerFactory<'de> {
+    #[cfg(feature = "plugins")]
+    fn load(&mut self, plugin_name: String, set: &mut PartialPluginSet) -> LoadResult<()> {
+        use crate::plugin::http_proxy;
+        use crate::plugin::reject::RejectHandler;
+
+        let factory = Arc::new_cyclic(|weak| {
+            set.stream_handlers
+                .insert(plugin_name.clone() + ".tcp", weak.clone() as _);
+            let tcp_next =
+                match set.get_or_create_stream_handler(plugin_name.clone(), self.tcp_next) {
+                    Ok(t) => t,
+                    Err(e) => {
+                        set.errors.push(e);
+                        Arc::downgrade(&(Arc::new(RejectHandler) as _))
+                    }
+                };
+            http_proxy::HttpProxyHandler::new(
+                self.user.zip(self.pass).map(|(u, p)| (&**u, &**p)),
+                tcp_next,
+            )
+        });
+        set.fully_constructed
+            .stream_handlers
+            .insert(plugin_name + ".tcp", factory);
+        Ok(())
+    }
+}
+
 impl<'de> HttpProxyFactory<'de> {
     pub(in super::super) fn parse(plugin: &'de Plugin) -> ConfigResult<ParsedPlugin<'de, Self>> {
         let Plugin { name, param, .. } = plugin;
         let config: Self = parse_param(name, param)?;
+        if config.handshake_timeout == 0 {
+            return Err(ConfigError::InvalidParam {
+                plugin: name.clone(),
+                field: "handshake_timeout",
+            });
+        }
         let tcp_next = config.tcp_next;
         Ok(ParsedPlugin {
             factory: config,
@@ -35,6 +118,8 @@ impl<'de> HttpProxyFactory<'de> {
 impl<'de> Factory for HttpProxyFactory<'de> {
     #[cfg(feature = "plugins")]
     fn load(&mut self, plugin_name: String, set: &mut PartialPluginSet) -> LoadResult<()> {
+        use std::time::Duration;
+
         use crate::plugin::http_proxy;
         use crate::plugin::null::Null;
 
@@ -54,6 +139,7 @@ impl<'de> Factory for HttpProxyFactory<'de> {
                     .filter(|(u, p)| !u.is_empty() && !p.is_empty())
                     .map(|(u, p)| (&**u, &**p)),
                 tcp_next,
+                Duration::from_millis(self.handshake_timeout),
             )
         });
         set.fully_constructed
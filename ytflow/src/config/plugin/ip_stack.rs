@@ -1,19 +1,131 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use cidr::{Ipv4Inet, Ipv6Inet};
 use serde::Deserialize;
 
 use crate::config::factory::*;
 use crate::config::*;
 
 #[derive(Clone, Deserialize)]
+#[cfg_attr(not(feature = "tun"), allow(dead_code))]
 pub struct IpStackFactory<'a> {
     tun: &'a str,
     tcp_next: &'a str,
     udp_next: &'a str,
+    #[serde(default)]
+    tcp: IpStackTcpConfig,
+    /// Drop all packets from the TUN while the kill switch is engaged, i.e.
+    /// an outbound health check is failing, the profile is being reloaded or
+    /// it is engaged manually.
+    #[serde(default)]
+    kill_switch: bool,
+    /// Addresses, gateways and MTU of the interface facing the TUN. Those left
+    /// out are taken from the vpn-tun providing the TUN, if any.
+    #[serde(default)]
+    interface: IpStackInterfaceConfig,
+    /// Milliseconds without any datagram after which a UDP session is closed.
+    #[serde(default = "default_udp_session_timeout")]
+    udp_session_timeout: u64,
+}
+
+fn default_udp_session_timeout() -> u64 {
+    120_000
+}
+
+#[derive(Clone, Default, Deserialize)]
+#[serde(default)]
+#[cfg_attr(not(feature = "tun"), allow(dead_code))]
+struct IpStackInterfaceConfig {
+    ipv4: Option<HumanRepr<Ipv4Inet>>,
+    ipv6: Option<HumanRepr<Ipv6Inet>>,
+    /// Defaults to the interface address.
+    ipv4_gateway: Option<HumanRepr<Ipv4Addr>>,
+    /// Defaults to the interface address.
+    ipv6_gateway: Option<HumanRepr<Ipv6Addr>>,
+    mtu: Option<u16>,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default)]
+#[cfg_attr(not(feature = "tun"), allow(dead_code))]
+struct IpStackTcpConfig {
+    timestamps: bool,
+    min_buffer: usize,
+    max_buffer: usize,
+    /// Fixed socket buffer sizes for each direction. The buffer tuner only
+    /// sizes the directions left unset.
+    rx_buffer: Option<usize>,
+    tx_buffer: Option<usize>,
+    max_sockets: usize,
+    /// Congestion controllers to run TCP sockets with. Only congestion
+    /// control differs between them. When more than one is listed,
+    /// connections are spread across them evenly and plugin info reports
+    /// stats of each controller for comparison.
+    congestion_controls: Vec<IpStackTcpCongestion>,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[cfg_attr(not(feature = "tun"), allow(dead_code))]
+enum IpStackTcpCongestion {
+    None,
+    #[cfg(feature = "tcp-congestion-eval")]
+    Reno,
+    #[cfg(feature = "tcp-congestion-eval")]
+    Cubic,
+}
+
+impl Default for IpStackTcpConfig {
+    fn default() -> Self {
+        Self {
+            timestamps: true,
+            min_buffer: 16 * 1024,
+            max_buffer: 1024 * 1024,
+            rx_buffer: None,
+            tx_buffer: None,
+            max_sockets: 1024,
+            congestion_controls: vec![IpStackTcpCongestion::None],
+        }
+    }
 }
 
 impl<'de> IpStackFactory<'de> {
     pub(in super::super) fn parse(plugin: &'de Plugin) -> ConfigResult<ParsedPlugin<'de, Self>> {
         let Plugin { name, param, .. } = plugin;
         let config: Self = parse_param(name, param)?;
+        let IpStackTcpConfig {
+            min_buffer,
+            max_buffer,
+            rx_buffer,
+            tx_buffer,
+            max_sockets,
+            congestion_controls,
+            ..
+        } = &config.tcp;
+        if *min_buffer < 1024
+            || min_buffer > max_buffer
+            || rx_buffer.is_some_and(|b| b < 1024)
+            || tx_buffer.is_some_and(|b| b < 1024)
+            || *max_sockets == 0
+            || congestion_controls.is_empty()
+        {
+            return Err(ConfigError::InvalidParam {
+                plugin: name.clone(),
+                field: "tcp",
+            });
+        }
+        if config.udp_session_timeout == 0 {
+            return Err(ConfigError::InvalidParam {
+                plugin: name.clone(),
+                field: "udp_session_timeout",
+            });
+        }
+        if config.interface.mtu.is_some_and(|m| m < super::MIN_TUN_MTU) {
+            return Err(ConfigError::InvalidParam {
+                plugin: name.clone(),
+                field: "interface.mtu",
+            });
+        }
         Ok(ParsedPlugin {
             factory: config.clone(),
             requires: vec![
@@ -36,9 +148,58 @@ impl<'de> IpStackFactory<'de> {
     }
 }
 
+#[cfg(feature = "tun")]
+impl IpStackInterfaceConfig {
+    fn resolve(&self, tun: super::TunInterface) -> crate::plugin::ip_stack::InterfaceOptions {
+        let default = crate::plugin::ip_stack::InterfaceOptions::default();
+        let ipv4 = self
+            .ipv4
+            .as_ref()
+            .map(|a| a.inner)
+            .or_else(|| {
+                tun.ipv4
+                    .map(|a| Ipv4Inet::new(a, 0).expect("0 is a valid IPv4 prefix length"))
+            })
+            .unwrap_or(default.ipv4);
+        let ipv6 = self
+            .ipv6
+            .as_ref()
+            .map(|a| a.inner)
+            .or_else(|| {
+                tun.ipv6
+                    .map(|a| Ipv6Inet::new(a, 0).expect("0 is a valid IPv6 prefix length"))
+            })
+            .unwrap_or(default.ipv6);
+        crate::plugin::ip_stack::InterfaceOptions {
+            ipv4,
+            ipv6,
+            ipv4_gateway: self
+                .ipv4_gateway
+                .as_ref()
+                .map_or(ipv4.address(), |a| a.inner),
+            ipv6_gateway: self
+                .ipv6_gateway
+                .as_ref()
+                .map_or(ipv6.address(), |a| a.inner),
+            mtu: self.mtu.or(tun.mtu).map_or(default.mtu, usize::from),
+        }
+    }
+}
+
 impl<'de> Factory for IpStackFactory<'de> {
-    #[cfg(feature = "plugins")]
+    // Builds for routers may leave out the userspace network stack and only run listeners.
+    #[cfg(all(feature = "plugins", not(feature = "tun")))]
+    fn load(&mut self, plugin_name: String, _set: &mut PartialPluginSet) -> LoadResult<()> {
+        Err(LoadError::NotBuiltIn {
+            plugin: plugin_name,
+            r#type: "ip-stack",
+            feature: "tun",
+        })
+    }
+    #[cfg(feature = "tun")]
     fn load(&mut self, plugin_name: String, set: &mut PartialPluginSet) -> LoadResult<()> {
+        use std::time::Duration;
+
         use crate::plugin::ip_stack;
         use crate::plugin::reject::RejectHandler;
 
@@ -64,9 +225,56 @@ impl<'de> Factory for IpStackFactory<'de> {
                 })
             }
         };
-        set.fully_constructed
-            .long_running_tasks
-            .push(ip_stack::run(tun, tcp_next, udp_next));
+        let interface = self.interface.resolve(
+            set.tun_interfaces
+                .get(self.tun)
+                .copied()
+                .unwrap_or_default(),
+        );
+        let kill_switch = self
+            .kill_switch
+            .then(|| set.control_hub.kill_switch().clone());
+        let tcp_congestions: Vec<_> = self
+            .tcp
+            .congestion_controls
+            .iter()
+            .map(|congestion| {
+                let congestion = match congestion {
+                    IpStackTcpCongestion::None => ip_stack::TcpCongestion::None,
+                    #[cfg(feature = "tcp-congestion-eval")]
+                    IpStackTcpCongestion::Reno => ip_stack::TcpCongestion::Reno,
+                    #[cfg(feature = "tcp-congestion-eval")]
+                    IpStackTcpCongestion::Cubic => ip_stack::TcpCongestion::Cubic,
+                };
+                (
+                    congestion,
+                    Arc::new(ip_stack::TcpCongestionStats::default()),
+                )
+            })
+            .collect();
+        set.control_hub.create_plugin_control(
+            plugin_name.clone(),
+            "ip-stack",
+            ip_stack::Responder::new(kill_switch.clone(), tcp_congestions.clone()),
+        );
+        set.fully_constructed.long_running_tasks.push(ip_stack::run(
+            plugin_name.as_str().into(),
+            tun,
+            tcp_next,
+            udp_next,
+            interface,
+            ip_stack::TcpOptions {
+                timestamps: self.tcp.timestamps,
+                min_buffer: self.tcp.min_buffer,
+                max_buffer: self.tcp.max_buffer,
+                rx_buffer: self.tcp.rx_buffer,
+                tx_buffer: self.tcp.tx_buffer,
+                max_sockets: self.tcp.max_sockets,
+            },
+            tcp_congestions,
+            Duration::from_millis(self.udp_session_timeout),
+            kill_switch,
+        ));
         Ok(())
     }
 }
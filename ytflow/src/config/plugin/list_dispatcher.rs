@@ -26,7 +26,7 @@ impl<'de> ListDispatcherFactory<'de> {
         let Plugin { name, param, .. } = plugin;
         let config: ListDispatcherConfig = parse_param(name, param)?;
 
-        if let ResourceSource::Literal { format, .. } = config.source {
+        if let Some(format) = config.source.literal_format() {
             if LIST_DISPATCHER_ALLOWED_RESOURCE_TYPES
                 .iter()
                 .all(|&t| format != t)
@@ -38,6 +38,9 @@ impl<'de> ListDispatcherFactory<'de> {
             }
         }
 
+        config.action.validate(name, "action")?;
+        config.fallback.validate(name, "fallback")?;
+
         let requires: Vec<_> = config
             .resolver
             .iter()
@@ -54,7 +57,7 @@ impl<'de> ListDispatcherFactory<'de> {
                     key,
                     allowed_types: &LIST_DISPATCHER_ALLOWED_RESOURCE_TYPES,
                 }),
-                ResourceSource::Literal { .. } => None,
+                ResourceSource::Literal { .. } | ResourceSource::CompressedLiteral { .. } => None,
             }
             .into_iter()
             .collect(),
@@ -87,6 +90,7 @@ fn load_rule_set(
 ) -> rd::RuleSet {
     use crate::resource::ResourceError;
 
+    let source = source.decompress(plugin_name, set);
     let resource_key;
     let resource_type;
     match source {
@@ -155,6 +159,9 @@ fn load_rule_set(
             }
             // TODO: process text based rule literals here
         }
+        ResourceSource::CompressedLiteral { .. } => {
+            unreachable!("compressed literals should have been decompressed")
+        }
     }
     set.errors.push(LoadError::ResourceTypeMismatch {
         plugin: plugin_name.into(),
@@ -0,0 +1,164 @@
+use serde::Deserialize;
+use serde_bytes::Bytes;
+
+use super::tls::{parse_trust, TrustStore};
+use crate::config::factory::*;
+use crate::config::*;
+use crate::flow::DEFAULT_HANDSHAKE_TIMEOUT;
+
+fn default_udp_path() -> &'static str {
+    "/.well-known/masque/udp/{target_host}/{target_port}/"
+}
+
+fn default_handshake_timeout() -> u64 {
+    DEFAULT_HANDSHAKE_TIMEOUT.as_millis() as u64
+}
+
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+#[derive(Deserialize)]
+pub struct MasqueClientFactory<'a> {
+    host: &'a str,
+    #[serde(default = "default_udp_path")]
+    udp_path: &'a str,
+    user: &'a Bytes,
+    pass: &'a Bytes,
+    next: &'a str,
+    /// Datagram session factory to reach the proxy over QUIC. When present,
+    /// UDP is proxied over HTTP/3 instead of `next`.
+    quic_next: Option<&'a str>,
+    sni: Option<&'a str>,
+    #[serde(default)]
+    skip_cert_check: bool,
+    #[serde(default)]
+    trust: TrustStore,
+    /// Key of a `ca-bundle` resource, required by the `custom` trust store.
+    ca: Option<&'a str>,
+    /// QUIC handshake timeout in milliseconds.
+    #[serde(default = "default_handshake_timeout")]
+    handshake_timeout: u64,
+}
+
+impl<'de> MasqueClientFactory<'de> {
+    pub(in super::super) fn parse(plugin: &'de Plugin) -> ConfigResult<ParsedPlugin<'de, Self>> {
+        let Plugin { name, param, .. } = plugin;
+        let config: Self = parse_param(name, param)?;
+        if http::uri::Authority::try_from(config.host).is_err() {
+            return Err(ConfigError::InvalidParam {
+                plugin: name.clone(),
+                field: "host",
+            });
+        }
+        if !config.udp_path.starts_with('/')
+            || !config.udp_path.contains("{target_host}")
+            || !config.udp_path.contains("{target_port}")
+        {
+            return Err(ConfigError::InvalidParam {
+                plugin: name.clone(),
+                field: "udp_path",
+            });
+        }
+        if config.handshake_timeout == 0 {
+            return Err(ConfigError::InvalidParam {
+                plugin: name.clone(),
+                field: "handshake_timeout",
+            });
+        }
+        let resources = parse_trust(name, config.trust, config.ca)?;
+        let mut requires = vec![Descriptor {
+            descriptor: config.next,
+            r#type: AccessPointType::STREAM_OUTBOUND_FACTORY,
+        }];
+        if let Some(quic_next) = config.quic_next {
+            requires.push(Descriptor {
+                descriptor: quic_next,
+                r#type: AccessPointType::DATAGRAM_SESSION_FACTORY,
+            });
+        }
+        Ok(ParsedPlugin {
+            factory: config,
+            requires,
+            provides: vec![
+                Descriptor {
+                    descriptor: name.to_string() + ".tcp",
+                    r#type: AccessPointType::STREAM_OUTBOUND_FACTORY,
+                },
+                Descriptor {
+                    descriptor: name.to_string() + ".udp",
+                    r#type: AccessPointType::DATAGRAM_SESSION_FACTORY,
+                },
+            ],
+            resources,
+        })
+    }
+}
+
+impl<'de> Factory for MasqueClientFactory<'de> {
+    #[cfg(feature = "plugins")]
+    fn load(&mut self, plugin_name: String, set: &mut PartialPluginSet) -> LoadResult<()> {
+        use std::time::Duration;
+
+        use super::tls::load_trust;
+        use crate::plugin::masque;
+        use crate::plugin::null::Null;
+        use crate::plugin::quic;
+
+        let h3_config = self.quic_next.map(|_| {
+            let trust = load_trust(self.trust, self.ca, &plugin_name, set);
+            quic::build_client_config(
+                vec![b"h3".to_vec()],
+                self.skip_cert_check,
+                &trust,
+                quic::CongestionController::default(),
+                false,
+            )
+        });
+        let factory = Arc::new_cyclic(|weak| {
+            set.stream_outbounds
+                .insert(plugin_name.clone() + ".tcp", weak.clone() as _);
+            set.datagram_outbounds
+                .insert(plugin_name.clone() + ".udp", weak.clone() as _);
+            let next = match set.get_or_create_stream_outbound(plugin_name.clone(), self.next) {
+                Ok(next) => next,
+                Err(e) => {
+                    set.errors.push(e);
+                    Arc::downgrade(&(Arc::new(Null) as _))
+                }
+            };
+            let h3 = self
+                .quic_next
+                .zip(h3_config)
+                .map(|(quic_next, client_config)| {
+                    let next =
+                        match set.get_or_create_datagram_outbound(plugin_name.clone(), quic_next) {
+                            Ok(next) => next,
+                            Err(e) => {
+                                set.errors.push(e);
+                                Arc::downgrade(&(Arc::new(Null) as _))
+                            }
+                        };
+                    masque::MasqueH3 {
+                        client_config,
+                        sni: self.sni.map(|s| s.to_string()),
+                        handshake_timeout: Duration::from_millis(self.handshake_timeout),
+                        next,
+                    }
+                });
+            masque::MasqueClient::new(
+                self.host.to_string(),
+                self.udp_path.to_string(),
+                Some((self.user, self.pass))
+                    .filter(|(u, p)| !u.is_empty() && !p.is_empty())
+                    .map(|(u, p)| (&**u, &**p)),
+                next,
+                h3,
+            )
+        });
+        set.fully_constructed
+            .stream_outbounds
+            .insert(plugin_name.clone() + ".tcp", factory.clone());
+        set.fully_constructed
+            .datagram_outbounds
+            .insert(plugin_name + ".udp", factory);
+        Ok(())
+    }
+}
@@ -0,0 +1,84 @@
+use serde::Deserialize;
+
+use crate::config::factory::*;
+use crate::config::*;
+
+fn default_limit_percent() -> u8 {
+    80
+}
+
+fn default_interval() -> u64 {
+    5_000
+}
+
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+#[derive(Clone, Deserialize)]
+pub struct MemoryWatchdogFactory {
+    /// Memory usage in bytes above which caches are shrunk.
+    threshold: Option<u64>,
+    /// Used instead of `threshold` when it is absent: the percentage of the
+    /// memory limit imposed by the platform above which caches are shrunk.
+    #[serde(default = "default_limit_percent")]
+    limit_percent: u8,
+    /// Interval between memory usage samples in milliseconds.
+    #[serde(default = "default_interval")]
+    interval: u64,
+}
+
+impl MemoryWatchdogFactory {
+    pub(in super::super) fn parse(plugin: &Plugin) -> ConfigResult<ParsedPlugin<'static, Self>> {
+        let Plugin { name, param, .. } = plugin;
+        let config: Self = parse_param(name, param)?;
+        if config.threshold == Some(0) {
+            return Err(ConfigError::InvalidParam {
+                plugin: name.clone(),
+                field: "threshold",
+            });
+        }
+        if config.limit_percent == 0 || config.limit_percent > 100 {
+            return Err(ConfigError::InvalidParam {
+                plugin: name.clone(),
+                field: "limit_percent",
+            });
+        }
+        if config.interval == 0 {
+            return Err(ConfigError::InvalidParam {
+                plugin: name.clone(),
+                field: "interval",
+            });
+        }
+        Ok(ParsedPlugin {
+            requires: vec![],
+            provides: vec![],
+            factory: config,
+            resources: vec![],
+        })
+    }
+}
+
+impl Factory for MemoryWatchdogFactory {
+    #[cfg(feature = "plugins")]
+    fn load(&mut self, plugin_name: String, set: &mut PartialPluginSet) -> LoadResult<()> {
+        use std::time::Duration;
+
+        use crate::plugin::memory_watchdog;
+
+        let watchdog = Arc::new(memory_watchdog::MemoryWatchdog::new(
+            plugin_name.clone(),
+            self.threshold,
+            self.limit_percent,
+            Duration::from_millis(self.interval),
+            set.control_hub.cache_shrinkers().clone(),
+            set.control_hub.events().clone(),
+        ));
+        set.control_hub.create_plugin_control(
+            plugin_name,
+            "memory-watchdog",
+            memory_watchdog::Responder::new(watchdog.clone()),
+        );
+        set.fully_constructed
+            .long_running_tasks
+            .push(tokio::spawn(async move { watchdog.run().await }));
+        Ok(())
+    }
+}
@@ -0,0 +1,73 @@
+use serde::Deserialize;
+
+use crate::config::factory::*;
+use crate::config::*;
+
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+#[derive(Deserialize)]
+pub struct MixedListenerFactory<'a> {
+    socks5_next: Option<&'a str>,
+    http_next: Option<&'a str>,
+    tls_next: Option<&'a str>,
+    fallback: &'a str,
+}
+
+impl<'de> MixedListenerFactory<'de> {
+    pub(in super::super) fn parse(plugin: &'de Plugin) -> ConfigResult<ParsedPlugin<'de, Self>> {
+        let Plugin { name, param, .. } = plugin;
+        let config: Self = parse_param(name, param)?;
+        Ok(ParsedPlugin {
+            requires: [
+                config.socks5_next,
+                config.http_next,
+                config.tls_next,
+                Some(config.fallback),
+            ]
+            .into_iter()
+            .flatten()
+            .map(|next| Descriptor {
+                descriptor: next,
+                r#type: AccessPointType::STREAM_HANDLER,
+            })
+            .collect(),
+            provides: vec![Descriptor {
+                descriptor: name.to_string() + ".tcp",
+                r#type: AccessPointType::STREAM_HANDLER,
+            }],
+            factory: config,
+            resources: vec![],
+        })
+    }
+}
+
+impl<'de> Factory for MixedListenerFactory<'de> {
+    #[cfg(feature = "plugins")]
+    fn load(&mut self, plugin_name: String, set: &mut PartialPluginSet) -> LoadResult<()> {
+        use crate::plugin::mixed_listener;
+        use crate::plugin::reject::RejectHandler;
+
+        let listener = Arc::new_cyclic(|weak| {
+            set.stream_handlers
+                .insert(plugin_name.clone() + ".tcp", weak.clone() as _);
+            let mut get_next = |descriptor| match set
+                .get_or_create_stream_handler(plugin_name.clone(), descriptor)
+            {
+                Ok(t) => t,
+                Err(e) => {
+                    set.errors.push(e);
+                    Arc::downgrade(&(Arc::new(RejectHandler)))
+                }
+            };
+            mixed_listener::MixedListener {
+                socks5_next: self.socks5_next.map(&mut get_next),
+                http_next: self.http_next.map(&mut get_next),
+                tls_next: self.tls_next.map(&mut get_next),
+                fallback: get_next(self.fallback),
+            }
+        });
+        set.fully_constructed
+            .stream_handlers
+            .insert(plugin_name + ".tcp", listener);
+        Ok(())
+    }
+}
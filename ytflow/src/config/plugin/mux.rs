@@ -0,0 +1,110 @@
+use serde::Deserialize;
+
+use crate::config::factory::*;
+use crate::config::*;
+
+fn default_max_connections() -> u32 {
+    4
+}
+
+fn default_max_streams() -> u32 {
+    8
+}
+
+fn default_idle_timeout() -> u64 {
+    60_000
+}
+
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum MuxProtocol {
+    #[default]
+    Smux,
+    Yamux,
+}
+
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+#[derive(Deserialize)]
+pub struct MuxClientFactory<'a> {
+    #[serde(default)]
+    protocol: MuxProtocol,
+    #[serde(default = "default_max_connections")]
+    max_connections: u32,
+    /// Streams per connection before another connection is opened.
+    #[serde(default = "default_max_streams")]
+    max_streams: u32,
+    /// In milliseconds.
+    #[serde(default = "default_idle_timeout")]
+    idle_timeout: u64,
+    next: &'a str,
+}
+
+impl<'de> MuxClientFactory<'de> {
+    pub(in super::super) fn parse(plugin: &'de Plugin) -> ConfigResult<ParsedPlugin<'de, Self>> {
+        let Plugin { name, param, .. } = plugin;
+        let config: Self = parse_param(name, param)?;
+        for (field, value) in [
+            ("max_connections", config.max_connections as u64),
+            ("max_streams", config.max_streams as u64),
+            ("idle_timeout", config.idle_timeout),
+        ] {
+            if value == 0 {
+                return Err(ConfigError::InvalidParam {
+                    plugin: name.clone(),
+                    field,
+                });
+            }
+        }
+        let next = config.next;
+        Ok(ParsedPlugin {
+            factory: config,
+            requires: vec![Descriptor {
+                descriptor: next,
+                r#type: AccessPointType::STREAM_OUTBOUND_FACTORY,
+            }],
+            provides: vec![Descriptor {
+                descriptor: name.to_string() + ".tcp",
+                r#type: AccessPointType::STREAM_OUTBOUND_FACTORY,
+            }],
+            resources: vec![],
+        })
+    }
+}
+
+impl<'de> Factory for MuxClientFactory<'de> {
+    #[cfg(feature = "plugins")]
+    fn load(&mut self, plugin_name: String, set: &mut PartialPluginSet) -> LoadResult<()> {
+        use std::time::Duration;
+
+        use crate::plugin::mux;
+        use crate::plugin::null::Null;
+
+        let protocol = match self.protocol {
+            MuxProtocol::Smux => mux::MuxProtocol::Smux,
+            MuxProtocol::Yamux => mux::MuxProtocol::Yamux,
+        };
+        let factory = Arc::new_cyclic(|weak| {
+            set.stream_outbounds
+                .insert(plugin_name.clone() + ".tcp", weak.clone() as _);
+            let next = match set.get_or_create_stream_outbound(plugin_name.clone(), self.next) {
+                Ok(next) => next,
+                Err(e) => {
+                    set.errors.push(e);
+                    Arc::downgrade(&(Arc::new(Null)))
+                }
+            };
+            mux::MuxOutboundFactory::new(
+                protocol,
+                self.max_connections as usize,
+                self.max_streams as usize,
+                Duration::from_millis(self.idle_timeout),
+                next,
+            )
+        });
+        set.fully_constructed
+            .stream_outbounds
+            .insert(plugin_name + ".tcp", factory);
+        Ok(())
+    }
+}
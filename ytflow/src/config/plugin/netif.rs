@@ -10,6 +10,10 @@ pub struct NetifFactory<'a> {
     #[serde(flatten)]
     pub selection: netif::SelectionMode,
     pub outbound_resolver: Option<&'a str>,
+    /// Names of probers checking the preferred netif of failover mode, such as
+    /// ping-probers bound to it. Traffic fails over while any of them is down.
+    #[serde(borrow, default, skip_serializing_if = "Vec::is_empty")]
+    pub probers: Vec<&'a str>,
 }
 
 impl<'de> NetifFactory<'de> {
@@ -46,13 +50,27 @@ impl<'de> NetifFactory<'de> {
 }
 
 impl<'a> Factory for NetifFactory<'a> {
-    #[cfg(feature = "plugins")]
+    #[cfg(all(feature = "plugins", not(feature = "netif")))]
+    fn load(&mut self, plugin_name: String, _set: &mut PartialPluginSet) -> LoadResult<()> {
+        Err(LoadError::NotBuiltIn {
+            plugin: plugin_name,
+            r#type: "netif",
+            feature: "netif",
+        })
+    }
+    #[cfg(feature = "netif")]
     fn load(&mut self, plugin_name: String, set: &mut PartialPluginSet) -> LoadResult<()> {
         use crate::plugin::null::Null;
 
         let mut err = None;
-        let netif =
-            netif::NetifSelector::new(self.selection.clone(), self.family_preference, |weak| {
+        let netif = netif::NetifSelector::new(
+            self.selection.clone(),
+            self.family_preference,
+            self.probers.iter().map(|p| p.to_string()).collect(),
+            set.control_hub.kill_switch().clone(),
+            plugin_name.clone(),
+            set.control_hub.events().clone(),
+            |weak| {
                 set.stream_outbounds
                     .insert(plugin_name.clone() + ".tcp", weak.clone());
                 set.datagram_outbounds
@@ -67,7 +85,8 @@ impl<'a> Factory for NetifFactory<'a> {
                             Arc::downgrade(&(Arc::new(Null) as _))
                         })
                 })
-            });
+            },
+        );
         if let Some(err) = err {
             set.errors.push(err);
         }
@@ -0,0 +1,163 @@
+use std::net::SocketAddr;
+
+use serde::Deserialize;
+
+use crate::config::factory::*;
+use crate::config::*;
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum ProbeModeParam {
+    #[default]
+    Auto,
+    Icmp,
+    Tcp,
+}
+
+fn default_interval() -> u64 {
+    30_000
+}
+
+fn default_timeout() -> u64 {
+    3_000
+}
+
+fn default_failure_threshold() -> u32 {
+    3
+}
+
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+#[derive(Clone, Deserialize)]
+pub struct PingProberFactory<'a> {
+    targets: Vec<SocketAddr>,
+    #[serde(default)]
+    method: ProbeModeParam,
+    /// Interval between probing rounds in milliseconds.
+    #[serde(default = "default_interval")]
+    interval: u64,
+    /// Timeout of a single probe in milliseconds.
+    #[serde(default = "default_timeout")]
+    timeout: u64,
+    #[serde(default = "default_failure_threshold")]
+    failure_threshold: u32,
+    /// Name of the network interface ICMP probes are sent through.
+    bind_device: Option<&'a str>,
+    tcp_next: &'a str,
+    udp_next: &'a str,
+}
+
+impl<'de> PingProberFactory<'de> {
+    pub(in super::super) fn parse(plugin: &'de Plugin) -> ConfigResult<ParsedPlugin<'de, Self>> {
+        let Plugin { name, param, .. } = plugin;
+        let config: Self = parse_param(name, param)?;
+        if config.targets.is_empty() {
+            return Err(ConfigError::InvalidParam {
+                plugin: name.clone(),
+                field: "targets",
+            });
+        }
+        if config.interval == 0 {
+            return Err(ConfigError::InvalidParam {
+                plugin: name.clone(),
+                field: "interval",
+            });
+        }
+        if config.failure_threshold == 0 {
+            return Err(ConfigError::InvalidParam {
+                plugin: name.clone(),
+                field: "failure_threshold",
+            });
+        }
+
+        Ok(ParsedPlugin {
+            requires: vec![
+                Descriptor {
+                    descriptor: config.tcp_next,
+                    r#type: AccessPointType::STREAM_OUTBOUND_FACTORY,
+                },
+                Descriptor {
+                    descriptor: config.udp_next,
+                    r#type: AccessPointType::DATAGRAM_SESSION_FACTORY,
+                },
+            ],
+            provides: vec![
+                Descriptor {
+                    descriptor: name.clone() + ".tcp",
+                    r#type: AccessPointType::STREAM_OUTBOUND_FACTORY,
+                },
+                Descriptor {
+                    descriptor: name.clone() + ".udp",
+                    r#type: AccessPointType::DATAGRAM_SESSION_FACTORY,
+                },
+            ],
+            factory: config,
+            resources: vec![],
+        })
+    }
+}
+
+impl<'de> Factory for PingProberFactory<'de> {
+    #[cfg(feature = "plugins")]
+    fn load(&mut self, plugin_name: String, set: &mut PartialPluginSet) -> LoadResult<()> {
+        use std::time::Duration;
+
+        use crate::plugin::null::Null;
+        use crate::plugin::ping_prober;
+
+        let tcp_next = match set.get_or_create_stream_outbound(plugin_name.clone(), self.tcp_next) {
+            Ok(t) => t,
+            Err(e) => {
+                set.errors.push(e);
+                Arc::downgrade(&(Arc::new(Null) as _))
+            }
+        };
+        let prober = Arc::new(ping_prober::Prober::new(
+            plugin_name.clone(),
+            self.targets.clone(),
+            match self.method {
+                ProbeModeParam::Auto => ping_prober::ProbeMode::Auto,
+                ProbeModeParam::Icmp => ping_prober::ProbeMode::Icmp,
+                ProbeModeParam::Tcp => ping_prober::ProbeMode::Tcp,
+            },
+            Duration::from_millis(self.interval),
+            Duration::from_millis(self.timeout),
+            self.failure_threshold,
+            self.bind_device.map(|s| s.to_string()),
+            tcp_next.clone(),
+            set.control_hub.kill_switch().clone(),
+            set.control_hub.events().clone(),
+        ));
+
+        let udp_next = match set.get_or_create_datagram_outbound(plugin_name.clone(), self.udp_next)
+        {
+            Ok(u) => u,
+            Err(e) => {
+                set.errors.push(e);
+                Arc::downgrade(&(Arc::new(Null) as _))
+            }
+        };
+        set.fully_constructed.stream_outbounds.insert(
+            plugin_name.clone() + ".tcp",
+            Arc::new(ping_prober::StreamGateFactory {
+                prober: prober.clone(),
+                next: tcp_next,
+            }),
+        );
+        set.fully_constructed.datagram_outbounds.insert(
+            plugin_name.clone() + ".udp",
+            Arc::new(ping_prober::DatagramGateFactory {
+                prober: prober.clone(),
+                next: udp_next,
+            }),
+        );
+        set.control_hub.create_plugin_control(
+            plugin_name,
+            "ping-prober",
+            ping_prober::Responder::new(prober.clone()),
+        );
+        set.fully_constructed
+            .long_running_tasks
+            .push(tokio::spawn(async move { prober.run().await }));
+        Ok(())
+    }
+}
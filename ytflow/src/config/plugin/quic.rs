@@ -0,0 +1,150 @@
+use serde::Deserialize;
+
+use super::tls::{parse_trust, TrustStore};
+use crate::config::factory::*;
+use crate::config::*;
+use crate::flow::DEFAULT_HANDSHAKE_TIMEOUT;
+
+fn default_handshake_timeout() -> u64 {
+    DEFAULT_HANDSHAKE_TIMEOUT.as_millis() as u64
+}
+
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum CongestionController {
+    #[default]
+    Cubic,
+    NewReno,
+    Bbr,
+}
+
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+#[derive(Deserialize)]
+pub struct QuicClientFactory<'a> {
+    sni: Option<&'a str>,
+    #[serde(borrow, default)]
+    alpn: Vec<&'a str>,
+    #[serde(default)]
+    skip_cert_check: bool,
+    #[serde(default)]
+    trust: TrustStore,
+    /// Key of a `ca-bundle` resource, required by the `custom` trust store.
+    ca: Option<&'a str>,
+    #[serde(default)]
+    congestion_controller: CongestionController,
+    #[serde(default)]
+    zero_rtt: bool,
+    /// In milliseconds.
+    #[serde(default = "default_handshake_timeout")]
+    handshake_timeout: u64,
+    next: &'a str,
+}
+
+impl<'de> QuicClientFactory<'de> {
+    pub(in super::super) fn parse(plugin: &'de Plugin) -> ConfigResult<ParsedPlugin<'de, Self>> {
+        let Plugin { name, param, .. } = plugin;
+        let config: Self = parse_param(name, param)?;
+        if config.handshake_timeout == 0 {
+            return Err(ConfigError::InvalidParam {
+                plugin: name.clone(),
+                field: "handshake_timeout",
+            });
+        }
+        let resources = parse_trust(name, config.trust, config.ca)?;
+        let next = config.next;
+        Ok(ParsedPlugin {
+            factory: config,
+            requires: vec![Descriptor {
+                descriptor: next,
+                r#type: AccessPointType::DATAGRAM_SESSION_FACTORY,
+            }],
+            provides: vec![Descriptor {
+                descriptor: name.to_string() + ".tcp",
+                r#type: AccessPointType::STREAM_OUTBOUND_FACTORY,
+            }],
+            resources,
+        })
+    }
+}
+
+impl<'de> Factory for QuicClientFactory<'de> {
+    #[cfg(feature = "plugins")]
+    fn load(&mut self, plugin_name: String, set: &mut PartialPluginSet) -> LoadResult<()> {
+        use std::time::Duration;
+
+        use super::tls::load_trust;
+        use crate::plugin::null::Null;
+        use crate::plugin::quic;
+
+        let congestion_controller = match self.congestion_controller {
+            CongestionController::Cubic => quic::CongestionController::Cubic,
+            CongestionController::NewReno => quic::CongestionController::NewReno,
+            CongestionController::Bbr => quic::CongestionController::Bbr,
+        };
+        let trust = load_trust(self.trust, self.ca, &plugin_name, set);
+        let factory = Arc::new_cyclic(|weak| {
+            set.stream_outbounds
+                .insert(plugin_name.clone() + ".tcp", weak.clone() as _);
+            let next = match set.get_or_create_datagram_outbound(plugin_name.clone(), self.next) {
+                Ok(next) => next,
+                Err(e) => {
+                    set.errors.push(e);
+                    Arc::downgrade(&(Arc::new(Null)))
+                }
+            };
+            quic::QuicOutboundFactory::new(
+                self.sni.map(|s| s.to_string()),
+                self.alpn.iter().map(|a| a.as_bytes().to_vec()).collect(),
+                self.skip_cert_check,
+                &trust,
+                congestion_controller,
+                self.zero_rtt,
+                Duration::from_millis(self.handshake_timeout),
+                next,
+            )
+        });
+        set.fully_constructed
+            .stream_outbounds
+            .insert(plugin_name + ".tcp", factory);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+
+    fn parse(param: &[(&str, &str)]) -> ConfigResult<Vec<String>> {
+        let param: BTreeMap<_, _> = param.iter().copied().collect();
+        let plugin = Plugin {
+            id: None,
+            name: "q".into(),
+            plugin: "quic-client".into(),
+            plugin_version: 0,
+            param: cbor4ii::serde::to_vec(vec![], &param).unwrap(),
+        };
+        QuicClientFactory::parse(&plugin)
+            .map(|p| p.resources.iter().map(|r| r.key.to_owned()).collect())
+    }
+
+    #[test]
+    fn test_parse_trust() {
+        assert!(parse(&[("next", "udp")]).unwrap().is_empty());
+        assert_eq!(
+            parse(&[("next", "udp"), ("trust", "custom"), ("ca", "corp")]).unwrap(),
+            ["corp"]
+        );
+        for invalid in [
+            &[("next", "udp"), ("trust", "custom")][..],
+            &[("next", "udp"), ("ca", "corp")],
+        ] {
+            assert!(matches!(
+                parse(invalid),
+                Err(ConfigError::InvalidParam { field: "ca", .. })
+            ));
+        }
+    }
+}
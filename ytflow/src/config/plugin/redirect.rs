@@ -4,10 +4,25 @@ use crate::config::factory::*;
 use crate::config::*;
 use crate::flow::*;
 
+fn default_hop_interval() -> u64 {
+    30_000
+}
+
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+#[derive(Clone, Deserialize)]
+struct PortHopping {
+    port_start: u16,
+    port_end: u16,
+    #[serde(default = "default_hop_interval")]
+    interval: u64,
+}
+
 #[cfg_attr(not(feature = "plugins"), allow(dead_code))]
 #[derive(Clone, Deserialize)]
 pub struct RedirectFactory<'a> {
     dest: DestinationAddr,
+    #[serde(default)]
+    port_hopping: Option<PortHopping>,
 
     tcp_next: &'a str,
     udp_next: &'a str,
@@ -17,6 +32,20 @@ impl<'de> RedirectFactory<'de> {
     pub(in super::super) fn parse(plugin: &'de Plugin) -> ConfigResult<ParsedPlugin<'de, Self>> {
         let Plugin { name, param, .. } = plugin;
         let config: Self = parse_param(name, param)?;
+        if let Some(hopping) = &config.port_hopping {
+            if hopping.port_start > hopping.port_end {
+                return Err(ConfigError::InvalidParam {
+                    plugin: name.clone(),
+                    field: "port_hopping",
+                });
+            }
+            if hopping.interval == 0 {
+                return Err(ConfigError::InvalidParam {
+                    plugin: name.clone(),
+                    field: "interval",
+                });
+            }
+        }
 
         Ok(ParsedPlugin {
             requires: vec![
@@ -48,6 +77,36 @@ impl<'de> RedirectFactory<'de> {
 impl<'de> Factory for RedirectFactory<'de> {
     #[cfg(feature = "plugins")]
     fn load(&mut self, plugin_name: String, set: &mut PartialPluginSet) -> LoadResult<()> {
+        use std::time::Duration;
+
+        use crate::plugin::redirect::PortHoppingPeer;
+
+        match &self.port_hopping {
+            Some(hopping) => {
+                let peer = PortHoppingPeer::new(
+                    self.dest.clone(),
+                    hopping.port_start..=hopping.port_end,
+                    Duration::from_millis(hopping.interval),
+                );
+                self.load_with_peer(plugin_name, set, peer);
+            }
+            None => {
+                let dest = self.dest.clone();
+                self.load_with_peer(plugin_name, set, move || dest.clone());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'de> RedirectFactory<'de> {
+    #[cfg(feature = "plugins")]
+    fn load_with_peer<R: crate::plugin::redirect::PeerProvider>(
+        &self,
+        plugin_name: String,
+        set: &mut PartialPluginSet,
+        peer: R,
+    ) {
         use crate::plugin::null::Null;
         use crate::plugin::redirect;
 
@@ -68,9 +127,8 @@ impl<'de> Factory for RedirectFactory<'de> {
                             Arc::downgrade(&(Arc::new(Null)))
                         }
                     };
-                let dest = self.dest.clone();
                 redirect::DatagramSessionRedirectFactory {
-                    remote_peer: move || dest.clone(),
+                    remote_peer: peer.clone(),
                     next,
                 }
             });
@@ -85,15 +143,13 @@ impl<'de> Factory for RedirectFactory<'de> {
                     Arc::downgrade(&(Arc::new(Null)))
                 }
             };
-            let dest = self.dest.clone();
             redirect::StreamRedirectOutboundFactory {
-                remote_peer: move || dest.clone(),
+                remote_peer: peer.clone(),
                 next,
             }
         });
         set.fully_constructed
             .stream_outbounds
             .insert(plugin_name + ".tcp", tcp_factory);
-        Ok(())
     }
 }
@@ -4,25 +4,92 @@ use std::collections::BTreeMap;
 use std::sync::Weak;
 
 use serde::Deserialize;
+use serde_bytes::Bytes;
 
 use crate::config::factory::*;
 use crate::config::*;
-#[cfg(feature = "plugins")]
 use crate::flow::*;
 use crate::plugin::rule_dispatcher as rd;
 #[cfg(feature = "plugins")]
 use crate::resource::ResourceError;
-use crate::resource::{RESOURCE_TYPE_GEOIP_COUNTRY, RESOURCE_TYPE_QUANX_FILTER};
+use crate::resource::{
+    RESOURCE_TYPE_CIDR_LIST, RESOURCE_TYPE_CIDR_TRIE, RESOURCE_TYPE_CLASH_RULE_PROVIDER,
+    RESOURCE_TYPE_DOMAIN_LIST, RESOURCE_TYPE_GEOIP_COUNTRY, RESOURCE_TYPE_GEOSITE,
+    RESOURCE_TYPE_QUANX_FILTER,
+};
 
-static RULE_DISPATCHER_ALLOWED_RESOURCE_TYPES: [&str; 2] =
-    [RESOURCE_TYPE_GEOIP_COUNTRY, RESOURCE_TYPE_QUANX_FILTER];
-static RULE_DISPATCHER_ALLOWED_LITERAL_RESOURCE_TYPES: [&str; 1] = [RESOURCE_TYPE_QUANX_FILTER];
+static RULE_DISPATCHER_ALLOWED_RESOURCE_TYPES: [&str; 7] = [
+    RESOURCE_TYPE_GEOIP_COUNTRY,
+    RESOURCE_TYPE_QUANX_FILTER,
+    RESOURCE_TYPE_CIDR_LIST,
+    RESOURCE_TYPE_CIDR_TRIE,
+    RESOURCE_TYPE_GEOSITE,
+    RESOURCE_TYPE_DOMAIN_LIST,
+    RESOURCE_TYPE_CLASH_RULE_PROVIDER,
+];
+static RULE_DISPATCHER_ALLOWED_LITERAL_RESOURCE_TYPES: [&str; 4] = [
+    RESOURCE_TYPE_QUANX_FILTER,
+    RESOURCE_TYPE_CIDR_LIST,
+    RESOURCE_TYPE_DOMAIN_LIST,
+    RESOURCE_TYPE_CLASH_RULE_PROVIDER,
+];
 
 #[derive(Clone, Deserialize)]
 pub struct Action<'a> {
     pub(super) tcp: Option<&'a str>,
     pub(super) udp: Option<&'a str>,
     pub(super) resolver: Option<&'a str>,
+    /// Route through a proxy picked from a proxy group instead of `tcp` and `udp`.
+    #[serde(borrow, default)]
+    pub(super) proxy_group: Option<ProxyGroupAction<'a>>,
+}
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(super) enum ProxyGroupPolicy {
+    #[default]
+    First,
+    Random,
+    LowestLatency,
+}
+
+fn default_probe_interval() -> u64 {
+    300_000
+}
+
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+#[derive(Clone, Deserialize)]
+pub(super) struct ProxyGroupAction<'a> {
+    group: &'a str,
+    /// Only consider proxies whose names contain this text, case-insensitively.
+    filter: Option<&'a str>,
+    #[serde(default)]
+    policy: ProxyGroupPolicy,
+    /// Destination to measure handshake latency against for `lowest-latency`.
+    probe: Option<DestinationAddr>,
+    /// Interval between probing rounds in milliseconds.
+    #[serde(default = "default_probe_interval")]
+    probe_interval: u64,
+    tcp_next: &'a str,
+    udp_next: &'a str,
+}
+
+impl<'a> Action<'a> {
+    pub(super) fn validate(&self, plugin_name: &str, field: &'static str) -> ConfigResult<()> {
+        let Some(proxy_group) = &self.proxy_group else {
+            return Ok(());
+        };
+        let conflicting = self.tcp.is_some() || self.udp.is_some();
+        let missing_probe = matches!(proxy_group.policy, ProxyGroupPolicy::LowestLatency)
+            && (proxy_group.probe.is_none() || proxy_group.probe_interval == 0);
+        if conflicting || missing_probe {
+            return Err(ConfigError::InvalidParam {
+                plugin: plugin_name.to_string(),
+                field,
+            });
+        }
+        Ok(())
+    }
 }
 
 #[derive(Clone, Deserialize)]
@@ -33,6 +100,68 @@ pub enum ResourceSource<'a> {
         format: &'a str,
         text: Vec<Cow<'a, str>>,
     },
+    /// Same as `Literal`, but the text is compressed with zstd to keep large
+    /// inline rule sets small in the database.
+    CompressedLiteral {
+        format: &'a str,
+        #[serde(borrow)]
+        zstd: &'a Bytes,
+    },
+}
+
+/// Compressed literals are rejected if they expand beyond this size.
+#[cfg(feature = "plugins")]
+const MAX_DECOMPRESSED_LITERAL_SIZE: usize = 64 * 1024 * 1024;
+
+impl<'a> ResourceSource<'a> {
+    pub(super) fn literal_format(&self) -> Option<&'a str> {
+        match self {
+            ResourceSource::Key(_) => None,
+            ResourceSource::Literal { format, .. }
+            | ResourceSource::CompressedLiteral { format, .. } => Some(format),
+        }
+    }
+
+    /// Turn a compressed literal into a plain literal. Other sources are
+    /// returned unchanged.
+    #[cfg(feature = "plugins")]
+    pub(super) fn decompress(self, plugin_name: &str, set: &mut PartialPluginSet) -> Self {
+        let ResourceSource::CompressedLiteral { format, zstd } = self else {
+            return self;
+        };
+        let text = zstd::bulk::decompress(zstd, MAX_DECOMPRESSED_LITERAL_SIZE)
+            .ok()
+            .and_then(|buf| String::from_utf8(buf).ok());
+        let text = match text {
+            Some(text) => vec![Cow::Owned(text)],
+            None => {
+                set.errors.push(LoadError::Resource {
+                    plugin: plugin_name.into(),
+                    error: ResourceError::InvalidData,
+                });
+                vec![]
+            }
+        };
+        ResourceSource::Literal { format, text }
+    }
+}
+
+fn default_verdict_cache_size() -> usize {
+    4096
+}
+
+fn default_verdict_cache_ttl() -> u64 {
+    300
+}
+
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+#[derive(Clone, Copy, Deserialize)]
+pub(super) struct VerdictCacheConfig {
+    #[serde(default = "default_verdict_cache_size")]
+    size: usize,
+    /// In seconds.
+    #[serde(default = "default_verdict_cache_ttl")]
+    ttl: u64,
 }
 
 #[derive(Clone, Deserialize)]
@@ -43,6 +172,14 @@ pub struct RuleDispatcherConfig<'a> {
     pub(super) actions: BTreeMap<&'a str, Action<'a>>,
     pub(super) rules: BTreeMap<&'a str, &'a str>,
     pub(super) fallback: Action<'a>,
+    /// Remember the action chosen for each destination, so that repeated
+    /// connections skip rule matching.
+    #[serde(default)]
+    pub(super) verdict_cache: Option<VerdictCacheConfig>,
+    /// Send UDP sessions starting with a QUIC Initial packet here, on any
+    /// port, before matching rules.
+    #[serde(default)]
+    pub(super) quic: Option<&'a str>,
 }
 
 #[cfg_attr(not(feature = "plugins"), allow(dead_code))]
@@ -67,6 +204,18 @@ pub(super) fn chain_requirements_from_action<'a, 'b>(
             descriptor: *r,
             r#type: AccessPointType::RESOLVER,
         }))
+        .chain(a.proxy_group.iter().flat_map(|g| {
+            [
+                Descriptor {
+                    descriptor: g.tcp_next,
+                    r#type: AccessPointType::STREAM_OUTBOUND_FACTORY,
+                },
+                Descriptor {
+                    descriptor: g.udp_next,
+                    r#type: AccessPointType::DATAGRAM_SESSION_FACTORY,
+                },
+            ]
+        }))
 }
 
 impl<'de> RuleDispatcherFactory<'de> {
@@ -74,7 +223,7 @@ impl<'de> RuleDispatcherFactory<'de> {
         let Plugin { name, param, .. } = plugin;
         let config: RuleDispatcherConfig = parse_param(name, param)?;
 
-        if let ResourceSource::Literal { format, .. } = config.source {
+        if let Some(format) = config.source.literal_format() {
             if RULE_DISPATCHER_ALLOWED_LITERAL_RESOURCE_TYPES
                 .iter()
                 .all(|&t| format != t)
@@ -86,7 +235,9 @@ impl<'de> RuleDispatcherFactory<'de> {
             }
         }
 
-        if let Some(ResourceSource::Literal { .. }) = &config.geoip {
+        if let Some(ResourceSource::Literal { .. } | ResourceSource::CompressedLiteral { .. }) =
+            &config.geoip
+        {
             return Err(ConfigError::InvalidParam {
                 plugin: name.to_string(),
                 field: "geoip",
@@ -99,6 +250,16 @@ impl<'de> RuleDispatcherFactory<'de> {
                 field: "actions",
             });
         }
+        for action in config.actions.values() {
+            action.validate(name, "actions")?;
+        }
+        config.fallback.validate(name, "fallback")?;
+        if let Some(VerdictCacheConfig { size: 0, .. }) = config.verdict_cache {
+            return Err(ConfigError::InvalidParam {
+                plugin: name.to_string(),
+                field: "verdict_cache.size",
+            });
+        }
         for rule_action in config.rules.values() {
             if !config.actions.contains_key(rule_action) {
                 return Err(ConfigError::InvalidParam {
@@ -122,6 +283,10 @@ impl<'de> RuleDispatcherFactory<'de> {
                     .flat_map(chain_requirements_from_action),
             )
             .chain(chain_requirements_from_action(&config.fallback))
+            .chain(config.quic.iter().map(|q| Descriptor {
+                descriptor: *q,
+                r#type: AccessPointType::DATAGRAM_SESSION_HANDLER,
+            }))
             .collect();
         Ok(ParsedPlugin {
             resources: match config.source {
@@ -129,7 +294,7 @@ impl<'de> RuleDispatcherFactory<'de> {
                     key,
                     allowed_types: &RULE_DISPATCHER_ALLOWED_RESOURCE_TYPES,
                 }),
-                ResourceSource::Literal { .. } => None,
+                ResourceSource::Literal { .. } | ResourceSource::CompressedLiteral { .. } => None,
             }
             .into_iter()
             .collect(),
@@ -170,6 +335,24 @@ pub(super) fn load_resolver(
     }
 }
 
+#[cfg(feature = "plugins")]
+pub(super) fn load_quic_sniffer(
+    quic: &str,
+    set: &mut PartialPluginSet,
+    plugin_name: &str,
+) -> crate::plugin::quic_sniff::QuicSniffer {
+    use crate::plugin::reject::RejectHandler;
+
+    let quic_next = match set.get_or_create_datagram_handler(plugin_name.to_string(), quic) {
+        Ok(quic_next) => quic_next,
+        Err(e) => {
+            set.errors.push(e);
+            Arc::downgrade(&(Arc::new(RejectHandler) as _))
+        }
+    };
+    crate::plugin::quic_sniff::QuicSniffer { quic_next }
+}
+
 #[cfg(feature = "plugins")]
 pub(super) fn load_action(
     action: &Action,
@@ -179,7 +362,24 @@ pub(super) fn load_action(
     use crate::plugin::null::Null;
     use crate::plugin::reject::RejectHandler;
 
-    let Action { tcp, udp, resolver } = action;
+    let Action {
+        tcp,
+        udp,
+        resolver,
+        proxy_group,
+    } = action;
+    let resolver = resolver
+        .as_ref()
+        .map(|resolver| load_resolver(resolver, set, plugin_name))
+        .unwrap_or_else(|| Arc::downgrade(&(Arc::new(Null) as _)));
+    if let Some(proxy_group) = proxy_group {
+        let (tcp_next, udp_next) = load_proxy_group_action(proxy_group, set, plugin_name);
+        return rd::Action {
+            tcp_next,
+            udp_next,
+            resolver,
+        };
+    }
     let tcp_next = tcp
         .as_ref()
         .map(
@@ -204,10 +404,6 @@ pub(super) fn load_action(
             },
         )
         .unwrap_or_else(|| Arc::downgrade(&(Arc::new(RejectHandler) as _)));
-    let resolver = resolver
-        .as_ref()
-        .map(|resolver| load_resolver(resolver, set, plugin_name))
-        .unwrap_or_else(|| Arc::downgrade(&(Arc::new(Null) as _)));
     rd::Action {
         tcp_next,
         udp_next,
@@ -215,6 +411,126 @@ pub(super) fn load_action(
     }
 }
 
+/// Build a private dyn-outbound that picks a proxy from the group, and
+/// forward handlers on top of it for the dispatcher to route to.
+#[cfg(feature = "plugins")]
+fn load_proxy_group_action(
+    action: &ProxyGroupAction,
+    set: &mut PartialPluginSet,
+    plugin_name: &str,
+) -> (Weak<dyn StreamHandler>, Weak<dyn DatagramSessionHandler>) {
+    use std::time::Duration;
+
+    use crate::data::{PluginCache, PluginId};
+    use crate::plugin::dyn_outbound::{DynOutbound, GroupPolicy, GroupSelector};
+    use crate::plugin::forward;
+    use crate::plugin::null::Null;
+    use crate::plugin::reject::RejectHandler;
+
+    let reject = || {
+        (
+            Arc::downgrade(&(Arc::new(RejectHandler) as _)),
+            Arc::downgrade(&(Arc::new(RejectHandler) as _)),
+        )
+    };
+    let Some(db) = set.db.cloned() else {
+        set.errors.push(LoadError::DatabaseRequired {
+            plugin: plugin_name.into(),
+        });
+        return reject();
+    };
+    let tcp_next = set
+        .get_or_create_stream_outbound(plugin_name.into(), action.tcp_next)
+        .unwrap_or_else(|e| {
+            set.errors.push(e);
+            Arc::downgrade(&(Arc::new(Null) as _))
+        });
+    let udp_next = set
+        .get_or_create_datagram_outbound(plugin_name.into(), action.udp_next)
+        .unwrap_or_else(|e| {
+            set.errors.push(e);
+            Arc::downgrade(&(Arc::new(Null) as _))
+        });
+    let selector = GroupSelector {
+        group: action.group.into(),
+        filter: action.filter.map(Into::into),
+        policy: match (action.policy, &action.probe) {
+            (ProxyGroupPolicy::LowestLatency, Some(probe)) => GroupPolicy::LowestLatency {
+                probe: probe.clone(),
+                interval: Duration::from_millis(action.probe_interval),
+            },
+            (ProxyGroupPolicy::Random, _) => GroupPolicy::Random,
+            _ => GroupPolicy::First,
+        },
+    };
+    // The selection is not remembered across restarts, hence no cache.
+    let outbound = Arc::new(DynOutbound::new(
+        db,
+        PluginCache::new(PluginId::default(), None),
+        None,
+        vec![],
+        tcp_next,
+        udp_next,
+        set.control_hub.events().clone(),
+    ));
+    if let Err(e) = outbound.select_from_group(&selector) {
+        set.errors.push(LoadError::ProxyGroupSelection {
+            plugin: plugin_name.into(),
+            group: action.group.into(),
+            reason: e.to_string(),
+        });
+        return reject();
+    }
+    set.fully_constructed
+        .long_running_tasks
+        .push(tokio::spawn(DynOutbound::run_group_prober(
+            Arc::downgrade(&outbound),
+            selector,
+        )));
+
+    let key_prefix = format!("{}.proxy_group.{}.", plugin_name, action.group);
+    let seq = set
+        .fully_constructed
+        .stream_handlers
+        .keys()
+        .filter(|k| k.starts_with(&key_prefix))
+        .count();
+    let name: Arc<str> = plugin_name.into();
+    let outbound_name: Arc<str> = format!("{}{}.out", key_prefix, seq).into();
+    let stat = forward::StatHandle::default();
+    let connections = set.control_hub.connections().clone();
+    let tcp: Arc<dyn StreamHandler> = Arc::new(forward::StreamForwardHandler {
+        plugin_name: name.clone(),
+        request_timeout: 100,
+        outbound: Arc::downgrade(&outbound) as _,
+        outbound_name: outbound_name.clone(),
+        stat: stat.clone(),
+        connections: connections.clone(),
+    });
+    let udp: Arc<dyn DatagramSessionHandler> = Arc::new(forward::DatagramForwardHandler {
+        plugin_name: name,
+        outbound: Arc::downgrade(&outbound) as _,
+        outbound_name,
+        stat,
+        connections,
+    });
+    let ret = (Arc::downgrade(&tcp), Arc::downgrade(&udp));
+    set.fully_constructed
+        .stream_handlers
+        .insert(format!("{}{}.tcp", key_prefix, seq), tcp);
+    set.fully_constructed
+        .datagram_handlers
+        .insert(format!("{}{}.udp", key_prefix, seq), udp);
+    // Keep the dyn-outbound alive as long as the plugin set.
+    set.fully_constructed
+        .stream_outbounds
+        .insert(format!("{}{}.out.tcp", key_prefix, seq), outbound.clone());
+    set.fully_constructed
+        .datagram_outbounds
+        .insert(format!("{}{}.out.udp", key_prefix, seq), outbound);
+    ret
+}
+
 #[cfg(feature = "plugins")]
 pub(super) fn validate_text<'t>(
     bytes: &'t [u8],
@@ -239,7 +555,7 @@ fn load_additional_geoip_db(
 ) -> Option<Arc<[u8]>> {
     let key = match source {
         ResourceSource::Key(key) => *key,
-        ResourceSource::Literal { .. } => {
+        ResourceSource::Literal { .. } | ResourceSource::CompressedLiteral { .. } => {
             set.errors.push(LoadError::ResourceTypeMismatch {
                 plugin: plugin_name.into(),
                 resource_key: "<literal>".into(),
@@ -293,6 +609,7 @@ fn load_rule_set(
         .iter()
         .map(|(rule, action)| (*rule, action_map[*action]))
         .collect();
+    let source = source.decompress(plugin_name, set);
     let resource_key;
     let resource_type;
     match source {
@@ -357,6 +674,74 @@ fn load_rule_set(
                         }
                     }
                 }
+                RESOURCE_TYPE_CIDR_LIST => {
+                    let text = validate_text(&bytes, plugin_name, set);
+                    match rd::RuleSet::build_cidr_list(text.lines(), &rule_action_map) {
+                        Some(ruleset) => return ruleset,
+                        None => {
+                            set.errors.push(LoadError::Resource {
+                                plugin: plugin_name.into(),
+                                error: ResourceError::InvalidData,
+                            });
+                            return Default::default();
+                        }
+                    }
+                }
+                RESOURCE_TYPE_CIDR_TRIE => {
+                    match rd::RuleSet::build_cidr_trie(&rule_action_map, bytes) {
+                        Some(ruleset) => return ruleset,
+                        None => {
+                            set.errors.push(LoadError::Resource {
+                                plugin: plugin_name.into(),
+                                error: ResourceError::InvalidData,
+                            });
+                            return Default::default();
+                        }
+                    }
+                }
+                RESOURCE_TYPE_GEOSITE => {
+                    match rd::RuleSet::build_geosite(&bytes, &rule_action_map) {
+                        Some(ruleset) => return ruleset,
+                        None => {
+                            set.errors.push(LoadError::Resource {
+                                plugin: plugin_name.into(),
+                                error: ResourceError::InvalidData,
+                            });
+                            return Default::default();
+                        }
+                    }
+                }
+                RESOURCE_TYPE_DOMAIN_LIST => {
+                    let text = validate_text(&bytes, plugin_name, set);
+                    match rd::RuleSet::build_domain_list(text.lines(), &rule_action_map) {
+                        Some(ruleset) => return ruleset,
+                        None => {
+                            set.errors.push(LoadError::Resource {
+                                plugin: plugin_name.into(),
+                                error: ResourceError::InvalidData,
+                            });
+                            return Default::default();
+                        }
+                    }
+                }
+                RESOURCE_TYPE_CLASH_RULE_PROVIDER => {
+                    let text = validate_text(&bytes, plugin_name, set);
+                    match rd::RuleSet::load_clash_rule_provider(
+                        text.lines(),
+                        &rule_action_map,
+                        additional_geoip_db
+                            .and_then(|source| load_additional_geoip_db(source, plugin_name, set)),
+                    ) {
+                        Some(ruleset) => return ruleset,
+                        None => {
+                            set.errors.push(LoadError::Resource {
+                                plugin: plugin_name.into(),
+                                error: ResourceError::InvalidData,
+                            });
+                            return Default::default();
+                        }
+                    }
+                }
                 format => resource_type = format,
             }
         }
@@ -382,10 +767,60 @@ fn load_rule_set(
                         }
                     }
                 }
+                RESOURCE_TYPE_CIDR_LIST => {
+                    match rd::RuleSet::build_cidr_list(
+                        text.iter().flat_map(|t| t.lines()),
+                        &rule_action_map,
+                    ) {
+                        Some(ruleset) => return ruleset,
+                        None => {
+                            set.errors.push(LoadError::Resource {
+                                plugin: plugin_name.into(),
+                                error: ResourceError::InvalidData,
+                            });
+                            return Default::default();
+                        }
+                    }
+                }
+                RESOURCE_TYPE_DOMAIN_LIST => {
+                    match rd::RuleSet::build_domain_list(
+                        text.iter().flat_map(|t| t.lines()),
+                        &rule_action_map,
+                    ) {
+                        Some(ruleset) => return ruleset,
+                        None => {
+                            set.errors.push(LoadError::Resource {
+                                plugin: plugin_name.into(),
+                                error: ResourceError::InvalidData,
+                            });
+                            return Default::default();
+                        }
+                    }
+                }
+                RESOURCE_TYPE_CLASH_RULE_PROVIDER => {
+                    match rd::RuleSet::load_clash_rule_provider(
+                        text.iter().flat_map(|t| t.lines()),
+                        &rule_action_map,
+                        additional_geoip_db
+                            .and_then(|source| load_additional_geoip_db(source, plugin_name, set)),
+                    ) {
+                        Some(ruleset) => return ruleset,
+                        None => {
+                            set.errors.push(LoadError::Resource {
+                                plugin: plugin_name.into(),
+                                error: ResourceError::InvalidData,
+                            });
+                            return Default::default();
+                        }
+                    }
+                }
                 _ => {}
             }
             // TODO: process text based rule literals here
         }
+        ResourceSource::CompressedLiteral { .. } => {
+            unreachable!("compressed literals should have been decompressed")
+        }
     }
     set.errors.push(LoadError::ResourceTypeMismatch {
         plugin: plugin_name.into(),
@@ -399,7 +834,16 @@ fn load_rule_set(
 impl<'de> Factory for RuleDispatcherFactory<'de> {
     #[cfg(feature = "plugins")]
     fn load(&mut self, plugin_name: String, set: &mut PartialPluginSet) -> LoadResult<()> {
+        use std::num::NonZeroUsize;
+        use std::time::Duration;
+
         let mut builder = rd::RuleDispatcherBuilder::default();
+        let verdict_cache = self.config.verdict_cache.and_then(|c| {
+            Some(Arc::new(rd::VerdictCache::new(
+                NonZeroUsize::new(c.size)?,
+                Duration::from_secs(c.ttl),
+            )))
+        });
         let plugin = Arc::new_cyclic(|weak| {
             set.stream_handlers
                 .insert(plugin_name.clone(), weak.clone() as _);
@@ -444,8 +888,21 @@ impl<'de> Factory for RuleDispatcherFactory<'de> {
             let fallback = load_action(&self.config.fallback, set, &plugin_name);
             let me = weak.clone();
             builder.set_resolver(resolver);
+            builder.set_verdict_cache(verdict_cache.clone());
+            builder.set_quic_sniffer(
+                self.config
+                    .quic
+                    .map(|quic| load_quic_sniffer(quic, set, &plugin_name)),
+            );
             builder.build(rule_set, fallback, me)
         });
+        if let Some(verdict_cache) = verdict_cache {
+            set.control_hub.create_plugin_control(
+                plugin_name.clone(),
+                "rule-dispatcher",
+                rd::Responder::new(verdict_cache),
+            );
+        }
         set.fully_constructed
             .stream_handlers
             .insert(plugin_name.clone() + ".tcp", plugin.clone());
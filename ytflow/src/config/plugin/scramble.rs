@@ -0,0 +1,81 @@
+use serde::Deserialize;
+use serde_bytes::Bytes;
+
+use crate::config::factory::*;
+use crate::config::*;
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum ScrambleMethod {
+    Xor,
+    AesCtr,
+}
+
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+#[derive(Clone, Deserialize)]
+pub struct ScrambleFactory<'a> {
+    method: ScrambleMethod,
+    #[serde(borrow)]
+    key: &'a Bytes,
+    next: &'a str,
+}
+
+impl<'de> ScrambleFactory<'de> {
+    pub(in super::super) fn parse(plugin: &'de Plugin) -> ConfigResult<ParsedPlugin<'de, Self>> {
+        let Plugin { name, param, .. } = plugin;
+        let config: Self = parse_param(name, param)?;
+        if config.key.is_empty() {
+            return Err(ConfigError::InvalidParam {
+                plugin: name.clone(),
+                field: "key",
+            });
+        }
+        let next = config.next;
+        Ok(ParsedPlugin {
+            factory: config,
+            requires: vec![Descriptor {
+                descriptor: next,
+                r#type: AccessPointType::DATAGRAM_SESSION_FACTORY,
+            }],
+            provides: vec![Descriptor {
+                descriptor: name.to_string() + ".udp",
+                r#type: AccessPointType::DATAGRAM_SESSION_FACTORY,
+            }],
+            resources: vec![],
+        })
+    }
+}
+
+impl<'de> Factory for ScrambleFactory<'de> {
+    #[cfg(feature = "plugins")]
+    fn load(&mut self, plugin_name: String, set: &mut PartialPluginSet) -> LoadResult<()> {
+        use crate::flow::TransformDatagramFactory;
+        use crate::plugin::null::Null;
+        use crate::plugin::obfs::scramble;
+
+        let factory = Arc::new_cyclic(|weak| {
+            set.datagram_outbounds
+                .insert(plugin_name.clone() + ".udp", weak.clone() as _);
+            let next = match set.get_or_create_datagram_outbound(plugin_name.clone(), self.next) {
+                Ok(next) => next,
+                Err(e) => {
+                    set.errors.push(e);
+                    Arc::downgrade(&(Arc::new(Null)))
+                }
+            };
+            TransformDatagramFactory {
+                transform: match self.method {
+                    ScrambleMethod::Xor => Arc::new(scramble::XorScramble::new(self.key)) as _,
+                    ScrambleMethod::AesCtr => {
+                        Arc::new(scramble::AesCtrScramble::new(self.key)) as _
+                    }
+                },
+                next,
+            }
+        });
+        set.fully_constructed
+            .datagram_outbounds
+            .insert(plugin_name + ".udp", factory);
+        Ok(())
+    }
+}
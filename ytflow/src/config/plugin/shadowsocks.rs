@@ -1,5 +1,6 @@
 use serde_bytes::Bytes;
 
+use super::udp_fallback::UdpFallbackConfig;
 use crate::config::factory::*;
 use crate::config::*;
 use crate::plugin::shadowsocks::SupportedCipher;
@@ -10,6 +11,7 @@ pub struct ShadowsocksFactory<'de> {
     password: &'de [u8],
     tcp_next: &'de str,
     udp_next: &'de str,
+    udp_fallback: Option<UdpFallbackConfig<'de>>,
 }
 
 pub fn parse_supported_cipher(input: &[u8]) -> Option<SupportedCipher> {
@@ -31,6 +33,9 @@ pub fn parse_supported_cipher(input: &[u8]) -> Option<SupportedCipher> {
         b"chacha20-ietf" => SupportedCipher::Chacha20Ietf,
         b"chacha20-ietf-poly1305" => SupportedCipher::Chacha20IetfPoly1305,
         b"xchacha20-ietf-poly1305" => SupportedCipher::XChacha20IetfPoly1305,
+        b"2022-blake3-aes-128-gcm" => SupportedCipher::Blake3Aes128Gcm,
+        b"2022-blake3-aes-256-gcm" => SupportedCipher::Blake3Aes256Gcm,
+        b"2022-blake3-chacha20-poly1305" => SupportedCipher::Blake3Chacha20Poly1305,
         _ => return None,
     })
 }
@@ -44,35 +49,44 @@ impl<'de> ShadowsocksFactory<'de> {
             password: &'a Bytes,
             tcp_next: &'a str,
             udp_next: &'a str,
+            #[serde(borrow, default)]
+            udp_fallback: Option<UdpFallbackConfig<'a>>,
         }
         let ShadowsocksConfig {
             method,
             password,
             tcp_next,
             udp_next,
+            udp_fallback,
         } = parse_param(name, param)?;
         let cipher =
             parse_supported_cipher(method.as_bytes()).ok_or_else(|| ConfigError::InvalidParam {
                 plugin: name.clone(),
                 field: "method",
             })?;
+        let mut requires = vec![
+            Descriptor {
+                descriptor: tcp_next,
+                r#type: AccessPointType::STREAM_OUTBOUND_FACTORY,
+            },
+            Descriptor {
+                descriptor: udp_next,
+                r#type: AccessPointType::DATAGRAM_SESSION_FACTORY,
+            },
+        ];
+        if let Some(udp_fallback) = &udp_fallback {
+            udp_fallback.validate(name)?;
+            requires.push(udp_fallback.requires());
+        }
         Ok(ParsedPlugin {
             factory: ShadowsocksFactory {
                 cipher,
                 password,
                 tcp_next,
                 udp_next,
+                udp_fallback,
             },
-            requires: vec![
-                Descriptor {
-                    descriptor: tcp_next,
-                    r#type: AccessPointType::STREAM_OUTBOUND_FACTORY,
-                },
-                Descriptor {
-                    descriptor: udp_next,
-                    r#type: AccessPointType::DATAGRAM_SESSION_FACTORY,
-                },
-            ],
+            requires,
             provides: vec![
                 Descriptor {
                     descriptor: name.to_string() + ".tcp",
@@ -91,6 +105,7 @@ impl<'de> ShadowsocksFactory<'de> {
 impl<'de> Factory for ShadowsocksFactory<'de> {
     #[cfg(feature = "plugins")]
     fn load(&mut self, name: String, set: &mut PartialPluginSet) -> LoadResult<()> {
+        use crate::flow::DatagramSessionFactory;
         use crate::plugin::null::Null;
         use crate::plugin::shadowsocks::factory;
 
@@ -99,6 +114,7 @@ impl<'de> Factory for ShadowsocksFactory<'de> {
             set: &'set mut PartialPluginSet<'f>,
             tcp_next: &'de str,
             udp_next: &'de str,
+            udp_fallback: Option<UdpFallbackConfig<'de>>,
             result: &'r mut LoadResult<()>,
         }
         impl<'set, 'de, 'f, 'r> factory::ReceiveFactory for FactoryReceiver<'set, 'de, 'f, 'r> {
@@ -118,19 +134,40 @@ impl<'de> Factory for ShadowsocksFactory<'de> {
                     factory.create_stream_factory(tcp_next)
                 });
                 let udp_ap = self.plugin_name.clone() + ".udp";
-                let udp_factory = Arc::new_cyclic(|weak| {
-                    self.set
-                        .datagram_outbounds
-                        .insert(udp_ap.clone(), weak.clone() as _);
-                    let udp_next = self
-                        .set
-                        .get_or_create_datagram_outbound(self.plugin_name, self.udp_next)
-                        .unwrap_or_else(|e| {
-                            *self.result = Err(e);
-                            Arc::downgrade(&(Arc::new(Null) as _))
+                let create_udp_factory =
+                    |set: &mut PartialPluginSet, result: &mut LoadResult<()>| {
+                        let udp_next = set
+                            .get_or_create_datagram_outbound(
+                                self.plugin_name.clone(),
+                                self.udp_next,
+                            )
+                            .unwrap_or_else(|e| {
+                                *result = Err(e);
+                                Arc::downgrade(&(Arc::new(Null) as _))
+                            });
+                        factory.create_datagram_session_factory(udp_next)
+                    };
+                let udp_factory: Arc<dyn DatagramSessionFactory> = match self.udp_fallback {
+                    Some(udp_fallback) => {
+                        let udp_factory = Arc::new_cyclic(|weak| {
+                            self.set
+                                .datagram_outbounds
+                                .insert(udp_ap.clone(), weak.clone() as _);
+                            let udp = Arc::new(create_udp_factory(self.set, self.result));
+                            udp_fallback.wrap(self.plugin_name.clone(), udp, self.set)
                         });
-                    factory.create_datagram_session_factory(udp_next)
-                });
+                        udp_factory as _
+                    }
+                    None => {
+                        let udp_factory = Arc::new_cyclic(|weak| {
+                            self.set
+                                .datagram_outbounds
+                                .insert(udp_ap.clone(), weak.clone() as _);
+                            create_udp_factory(self.set, self.result)
+                        });
+                        udp_factory as _
+                    }
+                };
                 self.set
                     .fully_constructed
                     .stream_outbounds
@@ -142,17 +179,24 @@ impl<'de> Factory for ShadowsocksFactory<'de> {
             }
         }
         let mut res = Ok(());
-        factory::create_factory(
+        let psk_res = factory::create_factory(
             self.cipher,
             self.password,
             FactoryReceiver {
-                plugin_name: name,
+                plugin_name: name.clone(),
                 set,
                 tcp_next: self.tcp_next,
                 udp_next: self.udp_next,
+                udp_fallback: self.udp_fallback,
                 result: &mut res,
             },
         );
+        if psk_res.is_err() {
+            res = Err(ConfigError::InvalidParam {
+                plugin: name,
+                field: "password",
+            });
+        }
         if let Err(e) = res {
             set.errors.push(e);
         }
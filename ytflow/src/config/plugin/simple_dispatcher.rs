@@ -18,13 +18,17 @@ pub struct SimpleDispatcherFactory<'a> {
     rules: Vec<Rule<'a>>,
     fallback_tcp: &'a str,
     fallback_udp: &'a str,
+    /// Send UDP sessions starting with a QUIC Initial packet here, on any
+    /// port, before matching rules.
+    #[serde(default)]
+    quic: Option<&'a str>,
 }
 
 impl<'de> SimpleDispatcherFactory<'de> {
     pub(in super::super) fn parse(plugin: &'de Plugin) -> ConfigResult<ParsedPlugin<'de, Self>> {
         let Plugin { name, param, .. } = plugin;
         let config: Self = parse_param(name, param)?;
-        let mut requires = Vec::with_capacity(config.rules.len() + 2);
+        let mut requires = Vec::with_capacity(config.rules.len() + 3);
         requires.push(Descriptor {
             descriptor: config.fallback_tcp,
             r#type: AccessPointType::STREAM_HANDLER,
@@ -33,6 +37,10 @@ impl<'de> SimpleDispatcherFactory<'de> {
             descriptor: config.fallback_udp,
             r#type: AccessPointType::DATAGRAM_SESSION_HANDLER,
         });
+        requires.extend(config.quic.map(|q| Descriptor {
+            descriptor: q,
+            r#type: AccessPointType::DATAGRAM_SESSION_HANDLER,
+        }));
         requires.extend(config.rules.iter().map(|r| {
             if r.is_udp {
                 Descriptor {
@@ -67,6 +75,7 @@ impl<'de> SimpleDispatcherFactory<'de> {
 impl<'de> Factory for SimpleDispatcherFactory<'de> {
     #[cfg(feature = "plugins")]
     fn load(&mut self, plugin_name: String, set: &mut PartialPluginSet) -> LoadResult<()> {
+        use super::rule_dispatcher::load_quic_sniffer;
         use crate::plugin::reject::RejectHandler;
 
         let udp_factory = Arc::new_cyclic(|weak| {
@@ -118,9 +127,14 @@ impl<'de> Factory for SimpleDispatcherFactory<'de> {
                         Arc::downgrade(&(Arc::new(RejectHandler) as _))
                     }
                 };
+            let quic_sniffer = self
+                .quic
+                .map(|quic| load_quic_sniffer(quic, set, &plugin_name));
             let mut ret = sd::datagram::SimpleDatagramDispatcher {
                 rules: Vec::with_capacity(self.rules.iter().filter(|r| r.is_udp).count()),
                 fallback,
+                quic_sniffer,
+                me: weak.clone(),
             };
             for rule in self.rules.iter().filter(|r| r.is_udp) {
                 let next = match set.get_or_create_datagram_handler(plugin_name.clone(), rule.next)
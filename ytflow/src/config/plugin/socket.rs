@@ -2,8 +2,10 @@ use std::net::{Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6};
 
 use serde::Deserialize;
 
+use super::udp_fallback::UdpFallbackConfig;
 use crate::config::factory::*;
 use crate::config::*;
+use crate::flow::DEFAULT_CONNECT_TIMEOUT;
 
 fn default_bind_addr_v4() -> Option<HumanRepr<SocketAddrV4>> {
     Some(HumanRepr {
@@ -17,6 +19,10 @@ fn default_bind_addr_v6() -> Option<HumanRepr<SocketAddrV6>> {
     })
 }
 
+fn default_connect_timeout() -> u64 {
+    DEFAULT_CONNECT_TIMEOUT.as_millis() as u64
+}
+
 #[cfg_attr(not(feature = "plugins"), allow(dead_code))]
 #[derive(Clone, Deserialize)]
 pub struct SocketFactory<'a> {
@@ -25,18 +31,59 @@ pub struct SocketFactory<'a> {
     bind_addr_v4: Option<HumanRepr<SocketAddrV4>>,
     #[serde(default = "default_bind_addr_v6")]
     bind_addr_v6: Option<HumanRepr<SocketAddrV6>>,
+    /// In milliseconds.
+    #[serde(default = "default_connect_timeout")]
+    connect_timeout: u64,
+    /// Marks outbound packets with this DSCP instead of the one carried by the flow.
+    #[serde(default)]
+    dscp: Option<u8>,
+    /// Prepend a PROXY protocol header to TCP connections, so that the next
+    /// hop learns the address of the original client.
+    #[serde(default)]
+    proxy_protocol: Option<ProxyProtocolVersion>,
+    /// Connect UDP sockets to their destinations to learn about unreachable
+    /// ports, for sessions talking to a single destination.
+    #[serde(default)]
+    udp_connected: bool,
+    #[serde(borrow, default)]
+    udp_fallback: Option<UdpFallbackConfig<'a>>,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+enum ProxyProtocolVersion {
+    V1,
+    V2,
 }
 
 impl<'de> SocketFactory<'de> {
     pub(in super::super) fn parse(plugin: &'de Plugin) -> ConfigResult<ParsedPlugin<'de, Self>> {
         let Plugin { name, param, .. } = plugin;
         let config: Self = parse_param(name, param)?;
+        if config.connect_timeout == 0 {
+            return Err(ConfigError::InvalidParam {
+                plugin: name.clone(),
+                field: "connect_timeout",
+            });
+        }
+        if config.dscp.map_or(false, |d| d > 63) {
+            return Err(ConfigError::InvalidParam {
+                plugin: name.clone(),
+                field: "dscp",
+            });
+        }
+        let mut requires = vec![Descriptor {
+            descriptor: config.resolver,
+            r#type: AccessPointType::RESOLVER,
+        }];
+        if let Some(udp_fallback) = &config.udp_fallback {
+            udp_fallback.validate(name)?;
+            requires.push(udp_fallback.requires());
+        }
         Ok(ParsedPlugin {
             factory: config.clone(),
-            requires: vec![Descriptor {
-                descriptor: config.resolver,
-                r#type: AccessPointType::RESOLVER,
-            }],
+            requires,
             provides: vec![Descriptor {
                 descriptor: name.clone(),
                 r#type: AccessPointType::STREAM_OUTBOUND_FACTORY
@@ -50,6 +97,8 @@ impl<'de> SocketFactory<'de> {
 impl<'de> Factory for SocketFactory<'de> {
     #[cfg(feature = "plugins")]
     fn load(&mut self, plugin_name: String, set: &mut PartialPluginSet) -> LoadResult<()> {
+        use std::time::Duration;
+
         use crate::plugin::null::Null;
         use crate::plugin::socket;
 
@@ -69,14 +118,27 @@ impl<'de> Factory for SocketFactory<'de> {
                 resolver,
                 bind_addr_v4: self.bind_addr_v4.clone().map(|h| h.inner),
                 bind_addr_v6: self.bind_addr_v6.clone().map(|h| h.inner),
+                connect_timeout: Duration::from_millis(self.connect_timeout),
+                dscp: self.dscp,
+                proxy_protocol: self.proxy_protocol.map(|v| match v {
+                    ProxyProtocolVersion::V1 => socket::ProxyProtocolVersion::V1,
+                    ProxyProtocolVersion::V2 => socket::ProxyProtocolVersion::V2,
+                }),
+                udp_connected: self.udp_connected,
             }
         });
         set.fully_constructed
             .stream_outbounds
             .insert(plugin_name.clone() + ".tcp", factory.clone());
+        let udp_factory: Arc<dyn crate::flow::DatagramSessionFactory> = match &self.udp_fallback {
+            Some(udp_fallback) => {
+                Arc::new(udp_fallback.wrap(plugin_name.clone(), factory, set)) as _
+            }
+            None => factory as _,
+        };
         set.fully_constructed
             .datagram_outbounds
-            .insert(plugin_name + ".udp", factory);
+            .insert(plugin_name + ".udp", udp_factory);
         Ok(())
     }
 }
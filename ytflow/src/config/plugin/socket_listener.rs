@@ -13,6 +13,67 @@ pub struct SocketListenerFactory<'a> {
     udp_listen: Vec<&'a str>,
     tcp_next: &'a str,
     udp_next: &'a str,
+    /// Require a PROXY protocol header (v1 or v2) on every TCP connection and
+    /// take the client address from it. Only enable this behind a trusted
+    /// load balancer, as the header is not authenticated.
+    #[serde(default)]
+    proxy_protocol: bool,
+    /// Set `SO_REUSEPORT` so that the ports can be shared with other sockets
+    /// doing the same. Ignored on Windows.
+    #[serde(default)]
+    reuse_port: bool,
+    /// Number of following ports to try when a port is in use.
+    #[serde(default)]
+    port_fallback: u16,
+}
+
+/// Bind `listen`, or one of the `port_fallback` ports after it if it is in
+/// use. Reports the process holding the port if all of them are in use, and
+/// publishes the address actually bound if it is a fallback.
+#[cfg(feature = "plugins")]
+fn listen_with_fallback<T>(
+    plugin_name: &str,
+    listen: &str,
+    kind: crate::plugin::socket::SocketKind,
+    port_fallback: u16,
+    events: &crate::control::events::EventBus,
+    mut bind: impl FnMut(String) -> std::io::Result<T>,
+) -> LoadResult<T> {
+    use std::io::ErrorKind;
+    use std::net::SocketAddr;
+
+    let mut res = bind(listen.to_owned());
+    if let Ok(addr) = listen.parse::<SocketAddr>() {
+        for offset in 1..=port_fallback {
+            if !matches!(&res, Err(e) if e.kind() == ErrorKind::AddrInUse) {
+                break;
+            }
+            let Some(port) = addr.port().checked_add(offset) else {
+                break;
+            };
+            let fallback = SocketAddr::new(addr.ip(), port).to_string();
+            res = bind(fallback.clone());
+            if res.is_ok() {
+                events.publish(crate::control::events::Event::ListenPortFallback {
+                    plugin: plugin_name.to_owned(),
+                    requested: listen.to_owned(),
+                    bound: fallback,
+                });
+            }
+        }
+    }
+    res.map_err(|error| match error.kind() {
+        ErrorKind::AddrInUse => LoadError::PortInUse {
+            plugin: plugin_name.to_owned(),
+            addr: listen.to_owned(),
+            owner: crate::plugin::socket::find_port_owner(kind, &listen)
+                .map_or_else(|| "another process".into(), |o| o.to_string()),
+        },
+        _ => LoadError::Io {
+            plugin: plugin_name.to_owned(),
+            error,
+        },
+    })
 }
 
 impl<'de> SocketListenerFactory<'de> {
@@ -52,14 +113,24 @@ impl<'de> Factory for SocketListenerFactory<'de> {
                     Arc::downgrade(&(Arc::new(RejectHandler) as _))
                 });
             for tcp_listen in &self.tcp_listen {
-                match socket::listen_tcp(tcp_next.clone(), (*tcp_listen).to_owned()) {
+                match listen_with_fallback(
+                    &plugin_name,
+                    tcp_listen,
+                    socket::SocketKind::Tcp,
+                    self.port_fallback,
+                    set.control_hub.events(),
+                    |addr| {
+                        socket::listen_tcp(
+                            plugin_name.as_str().into(),
+                            tcp_next.clone(),
+                            addr,
+                            self.proxy_protocol,
+                            self.reuse_port,
+                        )
+                    },
+                ) {
                     Ok(handle) => set.fully_constructed.long_running_tasks.push(handle),
-                    Err(e) => {
-                        set.errors.push(LoadError::Io {
-                            plugin: plugin_name.clone(),
-                            error: e,
-                        });
-                    }
+                    Err(e) => set.errors.push(e),
                 }
             }
         }
@@ -71,17 +142,72 @@ impl<'de> Factory for SocketListenerFactory<'de> {
                     Arc::downgrade(&(Arc::new(RejectHandler) as _))
                 });
             for udp_listen in &self.udp_listen {
-                match socket::listen_udp(udp_next.clone(), (*udp_listen).to_owned()) {
+                match listen_with_fallback(
+                    &plugin_name,
+                    udp_listen,
+                    socket::SocketKind::Udp,
+                    self.port_fallback,
+                    set.control_hub.events(),
+                    |addr| {
+                        socket::listen_udp(
+                            plugin_name.as_str().into(),
+                            udp_next.clone(),
+                            addr,
+                            self.reuse_port,
+                        )
+                    },
+                ) {
                     Ok(handle) => set.fully_constructed.long_running_tasks.push(handle),
-                    Err(e) => {
-                        set.errors.push(LoadError::Io {
-                            plugin: plugin_name.clone(),
-                            error: e,
-                        });
-                    }
+                    Err(e) => set.errors.push(e),
                 }
             }
         }
         Ok(())
     }
 }
+
+#[cfg(all(test, feature = "plugins"))]
+mod tests {
+    use std::io;
+
+    use super::*;
+    use crate::control::events::{Event, EventBus};
+    use crate::plugin::socket::SocketKind;
+
+    #[test]
+    fn test_listen_with_fallback_reports_bound_port() {
+        let events = EventBus::new();
+        let mut tried = vec![];
+        let bound =
+            listen_with_fallback("l", "127.0.0.1:1080", SocketKind::Tcp, 3, &events, |addr| {
+                tried.push(addr.clone());
+                if addr.ends_with(":1082") {
+                    Ok(addr)
+                } else {
+                    Err(io::ErrorKind::AddrInUse.into())
+                }
+            })
+            .unwrap();
+        assert_eq!(bound, "127.0.0.1:1082");
+        assert_eq!(
+            tried,
+            ["127.0.0.1:1080", "127.0.0.1:1081", "127.0.0.1:1082"]
+        );
+        let (records, _) = events.subscribe(0);
+        assert_eq!(records.len(), 1);
+        assert!(matches!(
+            &records[0].event,
+            Event::ListenPortFallback { plugin, requested, bound }
+                if plugin == "l" && requested == "127.0.0.1:1080" && bound == "127.0.0.1:1082"
+        ));
+    }
+
+    #[test]
+    fn test_listen_without_fallback_is_silent() {
+        let events = EventBus::new();
+        let bound =
+            listen_with_fallback("l", "127.0.0.1:1080", SocketKind::Tcp, 3, &events, Ok).unwrap();
+        assert_eq!(bound, "127.0.0.1:1080");
+        assert!(events.subscribe(0).0.is_empty());
+    }
+}
@@ -3,6 +3,11 @@ use serde_bytes::Bytes;
 
 use crate::config::factory::*;
 use crate::config::*;
+use crate::flow::DEFAULT_HANDSHAKE_TIMEOUT;
+
+fn default_handshake_timeout() -> u64 {
+    DEFAULT_HANDSHAKE_TIMEOUT.as_millis() as u64
+}
 
 #[cfg_attr(not(feature = "plugins"), allow(dead_code))]
 #[derive(Clone, Deserialize)]
@@ -13,6 +18,16 @@ struct Socks5Info<'a> {
     pass: &'a Bytes,
 }
 
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum SocksVersion {
+    #[default]
+    Socks5,
+    Socks4,
+    Socks4a,
+}
+
 #[cfg_attr(not(feature = "plugins"), allow(dead_code))]
 #[derive(Deserialize)]
 pub struct Socks5ServerFactory<'a> {
@@ -28,6 +43,11 @@ pub struct Socks5ServerFactory<'a> {
 pub struct Socks5ClientFactory<'a> {
     tcp_next: &'a str,
     udp_next: &'a str,
+    /// In milliseconds.
+    #[serde(default = "default_handshake_timeout")]
+    handshake_timeout: u64,
+    #[serde(default)]
+    version: SocksVersion,
     #[serde(flatten)]
     #[serde(borrow)]
     socks5: Option<Socks5Info<'a>>,
@@ -53,11 +73,10 @@ impl<'de> Socks5ServerFactory<'de> {
                     descriptor: name.to_string() + ".tcp",
                     r#type: AccessPointType::STREAM_HANDLER,
                 },
-                // TODO:
-                // Descriptor {
-                //     descriptor: name.to_string() + ".udp",
-                //     r#type: AccessPointType::DATAGRAM_SESSION_HANDLER,
-                // },
+                Descriptor {
+                    descriptor: name.to_string() + ".udp",
+                    r#type: AccessPointType::DATAGRAM_SESSION_HANDLER,
+                },
             ],
             factory: config,
             resources: vec![],
@@ -69,6 +88,12 @@ impl<'de> Socks5ClientFactory<'de> {
     pub(in super::super) fn parse(plugin: &'de Plugin) -> ConfigResult<ParsedPlugin<'de, Self>> {
         let Plugin { name, param, .. } = plugin;
         let config: Self = parse_param(name, param)?;
+        if config.handshake_timeout == 0 {
+            return Err(ConfigError::InvalidParam {
+                plugin: name.clone(),
+                field: "handshake_timeout",
+            });
+        }
         Ok(ParsedPlugin {
             requires: vec![
                 Descriptor {
@@ -94,6 +119,17 @@ impl<'de> Socks5ClientFactory<'de> {
             resources: vec![],
         })
     }
+
+    /// Round trips before the first payload: SOCKS4 only sends CONNECT, while
+    /// SOCKS5 negotiates the method first and authenticates if credentials are
+    /// set.
+    pub(in super::super) fn handshake_round_trips(&self) -> u32 {
+        match (self.version, &self.socks5) {
+            (SocksVersion::Socks4 | SocksVersion::Socks4a, _) => 1,
+            (SocksVersion::Socks5, None) => 2,
+            (SocksVersion::Socks5, Some(_)) => 3,
+        }
+    }
 }
 
 impl<'de> Factory for Socks5ServerFactory<'de> {
@@ -102,6 +138,7 @@ impl<'de> Factory for Socks5ServerFactory<'de> {
         use crate::plugin::reject::RejectHandler;
         use crate::plugin::socks5;
 
+        let udp_associations = socks5::UdpAssociations::default();
         let factory = Arc::new_cyclic(|weak| {
             set.stream_handlers
                 .insert(plugin_name.clone() + ".tcp", weak.clone() as _);
@@ -115,12 +152,29 @@ impl<'de> Factory for Socks5ServerFactory<'de> {
                 };
             socks5::Socks5Handler::new(
                 self.socks5.as_ref().map(|s| (&**s.user, &**s.pass)),
+                udp_associations.clone(),
                 tcp_next,
             )
         });
+        let udp_factory = Arc::new_cyclic(|weak| {
+            set.datagram_handlers
+                .insert(plugin_name.clone() + ".udp", weak.clone() as _);
+            let udp_next =
+                match set.get_or_create_datagram_handler(plugin_name.clone(), self.udp_next) {
+                    Ok(u) => u,
+                    Err(e) => {
+                        set.errors.push(e);
+                        Arc::downgrade(&(Arc::new(RejectHandler) as _))
+                    }
+                };
+            socks5::Socks5DatagramHandler::new(udp_associations, udp_next)
+        });
         set.fully_constructed
             .stream_handlers
-            .insert(plugin_name + ".tcp", factory);
+            .insert(plugin_name.clone() + ".tcp", factory);
+        set.fully_constructed
+            .datagram_handlers
+            .insert(plugin_name + ".udp", udp_factory);
         Ok(())
     }
 }
@@ -128,17 +182,21 @@ impl<'de> Factory for Socks5ServerFactory<'de> {
 impl<'de> Factory for Socks5ClientFactory<'de> {
     #[cfg(feature = "plugins")]
     fn load(&mut self, plugin_name: String, set: &mut PartialPluginSet) -> LoadResult<()> {
+        use std::time::Duration;
+
         use crate::plugin::null::Null;
         use crate::plugin::socks5;
 
+        let cred = self.socks5.as_ref().map(|s| (&**s.user, &**s.pass));
+        let handshake_timeout = Duration::from_millis(self.handshake_timeout);
+        let version = match self.version {
+            SocksVersion::Socks5 => socks5::SocksVersion::V5,
+            SocksVersion::Socks4 => socks5::SocksVersion::V4,
+            SocksVersion::Socks4a => socks5::SocksVersion::V4a,
+        };
         let factory = Arc::new_cyclic(|weak| {
             set.stream_outbounds
                 .insert(plugin_name.clone() + ".tcp", weak.clone() as _);
-            set.datagram_outbounds.insert(
-                plugin_name.clone() + ".udp",
-                // TODO: socks5 udp
-                Arc::downgrade(&Arc::new(Null)) as _,
-            );
             let tcp_next =
                 match set.get_or_create_stream_outbound(plugin_name.clone(), self.tcp_next) {
                     Ok(t) => t,
@@ -147,14 +205,42 @@ impl<'de> Factory for Socks5ClientFactory<'de> {
                         Arc::downgrade(&(Arc::new(Null) as _))
                     }
                 };
-            socks5::Socks5Outbound::new(
-                self.socks5.as_ref().map(|s| (&**s.user, &**s.pass)),
-                tcp_next,
-            )
+            socks5::Socks5Outbound::new(cred, tcp_next, handshake_timeout, version)
         });
         set.fully_constructed
             .stream_outbounds
-            .insert(plugin_name + ".tcp", factory);
+            .insert(plugin_name.clone() + ".tcp", factory);
+        if version != socks5::SocksVersion::V5 {
+            // SOCKS4 has no UDP support
+            set.fully_constructed
+                .datagram_outbounds
+                .insert(plugin_name + ".udp", Arc::new(Null));
+            return Ok(());
+        }
+        let udp_factory = Arc::new_cyclic(|weak| {
+            set.datagram_outbounds
+                .insert(plugin_name.clone() + ".udp", weak.clone() as _);
+            let tcp_next =
+                match set.get_or_create_stream_outbound(plugin_name.clone(), self.tcp_next) {
+                    Ok(t) => t,
+                    Err(e) => {
+                        set.errors.push(e);
+                        Arc::downgrade(&(Arc::new(Null) as _))
+                    }
+                };
+            let udp_next =
+                match set.get_or_create_datagram_outbound(plugin_name.clone(), self.udp_next) {
+                    Ok(u) => u,
+                    Err(e) => {
+                        set.errors.push(e);
+                        Arc::downgrade(&(Arc::new(Null) as _))
+                    }
+                };
+            socks5::Socks5DatagramOutbound::new(cred, tcp_next, udp_next, handshake_timeout)
+        });
+        set.fully_constructed
+            .datagram_outbounds
+            .insert(plugin_name + ".udp", udp_factory);
         Ok(())
     }
 }
@@ -0,0 +1,95 @@
+use serde::Deserialize;
+
+use crate::config::factory::*;
+use crate::config::*;
+use crate::flow::*;
+
+fn default_interval() -> u64 {
+    25_000
+}
+
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+#[derive(Clone, Deserialize)]
+pub struct StunKeepaliveFactory<'a> {
+    server: DestinationAddr,
+    /// Interval between Binding Requests in milliseconds. Most NATs expire UDP
+    /// mappings after 30 seconds of inactivity.
+    #[serde(default = "default_interval")]
+    interval: u64,
+    next: &'a str,
+}
+
+impl<'de> StunKeepaliveFactory<'de> {
+    pub(in super::super) fn parse(plugin: &'de Plugin) -> ConfigResult<ParsedPlugin<'de, Self>> {
+        let Plugin { name, param, .. } = plugin;
+        let config: Self = parse_param(name, param)?;
+        if config.interval == 0 {
+            return Err(ConfigError::InvalidParam {
+                plugin: name.clone(),
+                field: "interval",
+            });
+        }
+        Ok(ParsedPlugin {
+            requires: vec![Descriptor {
+                descriptor: config.next,
+                r#type: AccessPointType::DATAGRAM_SESSION_FACTORY,
+            }],
+            provides: vec![Descriptor {
+                descriptor: name.clone() + ".udp",
+                r#type: AccessPointType::DATAGRAM_SESSION_FACTORY,
+            }],
+            factory: config,
+            resources: vec![],
+        })
+    }
+}
+
+impl<'de> Factory for StunKeepaliveFactory<'de> {
+    #[cfg(feature = "plugins")]
+    fn load(&mut self, plugin_name: String, set: &mut PartialPluginSet) -> LoadResult<()> {
+        use std::time::Duration;
+
+        use crate::control::events::Event;
+        use crate::plugin::null::Null;
+        use crate::plugin::stun_keepalive;
+
+        let monitor = Arc::new(stun_keepalive::ExternalAddressMonitor::default());
+        monitor.on_change({
+            let plugin = plugin_name.clone();
+            let events = set.control_hub.events().clone();
+            move |change| {
+                events.publish(Event::ExternalAddressChanged {
+                    plugin: plugin.clone(),
+                    previous: change.previous.to_string(),
+                    current: change.current.to_string(),
+                })
+            }
+        });
+        let factory = Arc::new_cyclic(|weak| {
+            set.datagram_outbounds
+                .insert(plugin_name.clone() + ".udp", weak.clone() as _);
+            let next = match set.get_or_create_datagram_outbound(plugin_name.clone(), self.next) {
+                Ok(t) => t,
+                Err(e) => {
+                    set.errors.push(e);
+                    Arc::downgrade(&(Arc::new(Null)))
+                }
+            };
+            stun_keepalive::StunKeepaliveFactory::new(
+                self.server.clone(),
+                Duration::from_millis(self.interval),
+                monitor.clone(),
+                next,
+            )
+        });
+        set.fully_constructed
+            .datagram_outbounds
+            .insert(plugin_name.clone() + ".udp", factory);
+        set.control_hub.create_plugin_control(
+            plugin_name,
+            "stun-keepalive",
+            stun_keepalive::Responder::new(monitor),
+        );
+        Ok(())
+    }
+}
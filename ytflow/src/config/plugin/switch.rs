@@ -154,9 +154,11 @@ impl<'de> Factory for SwitchFactory<'de> {
         });
 
         let responder = switch::Responder {
+            plugin_name: plugin_name.clone(),
             choices,
             switch: switch.clone(),
             cache,
+            events: set.control_hub.events().clone(),
         };
 
         set.fully_constructed
@@ -2,6 +2,24 @@ use serde::Deserialize;
 
 use crate::config::factory::*;
 use crate::config::*;
+use crate::flow::DEFAULT_HANDSHAKE_TIMEOUT;
+use crate::resource::RESOURCE_TYPE_CA_BUNDLE;
+
+static TLS_ALLOWED_RESOURCE_TYPES: [&str; 1] = [RESOURCE_TYPE_CA_BUNDLE];
+
+fn default_handshake_timeout() -> u64 {
+    DEFAULT_HANDSHAKE_TIMEOUT.as_millis() as u64
+}
+
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+#[derive(Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(super) enum TrustStore {
+    #[default]
+    System,
+    Bundled,
+    Custom,
+}
 
 #[cfg_attr(not(feature = "plugins"), allow(dead_code))]
 #[derive(Deserialize)]
@@ -11,6 +29,13 @@ pub struct TlsFactory<'a> {
     alpn: Vec<&'a str>,
     #[serde(default)]
     skip_cert_check: bool,
+    #[serde(default)]
+    trust: TrustStore,
+    /// Key of a `ca-bundle` resource, required by the `custom` trust store.
+    ca: Option<&'a str>,
+    /// In milliseconds.
+    #[serde(default = "default_handshake_timeout")]
+    handshake_timeout: u64,
     next: &'a str,
 }
 
@@ -18,6 +43,13 @@ impl<'de> TlsFactory<'de> {
     pub(in super::super) fn parse(plugin: &'de Plugin) -> ConfigResult<ParsedPlugin<'de, Self>> {
         let Plugin { name, param, .. } = plugin;
         let config: Self = parse_param(name, param)?;
+        if config.handshake_timeout == 0 {
+            return Err(ConfigError::InvalidParam {
+                plugin: name.clone(),
+                field: "handshake_timeout",
+            });
+        }
+        let resources = parse_trust(name, config.trust, config.ca)?;
         let next = config.next;
         Ok(ParsedPlugin {
             factory: config,
@@ -29,17 +61,103 @@ impl<'de> TlsFactory<'de> {
                 descriptor: name.to_string() + ".tcp",
                 r#type: AccessPointType::STREAM_OUTBOUND_FACTORY,
             }],
-            resources: vec![],
+            resources,
+        })
+    }
+}
+
+/// Check that a CA bundle is given exactly when the `custom` trust store is
+/// used, and require it as a resource.
+pub(super) fn parse_trust<'de>(
+    plugin_name: &str,
+    trust: TrustStore,
+    ca: Option<&'de str>,
+) -> ConfigResult<Vec<RequiredResource<'de>>> {
+    if (trust == TrustStore::Custom) != ca.is_some() {
+        return Err(ConfigError::InvalidParam {
+            plugin: plugin_name.into(),
+            field: "ca",
+        });
+    }
+    Ok(ca
+        .map(|key| RequiredResource {
+            key,
+            allowed_types: &TLS_ALLOWED_RESOURCE_TYPES,
         })
+        .into_iter()
+        .collect())
+}
+
+#[cfg(feature = "plugins")]
+pub(super) fn load_trust(
+    trust: TrustStore,
+    ca: Option<&str>,
+    plugin_name: &str,
+    set: &mut PartialPluginSet,
+) -> crate::plugin::tls::RootTrust {
+    use crate::plugin::tls::RootTrust;
+
+    match (trust, ca) {
+        (TrustStore::Bundled, _) => RootTrust::Bundled,
+        (TrustStore::Custom, Some(key)) => load_ca_bundle(key, plugin_name, set),
+        _ => RootTrust::System,
     }
 }
 
+/// Load CA certificates from a resource. Failures are reported as load errors,
+/// and no server will be trusted in that case.
+#[cfg(feature = "plugins")]
+fn load_ca_bundle(
+    key: &str,
+    plugin_name: &str,
+    set: &mut PartialPluginSet,
+) -> crate::plugin::tls::RootTrust {
+    use crate::plugin::tls::RootTrust;
+    use crate::resource::ResourceError;
+
+    let no_trust = RootTrust::Custom(Arc::from(Vec::new()));
+    let resource_error = |error| LoadError::Resource {
+        plugin: plugin_name.into(),
+        error,
+    };
+    let metadata = match set.resource_registry.query_metadata(key) {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            set.errors.push(resource_error(e));
+            return no_trust;
+        }
+    };
+    if metadata.r#type != RESOURCE_TYPE_CA_BUNDLE {
+        set.errors.push(LoadError::ResourceTypeMismatch {
+            plugin: plugin_name.into(),
+            resource_key: key.into(),
+            expected: &TLS_ALLOWED_RESOURCE_TYPES,
+            actual: metadata.r#type.clone(),
+        });
+        return no_trust;
+    }
+    let bytes = match set.resource_registry.query_bytes(&metadata.handle) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            set.errors.push(resource_error(e));
+            return no_trust;
+        }
+    };
+    RootTrust::from_pem(&bytes).unwrap_or_else(|| {
+        set.errors.push(resource_error(ResourceError::InvalidData));
+        no_trust
+    })
+}
+
 impl<'de> Factory for TlsFactory<'de> {
     #[cfg(feature = "plugins")]
     fn load(&mut self, plugin_name: String, set: &mut PartialPluginSet) -> LoadResult<()> {
+        use std::time::Duration;
+
         use crate::plugin::null::Null;
         use crate::plugin::tls;
 
+        let trust = load_trust(self.trust, self.ca, &plugin_name, set);
         let factory = Arc::new_cyclic(|weak| {
             set.stream_outbounds
                 .insert(plugin_name.clone() + ".tcp", weak.clone() as _);
@@ -51,11 +169,13 @@ impl<'de> Factory for TlsFactory<'de> {
                 }
             };
 
-            tls::SslStreamFactory::new(
+            tls::SslStreamFactory::with_root_trust(
                 next,
                 std::mem::take(&mut self.alpn),
                 self.skip_cert_check,
                 self.sni.map(|s| s.to_string()),
+                Duration::from_millis(self.handshake_timeout),
+                trust,
             )
         });
         set.fully_constructed
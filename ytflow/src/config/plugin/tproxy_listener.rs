@@ -0,0 +1,107 @@
+use std::net::SocketAddr;
+
+use serde::Deserialize;
+
+use crate::config::factory::*;
+use crate::config::*;
+
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+#[derive(Deserialize)]
+pub struct TproxyListenerFactory<'a> {
+    #[serde(default)]
+    tcp_listen: Vec<HumanRepr<SocketAddr>>,
+    #[serde(default)]
+    udp_listen: Vec<HumanRepr<SocketAddr>>,
+    tcp_next: &'a str,
+    udp_next: &'a str,
+}
+
+impl<'de> TproxyListenerFactory<'de> {
+    pub(in super::super) fn parse(plugin: &'de Plugin) -> ConfigResult<ParsedPlugin<'de, Self>> {
+        let Plugin { param, name, .. } = plugin;
+        let config: Self = parse_param(name, param)?;
+        Ok(ParsedPlugin {
+            requires: (!config.tcp_listen.is_empty())
+                .then_some(Descriptor {
+                    descriptor: config.tcp_next,
+                    r#type: AccessPointType::STREAM_HANDLER,
+                })
+                .into_iter()
+                .chain((!config.udp_listen.is_empty()).then_some(Descriptor {
+                    descriptor: config.udp_next,
+                    r#type: AccessPointType::DATAGRAM_SESSION_HANDLER,
+                }))
+                .collect(),
+            factory: config,
+            provides: vec![],
+            resources: vec![],
+        })
+    }
+}
+
+impl<'de> Factory for TproxyListenerFactory<'de> {
+    #[cfg(all(feature = "plugins", target_os = "linux"))]
+    fn load(&mut self, plugin_name: String, set: &mut PartialPluginSet) -> LoadResult<()> {
+        use crate::plugin::reject::RejectHandler;
+        use crate::plugin::tproxy;
+
+        if !self.tcp_listen.is_empty() {
+            let tcp_next = set
+                .get_or_create_stream_handler(plugin_name.clone(), self.tcp_next)
+                .unwrap_or_else(|e| {
+                    set.errors.push(e);
+                    Arc::downgrade(&(Arc::new(RejectHandler) as _))
+                });
+            for tcp_listen in &self.tcp_listen {
+                match tproxy::listen_tcp(
+                    plugin_name.as_str().into(),
+                    tcp_next.clone(),
+                    tcp_listen.inner,
+                ) {
+                    Ok(handle) => set.fully_constructed.long_running_tasks.push(handle),
+                    Err(e) => {
+                        set.errors.push(LoadError::Io {
+                            plugin: plugin_name.clone(),
+                            error: e,
+                        });
+                    }
+                }
+            }
+        }
+        if !self.udp_listen.is_empty() {
+            let udp_next = set
+                .get_or_create_datagram_handler(plugin_name.clone(), self.udp_next)
+                .unwrap_or_else(|e| {
+                    set.errors.push(e);
+                    Arc::downgrade(&(Arc::new(RejectHandler) as _))
+                });
+            for udp_listen in &self.udp_listen {
+                match tproxy::listen_udp(
+                    plugin_name.as_str().into(),
+                    udp_next.clone(),
+                    udp_listen.inner,
+                ) {
+                    Ok(handle) => set.fully_constructed.long_running_tasks.push(handle),
+                    Err(e) => {
+                        set.errors.push(LoadError::Io {
+                            plugin: plugin_name.clone(),
+                            error: e,
+                        });
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(all(feature = "plugins", not(target_os = "linux")))]
+    fn load(&mut self, plugin_name: String, _set: &mut PartialPluginSet) -> LoadResult<()> {
+        Err(LoadError::Io {
+            plugin: plugin_name,
+            error: std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "transparent proxy is only supported on Linux",
+            ),
+        })
+    }
+}
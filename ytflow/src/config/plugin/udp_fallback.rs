@@ -0,0 +1,64 @@
+use serde::Deserialize;
+
+use crate::config::factory::*;
+use crate::config::*;
+
+fn default_probe_window() -> u64 {
+    3000
+}
+
+/// Param `udp_fallback` of plugins providing datagram outbounds. Sessions are
+/// carried by `next`, a datagram outbound over a stream based transport such as
+/// `trojan-client.udp`, once UDP egress to the destination looks blocked.
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+#[derive(Clone, Copy, Deserialize)]
+pub(super) struct UdpFallbackConfig<'a> {
+    pub(super) next: &'a str,
+    /// In milliseconds. UDP is deemed blocked if nothing comes back within
+    /// this period after the first datagram is sent.
+    #[serde(default = "default_probe_window")]
+    pub(super) probe_window: u64,
+}
+
+impl<'a> UdpFallbackConfig<'a> {
+    pub(super) fn validate(&self, plugin: &str) -> ConfigResult<()> {
+        if self.probe_window == 0 {
+            return Err(ConfigError::InvalidParam {
+                plugin: plugin.to_string(),
+                field: "udp_fallback.probe_window",
+            });
+        }
+        Ok(())
+    }
+
+    pub(super) fn requires(&self) -> Descriptor<&'a str> {
+        Descriptor {
+            descriptor: self.next,
+            r#type: AccessPointType::DATAGRAM_SESSION_FACTORY,
+        }
+    }
+
+    /// Wrap `udp` so that its sessions fall back to `next` when UDP is blocked.
+    #[cfg(feature = "plugins")]
+    pub(super) fn wrap(
+        &self,
+        plugin_name: String,
+        udp: Arc<dyn crate::flow::DatagramSessionFactory>,
+        set: &mut PartialPluginSet,
+    ) -> crate::plugin::udp_fallback::UdpFallbackFactory {
+        use crate::plugin::null::Null;
+        use crate::plugin::udp_fallback::UdpFallbackFactory;
+
+        let tcp = set
+            .get_or_create_datagram_outbound(plugin_name, self.next)
+            .unwrap_or_else(|e| {
+                set.errors.push(e);
+                Arc::downgrade(&(Arc::new(Null) as _))
+            });
+        UdpFallbackFactory::new(
+            udp,
+            tcp,
+            std::time::Duration::from_millis(self.probe_window),
+        )
+    }
+}
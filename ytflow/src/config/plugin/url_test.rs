@@ -0,0 +1,214 @@
+use serde::Deserialize;
+
+use crate::config::factory::*;
+use crate::config::*;
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum SelectModeParam {
+    #[default]
+    Fastest,
+    Fallback,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum StickyKeyParam {
+    Destination,
+    Source,
+}
+
+fn default_url() -> &'static str {
+    "https://www.gstatic.com/generate_204"
+}
+
+fn default_interval() -> u64 {
+    300_000
+}
+
+fn default_timeout() -> u64 {
+    5_000
+}
+
+fn default_tolerance() -> u64 {
+    50
+}
+
+fn default_sticky_ttl() -> u64 {
+    600_000
+}
+
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+#[derive(Clone, Deserialize)]
+struct Candidate<'a> {
+    name: String,
+    tcp_next: &'a str,
+    udp_next: &'a str,
+    /// Names of probers in front of this candidate. It is skipped while any
+    /// of them is down.
+    #[serde(borrow, default)]
+    probers: Vec<&'a str>,
+}
+
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+#[derive(Clone, Deserialize)]
+pub struct UrlTestFactory<'a> {
+    #[serde(borrow)]
+    candidates: Vec<Candidate<'a>>,
+    #[serde(default = "default_url")]
+    url: &'a str,
+    #[serde(default)]
+    mode: SelectModeParam,
+    /// Interval between probing rounds in milliseconds.
+    #[serde(default = "default_interval")]
+    interval: u64,
+    /// Timeout of a single probe in milliseconds.
+    #[serde(default = "default_timeout")]
+    timeout: u64,
+    /// In `fastest` mode, the current candidate is kept unless another one is
+    /// faster by more than this many milliseconds.
+    #[serde(default = "default_tolerance")]
+    tolerance: u64,
+    /// Keep flows with the same destination host or client address on the
+    /// candidate of the previous flow, even after another one is selected.
+    #[serde(default)]
+    sticky: Option<StickyKeyParam>,
+    /// Milliseconds after the last flow before a sticky pin is dropped.
+    #[serde(default = "default_sticky_ttl")]
+    sticky_ttl: u64,
+}
+
+impl<'de> UrlTestFactory<'de> {
+    pub(in super::super) fn parse(plugin: &'de Plugin) -> ConfigResult<ParsedPlugin<'de, Self>> {
+        let Plugin { name, param, .. } = plugin;
+        let config: Self = parse_param(name, param)?;
+        let invalid = |field| ConfigError::InvalidParam {
+            plugin: name.clone(),
+            field,
+        };
+        if config.candidates.is_empty() {
+            return Err(invalid("candidates"));
+        }
+        let url: http::Uri = config.url.parse().map_err(|_| invalid("url"))?;
+        if !matches!(url.scheme_str(), Some("http" | "https")) || url.host().is_none() {
+            return Err(invalid("url"));
+        }
+        if config.interval == 0 {
+            return Err(invalid("interval"));
+        }
+        if config.timeout == 0 {
+            return Err(invalid("timeout"));
+        }
+        if config.sticky.is_some() && config.sticky_ttl == 0 {
+            return Err(invalid("sticky_ttl"));
+        }
+
+        Ok(ParsedPlugin {
+            requires: config
+                .candidates
+                .iter()
+                .flat_map(|c| {
+                    [
+                        Descriptor {
+                            descriptor: c.tcp_next,
+                            r#type: AccessPointType::STREAM_OUTBOUND_FACTORY,
+                        },
+                        Descriptor {
+                            descriptor: c.udp_next,
+                            r#type: AccessPointType::DATAGRAM_SESSION_FACTORY,
+                        },
+                    ]
+                })
+                .collect(),
+            provides: vec![
+                Descriptor {
+                    descriptor: name.clone() + ".tcp",
+                    r#type: AccessPointType::STREAM_OUTBOUND_FACTORY,
+                },
+                Descriptor {
+                    descriptor: name.clone() + ".udp",
+                    r#type: AccessPointType::DATAGRAM_SESSION_FACTORY,
+                },
+            ],
+            factory: config,
+            resources: vec![],
+        })
+    }
+}
+
+impl<'de> Factory for UrlTestFactory<'de> {
+    #[cfg(feature = "plugins")]
+    fn load(&mut self, plugin_name: String, set: &mut PartialPluginSet) -> LoadResult<()> {
+        use std::time::Duration;
+
+        use crate::plugin::null::Null;
+        use crate::plugin::url_test;
+
+        let mut candidates = Vec::with_capacity(self.candidates.len());
+        for candidate in &self.candidates {
+            let tcp_next =
+                match set.get_or_create_stream_outbound(plugin_name.clone(), candidate.tcp_next) {
+                    Ok(t) => t,
+                    Err(e) => {
+                        set.errors.push(e);
+                        Arc::downgrade(&(Arc::new(Null) as _))
+                    }
+                };
+            let udp_next = match set
+                .get_or_create_datagram_outbound(plugin_name.clone(), candidate.udp_next)
+            {
+                Ok(u) => u,
+                Err(e) => {
+                    set.errors.push(e);
+                    Arc::downgrade(&(Arc::new(Null) as _))
+                }
+            };
+            candidates.push(url_test::Candidate {
+                name: candidate.name.clone(),
+                tcp_next,
+                udp_next,
+                probers: candidate.probers.iter().map(|p| p.to_string()).collect(),
+            });
+        }
+        let url_test = Arc::new(url_test::UrlTest::new(
+            candidates,
+            self.url.parse().expect("URL has been validated"),
+            Duration::from_millis(self.interval),
+            Duration::from_millis(self.timeout),
+            Duration::from_millis(self.tolerance),
+            match self.mode {
+                SelectModeParam::Fastest => url_test::SelectMode::Fastest,
+                SelectModeParam::Fallback => url_test::SelectMode::Fallback,
+            },
+            self.sticky.map(|key| url_test::Sticky {
+                key: match key {
+                    StickyKeyParam::Destination => url_test::StickyKey::Destination,
+                    StickyKeyParam::Source => url_test::StickyKey::Source,
+                },
+                ttl: Duration::from_millis(self.sticky_ttl),
+            }),
+            set.control_hub.kill_switch().clone(),
+        ));
+        set.fully_constructed.stream_outbounds.insert(
+            plugin_name.clone() + ".tcp",
+            Arc::new(url_test::StreamUrlTestFactory {
+                url_test: url_test.clone(),
+            }),
+        );
+        set.fully_constructed.datagram_outbounds.insert(
+            plugin_name.clone() + ".udp",
+            Arc::new(url_test::DatagramUrlTestFactory {
+                url_test: url_test.clone(),
+            }),
+        );
+        set.control_hub.create_plugin_control(
+            plugin_name.clone(),
+            "url-test",
+            url_test::Responder::new(url_test.clone()),
+        );
+        set.fully_constructed.long_running_tasks.push(tokio::spawn(
+            url_test.run(plugin_name, set.control_hub.events().clone()),
+        ));
+        Ok(())
+    }
+}
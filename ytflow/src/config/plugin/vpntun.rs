@@ -1,7 +1,8 @@
 use std::cell::RefCell;
+use std::collections::BTreeMap;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
-use cidr::{Ipv4Cidr, Ipv6Cidr};
+use cidr::{Ipv4Cidr, Ipv6Cidr, Ipv6Inet};
 
 use crate::config::factory::*;
 use crate::config::HumanRepr;
@@ -18,17 +19,217 @@ thread_local! {
 pub struct VpnTunFactory {
     pub ipv4: Option<HumanRepr<Ipv4Addr>>,
     pub ipv6: Option<HumanRepr<Ipv6Addr>>,
+    /// Additional IPv6 addresses assigned to the tunnel, e.g. from a delegated prefix.
+    #[serde(default)]
+    pub ipv6_prefixes: Vec<VpnIpv6Prefix>,
     pub ipv4_route: Vec<HumanRepr<Ipv4Cidr>>,
     pub ipv6_route: Vec<HumanRepr<Ipv6Cidr>>,
     pub dns: Vec<HumanRepr<IpAddr>>,
+    /// Search domains appended to single-label names, such as `corp.example.com`.
+    #[serde(default)]
+    pub dns_suffixes: Vec<String>,
+    /// Static addresses of hostnames that must resolve while the tunnel is up,
+    /// typically intranet names unknown to the public DNS.
+    #[serde(default)]
+    pub hosts: BTreeMap<String, Vec<HumanRepr<IpAddr>>>,
     // Use String so that the struct can be 'static.
     pub web_proxy: Option<String>,
+    /// Ask the VPN entrypoint to keep blocking traffic when the tunnel goes
+    /// down instead of falling back to direct routes, if the platform allows.
+    /// Pair it with `kill_switch` of the ip-stack consuming this TUN.
+    #[serde(default)]
+    pub kill_switch: bool,
+    /// MTU of the tunnel interface. The ip-stack consuming this TUN also
+    /// adopts it unless configured otherwise.
+    #[serde(default)]
+    pub mtu: Option<u16>,
 }
 
+/// Interface settings of a TUN provided by vpn-tun, for the ip-stack
+/// consuming it to fill in what it is not configured with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TunInterface {
+    pub ipv4: Option<Ipv4Addr>,
+    pub ipv6: Option<Ipv6Addr>,
+    pub mtu: Option<u16>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct VpnIpv6Prefix {
+    /// Interface address together with the length of the prefix it belongs to,
+    /// such as `2001:db8:1::1/56`.
+    pub address: HumanRepr<Ipv6Inet>,
+    /// Whether the whole prefix should be routed into the tunnel.
+    #[serde(default = "default_announce")]
+    pub announce: bool,
+    pub metric: Option<u32>,
+}
+
+fn default_announce() -> bool {
+    true
+}
+
+/// An IPv6 route to be set up by the VPN entrypoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VpnRouteAssignment {
+    pub destination: Ipv6Cidr,
+    /// Preferred source address for this route, if any.
+    pub source: Option<Ipv6Addr>,
+    pub metric: Option<u32>,
+}
+
+/// Name resolution settings to be pushed by the VPN entrypoint, e.g. into a
+/// `VpnDomainNameAssignment` on Windows.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VpnDomainNameAssignment {
+    pub search_suffixes: Vec<String>,
+    pub hosts: Vec<VpnHostOverride>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VpnHostOverride {
+    /// Fully qualified name without the trailing dot, in lowercase.
+    pub name: String,
+    pub addresses: Vec<IpAddr>,
+}
+
+/// Lowercases a domain name and strips the leading and trailing dots. Returns
+/// `None` if nothing is left or the name contains whitespace.
+fn normalize_domain_name(name: &str) -> Option<String> {
+    let name = name.trim().trim_matches('.');
+    if name.is_empty() || name.contains(char::is_whitespace) || name.contains("..") {
+        return None;
+    }
+    Some(name.to_ascii_lowercase())
+}
+
+/// The minimum MTU of links carrying IPv6, as per RFC 8200.
+pub const MIN_TUN_MTU: u16 = 1280;
+
 impl VpnTunFactory {
+    pub fn interface(&self) -> TunInterface {
+        TunInterface {
+            ipv4: self.ipv4.as_ref().map(|a| a.inner),
+            ipv6: self.ipv6_addresses().first().map(|a| a.address()),
+            mtu: self.mtu,
+        }
+    }
+
+    /// All IPv6 addresses to be assigned to the tunnel interface. The legacy `ipv6`
+    /// field, if present, comes first with a prefix length of 128.
+    pub fn ipv6_addresses(&self) -> Vec<Ipv6Inet> {
+        let mut addrs: Vec<Ipv6Inet> = Vec::with_capacity(self.ipv6_prefixes.len() + 1);
+        let legacy = self
+            .ipv6
+            .as_ref()
+            .map(|a| Ipv6Inet::new(a.inner, 128).expect("128 is a valid IPv6 prefix length"));
+        for addr in legacy
+            .into_iter()
+            .chain(self.ipv6_prefixes.iter().map(|p| p.address.inner))
+        {
+            if !addrs.iter().any(|a| a.address() == addr.address()) {
+                addrs.push(addr);
+            }
+        }
+        addrs
+    }
+
+    /// IPv6 routes combining `ipv6_route` with the prefixes marked for announcement.
+    /// Duplicated destinations are merged, keeping the first occurrence.
+    pub fn ipv6_route_assignments(&self) -> Vec<VpnRouteAssignment> {
+        let explicit = self.ipv6_route.iter().map(|r| VpnRouteAssignment {
+            destination: r.inner,
+            source: None,
+            metric: None,
+        });
+        let announced =
+            self.ipv6_prefixes
+                .iter()
+                .filter(|p| p.announce)
+                .map(|p| VpnRouteAssignment {
+                    destination: p.address.inner.network(),
+                    source: Some(p.address.inner.address()),
+                    metric: p.metric,
+                });
+        let mut routes: Vec<VpnRouteAssignment> = Vec::new();
+        for route in announced.chain(explicit) {
+            if !routes.iter().any(|r| r.destination == route.destination) {
+                routes.push(route);
+            }
+        }
+        routes
+    }
+
+    /// DNS suffixes and static hosts with normalized names. Duplicated suffixes
+    /// are dropped and addresses of hosts differing only in case are merged.
+    pub fn domain_name_assignment(&self) -> VpnDomainNameAssignment {
+        let mut search_suffixes: Vec<String> = Vec::with_capacity(self.dns_suffixes.len());
+        for suffix in self
+            .dns_suffixes
+            .iter()
+            .filter_map(|s| normalize_domain_name(s))
+        {
+            if !search_suffixes.contains(&suffix) {
+                search_suffixes.push(suffix);
+            }
+        }
+        let mut hosts: Vec<VpnHostOverride> = Vec::with_capacity(self.hosts.len());
+        for (name, addrs) in &self.hosts {
+            let Some(name) = normalize_domain_name(name) else {
+                continue;
+            };
+            let idx = match hosts.iter().position(|h| h.name == name) {
+                Some(idx) => idx,
+                None => {
+                    hosts.push(VpnHostOverride {
+                        name,
+                        addresses: vec![],
+                    });
+                    hosts.len() - 1
+                }
+            };
+            let addresses = &mut hosts[idx].addresses;
+            for addr in addrs.iter().map(|a| a.inner) {
+                if !addresses.contains(&addr) {
+                    addresses.push(addr);
+                }
+            }
+        }
+        VpnDomainNameAssignment {
+            search_suffixes,
+            hosts,
+        }
+    }
+
     pub(in super::super) fn parse(plugin: &Plugin) -> ConfigResult<ParsedPlugin<'_, Self>> {
         let Plugin { name, param, .. } = plugin;
         let config: Self = parse_param(name, param)?;
+        if config
+            .dns_suffixes
+            .iter()
+            .any(|s| normalize_domain_name(s).is_none())
+        {
+            return Err(ConfigError::InvalidParam {
+                plugin: name.clone(),
+                field: "dns_suffixes",
+            });
+        }
+        if config.mtu.is_some_and(|m| m < MIN_TUN_MTU) {
+            return Err(ConfigError::InvalidParam {
+                plugin: name.clone(),
+                field: "mtu",
+            });
+        }
+        if config
+            .hosts
+            .iter()
+            .any(|(h, addrs)| normalize_domain_name(h).is_none() || addrs.is_empty())
+        {
+            return Err(ConfigError::InvalidParam {
+                plugin: name.clone(),
+                field: "hosts",
+            });
+        }
         Ok(ParsedPlugin {
             factory: config,
             requires: vec![],
@@ -41,7 +242,15 @@ impl VpnTunFactory {
     }
 }
 impl Factory for VpnTunFactory {
-    #[cfg(feature = "plugins")]
+    #[cfg(all(feature = "plugins", not(feature = "tun")))]
+    fn load(&mut self, plugin_name: String, _set: &mut PartialPluginSet) -> LoadResult<()> {
+        Err(LoadError::NotBuiltIn {
+            plugin: plugin_name,
+            r#type: "vpn-tun",
+            feature: "tun",
+        })
+    }
+    #[cfg(feature = "tun")]
     fn load(&mut self, plugin_name: String, set: &mut PartialPluginSet) -> LoadResult<()> {
         let tun = (ON_VPNTUN.with(|cb| cb.borrow_mut().take())).ok_or_else(|| {
             ConfigError::TooManyPlugin {
@@ -49,7 +258,163 @@ impl Factory for VpnTunFactory {
                 r#type: "vpn-tun",
             }
         })?(self);
-        set.fully_constructed.tun.insert(plugin_name + ".tun", tun);
+        let descriptor = plugin_name + ".tun";
+        set.tun_interfaces
+            .insert(descriptor.clone(), self.interface());
+        set.fully_constructed.tun.insert(descriptor, tun);
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn factory(dns_suffixes: &[&str], hosts: &[(&str, &[&str])]) -> VpnTunFactory {
+        VpnTunFactory {
+            ipv4: None,
+            ipv6: None,
+            ipv6_prefixes: vec![],
+            ipv4_route: vec![],
+            ipv6_route: vec![],
+            dns: vec![],
+            dns_suffixes: dns_suffixes.iter().map(|s| s.to_string()).collect(),
+            hosts: hosts
+                .iter()
+                .map(|(h, addrs)| {
+                    let addrs = addrs
+                        .iter()
+                        .map(|a| HumanRepr {
+                            inner: a.parse().unwrap(),
+                        })
+                        .collect();
+                    (h.to_string(), addrs)
+                })
+                .collect(),
+            web_proxy: None,
+            kill_switch: false,
+            mtu: None,
+        }
+    }
+
+    #[test]
+    fn test_domain_name_assignment_normalizes_names() {
+        let f = factory(
+            &["corp.example.com.", ".Corp.Example.com", "lan"],
+            &[
+                ("Wiki.corp.example.com", &["10.0.0.1"]),
+                ("wiki.corp.example.com.", &["10.0.0.1", "fd00::1"]),
+            ],
+        );
+        assert_eq!(
+            f.domain_name_assignment(),
+            VpnDomainNameAssignment {
+                search_suffixes: vec!["corp.example.com".into(), "lan".into()],
+                hosts: vec![VpnHostOverride {
+                    name: "wiki.corp.example.com".into(),
+                    addresses: vec!["10.0.0.1".parse().unwrap(), "fd00::1".parse().unwrap()],
+                }],
+            }
+        );
+    }
+
+    #[test]
+    fn test_interface_prefers_legacy_ipv6() {
+        let mut f = factory(&[], &[]);
+        f.ipv4 = Some(HumanRepr {
+            inner: "10.8.0.1".parse().unwrap(),
+        });
+        f.ipv6_prefixes = vec![VpnIpv6Prefix {
+            address: HumanRepr {
+                inner: "2001:db8:1::1/56".parse().unwrap(),
+            },
+            announce: true,
+            metric: None,
+        }];
+        f.mtu = Some(9000);
+        assert_eq!(
+            f.interface(),
+            TunInterface {
+                ipv4: Some("10.8.0.1".parse().unwrap()),
+                ipv6: Some("2001:db8:1::1".parse().unwrap()),
+                mtu: Some(9000),
+            }
+        );
+        f.ipv6 = Some(HumanRepr {
+            inner: "fd00::2".parse().unwrap(),
+        });
+        assert_eq!(f.interface().ipv6, Some("fd00::2".parse().unwrap()));
+    }
+
+    fn prefix(address: &str, announce: bool, metric: Option<u32>) -> VpnIpv6Prefix {
+        VpnIpv6Prefix {
+            address: HumanRepr {
+                inner: address.parse().unwrap(),
+            },
+            announce,
+            metric,
+        }
+    }
+
+    #[test]
+    fn test_ipv6_addresses_dedup_legacy_first() {
+        let mut f = factory(&[], &[]);
+        f.ipv6 = Some(HumanRepr {
+            inner: "2001:db8:1::1".parse().unwrap(),
+        });
+        f.ipv6_prefixes = vec![
+            prefix("2001:db8:1::1/56", true, None),
+            prefix("fd00:1::1/64", false, None),
+        ];
+        assert_eq!(
+            f.ipv6_addresses(),
+            [
+                "2001:db8:1::1/128".parse::<Ipv6Inet>().unwrap(),
+                "fd00:1::1/64".parse().unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ipv6_route_assignments_from_prefixes() {
+        let mut f = factory(&[], &[]);
+        f.ipv6_prefixes = vec![
+            prefix("2001:db8:1::1/56", true, Some(10)),
+            prefix("fd00:1::1/64", false, None),
+            prefix("2001:db8:1:2::1/56", true, None),
+        ];
+        f.ipv6_route = vec![
+            HumanRepr {
+                inner: "2001:db8:1::/56".parse().unwrap(),
+            },
+            HumanRepr {
+                inner: "::/0".parse().unwrap(),
+            },
+        ];
+        assert_eq!(
+            f.ipv6_route_assignments(),
+            [
+                // Announced prefixes are routed with their own address as the
+                // source and take precedence over the same explicit route.
+                // Unannounced and duplicated prefixes add no route.
+                VpnRouteAssignment {
+                    destination: "2001:db8:1::/56".parse().unwrap(),
+                    source: Some("2001:db8:1::1".parse().unwrap()),
+                    metric: Some(10),
+                },
+                VpnRouteAssignment {
+                    destination: "::/0".parse().unwrap(),
+                    source: None,
+                    metric: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_normalize_domain_name_rejects_invalid() {
+        assert_eq!(normalize_domain_name("."), None);
+        assert_eq!(normalize_domain_name("a b.com"), None);
+        assert_eq!(normalize_domain_name("a..com"), None);
+    }
+}
@@ -5,26 +5,99 @@ use serde::Deserialize;
 
 use crate::config::factory::*;
 use crate::config::*;
+use crate::flow::DEFAULT_HANDSHAKE_TIMEOUT;
 
 fn default_path() -> &'static str {
     "/"
 }
 
+fn default_handshake_timeout() -> u64 {
+    DEFAULT_HANDSHAKE_TIMEOUT.as_millis() as u64
+}
+
+/// How to pick one of the configured Host headers or paths for each
+/// connection.
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(super) enum RotationConfig {
+    #[default]
+    RoundRobin,
+    Random,
+}
+
+#[cfg(feature = "plugins")]
+impl RotationConfig {
+    pub(super) fn rotate(self, items: &[&str]) -> crate::plugin::rotation::Rotation<String> {
+        use crate::plugin::rotation::{Rotation, RotationMode};
+        Rotation::new(
+            items.iter().map(|s| s.to_string()).collect(),
+            match self {
+                RotationConfig::RoundRobin => RotationMode::RoundRobin,
+                RotationConfig::Random => RotationMode::Random,
+            },
+        )
+    }
+}
+
+/// Parse custom headers. A Host header is returned separately.
+pub(super) fn parse_headers<'a>(
+    plugin_name: &str,
+    headers: BTreeMap<&'a str, &'a str>,
+) -> ConfigResult<(Option<&'a str>, HeaderMap)> {
+    let mut host = None;
+    let mut header_map = HeaderMap::with_capacity(headers.len());
+    for (k, v) in headers {
+        if k.eq_ignore_ascii_case("host") {
+            host = Some(v);
+            continue;
+        }
+        let Ok(header) = HeaderName::from_bytes(k.as_bytes()) else {
+            return Err(ConfigError::InvalidParam {
+                plugin: plugin_name.into(),
+                field: "headers_header",
+            });
+        };
+        let Ok(value) = HeaderValue::from_str(v) else {
+            return Err(ConfigError::InvalidParam {
+                plugin: plugin_name.into(),
+                field: "headers_value",
+            });
+        };
+        header_map.insert(header, value);
+    }
+    Ok((host, header_map))
+}
+
 #[derive(Deserialize)]
 pub struct WsClientConfig<'a> {
     host: Option<&'a str>,
     #[serde(default = "default_path")]
     path: &'a str,
+    /// Rotated along with `host`.
+    #[serde(borrow, default)]
+    hosts: Vec<&'a str>,
+    /// Rotated along with `path`.
+    #[serde(borrow, default)]
+    paths: Vec<&'a str>,
+    #[serde(default)]
+    rotation: RotationConfig,
     #[serde(borrow)]
     headers: BTreeMap<&'a str, &'a str>,
+    /// In milliseconds.
+    #[serde(default = "default_handshake_timeout")]
+    handshake_timeout: u64,
     next: &'a str,
 }
 
 #[cfg_attr(not(feature = "plugins"), allow(dead_code))]
 pub struct WsClientFactory<'a> {
-    host: Option<&'a str>,
-    path: &'a str,
+    /// Empty if the Host header is derived from the destination.
+    hosts: Vec<&'a str>,
+    paths: Vec<&'a str>,
+    rotation: RotationConfig,
     headers: HeaderMap,
+    handshake_timeout: u64,
     next: &'a str,
 }
 
@@ -32,38 +105,41 @@ impl<'de> WsClientFactory<'de> {
     pub(in super::super) fn parse(plugin: &'de Plugin) -> ConfigResult<ParsedPlugin<'de, Self>> {
         let Plugin { name, param, .. } = plugin;
         let config: WsClientConfig = parse_param(name, param)?;
-        let next = config.next;
-        let mut headers = HeaderMap::with_capacity(config.headers.len());
-        for (k, v) in config.headers {
-            let Ok(header) = HeaderName::from_bytes(k.as_bytes()) else {
-                return Err(ConfigError::InvalidParam {
-                    plugin: name.clone(),
-                    field: "headers_header",
-                });
-            };
-            let Ok(value) = HeaderValue::from_str(v) else {
-                return Err(ConfigError::InvalidParam {
-                    plugin: name.clone(),
-                    field: "headers_value",
-                });
-            };
-            headers.insert(header, value);
+        if config.handshake_timeout == 0 {
+            return Err(ConfigError::InvalidParam {
+                plugin: name.clone(),
+                field: "handshake_timeout",
+            });
         }
+        let next = config.next;
+        let (header_host, headers) = parse_headers(name, config.headers)?;
+        // A Host header set in `headers` is treated as `host` to avoid sending it twice.
+        let host = config.host.or(header_host);
+        let hosts = host.into_iter().chain(config.hosts).collect();
+        let paths = std::iter::once(config.path).chain(config.paths).collect();
         Ok(ParsedPlugin {
             factory: WsClientFactory {
-                host: config.host,
-                path: config.path,
+                hosts,
+                paths,
+                rotation: config.rotation,
                 headers,
+                handshake_timeout: config.handshake_timeout,
                 next,
             },
             requires: vec![Descriptor {
                 descriptor: next,
                 r#type: AccessPointType::STREAM_OUTBOUND_FACTORY,
             }],
-            provides: vec![Descriptor {
-                descriptor: name.to_string() + ".tcp",
-                r#type: AccessPointType::STREAM_OUTBOUND_FACTORY,
-            }],
+            provides: vec![
+                Descriptor {
+                    descriptor: name.to_string() + ".tcp",
+                    r#type: AccessPointType::STREAM_OUTBOUND_FACTORY,
+                },
+                Descriptor {
+                    descriptor: name.to_string() + ".udp",
+                    r#type: AccessPointType::DATAGRAM_SESSION_FACTORY,
+                },
+            ],
             resources: vec![],
         })
     }
@@ -72,12 +148,16 @@ impl<'de> WsClientFactory<'de> {
 impl<'de> Factory for WsClientFactory<'de> {
     #[cfg(feature = "plugins")]
     fn load(&mut self, plugin_name: String, set: &mut PartialPluginSet) -> LoadResult<()> {
+        use std::time::Duration;
+
         use crate::plugin::null::Null;
         use crate::plugin::ws;
 
         let factory = Arc::new_cyclic(|weak| {
             set.stream_outbounds
                 .insert(plugin_name.clone() + ".tcp", weak.clone() as _);
+            set.datagram_outbounds
+                .insert(plugin_name.clone() + ".udp", weak.clone() as _);
             let next = match set.get_or_create_stream_outbound(plugin_name.clone(), self.next) {
                 Ok(next) => next,
                 Err(e) => {
@@ -87,15 +167,19 @@ impl<'de> Factory for WsClientFactory<'de> {
             };
 
             ws::WebSocketStreamOutboundFactory::new(
-                self.host.map(|s| s.to_owned()),
-                self.path.to_string(),
+                (!self.hosts.is_empty()).then(|| self.rotation.rotate(&self.hosts)),
+                self.rotation.rotate(&self.paths),
                 std::mem::take(&mut self.headers),
                 next,
+                Duration::from_millis(self.handshake_timeout),
             )
         });
         set.fully_constructed
             .stream_outbounds
-            .insert(plugin_name + ".tcp", factory);
+            .insert(plugin_name.clone() + ".tcp", factory.clone());
+        set.fully_constructed
+            .datagram_outbounds
+            .insert(plugin_name + ".udp", factory);
         Ok(())
     }
 }
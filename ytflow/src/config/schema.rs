@@ -0,0 +1,871 @@
+use serde::Serialize;
+
+use super::factory::AccessPointType;
+
+const SH: AccessPointType = AccessPointType::STREAM_HANDLER;
+const DSH: AccessPointType = AccessPointType::DATAGRAM_SESSION_HANDLER;
+const SOF: AccessPointType = AccessPointType::STREAM_OUTBOUND_FACTORY;
+const DSF: AccessPointType = AccessPointType::DATAGRAM_SESSION_FACTORY;
+const RESOLVER: AccessPointType = AccessPointType::RESOLVER;
+const TUN: AccessPointType = AccessPointType::TUN;
+
+/// Machine-readable description of a built-in plugin type, so that editors
+/// and validation tools do not have to hardcode the param layout.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct PluginSchema {
+    pub name: &'static str,
+    pub version: u16,
+    pub params: &'static [ParamSchema],
+    pub provides: &'static [ProvideSchema],
+}
+
+/// A field of the plugin param. Nested fields are named by their paths, where
+/// `[]` stands for any element of an array and `*` for any value of a map.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ParamSchema {
+    pub name: &'static str,
+    pub r#type: &'static str,
+    pub required: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<ParamDefault>,
+    /// Set when the field refers to access points provided by other plugins.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_point: Option<AccessPointType>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(untagged)]
+pub enum ParamDefault {
+    Bool(bool),
+    UInt(u64),
+    Float(f64),
+    Str(&'static str),
+    List(&'static [&'static str]),
+}
+
+/// An access point provided by the plugin. In `descriptor`, `{name}` stands
+/// for the plugin name and other placeholders for values of the named field.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ProvideSchema {
+    pub descriptor: &'static str,
+    pub r#type: AccessPointType,
+}
+
+const fn param(name: &'static str, r#type: &'static str) -> ParamSchema {
+    ParamSchema {
+        name,
+        r#type,
+        required: true,
+        default: None,
+        access_point: None,
+    }
+}
+
+const fn optional(
+    name: &'static str,
+    r#type: &'static str,
+    default: Option<ParamDefault>,
+) -> ParamSchema {
+    ParamSchema {
+        name,
+        r#type,
+        required: false,
+        default,
+        access_point: None,
+    }
+}
+
+const fn next(name: &'static str, access_point: AccessPointType) -> ParamSchema {
+    ParamSchema {
+        name,
+        r#type: "access-point",
+        required: true,
+        default: None,
+        access_point: Some(access_point),
+    }
+}
+
+const fn optional_next(name: &'static str, access_point: AccessPointType) -> ParamSchema {
+    ParamSchema {
+        required: false,
+        ..next(name, access_point)
+    }
+}
+
+const fn nexts(
+    name: &'static str,
+    r#type: &'static str,
+    access_point: AccessPointType,
+) -> ParamSchema {
+    ParamSchema {
+        r#type,
+        ..next(name, access_point)
+    }
+}
+
+const fn provide(descriptor: &'static str, r#type: AccessPointType) -> ProvideSchema {
+    ProvideSchema { descriptor, r#type }
+}
+
+const fn plugin(
+    name: &'static str,
+    params: &'static [ParamSchema],
+    provides: &'static [ProvideSchema],
+) -> PluginSchema {
+    PluginSchema {
+        name,
+        version: 0,
+        params,
+        provides,
+    }
+}
+
+const EMPTY_LIST: Option<ParamDefault> = Some(ParamDefault::List(&[]));
+const HANDSHAKE_TIMEOUT: Option<ParamDefault> = Some(ParamDefault::UInt(10_000));
+const PROBE_WINDOW: Option<ParamDefault> = Some(ParamDefault::UInt(3_000));
+
+const PROVIDES_HANDLERS: &[ProvideSchema] =
+    &[provide("{name}.tcp", SH), provide("{name}.udp", DSH)];
+const PROVIDES_OUTBOUNDS: &[ProvideSchema] =
+    &[provide("{name}.tcp", SOF), provide("{name}.udp", DSF)];
+const PROVIDES_DISPATCHER: &[ProvideSchema] = &[
+    provide("{name}.tcp", SH),
+    provide("{name}.udp", DSH),
+    provide("{name}.resolver", RESOLVER),
+];
+const PROVIDES_STREAM_OUTBOUND: &[ProvideSchema] = &[provide("{name}.tcp", SOF)];
+
+const PROVIDES_OUTBOUNDS_RESOLVER: &[ProvideSchema] = &[
+    provide("{name}.tcp", SOF),
+    provide("{name}.udp", DSF),
+    provide("{name}.resolver", RESOLVER),
+];
+
+/// Params of a dispatcher, followed by fields of the actions under each
+/// prefix.
+macro_rules! dispatcher_params {
+    ($($head:expr,)*; $($prefix:literal),*) => {
+        &[
+            $($head,)*
+            $(
+                optional_next(concat!($prefix, ".tcp"), SH),
+                optional_next(concat!($prefix, ".udp"), DSH),
+                optional_next(concat!($prefix, ".resolver"), RESOLVER),
+                optional(concat!($prefix, ".proxy_group"), "object", None),
+                param(concat!($prefix, ".proxy_group.group"), "string"),
+                optional(concat!($prefix, ".proxy_group.filter"), "string", None),
+                optional(
+                    concat!($prefix, ".proxy_group.policy"),
+                    "first | random | lowest-latency",
+                    Some(ParamDefault::Str("first")),
+                ),
+                optional(concat!($prefix, ".proxy_group.probe"), "destination", None),
+                optional(
+                    concat!($prefix, ".proxy_group.probe_interval"),
+                    "u64",
+                    Some(ParamDefault::UInt(300_000)),
+                ),
+                next(concat!($prefix, ".proxy_group.tcp_next"), SOF),
+                next(concat!($prefix, ".proxy_group.udp_next"), DSF),
+            )*
+        ]
+    };
+}
+
+static BUILTIN_PLUGIN_SCHEMAS: &[PluginSchema] = &[
+    plugin("reject", &[], PROVIDES_HANDLERS),
+    plugin("null", &[], PROVIDES_OUTBOUNDS_RESOLVER),
+    // v1 renamed `tcp.stacks` to `tcp.congestion_controls`.
+    PluginSchema {
+        version: 1,
+        ..plugin(
+            "ip-stack",
+            &[
+                next("tun", TUN),
+                next("tcp_next", SH),
+                next("udp_next", DSH),
+                optional("tcp", "object", None),
+                optional("tcp.timestamps", "bool", Some(ParamDefault::Bool(true))),
+                optional(
+                    "tcp.min_buffer",
+                    "usize",
+                    Some(ParamDefault::UInt(16 * 1024)),
+                ),
+                optional(
+                    "tcp.max_buffer",
+                    "usize",
+                    Some(ParamDefault::UInt(1024 * 1024)),
+                ),
+                optional(
+                    "tcp.congestion_controls",
+                    "[string]",
+                    Some(ParamDefault::List(&["none"])),
+                ),
+                optional("tcp.rx_buffer", "usize", None),
+                optional("tcp.tx_buffer", "usize", None),
+                optional("tcp.max_sockets", "usize", Some(ParamDefault::UInt(1024))),
+                optional(
+                    "udp_session_timeout",
+                    "u64",
+                    Some(ParamDefault::UInt(120_000)),
+                ),
+                optional("kill_switch", "bool", Some(ParamDefault::Bool(false))),
+                optional("interface", "object", None),
+                optional("interface.ipv4", "ipv4-inet", None),
+                optional("interface.ipv6", "ipv6-inet", None),
+                optional("interface.ipv4_gateway", "ipv4-addr", None),
+                optional("interface.ipv6_gateway", "ipv6-addr", None),
+                optional("interface.mtu", "u16", None),
+            ],
+            &[],
+        )
+    },
+    plugin(
+        "socket-listener",
+        &[
+            optional("tcp_listen", "[string]", EMPTY_LIST),
+            optional("udp_listen", "[string]", EMPTY_LIST),
+            next("tcp_next", SH),
+            next("udp_next", DSH),
+            optional("proxy_protocol", "bool", Some(ParamDefault::Bool(false))),
+            optional("reuse_port", "bool", Some(ParamDefault::Bool(false))),
+            optional("port_fallback", "u16", Some(ParamDefault::UInt(0))),
+        ],
+        &[],
+    ),
+    plugin(
+        "tproxy-listener",
+        &[
+            optional("tcp_listen", "[socket-addr]", EMPTY_LIST),
+            optional("udp_listen", "[socket-addr]", EMPTY_LIST),
+            next("tcp_next", SH),
+            next("udp_next", DSH),
+        ],
+        &[],
+    ),
+    plugin(
+        "vpn-tun",
+        &[
+            optional("ipv4", "ipv4-addr", None),
+            optional("ipv6", "ipv6-addr", None),
+            optional("ipv6_prefixes", "[object]", EMPTY_LIST),
+            param("ipv6_prefixes[].address", "ipv6-inet"),
+            optional(
+                "ipv6_prefixes[].announce",
+                "bool",
+                Some(ParamDefault::Bool(true)),
+            ),
+            optional("ipv6_prefixes[].metric", "u32", None),
+            param("ipv4_route", "[ipv4-cidr]"),
+            param("ipv6_route", "[ipv6-cidr]"),
+            param("dns", "[ip-addr]"),
+            optional("dns_suffixes", "[string]", EMPTY_LIST),
+            optional("hosts", "map<string, [ip-addr]>", None),
+            optional("web_proxy", "string", None),
+            optional("kill_switch", "bool", Some(ParamDefault::Bool(false))),
+            optional("mtu", "u16", None),
+        ],
+        &[provide("{name}.tun", TUN)],
+    ),
+    plugin(
+        "host-resolver",
+        &[
+            optional("doh", "[object]", EMPTY_LIST),
+            param("doh[].url", "string"),
+            next("doh[].next", SOF),
+            optional("doh[].use_get", "bool", Some(ParamDefault::Bool(false))),
+            nexts("udp", "[access-point]", DSF),
+            nexts("tcp", "[access-point]", SOF),
+            optional("min_ttl", "u32", None),
+            optional("max_ttl", "u32", None),
+        ],
+        &[provide("{name}.resolver", RESOLVER)],
+    ),
+    plugin(
+        "fake-ip",
+        &[
+            param("prefix_v4", "[u8; 2]"),
+            param("prefix_v6", "[u8; 14]"),
+            next("fallback", RESOLVER),
+        ],
+        &[provide("{name}.resolver", RESOLVER)],
+    ),
+    plugin(
+        "system-resolver",
+        &[],
+        &[provide("{name}.resolver", RESOLVER)],
+    ),
+    plugin(
+        "switch",
+        &[
+            param("choices", "[object]"),
+            param("choices[].name", "string"),
+            param("choices[].description", "string"),
+            next("choices[].tcp_next", SH),
+            next("choices[].udp_next", DSH),
+        ],
+        PROVIDES_HANDLERS,
+    ),
+    plugin(
+        "dns-server",
+        &[
+            param("concurrency_limit", "u32"),
+            next("resolver", RESOLVER),
+            param("ttl", "u32"),
+            optional("min_ttl", "u32", None),
+            optional("max_ttl", "u32", None),
+            nexts("tcp_map_back", "[access-point]", SH),
+            nexts("udp_map_back", "[access-point]", DSH),
+            optional("query_log", "object", None),
+            param("query_log.mode", "full | hashed-domains | aggregate-only"),
+            optional("query_log.capacity", "u32", Some(ParamDefault::UInt(1000))),
+        ],
+        &[
+            provide("{name}.udp", DSH),
+            provide("{name}.tcp_map_back.{tcp_map_back}", SH),
+            provide("{name}.udp_map_back.{udp_map_back}", DSH),
+        ],
+    ),
+    plugin(
+        "dns-hijack",
+        &[
+            optional("except", "[ip-cidr]", EMPTY_LIST),
+            optional_next("dns_tcp", SH),
+            next("dns_udp", DSH),
+            next("tcp_next", SH),
+            next("udp_next", DSH),
+        ],
+        PROVIDES_HANDLERS,
+    ),
+    plugin(
+        "socks5-server",
+        &[
+            next("tcp_next", SH),
+            next("udp_next", DSH),
+            optional("user", "bytes", None),
+            optional("pass", "bytes", None),
+        ],
+        PROVIDES_HANDLERS,
+    ),
+    plugin(
+        "http-proxy-server",
+        &[
+            next("tcp_next", SH),
+            optional("user", "bytes", None),
+            optional("pass", "bytes", None),
+        ],
+        &[provide("{name}.tcp", SH)],
+    ),
+    plugin(
+        "http-obfs-server",
+        &[next("next", SH)],
+        &[provide("{name}.tcp", SH)],
+    ),
+    plugin(
+        "resolve-dest",
+        &[
+            next("resolver", RESOLVER),
+            optional_next("tcp_next", SH),
+            optional_next("udp_next", DSH),
+        ],
+        PROVIDES_HANDLERS,
+    ),
+    plugin(
+        "simple-dispatcher",
+        &[
+            param("rules", "[object]"),
+            param("rules[].src", "condition"),
+            param("rules[].dst", "condition"),
+            param("rules[].is_udp", "bool"),
+            next("rules[].next", SH.union(DSH)),
+            next("fallback_tcp", SH),
+            next("fallback_udp", DSH),
+            optional_next("quic", DSH),
+        ],
+        PROVIDES_HANDLERS,
+    ),
+    plugin(
+        "rule-dispatcher",
+        dispatcher_params!(
+            optional_next("resolver", RESOLVER),
+            param("source", "resource-source"),
+            optional("geoip", "resource-source", None),
+            param("actions", "map<string, object>"),
+            param("rules", "map<string, string>"),
+            param("fallback", "object"),
+            optional("verdict_cache", "object", None),
+            optional("verdict_cache.size", "usize", Some(ParamDefault::UInt(4096))),
+            optional("verdict_cache.ttl", "u64", Some(ParamDefault::UInt(300))),
+            optional_next("quic", DSH),
+            ; "actions.*", "fallback"
+        ),
+        PROVIDES_DISPATCHER,
+    ),
+    plugin(
+        "list-dispatcher",
+        dispatcher_params!(
+            optional_next("resolver", RESOLVER),
+            param("source", "resource-source"),
+            param("action", "object"),
+            param("fallback", "object"),
+            ; "action", "fallback"
+        ),
+        PROVIDES_DISPATCHER,
+    ),
+    plugin(
+        "alpn-dispatcher",
+        &[
+            nexts("alpn", "map<string, access-point>", SH),
+            next("fallback", SH),
+        ],
+        &[provide("{name}.tcp", SH)],
+    ),
+    plugin(
+        "mixed-listener",
+        &[
+            optional_next("socks5_next", SH),
+            optional_next("http_next", SH),
+            optional_next("tls_next", SH),
+            next("fallback", SH),
+        ],
+        &[provide("{name}.tcp", SH)],
+    ),
+    plugin(
+        "forward",
+        &[
+            optional("request_timeout", "u64", Some(ParamDefault::UInt(100))),
+            next("tcp_next", SOF),
+            next("udp_next", DSF),
+        ],
+        PROVIDES_HANDLERS,
+    ),
+    plugin(
+        "dyn-outbound",
+        &[next("tcp_next", SOF), next("udp_next", DSF)],
+        PROVIDES_OUTBOUNDS,
+    ),
+    plugin(
+        "shadowsocks-client",
+        &[
+            param("method", "string"),
+            param("password", "bytes"),
+            next("tcp_next", SOF),
+            next("udp_next", DSF),
+            optional("udp_fallback", "object", None),
+            next("udp_fallback.next", DSF),
+            optional("udp_fallback.probe_window", "u64", PROBE_WINDOW),
+        ],
+        PROVIDES_OUTBOUNDS,
+    ),
+    plugin(
+        "socks5-client",
+        &[
+            next("tcp_next", SOF),
+            next("udp_next", DSF),
+            optional("handshake_timeout", "u64", HANDSHAKE_TIMEOUT),
+            optional(
+                "version",
+                "socks5 | socks4 | socks4a",
+                Some(ParamDefault::Str("socks5")),
+            ),
+            optional("user", "bytes", None),
+            optional("pass", "bytes", None),
+        ],
+        PROVIDES_OUTBOUNDS,
+    ),
+    plugin(
+        "http-proxy-client",
+        &[
+            param("user", "bytes"),
+            param("pass", "bytes"),
+            optional("handshake_timeout", "u64", HANDSHAKE_TIMEOUT),
+            next("tcp_next", SOF),
+        ],
+        PROVIDES_STREAM_OUTBOUND,
+    ),
+    plugin(
+        "tls-client",
+        &[
+            optional("sni", "string", None),
+            optional("alpn", "[string]", EMPTY_LIST),
+            optional("skip_cert_check", "bool", Some(ParamDefault::Bool(false))),
+            optional(
+                "trust",
+                "system | bundled | custom",
+                Some(ParamDefault::Str("system")),
+            ),
+            optional("ca", "string", None),
+            optional("handshake_timeout", "u64", HANDSHAKE_TIMEOUT),
+            next("next", SOF),
+        ],
+        PROVIDES_STREAM_OUTBOUND,
+    ),
+    plugin(
+        "trojan-client",
+        &[param("password", "bytes"), next("tls_next", SOF)],
+        PROVIDES_OUTBOUNDS,
+    ),
+    plugin(
+        "vmess-client",
+        &[
+            param("user_id", "uuid"),
+            optional("alter_id", "u16", Some(ParamDefault::UInt(0))),
+            optional("security", "string", Some(ParamDefault::Str("auto"))),
+            next("tcp_next", SOF),
+        ],
+        PROVIDES_OUTBOUNDS,
+    ),
+    plugin(
+        "http-obfs-client",
+        &[
+            param("host", "string"),
+            param("path", "string"),
+            optional("hosts", "[string]", EMPTY_LIST),
+            optional("paths", "[string]", EMPTY_LIST),
+            optional(
+                "rotation",
+                "round-robin | random",
+                Some(ParamDefault::Str("round-robin")),
+            ),
+            optional("headers", "map<string, string>", None),
+            next("next", SOF),
+        ],
+        PROVIDES_STREAM_OUTBOUND,
+    ),
+    plugin(
+        "tls-obfs-client",
+        &[param("host", "string"), next("next", SOF)],
+        PROVIDES_STREAM_OUTBOUND,
+    ),
+    plugin(
+        "scramble",
+        &[
+            param("method", "xor | aes-ctr"),
+            param("key", "bytes"),
+            next("next", DSF),
+        ],
+        &[provide("{name}.udp", DSF)],
+    ),
+    plugin(
+        "ws-client",
+        &[
+            optional("host", "string", None),
+            optional("path", "string", Some(ParamDefault::Str("/"))),
+            optional("hosts", "[string]", EMPTY_LIST),
+            optional("paths", "[string]", EMPTY_LIST),
+            optional(
+                "rotation",
+                "round-robin | random",
+                Some(ParamDefault::Str("round-robin")),
+            ),
+            param("headers", "map<string, string>"),
+            optional("handshake_timeout", "u64", HANDSHAKE_TIMEOUT),
+            next("next", SOF),
+        ],
+        PROVIDES_OUTBOUNDS,
+    ),
+    plugin(
+        "quic-client",
+        &[
+            optional("sni", "string", None),
+            optional("alpn", "[string]", EMPTY_LIST),
+            optional("skip_cert_check", "bool", Some(ParamDefault::Bool(false))),
+            optional(
+                "trust",
+                "system | bundled | custom",
+                Some(ParamDefault::Str("system")),
+            ),
+            optional("ca", "string", None),
+            optional(
+                "congestion_controller",
+                "cubic | new_reno | bbr",
+                Some(ParamDefault::Str("cubic")),
+            ),
+            optional("zero_rtt", "bool", Some(ParamDefault::Bool(false))),
+            optional("handshake_timeout", "u64", HANDSHAKE_TIMEOUT),
+            next("next", DSF),
+        ],
+        PROVIDES_STREAM_OUTBOUND,
+    ),
+    plugin(
+        "mux-client",
+        &[
+            optional("protocol", "smux | yamux", Some(ParamDefault::Str("smux"))),
+            optional("max_connections", "u32", Some(ParamDefault::UInt(4))),
+            optional("max_streams", "u32", Some(ParamDefault::UInt(8))),
+            optional("idle_timeout", "u64", Some(ParamDefault::UInt(60_000))),
+            next("next", SOF),
+        ],
+        PROVIDES_STREAM_OUTBOUND,
+    ),
+    plugin(
+        "masque-client",
+        &[
+            param("host", "string"),
+            optional(
+                "udp_path",
+                "string",
+                Some(ParamDefault::Str(
+                    "/.well-known/masque/udp/{target_host}/{target_port}/",
+                )),
+            ),
+            param("user", "bytes"),
+            param("pass", "bytes"),
+            next("next", SOF),
+            optional_next("quic_next", DSF),
+            optional("sni", "string", None),
+            optional("skip_cert_check", "bool", Some(ParamDefault::Bool(false))),
+            optional(
+                "trust",
+                "system | bundled | custom",
+                Some(ParamDefault::Str("system")),
+            ),
+            optional("ca", "string", None),
+            optional("handshake_timeout", "u64", HANDSHAKE_TIMEOUT),
+        ],
+        PROVIDES_OUTBOUNDS,
+    ),
+    plugin(
+        "redirect",
+        &[
+            param("dest", "destination"),
+            optional("port_hopping", "object", None),
+            param("port_hopping.port_start", "u16"),
+            param("port_hopping.port_end", "u16"),
+            optional(
+                "port_hopping.interval",
+                "u64",
+                Some(ParamDefault::UInt(30_000)),
+            ),
+            next("tcp_next", SOF),
+            next("udp_next", DSF),
+        ],
+        PROVIDES_OUTBOUNDS,
+    ),
+    plugin(
+        "circuit-breaker",
+        &[
+            optional("failure_threshold", "u32", Some(ParamDefault::UInt(5))),
+            optional("cooldown", "u64", Some(ParamDefault::UInt(30_000))),
+            next("tcp_next", SOF),
+            next("udp_next", DSF),
+        ],
+        PROVIDES_OUTBOUNDS,
+    ),
+    plugin(
+        "bonding",
+        &[
+            optional(
+                "mode",
+                "duplicate | stripe",
+                Some(ParamDefault::Str("duplicate")),
+            ),
+            nexts("nexts", "[access-point]", DSF),
+        ],
+        &[provide("{name}.udp", DSF)],
+    ),
+    plugin(
+        "guard",
+        &[
+            param("allow", "[string]"),
+            next("tcp_next", SOF),
+            next("udp_next", DSF),
+        ],
+        PROVIDES_OUTBOUNDS,
+    ),
+    plugin(
+        "delay",
+        &[
+            optional("latency", "u32", Some(ParamDefault::UInt(0))),
+            optional("jitter", "u32", Some(ParamDefault::UInt(0))),
+            optional("loss", "f32", Some(ParamDefault::Float(0.))),
+            optional("bandwidth", "u64", Some(ParamDefault::UInt(0))),
+            next("tcp_next", SOF),
+            next("udp_next", DSF),
+        ],
+        PROVIDES_OUTBOUNDS,
+    ),
+    plugin(
+        "ping-prober",
+        &[
+            param("targets", "[socket-addr]"),
+            optional(
+                "method",
+                "auto | icmp | tcp",
+                Some(ParamDefault::Str("auto")),
+            ),
+            optional("interval", "u64", Some(ParamDefault::UInt(30_000))),
+            optional("timeout", "u64", Some(ParamDefault::UInt(3_000))),
+            optional("failure_threshold", "u32", Some(ParamDefault::UInt(3))),
+            optional("bind_device", "string", None),
+            next("tcp_next", SOF),
+            next("udp_next", DSF),
+        ],
+        PROVIDES_OUTBOUNDS,
+    ),
+    plugin(
+        "url-test",
+        &[
+            param("candidates", "[object]"),
+            param("candidates[].name", "string"),
+            next("candidates[].tcp_next", SOF),
+            next("candidates[].udp_next", DSF),
+            optional("candidates[].probers", "[string]", EMPTY_LIST),
+            optional(
+                "url",
+                "string",
+                Some(ParamDefault::Str("https://www.gstatic.com/generate_204")),
+            ),
+            optional(
+                "mode",
+                "fastest | fallback",
+                Some(ParamDefault::Str("fastest")),
+            ),
+            optional("interval", "u64", Some(ParamDefault::UInt(300_000))),
+            optional("timeout", "u64", Some(ParamDefault::UInt(5_000))),
+            optional("tolerance", "u64", Some(ParamDefault::UInt(50))),
+            optional("sticky", "destination | source", None),
+            optional("sticky_ttl", "u64", Some(ParamDefault::UInt(600_000))),
+        ],
+        PROVIDES_OUTBOUNDS,
+    ),
+    plugin(
+        "stun-keepalive",
+        &[
+            param("server", "destination"),
+            optional("interval", "u64", Some(ParamDefault::UInt(25_000))),
+            next("next", DSF),
+        ],
+        &[provide("{name}.udp", DSF)],
+    ),
+    plugin(
+        "health-endpoint",
+        &[optional("probers", "[string]", EMPTY_LIST)],
+        &[provide("{name}.tcp", SH)],
+    ),
+    plugin(
+        "memory-watchdog",
+        &[
+            optional("threshold", "u64", None),
+            optional("limit_percent", "u8", Some(ParamDefault::UInt(80))),
+            optional("interval", "u64", Some(ParamDefault::UInt(5_000))),
+        ],
+        &[],
+    ),
+    plugin(
+        "automation",
+        &[
+            param("rules", "[object]"),
+            param("rules[].on", "[string]"),
+            optional("rules[].webhook", "object", None),
+            param("rules[].webhook.url", "string"),
+            optional(
+                "rules[].webhook.body",
+                "string",
+                Some(ParamDefault::Str("{message}")),
+            ),
+            optional(
+                "rules[].webhook.content_type",
+                "string",
+                Some(ParamDefault::Str("text/plain; charset=utf-8")),
+            ),
+            optional("rules[].command", "object", None),
+            param("rules[].command.path", "string"),
+            optional("rules[].command.args", "[string]", EMPTY_LIST),
+            optional("rules[].cooldown", "u64", Some(ParamDefault::UInt(60_000))),
+            next("tcp_next", SOF),
+        ],
+        &[],
+    ),
+    plugin(
+        "socket",
+        &[
+            next("resolver", RESOLVER),
+            optional(
+                "bind_addr_v4",
+                "socket-addr-v4",
+                Some(ParamDefault::Str("0.0.0.0:0")),
+            ),
+            optional(
+                "bind_addr_v6",
+                "socket-addr-v6",
+                Some(ParamDefault::Str("[::]:0")),
+            ),
+            optional("connect_timeout", "u64", Some(ParamDefault::UInt(10_000))),
+            optional("dscp", "u8", None),
+            optional("proxy_protocol", "v1 | v2", None),
+            optional("udp_connected", "bool", Some(ParamDefault::Bool(false))),
+            optional("udp_fallback", "object", None),
+            next("udp_fallback.next", DSF),
+            optional("udp_fallback.probe_window", "u64", PROBE_WINDOW),
+        ],
+        &[provide("{name}", SOF.union(DSF))],
+    ),
+    plugin(
+        "netif",
+        &[
+            param("family_preference", "Both | Ipv4Only | Ipv6Only"),
+            param("type", "Auto | Manual | Failover"),
+            optional("netif", "string | object", None),
+            optional_next("outbound_resolver", RESOLVER),
+            optional("probers", "[string]", EMPTY_LIST),
+        ],
+        PROVIDES_OUTBOUNDS_RESOLVER,
+    ),
+];
+
+/// Descriptors of all built-in plugin types.
+pub fn builtin_plugin_schemas() -> &'static [PluginSchema] {
+    BUILTIN_PLUGIN_SCHEMAS
+}
+
+pub fn plugin_schema(name: &str) -> Option<&'static PluginSchema> {
+    BUILTIN_PLUGIN_SCHEMAS.iter().find(|s| s.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::*;
+    use crate::config::{ConfigError, Plugin};
+
+    #[test]
+    fn test_schemas_match_factories() {
+        let mut names = BTreeSet::new();
+        for schema in builtin_plugin_schemas() {
+            assert!(names.insert(schema.name), "duplicate {}", schema.name);
+            let plugin = Plugin {
+                id: None,
+                name: "test".into(),
+                plugin: schema.name.into(),
+                plugin_version: schema.version,
+                param: cbor4ii::serde::to_vec(vec![], &()).unwrap(),
+            };
+            let res = crate::config::factory::create_factory_from_plugin(&plugin);
+            assert!(
+                !matches!(res, Err(ConfigError::NoPluginType { .. })),
+                "unknown plugin {}",
+                schema.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_nested_params_follow_parents() {
+        for schema in builtin_plugin_schemas() {
+            for p in schema.params {
+                let Some((parent, _)) = p.name.rsplit_once('.') else {
+                    continue;
+                };
+                let parent = parent.trim_end_matches("[]").trim_end_matches(".*");
+                assert!(
+                    schema.params.iter().any(|q| q.name == parent),
+                    "{}: {} has no parent",
+                    schema.name,
+                    p.name
+                );
+            }
+        }
+    }
+}
@@ -1,8 +1,10 @@
+use std::any::Any;
 use std::collections::{BTreeMap, HashMap};
 use std::mem::ManuallyDrop;
 use std::sync::{Arc, Weak};
 
 use super::*;
+use crate::control::events::Event;
 use crate::data::Database;
 use crate::flow::*;
 use crate::resource::ResourceRegistry;
@@ -16,6 +18,8 @@ pub struct PluginSet {
     pub(super) datagram_outbounds: ManuallyDrop<HashMap<String, Arc<dyn DatagramSessionFactory>>>,
     pub(super) resolver: ManuallyDrop<HashMap<String, Arc<dyn Resolver>>>,
     pub(super) tun: ManuallyDrop<HashMap<String, Arc<dyn Tun>>>,
+    /// Wrappers counting traffic of access points handed out to plugins.
+    pub(super) counted_access_points: Vec<Arc<dyn Any + Send + Sync>>,
 }
 
 pub(super) struct PartialPluginSet<'f> {
@@ -31,6 +35,11 @@ pub(super) struct PartialPluginSet<'f> {
     pub(super) datagram_outbounds: HashMap<String, Weak<dyn DatagramSessionFactory>>,
     pub(super) resolver: HashMap<String, Weak<dyn Resolver>>,
     pub(super) tun: HashMap<String, Weak<dyn Tun>>,
+    /// Settings of TUN interfaces known at load time, keyed by descriptor.
+    pub(super) tun_interfaces: HashMap<String, super::plugin::TunInterface>,
+    /// Whether traffic through access points is attributed to the plugins
+    /// providing them.
+    pub(super) counting_traffic: bool,
 }
 
 fn lookup<T: ?Sized>(
@@ -44,6 +53,55 @@ fn lookup<T: ?Sized>(
         .or_else(|| weak_map.get(descriptor).cloned())
 }
 
+trait CountTraffic {
+    fn count_traffic(
+        next: Weak<Self>,
+        stats: &TrafficStats,
+        plugin: &str,
+        keep_alive: &mut Vec<Arc<dyn Any + Send + Sync>>,
+    ) -> Weak<Self>;
+}
+
+macro_rules! impl_count_traffic {
+    ($item_type: ident, $wrapper: ident) => {
+        impl CountTraffic for dyn $item_type {
+            fn count_traffic(
+                next: Weak<Self>,
+                stats: &TrafficStats,
+                plugin: &str,
+                keep_alive: &mut Vec<Arc<dyn Any + Send + Sync>>,
+            ) -> Weak<Self> {
+                let counted = Arc::new($wrapper {
+                    inner: next,
+                    counter: stats.counter(plugin),
+                });
+                let weak = Arc::downgrade(&counted);
+                keep_alive.push(counted);
+                weak
+            }
+        }
+    };
+    ($item_type: ident) => {
+        impl CountTraffic for dyn $item_type {
+            fn count_traffic(
+                next: Weak<Self>,
+                _stats: &TrafficStats,
+                _plugin: &str,
+                _keep_alive: &mut Vec<Arc<dyn Any + Send + Sync>>,
+            ) -> Weak<Self> {
+                next
+            }
+        }
+    };
+}
+
+impl_count_traffic!(StreamHandler, CountedStreamHandler);
+impl_count_traffic!(StreamOutboundFactory, CountedStreamOutboundFactory);
+impl_count_traffic!(DatagramSessionHandler, CountedDatagramSessionHandler);
+impl_count_traffic!(DatagramSessionFactory, CountedDatagramSessionFactory);
+impl_count_traffic!(Resolver);
+impl_count_traffic!(Tun);
+
 macro_rules! impl_get_or_create {
     ($fn_name: ident, $dict_name: ident, $item_type: ident) => {
         pub(super) fn $fn_name(
@@ -57,7 +115,7 @@ macro_rules! impl_get_or_create {
                     &self.fully_constructed.$dict_name,
                     &self.$dict_name,
                 ) {
-                    return Ok(next);
+                    return Ok(self.count_traffic(&initiator, descriptor, next));
                 };
                 self.load_plugin(initiator.clone(), descriptor)?;
             }
@@ -85,7 +143,28 @@ impl<'a> PartialPluginSet<'a> {
             datagram_outbounds: HashMap::new(),
             resolver: HashMap::new(),
             tun: HashMap::new(),
+            tun_interfaces: HashMap::new(),
+            counting_traffic: false,
+        }
+    }
+    fn count_traffic<T: CountTraffic + ?Sized>(
+        &mut self,
+        initiator: &str,
+        descriptor: &str,
+        next: Weak<T>,
+    ) -> Weak<T> {
+        let plugin = descriptor.split('.').next().unwrap_or("");
+        // Access points used by the plugin providing them are not counted
+        // again.
+        if !self.counting_traffic || plugin == initiator {
+            return next;
         }
+        T::count_traffic(
+            next,
+            self.control_hub.traffic(),
+            plugin,
+            &mut self.fully_constructed.counted_access_points,
+        )
     }
     fn load_plugin(&mut self, initiator: String, descriptor: &str) -> LoadResult<()> {
         let plugin_name = descriptor.split('.').next().unwrap_or("").to_owned();
@@ -106,7 +185,22 @@ impl<'a> PartialPluginSet<'a> {
                 .into());
             }
         };
-        plugin.load(plugin_name, self)
+        let span = crate::log::plugin_load_span(&plugin_name);
+        let _enter = span.enter();
+        let res = plugin.load(plugin_name.clone(), self);
+        self.control_hub
+            .plugin_graph_mut()
+            .record_load(&plugin_name, res.as_ref().err().map(|e| e.to_string()));
+        self.control_hub.events().publish(match &res {
+            Ok(()) => Event::PluginLoaded {
+                plugin: plugin_name,
+            },
+            Err(e) => Event::PluginFailed {
+                plugin: plugin_name,
+                error: e.to_string(),
+            },
+        });
+        res
     }
     impl_get_or_create!(get_or_create_stream_handler, stream_handlers, StreamHandler);
     impl_get_or_create!(
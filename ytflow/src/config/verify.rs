@@ -1,7 +1,11 @@
+use std::collections::BTreeMap;
+
 use serde::Serialize;
 
-use super::factory::{DemandDescriptor, ParsedPlugin, ProvideDescriptor, RequiredResource};
-use super::plugin::Plugin;
+use super::factory::{
+    AccessPointType, DemandDescriptor, ParsedPlugin, ProvideDescriptor, RequiredResource,
+};
+use super::plugin::{Plugin, Socks5ClientFactory};
 use super::ConfigResult;
 
 #[derive(Debug, Clone, Serialize)]
@@ -25,3 +29,278 @@ pub fn verify_plugin(plugin: &'_ Plugin) -> ConfigResult<VerifyResult<'_>> {
         resources,
     })
 }
+
+/// Number of round trips a plugin adds before the first byte of payload can
+/// reach the remote, assuming TLS 1.3. Protocols that send their headers along
+/// with the first payload cost nothing.
+pub fn handshake_round_trips(plugin: &Plugin) -> u32 {
+    match plugin.plugin.as_str() {
+        // TCP three-way handshake
+        "socket" => 1,
+        "tls-client" | "ws-client" | "http-proxy-client" | "masque-client" => 1,
+        // QUIC carries the TLS 1.3 handshake in its own
+        "quic-client" => 1,
+        "socks5-client" => Socks5ClientFactory::parse(plugin)
+            .map_or(2, |parsed| parsed.factory.handshake_round_trips()),
+        _ => 0,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ChainWarning {
+    /// A TLS layer is wrapped in another TLS layer.
+    RedundantTls { outer: String, inner: String },
+    /// The chain refers back to a plugin already in it.
+    Cycle { plugin: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChainEstimate {
+    pub plugin: String,
+    /// Plugins on the path with the most round trips, starting from `plugin`.
+    pub chain: Vec<String>,
+    pub handshake_round_trips: u32,
+    /// Problems found on any path starting from `plugin`.
+    pub warnings: Vec<ChainWarning>,
+}
+
+struct ChainNode<'a> {
+    plugin_type: &'a str,
+    round_trips: u32,
+    next: Vec<&'a str>,
+}
+
+struct ChainWalker<'a> {
+    nodes: BTreeMap<&'a str, ChainNode<'a>>,
+    stack: Vec<&'a str>,
+    warnings: Vec<ChainWarning>,
+}
+
+impl<'a> ChainWalker<'a> {
+    fn warn(&mut self, warning: ChainWarning) {
+        if !self.warnings.contains(&warning) {
+            self.warnings.push(warning);
+        }
+    }
+
+    /// Returns the round trips and the plugins of the slowest path from `name`.
+    fn walk(&mut self, name: &'a str) -> (u32, Vec<String>) {
+        if self.stack.contains(&name) {
+            self.warn(ChainWarning::Cycle {
+                plugin: name.into(),
+            });
+            return (0, vec![]);
+        }
+        let Some(node) = self.nodes.get(name) else {
+            return (0, vec![]);
+        };
+        let (plugin_type, round_trips, next) =
+            (node.plugin_type, node.round_trips, node.next.clone());
+        if plugin_type == "tls-client" {
+            let outer = self
+                .stack
+                .iter()
+                .rev()
+                .find(|n| self.nodes[*n].plugin_type == "tls-client");
+            if let Some(outer) = outer.map(|n| n.to_string()) {
+                self.warn(ChainWarning::RedundantTls {
+                    outer,
+                    inner: name.into(),
+                });
+            }
+        }
+        self.stack.push(name);
+        let (rtt, mut chain) = next
+            .into_iter()
+            .map(|n| self.walk(n))
+            .max_by_key(|(rtt, _)| *rtt)
+            .unwrap_or_default();
+        self.stack.pop();
+        chain.insert(0, name.into());
+        (rtt + round_trips, chain)
+    }
+}
+
+/// Estimate handshake round trips of every stream outbound in a profile by
+/// following the stream outbounds each plugin requires. Plugins that cannot be
+/// parsed are ignored; use [`verify_plugin`] to report them.
+pub fn estimate_chains(plugins: &[Plugin]) -> Vec<ChainEstimate> {
+    let parsed: Vec<_> = plugins
+        .iter()
+        .filter_map(|p| {
+            super::factory::create_factory_from_plugin(p)
+                .ok()
+                .map(|parsed| (p, parsed))
+        })
+        .collect();
+    let providers: BTreeMap<&str, &str> = parsed
+        .iter()
+        .flat_map(|(p, parsed)| {
+            parsed
+                .provides
+                .iter()
+                .filter(|d| d.r#type.contains(AccessPointType::STREAM_OUTBOUND_FACTORY))
+                .map(|d| (d.descriptor.as_str(), p.name.as_str()))
+        })
+        .collect();
+    let nodes = parsed
+        .iter()
+        .filter(|(_, parsed)| {
+            parsed
+                .provides
+                .iter()
+                .any(|d| d.r#type.contains(AccessPointType::STREAM_OUTBOUND_FACTORY))
+        })
+        .map(|(p, parsed)| {
+            let next = parsed
+                .requires
+                .iter()
+                .filter(|d| d.r#type.contains(AccessPointType::STREAM_OUTBOUND_FACTORY))
+                .filter_map(|d| providers.get(d.descriptor).copied())
+                .collect();
+            (
+                p.name.as_str(),
+                ChainNode {
+                    plugin_type: p.plugin.as_str(),
+                    round_trips: handshake_round_trips(p),
+                    next,
+                },
+            )
+        })
+        .collect();
+    let mut walker = ChainWalker {
+        nodes,
+        stack: vec![],
+        warnings: vec![],
+    };
+    let names: Vec<&str> = walker.nodes.keys().copied().collect();
+    names
+        .into_iter()
+        .map(|name| {
+            let (handshake_round_trips, chain) = walker.walk(name);
+            ChainEstimate {
+                plugin: name.into(),
+                chain,
+                handshake_round_trips,
+                warnings: std::mem::take(&mut walker.warnings),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use cbor4ii::core::Value as CborValue;
+
+    use super::*;
+
+    fn plugin(name: &str, plugin: &str, fields: Vec<(&str, CborValue)>) -> Plugin {
+        let fields = fields
+            .into_iter()
+            .map(|(k, v)| (CborValue::Text(k.into()), v))
+            .collect();
+        Plugin {
+            id: None,
+            name: name.into(),
+            plugin: plugin.into(),
+            plugin_version: 0,
+            param: cbor4ii::serde::to_vec(vec![], &CborValue::Map(fields)).unwrap(),
+        }
+    }
+
+    fn text(s: &str) -> CborValue {
+        CborValue::Text(s.into())
+    }
+
+    fn socket(name: &str) -> Plugin {
+        plugin(name, "socket", vec![("resolver", text("r"))])
+    }
+
+    fn tls(name: &str, next: &str) -> Plugin {
+        plugin(name, "tls-client", vec![("next", text(next))])
+    }
+
+    fn ws(name: &str, next: &str) -> Plugin {
+        plugin(
+            name,
+            "ws-client",
+            vec![("headers", CborValue::Map(vec![])), ("next", text(next))],
+        )
+    }
+
+    fn estimate<'a>(estimates: &'a [ChainEstimate], plugin: &str) -> &'a ChainEstimate {
+        estimates.iter().find(|e| e.plugin == plugin).unwrap()
+    }
+
+    #[test]
+    fn test_trojan_over_ws_over_tls() {
+        let plugins = [
+            socket("s"),
+            tls("t", "s"),
+            ws("w", "t.tcp"),
+            plugin(
+                "tr",
+                "trojan-client",
+                vec![
+                    ("password", CborValue::Bytes(b"pass".to_vec())),
+                    ("tls_next", text("w.tcp")),
+                ],
+            ),
+        ];
+        let estimates = estimate_chains(&plugins);
+        let trojan = estimate(&estimates, "tr");
+        assert_eq!(trojan.handshake_round_trips, 3);
+        assert_eq!(trojan.chain, ["tr", "w", "t", "s"]);
+        assert!(trojan.warnings.is_empty());
+        assert_eq!(estimate(&estimates, "s").handshake_round_trips, 1);
+    }
+
+    #[test]
+    fn test_redundant_tls() {
+        let plugins = [socket("s"), tls("inner", "s"), tls("outer", "inner.tcp")];
+        let estimates = estimate_chains(&plugins);
+        let outer = estimate(&estimates, "outer");
+        assert_eq!(outer.handshake_round_trips, 3);
+        assert_eq!(
+            outer.warnings,
+            [ChainWarning::RedundantTls {
+                outer: "outer".into(),
+                inner: "inner".into(),
+            }]
+        );
+        assert!(estimate(&estimates, "inner").warnings.is_empty());
+    }
+
+    #[test]
+    fn test_cycle() {
+        let plugins = [ws("a", "b.tcp"), ws("b", "a.tcp")];
+        let estimates = estimate_chains(&plugins);
+        let a = estimate(&estimates, "a");
+        assert_eq!(a.handshake_round_trips, 2);
+        assert_eq!(a.chain, ["a", "b"]);
+        assert_eq!(a.warnings, [ChainWarning::Cycle { plugin: "a".into() }]);
+    }
+
+    #[test]
+    fn test_handshake_round_trips() {
+        let socks = |extra: Vec<(&'static str, CborValue)>| {
+            let mut fields = vec![("tcp_next", text("s")), ("udp_next", text("s"))];
+            fields.extend(extra);
+            handshake_round_trips(&plugin("p", "socks5-client", fields))
+        };
+        assert_eq!(socks(vec![]), 2);
+        assert_eq!(
+            socks(vec![
+                ("user", CborValue::Bytes(b"user".to_vec())),
+                ("pass", CborValue::Bytes(b"pass".to_vec())),
+            ]),
+            3
+        );
+        assert_eq!(socks(vec![("version", text("socks4"))]), 1);
+        assert_eq!(
+            handshake_round_trips(&plugin("q", "quic-client", vec![("next", text("s"))])),
+            1
+        );
+    }
+}
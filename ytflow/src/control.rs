@@ -1,6 +1,17 @@
+pub mod events;
+mod graph;
 mod hub;
+mod kill_switch;
+mod memory;
 mod plugin;
+mod reloader;
 pub mod rpc;
+mod scheduler;
 
+pub use graph::*;
 pub use hub::*;
+pub use kill_switch::*;
+pub use memory::*;
 pub use plugin::*;
+pub use reloader::*;
+pub use scheduler::*;
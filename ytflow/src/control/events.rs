@@ -0,0 +1,248 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+/// Number of recent events kept for subscribers that join late, e.g. a
+/// frontend connecting after all plugins have been loaded.
+const BACKLOG_SIZE: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "t", rename_all = "snake_case")]
+pub enum Event {
+    PluginLoaded {
+        plugin: String,
+    },
+    PluginFailed {
+        plugin: String,
+        error: String,
+    },
+    NetifChanged {
+        plugin: String,
+        netif: String,
+    },
+    SwitchChoiceChanged {
+        plugin: String,
+        idx: u32,
+        name: String,
+    },
+    SubscriptionUpdated {
+        proxy_group_id: u32,
+    },
+    ResourceRefreshed {
+        resource_id: u32,
+    },
+    MemoryPressure {
+        plugin: String,
+        usage: u64,
+        threshold: u64,
+    },
+    /// All plugins of the profile have been loaded, with `errors` load errors.
+    Connected {
+        errors: u32,
+    },
+    /// A ping-prober has started or stopped failing its targets, or all
+    /// candidates of a url-test have gone down or one has come back.
+    OutboundHealthChanged {
+        plugin: String,
+        healthy: bool,
+    },
+    /// Usage of a traffic quota has just reached its warning level, or the
+    /// limit itself.
+    QuotaThresholdReached {
+        quota_id: u32,
+        bytes_used: u64,
+        bytes_limit: u64,
+        exceeded: bool,
+    },
+    /// The external address learned by a stun-keepalive has changed, so peers
+    /// may have to be told about the new address.
+    ExternalAddressChanged {
+        plugin: String,
+        previous: String,
+        current: String,
+    },
+    /// A listener could not bind the configured address and has bound one of
+    /// the fallback ports instead, which clients have to be pointed at.
+    ListenPortFallback {
+        plugin: String,
+        requested: String,
+        bound: String,
+    },
+}
+
+impl Event {
+    /// Name of the event as serialized in the `t` field.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Event::PluginLoaded { .. } => "plugin_loaded",
+            Event::PluginFailed { .. } => "plugin_failed",
+            Event::NetifChanged { .. } => "netif_changed",
+            Event::SwitchChoiceChanged { .. } => "switch_choice_changed",
+            Event::SubscriptionUpdated { .. } => "subscription_updated",
+            Event::ResourceRefreshed { .. } => "resource_refreshed",
+            Event::MemoryPressure { .. } => "memory_pressure",
+            Event::Connected { .. } => "connected",
+            Event::OutboundHealthChanged { .. } => "outbound_health_changed",
+            Event::QuotaThresholdReached { .. } => "quota_threshold_reached",
+            Event::ExternalAddressChanged { .. } => "external_address_changed",
+            Event::ListenPortFallback { .. } => "listen_port_fallback",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EventRecord {
+    #[serde(rename = "s")]
+    pub seq: u64,
+    #[serde(rename = "e")]
+    pub event: Event,
+}
+
+struct Backlog {
+    next_seq: u64,
+    records: VecDeque<EventRecord>,
+}
+
+struct EventBusInner {
+    tx: broadcast::Sender<EventRecord>,
+    backlog: Mutex<Backlog>,
+}
+
+/// A cheaply cloneable handle to publish and subscribe to core events.
+#[derive(Clone)]
+pub struct EventBus(Arc<EventBusInner>);
+
+pub struct EventSubscription(broadcast::Receiver<EventRecord>);
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(BACKLOG_SIZE);
+        Self(Arc::new(EventBusInner {
+            tx,
+            backlog: Mutex::new(Backlog {
+                next_seq: 1,
+                records: VecDeque::with_capacity(BACKLOG_SIZE),
+            }),
+        }))
+    }
+
+    pub fn publish(&self, event: Event) {
+        let mut backlog = self.0.backlog.lock().unwrap();
+        let record = EventRecord {
+            seq: backlog.next_seq,
+            event,
+        };
+        backlog.next_seq += 1;
+        if backlog.records.len() == BACKLOG_SIZE {
+            backlog.records.pop_front();
+        }
+        backlog.records.push_back(record.clone());
+        // Publish while holding the lock so that a concurrent subscriber sees
+        // each event either in the backlog or in the channel, but not both.
+        let _ = self.0.tx.send(record);
+    }
+
+    /// Returns retained events with a sequence number greater than `since`,
+    /// together with a subscription for all events published afterwards.
+    pub fn subscribe(&self, since: u64) -> (Vec<EventRecord>, EventSubscription) {
+        let backlog = self.0.backlog.lock().unwrap();
+        let records = backlog
+            .records
+            .iter()
+            .filter(|r| r.seq > since)
+            .cloned()
+            .collect();
+        (records, EventSubscription(self.0.tx.subscribe()))
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventSubscription {
+    /// Waits for the next event. Events missed by a slow subscriber are
+    /// skipped. Returns `None` when the bus is gone.
+    pub async fn recv(&mut self) -> Option<EventRecord> {
+        loop {
+            match self.0.recv().await {
+                Ok(record) => return Some(record),
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn loaded(plugin: &str) -> Event {
+        Event::PluginLoaded {
+            plugin: plugin.into(),
+        }
+    }
+
+    #[test]
+    fn test_subscribe_replays_backlog_since() {
+        let bus = EventBus::new();
+        bus.publish(loaded("a"));
+        bus.publish(loaded("b"));
+        let (records, _) = bus.subscribe(1);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].seq, 2);
+        assert_eq!(records[0].event, loaded("b"));
+    }
+
+    #[test]
+    fn test_backlog_is_bounded() {
+        let bus = EventBus::new();
+        for _ in 0..BACKLOG_SIZE + 10 {
+            bus.publish(loaded("a"));
+        }
+        let (records, _) = bus.subscribe(0);
+        assert_eq!(records.len(), BACKLOG_SIZE);
+        assert_eq!(records[0].seq, 11);
+    }
+
+    #[test]
+    fn test_kind_matches_tag() {
+        let events = [
+            loaded("a"),
+            Event::Connected { errors: 0 },
+            Event::OutboundHealthChanged {
+                plugin: "a".into(),
+                healthy: false,
+            },
+        ];
+        for event in events {
+            use cbor4ii::core::Value;
+            let value: Value =
+                cbor4ii::serde::from_slice(&cbor4ii::serde::to_vec(vec![], &event).unwrap())
+                    .unwrap();
+            let Value::Map(map) = value else {
+                panic!("event should be serialized as a map");
+            };
+            assert!(map.iter().any(|(k, v)| matches!(
+                (k, v),
+                (Value::Text(k), Value::Text(v)) if k == "t" && v == event.kind()
+            )));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscription_receives_new_events() {
+        let bus = EventBus::new();
+        bus.publish(loaded("a"));
+        let (_, mut sub) = bus.subscribe(0);
+        bus.publish(loaded("b"));
+        let record = sub.recv().await.unwrap();
+        assert_eq!(record.seq, 2);
+        assert_eq!(record.event, loaded("b"));
+    }
+}
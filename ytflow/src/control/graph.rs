@@ -0,0 +1,148 @@
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+use serde::Serialize;
+
+use crate::config::factory::PluginDeclaration;
+
+enum LoadState {
+    Pending,
+    Loaded(Instant),
+    Failed(String),
+}
+
+struct PluginNode {
+    declaration: PluginDeclaration,
+    state: LoadState,
+}
+
+/// Plugins of the running profile, with the access points they declared and
+/// whether they were actually loaded.
+#[derive(Default)]
+pub struct PluginGraph {
+    nodes: BTreeMap<String, PluginNode>,
+    errors: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PluginNodeInfo<'a> {
+    #[serde(flatten)]
+    pub declaration: &'a PluginDeclaration,
+    /// One of `pending`, `loaded` or `failed`.
+    pub state: &'static str,
+    pub error: Option<&'a str>,
+    /// Seconds since the plugin was loaded.
+    pub uptime: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PluginGraphInfo<'a> {
+    pub plugins: Vec<PluginNodeInfo<'a>>,
+    /// All errors collected while loading the profile.
+    pub errors: &'a [String],
+}
+
+impl PluginGraph {
+    pub fn new(declarations: impl IntoIterator<Item = PluginDeclaration>) -> Self {
+        Self {
+            nodes: declarations
+                .into_iter()
+                .map(|declaration| {
+                    let node = PluginNode {
+                        declaration,
+                        state: LoadState::Pending,
+                    };
+                    (node.declaration.name.clone(), node)
+                })
+                .collect(),
+            errors: vec![],
+        }
+    }
+
+    pub(crate) fn record_load(&mut self, plugin: &str, error: Option<String>) {
+        let Some(node) = self.nodes.get_mut(plugin) else {
+            return;
+        };
+        node.state = match error {
+            Some(error) => LoadState::Failed(error),
+            None => LoadState::Loaded(Instant::now()),
+        };
+    }
+
+    pub(crate) fn set_errors(&mut self, errors: Vec<String>) {
+        self.errors = errors;
+    }
+
+    pub fn snapshot(&self, now: Instant) -> PluginGraphInfo<'_> {
+        let plugins = self
+            .nodes
+            .values()
+            .map(|node| {
+                let (state, error, uptime) = match &node.state {
+                    LoadState::Pending => ("pending", None, None),
+                    LoadState::Loaded(at) => (
+                        "loaded",
+                        None,
+                        Some(now.saturating_duration_since(*at).as_secs()),
+                    ),
+                    LoadState::Failed(e) => ("failed", Some(e.as_str()), None),
+                };
+                PluginNodeInfo {
+                    declaration: &node.declaration,
+                    state,
+                    error,
+                    uptime,
+                }
+            })
+            .collect();
+        PluginGraphInfo {
+            plugins,
+            errors: &self.errors,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::config::factory::{AccessPointType, Descriptor};
+
+    fn declaration(name: &str) -> PluginDeclaration {
+        PluginDeclaration {
+            name: name.into(),
+            plugin: "reject".into(),
+            requires: vec![],
+            provides: vec![Descriptor {
+                descriptor: name.to_string() + ".tcp",
+                r#type: AccessPointType::STREAM_HANDLER,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut graph = PluginGraph::new([declaration("a"), declaration("b"), declaration("c")]);
+        graph.record_load("a", None);
+        graph.record_load("b", Some("bad".into()));
+        graph.record_load("nonexistent", None);
+        graph.set_errors(vec!["bad".into()]);
+
+        let snapshot = graph.snapshot(Instant::now() + Duration::from_secs(3));
+        let states: Vec<_> = snapshot
+            .plugins
+            .iter()
+            .map(|p| (&*p.declaration.name, p.state, p.error, p.uptime))
+            .collect();
+        assert_eq!(
+            states,
+            [
+                ("a", "loaded", None, Some(3)),
+                ("b", "failed", Some("bad"), None),
+                ("c", "pending", None, None),
+            ]
+        );
+        assert_eq!(snapshot.errors, ["bad"]);
+    }
+}
@@ -1,11 +1,113 @@
+use std::sync::Arc;
+
+use super::events::EventBus;
+use super::graph::PluginGraph;
+use super::kill_switch::KillSwitch;
+use super::memory::CacheShrinkers;
 use super::plugin;
+use super::reloader::ProfileReloader;
+use super::scheduler::Scheduler;
+use crate::flow::{ConnectionTracker, TrafficStats};
 
 #[derive(Default)]
 pub struct ControlHub {
     pub(super) plugins: Vec<plugin::PluginController>,
+    pub(super) events: EventBus,
+    pub(super) cache_shrinkers: CacheShrinkers,
+    pub(super) kill_switch: KillSwitch,
+    pub(super) scheduler: Scheduler,
+    pub(super) plugin_graph: PluginGraph,
+    pub(super) connections: ConnectionTracker,
+    pub(super) traffic: TrafficStats,
+    pub(super) reloader: Option<ProfileReloader>,
 }
 
 impl ControlHub {
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    pub fn cache_shrinkers(&self) -> &CacheShrinkers {
+        &self.cache_shrinkers
+    }
+
+    pub fn kill_switch(&self) -> &KillSwitch {
+        &self.kill_switch
+    }
+
+    /// Engage the kill switch of this plugin set while the embedder replaces
+    /// it with a new one.
+    pub fn set_profile_reloading(&self, reloading: bool) {
+        self.kill_switch.set_reloading(reloading);
+    }
+
+    pub fn scheduler(&self) -> &Scheduler {
+        &self.scheduler
+    }
+
+    pub fn plugin_graph(&self) -> &PluginGraph {
+        &self.plugin_graph
+    }
+
+    pub fn connections(&self) -> &ConnectionTracker {
+        &self.connections
+    }
+
+    pub fn traffic(&self) -> &TrafficStats {
+        &self.traffic
+    }
+
+    pub(crate) fn plugin_graph_mut(&mut self) -> &mut PluginGraph {
+        &mut self.plugin_graph
+    }
+
+    pub(crate) fn set_scheduler(&mut self, scheduler: Scheduler) {
+        self.scheduler = scheduler;
+    }
+
+    /// The plugins able to handle requests, by name.
+    pub(crate) fn plugin_responders(&self) -> Vec<(String, Arc<dyn plugin::PluginResponder>)> {
+        self.plugins
+            .iter()
+            .map(|p| (p.name.clone(), p.responder.clone()))
+            .collect()
+    }
+
+    /// Hand out a reloader polled by the embedder, so that frontends can
+    /// trigger [`ControlHub::reload_profile`].
+    pub fn set_profile_reloader(&mut self, reloader: ProfileReloader) {
+        self.reloader = Some(reloader);
+    }
+
+    /// Ask the embedder to re-read the selected profile from the database,
+    /// start a new plugin set and drain the current one. Returns `false` if
+    /// the embedder has not installed a reloader, in which case nothing
+    /// would pick up the request.
+    pub fn reload_profile(&self) -> bool {
+        match &self.reloader {
+            Some(reloader) => {
+                reloader.request();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Send a request to the plugin named `name`, as if it came from a
+    /// frontend.
+    pub fn request_plugin(
+        &self,
+        name: &str,
+        func: &str,
+        params: &[u8],
+    ) -> plugin::PluginRequestResult<Vec<u8>> {
+        self.plugins
+            .iter()
+            .find(|p| p.name == name)
+            .ok_or(plugin::PluginRequestError::NoSuchPlugin)
+            .and_then(|p| p.responder.on_request(func, params))
+    }
+
     pub fn create_plugin_control(
         &mut self,
         name: String,
@@ -16,7 +118,7 @@ impl ControlHub {
             id: self.plugins.len() as u32 + 1,
             name,
             plugin,
-            responder: Box::new(responder),
+            responder: Arc::new(responder),
         });
         plugin::PluginControlHandle {}
     }
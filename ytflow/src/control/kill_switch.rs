@@ -0,0 +1,133 @@
+use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct KillSwitchStatus {
+    pub engaged: bool,
+    pub manual: bool,
+    pub reloading: bool,
+    /// Plugins reporting the outbound chain as unhealthy.
+    pub unhealthy: Vec<String>,
+}
+
+#[derive(Default)]
+struct KillSwitchState {
+    manual: bool,
+    reloading: bool,
+    unhealthy: BTreeSet<String>,
+}
+
+#[derive(Default)]
+struct KillSwitchInner {
+    engaged: AtomicBool,
+    state: Mutex<KillSwitchState>,
+}
+
+/// Decides whether inbound traffic should be blackholed instead of being
+/// forwarded, so that nothing leaks out while the outbound chain cannot be
+/// relied on. Only plugins configured to honor it drop any traffic.
+#[derive(Clone, Default)]
+pub struct KillSwitch(Arc<KillSwitchInner>);
+
+impl KillSwitch {
+    pub fn is_engaged(&self) -> bool {
+        self.0.engaged.load(Ordering::Relaxed)
+    }
+
+    /// Keep the switch engaged while the plugin set is being replaced, so
+    /// that flows still going through it cannot leak out.
+    pub(super) fn set_reloading(&self, reloading: bool) {
+        let mut state = self.0.state.lock().unwrap();
+        state.reloading = reloading;
+        self.update(&state);
+    }
+
+    pub fn set_manual(&self, engaged: bool) {
+        let mut state = self.0.state.lock().unwrap();
+        state.manual = engaged;
+        self.update(&state);
+    }
+
+    /// Called by health checking plugins every time a check completes.
+    pub fn report_health(&self, source: &str, healthy: bool) {
+        let mut state = self.0.state.lock().unwrap();
+        let changed = if healthy {
+            state.unhealthy.remove(source)
+        } else {
+            state.unhealthy.insert(source.to_string())
+        };
+        if changed {
+            self.update(&state);
+        }
+    }
+
+    /// Whether the last check reported by `source` passed. Sources that have
+    /// not reported yet count as healthy.
+    pub fn is_healthy(&self, source: &str) -> bool {
+        !self.0.state.lock().unwrap().unhealthy.contains(source)
+    }
+
+    pub fn status(&self) -> KillSwitchStatus {
+        let state = self.0.state.lock().unwrap();
+        KillSwitchStatus {
+            engaged: self.is_engaged(),
+            manual: state.manual,
+            reloading: state.reloading,
+            unhealthy: state.unhealthy.iter().cloned().collect(),
+        }
+    }
+
+    fn update(&self, state: &KillSwitchState) {
+        self.0.engaged.store(
+            state.manual || state.reloading || !state.unhealthy.is_empty(),
+            Ordering::Relaxed,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kill_switch_unhealthy_sources() {
+        let ks = KillSwitch::default();
+        assert!(!ks.is_engaged());
+        ks.report_health("a", false);
+        ks.report_health("b", false);
+        assert!(ks.is_engaged());
+        ks.report_health("a", true);
+        assert!(ks.is_engaged());
+        ks.report_health("b", true);
+        assert!(!ks.is_engaged());
+        assert!(ks.is_healthy("a") && ks.is_healthy("never-reported"));
+    }
+
+    #[test]
+    fn test_kill_switch_manual() {
+        let ks = KillSwitch::default();
+        ks.set_manual(true);
+        ks.report_health("a", true);
+        assert!(ks.is_engaged());
+        assert!(ks.status().manual);
+        ks.set_manual(false);
+        assert!(!ks.is_engaged());
+    }
+
+    #[test]
+    fn test_kill_switch_reloading() {
+        let ks = KillSwitch::default();
+        ks.set_reloading(true);
+        assert!(ks.is_engaged());
+        assert!(ks.status().reloading);
+        // Unrelated sources must not release the switch.
+        ks.report_health("a", true);
+        assert!(ks.is_engaged());
+        ks.set_reloading(false);
+        assert!(!ks.is_engaged());
+        assert!(!KillSwitch::default().is_engaged());
+    }
+}
@@ -0,0 +1,31 @@
+use std::sync::{Arc, Mutex, Weak};
+
+/// Implemented by plugins holding caches that can be rebuilt on demand, so
+/// that memory can be given back when the process is running out of it.
+pub trait CacheShrink: Send + Sync {
+    fn shrink_cache(&self);
+}
+
+/// Caches registered by plugins in the same plugin set.
+#[derive(Clone, Default)]
+pub struct CacheShrinkers(Arc<Mutex<Vec<Weak<dyn CacheShrink>>>>);
+
+impl CacheShrinkers {
+    pub fn register(&self, cache: Weak<dyn CacheShrink>) {
+        self.0.lock().unwrap().push(cache);
+    }
+
+    /// Shrink all caches that are still alive, and return how many of them
+    /// have been shrunk.
+    pub fn shrink_all(&self) -> usize {
+        let caches: Vec<_> = {
+            let mut caches = self.0.lock().unwrap();
+            caches.retain(|c| c.strong_count() > 0);
+            caches.iter().filter_map(Weak::upgrade).collect()
+        };
+        for cache in &caches {
+            cache.shrink_cache();
+        }
+        caches.len()
+    }
+}
@@ -1,5 +1,6 @@
 use std::borrow::Cow;
 use std::convert::Infallible;
+use std::sync::Arc;
 
 use cbor4ii::serde::DecodeError;
 use serde::{Deserialize, Serialize, Serializer};
@@ -40,7 +41,7 @@ pub(super) struct PluginController {
     pub(super) id: u32,
     pub(super) name: String,
     pub(super) plugin: &'static str,
-    pub(super) responder: Box<dyn PluginResponder>,
+    pub(super) responder: Arc<dyn PluginResponder>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
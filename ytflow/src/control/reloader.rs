@@ -0,0 +1,71 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+static SIGHUP_RECEIVED: AtomicBool = AtomicBool::new(false);
+
+/// Lets frontends ask the embedder to reload the selected profile. The
+/// embedder owns the plugin set, so it polls for requests and carries out
+/// the reload itself.
+#[derive(Clone, Default)]
+pub struct ProfileReloader(Arc<AtomicBool>);
+
+impl ProfileReloader {
+    pub fn request(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether a reload has been requested since the last call.
+    pub fn take_requested(&self) -> bool {
+        self.0.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// Record SIGHUP instead of terminating the process, to be picked up by
+/// [`take_sighup`].
+#[cfg(all(unix, feature = "plugins"))]
+pub fn handle_sighup() -> std::io::Result<()> {
+    extern "C" fn on_sighup(_: libc::c_int) {
+        SIGHUP_RECEIVED.store(true, Ordering::Relaxed);
+    }
+
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = on_sighup as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        if libc::sigaction(libc::SIGHUP, &action, std::ptr::null_mut()) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Whether SIGHUP has been received since the last call.
+pub fn take_sighup() -> bool {
+    SIGHUP_RECEIVED.swap(false, Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_take_requested() {
+        let reloader = ProfileReloader::default();
+        assert!(!reloader.take_requested());
+        reloader.clone().request();
+        assert!(reloader.take_requested());
+        assert!(!reloader.take_requested());
+    }
+
+    #[test]
+    fn test_reload_through_control_hub() {
+        let mut hub = crate::control::ControlHub::default();
+        // Nothing would pick up the request.
+        assert!(!hub.reload_profile());
+        let reloader = ProfileReloader::default();
+        hub.set_profile_reloader(reloader.clone());
+        assert!(hub.reload_profile());
+        assert!(reloader.take_requested());
+    }
+}
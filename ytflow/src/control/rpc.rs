@@ -1,7 +1,9 @@
 use std::collections::BTreeMap;
 use std::io;
+use std::time::Instant;
 
 use cbor4ii::serde::{from_slice, to_writer, EncodeError};
+use chrono::{Local, NaiveDateTime};
 use futures::{
     sink::{Sink, SinkExt},
     stream::{TryStream, TryStreamExt},
@@ -10,6 +12,7 @@ use serde::{Deserialize, Serialize};
 use serde_bytes::ByteBuf;
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
+use super::events::{Event, EventSubscription};
 use super::plugin;
 
 #[derive(Deserialize)]
@@ -27,6 +30,69 @@ enum ControlHubRequest {
         #[serde(rename = "p")]
         params: ByteBuf,
     },
+    /// Returns retained events after sequence number `since`. On stream and
+    /// datagram transports, the connection then keeps delivering new events.
+    #[serde(rename = "e")]
+    SubscribeEvents {
+        #[serde(rename = "s", default)]
+        since: u64,
+    },
+    /// Lets a frontend announce changes it made to the database.
+    #[serde(rename = "n")]
+    PublishEvent {
+        #[serde(rename = "e")]
+        event: Event,
+    },
+    /// Lists upcoming scheduled transitions.
+    #[serde(rename = "t")]
+    ListTransitions {
+        #[serde(rename = "l", default = "default_transition_limit")]
+        limit: usize,
+    },
+    /// Returns all plugins of the running profile and their load states.
+    #[serde(rename = "g")]
+    GetPluginGraph,
+    #[serde(rename = "o")]
+    OverrideTransition {
+        #[serde(rename = "i")]
+        schedule_id: u32,
+        #[serde(rename = "o")]
+        r#override: TransitionOverride,
+    },
+    /// Lists active streams and datagram sessions.
+    #[serde(rename = "l")]
+    ListConnections,
+    /// Tears down an active connection.
+    #[serde(rename = "x")]
+    CloseConnection {
+        #[serde(rename = "i")]
+        id: u64,
+    },
+    /// Returns traffic counters and rates of each plugin.
+    #[serde(rename = "s")]
+    GetStats,
+    /// Returns the number of panics caught in plugin tasks, in total and per
+    /// plugin.
+    #[serde(rename = "k")]
+    GetCrashStats,
+    /// Re-reads the selected profile and restarts all plugins.
+    #[serde(rename = "r")]
+    ReloadProfile,
+}
+
+fn default_transition_limit() -> usize {
+    16
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "t", rename_all = "snake_case")]
+enum TransitionOverride {
+    /// Do not take the action of the transition at `at`.
+    Skip { at: NaiveDateTime },
+    /// Undo a previous skip.
+    Restore { at: NaiveDateTime },
+    /// Take the action now.
+    RunNow,
 }
 
 #[derive(Serialize)]
@@ -60,31 +126,115 @@ impl<'h> ControlHubService<'h> {
         req: &[u8],
         res: &mut W,
     ) -> Result<(), EncodeError<io::Error>> {
+        self.handle_request(req, res).map(|_| ())
+    }
+
+    fn handle_request<W: io::Write>(
+        &mut self,
+        req: &[u8],
+        res: &mut W,
+    ) -> Result<Option<EventSubscription>, EncodeError<io::Error>> {
         let req: ControlHubRequest = match from_slice(req) {
             Ok(req) => req,
             Err(e) => {
-                return to_writer(
+                to_writer(
                     res,
                     &ControlHubResponse::<(), _>::Err {
                         error: e.to_string(),
                     },
-                );
+                )?;
+                return Ok(None);
             }
         };
 
         match req {
             ControlHubRequest::CollectAllPluginInfo { hashcodes } => {
                 let data = self.collect_all_plugin_info(hashcodes);
-                to_writer(res, &ControlHubResponse::<_, ()>::Ok { data })
+                to_writer(res, &ControlHubResponse::<_, ()>::Ok { data })?;
             }
             ControlHubRequest::SendRequestToPlugin { id, func, params } => {
                 let response: ControlHubResponse<_, _> = self
                     .send_request_to_plugin(id, &func, &params)
                     .map(ByteBuf::from)
                     .into();
-                to_writer(res, &response)
+                to_writer(res, &response)?;
+            }
+            ControlHubRequest::SubscribeEvents { since } => {
+                let (data, subscription) = self.0.events.subscribe(since);
+                to_writer(res, &ControlHubResponse::<_, ()>::Ok { data })?;
+                return Ok(Some(subscription));
+            }
+            ControlHubRequest::PublishEvent { event } => {
+                let response: ControlHubResponse<(), _> = match event {
+                    Event::SubscriptionUpdated { .. } | Event::ResourceRefreshed { .. } => {
+                        self.0.events.publish(event);
+                        Ok(())
+                    }
+                    _ => Err("event cannot be published by frontends"),
+                }
+                .into();
+                to_writer(res, &response)?;
+            }
+            ControlHubRequest::ListTransitions { limit } => {
+                let data = self.0.scheduler.upcoming(Local::now().naive_local(), limit);
+                to_writer(res, &ControlHubResponse::<_, ()>::Ok { data })?;
+            }
+            ControlHubRequest::GetPluginGraph => {
+                let data = self.0.plugin_graph.snapshot(Instant::now());
+                to_writer(res, &ControlHubResponse::<_, ()>::Ok { data })?;
+            }
+            ControlHubRequest::OverrideTransition {
+                schedule_id,
+                r#override,
+            } => {
+                let scheduler = &self.0.scheduler;
+                let found = match r#override {
+                    TransitionOverride::Skip { at } => scheduler.set_skipped(schedule_id, at, true),
+                    TransitionOverride::Restore { at } => {
+                        scheduler.set_skipped(schedule_id, at, false)
+                    }
+                    TransitionOverride::RunNow => scheduler.run_now(schedule_id),
+                };
+                let response: ControlHubResponse<(), _> = if found {
+                    Ok(())
+                } else {
+                    Err("no such transition")
+                }
+                .into();
+                to_writer(res, &response)?;
+            }
+            ControlHubRequest::ListConnections => {
+                let data = self.0.connections.list();
+                to_writer(res, &ControlHubResponse::<_, ()>::Ok { data })?;
+            }
+            ControlHubRequest::CloseConnection { id } => {
+                let response: ControlHubResponse<(), _> = if self.0.connections.close(id) {
+                    Ok(())
+                } else {
+                    Err("no such connection")
+                }
+                .into();
+                to_writer(res, &response)?;
+            }
+            ControlHubRequest::GetStats => {
+                let data = self.0.traffic.snapshot(Instant::now());
+                to_writer(res, &ControlHubResponse::<_, ()>::Ok { data })?;
+            }
+            ControlHubRequest::GetCrashStats => {
+                let data = crate::log::crash_stats();
+                to_writer(res, &ControlHubResponse::<_, ()>::Ok { data })?;
+            }
+            ControlHubRequest::ReloadProfile => {
+                let response: ControlHubResponse<(), _> = if self.0.reload_profile() {
+                    Ok(())
+                } else {
+                    Err("profile reloading is not supported by the host")
+                }
+                .into();
+                to_writer(res, &response)?;
             }
         }
+        Ok(None)
     }
 
     fn collect_all_plugin_info(&mut self, hashcodes: BTreeMap<u32, u32>) -> Vec<super::PluginInfo> {
@@ -129,12 +279,29 @@ where
         io.read_exact(&mut buf[..]).await?;
         let mut res = Vec::with_capacity(128);
         res.extend_from_slice(&[0; 4]);
-        service
-            .execute_request(&buf[..], &mut res)
+        let subscription = service
+            .handle_request(&buf[..], &mut res)
             .expect("Cannot write service response");
         let len_bytes: [u8; 4] = ((res.len() - 4) as u32).to_be_bytes();
         res[..4].copy_from_slice(&len_bytes);
         io.write_all(&res).await?;
+
+        if let Some(mut subscription) = subscription {
+            // From now on this connection only delivers events.
+            while let Some(record) = subscription.recv().await {
+                res.clear();
+                res.extend_from_slice(&[0; 4]);
+                to_writer(
+                    &mut res,
+                    &ControlHubResponse::<_, ()>::Ok { data: [record] },
+                )
+                .expect("Cannot write event");
+                let len_bytes: [u8; 4] = ((res.len() - 4) as u32).to_be_bytes();
+                res[..4].copy_from_slice(&len_bytes);
+                io.write_all(&res).await?;
+            }
+            return Ok(());
+        }
     }
 }
 
@@ -147,10 +314,23 @@ where
             continue;
         }
         let mut res = Vec::with_capacity(128);
-        service
-            .execute_request(&req, &mut res)
+        let subscription = service
+            .handle_request(&req, &mut res)
             .expect("Cannot write service response");
         io.send(res).await?;
+
+        if let Some(mut subscription) = subscription {
+            while let Some(record) = subscription.recv().await {
+                let mut res = Vec::with_capacity(128);
+                to_writer(
+                    &mut res,
+                    &ControlHubResponse::<_, ()>::Ok { data: [record] },
+                )
+                .expect("Cannot write event");
+                io.send(res).await?;
+            }
+            break;
+        }
     }
     Ok(())
 }
@@ -0,0 +1,331 @@
+use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{Local, NaiveDateTime};
+use serde::Serialize;
+
+use super::plugin::PluginResponder;
+use crate::data::{CronSchedule, ProfileId, Schedule, ScheduleAction};
+
+/// How often the scheduler checks for due transitions.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A point in time at which a schedule fires.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Transition {
+    pub schedule_id: u32,
+    pub name: String,
+    /// In local time.
+    pub at: NaiveDateTime,
+    pub action: ScheduleAction,
+    /// Skipped by the user. The action will not be taken.
+    pub skipped: bool,
+}
+
+struct Entry {
+    schedule: Schedule,
+    cron: CronSchedule,
+}
+
+struct SchedulerState {
+    entries: Vec<Entry>,
+    last_checked: NaiveDateTime,
+    skipped: BTreeSet<(u32, NaiveDateTime)>,
+    /// Schedules to be run at the next check regardless of their time.
+    forced: Vec<u32>,
+    /// The profile to be activated by the latest due transition.
+    activation: Option<ProfileId>,
+}
+
+/// Decides when the actions of schedules stored in the database are due.
+///
+/// A scheduler is created and driven for each profile loaded with a
+/// database. Switch choices are selected right away. Activating another
+/// profile means replacing the running plugin set, which is owned by the
+/// embedder, so the embedder polls [`Scheduler::take_profile_activation`]
+/// and carries it out.
+#[derive(Clone)]
+pub struct Scheduler(Arc<Mutex<SchedulerState>>);
+
+impl Scheduler {
+    /// Create a scheduler for enabled schedules with a valid cron
+    /// expression. Only transitions after `now` will become due.
+    pub fn new(schedules: Vec<Schedule>, now: NaiveDateTime) -> Self {
+        let entries = schedules
+            .into_iter()
+            .filter(|s| s.enabled)
+            .filter_map(|schedule| {
+                let cron = schedule.cron_schedule()?;
+                Some(Entry { schedule, cron })
+            })
+            .collect();
+        Self(Arc::new(Mutex::new(SchedulerState {
+            entries,
+            last_checked: now,
+            skipped: BTreeSet::new(),
+            forced: vec![],
+            activation: None,
+        })))
+    }
+
+    /// Returns the next `limit` transitions after `now` in chronological
+    /// order.
+    pub fn upcoming(&self, now: NaiveDateTime, limit: usize) -> Vec<Transition> {
+        let state = self.0.lock().unwrap();
+        let mut ret = vec![];
+        for Entry { schedule, cron } in &state.entries {
+            let mut at = now;
+            for _ in 0..limit {
+                let Some(next) = cron.next_after(at) else {
+                    break;
+                };
+                ret.push(Transition {
+                    schedule_id: schedule.id.0,
+                    name: schedule.name.clone(),
+                    at: next,
+                    action: schedule.action.clone(),
+                    skipped: state.skipped.contains(&(schedule.id.0, next)),
+                });
+                at = next;
+            }
+        }
+        ret.sort_by_key(|t| t.at);
+        ret.truncate(limit);
+        ret
+    }
+
+    /// Skip or restore a single upcoming transition. Returns `false` if the
+    /// schedule does not fire at `at`.
+    pub fn set_skipped(&self, schedule_id: u32, at: NaiveDateTime, skipped: bool) -> bool {
+        let mut state = self.0.lock().unwrap();
+        let fires_at = state.entries.iter().any(|e| {
+            e.schedule.id.0 == schedule_id
+                && at > state.last_checked
+                && e.cron.next_after(at - chrono::TimeDelta::minutes(1)) == Some(at)
+        });
+        if !fires_at {
+            return false;
+        }
+        if skipped {
+            state.skipped.insert((schedule_id, at));
+        } else {
+            state.skipped.remove(&(schedule_id, at));
+        }
+        true
+    }
+
+    /// Make a schedule due at the next check. Returns `false` if there is no
+    /// such schedule.
+    pub fn run_now(&self, schedule_id: u32) -> bool {
+        let mut state = self.0.lock().unwrap();
+        if !state.entries.iter().any(|e| e.schedule.id.0 == schedule_id) {
+            return false;
+        }
+        state.forced.push(schedule_id);
+        true
+    }
+
+    /// Returns transitions that have become due since the last check, in
+    /// chronological order. When a schedule fired multiple times, e.g. after
+    /// the device woke up from sleep, only its latest transition is
+    /// returned.
+    fn take_due(&self, now: NaiveDateTime) -> Vec<Transition> {
+        let mut state = self.0.lock().unwrap();
+        let state = &mut *state;
+        let mut ret = vec![];
+        for Entry { schedule, cron } in &state.entries {
+            let forced = state.forced.contains(&schedule.id.0);
+            let mut latest = None;
+            let mut at = state.last_checked;
+            while let Some(next) = cron.next_after(at).filter(|&n| n <= now) {
+                if !state.skipped.remove(&(schedule.id.0, next)) {
+                    latest = Some(next);
+                }
+                at = next;
+            }
+            if forced {
+                latest = Some(now);
+            }
+            if let Some(at) = latest {
+                ret.push(Transition {
+                    schedule_id: schedule.id.0,
+                    name: schedule.name.clone(),
+                    at,
+                    action: schedule.action.clone(),
+                    skipped: false,
+                });
+            }
+        }
+        state.forced.clear();
+        // Clock changes must not make past transitions due again.
+        state.last_checked = state.last_checked.max(now);
+        ret.sort_by_key(|t| t.at);
+        ret
+    }
+}
+
+impl Scheduler {
+    /// The profile that a due transition asks to activate, if any since the
+    /// last call.
+    pub fn take_profile_activation(&self) -> Option<ProfileId> {
+        self.0.lock().unwrap().activation.take()
+    }
+
+    /// Carry out the transitions due at `now`. `responders` are the plugins
+    /// of the running profile by name.
+    fn run_due(&self, now: NaiveDateTime, responders: &[(String, Arc<dyn PluginResponder>)]) {
+        for transition in self.take_due(now) {
+            match transition.action {
+                ScheduleAction::SwitchChoice { plugin, choice } => {
+                    let Some((_, responder)) = responders.iter().find(|(n, _)| *n == plugin) else {
+                        continue;
+                    };
+                    let params = cbor4ii::serde::to_vec(vec![], &choice).unwrap();
+                    // The switch publishes an event when the choice changes.
+                    let _ = responder.on_request("sn", &params);
+                }
+                ScheduleAction::ActivateProfile { profile_id } => {
+                    self.0.lock().unwrap().activation = Some(profile_id);
+                }
+            }
+        }
+    }
+
+    pub(crate) async fn drive(self, responders: Vec<(String, Arc<dyn PluginResponder>)>) {
+        let mut interval = tokio::time::interval(CHECK_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            self.run_due(Local::now().naive_local(), &responders);
+        }
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new(vec![], NaiveDateTime::MIN)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::data::Id;
+
+    fn at(d: u32, h: u32, min: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 1, d)
+            .unwrap()
+            .and_hms_opt(h, min, 0)
+            .unwrap()
+    }
+
+    fn schedule(id: u32, cron: &str, choice: &str) -> Schedule {
+        Schedule {
+            id: Id::new(id),
+            name: choice.into(),
+            cron: cron.into(),
+            action: ScheduleAction::SwitchChoice {
+                plugin: "main".into(),
+                choice: choice.into(),
+            },
+            enabled: true,
+            created_at: at(1, 0, 0),
+        }
+    }
+
+    fn scheduler() -> Scheduler {
+        Scheduler::new(
+            vec![
+                schedule(1, "0 9 * * *", "work"),
+                schedule(2, "0 18 * * *", "home"),
+            ],
+            at(1, 12, 0),
+        )
+    }
+
+    #[test]
+    fn test_upcoming() {
+        let upcoming = scheduler().upcoming(at(1, 12, 0), 3);
+        let times: Vec<_> = upcoming.iter().map(|t| (t.schedule_id, t.at)).collect();
+        assert_eq!(
+            times,
+            [(2, at(1, 18, 0)), (1, at(2, 9, 0)), (2, at(2, 18, 0))]
+        );
+    }
+
+    #[test]
+    fn test_take_due() {
+        let scheduler = scheduler();
+        assert!(scheduler.take_due(at(1, 17, 59)).is_empty());
+        let due = scheduler.take_due(at(1, 18, 0));
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].name, "home");
+        assert!(scheduler.take_due(at(1, 18, 0)).is_empty());
+        // After sleeping for two days, only the latest transition of each
+        // schedule is due.
+        let due = scheduler.take_due(at(3, 10, 0));
+        let times: Vec<_> = due.iter().map(|t| (t.schedule_id, t.at)).collect();
+        assert_eq!(times, [(2, at(2, 18, 0)), (1, at(3, 9, 0))]);
+    }
+
+    #[test]
+    fn test_overrides() {
+        let scheduler = scheduler();
+        assert!(!scheduler.set_skipped(2, at(1, 18, 1), true));
+        assert!(scheduler.set_skipped(2, at(1, 18, 0), true));
+        assert!(scheduler.upcoming(at(1, 12, 0), 1)[0].skipped);
+        assert!(scheduler.take_due(at(1, 19, 0)).is_empty());
+
+        assert!(!scheduler.run_now(3));
+        assert!(scheduler.run_now(1));
+        let due = scheduler.take_due(at(1, 19, 1));
+        assert_eq!(due.len(), 1);
+        assert_eq!((due[0].schedule_id, due[0].at), (1, at(1, 19, 1)));
+    }
+
+    #[derive(Default)]
+    struct RecordingResponder(Mutex<Vec<(String, Vec<u8>)>>);
+
+    impl PluginResponder for RecordingResponder {
+        fn collect_info(&self, _hash: &mut u32) -> Option<Vec<u8>> {
+            None
+        }
+        fn on_request(
+            &self,
+            func: &str,
+            params: &[u8],
+        ) -> super::super::plugin::PluginRequestResult<Vec<u8>> {
+            self.0.lock().unwrap().push((func.into(), params.into()));
+            Ok(vec![])
+        }
+    }
+
+    #[test]
+    fn test_run_due() {
+        let night = Schedule {
+            id: Id::new(2),
+            name: "night".into(),
+            cron: "0 22 * * *".into(),
+            action: ScheduleAction::ActivateProfile {
+                profile_id: Id::new(5),
+            },
+            enabled: true,
+            created_at: at(1, 0, 0),
+        };
+        let scheduler = Scheduler::new(vec![schedule(1, "0 9 * * *", "work"), night], at(1, 12, 0));
+        let main = Arc::new(RecordingResponder::default());
+        let responders = vec![("main".to_string(), main.clone() as Arc<dyn PluginResponder>)];
+
+        scheduler.run_due(at(1, 22, 0), &responders);
+        assert!(main.0.lock().unwrap().is_empty());
+        assert_eq!(scheduler.take_profile_activation(), Some(Id::new(5)));
+        assert_eq!(scheduler.take_profile_activation(), None);
+
+        scheduler.run_due(at(2, 9, 0), &responders);
+        let params = cbor4ii::serde::to_vec(vec![], "work").unwrap();
+        assert_eq!(*main.0.lock().unwrap(), [("sn".to_string(), params)]);
+    }
+}
@@ -11,6 +11,10 @@ pub enum DataError {
         domain: &'static str,
         field: &'static str,
     },
+    #[error("profile {profile_id} is locked")]
+    ProfileLocked { profile_id: u32 },
+    #[error("cannot access database file")]
+    Io(#[from] std::io::Error),
 }
 
 impl From<refinery::Error> for DataError {
@@ -0,0 +1,197 @@
+use chrono::{NaiveDateTime, Utc};
+use rusqlite::{params, Error as SqError, OptionalExtension, Row};
+use serde::Serialize;
+
+use super::*;
+
+pub type InboundUserId = super::Id<InboundUser>;
+
+/// An account accepted by a server-side plugin, identified by a password or
+/// UUID depending on the protocol.
+#[derive(Debug, Clone, Serialize)]
+pub struct InboundUser {
+    pub id: InboundUserId,
+    pub plugin_id: PluginId,
+    pub name: String,
+    #[serde(with = "serde_bytes")]
+    pub credential: Vec<u8>,
+    pub enabled: bool,
+    /// Maximum number of concurrent connections. `None` means unlimited.
+    pub max_connections: Option<u32>,
+    pub upload_bytes: u64,
+    pub download_bytes: u64,
+    pub last_seen_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+fn map_from_row(row: &Row) -> Result<InboundUser, SqError> {
+    Ok(InboundUser {
+        id: super::Id(row.get(0)?, Default::default()),
+        plugin_id: super::Id(row.get(1)?, Default::default()),
+        name: row.get(2)?,
+        credential: row.get(3)?,
+        enabled: row.get(4)?,
+        max_connections: row.get(5)?,
+        upload_bytes: row.get(6)?,
+        download_bytes: row.get(7)?,
+        last_seen_at: row.get(8)?,
+        created_at: row.get(9)?,
+    })
+}
+
+const SELECT_COLUMNS: &str = r"SELECT `id`, `plugin_id`, `name`, `credential`, `enabled`, `max_connections`,
+    `upload_bytes`, `download_bytes`, `last_seen_at`, `created_at` FROM `yt_inbound_users`";
+
+impl InboundUser {
+    pub fn query_by_id(id: u32, conn: &super::Connection) -> DataResult<Option<InboundUser>> {
+        Ok(conn
+            .query_row_and_then(
+                &format!("{} WHERE `id` = ?", SELECT_COLUMNS),
+                [&id],
+                map_from_row,
+            )
+            .optional()?)
+    }
+    pub fn query_all_by_plugin(
+        plugin_id: PluginId,
+        conn: &super::Connection,
+    ) -> DataResult<Vec<InboundUser>> {
+        let mut stmt = conn.prepare_cached(&format!(
+            "{} WHERE `plugin_id` = ? ORDER BY `id` ASC",
+            SELECT_COLUMNS
+        ))?;
+        let ret = stmt
+            .query_and_then([&plugin_id.0], map_from_row)?
+            .filter_map(|r| r.ok())
+            .collect();
+        Ok(ret)
+    }
+    fn validate(name: &str, credential: &[u8]) -> DataResult<()> {
+        if name.is_empty() {
+            return Err(DataError::InvalidData {
+                domain: "inbound_user",
+                field: "name",
+            });
+        }
+        if credential.is_empty() {
+            return Err(DataError::InvalidData {
+                domain: "inbound_user",
+                field: "credential",
+            });
+        }
+        Ok(())
+    }
+    pub fn create(
+        plugin_id: PluginId,
+        name: String,
+        credential: &[u8],
+        max_connections: Option<u32>,
+        conn: &super::Connection,
+    ) -> DataResult<u32> {
+        Self::validate(&name, credential)?;
+        conn.execute(
+            "INSERT INTO `yt_inbound_users` (`plugin_id`, `name`, `credential`, `max_connections`) VALUES (?, ?, ?, ?)",
+            params![&plugin_id.0, name, credential, max_connections],
+        )?;
+        Ok(conn.last_insert_rowid() as u32)
+    }
+    pub fn update(
+        id: u32,
+        name: String,
+        credential: &[u8],
+        max_connections: Option<u32>,
+        conn: &super::Connection,
+    ) -> DataResult<()> {
+        Self::validate(&name, credential)?;
+        conn.execute(
+            "UPDATE `yt_inbound_users` SET `name` = ?, `credential` = ?, `max_connections` = ? WHERE `id` = ?",
+            params![name, credential, max_connections, id],
+        )?;
+        Ok(())
+    }
+    pub fn set_enabled(id: u32, enabled: bool, conn: &super::Connection) -> DataResult<()> {
+        conn.execute(
+            "UPDATE `yt_inbound_users` SET `enabled` = ? WHERE `id` = ?",
+            params![enabled, id],
+        )?;
+        Ok(())
+    }
+    pub fn reset_usage(id: u32, conn: &super::Connection) -> DataResult<()> {
+        conn.execute(
+            "UPDATE `yt_inbound_users` SET `upload_bytes` = 0, `download_bytes` = 0 WHERE `id` = ?",
+            [id],
+        )?;
+        Ok(())
+    }
+    pub fn delete(id: u32, conn: &super::Connection) -> DataResult<()> {
+        conn.execute("DELETE FROM `yt_inbound_users` WHERE `id` = ?", [id])?;
+        Ok(())
+    }
+
+    /// Add traffic to the counters of a user and mark it as seen just now.
+    pub fn add_usage(
+        id: u32,
+        upload_bytes: u64,
+        download_bytes: u64,
+        conn: &super::Connection,
+    ) -> DataResult<()> {
+        conn.execute(
+            r"UPDATE `yt_inbound_users` SET
+            `upload_bytes` = `upload_bytes` + ?,
+            `download_bytes` = `download_bytes` + ?,
+            `last_seen_at` = ?
+            WHERE `id` = ?",
+            params![upload_bytes, download_bytes, Utc::now().naive_utc(), id],
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{Plugin, Profile};
+
+    fn setup() -> (Connection, PluginId) {
+        let conn = Database::connect_temp().unwrap();
+        let profile_id = Profile::create("test".into(), "en-US".into(), &conn).unwrap();
+        let plugin_id = Plugin::create(
+            profile_id.into(),
+            "test".into(),
+            "".into(),
+            "null".into(),
+            0,
+            vec![],
+            &conn,
+        )
+        .unwrap();
+        (conn, plugin_id.into())
+    }
+
+    #[test]
+    fn test_add_usage() {
+        let (conn, plugin_id) = setup();
+        let id = InboundUser::create(plugin_id, "alice".into(), b"pass", Some(2), &conn).unwrap();
+        InboundUser::add_usage(id, 10, 20, &conn).unwrap();
+        InboundUser::add_usage(id, 1, 2, &conn).unwrap();
+        let user = InboundUser::query_by_id(id, &conn).unwrap().unwrap();
+        assert_eq!((user.upload_bytes, user.download_bytes), (11, 22));
+        assert!(user.last_seen_at.is_some());
+        assert!(user.enabled);
+        assert_eq!(user.max_connections, Some(2));
+
+        InboundUser::reset_usage(id, &conn).unwrap();
+        InboundUser::set_enabled(id, false, &conn).unwrap();
+        let user = InboundUser::query_by_id(id, &conn).unwrap().unwrap();
+        assert_eq!((user.upload_bytes, user.download_bytes), (0, 0));
+        assert!(!user.enabled);
+    }
+
+    #[test]
+    fn test_duplicate_credential() {
+        let (conn, plugin_id) = setup();
+        InboundUser::create(plugin_id, "alice".into(), b"pass", None, &conn).unwrap();
+        assert!(InboundUser::create(plugin_id, "bob".into(), b"pass", None, &conn).is_err());
+        assert!(InboundUser::create(plugin_id, "carol".into(), b"", None, &conn).is_err());
+    }
+}
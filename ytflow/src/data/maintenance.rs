@@ -0,0 +1,289 @@
+use std::path::{Path, PathBuf};
+
+use rusqlite::{params, ErrorCode};
+use serde::Serialize;
+
+use super::*;
+
+/// Results of `PRAGMA wal_checkpoint`.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct WalCheckpoint {
+    pub busy: bool,
+    pub log_frames: i32,
+    pub checkpointed_frames: i32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecoveredTable {
+    pub name: String,
+    pub rows: usize,
+    /// Whether all rows of the table could be read from the corrupted database.
+    pub complete: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecoveryReport {
+    /// Where the corrupted database file has been moved to.
+    pub backup_path: PathBuf,
+    pub tables: Vec<RecoveredTable>,
+}
+
+impl DataError {
+    pub fn is_corruption(&self) -> bool {
+        matches!(
+            self,
+            DataError::Database(e) if matches!(
+                e.sqlite_error_code(),
+                Some(ErrorCode::DatabaseCorrupt | ErrorCode::NotADatabase)
+            )
+        )
+    }
+}
+
+/// Run `PRAGMA integrity_check`. An empty list indicates no problems were found.
+pub fn integrity_check(conn: &Connection) -> DataResult<Vec<String>> {
+    let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+    let messages = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(if messages.len() == 1 && messages[0] == "ok" {
+        vec![]
+    } else {
+        messages
+    })
+}
+
+pub fn vacuum(conn: &Connection) -> DataResult<()> {
+    conn.execute_batch("VACUUM")?;
+    Ok(())
+}
+
+/// Checkpoint the WAL into the database file and truncate it. Does nothing
+/// useful when the database is not in WAL mode.
+pub fn wal_checkpoint(conn: &Connection) -> DataResult<WalCheckpoint> {
+    Ok(
+        conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |row| {
+            Ok(WalCheckpoint {
+                busy: row.get::<_, i32>(0)? != 0,
+                log_frames: row.get(1)?,
+                checkpointed_frames: row.get(2)?,
+            })
+        })?,
+    )
+}
+
+fn quick_check_ok(path: &Path) -> DataResult<bool> {
+    let conn = Connection::open(path)?;
+    let res: String = conn.query_row("PRAGMA quick_check(1)", [], |row| row.get(0))?;
+    Ok(res == "ok")
+}
+
+fn backup_path_for(path: &Path) -> PathBuf {
+    let timestamp = chrono::Utc::now().format("%Y%m%d%H%M%S");
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(format!(".corrupt-{}", timestamp));
+    path.with_file_name(file_name)
+}
+
+fn table_columns(conn: &Connection, schema: &str, table: &str) -> DataResult<Vec<String>> {
+    let mut stmt = conn.prepare(&format!(
+        r#"SELECT "name" FROM pragma_table_info(?1, '{}')"#,
+        schema
+    ))?;
+    let cols = stmt
+        .query_map([table], |row| row.get(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(cols)
+}
+
+fn user_tables(conn: &Connection) -> DataResult<Vec<String>> {
+    let mut stmt = conn.prepare(
+        r#"SELECT "name" FROM main.sqlite_master WHERE "type" = 'table'
+            AND "name" NOT LIKE 'sqlite_%' AND "name" != 'refinery_schema_history'"#,
+    )?;
+    let tables = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(tables)
+}
+
+fn salvage_table(conn: &Connection, table: &str) -> DataResult<RecoveredTable> {
+    let old_cols = table_columns(conn, "old", table).unwrap_or_default();
+    let cols: Vec<_> = table_columns(conn, "main", table)?
+        .into_iter()
+        .filter(|c| old_cols.contains(c))
+        .map(|c| format!(r#""{}""#, c.replace('"', r#""""#)))
+        .collect();
+    let mut recovered = RecoveredTable {
+        name: table.to_string(),
+        rows: 0,
+        complete: !cols.is_empty(),
+    };
+    if cols.is_empty() {
+        return Ok(recovered);
+    }
+    let cols = cols.join(", ");
+    let quoted_table = format!(r#""{}""#, table.replace('"', r#""""#));
+
+    // Copy row by row so that a single unreadable page does not fail the whole table.
+    let mut rowid_stmt = conn.prepare(&format!(
+        "SELECT rowid FROM old.{} ORDER BY rowid",
+        quoted_table
+    ))?;
+    let mut copy_stmt = conn.prepare(&format!(
+        "INSERT OR IGNORE INTO main.{t} ({cols}) SELECT {cols} FROM old.{t} WHERE rowid = ?1",
+        t = quoted_table,
+        cols = cols,
+    ))?;
+    let mut rows = rowid_stmt.query([])?;
+    loop {
+        let rowid: i64 = match rows.next() {
+            Ok(Some(row)) => match row.get(0) {
+                Ok(rowid) => rowid,
+                Err(_) => {
+                    recovered.complete = false;
+                    continue;
+                }
+            },
+            Ok(None) => break,
+            Err(_) => {
+                recovered.complete = false;
+                break;
+            }
+        };
+        match copy_stmt.execute(params![rowid]) {
+            Ok(n) => recovered.rows += n,
+            Err(_) => recovered.complete = false,
+        }
+    }
+    Ok(recovered)
+}
+
+impl Database {
+    /// Whether the database file at `path` is corrupted according to
+    /// `PRAGMA quick_check`. Meant to be run when [`Database::open`] fails,
+    /// before deciding on [`Database::recover`].
+    pub fn is_corrupted(path: impl AsRef<Path>) -> DataResult<bool> {
+        match quick_check_ok(path.as_ref()) {
+            Ok(ok) => Ok(!ok),
+            Err(e) if e.is_corruption() => Ok(true),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Move the database file at `path` aside and salvage readable rows into a
+    /// fresh database at the original location.
+    pub fn recover(path: impl AsRef<Path>) -> DataResult<RecoveryReport> {
+        let path = path.as_ref();
+        let backup_path = backup_path_for(path);
+        std::fs::rename(path, &backup_path)?;
+        for suffix in ["-wal", "-shm"] {
+            let mut side = path.as_os_str().to_os_string();
+            side.push(suffix);
+            let mut side_backup = backup_path.as_os_str().to_os_string();
+            side_backup.push(suffix);
+            let _ = std::fs::rename(side, side_backup);
+        }
+
+        let conn = Database::open(path)?.connect()?;
+        conn.pragma_update(None, "foreign_keys", "OFF")?;
+        // A file with a broken header cannot even be attached.
+        let attached = conn
+            .execute("ATTACH DATABASE ?1 AS old", [backup_path.to_string_lossy()])
+            .is_ok();
+        let tables = user_tables(&conn)?;
+        let tables = tables
+            .iter()
+            .map(|t| match attached {
+                true => salvage_table(&conn, t),
+                false => Ok(RecoveredTable {
+                    name: t.clone(),
+                    rows: 0,
+                    complete: false,
+                }),
+            })
+            .collect::<DataResult<Vec<_>>>()?;
+        if attached {
+            conn.execute_batch("DETACH DATABASE old")?;
+        }
+        Ok(RecoveryReport {
+            backup_path,
+            tables,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TempDb(PathBuf);
+
+    impl TempDb {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "ytflow-maintenance-{}-{}.db",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_file(&path);
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDb {
+        fn drop(&mut self) {
+            let dir = self.0.parent().unwrap();
+            let prefix = self.0.file_name().unwrap().to_string_lossy().into_owned();
+            for entry in std::fs::read_dir(dir).into_iter().flatten().flatten() {
+                if entry.file_name().to_string_lossy().starts_with(&prefix) {
+                    let _ = std::fs::remove_file(entry.path());
+                }
+            }
+        }
+    }
+
+    fn create_profiles(path: &Path) {
+        let conn = Database::open(path).unwrap().connect().unwrap();
+        Profile::create("a".into(), "en-US".into(), &conn).unwrap();
+        Profile::create("b".into(), "en-US".into(), &conn).unwrap();
+    }
+
+    #[test]
+    fn test_recover_healthy_db() {
+        let db = TempDb::new("healthy");
+        create_profiles(&db.0);
+        assert!(!Database::is_corrupted(&db.0).unwrap());
+
+        let report = Database::recover(&db.0).unwrap();
+        assert!(report.backup_path.exists());
+        let profiles = report
+            .tables
+            .iter()
+            .find(|t| t.name == "yt_profiles")
+            .unwrap();
+        assert_eq!((profiles.rows, profiles.complete), (2, true));
+        let conn = Database::open(&db.0).unwrap().connect().unwrap();
+        assert_eq!(Profile::query_all(&conn).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_recover_corrupted_db() {
+        let db = TempDb::new("corrupted");
+        create_profiles(&db.0);
+        // Overwrite the header, leaving no readable page behind.
+        let mut data = std::fs::read(&db.0).unwrap();
+        data[..100].fill(0xff);
+        std::fs::write(&db.0, &data).unwrap();
+
+        assert!(Database::open(&db.0).is_err());
+        assert!(Database::is_corrupted(&db.0).unwrap());
+
+        let report = Database::recover(&db.0).unwrap();
+        assert_eq!(std::fs::read(&report.backup_path).unwrap(), data);
+        assert!(report.tables.iter().all(|t| t.rows == 0 && !t.complete));
+        let conn = Database::open(&db.0).unwrap().connect().unwrap();
+        assert!(Profile::query_all(&conn).unwrap().is_empty());
+        assert!(integrity_check(&conn).unwrap().is_empty());
+    }
+}
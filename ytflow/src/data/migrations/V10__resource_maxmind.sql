@@ -0,0 +1,9 @@
+CREATE TABLE `yt_resources_maxmind_permalink` (
+    `id` INTEGER PRIMARY KEY,
+    `resource_id` INTEGER NOT NULL UNIQUE REFERENCES `yt_resources`(`id`) ON DELETE CASCADE ON UPDATE CASCADE,
+    `edition_id` VARCHAR(255) NOT NULL,
+    `account_id` VARCHAR(255) NOT NULL,
+    `license_key` VARCHAR(255) NOT NULL,
+    `last_modified` VARCHAR(255),
+    `retrieved_at` TEXT
+);
@@ -0,0 +1,13 @@
+CREATE TABLE `yt_signing_keys` (
+    `id` INTEGER PRIMARY KEY,
+    `name` VARCHAR(255) NOT NULL,
+    `secret_key` BLOB NOT NULL,
+    `public_key` BLOB NOT NULL UNIQUE,
+    `created_at` TEXT NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now'))
+);
+CREATE TABLE `yt_trusted_keys` (
+    `id` INTEGER PRIMARY KEY,
+    `name` VARCHAR(255) NOT NULL,
+    `public_key` BLOB NOT NULL UNIQUE,
+    `created_at` TEXT NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now'))
+);
@@ -0,0 +1,7 @@
+CREATE TABLE `yt_sync_states` (
+    `id` INTEGER PRIMARY KEY,
+    `item_key` VARCHAR(255) NOT NULL UNIQUE,
+    `version_vector` BLOB NOT NULL,
+    `content_hash` BLOB,
+    `synced_at` TEXT NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now'))
+);
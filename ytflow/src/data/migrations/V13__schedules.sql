@@ -0,0 +1,12 @@
+CREATE TABLE `yt_schedules` (
+    `id` INTEGER PRIMARY KEY,
+    `name` VARCHAR(255) NOT NULL,
+    `cron` VARCHAR(255) NOT NULL,
+    `switch_plugin` VARCHAR(255),
+    `switch_choice` VARCHAR(255),
+    `profile_id` INTEGER REFERENCES `yt_profiles`(`id`) ON DELETE CASCADE ON UPDATE CASCADE,
+    `enabled` INTEGER NOT NULL DEFAULT 1,
+    `created_at` TEXT NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+    CHECK ((`switch_plugin` IS NULL) = (`switch_choice` IS NULL)),
+    CHECK ((`switch_plugin` IS NULL) != (`profile_id` IS NULL))
+);
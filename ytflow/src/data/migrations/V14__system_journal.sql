@@ -0,0 +1,7 @@
+CREATE TABLE `yt_system_journal` (
+    `id` INTEGER PRIMARY KEY,
+    `kind` VARCHAR(32) NOT NULL,
+    `target` TEXT NOT NULL,
+    `undo` BLOB NOT NULL,
+    `created_at` TEXT NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now'))
+);
@@ -0,0 +1,2 @@
+ALTER TABLE `yt_plugin_cache` ADD COLUMN `format_version` INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE `yt_plugin_cache` ADD COLUMN `checksum` INTEGER;
@@ -0,0 +1,14 @@
+CREATE TABLE `yt_inbound_users` (
+    `id` INTEGER PRIMARY KEY,
+    `plugin_id` INTEGER NOT NULL REFERENCES `yt_plugins`(`id`) ON DELETE CASCADE ON UPDATE CASCADE,
+    `name` VARCHAR(255) NOT NULL,
+    `credential` BLOB NOT NULL,
+    `enabled` INTEGER NOT NULL DEFAULT 1,
+    `max_connections` INTEGER,
+    `upload_bytes` INTEGER NOT NULL DEFAULT 0,
+    `download_bytes` INTEGER NOT NULL DEFAULT 0,
+    `last_seen_at` TEXT,
+    `created_at` TEXT NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+    UNIQUE (`plugin_id`, `name`),
+    UNIQUE (`plugin_id`, `credential`)
+);
@@ -0,0 +1,14 @@
+CREATE TABLE `yt_profile_translations` (
+    `profile_id` INTEGER NOT NULL REFERENCES `yt_profiles`(`id`) ON DELETE CASCADE ON UPDATE CASCADE,
+    `locale` VARCHAR(35) NOT NULL,
+    `name` VARCHAR(255) NOT NULL,
+    `desc` TEXT NOT NULL DEFAULT '',
+    PRIMARY KEY (`profile_id`, `locale`)
+);
+
+CREATE TABLE `yt_plugin_translations` (
+    `plugin_id` INTEGER NOT NULL REFERENCES `yt_plugins`(`id`) ON DELETE CASCADE ON UPDATE CASCADE,
+    `locale` VARCHAR(35) NOT NULL,
+    `desc` TEXT NOT NULL,
+    PRIMARY KEY (`plugin_id`, `locale`)
+);
@@ -0,0 +1,8 @@
+CREATE TABLE `yt_profile_snapshots` (
+    `id` INTEGER PRIMARY KEY,
+    `profile_id` INTEGER NOT NULL REFERENCES `yt_profiles`(`id`) ON DELETE CASCADE ON UPDATE CASCADE,
+    `name` VARCHAR(255) NOT NULL,
+    `data` BLOB NOT NULL,
+    `created_at` TEXT NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+    UNIQUE (`profile_id`, `name`)
+);
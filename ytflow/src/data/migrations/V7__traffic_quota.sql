@@ -0,0 +1,14 @@
+CREATE TABLE `yt_traffic_quotas` (
+    `id` INTEGER PRIMARY KEY,
+    `proxy_id` INTEGER REFERENCES `yt_proxies`(`id`) ON DELETE CASCADE ON UPDATE CASCADE,
+    `proxy_group_id` INTEGER REFERENCES `yt_proxy_groups`(`id`) ON DELETE CASCADE ON UPDATE CASCADE,
+    `bytes_limit` INTEGER NOT NULL,
+    `warn_percent` INTEGER NOT NULL DEFAULT 90,
+    `reset_day` INTEGER NOT NULL DEFAULT 1,
+    `upload_bytes_used` INTEGER NOT NULL DEFAULT 0,
+    `download_bytes_used` INTEGER NOT NULL DEFAULT 0,
+    `period_started_at` TEXT NOT NULL DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now')),
+    CHECK ((`proxy_id` IS NULL) != (`proxy_group_id` IS NULL)),
+    UNIQUE (`proxy_id`),
+    UNIQUE (`proxy_group_id`)
+);
@@ -0,0 +1 @@
+ALTER TABLE `yt_profiles` ADD COLUMN `locked` INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,10 @@
+CREATE TABLE `yt_traffic_stats` (
+    `id` INTEGER PRIMARY KEY,
+    `date` TEXT NOT NULL,
+    `profile_id` INTEGER REFERENCES `yt_profiles`(`id`) ON DELETE CASCADE ON UPDATE CASCADE,
+    `proxy_id` INTEGER REFERENCES `yt_proxies`(`id`) ON DELETE SET NULL ON UPDATE CASCADE,
+    `upload_bytes` INTEGER NOT NULL DEFAULT 0,
+    `download_bytes` INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX `yt_traffic_stats_date` ON `yt_traffic_stats` (`date`);
@@ -1,11 +1,21 @@
 mod db;
 mod error;
+mod inbound_user;
+pub mod maintenance;
 mod plugin;
 mod plugin_cache;
 mod profile;
+mod profile_snapshot;
 mod proxy;
 pub mod proxy_group;
 mod resource;
+mod schedule;
+mod signing_key;
+mod sync_state;
+mod system_journal;
+mod traffic_quota;
+mod traffic_stat;
+mod translation;
 
 use std::fmt::{self, Debug, Display, Formatter};
 use std::marker::PhantomData;
@@ -59,12 +69,21 @@ impl<T> Id<T> {
 pub use db::Connection;
 pub use db::Database;
 pub use error::*;
+pub use inbound_user::{InboundUser, InboundUserId};
 pub use plugin::{Plugin, PluginId};
 pub use plugin_cache::PluginCache;
 pub use profile::{Profile, ProfileId};
+pub use profile_snapshot::{ProfileSnapshot, ProfileSnapshotId};
 pub use proxy::{Proxy, ProxyId, ProxyInput};
 pub use proxy_group::{ProxyGroup, ProxyGroupId, ProxySubscription};
 pub use resource::{
-    Resource, ResourceGitHubRelease, ResourceGitHubReleaseId, ResourceId, ResourceUrl,
-    ResourceUrlId,
+    Resource, ResourceGitHubRelease, ResourceGitHubReleaseId, ResourceId, ResourceMaxmindPermalink,
+    ResourceMaxmindPermalinkId, ResourceUrl, ResourceUrlId,
 };
+pub use schedule::{CronSchedule, Schedule, ScheduleAction, ScheduleId};
+pub use signing_key::{SigningKey, SigningKeyId, TrustedKey, TrustedKeyId};
+pub use sync_state::{SyncState, SyncStateId};
+pub use system_journal::{SystemJournalEntry, SystemJournalEntryId, SystemMutationKind};
+pub use traffic_quota::{quota_period_start, TrafficQuota, TrafficQuotaId};
+pub use traffic_stat::TrafficStat;
+pub use translation::{best_locale_match, LocalizedProfile, PluginTranslation, ProfileTranslation};
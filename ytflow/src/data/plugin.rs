@@ -1,5 +1,5 @@
 use chrono::NaiveDateTime;
-use rusqlite::{params, Error as SqError, Row};
+use rusqlite::{params, Error as SqError, OptionalExtension, Row};
 use serde::Serialize;
 
 use super::*;
@@ -44,6 +44,25 @@ impl Plugin {
             .collect();
         Ok(ret)
     }
+    /// Visit plugins of a profile one at a time without loading all of them
+    /// into memory. Stops when `f` returns `false`.
+    pub fn for_each_by_profile(
+        profile_id: super::ProfileId,
+        conn: &super::Connection,
+        mut f: impl FnMut(Plugin) -> bool,
+    ) -> DataResult<()> {
+        let mut stmt = conn.prepare_cached(
+            r"SELECT `id`, `name`, `desc`, `plugin`, `plugin_version`, `param`, `updated_at`
+            FROM `yt_plugins` WHERE `profile_id` = ? ORDER BY `id` ASC",
+        )?;
+        let rows = stmt.query_and_then([&profile_id.0], map_from_row)?;
+        for plugin in rows.filter_map(|r: Result<Plugin, SqError>| r.ok()) {
+            if !f(plugin) {
+                break;
+            }
+        }
+        Ok(())
+    }
     pub fn query_entry_by_profile(
         profile_id: super::ProfileId,
         conn: &super::Connection,
@@ -61,6 +80,19 @@ impl Plugin {
             .collect();
         Ok(ret)
     }
+    pub fn query_profile_id(
+        id: PluginId,
+        conn: &super::Connection,
+    ) -> DataResult<Option<super::ProfileId>> {
+        Ok(conn
+            .query_row(
+                "SELECT `profile_id` FROM `yt_plugins` WHERE `id` = ?",
+                [id.0],
+                |row| row.get::<_, u32>(0),
+            )
+            .optional()?
+            .map(Into::into))
+    }
     pub fn create(
         profile_id: super::ProfileId,
         name: String,
@@ -70,6 +102,7 @@ impl Plugin {
         param: Vec<u8>,
         conn: &super::Connection,
     ) -> DataResult<u32> {
+        super::profile::ensure_unlocked(profile_id.0, conn)?;
         conn.execute(
             "INSERT INTO `yt_plugins` (`profile_id`, `name`, `desc`, `plugin`, `plugin_version`, `param`) VALUES (?, ?, ?, ?, ?, ?)",
             params![profile_id.0, name, desc, plugin, plugin_version, param],
@@ -81,6 +114,7 @@ impl Plugin {
         plugin_id: PluginId,
         conn: &super::Connection,
     ) -> DataResult<()> {
+        super::profile::ensure_unlocked(profile_id.0, conn)?;
         conn.execute(
             "INSERT INTO `yt_profile_entry_plugin` (`profile_id`, `plugin_id`) VALUES (?, ?)",
             params![profile_id.0, plugin_id.0],
@@ -92,6 +126,7 @@ impl Plugin {
         plugin_id: PluginId,
         conn: &super::Connection,
     ) -> DataResult<()> {
+        super::profile::ensure_unlocked(profile_id.0, conn)?;
         conn.execute(
             "DELETE FROM `yt_profile_entry_plugin` WHERE `profile_id` = ? AND `plugin_id` = ?",
             params![profile_id.0, plugin_id.0],
@@ -108,6 +143,8 @@ impl Plugin {
         param: Vec<u8>,
         conn: &super::Connection,
     ) -> DataResult<()> {
+        super::profile::ensure_plugin_unlocked(id, conn)?;
+        super::profile::ensure_unlocked(profile_id.0, conn)?;
         conn.execute(
             "UPDATE `yt_plugins` SET `profile_id` = ?, `name` = ?, `desc` = ?, `plugin` = ?, `plugin_version` = ?, `param` = ? WHERE `id` = ?",
             params![profile_id.0, name, desc, plugin, plugin_version, param, id],
@@ -115,6 +152,7 @@ impl Plugin {
         Ok(())
     }
     pub fn delete(id: u32, conn: &super::Connection) -> DataResult<()> {
+        super::profile::ensure_plugin_unlocked(id, conn)?;
         conn.execute("DELETE FROM `yt_plugins` WHERE `id` = ?", [id])?;
         Ok(())
     }
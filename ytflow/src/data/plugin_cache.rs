@@ -1,43 +1,258 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
 use cbor4ii::serde::{from_slice, to_vec};
 use rusqlite::{params, OptionalExtension};
 use serde::{de::DeserializeOwned, Serialize};
 
-use super::{DataResult, Database, PluginId};
+use super::{Connection, DataResult, Database, PluginId};
+
+/// Records written by older versions, without a checksum.
+const FORMAT_VERSION_LEGACY: u32 = 0;
+/// CBOR value guarded by a CRC-32 checksum.
+const FORMAT_VERSION_CHECKSUM: u32 = 1;
+
+/// How long to wait for more writes before flushing them in a single transaction.
+const COALESCE_DELAY: Duration = Duration::from_millis(500);
 
+/// Key-value storage for plugins to persist their states across runs.
+///
+/// Writes are buffered and flushed on a background task when a Tokio runtime is
+/// available, so that frequent updates from plugins such as dns-server or fakeip
+/// cost one transaction (and one fsync) per batch. Remaining writes are flushed
+/// when the last clone of the cache is dropped.
 #[derive(Clone)]
 pub struct PluginCache {
     plugin_id: PluginId,
-    db: Option<Database>,
+    store: Option<Arc<CacheStore>>,
+}
+
+struct CacheStore {
+    plugin_id: PluginId,
+    db: Database,
+    pending: Mutex<BTreeMap<String, Vec<u8>>>,
+    flush_scheduled: AtomicBool,
 }
 
 impl PluginCache {
     pub fn new(plugin_id: PluginId, db: Option<Database>) -> Self {
-        Self { plugin_id, db }
+        let store = db.map(|db| {
+            Arc::new(CacheStore {
+                plugin_id,
+                db,
+                pending: Mutex::new(BTreeMap::new()),
+                flush_scheduled: AtomicBool::new(false),
+            })
+        });
+        Self { plugin_id, store }
     }
 
     pub fn set<T: Serialize>(&self, key: &str, value: &T) -> DataResult<()> {
-        let Some(db) = &self.db else { return Ok(()) };
-        let conn = db.connect()?;
+        let Some(store) = &self.store else {
+            return Ok(());
+        };
+        let value = to_vec(vec![], value).unwrap();
+        store.pending.lock().unwrap().insert(key.into(), value);
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                if !store.flush_scheduled.swap(true, Ordering::AcqRel) {
+                    let store = store.clone();
+                    handle.spawn(async move {
+                        tokio::time::sleep(COALESCE_DELAY).await;
+                        // Errors are retried with the next write
+                        let _ = tokio::task::spawn_blocking(move || store.flush()).await;
+                    });
+                }
+                Ok(())
+            }
+            Err(_) => store.flush(),
+        }
+    }
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> DataResult<Option<T>> {
+        let Some(store) = &self.store else {
+            return Ok(None);
+        };
+        if let Some(value) = store.pending.lock().unwrap().get(key) {
+            return Ok(from_slice(value).ok());
+        }
+        let conn = store.db.connect()?;
+        let ret = read_record(self.plugin_id, key, &conn)?;
+        Ok(ret.and_then(|v| from_slice(&v).ok()))
+    }
+    /// Write all buffered values to the database immediately.
+    pub fn flush(&self) -> DataResult<()> {
+        match &self.store {
+            Some(store) => store.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl CacheStore {
+    fn flush(&self) -> DataResult<()> {
+        // Clear the flag before draining so that concurrent writes schedule another flush.
+        self.flush_scheduled.store(false, Ordering::Release);
+        let records = std::mem::take(&mut *self.pending.lock().unwrap());
+        if records.is_empty() {
+            return Ok(());
+        }
+        let res = self
+            .db
+            .connect()
+            .and_then(|mut conn| write_records(self.plugin_id, &records, &mut conn));
+        if res.is_err() {
+            // Put back what has not been overwritten in the meantime.
+            let mut pending = self.pending.lock().unwrap();
+            for (key, value) in records {
+                pending.entry(key).or_insert(value);
+            }
+        }
+        res
+    }
+}
+
+impl Drop for CacheStore {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+fn write_records(
+    plugin_id: PluginId,
+    records: &BTreeMap<String, Vec<u8>>,
+    conn: &mut Connection,
+) -> DataResult<()> {
+    let tx = conn.transaction()?;
+    {
+        let mut stmt = tx.prepare_cached(
+            "INSERT OR REPLACE INTO `yt_plugin_cache` (`plugin_id`, `key`, `value`, `format_version`, `checksum`)
+            VALUES (?1, ?2, ?3, ?4, ?5)",
+        )?;
+        for (key, value) in records {
+            stmt.execute(params![
+                plugin_id.0,
+                key,
+                value,
+                FORMAT_VERSION_CHECKSUM,
+                checksum(value)
+            ])?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Reads the raw value of a record. Records that fail verification, e.g. torn by
+/// a power loss, are deleted and reported as missing.
+fn read_record(plugin_id: PluginId, key: &str, conn: &Connection) -> DataResult<Option<Vec<u8>>> {
+    let Some((value, format_version, stored_checksum)) = conn
+        .query_row(
+            "SELECT `value`, `format_version`, `checksum` FROM `yt_plugin_cache` WHERE `plugin_id` = ?1 AND `key` = ?2",
+            params![plugin_id.0, key],
+            |row| {
+                Ok((
+                    row.get::<_, Vec<u8>>(0),
+                    row.get::<_, u32>(1)?,
+                    row.get::<_, Option<u32>>(2)?,
+                ))
+            },
+        )
+        .optional()?
+    else {
+        return Ok(None);
+    };
+    let valid = match (value.as_ref(), format_version) {
+        (Err(_), _) => false,
+        (Ok(value), FORMAT_VERSION_CHECKSUM) => stored_checksum == Some(checksum(value)),
+        (Ok(value), FORMAT_VERSION_LEGACY) => from_slice::<serde::de::IgnoredAny>(value).is_ok(),
+        // Written by a newer version. Leave it alone.
+        _ => return Ok(None),
+    };
+    if !valid {
         conn.execute(
-            "INSERT OR REPLACE INTO `yt_plugin_cache` (`plugin_id`, `key`, `value`) VALUES (?1, ?2, ?3)",
-            params![self.plugin_id.0, key, to_vec(vec![], value).unwrap()],
+            "DELETE FROM `yt_plugin_cache` WHERE `plugin_id` = ?1 AND `key` = ?2",
+            params![plugin_id.0, key],
         )?;
-        Ok(())
+        return Ok(None);
     }
-    pub fn get<T: DeserializeOwned>(&self, key: &str) -> DataResult<Option<T>> {
-        let Some(db) = &self.db else { return Ok(None) };
-        let conn = db.connect()?;
-        let ret = conn
-            .query_row(
-                "SELECT `value` FROM `yt_plugin_cache` WHERE `plugin_id` = ?1 AND `key` = ?2",
-                params![self.plugin_id.0, key],
-                |row| {
-                    let value: Vec<u8> = row.get(0)?;
-                    Ok(from_slice::<T>(&value).ok())
-                },
-            )
-            .optional()?
-            .flatten();
-        Ok(ret)
+    Ok(value.ok())
+}
+
+/// CRC-32 (IEEE 802.3) of a record value.
+fn checksum(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in data {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{Plugin, Profile};
+
+    fn setup() -> (Connection, PluginId) {
+        let conn = Database::connect_temp().unwrap();
+        let profile_id = Profile::create("test".into(), "en-US".into(), &conn).unwrap();
+        let plugin_id = Plugin::create(
+            profile_id.into(),
+            "test".into(),
+            "".into(),
+            "null".into(),
+            0,
+            vec![],
+            &conn,
+        )
+        .unwrap();
+        (conn, plugin_id.into())
+    }
+
+    #[test]
+    fn test_checksum() {
+        assert_eq!(checksum(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn test_write_read_records() {
+        let (mut conn, plugin_id) = setup();
+        let records = BTreeMap::from([
+            ("a".to_string(), to_vec(vec![], &1u32).unwrap()),
+            ("b".to_string(), to_vec(vec![], &"x").unwrap()),
+        ]);
+        write_records(plugin_id, &records, &mut conn).unwrap();
+        let a = read_record(plugin_id, "a", &conn).unwrap().unwrap();
+        assert_eq!(from_slice::<u32>(&a).unwrap(), 1);
+        assert_eq!(read_record(plugin_id, "c", &conn).unwrap(), None);
+    }
+
+    #[test]
+    fn test_read_record_drops_corrupted() {
+        let (mut conn, plugin_id) = setup();
+        let records = BTreeMap::from([("a".to_string(), to_vec(vec![], &1u32).unwrap())]);
+        write_records(plugin_id, &records, &mut conn).unwrap();
+        conn.execute("UPDATE `yt_plugin_cache` SET `value` = X'1a'", [])
+            .unwrap();
+        assert_eq!(read_record(plugin_id, "a", &conn).unwrap(), None);
+        let count: u32 = conn
+            .query_row("SELECT COUNT(*) FROM `yt_plugin_cache`", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn test_read_record_legacy() {
+        let (conn, plugin_id) = setup();
+        conn.execute(
+            "INSERT INTO `yt_plugin_cache` (`plugin_id`, `key`, `value`) VALUES (?1, 'a', ?2)",
+            params![plugin_id.0, to_vec(vec![], &1u32).unwrap()],
+        )
+        .unwrap();
+        assert!(read_record(plugin_id, "a", &conn).unwrap().is_some());
     }
 }
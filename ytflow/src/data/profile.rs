@@ -15,6 +15,9 @@ pub struct Profile {
     pub locale: String,
     pub last_used_at: NaiveDateTime,
     pub created_at: NaiveDateTime,
+    /// A locked profile, as well as its plugins, cannot be modified or deleted
+    /// until unlocked.
+    pub locked: bool,
 }
 
 fn map_from_row(row: &Row) -> Result<Profile, SqError> {
@@ -34,6 +37,7 @@ fn map_from_row(row: &Row) -> Result<Profile, SqError> {
         locale: row.get(3)?,
         last_used_at: row.get(4)?,
         created_at: row.get(5)?,
+        locked: row.get(6)?,
     })
 }
 
@@ -41,7 +45,7 @@ impl Profile {
     pub fn query_by_id(id: usize, conn: &super::Connection) -> DataResult<Option<Profile>> {
         Ok(conn
             .query_row_and_then(
-                r"SELECT `id`, `permanent_id`, `name`, `locale`, `last_used_at`, `created_at`
+                r"SELECT `id`, `permanent_id`, `name`, `locale`, `last_used_at`, `created_at`, `locked`
                 FROM `yt_profiles` WHERE `id` = ?",
                 [&id],
                 map_from_row,
@@ -49,7 +53,7 @@ impl Profile {
             .optional()?)
     }
     pub fn query_all(conn: &super::Connection) -> DataResult<Vec<Profile>> {
-        let mut stmt = conn.prepare_cached("SELECT `id`, `permanent_id`, `name`, `locale`, `last_used_at`, `created_at` FROM `yt_profiles` ORDER BY `id` ASC")?;
+        let mut stmt = conn.prepare_cached("SELECT `id`, `permanent_id`, `name`, `locale`, `last_used_at`, `created_at`, `locked` FROM `yt_profiles` ORDER BY `id` ASC")?;
         let ret = stmt
             .query_and_then([], map_from_row)?
             .filter_map(|r: Result<Profile, SqError>| r.ok())
@@ -69,14 +73,104 @@ impl Profile {
         locale: String,
         conn: &super::Connection,
     ) -> DataResult<()> {
+        ensure_unlocked(id, conn)?;
         conn.execute(
             "UPDATE `yt_profiles` SET `name` = ?, `locale` = ? WHERE `id` = ?",
             params![name, locale, id],
         )?;
         Ok(())
     }
+    pub fn set_locked(id: u32, locked: bool, conn: &super::Connection) -> DataResult<()> {
+        conn.execute(
+            "UPDATE `yt_profiles` SET `locked` = ? WHERE `id` = ?",
+            params![locked, id],
+        )?;
+        Ok(())
+    }
     pub fn delete(id: u32, conn: &super::Connection) -> DataResult<()> {
+        ensure_unlocked(id, conn)?;
         conn.execute("DELETE FROM `yt_profiles` WHERE `id` = ?", [id])?;
         Ok(())
     }
+    /// Copy a profile under a new name, along with its plugins, entry plugins
+    /// and translations. The copy is never locked. Returns the ID of the copy.
+    pub fn duplicate(id: u32, name: String, conn: &mut super::Connection) -> DataResult<u32> {
+        let tx = conn.transaction()?;
+        let inserted = tx.execute(
+            "INSERT INTO `yt_profiles` (`name`, `locale`) SELECT ?, `locale` FROM `yt_profiles` WHERE `id` = ?",
+            params![name, id],
+        )?;
+        if inserted == 0 {
+            return Err(SqError::QueryReturnedNoRows.into());
+        }
+        let new_id = tx.last_insert_rowid() as u32;
+        tx.execute(
+            r"INSERT INTO `yt_profile_translations` (`profile_id`, `locale`, `name`, `desc`)
+            SELECT ?, `locale`, `name`, `desc` FROM `yt_profile_translations` WHERE `profile_id` = ?",
+            params![new_id, id],
+        )?;
+
+        let entry_ids: Vec<_> = Plugin::query_entry_by_profile(id.into(), &tx)?
+            .into_iter()
+            .map(|p| p.id)
+            .collect();
+        for plugin in Plugin::query_all_by_profile(id.into(), &tx)? {
+            tx.execute(
+                "INSERT INTO `yt_plugins` (`profile_id`, `name`, `desc`, `plugin`, `plugin_version`, `param`) VALUES (?, ?, ?, ?, ?, ?)",
+                params![
+                    new_id,
+                    plugin.name,
+                    plugin.desc,
+                    plugin.plugin,
+                    plugin.plugin_version,
+                    plugin.param.into_vec()
+                ],
+            )?;
+            let new_plugin_id = tx.last_insert_rowid() as u32;
+            tx.execute(
+                r"INSERT INTO `yt_plugin_translations` (`plugin_id`, `locale`, `desc`)
+                SELECT ?, `locale`, `desc` FROM `yt_plugin_translations` WHERE `plugin_id` = ?",
+                params![new_plugin_id, plugin.id.0],
+            )?;
+            if entry_ids.contains(&plugin.id) {
+                tx.execute(
+                    "INSERT INTO `yt_profile_entry_plugin` (`profile_id`, `plugin_id`) VALUES (?, ?)",
+                    params![new_id, new_plugin_id],
+                )?;
+            }
+        }
+        tx.commit()?;
+        Ok(new_id)
+    }
+}
+
+/// Fail with [`DataError::ProfileLocked`] if the profile is locked. A missing
+/// profile is not considered locked.
+pub(super) fn ensure_unlocked(profile_id: u32, conn: &super::Connection) -> DataResult<()> {
+    let locked: Option<bool> = conn
+        .query_row(
+            "SELECT `locked` FROM `yt_profiles` WHERE `id` = ?",
+            [profile_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if locked == Some(true) {
+        return Err(DataError::ProfileLocked { profile_id });
+    }
+    Ok(())
+}
+
+/// Like [`ensure_unlocked`], but for the profile the plugin belongs to.
+pub(super) fn ensure_plugin_unlocked(plugin_id: u32, conn: &super::Connection) -> DataResult<()> {
+    let profile_id: Option<u32> = conn
+        .query_row(
+            "SELECT `profile_id` FROM `yt_plugins` WHERE `id` = ?",
+            [plugin_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    match profile_id {
+        Some(profile_id) => ensure_unlocked(profile_id, conn),
+        None => Ok(()),
+    }
 }
@@ -0,0 +1,308 @@
+use chrono::NaiveDateTime;
+use rusqlite::{params, Error as SqError, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+
+use super::*;
+
+pub type ProfileSnapshotId = super::Id<ProfileSnapshot>;
+
+/// A named copy of the plugins of a profile, which can be restored later to
+/// roll back changes made since.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfileSnapshot {
+    pub id: ProfileSnapshotId,
+    pub profile_id: ProfileId,
+    pub name: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotData {
+    plugins: Vec<SnapshotPlugin>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotPlugin {
+    name: String,
+    desc: String,
+    plugin: String,
+    plugin_version: u16,
+    param: serde_bytes::ByteBuf,
+    is_entry: bool,
+}
+
+fn map_from_row(row: &Row) -> Result<ProfileSnapshot, SqError> {
+    Ok(ProfileSnapshot {
+        id: super::Id(row.get(0)?, Default::default()),
+        profile_id: super::Id(row.get(1)?, Default::default()),
+        name: row.get(2)?,
+        created_at: row.get(3)?,
+    })
+}
+
+impl ProfileSnapshot {
+    pub fn query_all_by_profile(
+        profile_id: ProfileId,
+        conn: &super::Connection,
+    ) -> DataResult<Vec<ProfileSnapshot>> {
+        let mut stmt = conn.prepare_cached(
+            r"SELECT `id`, `profile_id`, `name`, `created_at` FROM `yt_profile_snapshots`
+            WHERE `profile_id` = ? ORDER BY `id` ASC",
+        )?;
+        let ret = stmt
+            .query_and_then([&profile_id.0], map_from_row)?
+            .filter_map(|r: Result<ProfileSnapshot, SqError>| r.ok())
+            .collect();
+        Ok(ret)
+    }
+    pub fn query_by_name(
+        profile_id: ProfileId,
+        name: &str,
+        conn: &super::Connection,
+    ) -> DataResult<Option<ProfileSnapshot>> {
+        Ok(conn
+            .query_row_and_then(
+                r"SELECT `id`, `profile_id`, `name`, `created_at` FROM `yt_profile_snapshots`
+                WHERE `profile_id` = ? AND `name` = ?",
+                params![profile_id.0, name],
+                map_from_row,
+            )
+            .optional()?)
+    }
+    /// Record the current plugins and entry plugins of a profile. Locked
+    /// profiles can be snapshotted as well.
+    pub fn create(
+        profile_id: ProfileId,
+        name: String,
+        conn: &super::Connection,
+    ) -> DataResult<u32> {
+        let entry_ids: Vec<_> = Plugin::query_entry_by_profile(profile_id, conn)?
+            .into_iter()
+            .map(|p| p.id)
+            .collect();
+        let plugins = Plugin::query_all_by_profile(profile_id, conn)?
+            .into_iter()
+            .map(|p| SnapshotPlugin {
+                is_entry: entry_ids.contains(&p.id),
+                name: p.name,
+                desc: p.desc,
+                plugin: p.plugin,
+                plugin_version: p.plugin_version,
+                param: p.param,
+            })
+            .collect();
+        let data = cbor4ii::serde::to_vec(vec![], &SnapshotData { plugins }).unwrap();
+        conn.execute(
+            "INSERT INTO `yt_profile_snapshots` (`profile_id`, `name`, `data`) VALUES (?, ?, ?)",
+            params![profile_id.0, name, data],
+        )?;
+        Ok(conn.last_insert_rowid() as u32)
+    }
+    /// Bring the plugins and entry plugins of the profile back to the state
+    /// recorded in the snapshot. Plugins are matched by name: matching plugins
+    /// are updated in place and keep their IDs, so that their caches and
+    /// translations survive; the rest are deleted or recreated.
+    pub fn restore(id: u32, conn: &mut super::Connection) -> DataResult<()> {
+        let tx = conn.transaction()?;
+        let (profile_id, data): (u32, Vec<u8>) = tx.query_row(
+            "SELECT `profile_id`, `data` FROM `yt_profile_snapshots` WHERE `id` = ?",
+            [id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        super::profile::ensure_unlocked(profile_id, &tx)?;
+        let data: SnapshotData =
+            cbor4ii::serde::from_slice(&data).map_err(|_| DataError::InvalidData {
+                domain: "profile_snapshot",
+                field: "data",
+            })?;
+
+        let existing = Plugin::query_all_by_profile(profile_id.into(), &tx)?;
+        for plugin in existing
+            .iter()
+            .filter(|p| !data.plugins.iter().any(|s| s.name == p.name))
+        {
+            tx.execute("DELETE FROM `yt_plugins` WHERE `id` = ?", [plugin.id.0])?;
+        }
+        tx.execute(
+            "DELETE FROM `yt_profile_entry_plugin` WHERE `profile_id` = ?",
+            [profile_id],
+        )?;
+        for snapshot in data.plugins {
+            let plugin_id = match existing.iter().find(|p| p.name == snapshot.name) {
+                Some(p)
+                    if p.desc == snapshot.desc
+                        && p.plugin == snapshot.plugin
+                        && p.plugin_version == snapshot.plugin_version
+                        && p.param == snapshot.param =>
+                {
+                    p.id.0
+                }
+                Some(p) => {
+                    tx.execute(
+                        "UPDATE `yt_plugins` SET `desc` = ?, `plugin` = ?, `plugin_version` = ?, `param` = ? WHERE `id` = ?",
+                        params![
+                            snapshot.desc,
+                            snapshot.plugin,
+                            snapshot.plugin_version,
+                            snapshot.param.into_vec(),
+                            p.id.0
+                        ],
+                    )?;
+                    p.id.0
+                }
+                None => {
+                    tx.execute(
+                        "INSERT INTO `yt_plugins` (`profile_id`, `name`, `desc`, `plugin`, `plugin_version`, `param`) VALUES (?, ?, ?, ?, ?, ?)",
+                        params![
+                            profile_id,
+                            snapshot.name,
+                            snapshot.desc,
+                            snapshot.plugin,
+                            snapshot.plugin_version,
+                            snapshot.param.into_vec()
+                        ],
+                    )?;
+                    tx.last_insert_rowid() as u32
+                }
+            };
+            if snapshot.is_entry {
+                tx.execute(
+                    "INSERT INTO `yt_profile_entry_plugin` (`profile_id`, `plugin_id`) VALUES (?, ?)",
+                    params![profile_id, plugin_id],
+                )?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+    pub fn rename(id: u32, name: String, conn: &super::Connection) -> DataResult<()> {
+        conn.execute(
+            "UPDATE `yt_profile_snapshots` SET `name` = ? WHERE `id` = ?",
+            params![name, id],
+        )?;
+        Ok(())
+    }
+    pub fn delete(id: u32, conn: &super::Connection) -> DataResult<()> {
+        conn.execute("DELETE FROM `yt_profile_snapshots` WHERE `id` = ?", [id])?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_plugin(profile_id: ProfileId, name: &str, conn: &Connection) -> PluginId {
+        Plugin::create(
+            profile_id,
+            name.into(),
+            "".into(),
+            "null".into(),
+            0,
+            vec![0xf6],
+            conn,
+        )
+        .unwrap()
+        .into()
+    }
+
+    #[test]
+    fn test_duplicate_profile() {
+        let mut conn = Database::connect_temp().unwrap();
+        let profile_id: ProfileId = Profile::create("a".into(), "en-US".into(), &conn)
+            .unwrap()
+            .into();
+        let entry_id = create_plugin(profile_id, "entry", &conn);
+        create_plugin(profile_id, "other", &conn);
+        Plugin::set_as_entry(profile_id, entry_id, &conn).unwrap();
+        PluginTranslation::upsert(entry_id, "ja".into(), "d".into(), &conn).unwrap();
+        Profile::set_locked(profile_id.0, true, &conn).unwrap();
+
+        let copy_id: ProfileId = Profile::duplicate(profile_id.0, "b".into(), &mut conn)
+            .unwrap()
+            .into();
+        let copy = Profile::query_by_id(copy_id.0 as _, &conn)
+            .unwrap()
+            .unwrap();
+        assert_eq!((&*copy.name, copy.locked), ("b", false));
+        let plugins = Plugin::query_all_by_profile(copy_id, &conn).unwrap();
+        assert_eq!(
+            plugins.iter().map(|p| &*p.name).collect::<Vec<_>>(),
+            ["entry", "other"]
+        );
+        let entries = Plugin::query_entry_by_profile(copy_id, &conn).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].id, plugins[0].id);
+        assert_ne!(entries[0].id, entry_id);
+        assert_eq!(
+            PluginTranslation::query_all_by_profile(copy_id, &conn)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(Profile::duplicate(profile_id.0, "b".into(), &mut conn).is_err());
+        assert!(Profile::duplicate(u32::MAX, "c".into(), &mut conn).is_err());
+    }
+
+    #[test]
+    fn test_snapshot_restore() {
+        let mut conn = Database::connect_temp().unwrap();
+        let profile_id: ProfileId = Profile::create("a".into(), "en-US".into(), &conn)
+            .unwrap()
+            .into();
+        let kept_id = create_plugin(profile_id, "kept", &conn);
+        let removed_id = create_plugin(profile_id, "removed", &conn);
+        Plugin::set_as_entry(profile_id, kept_id, &conn).unwrap();
+        let snapshot_id = ProfileSnapshot::create(profile_id, "v1".into(), &conn).unwrap();
+        assert!(ProfileSnapshot::create(profile_id, "v1".into(), &conn).is_err());
+
+        Plugin::update(
+            kept_id.0,
+            profile_id,
+            "kept".into(),
+            "changed".into(),
+            "null".into(),
+            1,
+            vec![],
+            &conn,
+        )
+        .unwrap();
+        Plugin::unset_as_entry(profile_id, kept_id, &conn).unwrap();
+        Plugin::delete(removed_id.0, &conn).unwrap();
+        create_plugin(profile_id, "added", &conn);
+
+        Profile::set_locked(profile_id.0, true, &conn).unwrap();
+        assert!(matches!(
+            ProfileSnapshot::restore(snapshot_id, &mut conn),
+            Err(DataError::ProfileLocked { .. })
+        ));
+        Profile::set_locked(profile_id.0, false, &conn).unwrap();
+        ProfileSnapshot::restore(snapshot_id, &mut conn).unwrap();
+
+        let plugins = Plugin::query_all_by_profile(profile_id, &conn).unwrap();
+        assert_eq!(
+            plugins.iter().map(|p| &*p.name).collect::<Vec<_>>(),
+            ["kept", "removed"]
+        );
+        assert_eq!(plugins[0].id, kept_id);
+        assert_eq!(
+            (
+                &*plugins[0].desc,
+                plugins[0].plugin_version,
+                &plugins[0].param[..]
+            ),
+            ("", 0, &[0xf6][..])
+        );
+        let entries = Plugin::query_entry_by_profile(profile_id, &conn).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].id, kept_id);
+
+        let snapshots = ProfileSnapshot::query_all_by_profile(profile_id, &conn).unwrap();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].id.0, snapshot_id);
+        ProfileSnapshot::delete(snapshot_id, &conn).unwrap();
+        assert!(ProfileSnapshot::query_by_name(profile_id, "v1", &conn)
+            .unwrap()
+            .is_none());
+    }
+}
@@ -164,15 +164,44 @@ impl Proxy {
         new_proxies: Vec<ProxyInput>,
         conn: &mut Connection,
     ) -> DataResult<()> {
+        Self::batch_update_by_group_with_rotation(proxy_group_id, new_proxies, |_, _| false, conn)
+            .map(|_| ())
+    }
+
+    /// Same as [`Proxy::batch_update_by_group`], except that proxies for which
+    /// `is_credential_rotation` returns true are updated in place, keeping their
+    /// IDs, order and associated statistics. Returns the IDs of proxies updated in place.
+    pub fn batch_update_by_group_with_rotation(
+        proxy_group_id: ProxyGroupId,
+        new_proxies: Vec<ProxyInput>,
+        is_credential_rotation: impl Fn(&Proxy, &ProxyInput) -> bool,
+        conn: &mut Connection,
+    ) -> DataResult<Vec<ProxyId>> {
         let tx = conn.transaction()?;
 
         let old_proxies = Self::query_all_by_group(proxy_group_id, &tx)?;
+        let mut rotated = vec![];
         // Delete all proxies starting from the first proxy that is not in the new list, and then insert all new proxies from that point.
         let mut zipped = old_proxies.iter().zip_longest(new_proxies);
         let mut proxy_to_insert_from = loop {
             let mut proxy_to_insert_from = None;
             let proxy_to_delete_from = match zipped.next() {
                 Some(EitherOrBoth::Both(old, new)) if are_proxies_equivalent(old, &new) => continue,
+                Some(EitherOrBoth::Both(old, new))
+                    if old.name == new.name
+                        && old.proxy_version == new.proxy_version
+                        && is_credential_rotation(old, &new) =>
+                {
+                    Self::update(
+                        old.id.0,
+                        new.name,
+                        new.proxy.into_vec(),
+                        new.proxy_version,
+                        &tx,
+                    )?;
+                    rotated.push(old.id);
+                    continue;
+                }
                 Some(EitherOrBoth::Both(old, new)) => {
                     proxy_to_insert_from = Some(EitherOrBoth::Right(new));
                     old
@@ -207,6 +236,55 @@ impl Proxy {
         }
 
         tx.commit()?;
-        Ok(())
+        Ok(rotated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(name: &str, proxy: &[u8]) -> ProxyInput {
+        ProxyInput {
+            name: name.into(),
+            proxy: serde_bytes::ByteBuf::from(proxy),
+            proxy_version: 0,
+        }
+    }
+
+    #[test]
+    fn test_batch_update_with_rotation_persists_in_place() {
+        let mut conn = Database::connect_temp().unwrap();
+        let group_id: ProxyGroupId = ProxyGroup::create("g".into(), "manual".into(), &conn)
+            .unwrap()
+            .into();
+        Proxy::batch_update_by_group(
+            group_id,
+            vec![input("a", b"a1"), input("b", b"b1")],
+            &mut conn,
+        )
+        .unwrap();
+        let old = Proxy::query_all_by_group(group_id, &conn).unwrap();
+
+        let rotated = Proxy::batch_update_by_group_with_rotation(
+            group_id,
+            vec![input("a", b"a2"), input("b", b"b1"), input("c", b"c1")],
+            |old, new| old.name == "a" && &*new.proxy == b"a2",
+            &mut conn,
+        )
+        .unwrap();
+        assert_eq!(rotated, [old[0].id]);
+
+        let new = Proxy::query_all_by_group(group_id, &conn).unwrap();
+        let summary: Vec<_> = new
+            .iter()
+            .map(|p| (p.name.as_str(), p.proxy.as_slice()))
+            .collect();
+        assert_eq!(
+            summary,
+            [("a", &b"a2"[..]), ("b", &b"b1"[..]), ("c", &b"c1"[..])]
+        );
+        assert_eq!(new[0].id, old[0].id);
+        assert_eq!(new[1].id, old[1].id);
     }
 }
@@ -23,6 +23,8 @@ pub struct ProxySubscription {
     pub bytes_total: Option<u64>,
     pub expires_at: Option<String>,
     pub retrieved_at: Option<NaiveDateTime>,
+    pub update_frequency: Option<String>,
+    pub additional_info: Option<String>,
 }
 
 pub const PROXY_GROUP_TYPE_MANUAL: &str = "manual";
@@ -46,6 +48,8 @@ fn map_subscription_from_row(row: &Row) -> Result<ProxySubscription, SqError> {
         bytes_total: row.get(4)?,
         expires_at: row.get(5)?,
         retrieved_at: row.get(6)?,
+        update_frequency: row.get(7)?,
+        additional_info: row.get(8)?,
     })
 }
 
@@ -116,7 +120,7 @@ impl ProxySubscription {
     ) -> DataResult<ProxySubscription> {
         Ok(conn
             .query_row_and_then(
-                r"SELECT `format`, `url`, `upload_bytes_used`, `download_bytes_used`, `bytes_total`, `expires_at`, `retrieved_at`
+                r"SELECT `format`, `url`, `upload_bytes_used`, `download_bytes_used`, `bytes_total`, `expires_at`, `retrieved_at`, `update_frequency`, `additional_info`
                 FROM `yt_proxy_subscriptions` WHERE `proxy_group_id` = ?",
                 [&proxy_group_id],
                 map_subscription_from_row,
@@ -148,4 +152,19 @@ impl ProxySubscription {
         )?;
         Ok(())
     }
+    pub fn update_metadata_by_proxy_group_id(
+        proxy_group_id: u32,
+        update_frequency: Option<String>,
+        additional_info: Option<String>,
+        conn: &super::Connection,
+    ) -> DataResult<()> {
+        conn.execute(
+            r"UPDATE `yt_proxy_subscriptions` SET
+            `update_frequency` = ?,
+            `additional_info` = ?
+            WHERE `proxy_group_id` = ?",
+            params![update_frequency, additional_info, proxy_group_id],
+        )?;
+        Ok(())
+    }
 }
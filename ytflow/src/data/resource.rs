@@ -7,6 +7,7 @@ use super::*;
 pub type ResourceId = super::Id<Resource>;
 pub type ResourceUrlId = super::Id<ResourceUrl>;
 pub type ResourceGitHubReleaseId = super::Id<ResourceGitHubRelease>;
+pub type ResourceMaxmindPermalinkId = super::Id<ResourceMaxmindPermalink>;
 
 #[derive(Debug, Clone, Serialize)]
 pub struct Resource {
@@ -39,6 +40,18 @@ pub struct ResourceGitHubRelease {
     pub retrieved_at: Option<NaiveDateTime>,
 }
 
+/// A MaxMind GeoLite2 or GeoIP2 database downloaded from its permalink with the credentials
+/// of a MaxMind account.
+#[derive(Debug, Clone, Serialize)]
+pub struct ResourceMaxmindPermalink {
+    pub id: ResourceMaxmindPermalinkId,
+    pub edition_id: String,
+    pub account_id: String,
+    pub license_key: String,
+    pub last_modified: Option<String>,
+    pub retrieved_at: Option<NaiveDateTime>,
+}
+
 fn map_resource_from_row(row: &Row) -> Result<Resource, SqError> {
     Ok(Resource {
         id: super::Id(row.get(0)?, Default::default()),
@@ -73,6 +86,17 @@ fn map_resource_github_release_from_row(row: &Row) -> Result<ResourceGitHubRelea
     })
 }
 
+fn map_resource_maxmind_permalink_from_row(row: &Row) -> Result<ResourceMaxmindPermalink, SqError> {
+    Ok(ResourceMaxmindPermalink {
+        id: super::Id(row.get(0)?, Default::default()),
+        edition_id: row.get(1)?,
+        account_id: row.get(2)?,
+        license_key: row.get(3)?,
+        last_modified: row.get(4)?,
+        retrieved_at: row.get(5)?,
+    })
+}
+
 impl Resource {
     pub fn query_all(conn: &super::Connection) -> DataResult<Vec<Resource>> {
         let mut stmt = conn.prepare_cached(
@@ -86,6 +110,18 @@ impl Resource {
         Ok(ret)
     }
 
+    pub fn query_by_id(id: u32, conn: &super::Connection) -> DataResult<Option<Resource>> {
+        let mut stmt = conn.prepare_cached(
+            "SELECT `id`, `key`, `type`, `local_file`, `remote_type`, `created_at`, `updated_at` 
+         FROM `yt_resources` WHERE `id` = ?",
+        )?;
+        let ret = stmt
+            .query_and_then(params![id], map_resource_from_row)?
+            .next()
+            .transpose()?;
+        Ok(ret)
+    }
+
     pub fn query_by_key(key: &str, conn: &super::Connection) -> DataResult<Option<Resource>> {
         let mut stmt = conn.prepare_cached(
             "SELECT `id`, `key`, `type`, `local_file`, `remote_type`, `created_at`, `updated_at` 
@@ -142,6 +178,29 @@ impl Resource {
         Ok(resource_id)
     }
 
+    pub fn create_with_maxmind_permalink(
+        key: String,
+        r#type: String,
+        local_file: String,
+        edition_id: String,
+        account_id: String,
+        license_key: String,
+        conn: &mut super::Connection,
+    ) -> DataResult<u32> {
+        let tx = conn.transaction()?;
+        tx.execute(
+            r"INSERT INTO `yt_resources` (`key`, `type`, `local_file`, `remote_type`) VALUES (?, ?, ?, ?)",
+            params![key, r#type, local_file, "maxmind_permalink"],
+        )?;
+        let resource_id = tx.last_insert_rowid() as u32;
+        tx.execute(
+            r"INSERT INTO `yt_resources_maxmind_permalink` (`resource_id`, `edition_id`, `account_id`, `license_key`) VALUES (?, ?, ?, ?)",
+            params![resource_id, edition_id, account_id, license_key],
+        )?;
+        tx.commit()?;
+        Ok(resource_id)
+    }
+
     pub fn delete(id: u32, conn: &super::Connection) -> DataResult<()> {
         conn.execute("DELETE FROM `yt_resources` WHERE `id` = ?", params![id])?;
         Ok(())
@@ -204,3 +263,30 @@ impl ResourceGitHubRelease {
         Ok(())
     }
 }
+
+impl ResourceMaxmindPermalink {
+    pub fn query_by_resource_id(
+        resource_id: u32,
+        conn: &super::Connection,
+    ) -> DataResult<Option<ResourceMaxmindPermalink>> {
+        Ok(conn
+            .query_row_and_then(
+                r"SELECT `id`, `edition_id`, `account_id`, `license_key`, `last_modified`, `retrieved_at`
+                FROM `yt_resources_maxmind_permalink` WHERE `resource_id` = ?",
+                [&resource_id],
+                map_resource_maxmind_permalink_from_row,
+            )
+            .optional()?)
+    }
+    pub fn update_retrieved_by_resource_id(
+        resource_id: u32,
+        last_modified: Option<String>,
+        conn: &super::Connection,
+    ) -> DataResult<()> {
+        conn.execute(
+            r"UPDATE `yt_resources_maxmind_permalink` SET `last_modified` = ?, `retrieved_at` = (strftime('%Y-%m-%d %H:%M:%f', 'now')) WHERE `resource_id` = ?",
+            params![last_modified, resource_id],
+        )?;
+        Ok(())
+    }
+}
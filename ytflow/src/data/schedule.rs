@@ -0,0 +1,316 @@
+use chrono::{Datelike, NaiveDate, NaiveDateTime, Timelike};
+use rusqlite::types::Type;
+use rusqlite::{params, Error as SqError, Row};
+use serde::Serialize;
+
+use super::*;
+
+pub type ScheduleId = super::Id<Schedule>;
+
+/// What happens when a schedule fires.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ScheduleAction {
+    /// Select the choice named `choice` in the switch plugin `plugin` of the
+    /// running profile.
+    SwitchChoice { plugin: String, choice: String },
+    /// Stop the running profile and start another one.
+    ActivateProfile { profile_id: ProfileId },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Schedule {
+    pub id: ScheduleId,
+    pub name: String,
+    /// See [`CronSchedule`].
+    pub cron: String,
+    pub action: ScheduleAction,
+    pub enabled: bool,
+    pub created_at: NaiveDateTime,
+}
+
+/// A five-field cron expression (minute, hour, day of month, month and day of
+/// week), evaluated in local time. Each field accepts `*`, numbers, ranges
+/// (`1-5`), steps (`*/15`, `8-18/2`) and lists of them (`1,3,5`). Sunday is
+/// either 0 or 7.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u32,
+    days: u32,
+    months: u16,
+    weekdays: u8,
+    days_any: bool,
+    weekdays_any: bool,
+}
+
+fn parse_cron_field(field: &str, min: u32, max: u32) -> Option<u64> {
+    let mut mask = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse().ok().filter(|&s: &usize| s > 0)?),
+            None => (part, 1),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (start.parse().ok()?, end.parse().ok()?)
+        } else {
+            let start = range.parse().ok()?;
+            // `5/15` runs from 5 to the end of the range
+            (start, if step > 1 { max } else { start })
+        };
+        if start < min || end > max || start > end {
+            return None;
+        }
+        for v in (start..=end).step_by(step) {
+            mask |= 1u64 << v;
+        }
+    }
+    Some(mask)
+}
+
+impl CronSchedule {
+    pub fn parse(expr: &str) -> Option<Self> {
+        let fields: Vec<_> = expr.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return None;
+        };
+        let weekdays = parse_cron_field(weekday, 0, 7)?;
+        Some(Self {
+            minutes: parse_cron_field(minute, 0, 59)?,
+            hours: parse_cron_field(hour, 0, 23)? as u32,
+            days: parse_cron_field(day, 1, 31)? as u32,
+            months: parse_cron_field(month, 1, 12)? as u16,
+            weekdays: ((weekdays | weekdays >> 7) & 0x7f) as u8,
+            days_any: day.starts_with('*'),
+            weekdays_any: weekday.starts_with('*'),
+        })
+    }
+
+    fn matches_date(&self, date: NaiveDate) -> bool {
+        if self.months & (1 << date.month()) == 0 {
+            return false;
+        }
+        let day = self.days & (1 << date.day()) != 0;
+        let weekday = self.weekdays & (1 << date.weekday().num_days_from_sunday()) != 0;
+        // As in Vixie cron, when both day of month and day of week are
+        // restricted, matching either of them is enough.
+        if self.days_any || self.weekdays_any {
+            day && weekday
+        } else {
+            day || weekday
+        }
+    }
+
+    /// Returns the first matching minute strictly after `after`, looking
+    /// ahead eight years at most.
+    pub fn next_after(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        let mut date = after.date();
+        let mut from = after.hour() * 60 + after.minute() + 1;
+        for _ in 0..366 * 8 {
+            if self.matches_date(date) {
+                let found = (from..24 * 60).find(|m| {
+                    self.hours & (1 << (m / 60)) != 0 && self.minutes & (1 << (m % 60)) != 0
+                });
+                if let Some(m) = found {
+                    return date.and_hms_opt(m / 60, m % 60, 0);
+                }
+            }
+            date = date.succ_opt()?;
+            from = 0;
+        }
+        None
+    }
+}
+
+fn map_from_row(row: &Row) -> Result<Schedule, SqError> {
+    let action = match (row.get(3)?, row.get(4)?, row.get::<_, Option<u32>>(5)?) {
+        (Some(plugin), Some(choice), None) => ScheduleAction::SwitchChoice { plugin, choice },
+        (None, None, Some(profile_id)) => ScheduleAction::ActivateProfile {
+            profile_id: profile_id.into(),
+        },
+        _ => {
+            return Err(SqError::InvalidColumnType(
+                5,
+                String::from("profile_id"),
+                Type::Null,
+            ))
+        }
+    };
+    Ok(Schedule {
+        id: super::Id(row.get(0)?, Default::default()),
+        name: row.get(1)?,
+        cron: row.get(2)?,
+        action,
+        enabled: row.get(6)?,
+        created_at: row.get(7)?,
+    })
+}
+
+fn validate(cron: &str) -> DataResult<()> {
+    // An expression such as `0 0 31 2 *` is valid but never fires. Eight years
+    // starting from a leap year cover every date.
+    let epoch = NaiveDate::from_ymd_opt(2000, 1, 1)
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap();
+    CronSchedule::parse(cron)
+        .and_then(|c| c.next_after(epoch))
+        .map(|_| ())
+        .ok_or(DataError::InvalidData {
+            domain: "schedule",
+            field: "cron",
+        })
+}
+
+fn action_columns(action: &ScheduleAction) -> (Option<&str>, Option<&str>, Option<u32>) {
+    match action {
+        ScheduleAction::SwitchChoice { plugin, choice } => (Some(plugin), Some(choice), None),
+        ScheduleAction::ActivateProfile { profile_id } => (None, None, Some(profile_id.0)),
+    }
+}
+
+impl Schedule {
+    pub fn cron_schedule(&self) -> Option<CronSchedule> {
+        CronSchedule::parse(&self.cron)
+    }
+
+    pub fn query_all(conn: &super::Connection) -> DataResult<Vec<Schedule>> {
+        let mut stmt = conn.prepare_cached(
+            r"SELECT `id`, `name`, `cron`, `switch_plugin`, `switch_choice`, `profile_id`, `enabled`, `created_at`
+            FROM `yt_schedules` ORDER BY `id` ASC",
+        )?;
+        let ret = stmt
+            .query_and_then([], map_from_row)?
+            .filter_map(|r: Result<Schedule, SqError>| r.ok())
+            .collect();
+        Ok(ret)
+    }
+    pub fn create(
+        name: String,
+        cron: String,
+        action: &ScheduleAction,
+        conn: &super::Connection,
+    ) -> DataResult<u32> {
+        validate(&cron)?;
+        let (switch_plugin, switch_choice, profile_id) = action_columns(action);
+        conn.execute(
+            "INSERT INTO `yt_schedules` (`name`, `cron`, `switch_plugin`, `switch_choice`, `profile_id`) VALUES (?, ?, ?, ?, ?)",
+            params![name, cron, switch_plugin, switch_choice, profile_id],
+        )?;
+        Ok(conn.last_insert_rowid() as u32)
+    }
+    pub fn update(
+        id: u32,
+        name: String,
+        cron: String,
+        action: &ScheduleAction,
+        enabled: bool,
+        conn: &super::Connection,
+    ) -> DataResult<()> {
+        validate(&cron)?;
+        let (switch_plugin, switch_choice, profile_id) = action_columns(action);
+        conn.execute(
+            "UPDATE `yt_schedules` SET `name` = ?, `cron` = ?, `switch_plugin` = ?, `switch_choice` = ?, `profile_id` = ?, `enabled` = ? WHERE `id` = ?",
+            params![name, cron, switch_plugin, switch_choice, profile_id, enabled, id],
+        )?;
+        Ok(())
+    }
+    pub fn delete(id: u32, conn: &super::Connection) -> DataResult<()> {
+        conn.execute("DELETE FROM `yt_schedules` WHERE `id` = ?", [id])?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(y: i32, m: u32, d: u32, h: u32, min: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, m, d)
+            .unwrap()
+            .and_hms_opt(h, min, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_cron_next_after() {
+        // Work hours on weekdays. 2024-01-05 is a Friday.
+        let cron = CronSchedule::parse("0 9 * * 1-5").unwrap();
+        assert_eq!(
+            cron.next_after(at(2024, 1, 5, 8, 59)),
+            Some(at(2024, 1, 5, 9, 0))
+        );
+        assert_eq!(
+            cron.next_after(at(2024, 1, 5, 9, 0)),
+            Some(at(2024, 1, 8, 9, 0))
+        );
+        let cron = CronSchedule::parse("*/20 22-23 * * 0,7").unwrap();
+        assert_eq!(
+            cron.next_after(at(2024, 1, 7, 22, 41)),
+            Some(at(2024, 1, 7, 23, 0))
+        );
+    }
+
+    #[test]
+    fn test_cron_day_or_weekday() {
+        // The 13th of every month or any Friday
+        let cron = CronSchedule::parse("0 0 13 * 5").unwrap();
+        assert_eq!(
+            cron.next_after(at(2024, 1, 1, 0, 0)),
+            Some(at(2024, 1, 5, 0, 0))
+        );
+        assert_eq!(
+            cron.next_after(at(2024, 1, 12, 0, 0)),
+            Some(at(2024, 1, 13, 0, 0))
+        );
+    }
+
+    #[test]
+    fn test_cron_invalid() {
+        for expr in [
+            "",
+            "* * * *",
+            "60 * * * *",
+            "* * 0 * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+        ] {
+            assert_eq!(CronSchedule::parse(expr), None, "{}", expr);
+        }
+        assert!(validate("0 0 31 2 *").is_err());
+        assert!(validate("0 0 29 2 *").is_ok());
+    }
+
+    #[test]
+    fn test_schedule_crud() {
+        let conn = Database::connect_temp().unwrap();
+        let action = ScheduleAction::SwitchChoice {
+            plugin: "main-switch".into(),
+            choice: "direct".into(),
+        };
+        let id = Schedule::create("Night".into(), "0 23 * * *".into(), &action, &conn).unwrap();
+        assert!(Schedule::create("Bad".into(), "0 24 * * *".into(), &action, &conn).is_err());
+        let schedules = Schedule::query_all(&conn).unwrap();
+        assert_eq!(schedules.len(), 1);
+        assert_eq!(schedules[0].action, action);
+        assert!(schedules[0].enabled);
+
+        Schedule::update(
+            id,
+            "Night".into(),
+            "0 22 * * *".into(),
+            &action,
+            false,
+            &conn,
+        )
+        .unwrap();
+        let schedule = &Schedule::query_all(&conn).unwrap()[0];
+        assert_eq!(schedule.cron, "0 22 * * *");
+        assert!(!schedule.enabled);
+
+        Schedule::delete(id, &conn).unwrap();
+        assert!(Schedule::query_all(&conn).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,136 @@
+use chrono::NaiveDateTime;
+use rusqlite::{params, Error as SqError, OptionalExtension, Row};
+use serde::Serialize;
+
+use super::*;
+
+pub type SigningKeyId = super::Id<SigningKey>;
+pub type TrustedKeyId = super::Id<TrustedKey>;
+
+/// An ed25519 key pair owned by this device, used to sign exported profile bundles.
+#[derive(Debug, Clone, Serialize)]
+pub struct SigningKey {
+    pub id: SigningKeyId,
+    pub name: String,
+    #[serde(skip)]
+    pub secret_key: [u8; 32],
+    #[serde(with = "serde_bytes")]
+    pub public_key: [u8; 32],
+    pub created_at: NaiveDateTime,
+}
+
+/// The ed25519 public key of a publisher whose profile bundles are trusted.
+#[derive(Debug, Clone, Serialize)]
+pub struct TrustedKey {
+    pub id: TrustedKeyId,
+    pub name: String,
+    #[serde(with = "serde_bytes")]
+    pub public_key: [u8; 32],
+    pub created_at: NaiveDateTime,
+}
+
+fn get_key(row: &Row, idx: usize, field: &'static str) -> Result<[u8; 32], SqError> {
+    let row_ref = row.get_ref(idx)?;
+    row_ref
+        .as_blob()
+        .ok()
+        .and_then(|b| <[u8; 32]>::try_from(b).ok())
+        .ok_or_else(|| SqError::InvalidColumnType(idx, String::from(field), row_ref.data_type()))
+}
+
+fn map_signing_key_from_row(row: &Row) -> Result<SigningKey, SqError> {
+    Ok(SigningKey {
+        id: super::Id(row.get(0)?, Default::default()),
+        name: row.get(1)?,
+        secret_key: get_key(row, 2, "secret_key")?,
+        public_key: get_key(row, 3, "public_key")?,
+        created_at: row.get(4)?,
+    })
+}
+
+fn map_trusted_key_from_row(row: &Row) -> Result<TrustedKey, SqError> {
+    Ok(TrustedKey {
+        id: super::Id(row.get(0)?, Default::default()),
+        name: row.get(1)?,
+        public_key: get_key(row, 2, "public_key")?,
+        created_at: row.get(3)?,
+    })
+}
+
+impl SigningKey {
+    pub fn query_by_id(id: u32, conn: &super::Connection) -> DataResult<Option<SigningKey>> {
+        Ok(conn
+            .query_row_and_then(
+                r"SELECT `id`, `name`, `secret_key`, `public_key`, `created_at`
+                FROM `yt_signing_keys` WHERE `id` = ?",
+                [&id],
+                map_signing_key_from_row,
+            )
+            .optional()?)
+    }
+    pub fn query_all(conn: &super::Connection) -> DataResult<Vec<SigningKey>> {
+        let mut stmt = conn.prepare_cached("SELECT `id`, `name`, `secret_key`, `public_key`, `created_at` FROM `yt_signing_keys` ORDER BY `id` ASC")?;
+        let ret = stmt
+            .query_and_then([], map_signing_key_from_row)?
+            .filter_map(|r: Result<SigningKey, SqError>| r.ok())
+            .collect();
+        Ok(ret)
+    }
+    pub fn create(
+        name: String,
+        secret_key: [u8; 32],
+        public_key: [u8; 32],
+        conn: &super::Connection,
+    ) -> DataResult<u32> {
+        conn.execute(
+            "INSERT INTO `yt_signing_keys` (`name`, `secret_key`, `public_key`) VALUES (?, ?, ?)",
+            params![name, &secret_key[..], &public_key[..]],
+        )?;
+        Ok(conn.last_insert_rowid() as u32)
+    }
+    pub fn delete(id: u32, conn: &super::Connection) -> DataResult<()> {
+        conn.execute("DELETE FROM `yt_signing_keys` WHERE `id` = ?", [id])?;
+        Ok(())
+    }
+}
+
+impl TrustedKey {
+    pub fn query_by_public_key(
+        public_key: &[u8; 32],
+        conn: &super::Connection,
+    ) -> DataResult<Option<TrustedKey>> {
+        Ok(conn
+            .query_row_and_then(
+                r"SELECT `id`, `name`, `public_key`, `created_at`
+                FROM `yt_trusted_keys` WHERE `public_key` = ?",
+                [&public_key[..]],
+                map_trusted_key_from_row,
+            )
+            .optional()?)
+    }
+    pub fn query_all(conn: &super::Connection) -> DataResult<Vec<TrustedKey>> {
+        let mut stmt = conn.prepare_cached("SELECT `id`, `name`, `public_key`, `created_at` FROM `yt_trusted_keys` ORDER BY `id` ASC")?;
+        let ret = stmt
+            .query_and_then([], map_trusted_key_from_row)?
+            .filter_map(|r: Result<TrustedKey, SqError>| r.ok())
+            .collect();
+        Ok(ret)
+    }
+    pub fn create(name: String, public_key: &[u8], conn: &super::Connection) -> DataResult<u32> {
+        if public_key.len() != 32 {
+            return Err(DataError::InvalidData {
+                domain: "trusted_key",
+                field: "public_key",
+            });
+        }
+        conn.execute(
+            "INSERT INTO `yt_trusted_keys` (`name`, `public_key`) VALUES (?, ?)",
+            params![name, public_key],
+        )?;
+        Ok(conn.last_insert_rowid() as u32)
+    }
+    pub fn delete(id: u32, conn: &super::Connection) -> DataResult<()> {
+        conn.execute("DELETE FROM `yt_trusted_keys` WHERE `id` = ?", [id])?;
+        Ok(())
+    }
+}
@@ -0,0 +1,79 @@
+use chrono::NaiveDateTime;
+use rusqlite::{params, Error as SqError, OptionalExtension, Row};
+use serde::Serialize;
+
+use super::*;
+
+pub type SyncStateId = super::Id<SyncState>;
+
+/// What was last agreed with the remote endpoint about a synced item.
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncState {
+    pub id: SyncStateId,
+    /// The key of the item on the remote endpoint.
+    pub item_key: String,
+    /// The encoded version vector of the last synced snapshot.
+    pub version_vector: serde_bytes::ByteBuf,
+    /// The hash of the local content at the last sync, used to detect local changes since then.
+    /// `None` if the content was just pulled and has yet to be applied locally.
+    pub content_hash: Option<serde_bytes::ByteBuf>,
+    pub synced_at: NaiveDateTime,
+}
+
+fn map_from_row(row: &Row) -> Result<SyncState, SqError> {
+    Ok(SyncState {
+        id: super::Id(row.get(0)?, Default::default()),
+        item_key: row.get(1)?,
+        version_vector: serde_bytes::ByteBuf::from(row.get::<_, Vec<u8>>(2)?),
+        content_hash: row
+            .get::<_, Option<Vec<u8>>>(3)?
+            .map(serde_bytes::ByteBuf::from),
+        synced_at: row.get(4)?,
+    })
+}
+
+impl SyncState {
+    pub fn query_by_item_key(
+        item_key: &str,
+        conn: &super::Connection,
+    ) -> DataResult<Option<SyncState>> {
+        Ok(conn
+            .query_row_and_then(
+                r"SELECT `id`, `item_key`, `version_vector`, `content_hash`, `synced_at`
+                FROM `yt_sync_states` WHERE `item_key` = ?",
+                [item_key],
+                map_from_row,
+            )
+            .optional()?)
+    }
+    pub fn query_all(conn: &super::Connection) -> DataResult<Vec<SyncState>> {
+        let mut stmt = conn.prepare_cached("SELECT `id`, `item_key`, `version_vector`, `content_hash`, `synced_at` FROM `yt_sync_states` ORDER BY `id` ASC")?;
+        let ret = stmt
+            .query_and_then([], map_from_row)?
+            .filter_map(|r: Result<SyncState, SqError>| r.ok())
+            .collect();
+        Ok(ret)
+    }
+    /// Record a successful sync of the item, replacing the previous state.
+    pub fn upsert(
+        item_key: &str,
+        version_vector: &[u8],
+        content_hash: Option<&[u8]>,
+        conn: &super::Connection,
+    ) -> DataResult<()> {
+        conn.execute(
+            r"INSERT INTO `yt_sync_states` (`item_key`, `version_vector`, `content_hash`)
+            VALUES (?1, ?2, ?3)
+            ON CONFLICT (`item_key`) DO UPDATE SET
+                `version_vector` = ?2,
+                `content_hash` = ?3,
+                `synced_at` = strftime('%Y-%m-%d %H:%M:%f', 'now')",
+            params![item_key, version_vector, content_hash],
+        )?;
+        Ok(())
+    }
+    pub fn delete(id: u32, conn: &super::Connection) -> DataResult<()> {
+        conn.execute("DELETE FROM `yt_sync_states` WHERE `id` = ?", [id])?;
+        Ok(())
+    }
+}
@@ -0,0 +1,122 @@
+use chrono::NaiveDateTime;
+use rusqlite::{params, Error as SqError, Row};
+use serde::Serialize;
+
+use super::*;
+
+pub type SystemJournalEntryId = super::Id<SystemJournalEntry>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SystemMutationKind {
+    Route,
+    Dns,
+    SystemProxy,
+}
+
+impl SystemMutationKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SystemMutationKind::Route => "route",
+            SystemMutationKind::Dns => "dns",
+            SystemMutationKind::SystemProxy => "system_proxy",
+        }
+    }
+    pub fn parse(kind: &str) -> Option<Self> {
+        Some(match kind {
+            "route" => SystemMutationKind::Route,
+            "dns" => SystemMutationKind::Dns,
+            "system_proxy" => SystemMutationKind::SystemProxy,
+            _ => return None,
+        })
+    }
+}
+
+/// A system-level change made while a profile is running, such as a route
+/// added or the DNS servers of an interface replaced.
+///
+/// An entry is recorded before the change is applied, and deleted after the
+/// change has been reverted. A clean shutdown thus leaves the journal empty.
+/// Any entry found on startup was left by a run that crashed, and must be
+/// rolled back before connecting again.
+#[derive(Debug, Clone, Serialize)]
+pub struct SystemJournalEntry {
+    pub id: SystemJournalEntryId,
+    pub kind: SystemMutationKind,
+    /// What has been changed, e.g. the destination of a route or the name of
+    /// an interface.
+    pub target: String,
+    /// Whatever is needed to revert the change, e.g. the previous DNS
+    /// servers. Opaque to the core.
+    pub undo: serde_bytes::ByteBuf,
+    pub created_at: NaiveDateTime,
+}
+
+fn map_from_row(row: &Row) -> Result<SystemJournalEntry, SqError> {
+    let kind = row.get::<_, String>(1)?;
+    Ok(SystemJournalEntry {
+        id: super::Id(row.get(0)?, Default::default()),
+        kind: SystemMutationKind::parse(&kind).ok_or_else(|| {
+            SqError::InvalidColumnType(1, String::from("kind"), rusqlite::types::Type::Text)
+        })?,
+        target: row.get(2)?,
+        undo: serde_bytes::ByteBuf::from(row.get::<_, Vec<u8>>(3)?),
+        created_at: row.get(4)?,
+    })
+}
+
+impl SystemJournalEntry {
+    /// Entries in the order they were recorded. Changes should be rolled back
+    /// in reverse order.
+    pub fn query_all(conn: &super::Connection) -> DataResult<Vec<SystemJournalEntry>> {
+        let mut stmt = conn.prepare_cached(
+            "SELECT `id`, `kind`, `target`, `undo`, `created_at` FROM `yt_system_journal` ORDER BY `id` ASC",
+        )?;
+        let ret = stmt
+            .query_and_then([], map_from_row)?
+            .filter_map(|r: Result<SystemJournalEntry, SqError>| r.ok())
+            .collect();
+        Ok(ret)
+    }
+    pub fn record(
+        kind: SystemMutationKind,
+        target: String,
+        undo: &[u8],
+        conn: &super::Connection,
+    ) -> DataResult<u32> {
+        conn.execute(
+            "INSERT INTO `yt_system_journal` (`kind`, `target`, `undo`) VALUES (?, ?, ?)",
+            params![kind.as_str(), target, undo],
+        )?;
+        Ok(conn.last_insert_rowid() as u32)
+    }
+    pub fn delete(id: u32, conn: &super::Connection) -> DataResult<()> {
+        conn.execute("DELETE FROM `yt_system_journal` WHERE `id` = ?", [id])?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journal_record_and_delete() {
+        let conn = Database::connect_temp().unwrap();
+        let route =
+            SystemJournalEntry::record(SystemMutationKind::Route, "0.0.0.0/1".into(), b"", &conn)
+                .unwrap();
+        let dns =
+            SystemJournalEntry::record(SystemMutationKind::Dns, "eth0".into(), b"\x01", &conn)
+                .unwrap();
+        let entries = SystemJournalEntry::query_all(&conn).unwrap();
+        let ids: Vec<_> = entries.iter().map(|e| e.id.0).collect();
+        assert_eq!(ids, [route, dns]);
+        assert_eq!(entries[1].kind, SystemMutationKind::Dns);
+        assert_eq!(&entries[1].undo[..], b"\x01");
+
+        SystemJournalEntry::delete(route, &conn).unwrap();
+        SystemJournalEntry::delete(dns, &conn).unwrap();
+        assert!(SystemJournalEntry::query_all(&conn).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,256 @@
+use chrono::{Datelike, NaiveDate, NaiveDateTime, Utc};
+use rusqlite::{params, Error as SqError, OptionalExtension, Row};
+use serde::Serialize;
+
+use super::*;
+
+pub type TrafficQuotaId = super::Id<TrafficQuota>;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TrafficQuota {
+    pub id: TrafficQuotaId,
+    pub proxy_id: Option<ProxyId>,
+    pub proxy_group_id: Option<ProxyGroupId>,
+    pub bytes_limit: u64,
+    pub warn_percent: u8,
+    /// Day of month on which the usage is reset. Months without such a day
+    /// reset on their last day instead.
+    pub reset_day: u8,
+    pub upload_bytes_used: u64,
+    pub download_bytes_used: u64,
+    pub period_started_at: NaiveDateTime,
+}
+
+fn map_from_row(row: &Row) -> Result<TrafficQuota, SqError> {
+    Ok(TrafficQuota {
+        id: super::Id(row.get(0)?, Default::default()),
+        proxy_id: row.get::<_, Option<u32>>(1)?.map(Into::into),
+        proxy_group_id: row.get::<_, Option<u32>>(2)?.map(Into::into),
+        bytes_limit: row.get(3)?,
+        warn_percent: row.get(4)?,
+        reset_day: row.get(5)?,
+        upload_bytes_used: row.get(6)?,
+        download_bytes_used: row.get(7)?,
+        period_started_at: row.get(8)?,
+    })
+}
+
+fn reset_date_in_month(year: i32, month: u32, reset_day: u8) -> NaiveDate {
+    (1..=reset_day.clamp(1, 31) as u32)
+        .rev()
+        .find_map(|d| NaiveDate::from_ymd_opt(year, month, d))
+        .expect("the first day of a month must be valid")
+}
+
+fn next_month(year: i32, month: u32) -> (i32, u32) {
+    if month == 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    }
+}
+
+fn prev_month(year: i32, month: u32) -> (i32, u32) {
+    if month == 1 {
+        (year - 1, 12)
+    } else {
+        (year, month - 1)
+    }
+}
+
+/// Returns the start of the billing period that contains `now`.
+pub fn quota_period_start(reset_day: u8, now: NaiveDateTime) -> NaiveDateTime {
+    let today = now.date();
+    let this_month = reset_date_in_month(today.year(), today.month(), reset_day);
+    let start = if this_month <= today {
+        this_month
+    } else {
+        let (year, month) = prev_month(today.year(), today.month());
+        reset_date_in_month(year, month, reset_day)
+    };
+    start.and_hms_opt(0, 0, 0).unwrap()
+}
+
+const SELECT_COLUMNS: &str = r"SELECT `id`, `proxy_id`, `proxy_group_id`, `bytes_limit`, `warn_percent`, `reset_day`,
+    `upload_bytes_used`, `download_bytes_used`, `period_started_at` FROM `yt_traffic_quotas`";
+
+impl TrafficQuota {
+    pub fn bytes_used(&self) -> u64 {
+        self.upload_bytes_used
+            .saturating_add(self.download_bytes_used)
+    }
+    pub fn is_approaching_limit(&self) -> bool {
+        self.bytes_used() as u128 * 100 >= self.bytes_limit as u128 * self.warn_percent as u128
+    }
+    pub fn is_exceeded(&self) -> bool {
+        self.bytes_used() >= self.bytes_limit
+    }
+    pub fn next_reset_at(&self) -> NaiveDateTime {
+        let start = quota_period_start(self.reset_day, self.period_started_at).date();
+        let (year, month) = next_month(start.year(), start.month());
+        reset_date_in_month(year, month, self.reset_day)
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+    }
+
+    pub fn query_all(conn: &super::Connection) -> DataResult<Vec<TrafficQuota>> {
+        let mut stmt = conn.prepare_cached(&format!("{} ORDER BY `id` ASC", SELECT_COLUMNS))?;
+        let ret = stmt
+            .query_and_then([], map_from_row)?
+            .filter_map(|r| r.ok())
+            .collect();
+        Ok(ret)
+    }
+    pub fn query_by_proxy(
+        proxy_id: ProxyId,
+        conn: &super::Connection,
+    ) -> DataResult<Option<TrafficQuota>> {
+        Ok(conn
+            .query_row_and_then(
+                &format!("{} WHERE `proxy_id` = ?", SELECT_COLUMNS),
+                [&proxy_id.0],
+                map_from_row,
+            )
+            .optional()?)
+    }
+    pub fn query_by_proxy_group(
+        proxy_group_id: ProxyGroupId,
+        conn: &super::Connection,
+    ) -> DataResult<Option<TrafficQuota>> {
+        Ok(conn
+            .query_row_and_then(
+                &format!("{} WHERE `proxy_group_id` = ?", SELECT_COLUMNS),
+                [&proxy_group_id.0],
+                map_from_row,
+            )
+            .optional()?)
+    }
+    fn validate(warn_percent: u8, reset_day: u8) -> DataResult<()> {
+        if warn_percent > 100 {
+            return Err(DataError::InvalidData {
+                domain: "traffic_quota",
+                field: "warn_percent",
+            });
+        }
+        if !(1..=31).contains(&reset_day) {
+            return Err(DataError::InvalidData {
+                domain: "traffic_quota",
+                field: "reset_day",
+            });
+        }
+        Ok(())
+    }
+    pub fn create_for_proxy(
+        proxy_id: ProxyId,
+        bytes_limit: u64,
+        warn_percent: u8,
+        reset_day: u8,
+        conn: &super::Connection,
+    ) -> DataResult<u32> {
+        Self::validate(warn_percent, reset_day)?;
+        conn.execute(
+            "INSERT INTO `yt_traffic_quotas` (`proxy_id`, `bytes_limit`, `warn_percent`, `reset_day`, `period_started_at`) VALUES (?, ?, ?, ?, ?)",
+            params![
+                &proxy_id.0,
+                bytes_limit,
+                warn_percent,
+                reset_day,
+                quota_period_start(reset_day, Utc::now().naive_utc())
+            ],
+        )?;
+        Ok(conn.last_insert_rowid() as u32)
+    }
+    pub fn create_for_proxy_group(
+        proxy_group_id: ProxyGroupId,
+        bytes_limit: u64,
+        warn_percent: u8,
+        reset_day: u8,
+        conn: &super::Connection,
+    ) -> DataResult<u32> {
+        Self::validate(warn_percent, reset_day)?;
+        conn.execute(
+            "INSERT INTO `yt_traffic_quotas` (`proxy_group_id`, `bytes_limit`, `warn_percent`, `reset_day`, `period_started_at`) VALUES (?, ?, ?, ?, ?)",
+            params![
+                &proxy_group_id.0,
+                bytes_limit,
+                warn_percent,
+                reset_day,
+                quota_period_start(reset_day, Utc::now().naive_utc())
+            ],
+        )?;
+        Ok(conn.last_insert_rowid() as u32)
+    }
+    pub fn update(
+        id: u32,
+        bytes_limit: u64,
+        warn_percent: u8,
+        reset_day: u8,
+        conn: &super::Connection,
+    ) -> DataResult<()> {
+        Self::validate(warn_percent, reset_day)?;
+        conn.execute(
+            "UPDATE `yt_traffic_quotas` SET `bytes_limit` = ?, `warn_percent` = ?, `reset_day` = ? WHERE `id` = ?",
+            params![bytes_limit, warn_percent, reset_day, id],
+        )?;
+        Ok(())
+    }
+    pub fn reset_usage(id: u32, conn: &super::Connection) -> DataResult<()> {
+        conn.execute(
+            r"UPDATE `yt_traffic_quotas` SET
+            `upload_bytes_used` = 0,
+            `download_bytes_used` = 0,
+            `period_started_at` = (strftime('%Y-%m-%d %H:%M:%f', 'now'))
+            WHERE `id` = ?",
+            [id],
+        )?;
+        Ok(())
+    }
+    pub fn delete(id: u32, conn: &super::Connection) -> DataResult<()> {
+        conn.execute("DELETE FROM `yt_traffic_quotas` WHERE `id` = ?", [id])?;
+        Ok(())
+    }
+
+    /// Add traffic to the quotas of a proxy and the group it belongs to,
+    /// starting a new period for quotas whose reset day has passed.
+    /// Returns the updated quotas.
+    pub fn add_usage(
+        proxy_id: ProxyId,
+        proxy_group_id: ProxyGroupId,
+        upload_bytes: u64,
+        download_bytes: u64,
+        conn: &mut super::Connection,
+    ) -> DataResult<Vec<TrafficQuota>> {
+        let now = Utc::now().naive_utc();
+        let tx = conn.transaction()?;
+        let quotas = tx
+            .prepare_cached(&format!(
+                "{} WHERE `proxy_id` = ? OR `proxy_group_id` = ?",
+                SELECT_COLUMNS
+            ))?
+            .query_and_then([&proxy_id.0, &proxy_group_id.0], map_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut ret = Vec::with_capacity(quotas.len());
+        for mut quota in quotas {
+            let period_start = quota_period_start(quota.reset_day, now);
+            if quota.period_started_at < period_start {
+                quota.upload_bytes_used = 0;
+                quota.download_bytes_used = 0;
+                quota.period_started_at = period_start;
+            }
+            quota.upload_bytes_used = quota.upload_bytes_used.saturating_add(upload_bytes);
+            quota.download_bytes_used = quota.download_bytes_used.saturating_add(download_bytes);
+            tx.execute(
+                "UPDATE `yt_traffic_quotas` SET `upload_bytes_used` = ?, `download_bytes_used` = ?, `period_started_at` = ? WHERE `id` = ?",
+                params![
+                    quota.upload_bytes_used,
+                    quota.download_bytes_used,
+                    quota.period_started_at,
+                    &quota.id.0
+                ],
+            )?;
+            ret.push(quota);
+        }
+        tx.commit()?;
+        Ok(ret)
+    }
+}
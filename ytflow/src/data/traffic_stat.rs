@@ -0,0 +1,114 @@
+use chrono::NaiveDate;
+use rusqlite::{params, Error as SqError, OptionalExtension, Row};
+use serde::Serialize;
+
+use super::*;
+
+/// Traffic aggregated by day. Depending on the query, `profile_id` and
+/// `proxy_id` are `None` when the traffic is summed up across them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TrafficStat {
+    pub date: NaiveDate,
+    pub profile_id: Option<ProfileId>,
+    pub proxy_id: Option<ProxyId>,
+    pub upload_bytes: u64,
+    pub download_bytes: u64,
+}
+
+fn map_from_row(row: &Row) -> Result<TrafficStat, SqError> {
+    Ok(TrafficStat {
+        date: row.get(0)?,
+        profile_id: row.get::<_, Option<u32>>(1)?.map(Into::into),
+        proxy_id: row.get::<_, Option<u32>>(2)?.map(Into::into),
+        upload_bytes: row.get(3)?,
+        download_bytes: row.get(4)?,
+    })
+}
+
+impl TrafficStat {
+    pub fn add(
+        date: NaiveDate,
+        profile_id: Option<ProfileId>,
+        proxy_id: Option<ProxyId>,
+        upload_bytes: u64,
+        download_bytes: u64,
+        conn: &mut super::Connection,
+    ) -> DataResult<()> {
+        let profile_id = profile_id.map(|p| p.0);
+        let proxy_id = proxy_id.map(|p| p.0);
+        let tx = conn.transaction()?;
+        let id: Option<u32> = tx
+            .query_row(
+                "SELECT `id` FROM `yt_traffic_stats` WHERE `date` = ? AND `profile_id` IS ? AND `proxy_id` IS ?",
+                params![date, profile_id, proxy_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        match id {
+            Some(id) => tx.execute(
+                "UPDATE `yt_traffic_stats` SET `upload_bytes` = `upload_bytes` + ?, `download_bytes` = `download_bytes` + ? WHERE `id` = ?",
+                params![upload_bytes, download_bytes, id],
+            )?,
+            None => tx.execute(
+                "INSERT INTO `yt_traffic_stats` (`date`, `profile_id`, `proxy_id`, `upload_bytes`, `download_bytes`) VALUES (?, ?, ?, ?, ?)",
+                params![date, profile_id, proxy_id, upload_bytes, download_bytes],
+            )?,
+        };
+        tx.commit()?;
+        Ok(())
+    }
+    /// Total traffic of each day in `[from, to]`.
+    pub fn query_daily(
+        from: NaiveDate,
+        to: NaiveDate,
+        conn: &super::Connection,
+    ) -> DataResult<Vec<TrafficStat>> {
+        let mut stmt = conn.prepare_cached(
+            r"SELECT `date`, NULL, NULL, SUM(`upload_bytes`), SUM(`download_bytes`)
+            FROM `yt_traffic_stats` WHERE `date` BETWEEN ? AND ?
+            GROUP BY `date` ORDER BY `date` ASC",
+        )?;
+        let ret = stmt
+            .query_and_then(params![from, to], map_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ret)
+    }
+    /// Traffic of a profile in `[from, to]`, by day and proxy.
+    pub fn query_by_profile(
+        profile_id: ProfileId,
+        from: NaiveDate,
+        to: NaiveDate,
+        conn: &super::Connection,
+    ) -> DataResult<Vec<TrafficStat>> {
+        let mut stmt = conn.prepare_cached(
+            r"SELECT `date`, `profile_id`, `proxy_id`, SUM(`upload_bytes`), SUM(`download_bytes`)
+            FROM `yt_traffic_stats` WHERE `profile_id` = ? AND `date` BETWEEN ? AND ?
+            GROUP BY `date`, `proxy_id` ORDER BY `date` ASC, `proxy_id` ASC",
+        )?;
+        let ret = stmt
+            .query_and_then(params![profile_id.0, from, to], map_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ret)
+    }
+    /// Traffic through a proxy in `[from, to]`, by day and profile.
+    pub fn query_by_proxy(
+        proxy_id: ProxyId,
+        from: NaiveDate,
+        to: NaiveDate,
+        conn: &super::Connection,
+    ) -> DataResult<Vec<TrafficStat>> {
+        let mut stmt = conn.prepare_cached(
+            r"SELECT `date`, `profile_id`, `proxy_id`, SUM(`upload_bytes`), SUM(`download_bytes`)
+            FROM `yt_traffic_stats` WHERE `proxy_id` = ? AND `date` BETWEEN ? AND ?
+            GROUP BY `date`, `profile_id` ORDER BY `date` ASC, `profile_id` ASC",
+        )?;
+        let ret = stmt
+            .query_and_then(params![proxy_id.0, from, to], map_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ret)
+    }
+    /// Delete statistics recorded before `date`. Returns the number of rows deleted.
+    pub fn delete_before(date: NaiveDate, conn: &super::Connection) -> DataResult<usize> {
+        Ok(conn.execute("DELETE FROM `yt_traffic_stats` WHERE `date` < ?", [date])?)
+    }
+}
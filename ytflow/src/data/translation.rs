@@ -0,0 +1,369 @@
+use rusqlite::{params, Error as SqError, Row};
+use serde::Serialize;
+
+use super::*;
+
+/// Name and description of a profile in a locale other than the one the
+/// profile is written in.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ProfileTranslation {
+    pub profile_id: ProfileId,
+    pub locale: String,
+    pub name: String,
+    pub desc: String,
+}
+
+/// Description of a plugin in a locale other than the one its profile is
+/// written in.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PluginTranslation {
+    pub plugin_id: PluginId,
+    pub locale: String,
+    pub desc: String,
+}
+
+/// A profile with its name and description in the locale that best matches
+/// the one requested.
+#[derive(Debug, Clone, Serialize)]
+pub struct LocalizedProfile {
+    #[serde(flatten)]
+    pub profile: Profile,
+    /// Empty if the profile is shown in its own locale.
+    pub desc: String,
+    /// The locale the name and description are in.
+    pub matched_locale: String,
+}
+
+fn map_profile_translation_from_row(row: &Row) -> Result<ProfileTranslation, SqError> {
+    Ok(ProfileTranslation {
+        profile_id: super::Id(row.get(0)?, Default::default()),
+        locale: row.get(1)?,
+        name: row.get(2)?,
+        desc: row.get(3)?,
+    })
+}
+
+fn map_plugin_translation_from_row(row: &Row) -> Result<PluginTranslation, SqError> {
+    Ok(PluginTranslation {
+        plugin_id: super::Id(row.get(0)?, Default::default()),
+        locale: row.get(1)?,
+        desc: row.get(2)?,
+    })
+}
+
+fn validate_locale(locale: &str, domain: &'static str) -> DataResult<()> {
+    if locale.is_empty()
+        || !locale
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-')
+    {
+        return Err(DataError::InvalidData {
+            domain,
+            field: "locale",
+        });
+    }
+    Ok(())
+}
+
+impl ProfileTranslation {
+    pub fn query_all_by_profile(
+        profile_id: ProfileId,
+        conn: &super::Connection,
+    ) -> DataResult<Vec<ProfileTranslation>> {
+        let mut stmt = conn.prepare_cached(
+            r"SELECT `profile_id`, `locale`, `name`, `desc` FROM `yt_profile_translations`
+            WHERE `profile_id` = ? ORDER BY `locale` ASC",
+        )?;
+        let ret = stmt
+            .query_and_then([&profile_id.0], map_profile_translation_from_row)?
+            .filter_map(|r| r.ok())
+            .collect();
+        Ok(ret)
+    }
+    /// Show `profile` in the locale that best matches `requested`. The
+    /// profile's own locale wins if it matches equally well.
+    pub fn localize(
+        profile: Profile,
+        requested: &str,
+        conn: &super::Connection,
+    ) -> DataResult<LocalizedProfile> {
+        let mut translations = Self::query_all_by_profile(profile.id, conn)?;
+        let locales: Vec<_> = std::iter::once(profile.locale.as_str())
+            .chain(translations.iter().map(|t| t.locale.as_str()))
+            .collect();
+        let idx = best_locale_match(requested, &locales).unwrap_or(0);
+        Ok(match idx.checked_sub(1) {
+            Some(idx) => {
+                let t = translations.swap_remove(idx);
+                LocalizedProfile {
+                    profile: Profile {
+                        name: t.name,
+                        ..profile
+                    },
+                    desc: t.desc,
+                    matched_locale: t.locale,
+                }
+            }
+            None => LocalizedProfile {
+                matched_locale: profile.locale.clone(),
+                profile,
+                desc: String::new(),
+            },
+        })
+    }
+    /// Insert a translation, or replace the existing one in the same locale.
+    pub fn upsert(
+        profile_id: ProfileId,
+        locale: String,
+        name: String,
+        desc: String,
+        conn: &super::Connection,
+    ) -> DataResult<()> {
+        validate_locale(&locale, "profile_translation")?;
+        super::profile::ensure_unlocked(profile_id.0, conn)?;
+        conn.execute(
+            r"INSERT INTO `yt_profile_translations` (`profile_id`, `locale`, `name`, `desc`)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT (`profile_id`, `locale`) DO UPDATE SET `name` = excluded.`name`, `desc` = excluded.`desc`",
+            params![profile_id.0, locale, name, desc],
+        )?;
+        Ok(())
+    }
+    pub fn delete(profile_id: ProfileId, locale: &str, conn: &super::Connection) -> DataResult<()> {
+        super::profile::ensure_unlocked(profile_id.0, conn)?;
+        conn.execute(
+            "DELETE FROM `yt_profile_translations` WHERE `profile_id` = ? AND `locale` = ?",
+            params![profile_id.0, locale],
+        )?;
+        Ok(())
+    }
+}
+
+impl PluginTranslation {
+    pub fn query_all_by_plugin(
+        plugin_id: PluginId,
+        conn: &super::Connection,
+    ) -> DataResult<Vec<PluginTranslation>> {
+        let mut stmt = conn.prepare_cached(
+            r"SELECT `plugin_id`, `locale`, `desc` FROM `yt_plugin_translations`
+            WHERE `plugin_id` = ? ORDER BY `locale` ASC",
+        )?;
+        let ret = stmt
+            .query_and_then([&plugin_id.0], map_plugin_translation_from_row)?
+            .filter_map(|r| r.ok())
+            .collect();
+        Ok(ret)
+    }
+    /// All plugin translations in a profile, ordered by plugin.
+    pub fn query_all_by_profile(
+        profile_id: ProfileId,
+        conn: &super::Connection,
+    ) -> DataResult<Vec<PluginTranslation>> {
+        let mut stmt = conn.prepare_cached(
+            r"SELECT t.`plugin_id`, t.`locale`, t.`desc` FROM `yt_plugin_translations` t
+            JOIN `yt_plugins` p ON p.`id` = t.`plugin_id`
+            WHERE p.`profile_id` = ? ORDER BY t.`plugin_id` ASC, t.`locale` ASC",
+        )?;
+        let ret = stmt
+            .query_and_then([&profile_id.0], map_plugin_translation_from_row)?
+            .filter_map(|r| r.ok())
+            .collect();
+        Ok(ret)
+    }
+    /// Replace descriptions of `plugins` in a profile written in
+    /// `profile_locale` with the translations best matching `requested`.
+    pub fn localize(
+        plugins: &mut [Plugin],
+        profile_id: ProfileId,
+        profile_locale: &str,
+        requested: &str,
+        conn: &super::Connection,
+    ) -> DataResult<()> {
+        let translations = Self::query_all_by_profile(profile_id, conn)?;
+        if translations.is_empty() {
+            return Ok(());
+        }
+        for plugin in plugins {
+            let mut candidates: Vec<_> = translations
+                .iter()
+                .filter(|t| t.plugin_id == plugin.id)
+                .map(|t| (t.locale.as_str(), Some(t)))
+                .collect();
+            if candidates.is_empty() {
+                continue;
+            }
+            candidates.insert(0, (profile_locale, None));
+            let locales: Vec<_> = candidates.iter().map(|(l, _)| *l).collect();
+            let Some(idx) = best_locale_match(requested, &locales) else {
+                continue;
+            };
+            if let Some(t) = candidates[idx].1 {
+                plugin.desc = t.desc.clone();
+            }
+        }
+        Ok(())
+    }
+    /// Insert a translation, or replace the existing one in the same locale.
+    pub fn upsert(
+        plugin_id: PluginId,
+        locale: String,
+        desc: String,
+        conn: &super::Connection,
+    ) -> DataResult<()> {
+        validate_locale(&locale, "plugin_translation")?;
+        super::profile::ensure_plugin_unlocked(plugin_id.0, conn)?;
+        conn.execute(
+            r"INSERT INTO `yt_plugin_translations` (`plugin_id`, `locale`, `desc`) VALUES (?, ?, ?)
+            ON CONFLICT (`plugin_id`, `locale`) DO UPDATE SET `desc` = excluded.`desc`",
+            params![plugin_id.0, locale, desc],
+        )?;
+        Ok(())
+    }
+    pub fn delete(plugin_id: PluginId, locale: &str, conn: &super::Connection) -> DataResult<()> {
+        super::profile::ensure_plugin_unlocked(plugin_id.0, conn)?;
+        conn.execute(
+            "DELETE FROM `yt_plugin_translations` WHERE `plugin_id` = ? AND `locale` = ?",
+            params![plugin_id.0, locale],
+        )?;
+        Ok(())
+    }
+}
+
+/// Find the index of the locale in `available` that best serves `requested`, following the
+/// lookup scheme of RFC 4647: subtags are removed from the end of `requested`
+/// until a locale matches. Failing that, any locale of the same language is
+/// taken, so that `de-AT` still gets `de-DE` rather than nothing. Comparisons are
+/// case-insensitive and `_` is treated as `-`.
+pub fn best_locale_match<S: AsRef<str>>(requested: &str, available: &[S]) -> Option<usize> {
+    let normalize = |s: &str| s.replace('_', "-").to_ascii_lowercase();
+    let available_normalized: Vec<_> = available.iter().map(|s| normalize(s.as_ref())).collect();
+    let mut range = normalize(requested);
+    while !range.is_empty() {
+        if let Some(idx) = available_normalized.iter().position(|a| *a == range) {
+            return Some(idx);
+        }
+        match range.rfind('-') {
+            Some(pos) => {
+                range.truncate(pos);
+                // Single-letter subtags introduce extensions, which must not
+                // be left dangling.
+                if range.len() >= 2 && range.as_bytes()[range.len() - 2] == b'-' {
+                    range.truncate(range.len() - 2);
+                }
+            }
+            None => break,
+        }
+    }
+    if range.is_empty() {
+        return None;
+    }
+    available_normalized
+        .iter()
+        .position(|a| a.split('-').next() == Some(range.as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{Plugin, Profile};
+
+    #[test]
+    fn test_best_locale_match() {
+        let available = ["en-US", "zh-CN", "zh-TW", "ja"];
+        assert_eq!(best_locale_match("zh-CN", &available), Some(1));
+        assert_eq!(best_locale_match("zh_tw", &available), Some(2));
+        assert_eq!(best_locale_match("ja-JP", &available), Some(3));
+        assert_eq!(best_locale_match("en-GB", &available), Some(0));
+        assert_eq!(best_locale_match("zh-Hans-CN", &available), Some(1));
+        assert_eq!(best_locale_match("de-x-foo", &available), None);
+        assert_eq!(best_locale_match("", &available), None);
+    }
+
+    #[test]
+    fn test_translations_upsert() {
+        let conn = Database::connect_temp().unwrap();
+        let profile_id: ProfileId = Profile::create("test".into(), "en-US".into(), &conn)
+            .unwrap()
+            .into();
+        let plugin_id: PluginId = Plugin::create(
+            profile_id,
+            "test".into(),
+            "".into(),
+            "null".into(),
+            0,
+            vec![],
+            &conn,
+        )
+        .unwrap()
+        .into();
+
+        ProfileTranslation::upsert(profile_id, "zh-CN".into(), "a".into(), "".into(), &conn)
+            .unwrap();
+        ProfileTranslation::upsert(profile_id, "zh-CN".into(), "b".into(), "c".into(), &conn)
+            .unwrap();
+        assert!(ProfileTranslation::upsert(
+            profile_id,
+            "zh CN".into(),
+            "".into(),
+            "".into(),
+            &conn
+        )
+        .is_err());
+        let translations = ProfileTranslation::query_all_by_profile(profile_id, &conn).unwrap();
+        assert_eq!(translations.len(), 1);
+        assert_eq!((&*translations[0].name, &*translations[0].desc), ("b", "c"));
+
+        PluginTranslation::upsert(plugin_id, "ja".into(), "d".into(), &conn).unwrap();
+        assert_eq!(
+            PluginTranslation::query_all_by_profile(profile_id, &conn)
+                .unwrap()
+                .len(),
+            1
+        );
+        Profile::set_locked(profile_id.0, true, &conn).unwrap();
+        assert!(PluginTranslation::delete(plugin_id, "ja", &conn).is_err());
+        Profile::set_locked(profile_id.0, false, &conn).unwrap();
+        PluginTranslation::delete(plugin_id, "ja", &conn).unwrap();
+        assert!(PluginTranslation::query_all_by_plugin(plugin_id, &conn)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_localize() {
+        let conn = Database::connect_temp().unwrap();
+        let profile_id: ProfileId = Profile::create("Default".into(), "en-US".into(), &conn)
+            .unwrap()
+            .into();
+        let plugin_id: PluginId = Plugin::create(
+            profile_id,
+            "test".into(),
+            "Direct".into(),
+            "null".into(),
+            0,
+            vec![],
+            &conn,
+        )
+        .unwrap()
+        .into();
+        ProfileTranslation::upsert(profile_id, "zh-CN".into(), "默认".into(), "".into(), &conn)
+            .unwrap();
+        PluginTranslation::upsert(plugin_id, "zh-CN".into(), "直连".into(), &conn).unwrap();
+        let profile = Profile::query_by_id(profile_id.0 as _, &conn)
+            .unwrap()
+            .unwrap();
+
+        let localized = ProfileTranslation::localize(profile.clone(), "zh-Hans-CN", &conn).unwrap();
+        assert_eq!(localized.profile.name, "默认");
+        assert_eq!(localized.matched_locale, "zh-CN");
+        let localized = ProfileTranslation::localize(profile.clone(), "fr", &conn).unwrap();
+        assert_eq!(localized.profile.name, "Default");
+        assert_eq!(localized.matched_locale, "en-US");
+
+        let mut plugins = Plugin::query_all_by_profile(profile_id, &conn).unwrap();
+        PluginTranslation::localize(&mut plugins, profile_id, "en-US", "en-GB", &conn).unwrap();
+        assert_eq!(plugins[0].desc, "Direct");
+        PluginTranslation::localize(&mut plugins, profile_id, "en-US", "zh-CN", &conn).unwrap();
+        assert_eq!(plugins[0].desc, "直连");
+    }
+}
@@ -1,21 +1,35 @@
+mod abort;
 mod compat;
 mod context;
 mod datagram;
+mod datagram_transform;
+mod deadline;
 mod error;
+mod inbound_watch;
 mod manager;
 mod multiplexed_datagram;
 mod reader;
 mod resolver;
 mod stream;
+#[cfg(test)]
+pub mod testing;
+mod tracker;
+mod traffic;
 mod tun;
 
+pub use abort::*;
 pub use compat::*;
 pub use context::*;
 pub use datagram::*;
+pub use datagram_transform::*;
+pub use deadline::*;
 pub use error::*;
+pub use inbound_watch::*;
 pub use manager::*;
 pub use multiplexed_datagram::*;
 pub use reader::StreamReader;
 pub use resolver::*;
 pub use stream::*;
+pub use tracker::*;
+pub use traffic::*;
 pub use tun::*;
@@ -0,0 +1,161 @@
+use std::future::Future;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use tokio::sync::Notify;
+
+use super::FlowResult;
+
+#[derive(Debug)]
+struct AbortState {
+    aborted: AtomicBool,
+    notify: Notify,
+    /// Number of live handles sharing this state.
+    handles: AtomicUsize,
+}
+
+/// Owned by a [`FlowContext`](super::FlowContext). Dropping the handle along with all
+/// handles [shared](AbortHandle::share) from it, or calling [`AbortHandle::abort`] on any
+/// of them, wakes every [`AbortSignal`] derived from it.
+#[derive(Debug)]
+pub struct AbortHandle {
+    state: Arc<AbortState>,
+}
+
+/// A cheap observer of an [`AbortHandle`] that can be moved into pending work.
+#[derive(Debug, Clone)]
+pub struct AbortSignal {
+    state: Arc<AbortState>,
+}
+
+impl AbortHandle {
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(AbortState {
+                aborted: AtomicBool::new(false),
+                notify: Notify::new(),
+                handles: AtomicUsize::new(1),
+            }),
+        }
+    }
+    /// Another handle to the same state, for a copy of the context of the same flow.
+    pub fn share(&self) -> Self {
+        self.state.handles.fetch_add(1, Ordering::Relaxed);
+        Self {
+            state: self.state.clone(),
+        }
+    }
+    pub fn signal(&self) -> AbortSignal {
+        AbortSignal {
+            state: self.state.clone(),
+        }
+    }
+    pub fn abort(&self) {
+        if !self.state.aborted.swap(true, Ordering::AcqRel) {
+            self.state.notify.notify_waiters();
+        }
+    }
+    pub fn is_aborted(&self) -> bool {
+        self.state.aborted.load(Ordering::Acquire)
+    }
+}
+
+impl Default for AbortHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for AbortHandle {
+    fn drop(&mut self) {
+        if self.state.handles.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.abort();
+        }
+    }
+}
+
+impl AbortSignal {
+    pub fn is_aborted(&self) -> bool {
+        self.state.aborted.load(Ordering::Acquire)
+    }
+    /// Resolves once the owning handle is aborted or dropped.
+    pub async fn aborted(&self) {
+        // `notify_waiters` only wakes futures created before it is called, so register
+        // interest first and check the flag afterwards.
+        let notified = self.state.notify.notified();
+        if self.is_aborted() {
+            return;
+        }
+        notified.await
+    }
+    /// Run `fut` to completion, unless the owning handle is aborted or dropped
+    /// first, in which case fail with [`io::ErrorKind::Interrupted`].
+    pub async fn guard<T>(&self, fut: impl Future<Output = FlowResult<T>>) -> FlowResult<T> {
+        if self.is_aborted() {
+            return Err(io::Error::from(io::ErrorKind::Interrupted).into());
+        }
+        tokio::select! {
+            r = fut => r,
+            _ = self.aborted() => Err(io::Error::from(io::ErrorKind::Interrupted).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_signal_fires_on_drop() {
+        let handle = AbortHandle::new();
+        let signal = handle.signal();
+        assert!(!signal.is_aborted());
+        let waiter = tokio::spawn({
+            let signal = signal.clone();
+            async move { signal.aborted().await }
+        });
+        tokio::task::yield_now().await;
+        drop(handle);
+        waiter.await.unwrap();
+        assert!(signal.is_aborted());
+        // Already aborted: returns immediately.
+        signal.aborted().await;
+    }
+
+    #[test]
+    fn test_shared_handles() {
+        let handle = AbortHandle::new();
+        let shared = handle.share();
+        let signal = shared.signal();
+        drop(handle);
+        assert!(!signal.is_aborted());
+        drop(shared);
+        assert!(signal.is_aborted());
+
+        let handle = AbortHandle::new();
+        let shared = handle.share();
+        shared.abort();
+        assert!(handle.is_aborted());
+    }
+
+    #[tokio::test]
+    async fn test_guard_interrupts_pending_work() {
+        let handle = AbortHandle::new();
+        let signal = handle.signal();
+        let work = tokio::spawn(async move {
+            signal
+                .guard(futures::future::pending::<FlowResult<()>>())
+                .await
+        });
+        tokio::task::yield_now().await;
+        handle.abort();
+        let err = work.await.unwrap().unwrap_err();
+        assert!(
+            matches!(err, super::super::FlowError::Io(e) if e.kind() == io::ErrorKind::Interrupted)
+        );
+        // Completed work is returned as is.
+        let handle = AbortHandle::new();
+        assert!(handle.signal().guard(async { Ok(()) }).await.is_ok());
+    }
+}
@@ -1,10 +1,13 @@
 use std::borrow::Cow;
 use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
 
 use serde::{de, Deserialize, Deserializer, Serialize};
 use smallvec::SmallVec;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+use super::{AbortHandle, ResolveOptions};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum HostName {
     DomainName(String),
     Ip(IpAddr),
@@ -19,7 +22,7 @@ impl ToString for HostName {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct DestinationAddr {
     pub host: HostName,
     pub port: u16,
@@ -64,6 +67,13 @@ pub struct FlowContext {
     pub remote_peer: DestinationAddr,
     pub af_sensitive: bool,
     pub application_layer_protocol: SmallVec<[&'static str; 2]>,
+    /// The traffic class octet (DSCP and ECN bits) of the packets that started this flow, if
+    /// the inbound knows it.
+    pub traffic_class: Option<u8>,
+    /// Aborted when the context and all its [forks](FlowContext::fork) are dropped, so work
+    /// started on behalf of this flow (such as resolving the destination) can stop once the
+    /// flow is torn down.
+    pub abort: AbortHandle,
 }
 
 impl FlowContext {
@@ -73,6 +83,8 @@ impl FlowContext {
             remote_peer,
             af_sensitive: false,
             application_layer_protocol: Default::default(),
+            traffic_class: None,
+            abort: AbortHandle::new(),
         }
     }
     pub fn new_af_sensitive(local_peer: SocketAddr, remote_peer: DestinationAddr) -> Self {
@@ -81,6 +93,27 @@ impl FlowContext {
             remote_peer,
             af_sensitive: true,
             application_layer_protocol: Default::default(),
+            traffic_class: None,
+            abort: AbortHandle::new(),
+        }
+    }
+    /// A copy of this context for trying the same flow through another handler or outbound.
+    /// The copy shares the abort state, so the flow is only torn down with the last copy.
+    pub fn fork(&self) -> Self {
+        Self {
+            local_peer: self.local_peer,
+            remote_peer: self.remote_peer.clone(),
+            af_sensitive: self.af_sensitive,
+            application_layer_protocol: self.application_layer_protocol.clone(),
+            traffic_class: self.traffic_class,
+            abort: self.abort.share(),
+        }
+    }
+    /// Options for resolving on behalf of this flow, cancelled when the flow is torn down.
+    pub fn resolve_options(&self, timeout: Duration) -> ResolveOptions {
+        ResolveOptions {
+            timeout: Some(timeout),
+            abort: Some(self.abort.signal()),
         }
     }
 }
@@ -0,0 +1,62 @@
+use std::sync::{Arc, Weak};
+use std::task::{ready, Context, Poll};
+
+use async_trait::async_trait;
+
+use super::*;
+
+/// A transformation applied to every datagram payload of a session, such as
+/// scrambling or obfuscation. Implementations must not depend on the order of
+/// datagrams, as they may be lost or reordered.
+pub trait DatagramTransform: Send + Sync {
+    /// Transform an outgoing payload in place.
+    fn encode(&self, buf: &mut Buffer);
+    /// Reverse [`DatagramTransform::encode`] on an incoming payload in place.
+    /// Returns `false` if the datagram is malformed and should be dropped.
+    fn decode(&self, buf: &mut Buffer) -> bool;
+}
+
+pub struct TransformDatagramSession {
+    pub lower: Box<dyn DatagramSession>,
+    pub transform: Arc<dyn DatagramTransform>,
+}
+
+impl DatagramSession for TransformDatagramSession {
+    fn poll_recv_from(&mut self, cx: &mut Context) -> Poll<Option<(DestinationAddr, Buffer)>> {
+        loop {
+            let Some((dest, mut buf)) = ready!(self.lower.poll_recv_from(cx)) else {
+                return Poll::Ready(None);
+            };
+            if self.transform.decode(&mut buf) {
+                return Poll::Ready(Some((dest, buf)));
+            }
+        }
+    }
+    fn poll_send_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        self.lower.poll_send_ready(cx)
+    }
+    fn send_to(&mut self, remote_peer: DestinationAddr, mut buf: Buffer) {
+        self.transform.encode(&mut buf);
+        self.lower.send_to(remote_peer, buf)
+    }
+    fn poll_shutdown(&mut self, cx: &mut Context<'_>) -> Poll<FlowResult<()>> {
+        self.lower.poll_shutdown(cx)
+    }
+}
+
+/// Binds sessions from `next` with all payloads going through `transform`.
+pub struct TransformDatagramFactory {
+    pub transform: Arc<dyn DatagramTransform>,
+    pub next: Weak<dyn DatagramSessionFactory>,
+}
+
+#[async_trait]
+impl DatagramSessionFactory for TransformDatagramFactory {
+    async fn bind(&self, context: Box<FlowContext>) -> FlowResult<Box<dyn DatagramSession>> {
+        let next = self.next.upgrade().ok_or(FlowError::NoOutbound)?;
+        Ok(Box::new(TransformDatagramSession {
+            lower: next.bind(context).await?,
+            transform: self.transform.clone(),
+        }))
+    }
+}
@@ -0,0 +1,33 @@
+use std::future::Future;
+use std::io;
+use std::time::Duration;
+
+use super::{AbortSignal, FlowResult};
+
+/// Applied when dialing a socket unless the plugin overrides it.
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Applied to protocol handshakes (TLS, WebSocket upgrade, proxy authentication) unless the
+/// plugin overrides it.
+pub const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Applied when a plugin resolves the destination of a flow it is handling.
+pub const DEFAULT_RESOLVE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Fail with [`io::ErrorKind::TimedOut`] if `fut` does not complete within `timeout`.
+pub async fn with_deadline<T>(
+    timeout: Duration,
+    fut: impl Future<Output = FlowResult<T>>,
+) -> FlowResult<T> {
+    tokio::time::timeout(timeout, fut)
+        .await
+        .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))?
+}
+
+/// Like [`with_deadline`], but also give up with [`io::ErrorKind::Interrupted`]
+/// once the flow is torn down, e.g. when the client has disconnected.
+pub async fn with_flow_deadline<T>(
+    timeout: Duration,
+    abort: &AbortSignal,
+    fut: impl Future<Output = FlowResult<T>>,
+) -> FlowResult<T> {
+    abort.guard(with_deadline(timeout, fut)).await
+}
@@ -0,0 +1,267 @@
+use std::num::NonZeroUsize;
+use std::task::{Context, Poll};
+
+use futures::future::{poll_fn, BoxFuture};
+
+use super::*;
+
+/// Progress of reading from the client while the flow waits on other work.
+pub enum EarlyRead {
+    Idle,
+    /// An rx buffer has been committed to the inbound but not yet filled.
+    Committed(SizeHint),
+    /// Data received, and whether the client has closed its side afterwards.
+    Received(Buffer, bool),
+}
+
+/// Resolves once the client closes `lower` before sending anything. Data that
+/// arrives in the meantime is kept in `early` to be handed over later, and the
+/// future stays pending. Dropping the future halfway is fine: polling a new one
+/// with the same `early` picks up the read already in progress.
+pub async fn inbound_closed(lower: &mut dyn Stream, early: &mut EarlyRead) -> FlowError {
+    if let EarlyRead::Idle = early {
+        // Streams like IpStackStream report a size hint right away, so only an
+        // actual read tells whether the client is still there.
+        let size = match poll_fn(|cx| lower.poll_request_size(cx)).await {
+            Ok(size) => size,
+            Err(e) => return e,
+        };
+        if let Err((_, e)) = lower.commit_rx_buffer(Vec::with_capacity(size.with_min_content(1500)))
+        {
+            return e;
+        }
+        *early = EarlyRead::Committed(size);
+    }
+    if let EarlyRead::Committed(_) = early {
+        *early = match poll_fn(|cx| lower.poll_rx_buffer(cx)).await {
+            Ok(buf) => EarlyRead::Received(buf, false),
+            Err((buf, FlowError::Eof)) if !buf.is_empty() => EarlyRead::Received(buf, true),
+            Err((_, e)) => return e,
+        };
+    }
+    futures::future::pending().await
+}
+
+/// An inbound held by a plugin while the flow waits on other work, such as
+/// resolving the destination.
+pub trait PendingInbound: Send + 'static {
+    /// Resolves once the client has gone away.
+    fn closed(&mut self) -> BoxFuture<'_, ()>;
+}
+
+/// A stream waiting to be handed over to the next handler.
+pub struct PendingStream {
+    lower: Box<dyn Stream>,
+    initial_data: Buffer,
+    early: EarlyRead,
+}
+
+impl PendingStream {
+    pub fn new(lower: Box<dyn Stream>, initial_data: Buffer) -> Self {
+        Self {
+            lower,
+            initial_data,
+            early: EarlyRead::Idle,
+        }
+    }
+
+    /// The stream to hand over, with data received while waiting appended to the
+    /// initial data.
+    pub fn into_parts(self) -> (Box<dyn Stream>, Buffer) {
+        let Self {
+            lower,
+            mut initial_data,
+            early,
+        } = self;
+        let read = match early {
+            EarlyRead::Idle => return (lower, initial_data),
+            EarlyRead::Received(buf, eof) => {
+                initial_data.extend_from_slice(&buf);
+                if !eof {
+                    return (lower, initial_data);
+                }
+                ResumedRead::Eof
+            }
+            EarlyRead::Committed(_) => ResumedRead::Committed,
+        };
+        (Box::new(ResumedStream { inner: lower, read }), initial_data)
+    }
+}
+
+impl PendingInbound for PendingStream {
+    fn closed(&mut self) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            inbound_closed(self.lower.as_mut(), &mut self.early).await;
+        })
+    }
+}
+
+impl PendingInbound for Box<dyn DatagramSession> {
+    fn closed(&mut self) -> BoxFuture<'_, ()> {
+        // Sessions only close after being idle for much longer than any pending
+        // work is allowed to take, and reading here would drop datagrams.
+        Box::pin(futures::future::pending())
+    }
+}
+
+enum ResumedRead {
+    /// The read started while waiting is still in flight.
+    Committed,
+    /// Data from that read, and whether the client has closed its side afterwards.
+    Ready(Buffer, bool),
+    Filled(Buffer, bool),
+    Eof,
+    Passthrough,
+}
+
+/// Finishes a read started by [`inbound_closed`] before reading from the inner
+/// stream as usual.
+struct ResumedStream {
+    inner: Box<dyn Stream>,
+    read: ResumedRead,
+}
+
+impl Stream for ResumedStream {
+    fn poll_request_size(&mut self, cx: &mut Context<'_>) -> Poll<FlowResult<SizeHint>> {
+        if let ResumedRead::Committed = self.read {
+            self.read = match futures::ready!(self.inner.poll_rx_buffer(cx)) {
+                Ok(buf) => ResumedRead::Ready(buf, false),
+                Err((buf, FlowError::Eof)) if !buf.is_empty() => ResumedRead::Ready(buf, true),
+                Err((_, e)) => {
+                    self.read = ResumedRead::Passthrough;
+                    return Poll::Ready(Err(e));
+                }
+            };
+        }
+        match &self.read {
+            ResumedRead::Ready(buf, _) => Poll::Ready(Ok(SizeHint::AtLeast(buf.len()))),
+            ResumedRead::Eof => Poll::Ready(Err(FlowError::Eof)),
+            _ => self.inner.poll_request_size(cx),
+        }
+    }
+
+    fn commit_rx_buffer(&mut self, mut buffer: Buffer) -> Result<(), (Buffer, FlowError)> {
+        match std::mem::replace(&mut self.read, ResumedRead::Passthrough) {
+            ResumedRead::Ready(data, eof) => {
+                buffer.extend_from_slice(&data);
+                self.read = ResumedRead::Filled(buffer, eof);
+                Ok(())
+            }
+            read => {
+                self.read = read;
+                self.inner.commit_rx_buffer(buffer)
+            }
+        }
+    }
+
+    fn poll_rx_buffer(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Buffer, (Buffer, FlowError)>> {
+        match std::mem::replace(&mut self.read, ResumedRead::Passthrough) {
+            ResumedRead::Filled(buf, eof) => {
+                if eof {
+                    self.read = ResumedRead::Eof;
+                }
+                Poll::Ready(Ok(buf))
+            }
+            read => {
+                self.read = read;
+                self.inner.poll_rx_buffer(cx)
+            }
+        }
+    }
+
+    fn poll_tx_buffer(
+        &mut self,
+        cx: &mut Context<'_>,
+        size: NonZeroUsize,
+    ) -> Poll<FlowResult<Buffer>> {
+        self.inner.poll_tx_buffer(cx, size)
+    }
+
+    fn commit_tx_buffer(&mut self, buffer: Buffer) -> FlowResult<()> {
+        self.inner.commit_tx_buffer(buffer)
+    }
+
+    fn poll_flush_tx(&mut self, cx: &mut Context<'_>) -> Poll<FlowResult<()>> {
+        self.inner.poll_flush_tx(cx)
+    }
+
+    fn poll_close_tx(&mut self, cx: &mut Context<'_>) -> Poll<FlowResult<()>> {
+        self.inner.poll_close_tx(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+    use crate::flow::testing::*;
+
+    async fn read_all(mut stream: Box<dyn Stream>) -> Buffer {
+        let mut ret = vec![];
+        loop {
+            let size = match crate::get_request_size_boxed!(stream) {
+                Ok(size) => size,
+                Err(_) => return ret,
+            };
+            stream
+                .commit_rx_buffer(Vec::with_capacity(size.with_min_content(1500)))
+                .unwrap();
+            match crate::get_rx_buffer_boxed!(stream) {
+                Ok(buf) => ret.extend_from_slice(&buf),
+                Err((buf, _)) => {
+                    ret.extend_from_slice(&buf);
+                    return ret;
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_closed_fires_on_close() {
+        let (stream, peer) = stream_pair();
+        let mut pending = PendingStream::new(stream, vec![]);
+        drop(peer);
+        pending.closed().await;
+    }
+
+    #[tokio::test]
+    async fn test_hand_over_during_read() {
+        let (stream, mut peer) = stream_pair();
+        let mut pending = PendingStream::new(stream, b"GET".to_vec());
+        // Leave a read in flight.
+        tokio::select! {
+            _ = pending.closed() => unreachable!(),
+            _ = tokio::task::yield_now() => {}
+        }
+        let (stream, initial_data) = pending.into_parts();
+        assert_eq!(initial_data, b"GET");
+        peer.write_all(b" / HTTP/1.1").await.unwrap();
+        peer.shutdown().await.unwrap();
+        assert_eq!(read_all(stream).await, b" / HTTP/1.1");
+    }
+
+    #[tokio::test]
+    async fn test_hand_over_received_data() {
+        let (stream, mut peer) = stream_pair();
+        let mut pending = PendingStream::new(stream, vec![]);
+        peer.write_all(b"hello").await.unwrap();
+        tokio::time::timeout(std::time::Duration::from_millis(50), pending.closed())
+            .await
+            .unwrap_err();
+        let (mut stream, initial_data) = pending.into_parts();
+        assert_eq!(initial_data, b"hello");
+
+        // The stream is still usable in both directions.
+        let mut buf = crate::get_tx_buffer_boxed!(stream, NonZeroUsize::new(2).unwrap()).unwrap();
+        buf.extend_from_slice(b"ok");
+        stream.commit_tx_buffer(buf).unwrap();
+        poll_fn(|cx| stream.poll_flush_tx(cx)).await.unwrap();
+        let mut reply = [0; 2];
+        peer.read_exact(&mut reply).await.unwrap();
+        assert_eq!(&reply, b"ok");
+    }
+}
@@ -1,6 +1,7 @@
 use std::mem::ManuallyDrop;
 use std::pin::Pin;
 use std::task::{Context, Poll};
+use std::time::Duration;
 
 use flume::r#async::RecvStream;
 use futures::{ready, Stream};
@@ -30,12 +31,14 @@ impl<S: MultiplexedDatagramSession> Drop for MultiplexedDatagramSessionAdapter<S
 }
 
 impl<S: MultiplexedDatagramSession> MultiplexedDatagramSessionAdapter<S> {
-    pub fn new(inner: S, rx: MultiplexedDatagramRx, timeout: u64) -> Self {
+    /// The session closes if no datagram is sent or received for about
+    /// `timeout`, up to twice as long.
+    pub fn new(inner: S, rx: MultiplexedDatagramRx, timeout: Duration) -> Self {
         Self {
             inner,
             rx: Some(rx),
             has_io_within_tick: true,
-            timer: ManuallyDrop::new(interval(tokio::time::Duration::from_secs(timeout))),
+            timer: ManuallyDrop::new(interval(timeout)),
         }
     }
 
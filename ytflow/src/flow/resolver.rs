@@ -1,15 +1,68 @@
 use std::net::{Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
 
 use async_trait::async_trait;
 use smallvec::SmallVec;
 
+use super::{with_deadline, AbortSignal, FlowResult};
+
 pub type ResolvedV4 = SmallVec<[Ipv4Addr; 4]>;
 pub type ResolvedV6 = SmallVec<[Ipv6Addr; 2]>;
 pub type ResolveResultV4 = super::FlowResult<SmallVec<[Ipv4Addr; 4]>>;
 pub type ResolveResultV6 = super::FlowResult<SmallVec<[Ipv6Addr; 2]>>;
 
+/// Bounds a single resolution. A timed out resolution fails with
+/// [`std::io::ErrorKind::TimedOut`]; an aborted one fails with
+/// [`std::io::ErrorKind::Interrupted`].
+#[derive(Debug, Clone, Default)]
+pub struct ResolveOptions {
+    pub timeout: Option<Duration>,
+    pub abort: Option<AbortSignal>,
+}
+
+impl ResolveOptions {
+    async fn run<T>(self, fut: impl std::future::Future<Output = FlowResult<T>>) -> FlowResult<T> {
+        let Self { timeout, abort } = self;
+        let fut = async move {
+            match timeout {
+                Some(timeout) => with_deadline(timeout, fut).await,
+                None => fut.await,
+            }
+        };
+        match abort {
+            Some(abort) => abort.guard(fut).await,
+            None => fut.await,
+        }
+    }
+}
+
 #[async_trait]
 pub trait Resolver: Send + Sync {
     async fn resolve_ipv4(&self, domain: String) -> ResolveResultV4;
     async fn resolve_ipv6(&self, domain: String) -> ResolveResultV6;
+
+    /// Answer from memory without awaiting, if the resolver already knows the
+    /// domain. Latency-sensitive callers try this before `resolve_ipv4`.
+    fn try_resolve_ipv4_sync(&self, _domain: &str) -> Option<ResolvedV4> {
+        None
+    }
+    fn try_resolve_ipv6_sync(&self, _domain: &str) -> Option<ResolvedV6> {
+        None
+    }
+
+    /// Like `resolve_ipv4`, but gives up when the deadline passes or the flow requesting
+    /// the resolution is aborted. Dropping the pending query lets the resolver release any
+    /// resources held for it.
+    async fn resolve_ipv4_with(&self, domain: String, options: ResolveOptions) -> ResolveResultV4 {
+        if let Some(ips) = self.try_resolve_ipv4_sync(&domain) {
+            return Ok(ips);
+        }
+        options.run(self.resolve_ipv4(domain)).await
+    }
+    async fn resolve_ipv6_with(&self, domain: String, options: ResolveOptions) -> ResolveResultV6 {
+        if let Some(ips) = self.try_resolve_ipv6_sync(&domain) {
+            return Ok(ips);
+        }
+        options.run(self.resolve_ipv6(domain)).await
+    }
 }
@@ -0,0 +1,396 @@
+//! In-memory access points for unit tests. Plugins under test can be wired up
+//! with these mocks and driven from the test body without opening any socket.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::task::{Context, Poll};
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use tokio::io::DuplexStream;
+
+use super::*;
+
+/// Local address of flows created by [`context`].
+pub const LOCAL_PEER: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 1234);
+
+const PIPE_CAPACITY: usize = 64 * 1024;
+
+/// Parse `host:port` into a destination. IPv6 addresses must be enclosed in
+/// brackets; hosts that are not IP addresses are taken as domain names.
+pub fn dest(s: &str) -> DestinationAddr {
+    let (host, port) = s.rsplit_once(':').expect("destination should have a port");
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    DestinationAddr {
+        host: host
+            .parse()
+            .map(HostName::Ip)
+            .unwrap_or_else(|_| HostName::DomainName(host.into())),
+        port: port.parse().expect("port should be a u16"),
+    }
+}
+
+/// A flow from [`LOCAL_PEER`] to `remote_peer`.
+pub fn context(remote_peer: &str) -> Box<FlowContext> {
+    Box::new(FlowContext::new(LOCAL_PEER, dest(remote_peer)))
+}
+
+/// Keeps access points alive for the duration of a test while plugins under
+/// test only hold weak references to them, as they would in a plugin set.
+#[derive(Default)]
+pub struct AccessPoints(Vec<Arc<dyn Any + Send + Sync>>);
+
+impl AccessPoints {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hold `ap` and return a weak reference to be coerced into the access
+    /// point type a plugin expects, such as `Weak<dyn StreamHandler>`.
+    pub fn hold<T: Send + Sync + 'static>(&mut self, ap: Arc<T>) -> Weak<T> {
+        let weak = Arc::downgrade(&ap);
+        self.0.push(ap);
+        weak
+    }
+}
+
+/// A connected pair of a [`Stream`] and its peer, which can be read from and
+/// written to with [`tokio::io::AsyncReadExt`] and [`tokio::io::AsyncWriteExt`].
+pub fn stream_pair() -> (Box<dyn Stream>, DuplexStream) {
+    let (local, peer) = tokio::io::duplex(PIPE_CAPACITY);
+    (Box::new(CompatFlow::new(local, 4096)), peer)
+}
+
+pub struct AcceptedStream {
+    pub stream: Box<dyn Stream>,
+    pub initial_data: Buffer,
+    pub context: Box<FlowContext>,
+}
+
+/// A [`StreamHandler`] that queues incoming streams for the test to accept.
+pub struct MockStreamHandler {
+    tx: flume::Sender<AcceptedStream>,
+    rx: flume::Receiver<AcceptedStream>,
+}
+
+impl MockStreamHandler {
+    pub fn new() -> Arc<Self> {
+        let (tx, rx) = flume::unbounded();
+        Arc::new(Self { tx, rx })
+    }
+
+    pub async fn accept(&self) -> AcceptedStream {
+        self.rx
+            .recv_async()
+            .await
+            .expect("MockStreamHandler holds its own sender")
+    }
+
+    pub fn try_accept(&self) -> Option<AcceptedStream> {
+        self.rx.try_recv().ok()
+    }
+}
+
+impl StreamHandler for MockStreamHandler {
+    fn on_stream(&self, stream: Box<dyn Stream>, initial_data: Buffer, context: Box<FlowContext>) {
+        let _ = self.tx.send(AcceptedStream {
+            stream,
+            initial_data,
+            context,
+        });
+    }
+}
+
+/// The far end of a stream created by [`MockStreamOutboundFactory`].
+pub struct OutboundStream {
+    pub peer: DuplexStream,
+    pub remote_peer: DestinationAddr,
+    pub initial_data: Buffer,
+}
+
+/// A [`StreamOutboundFactory`] that connects to in-memory peers queued for
+/// the test to pick up, or refuses all connections.
+pub struct MockStreamOutboundFactory {
+    refuse: bool,
+    tx: flume::Sender<OutboundStream>,
+    rx: flume::Receiver<OutboundStream>,
+}
+
+impl MockStreamOutboundFactory {
+    pub fn new() -> Arc<Self> {
+        Self::with_refuse(false)
+    }
+
+    pub fn refusing() -> Arc<Self> {
+        Self::with_refuse(true)
+    }
+
+    fn with_refuse(refuse: bool) -> Arc<Self> {
+        let (tx, rx) = flume::unbounded();
+        Arc::new(Self { refuse, tx, rx })
+    }
+
+    pub async fn connected(&self) -> OutboundStream {
+        self.rx
+            .recv_async()
+            .await
+            .expect("MockStreamOutboundFactory holds its own sender")
+    }
+}
+
+#[async_trait]
+impl StreamOutboundFactory for MockStreamOutboundFactory {
+    async fn create_outbound(
+        &self,
+        context: &mut FlowContext,
+        initial_data: &'_ [u8],
+    ) -> FlowResult<(Box<dyn Stream>, Buffer)> {
+        if self.refuse {
+            return Err(io::Error::from(io::ErrorKind::ConnectionRefused).into());
+        }
+        let (stream, peer) = stream_pair();
+        let _ = self.tx.send(OutboundStream {
+            peer,
+            remote_peer: context.remote_peer.clone(),
+            initial_data: initial_data.to_vec(),
+        });
+        Ok((stream, Buffer::new()))
+    }
+}
+
+type Datagram = (DestinationAddr, Buffer);
+
+struct MemoryDatagramSession {
+    rx: flume::r#async::RecvStream<'static, Datagram>,
+    tx: flume::Sender<Datagram>,
+}
+
+impl DatagramSession for MemoryDatagramSession {
+    fn poll_recv_from(&mut self, cx: &mut Context) -> Poll<Option<Datagram>> {
+        self.rx.poll_next_unpin(cx)
+    }
+    fn poll_send_ready(&mut self, _cx: &mut Context<'_>) -> Poll<()> {
+        Poll::Ready(())
+    }
+    fn send_to(&mut self, remote_peer: DestinationAddr, buf: Buffer) {
+        let _ = self.tx.send((remote_peer, buf));
+    }
+    fn poll_shutdown(&mut self, _cx: &mut Context<'_>) -> Poll<FlowResult<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// The far end of an in-memory [`DatagramSession`]. Dropping it closes the
+/// session.
+pub struct DatagramPeer {
+    tx: flume::Sender<Datagram>,
+    rx: flume::Receiver<Datagram>,
+}
+
+impl DatagramPeer {
+    /// Deliver a datagram to the session, appearing to come from `from`.
+    pub fn send_to(&self, from: DestinationAddr, buf: impl Into<Buffer>) {
+        let _ = self.tx.send((from, buf.into()));
+    }
+
+    /// Receive a datagram sent by the session, or `None` if it is dropped.
+    pub async fn recv_from(&self) -> Option<Datagram> {
+        self.rx.recv_async().await.ok()
+    }
+}
+
+/// A connected pair of a [`DatagramSession`] and its peer.
+pub fn datagram_pair() -> (Box<dyn DatagramSession>, DatagramPeer) {
+    let (session_tx, peer_rx) = flume::unbounded();
+    let (peer_tx, session_rx) = flume::unbounded();
+    (
+        Box::new(MemoryDatagramSession {
+            rx: session_rx.into_stream(),
+            tx: session_tx,
+        }),
+        DatagramPeer {
+            tx: peer_tx,
+            rx: peer_rx,
+        },
+    )
+}
+
+enum DatagramBehavior {
+    Echo,
+    Blackhole,
+    Queue(flume::Sender<(DatagramPeer, Box<FlowContext>)>),
+}
+
+/// A [`DatagramSessionFactory`] binding in-memory sessions.
+pub struct MockDatagramSessionFactory {
+    behavior: DatagramBehavior,
+    binds: AtomicUsize,
+}
+
+impl MockDatagramSessionFactory {
+    fn with_behavior(behavior: DatagramBehavior) -> Arc<Self> {
+        Arc::new(Self {
+            behavior,
+            binds: AtomicUsize::new(0),
+        })
+    }
+
+    /// Sessions send every datagram straight back.
+    pub fn echo() -> Arc<Self> {
+        Self::with_behavior(DatagramBehavior::Echo)
+    }
+
+    /// Sessions swallow every datagram and never receive anything, as if UDP
+    /// were blocked.
+    pub fn blackhole() -> Arc<Self> {
+        Self::with_behavior(DatagramBehavior::Blackhole)
+    }
+
+    /// Peers of the sessions are handed to the test through the returned
+    /// receiver along with the context they were bound with.
+    pub fn queued() -> (Arc<Self>, flume::Receiver<(DatagramPeer, Box<FlowContext>)>) {
+        let (tx, rx) = flume::unbounded();
+        (Self::with_behavior(DatagramBehavior::Queue(tx)), rx)
+    }
+
+    /// How many sessions have been bound so far.
+    pub fn binds(&self) -> usize {
+        self.binds.load(Ordering::Relaxed)
+    }
+}
+
+#[async_trait]
+impl DatagramSessionFactory for MockDatagramSessionFactory {
+    async fn bind(&self, context: Box<FlowContext>) -> FlowResult<Box<dyn DatagramSession>> {
+        self.binds.fetch_add(1, Ordering::Relaxed);
+        let (session, peer) = datagram_pair();
+        match &self.behavior {
+            DatagramBehavior::Echo => {
+                tokio::spawn(async move {
+                    while let Some((dest, buf)) = peer.recv_from().await {
+                        peer.send_to(dest, buf);
+                    }
+                });
+            }
+            DatagramBehavior::Blackhole => {
+                tokio::spawn(async move { while peer.recv_from().await.is_some() {} });
+            }
+            DatagramBehavior::Queue(tx) => {
+                let _ = tx.send((peer, context));
+            }
+        }
+        Ok(session)
+    }
+}
+
+/// A [`DatagramSessionHandler`] that queues incoming sessions for the test to
+/// accept.
+pub struct MockDatagramSessionHandler {
+    tx: flume::Sender<(Box<dyn DatagramSession>, Box<FlowContext>)>,
+    rx: flume::Receiver<(Box<dyn DatagramSession>, Box<FlowContext>)>,
+}
+
+impl MockDatagramSessionHandler {
+    pub fn new() -> Arc<Self> {
+        let (tx, rx) = flume::unbounded();
+        Arc::new(Self { tx, rx })
+    }
+
+    pub async fn accept(&self) -> (Box<dyn DatagramSession>, Box<FlowContext>) {
+        self.rx
+            .recv_async()
+            .await
+            .expect("MockDatagramSessionHandler holds its own sender")
+    }
+}
+
+impl DatagramSessionHandler for MockDatagramSessionHandler {
+    fn on_session(&self, session: Box<dyn DatagramSession>, context: Box<FlowContext>) {
+        let _ = self.tx.send((session, context));
+    }
+}
+
+/// A [`Resolver`] answering from a static table. Unknown domains fail with
+/// [`io::ErrorKind::NotFound`].
+#[derive(Default)]
+pub struct MockResolver {
+    ipv4: HashMap<String, ResolvedV4>,
+    ipv6: HashMap<String, ResolvedV6>,
+    queries: AtomicUsize,
+}
+
+impl MockResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_ipv4(mut self, domain: &str, ip: Ipv4Addr) -> Self {
+        self.ipv4.entry(domain.into()).or_default().push(ip);
+        self
+    }
+
+    pub fn with_ipv6(mut self, domain: &str, ip: Ipv6Addr) -> Self {
+        self.ipv6.entry(domain.into()).or_default().push(ip);
+        self
+    }
+
+    /// How many queries have been made so far.
+    pub fn queries(&self) -> usize {
+        self.queries.load(Ordering::Relaxed)
+    }
+}
+
+#[async_trait]
+impl Resolver for MockResolver {
+    async fn resolve_ipv4(&self, domain: String) -> ResolveResultV4 {
+        self.queries.fetch_add(1, Ordering::Relaxed);
+        self.ipv4
+            .get(&domain)
+            .cloned()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound).into())
+    }
+    async fn resolve_ipv6(&self, domain: String) -> ResolveResultV6 {
+        self.queries.fetch_add(1, Ordering::Relaxed);
+        self.ipv6
+            .get(&domain)
+            .cloned()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound).into())
+    }
+}
+
+/// A [`Resolver`] that never answers. Each query given up by its caller is
+/// reported through the receiver returned by [`StallingResolver::new`].
+pub struct StallingResolver {
+    cancelled: flume::Sender<()>,
+}
+
+struct CancelOnDrop(flume::Sender<()>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        let _ = self.0.send(());
+    }
+}
+
+impl StallingResolver {
+    pub fn new() -> (Arc<Self>, flume::Receiver<()>) {
+        let (cancelled, rx) = flume::unbounded();
+        (Arc::new(Self { cancelled }), rx)
+    }
+}
+
+#[async_trait]
+impl Resolver for StallingResolver {
+    async fn resolve_ipv4(&self, _domain: String) -> ResolveResultV4 {
+        let _guard = CancelOnDrop(self.cancelled.clone());
+        futures::future::pending().await
+    }
+    async fn resolve_ipv6(&self, _domain: String) -> ResolveResultV6 {
+        let _guard = CancelOnDrop(self.cancelled.clone());
+        futures::future::pending().await
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use super::{AbortHandle, AbortSignal, DestinationAddr};
+use crate::atomic::AtomicU64;
+
+struct ConnectionEntry {
+    kind: &'static str,
+    local_peer: SocketAddr,
+    remote_peer: DestinationAddr,
+    handler: Arc<str>,
+    outbound: Arc<str>,
+    started_at: SystemTime,
+    uplink: AtomicU64,
+    downlink: AtomicU64,
+    close: AbortHandle,
+}
+
+#[derive(Default)]
+struct TrackerInner {
+    next_id: AtomicU64,
+    connections: Mutex<BTreeMap<u64, Arc<ConnectionEntry>>>,
+}
+
+/// Active streams and datagram sessions of a plugin set, as registered by
+/// the plugins relaying them.
+#[derive(Clone, Default)]
+pub struct ConnectionTracker(Arc<TrackerInner>);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConnectionInfo {
+    pub id: u64,
+    /// Either `tcp` or `udp`.
+    pub kind: &'static str,
+    pub local_peer: String,
+    pub remote_peer: String,
+    /// The plugin relaying the connection.
+    pub handler: String,
+    /// The outbound plugin the connection is relayed to.
+    pub outbound: String,
+    pub uplink: u64,
+    pub downlink: u64,
+    /// Unix timestamp in seconds.
+    pub started_at: u64,
+}
+
+/// Registration of a connection, removed from the tracker when dropped.
+pub struct TrackedConnection {
+    tracker: ConnectionTracker,
+    id: u64,
+    entry: Arc<ConnectionEntry>,
+}
+
+impl ConnectionTracker {
+    pub fn track(
+        &self,
+        kind: &'static str,
+        local_peer: SocketAddr,
+        remote_peer: DestinationAddr,
+        handler: Arc<str>,
+        outbound: Arc<str>,
+    ) -> TrackedConnection {
+        let id = self.0.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let entry = Arc::new(ConnectionEntry {
+            kind,
+            local_peer,
+            remote_peer,
+            handler,
+            outbound,
+            started_at: SystemTime::now(),
+            uplink: AtomicU64::new(0),
+            downlink: AtomicU64::new(0),
+            close: AbortHandle::new(),
+        });
+        self.0.connections.lock().unwrap().insert(id, entry.clone());
+        TrackedConnection {
+            tracker: self.clone(),
+            id,
+            entry,
+        }
+    }
+
+    pub fn list(&self) -> Vec<ConnectionInfo> {
+        let connections = self.0.connections.lock().unwrap();
+        connections
+            .iter()
+            .map(|(&id, entry)| ConnectionInfo {
+                id,
+                kind: entry.kind,
+                local_peer: entry.local_peer.to_string(),
+                remote_peer: entry.remote_peer.to_string(),
+                handler: entry.handler.to_string(),
+                outbound: entry.outbound.to_string(),
+                uplink: entry.uplink.load(Ordering::Relaxed),
+                downlink: entry.downlink.load(Ordering::Relaxed),
+                started_at: entry
+                    .started_at
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or_default(),
+            })
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.connections.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Ask the plugin relaying the connection to tear it down. Returns
+    /// whether such a connection exists.
+    pub fn close(&self, id: u64) -> bool {
+        let connections = self.0.connections.lock().unwrap();
+        match connections.get(&id) {
+            Some(entry) => {
+                entry.close.abort();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl TrackedConnection {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+    pub fn uplink(&self) -> &AtomicU64 {
+        &self.entry.uplink
+    }
+    pub fn downlink(&self) -> &AtomicU64 {
+        &self.entry.downlink
+    }
+    /// Fires when the connection is closed through [`ConnectionTracker::close`].
+    pub fn close_signal(&self) -> AbortSignal {
+        self.entry.close.signal()
+    }
+}
+
+impl Drop for TrackedConnection {
+    fn drop(&mut self) {
+        self.tracker.0.connections.lock().unwrap().remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flow::testing::{dest, LOCAL_PEER};
+
+    #[test]
+    fn test_track_and_list() {
+        let tracker = ConnectionTracker::default();
+        let conn = tracker.track(
+            "tcp",
+            LOCAL_PEER,
+            dest("example.com:443"),
+            "forward".into(),
+            "socket".into(),
+        );
+        conn.uplink().fetch_add(10, Ordering::Relaxed);
+        conn.downlink().fetch_add(20, Ordering::Relaxed);
+        let list = tracker.list();
+        assert_eq!(list.len(), 1);
+        let info = &list[0];
+        assert_eq!(info.id, conn.id());
+        assert_eq!(
+            (
+                &*info.remote_peer,
+                &*info.outbound,
+                info.uplink,
+                info.downlink
+            ),
+            ("example.com:443", "socket", 10, 20)
+        );
+        drop(conn);
+        assert!(tracker.is_empty());
+    }
+
+    #[test]
+    fn test_close() {
+        let tracker = ConnectionTracker::default();
+        let conn = tracker.track(
+            "udp",
+            LOCAL_PEER,
+            dest("1.1.1.1:53"),
+            "forward".into(),
+            "socket".into(),
+        );
+        let signal = conn.close_signal();
+        assert!(!tracker.close(conn.id() + 1));
+        assert!(!signal.is_aborted());
+        assert!(tracker.close(conn.id()));
+        assert!(signal.is_aborted());
+        // Closing only signals the relaying plugin, which unregisters the
+        // connection once it is torn down.
+        assert_eq!(tracker.len(), 1);
+        drop(conn);
+        assert!(!tracker.close(1));
+    }
+}
@@ -0,0 +1,410 @@
+use std::collections::BTreeMap;
+use std::num::NonZeroUsize;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use serde::Serialize;
+
+use super::*;
+use crate::atomic::AtomicU64;
+
+const RATE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Traffic going through the access points of a plugin. Uplink is towards
+/// remote peers, downlink is towards local peers. A packet is a datagram, or
+/// a buffer passed through a stream.
+#[derive(Default)]
+pub struct TrafficCounter {
+    uplink_bytes: AtomicU64,
+    downlink_bytes: AtomicU64,
+    uplink_packets: AtomicU64,
+    downlink_packets: AtomicU64,
+}
+
+impl TrafficCounter {
+    fn add(&self, uplink: bool, len: usize) {
+        let (bytes, packets) = if uplink {
+            (&self.uplink_bytes, &self.uplink_packets)
+        } else {
+            (&self.downlink_bytes, &self.downlink_packets)
+        };
+        bytes.fetch_add(len as u64, Ordering::Relaxed);
+        packets.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+struct RateSample {
+    at: Instant,
+    uplink_bytes: u64,
+    downlink_bytes: u64,
+    uplink_rate: u64,
+    downlink_rate: u64,
+}
+
+struct PluginTraffic {
+    counter: Arc<TrafficCounter>,
+    sample: RateSample,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PluginTrafficInfo {
+    pub plugin: String,
+    pub uplink_bytes: u64,
+    pub downlink_bytes: u64,
+    pub uplink_packets: u64,
+    pub downlink_packets: u64,
+    /// Bytes per second, averaged since the previous sample taken at least
+    /// one second earlier.
+    pub uplink_rate: u64,
+    pub downlink_rate: u64,
+}
+
+/// Traffic counters of all plugins in a plugin set, keyed by plugin name.
+#[derive(Clone, Default)]
+pub struct TrafficStats(Arc<Mutex<BTreeMap<String, PluginTraffic>>>);
+
+impl TrafficStats {
+    pub fn counter(&self, plugin: &str) -> Arc<TrafficCounter> {
+        let mut plugins = self.0.lock().unwrap();
+        plugins
+            .entry(plugin.to_string())
+            .or_insert_with(|| PluginTraffic {
+                counter: Default::default(),
+                sample: RateSample {
+                    at: Instant::now(),
+                    uplink_bytes: 0,
+                    downlink_bytes: 0,
+                    uplink_rate: 0,
+                    downlink_rate: 0,
+                },
+            })
+            .counter
+            .clone()
+    }
+
+    pub fn snapshot(&self, now: Instant) -> Vec<PluginTrafficInfo> {
+        let mut plugins = self.0.lock().unwrap();
+        plugins
+            .iter_mut()
+            .map(|(plugin, traffic)| {
+                let counter = &traffic.counter;
+                let uplink_bytes = counter.uplink_bytes.load(Ordering::Relaxed);
+                let downlink_bytes = counter.downlink_bytes.load(Ordering::Relaxed);
+                let sample = &mut traffic.sample;
+                let elapsed = now.saturating_duration_since(sample.at);
+                if elapsed >= RATE_INTERVAL {
+                    let rate = |curr: u64, prev: u64| {
+                        (curr.saturating_sub(prev) as u128 * 1000 / elapsed.as_millis()) as u64
+                    };
+                    *sample = RateSample {
+                        at: now,
+                        uplink_bytes,
+                        downlink_bytes,
+                        uplink_rate: rate(uplink_bytes, sample.uplink_bytes),
+                        downlink_rate: rate(downlink_bytes, sample.downlink_bytes),
+                    };
+                }
+                PluginTrafficInfo {
+                    plugin: plugin.clone(),
+                    uplink_bytes,
+                    downlink_bytes,
+                    uplink_packets: counter.uplink_packets.load(Ordering::Relaxed),
+                    downlink_packets: counter.downlink_packets.load(Ordering::Relaxed),
+                    uplink_rate: sample.uplink_rate,
+                    downlink_rate: sample.downlink_rate,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Counts traffic of a stream. `rx_uplink` tells whether data read from the
+/// stream goes uplink, which is the case for streams handed to a handler.
+struct CountedStream {
+    inner: Box<dyn Stream>,
+    counter: Arc<TrafficCounter>,
+    rx_uplink: bool,
+    rx_offset: usize,
+    tx_offset: usize,
+}
+
+impl Stream for CountedStream {
+    fn poll_request_size(&mut self, cx: &mut Context<'_>) -> Poll<FlowResult<SizeHint>> {
+        self.inner.poll_request_size(cx)
+    }
+
+    fn commit_rx_buffer(&mut self, buffer: Buffer) -> Result<(), (Buffer, FlowError)> {
+        self.rx_offset = buffer.len();
+        self.inner.commit_rx_buffer(buffer)
+    }
+
+    fn poll_rx_buffer(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Buffer, (Buffer, FlowError)>> {
+        let res = futures::ready!(self.inner.poll_rx_buffer(cx));
+        if let Ok(buf) | Err((buf, FlowError::Eof)) = &res {
+            let len = buf.len().saturating_sub(self.rx_offset);
+            if len > 0 {
+                self.counter.add(self.rx_uplink, len);
+            }
+        }
+        Poll::Ready(res)
+    }
+
+    fn poll_tx_buffer(
+        &mut self,
+        cx: &mut Context<'_>,
+        size: NonZeroUsize,
+    ) -> Poll<FlowResult<Buffer>> {
+        let res = futures::ready!(self.inner.poll_tx_buffer(cx, size));
+        if let Ok(buf) = &res {
+            self.tx_offset = buf.len();
+        }
+        Poll::Ready(res)
+    }
+
+    fn commit_tx_buffer(&mut self, buffer: Buffer) -> FlowResult<()> {
+        let len = buffer.len().saturating_sub(self.tx_offset);
+        self.tx_offset = 0;
+        self.inner.commit_tx_buffer(buffer)?;
+        if len > 0 {
+            self.counter.add(!self.rx_uplink, len);
+        }
+        Ok(())
+    }
+
+    fn poll_flush_tx(&mut self, cx: &mut Context<'_>) -> Poll<FlowResult<()>> {
+        self.inner.poll_flush_tx(cx)
+    }
+
+    fn poll_close_tx(&mut self, cx: &mut Context<'_>) -> Poll<FlowResult<()>> {
+        self.inner.poll_close_tx(cx)
+    }
+}
+
+/// Counts traffic of a datagram session, see [`CountedStream`].
+struct CountedDatagramSession {
+    inner: Box<dyn DatagramSession>,
+    counter: Arc<TrafficCounter>,
+    rx_uplink: bool,
+}
+
+impl DatagramSession for CountedDatagramSession {
+    fn poll_recv_from(&mut self, cx: &mut Context) -> Poll<Option<(DestinationAddr, Buffer)>> {
+        let res = futures::ready!(self.inner.poll_recv_from(cx));
+        if let Some((_, buf)) = &res {
+            self.counter.add(self.rx_uplink, buf.len());
+        }
+        Poll::Ready(res)
+    }
+
+    fn poll_send_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_send_ready(cx)
+    }
+
+    fn send_to(&mut self, remote_peer: DestinationAddr, buf: Buffer) {
+        self.counter.add(!self.rx_uplink, buf.len());
+        self.inner.send_to(remote_peer, buf)
+    }
+
+    fn poll_shutdown(&mut self, cx: &mut Context<'_>) -> Poll<FlowResult<()>> {
+        self.inner.poll_shutdown(cx)
+    }
+}
+
+/// Attributes traffic of streams handled by `inner` to its plugin.
+pub struct CountedStreamHandler {
+    pub inner: Weak<dyn StreamHandler>,
+    pub counter: Arc<TrafficCounter>,
+}
+
+impl StreamHandler for CountedStreamHandler {
+    fn on_stream(&self, lower: Box<dyn Stream>, initial_data: Buffer, context: Box<FlowContext>) {
+        let Some(inner) = self.inner.upgrade() else {
+            return;
+        };
+        if !initial_data.is_empty() {
+            self.counter.add(true, initial_data.len());
+        }
+        let lower = Box::new(CountedStream {
+            inner: lower,
+            counter: self.counter.clone(),
+            rx_uplink: true,
+            rx_offset: 0,
+            tx_offset: 0,
+        });
+        inner.on_stream(lower, initial_data, context)
+    }
+}
+
+/// Attributes traffic of streams created by `inner` to its plugin.
+pub struct CountedStreamOutboundFactory {
+    pub inner: Weak<dyn StreamOutboundFactory>,
+    pub counter: Arc<TrafficCounter>,
+}
+
+#[async_trait]
+impl StreamOutboundFactory for CountedStreamOutboundFactory {
+    async fn create_outbound(
+        &self,
+        context: &mut FlowContext,
+        initial_data: &'_ [u8],
+    ) -> FlowResult<(Box<dyn Stream>, Buffer)> {
+        let inner = self.inner.upgrade().ok_or(FlowError::NoOutbound)?;
+        let (stream, initial_res) = inner.create_outbound(context, initial_data).await?;
+        if !initial_data.is_empty() {
+            self.counter.add(true, initial_data.len());
+        }
+        if !initial_res.is_empty() {
+            self.counter.add(false, initial_res.len());
+        }
+        let stream = Box::new(CountedStream {
+            inner: stream,
+            counter: self.counter.clone(),
+            rx_uplink: false,
+            rx_offset: 0,
+            tx_offset: 0,
+        });
+        Ok((stream, initial_res))
+    }
+}
+
+/// Attributes traffic of sessions handled by `inner` to its plugin.
+pub struct CountedDatagramSessionHandler {
+    pub inner: Weak<dyn DatagramSessionHandler>,
+    pub counter: Arc<TrafficCounter>,
+}
+
+impl DatagramSessionHandler for CountedDatagramSessionHandler {
+    fn on_session(&self, session: Box<dyn DatagramSession>, context: Box<FlowContext>) {
+        let Some(inner) = self.inner.upgrade() else {
+            return;
+        };
+        let session = Box::new(CountedDatagramSession {
+            inner: session,
+            counter: self.counter.clone(),
+            rx_uplink: true,
+        });
+        inner.on_session(session, context)
+    }
+}
+
+/// Attributes traffic of sessions bound by `inner` to its plugin.
+pub struct CountedDatagramSessionFactory {
+    pub inner: Weak<dyn DatagramSessionFactory>,
+    pub counter: Arc<TrafficCounter>,
+}
+
+#[async_trait]
+impl DatagramSessionFactory for CountedDatagramSessionFactory {
+    async fn bind(&self, context: Box<FlowContext>) -> FlowResult<Box<dyn DatagramSession>> {
+        let inner = self.inner.upgrade().ok_or(FlowError::NoOutbound)?;
+        let session = inner.bind(context).await?;
+        Ok(Box::new(CountedDatagramSession {
+            inner: session,
+            counter: self.counter.clone(),
+            rx_uplink: false,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+    use crate::flow::testing::*;
+
+    #[tokio::test]
+    async fn test_counted_stream_outbound() {
+        let stats = TrafficStats::default();
+        let mut aps = AccessPoints::new();
+        let mock = MockStreamOutboundFactory::new();
+        let outbound = CountedStreamOutboundFactory {
+            inner: aps.hold(mock.clone()) as _,
+            counter: stats.counter("socket"),
+        };
+        let mut ctx = context("example.com:80");
+        let (mut stream, _) = outbound.create_outbound(&mut ctx, b"GET").await.unwrap();
+        let mut remote = mock.connected().await;
+
+        let mut buf = crate::get_tx_buffer_boxed!(stream, NonZeroUsize::new(5).unwrap()).unwrap();
+        buf.extend_from_slice(b" /\r\n\r\n");
+        stream.commit_tx_buffer(buf).unwrap();
+        crate::close_tx_boxed!(stream).unwrap();
+        let mut sent = vec![];
+        remote.peer.read_to_end(&mut sent).await.unwrap();
+        assert_eq!(sent, b" /\r\n\r\n");
+
+        remote.peer.write_all(b"HTTP/1.1").await.unwrap();
+        let _ = crate::get_request_size_boxed!(stream).unwrap();
+        stream.commit_rx_buffer(Vec::with_capacity(64)).unwrap();
+        let received = crate::get_rx_buffer_boxed!(stream)
+            .map_err(|(_, e)| e)
+            .unwrap();
+        assert_eq!(received, b"HTTP/1.1");
+
+        let info = &stats.snapshot(Instant::now())[0];
+        assert_eq!(
+            (
+                &*info.plugin,
+                info.uplink_bytes,
+                info.uplink_packets,
+                info.downlink_bytes,
+                info.downlink_packets
+            ),
+            ("socket", 9, 2, 8, 1)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_counted_datagram_handler() {
+        let stats = TrafficStats::default();
+        let mut aps = AccessPoints::new();
+        let mock = MockDatagramSessionHandler::new();
+        let handler = CountedDatagramSessionHandler {
+            inner: aps.hold(mock.clone()) as _,
+            counter: stats.counter("forward"),
+        };
+        let (session, peer) = datagram_pair();
+        handler.on_session(session, context("1.1.1.1:53"));
+        let (mut session, _) = mock.accept().await;
+        peer.send_to(dest("1.1.1.1:53"), b"query".to_vec());
+        let (_, buf) = futures::future::poll_fn(|cx| session.poll_recv_from(cx))
+            .await
+            .unwrap();
+        session.send_to(dest("1.1.1.1:53"), buf);
+        session.send_to(dest("1.1.1.1:53"), b"!".to_vec());
+
+        let info = &stats.snapshot(Instant::now())[0];
+        assert_eq!(
+            (
+                info.uplink_bytes,
+                info.uplink_packets,
+                info.downlink_bytes,
+                info.downlink_packets
+            ),
+            (5, 1, 6, 2)
+        );
+    }
+
+    #[test]
+    fn test_rate() {
+        let start = Instant::now();
+        let stats = TrafficStats::default();
+        let counter = stats.counter("p");
+        counter.add(true, 1000);
+        // Too early to sample.
+        assert_eq!(stats.snapshot(start)[0].uplink_rate, 0);
+        let info = &stats.snapshot(start + Duration::from_secs(2))[0];
+        assert_eq!((info.uplink_bytes, info.uplink_rate), (1000, 500));
+        let info = &stats.snapshot(start + Duration::from_millis(2500))[0];
+        assert_eq!(info.uplink_rate, 500);
+        let info = &stats.snapshot(start + Duration::from_secs(4))[0];
+        assert_eq!(info.uplink_rate, 0);
+    }
+}
@@ -7,6 +7,7 @@
 #![feature(result_flattening)]
 #![feature(lazy_cell)]
 
+pub(crate) mod atomic;
 pub mod config;
 #[cfg(feature = "plugins")]
 pub mod control;
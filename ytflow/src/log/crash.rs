@@ -0,0 +1,148 @@
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::Future;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+
+use futures::FutureExt;
+use serde::Serialize;
+
+use crate::atomic::AtomicU64;
+
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(1);
+static CRASH_COUNT: AtomicU64 = AtomicU64::new(0);
+static CRASHES_BY_PLUGIN: Mutex<BTreeMap<String, u64>> = Mutex::new(BTreeMap::new());
+
+/// Allocate an identifier to tell sessions apart in crash reports.
+pub fn next_session_id() -> u64 {
+    NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Number of plugin tasks that have panicked since the core started.
+pub fn crash_count() -> u64 {
+    CRASH_COUNT.load(Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct CrashStats {
+    pub total: u64,
+    /// Panics caught by [`isolate`] and [`isolate_sync`], keyed by plugin name.
+    pub plugins: BTreeMap<String, u64>,
+}
+
+/// Panics caught since the core started, in total and per plugin.
+pub fn crash_stats() -> CrashStats {
+    CrashStats {
+        total: crash_count(),
+        plugins: CRASHES_BY_PLUGIN.lock().unwrap().clone(),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CrashReport<'a> {
+    pub plugin: &'a str,
+    pub session_id: u64,
+    pub message: &'a str,
+}
+
+impl<'a> fmt::Display for CrashReport<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[crash] plugin={} session={} panic={:?}",
+            self.plugin, self.session_id, self.message
+        )
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(s) = payload.downcast_ref::<&'static str>() {
+        s
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s
+    } else {
+        "<non-string panic payload>"
+    }
+}
+
+fn report(plugin: &str, session_id: u64, payload: &(dyn Any + Send)) {
+    CRASH_COUNT.fetch_add(1, Ordering::Relaxed);
+    *CRASHES_BY_PLUGIN
+        .lock()
+        .unwrap()
+        .entry(plugin.to_string())
+        .or_default() += 1;
+    let report = CrashReport {
+        plugin,
+        session_id,
+        message: panic_message(payload),
+    };
+    super::debug_log(report.to_string());
+}
+
+/// Call a synchronous plugin entry point. A panic is reported and swallowed so that the caller,
+/// usually a listener loop, keeps running. Returns `None` if `f` panicked.
+pub fn isolate_sync<R>(plugin: &str, session_id: u64, f: impl FnOnce() -> R) -> Option<R> {
+    let span = super::session_span(plugin, session_id);
+    let _enter = span.enter();
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(r) => Some(r),
+        Err(payload) => {
+            report(plugin, session_id, &*payload);
+            None
+        }
+    }
+}
+
+/// Drive a per-connection plugin task. A panic is reported and swallowed instead of silently
+/// tearing down the task. Returns `None` if `fut` panicked.
+pub async fn isolate<F: Future>(plugin: &str, session_id: u64, fut: F) -> Option<F::Output> {
+    let span = super::session_span(plugin, session_id);
+    match super::instrument(AssertUnwindSafe(fut).catch_unwind(), span).await {
+        Ok(r) => Some(r),
+        Err(payload) => {
+            report(plugin, session_id, &*payload);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn crashes_of(plugin: &str) -> u64 {
+        crash_stats()
+            .plugins
+            .get(plugin)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn test_isolate_sync_swallows_panic() {
+        let before = crash_count();
+        assert_eq!(isolate_sync("crash-test-sync", 1, || 42), Some(42));
+        assert_eq!(crashes_of("crash-test-sync"), 0);
+        let res: Option<()> = isolate_sync("crash-test-sync", 2, || panic!("boom"));
+        assert_eq!(res, None);
+        assert_eq!(crashes_of("crash-test-sync"), 1);
+        assert!(crash_count() > before);
+    }
+
+    #[tokio::test]
+    async fn test_isolate_swallows_panic() {
+        let before = crash_count();
+        assert_eq!(isolate("crash-test-async", 1, async { 42 }).await, Some(42));
+        let res: Option<()> = isolate("crash-test-async", 2, async {
+            tokio::task::yield_now().await;
+            panic!("boom")
+        })
+        .await;
+        assert_eq!(res, None);
+        assert_eq!(crashes_of("crash-test-async"), 1);
+        assert!(crash_count() > before);
+    }
+}
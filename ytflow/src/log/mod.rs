@@ -1,3 +1,10 @@
+mod crash;
+pub mod probe;
+mod trace;
+
+pub use crash::*;
+pub use trace::*;
+
 #[allow(unused)]
 #[cfg(windows)]
 pub fn debug_log(log: impl AsRef<std::ffi::OsStr>) {
@@ -0,0 +1,75 @@
+//! Tracepoints along the packet path, for profiling with standard OS tooling.
+//!
+//! With the `tracepoints` feature, these fire USDT probes under the `ytflow`
+//! provider on Linux (e.g. `bpftrace -e 'usdt:*:ytflow:tun_recv { ... }'` or
+//! `perf probe sdt_ytflow:*`), and ETW events from the `YtFlow.Core` provider on
+//! Windows (e.g. `tracelog -start ytflow -guid *YtFlow.Core`). Disabled
+//! probes cost a no-op instruction. Without the feature, or on other platforms,
+//! all of these compile down to nothing.
+
+#[cfg(all(feature = "tracepoints", windows))]
+tracelogging::define_provider!(PROVIDER, "YtFlow.Core");
+
+#[cfg(all(feature = "tracepoints", windows))]
+fn ensure_registered() {
+    static REGISTER: std::sync::Once = std::sync::Once::new();
+    // Safety: the provider is never unregistered, which is fine as long as the
+    // module stays loaded.
+    REGISTER.call_once(|| unsafe {
+        PROVIDER.register();
+    });
+}
+
+macro_rules! tracepoint {
+    ($usdt: ident, $etw: literal, $field: literal, $value: expr) => {{
+        let value = $value as u64;
+        #[cfg(all(feature = "tracepoints", target_os = "linux"))]
+        probe::probe!(ytflow, $usdt, value);
+        #[cfg(all(feature = "tracepoints", windows))]
+        {
+            ensure_registered();
+            tracelogging::write_event!(PROVIDER, $etw, level(Verbose), u64($field, &value));
+        }
+        let _ = value;
+    }};
+}
+
+/// A packet of `len` bytes was read from the TUN device.
+#[inline(always)]
+pub fn tun_recv(len: usize) {
+    tracepoint!(tun_recv, "TunRecv", "len", len);
+}
+
+/// `len` bytes were sent towards the remote peer through an outbound.
+#[inline(always)]
+pub fn outbound_send(len: usize) {
+    tracepoint!(outbound_send, "OutboundSend", "len", len);
+}
+
+/// `len` bytes were sent back towards the local peer through an inbound.
+#[inline(always)]
+pub fn inbound_send(len: usize) {
+    tracepoint!(inbound_send, "InboundSend", "len", len);
+}
+
+/// Run `poll` on the userspace network stack in `ip-stack`, reporting how
+/// long it took in microseconds.
+#[inline(always)]
+pub fn ip_stack_poll<R>(poll: impl FnOnce() -> R) -> R {
+    #[cfg(all(feature = "tracepoints", any(target_os = "linux", windows)))]
+    {
+        let start = std::time::Instant::now();
+        let ret = poll();
+        tracepoint!(
+            ip_stack_poll,
+            "IpStackPoll",
+            "duration_us",
+            start.elapsed().as_micros()
+        );
+        ret
+    }
+    #[cfg(not(all(feature = "tracepoints", any(target_os = "linux", windows))))]
+    {
+        poll()
+    }
+}
@@ -0,0 +1,67 @@
+//! Thin wrappers over `tracing` spans. Without the `tracing` feature all of
+//! these compile down to nothing.
+
+use std::future::Future;
+
+#[cfg(feature = "tracing")]
+pub type Span = tracing::Span;
+
+#[cfg(not(feature = "tracing"))]
+#[derive(Debug, Clone)]
+pub struct Span;
+
+#[cfg(not(feature = "tracing"))]
+impl Span {
+    pub fn enter(&self) -> Entered {
+        Entered
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+pub struct Entered;
+
+/// Span covering the loading of a plugin, including plugins it depends on.
+pub fn plugin_load_span(plugin: &str) -> Span {
+    #[cfg(feature = "tracing")]
+    {
+        tracing::info_span!("plugin_load", plugin)
+    }
+    #[cfg(not(feature = "tracing"))]
+    {
+        let _ = plugin;
+        Span
+    }
+}
+
+/// Span covering a connection accepted by an access point.
+pub fn session_span(access_point: &str, session_id: u64) -> Span {
+    #[cfg(feature = "tracing")]
+    {
+        tracing::info_span!("session", access_point, session_id)
+    }
+    #[cfg(not(feature = "tracing"))]
+    {
+        let _ = (access_point, session_id);
+        Span
+    }
+}
+
+/// Attach `span` to `fut`, so that the span is entered every time `fut` is polled.
+pub fn instrument<F: Future>(fut: F, span: Span) -> impl Future<Output = F::Output> {
+    #[cfg(feature = "tracing")]
+    {
+        tracing::Instrument::instrument(fut, span)
+    }
+    #[cfg(not(feature = "tracing"))]
+    {
+        let _ = span;
+        fut
+    }
+}
+
+/// Start the tokio-console server. The runtime must be built with
+/// `RUSTFLAGS="--cfg tokio_unstable"` for task data to be available.
+#[cfg(feature = "tokio-console")]
+pub fn init_console() {
+    console_subscriber::init();
+}
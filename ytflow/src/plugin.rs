@@ -1,4 +1,15 @@
 #[cfg(feature = "plugins")]
+pub mod alpn_dispatcher;
+pub mod automation;
+#[cfg(feature = "plugins")]
+pub mod bonding;
+#[cfg(feature = "plugins")]
+pub mod circuit_breaker;
+#[cfg(feature = "plugins")]
+pub mod delay;
+#[cfg(feature = "plugins")]
+pub mod dns_hijack;
+#[cfg(feature = "plugins")]
 pub mod dns_server;
 pub mod dyn_outbound;
 #[cfg(feature = "plugins")]
@@ -7,41 +18,71 @@ pub mod fakeip;
 pub mod fallback;
 #[cfg(feature = "plugins")]
 pub mod forward;
+pub mod guard;
+#[cfg(feature = "plugins")]
+pub mod health_endpoint;
 #[cfg(feature = "plugins")]
 pub mod host_resolver;
 #[cfg(feature = "plugins")]
 pub mod http_proxy;
 #[cfg(feature = "plugins")]
+pub mod inbound_user;
+#[cfg(feature = "tun")]
 pub mod ip_stack;
+#[cfg(feature = "plugins")]
+pub mod masque;
+#[cfg(feature = "plugins")]
+pub mod memory_watchdog;
+#[cfg(feature = "plugins")]
+pub mod mixed_listener;
+#[cfg(feature = "plugins")]
+pub mod mux;
 pub mod netif;
 #[cfg(feature = "plugins")]
 pub mod null;
 #[cfg(feature = "plugins")]
 pub mod obfs;
 #[cfg(feature = "plugins")]
+pub mod ping_prober;
+#[cfg(feature = "plugins")]
+pub mod quic;
+#[cfg(feature = "plugins")]
+pub mod quic_sniff;
+#[cfg(feature = "plugins")]
 pub mod redirect;
 #[cfg(feature = "plugins")]
 pub mod reject;
 #[cfg(feature = "plugins")]
 pub mod resolve_dest;
+#[cfg(feature = "plugins")]
+pub mod rotation;
 pub mod rule_dispatcher;
 pub mod shadowsocks;
 pub mod simple_dispatcher;
 #[cfg(feature = "plugins")]
 pub mod socket;
+pub mod socket_hook;
 #[cfg(feature = "plugins")]
 pub mod socks5;
 #[cfg(feature = "plugins")]
+pub mod stun_keepalive;
+#[cfg(feature = "plugins")]
 pub mod switch;
 #[cfg(feature = "plugins")]
 pub mod system_resolver;
 #[cfg(feature = "plugins")]
 pub mod tls;
+#[cfg(all(feature = "plugins", target_os = "linux"))]
+pub mod tproxy;
 #[cfg(feature = "plugins")]
 pub mod trojan;
+#[cfg(feature = "plugins")]
+pub mod udp_fallback;
+#[cfg(feature = "plugins")]
+pub mod url_test;
 pub mod vmess;
 #[cfg(feature = "plugins")]
 pub mod ws;
 
 #[cfg(feature = "plugins")]
-mod h2;
+pub(crate) mod h2;
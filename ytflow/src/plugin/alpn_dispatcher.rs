@@ -0,0 +1,205 @@
+use std::sync::{Arc, Weak};
+
+use super::guard::sniff_tls_alpn;
+use crate::flow::*;
+
+/// The largest TLS record allowed, excluding its header.
+const MAX_RECORD_LEN: usize = 16384;
+
+pub struct AlpnRule {
+    pub alpn: String,
+    pub next: Weak<dyn StreamHandler>,
+}
+
+/// Dispatch TLS streams by the application layer protocols offered in the
+/// ClientHello, e.g. `h2` or `http/1.1`. The first protocol in the order of
+/// client preference that has a rule wins. Streams that are not TLS or offer
+/// no matching protocol go to `fallback`.
+///
+/// Dispatching happens before TLS is terminated, so the protocols are the
+/// ones offered by the client rather than the one a TLS server would
+/// negotiate. Each handler is expected to terminate TLS with a `tls-server`
+/// of its own.
+#[derive(Clone)]
+pub struct AlpnDispatcher {
+    pub rules: Arc<[AlpnRule]>,
+    pub fallback: Weak<dyn StreamHandler>,
+}
+
+/// How many bytes must be buffered before sniffing: the whole first record if
+/// `buf` starts with a TLS handshake record, which may take several segments
+/// for large ClientHellos.
+fn sniff_len(buf: &[u8]) -> usize {
+    match buf {
+        [0x16, _, _, hi, lo, ..] => {
+            5 + (u16::from_be_bytes([*hi, *lo]) as usize).min(MAX_RECORD_LEN)
+        }
+        [] | [0x16, ..] => 5,
+        _ => 0,
+    }
+}
+
+impl AlpnDispatcher {
+    fn match_rule(&self, initial_data: &[u8]) -> &Weak<dyn StreamHandler> {
+        sniff_tls_alpn(initial_data)
+            .into_iter()
+            .find_map(|alpn| self.rules.iter().find(|r| r.alpn.as_bytes() == alpn))
+            .map(|r| &r.next)
+            .unwrap_or(&self.fallback)
+    }
+
+    fn dispatch(&self, lower: Box<dyn Stream>, initial_data: Buffer, context: Box<FlowContext>) {
+        let Some(next) = self.match_rule(&initial_data).upgrade() else {
+            return;
+        };
+        next.on_stream(lower, initial_data, context);
+    }
+}
+
+impl StreamHandler for AlpnDispatcher {
+    fn on_stream(
+        &self,
+        mut lower: Box<dyn Stream>,
+        initial_data: Buffer,
+        context: Box<FlowContext>,
+    ) {
+        if !initial_data.is_empty() && initial_data.len() >= sniff_len(&initial_data) {
+            return self.dispatch(lower, initial_data, context);
+        }
+        // Wait for the ClientHello, which is sent by the client right away.
+        let dispatcher = self.clone();
+        tokio::spawn(async move {
+            let mut reader = StreamReader::new(4096, initial_data);
+            let res = with_deadline(DEFAULT_HANDSHAKE_TIMEOUT, async {
+                let mut len = 1;
+                loop {
+                    let required = reader
+                        .peek_at_least(&mut *lower, len, |buf| sniff_len(buf))
+                        .await?;
+                    if required <= len {
+                        return Ok(());
+                    }
+                    len = required;
+                }
+            })
+            .await;
+            if res.is_ok() {
+                let initial_data = reader.into_buffer().unwrap_or_default();
+                dispatcher.dispatch(lower, initial_data, context);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::AsyncWriteExt;
+
+    use super::*;
+    use crate::flow::testing::*;
+
+    fn client_hello(alpn: &[&str]) -> Vec<u8> {
+        let list: Vec<u8> = alpn
+            .iter()
+            .flat_map(|p| std::iter::once(p.len() as u8).chain(p.bytes()))
+            .collect();
+        let mut exts = vec![0x00, 0x10];
+        exts.extend_from_slice(&(list.len() as u16 + 2).to_be_bytes());
+        exts.extend_from_slice(&(list.len() as u16).to_be_bytes());
+        exts.extend_from_slice(&list);
+        let mut body = vec![0x03, 0x03];
+        body.extend_from_slice(&[0; 32]);
+        body.push(0); // session id
+        body.extend_from_slice(&[0x00, 0x02, 0x13, 0x01, 0x01, 0x00]);
+        body.extend_from_slice(&(exts.len() as u16).to_be_bytes());
+        body.extend_from_slice(&exts);
+        let mut handshake = vec![0x01];
+        handshake.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        handshake.extend_from_slice(&body);
+        let mut record = vec![0x16, 0x03, 0x01];
+        record.extend_from_slice(&(handshake.len() as u16).to_be_bytes());
+        record.extend_from_slice(&handshake);
+        record
+    }
+
+    fn dispatcher(
+        aps: &mut AccessPoints,
+    ) -> (
+        AlpnDispatcher,
+        Arc<MockStreamHandler>,
+        Arc<MockStreamHandler>,
+    ) {
+        let h2 = MockStreamHandler::new();
+        let fallback = MockStreamHandler::new();
+        let dispatcher = AlpnDispatcher {
+            rules: vec![AlpnRule {
+                alpn: "h2".into(),
+                next: aps.hold(h2.clone()) as _,
+            }]
+            .into(),
+            fallback: aps.hold(fallback.clone()) as _,
+        };
+        (dispatcher, h2, fallback)
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_sniffed_client_hello() {
+        let mut aps = AccessPoints::new();
+        let (dispatcher, h2, fallback) = dispatcher(&mut aps);
+        let hello = client_hello(&["h2", "http/1.1"]);
+        let (stream, mut peer) = stream_pair();
+        dispatcher.on_stream(stream, vec![], context("example.com:443"));
+        peer.write_all(&hello).await.unwrap();
+
+        let accepted = h2.accept().await;
+        assert_eq!(accepted.initial_data, hello);
+        assert!(fallback.try_accept().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_client_hello_in_two_writes() {
+        let mut aps = AccessPoints::new();
+        let (dispatcher, h2, fallback) = dispatcher(&mut aps);
+        let hello = client_hello(&["h2", "http/1.1"]);
+        let (stream, mut peer) = stream_pair();
+        // The ALPN extension is at the end, and only arrives in the second write.
+        let (first, second) = hello.split_at(hello.len() / 2);
+        dispatcher.on_stream(stream, first.to_vec(), context("example.com:443"));
+        tokio::task::yield_now().await;
+        assert!(h2.try_accept().is_none());
+        assert!(fallback.try_accept().is_none());
+        peer.write_all(second).await.unwrap();
+
+        let accepted = h2.accept().await;
+        assert_eq!(accepted.initial_data, hello);
+        assert!(fallback.try_accept().is_none());
+    }
+
+    #[test]
+    fn test_sniff_len() {
+        assert_eq!(sniff_len(&[]), 5);
+        assert_eq!(sniff_len(&[0x16, 0x03]), 5);
+        assert_eq!(sniff_len(&[0x16, 0x03, 0x01, 0x07, 0x00]), 5 + 0x700);
+        assert_eq!(
+            sniff_len(&[0x16, 0x03, 0x01, 0xff, 0xff]),
+            5 + MAX_RECORD_LEN
+        );
+        assert_eq!(sniff_len(b"GET /"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_fallback() {
+        let mut aps = AccessPoints::new();
+        let (dispatcher, h2, fallback) = dispatcher(&mut aps);
+        let (stream, _peer) = stream_pair();
+        let hello = client_hello(&["http/1.1"]);
+        dispatcher.on_stream(stream, hello.clone(), context("example.com:443"));
+        assert_eq!(fallback.try_accept().unwrap().initial_data, hello);
+
+        let (stream, _peer) = stream_pair();
+        let request = b"GET / HTTP/1.1\r\n\r\n".to_vec();
+        dispatcher.on_stream(stream, request, context("example.com:80"));
+        assert!(fallback.try_accept().is_some());
+        assert!(h2.try_accept().is_none());
+    }
+}
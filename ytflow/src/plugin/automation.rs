@@ -0,0 +1,24 @@
+#[cfg(feature = "plugins")]
+mod action;
+#[cfg(feature = "plugins")]
+mod engine;
+#[cfg(feature = "plugins")]
+mod responder;
+
+#[cfg(feature = "plugins")]
+pub use action::{set_allowed_commands, Action, WebhookClient};
+#[cfg(feature = "plugins")]
+pub use engine::{Automation, Rule, RuleStatus};
+#[cfg(feature = "plugins")]
+pub use responder::Responder;
+
+/// Names of events that rules can be triggered by.
+pub const TRIGGERS: &[&str] = &[
+    "connected",
+    "outbound_unhealthy",
+    "outbound_healthy",
+    "subscription_updated",
+    "quota_threshold_reached",
+    "plugin_failed",
+    "memory_pressure",
+];
@@ -0,0 +1,212 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, RwLock, Weak};
+
+use http::header::{CONTENT_TYPE, USER_AGENT};
+use http::uri::Scheme;
+use http::{Request, Uri};
+use hyper::{Body, Client as HyperClient};
+
+use crate::flow::*;
+use crate::plugin::h2::{FlowAdapterConnector, TokioHyperExecutor};
+use crate::plugin::tls::SslStreamFactory;
+
+/// Command lines that automation rules may execute, as a program followed by
+/// its arguments. Set by the embedder from local settings, never from a
+/// profile, since profiles can be shared and imported.
+static ALLOWED_COMMANDS: RwLock<Vec<(PathBuf, Vec<String>)>> = RwLock::new(Vec::new());
+
+/// Replace the command lines that automation rules may execute. Empty by
+/// default, so that no command runs unless the user has allowed it on this
+/// device. A rule must match both the program and the arguments of an entry,
+/// so that an allowed program cannot be run with arbitrary arguments.
+pub fn set_allowed_commands(commands: Vec<(PathBuf, Vec<String>)>) {
+    *ALLOWED_COMMANDS.write().unwrap() = commands;
+}
+
+fn is_command_allowed(program: &Path, args: &[String]) -> bool {
+    ALLOWED_COMMANDS
+        .read()
+        .unwrap()
+        .iter()
+        .any(|(p, a)| p == program && a == args)
+}
+
+/// Escape `value` to be substituted into a request body of `content_type`.
+/// JSON bodies get the contents of a JSON string, and form bodies get a
+/// percent-encoded value. Other bodies are substituted verbatim.
+fn escape_for(content_type: &str, value: &str) -> String {
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    if mime == "application/json" || mime.ends_with("+json") {
+        let mut ret = String::with_capacity(value.len());
+        for c in value.chars() {
+            match c {
+                '"' => ret.push_str("\\\""),
+                '\\' => ret.push_str("\\\\"),
+                '\n' => ret.push_str("\\n"),
+                '\r' => ret.push_str("\\r"),
+                '\t' => ret.push_str("\\t"),
+                c if (c as u32) < 0x20 => ret.push_str(&format!("\\u{:04x}", c as u32)),
+                c => ret.push(c),
+            }
+        }
+        ret
+    } else if mime == "application/x-www-form-urlencoded" {
+        let mut ret = String::with_capacity(value.len());
+        for b in value.bytes() {
+            match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'*' => {
+                    ret.push(b as char)
+                }
+                b' ' => ret.push('+'),
+                b => ret.push_str(&format!("%{:02X}", b)),
+            }
+        }
+        ret
+    } else {
+        value.into()
+    }
+}
+
+pub enum Action {
+    /// POST `body` to `url`, where `{event}` and `{message}` are replaced with
+    /// the name and a description of the event, escaped for `content_type`.
+    Webhook {
+        url: Uri,
+        body: String,
+        content_type: String,
+    },
+    /// Run `program` with `args`. The name and description of the event are
+    /// passed in the environment variables `YTFLOW_EVENT` and
+    /// `YTFLOW_MESSAGE`.
+    Command { program: PathBuf, args: Vec<String> },
+}
+
+/// Sends webhook requests through the outbound of the automation plugin. HTTPS
+/// requests are wrapped in TLS on top of it.
+pub struct WebhookClient {
+    plain_client: HyperClient<FlowAdapterConnector, Body>,
+    tls_client: HyperClient<FlowAdapterConnector, Body>,
+    _tls: Arc<dyn StreamOutboundFactory>,
+}
+
+impl WebhookClient {
+    pub fn new(next: Weak<dyn StreamOutboundFactory>) -> Self {
+        let tls: Arc<dyn StreamOutboundFactory> = Arc::new(SslStreamFactory::new(
+            next.clone(),
+            vec![],
+            false,
+            None,
+            DEFAULT_HANDSHAKE_TIMEOUT,
+        ));
+        let build_client = |next: Weak<dyn StreamOutboundFactory>| {
+            HyperClient::builder()
+                .executor(TokioHyperExecutor::new_current())
+                .build(FlowAdapterConnector { next })
+        };
+        Self {
+            plain_client: build_client(next),
+            tls_client: build_client(Arc::downgrade(&tls)),
+            _tls: tls,
+        }
+    }
+}
+
+impl Action {
+    pub(super) async fn execute(
+        &self,
+        client: &WebhookClient,
+        event: &str,
+        message: &str,
+    ) -> Result<(), String> {
+        match self {
+            Action::Webhook {
+                url,
+                body,
+                content_type,
+            } => {
+                let client = match url.scheme() {
+                    Some(s) if *s == Scheme::HTTPS => &client.tls_client,
+                    _ => &client.plain_client,
+                };
+                let body = body
+                    .replace("{event}", &escape_for(content_type, event))
+                    .replace("{message}", &escape_for(content_type, message));
+                let req = Request::post(url.clone())
+                    .header(USER_AGENT, concat!("ytflow/", env!("CARGO_PKG_VERSION")))
+                    .header(CONTENT_TYPE, content_type.as_str())
+                    .body(Body::from(body))
+                    .map_err(|e| e.to_string())?;
+                let res = client.request(req).await.map_err(|e| e.to_string())?;
+                if !res.status().is_success() {
+                    return Err(format!("unexpected HTTP status {}", res.status().as_u16()));
+                }
+                Ok(())
+            }
+            Action::Command { program, args } => {
+                if !is_command_allowed(program, args) {
+                    return Err(format!("{} is not an allowed command", program.display()));
+                }
+                let mut command = Command::new(program);
+                command
+                    .args(args)
+                    .env("YTFLOW_EVENT", event)
+                    .env("YTFLOW_MESSAGE", message)
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null());
+                let status = tokio::task::spawn_blocking(move || command.status())
+                    .await
+                    .map_err(|e| e.to_string())?
+                    .map_err(|e| e.to_string())?;
+                if !status.success() {
+                    return Err(format!("command exited with {}", status));
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_for() {
+        let message = "Outbound \"a\\b\"\nis unhealthy & 100%";
+        assert_eq!(
+            escape_for("application/json", message),
+            "Outbound \\\"a\\\\b\\\"\\nis unhealthy & 100%"
+        );
+        assert_eq!(
+            escape_for("Application/JSON; charset=utf-8", "\u{1}"),
+            "\\u0001"
+        );
+        assert_eq!(
+            escape_for("application/x-www-form-urlencoded", message),
+            "Outbound+%22a%5Cb%22%0Ais+unhealthy+%26+100%25"
+        );
+        assert_eq!(escape_for("text/plain; charset=utf-8", message), message);
+    }
+
+    #[test]
+    fn test_command_allowed_with_exact_args() {
+        set_allowed_commands(vec![("/usr/bin/notify".into(), vec!["--urgent".into()])]);
+        assert!(is_command_allowed(
+            Path::new("/usr/bin/notify"),
+            &["--urgent".into()]
+        ));
+        assert!(!is_command_allowed(Path::new("/usr/bin/notify"), &[]));
+        assert!(!is_command_allowed(
+            Path::new("/usr/bin/notify"),
+            &["--urgent".into(), "--exec".into()]
+        ));
+        set_allowed_commands(vec![]);
+    }
+}
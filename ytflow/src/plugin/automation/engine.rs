@@ -0,0 +1,234 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+use super::action::{Action, WebhookClient};
+use crate::control::events::{Event, EventSubscription};
+
+pub struct Rule {
+    pub on: Vec<String>,
+    pub action: Action,
+    /// Events arriving within this period after the rule was last triggered
+    /// are ignored, so that a flapping outbound does not flood the receiver.
+    pub cooldown: Duration,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct RuleStatus {
+    pub triggered: u32,
+    pub failed: u32,
+    /// Unix timestamp in seconds of the last time the rule was triggered.
+    pub last_triggered: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+struct RuleState {
+    status: RuleStatus,
+    last_triggered_at: Option<Instant>,
+}
+
+/// Runs the actions of rules in response to core events.
+pub struct Automation {
+    rules: Vec<Rule>,
+    client: WebhookClient,
+    states: Mutex<Vec<RuleState>>,
+}
+
+fn trigger_of(event: &Event) -> &'static str {
+    match event {
+        Event::OutboundHealthChanged { healthy: false, .. } => "outbound_unhealthy",
+        Event::OutboundHealthChanged { healthy: true, .. } => "outbound_healthy",
+        e => e.kind(),
+    }
+}
+
+fn describe(event: &Event) -> String {
+    match event {
+        Event::Connected { errors: 0 } => "Connected".into(),
+        Event::Connected { errors } => format!("Connected with {} errors", errors),
+        Event::OutboundHealthChanged {
+            plugin,
+            healthy: false,
+        } => format!("Outbound {} is unhealthy", plugin),
+        Event::OutboundHealthChanged {
+            plugin,
+            healthy: true,
+        } => format!("Outbound {} has recovered", plugin),
+        Event::SubscriptionUpdated { proxy_group_id } => {
+            format!("Subscription of proxy group {} updated", proxy_group_id)
+        }
+        Event::QuotaThresholdReached {
+            quota_id,
+            bytes_used,
+            bytes_limit,
+            exceeded,
+        } => format!(
+            "Traffic quota {} {}: {} of {} bytes used",
+            quota_id,
+            if *exceeded {
+                "exceeded"
+            } else {
+                "is running out"
+            },
+            bytes_used,
+            bytes_limit
+        ),
+        Event::PluginFailed { plugin, error } => {
+            format!("Plugin {} failed to load: {}", plugin, error)
+        }
+        Event::MemoryPressure {
+            usage, threshold, ..
+        } => format!("Memory usage {} bytes is above {} bytes", usage, threshold),
+        e => e.kind().into(),
+    }
+}
+
+impl Automation {
+    pub fn new(rules: Vec<Rule>, client: WebhookClient) -> Self {
+        let states = rules
+            .iter()
+            .map(|_| RuleState {
+                status: RuleStatus::default(),
+                last_triggered_at: None,
+            })
+            .collect();
+        Self {
+            rules,
+            client,
+            states: Mutex::new(states),
+        }
+    }
+
+    pub fn statuses(&self) -> Vec<RuleStatus> {
+        let states = self.states.lock().unwrap();
+        states.iter().map(|s| s.status.clone()).collect()
+    }
+
+    /// Indices of rules to be triggered by `event` at `now`, whose cooldown
+    /// starts over.
+    fn take_due_rules(&self, event: &Event, now: Instant) -> Vec<usize> {
+        let trigger = trigger_of(event);
+        let unix_now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .ok();
+        let mut states = self.states.lock().unwrap();
+        self.rules
+            .iter()
+            .zip(states.iter_mut())
+            .enumerate()
+            .filter(|(_, (rule, state))| {
+                rule.on.iter().any(|t| t == trigger)
+                    && !state
+                        .last_triggered_at
+                        .is_some_and(|t| now.saturating_duration_since(t) < rule.cooldown)
+            })
+            .map(|(idx, (_, state))| {
+                state.last_triggered_at = Some(now);
+                state.status.triggered = state.status.triggered.saturating_add(1);
+                state.status.last_triggered = unix_now;
+                idx
+            })
+            .collect()
+    }
+
+    async fn execute(self: Arc<Self>, idx: usize, trigger: &'static str, message: String) {
+        let res = self.rules[idx]
+            .action
+            .execute(&self.client, trigger, &message)
+            .await;
+        let mut states = self.states.lock().unwrap();
+        let status = &mut states[idx].status;
+        match res {
+            Ok(()) => status.last_error = None,
+            Err(e) => {
+                status.failed = status.failed.saturating_add(1);
+                status.last_error = Some(e);
+            }
+        }
+    }
+
+    pub async fn run(self: Arc<Self>, mut subscription: EventSubscription) {
+        while let Some(record) = subscription.recv().await {
+            let event = record.event;
+            let due = self.take_due_rules(&event, Instant::now());
+            if due.is_empty() {
+                continue;
+            }
+            let trigger = trigger_of(&event);
+            let message = describe(&event);
+            for idx in due {
+                tokio::spawn(self.clone().execute(idx, trigger, message.clone()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flow::*;
+    use crate::plugin::null::Null;
+
+    fn rule(on: &[&str], cooldown: Duration) -> Rule {
+        Rule {
+            on: on.iter().map(|s| s.to_string()).collect(),
+            action: Action::Command {
+                program: "true".into(),
+                args: vec![],
+            },
+            cooldown,
+        }
+    }
+
+    fn health_changed(healthy: bool) -> Event {
+        Event::OutboundHealthChanged {
+            plugin: "p".into(),
+            healthy,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_take_due_rules() {
+        let null: Arc<dyn StreamOutboundFactory> = Arc::new(Null);
+        let automation = Automation::new(
+            vec![
+                rule(&["outbound_unhealthy"], Duration::from_secs(60)),
+                rule(&["outbound_healthy", "connected"], Duration::ZERO),
+            ],
+            WebhookClient::new(Arc::downgrade(&null)),
+        );
+        let now = Instant::now();
+        assert_eq!(automation.take_due_rules(&health_changed(false), now), [0]);
+        assert!(automation
+            .take_due_rules(&health_changed(false), now + Duration::from_secs(30))
+            .is_empty());
+        assert_eq!(
+            automation.take_due_rules(&health_changed(false), now + Duration::from_secs(60)),
+            [0]
+        );
+        assert_eq!(automation.take_due_rules(&health_changed(true), now), [1]);
+        assert_eq!(
+            automation.take_due_rules(&Event::Connected { errors: 0 }, now),
+            [1]
+        );
+        assert_eq!(automation.statuses()[0].triggered, 2);
+    }
+
+    #[tokio::test]
+    async fn test_command_not_allowed() {
+        let null: Arc<dyn StreamOutboundFactory> = Arc::new(Null);
+        let automation = Arc::new(Automation::new(
+            vec![rule(&["connected"], Duration::ZERO)],
+            WebhookClient::new(Arc::downgrade(&null)),
+        ));
+        automation
+            .clone()
+            .execute(0, "connected", "Connected".into())
+            .await;
+        let status = &automation.statuses()[0];
+        assert_eq!(status.failed, 1);
+        assert!(status.last_error.is_some());
+    }
+}
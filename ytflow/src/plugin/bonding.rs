@@ -0,0 +1,4 @@
+mod dedup;
+mod outbound;
+
+pub use outbound::{BondingFactory, BondingMode};
@@ -0,0 +1,115 @@
+use crate::flow::*;
+
+/// Every datagram sent through bonding is prefixed by a big-endian sequence
+/// number, so that copies arriving through different paths can be told apart
+/// from datagrams that merely have the same content. The remote end strips
+/// the prefix and uses the same framing for its replies.
+pub(super) const SEQ_LEN: usize = 8;
+
+/// Sequence numbers this far behind the highest one received are considered
+/// stale and dropped.
+const DEDUP_WINDOW: u64 = 4096;
+
+pub(super) fn frame(seq: u64, buf: &[u8]) -> Buffer {
+    let mut framed = Vec::with_capacity(SEQ_LEN + buf.len());
+    framed.extend_from_slice(&seq.to_be_bytes());
+    framed.extend_from_slice(buf);
+    framed
+}
+
+pub(super) fn unframe(mut buf: Buffer) -> Option<(u64, Buffer)> {
+    let seq = u64::from_be_bytes(buf.get(..SEQ_LEN)?.try_into().unwrap());
+    buf.drain(..SEQ_LEN);
+    Some((seq, buf))
+}
+
+/// A sliding window over received sequence numbers, as used for replay
+/// protection in IPsec (RFC 4303).
+pub(super) struct Dedup {
+    top: Option<u64>,
+    seen: Box<[u64; (DEDUP_WINDOW / 64) as usize]>,
+}
+
+impl Dedup {
+    pub(super) fn new() -> Self {
+        Self {
+            top: None,
+            seen: Box::new([0; (DEDUP_WINDOW / 64) as usize]),
+        }
+    }
+
+    fn bit(seq: u64) -> (usize, u64) {
+        let idx = seq % DEDUP_WINDOW;
+        ((idx / 64) as usize, 1 << (idx % 64))
+    }
+
+    /// Returns `true` if `seq` has not been seen and is not stale.
+    pub(super) fn insert(&mut self, seq: u64) -> bool {
+        let top = self.top.unwrap_or(seq);
+        if seq > top {
+            if seq - top >= DEDUP_WINDOW {
+                self.seen.fill(0);
+            } else {
+                // Forget what occupied the slots before the window moved.
+                for slot in top + 1..=seq {
+                    let (word, mask) = Self::bit(slot);
+                    self.seen[word] &= !mask;
+                }
+            }
+            self.top = Some(seq);
+        } else if top - seq >= DEDUP_WINDOW {
+            return false;
+        } else if self.top.is_none() {
+            self.top = Some(seq);
+        }
+        let (word, mask) = Self::bit(seq);
+        let fresh = self.seen[word] & mask == 0;
+        self.seen[word] |= mask;
+        fresh
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dedup_drops_copies() {
+        let mut d = Dedup::new();
+        assert!(d.insert(1));
+        assert!(!d.insert(1));
+        assert!(d.insert(3));
+        // Reordered, but not a copy
+        assert!(d.insert(2));
+        assert!(!d.insert(2));
+        assert!(!d.insert(3));
+    }
+
+    #[test]
+    fn test_dedup_drops_stale() {
+        let mut d = Dedup::new();
+        assert!(d.insert(10));
+        assert!(d.insert(10 + DEDUP_WINDOW));
+        assert!(!d.insert(10));
+        assert!(d.insert(11));
+        assert!(!d.insert(11));
+    }
+
+    #[test]
+    fn test_dedup_window_reuse() {
+        let mut d = Dedup::new();
+        assert!(d.insert(0));
+        // Same slot as 0, but a new datagram
+        assert!(d.insert(DEDUP_WINDOW - 1));
+        assert!(d.insert(DEDUP_WINDOW));
+        assert!(!d.insert(DEDUP_WINDOW));
+    }
+
+    #[test]
+    fn test_frame_roundtrip() {
+        let framed = frame(0x0102, b"abc");
+        assert_eq!(framed.len(), SEQ_LEN + 3);
+        assert_eq!(unframe(framed), Some((0x0102, b"abc".to_vec())));
+        assert_eq!(unframe(vec![1, 2, 3]), None);
+    }
+}
@@ -0,0 +1,207 @@
+use std::sync::Weak;
+use std::task::{Context, Poll};
+
+use async_trait::async_trait;
+use futures::future::join_all;
+
+use super::dedup::{frame, unframe, Dedup};
+use crate::flow::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BondingMode {
+    /// Send every datagram through all paths, trading bandwidth for
+    /// reliability and latency.
+    Duplicate,
+    /// Send datagrams through paths in turn.
+    Stripe,
+}
+
+pub struct BondingFactory {
+    pub mode: BondingMode,
+    pub nexts: Vec<Weak<dyn DatagramSessionFactory>>,
+}
+
+#[async_trait]
+impl DatagramSessionFactory for BondingFactory {
+    async fn bind(&self, context: Box<FlowContext>) -> FlowResult<Box<dyn DatagramSession>> {
+        let results = join_all(self.nexts.iter().filter_map(Weak::upgrade).map(|next| {
+            let context = Box::new(context.fork());
+            async move { next.bind(context).await }
+        }))
+        .await;
+        let mut last_err = FlowError::NoOutbound;
+        let mut paths = Vec::with_capacity(results.len());
+        for res in results {
+            match res {
+                Ok(session) => paths.push(Path {
+                    session,
+                    send_ready: false,
+                    closed: false,
+                    shut_down: false,
+                }),
+                // Bond the remaining paths as long as one of them works.
+                Err(e) => last_err = e,
+            }
+        }
+        if paths.is_empty() {
+            return Err(last_err);
+        }
+        Ok(Box::new(BondingSession {
+            mode: self.mode,
+            paths,
+            next_send: 0,
+            next_recv: 0,
+            next_seq: 0,
+            dedup: Dedup::new(),
+        }))
+    }
+}
+
+struct Path {
+    session: Box<dyn DatagramSession>,
+    send_ready: bool,
+    closed: bool,
+    shut_down: bool,
+}
+
+struct BondingSession {
+    mode: BondingMode,
+    paths: Vec<Path>,
+    next_send: usize,
+    next_recv: usize,
+    next_seq: u64,
+    dedup: Dedup,
+}
+
+impl DatagramSession for BondingSession {
+    fn poll_recv_from(&mut self, cx: &mut Context) -> Poll<Option<(DestinationAddr, Buffer)>> {
+        let len = self.paths.len();
+        // Start from a different path each time so that a busy path cannot
+        // starve the others.
+        for i in 0..len {
+            let idx = (self.next_recv + i) % len;
+            let path = &mut self.paths[idx];
+            while !path.closed {
+                match path.session.poll_recv_from(cx) {
+                    Poll::Ready(Some((from, buf))) => {
+                        // Datagrams without a sequence number are dropped.
+                        let Some((seq, buf)) = unframe(buf) else {
+                            continue;
+                        };
+                        if self.dedup.insert(seq) {
+                            self.next_recv = (idx + 1) % len;
+                            return Poll::Ready(Some((from, buf)));
+                        }
+                    }
+                    Poll::Ready(None) => path.closed = true,
+                    Poll::Pending => break,
+                }
+            }
+        }
+        if self.paths.iter().all(|p| p.closed) {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+
+    fn poll_send_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut any_ready = false;
+        for path in self.paths.iter_mut().filter(|p| !p.closed) {
+            if !path.send_ready {
+                path.send_ready = path.session.poll_send_ready(cx).is_ready();
+            }
+            any_ready |= path.send_ready;
+        }
+        if any_ready {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+
+    fn send_to(&mut self, remote_peer: DestinationAddr, buf: Buffer) {
+        let len = self.paths.len();
+        let buf = frame(self.next_seq, &buf);
+        self.next_seq += 1;
+        match self.mode {
+            BondingMode::Duplicate => {
+                for path in self.paths.iter_mut().filter(|p| p.send_ready && !p.closed) {
+                    path.send_ready = false;
+                    path.session.send_to(remote_peer.clone(), buf.clone());
+                }
+            }
+            BondingMode::Stripe => {
+                let Some(idx) = (0..len)
+                    .map(|i| (self.next_send + i) % len)
+                    .find(|&idx| self.paths[idx].send_ready && !self.paths[idx].closed)
+                else {
+                    return;
+                };
+                let path = &mut self.paths[idx];
+                path.send_ready = false;
+                path.session.send_to(remote_peer, buf);
+                self.next_send = (idx + 1) % len;
+            }
+        }
+    }
+
+    fn poll_shutdown(&mut self, cx: &mut Context<'_>) -> Poll<FlowResult<()>> {
+        let mut all_done = true;
+        for path in self.paths.iter_mut().filter(|p| !p.shut_down) {
+            // Errors of individual paths are ignored, the session is going
+            // away anyway.
+            path.shut_down = path.session.poll_shutdown(cx).is_ready();
+            all_done &= path.shut_down;
+        }
+        if all_done {
+            Poll::Ready(Ok(()))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use futures::future::poll_fn;
+
+    use super::*;
+    use crate::flow::testing::*;
+
+    #[tokio::test]
+    async fn test_duplicate_mode_frames_and_dedups() {
+        let (next, peers) = MockDatagramSessionFactory::queued();
+        let next: Arc<dyn DatagramSessionFactory> = next;
+        let factory = BondingFactory {
+            mode: BondingMode::Duplicate,
+            nexts: vec![Arc::downgrade(&next), Arc::downgrade(&next)],
+        };
+        let mut session = factory.bind(context("192.0.2.1:53")).await.unwrap();
+        let (a, _) = peers.recv_async().await.unwrap();
+        let (b, _) = peers.recv_async().await.unwrap();
+
+        poll_fn(|cx| session.poll_send_ready(cx)).await;
+        session.send_to(dest("192.0.2.1:53"), b"ping".to_vec());
+        for peer in [&a, &b] {
+            let (_, buf) = peer.recv_from().await.unwrap();
+            assert_eq!(buf, frame(0, b"ping"));
+        }
+
+        // The same reply through both paths, then a different one with the
+        // same content.
+        a.send_to(dest("192.0.2.1:53"), frame(7, b"pong"));
+        b.send_to(dest("192.0.2.1:53"), frame(7, b"pong"));
+        b.send_to(dest("192.0.2.1:53"), frame(8, b"pong"));
+        a.send_to(dest("192.0.2.1:53"), b"bare".to_vec());
+        a.send_to(dest("192.0.2.1:53"), frame(9, b"end"));
+        let mut received = vec![];
+        while received.last().map(|b: &Buffer| &b[..]) != Some(&b"end"[..]) {
+            let (_, buf) = poll_fn(|cx| session.poll_recv_from(cx)).await.unwrap();
+            received.push(buf);
+        }
+        assert_eq!(received, [&b"pong"[..], b"pong", b"end"]);
+    }
+}
@@ -0,0 +1,7 @@
+mod breaker;
+mod outbound;
+mod responder;
+
+pub use breaker::Breaker;
+pub use outbound::{DatagramCircuitBreakerFactory, StreamCircuitBreakerFactory};
+pub use responder::Responder;
@@ -0,0 +1,192 @@
+use std::io;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::atomic::AtomicU64;
+use crate::flow::{FlowError, FlowResult};
+
+/// Whether `e` comes from the flow being aborted, e.g. the client went away,
+/// which says nothing about the upstream.
+fn is_aborted(e: &FlowError) -> bool {
+    matches!(e, FlowError::Io(e) if e.kind() == io::ErrorKind::Interrupted)
+}
+
+pub struct Breaker {
+    failure_threshold: u32,
+    cooldown: Duration,
+    consecutive_failures: AtomicU32,
+    /// `Some` when the circuit is open (or a half-open probe is in flight),
+    /// holding the instant after which the next attempt may pass through.
+    open_until: Mutex<Option<Instant>>,
+    pub(super) trip_count: AtomicU32,
+    pub(super) short_circuited: AtomicU64,
+}
+
+impl Breaker {
+    pub fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+            consecutive_failures: AtomicU32::new(0),
+            open_until: Mutex::new(None),
+            trip_count: AtomicU32::new(0),
+            short_circuited: AtomicU64::new(0),
+        }
+    }
+
+    /// Check whether a new dial attempt is allowed. When the cool-down period
+    /// has elapsed, exactly one attempt is let through as a probe while the
+    /// others keep being rejected.
+    pub(super) fn try_acquire(&self) -> FlowResult<()> {
+        let mut open_until = self.open_until.lock().unwrap();
+        let Some(until) = *open_until else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        if now < until {
+            drop(open_until);
+            self.short_circuited.fetch_add(1, Ordering::Relaxed);
+            return Err(FlowError::NoOutbound);
+        }
+        *open_until = Some(now + self.cooldown);
+        Ok(())
+    }
+
+    pub(super) fn on_success(&self) {
+        self.consecutive_failures.store(0, Ordering::Relaxed);
+        *self.open_until.lock().unwrap() = None;
+    }
+
+    pub(super) fn on_failure(&self) {
+        let failures = self
+            .consecutive_failures
+            .fetch_add(1, Ordering::Relaxed)
+            .saturating_add(1);
+        if failures < self.failure_threshold {
+            return;
+        }
+        let was_open = self
+            .open_until
+            .lock()
+            .unwrap()
+            .replace(Instant::now() + self.cooldown)
+            .is_some();
+        if !was_open {
+            self.trip_count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub(super) fn record<T>(&self, res: FlowResult<T>) -> FlowResult<T> {
+        match &res {
+            Ok(_) => self.on_success(),
+            Err(e) if is_aborted(e) => {}
+            Err(_) => self.on_failure(),
+        }
+        res
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open_until
+            .lock()
+            .unwrap()
+            .map_or(false, |until| Instant::now() < until)
+    }
+
+    pub fn consecutive_failures(&self) -> u32 {
+        self.consecutive_failures.load(Ordering::Relaxed)
+    }
+
+    /// Close the circuit immediately, regardless of the current state.
+    pub fn reset(&self) {
+        self.on_success();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COOLDOWN: Duration = Duration::from_millis(50);
+
+    fn refused() -> FlowResult<()> {
+        Err(io::Error::from(io::ErrorKind::ConnectionRefused).into())
+    }
+
+    fn fail(breaker: &Breaker, times: u32) {
+        for _ in 0..times {
+            breaker.try_acquire().unwrap();
+            let _ = breaker.record(refused());
+        }
+    }
+
+    #[test]
+    fn test_trips_after_consecutive_failures() {
+        let breaker = Breaker::new(3, COOLDOWN);
+        fail(&breaker, 2);
+        assert!(!breaker.is_open());
+        assert_eq!(breaker.consecutive_failures(), 2);
+        fail(&breaker, 1);
+        assert!(breaker.is_open());
+        assert_eq!(breaker.trip_count.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_rejects_during_cooldown() {
+        let breaker = Breaker::new(1, Duration::from_secs(60));
+        fail(&breaker, 1);
+        assert!(matches!(breaker.try_acquire(), Err(FlowError::NoOutbound)));
+        assert!(matches!(breaker.try_acquire(), Err(FlowError::NoOutbound)));
+        assert_eq!(breaker.short_circuited.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_half_open_probe_after_cooldown() {
+        let breaker = Breaker::new(1, COOLDOWN);
+        fail(&breaker, 1);
+        std::thread::sleep(COOLDOWN * 2);
+        // Only one probe passes while it is in flight.
+        breaker.try_acquire().unwrap();
+        assert!(breaker.try_acquire().is_err());
+        // A failed probe opens the circuit again without counting a new trip.
+        let _ = breaker.record(refused());
+        assert!(breaker.is_open());
+        assert_eq!(breaker.trip_count.load(Ordering::Relaxed), 1);
+
+        std::thread::sleep(COOLDOWN * 2);
+        breaker.try_acquire().unwrap();
+        breaker.record(Ok(())).unwrap();
+        assert!(!breaker.is_open());
+        breaker.try_acquire().unwrap();
+    }
+
+    #[test]
+    fn test_success_resets_failures() {
+        let breaker = Breaker::new(3, COOLDOWN);
+        fail(&breaker, 2);
+        breaker.record(Ok(())).unwrap();
+        assert_eq!(breaker.consecutive_failures(), 0);
+        fail(&breaker, 2);
+        assert!(!breaker.is_open());
+    }
+
+    #[test]
+    fn test_aborted_not_counted() {
+        let breaker = Breaker::new(1, COOLDOWN);
+        let aborted: FlowResult<()> = Err(io::Error::from(io::ErrorKind::Interrupted).into());
+        assert!(breaker.record(aborted).is_err());
+        assert_eq!(breaker.consecutive_failures(), 0);
+        assert!(!breaker.is_open());
+    }
+
+    #[test]
+    fn test_reset() {
+        let breaker = Breaker::new(1, Duration::from_secs(60));
+        fail(&breaker, 1);
+        assert!(breaker.is_open());
+        breaker.reset();
+        assert!(!breaker.is_open());
+        assert_eq!(breaker.consecutive_failures(), 0);
+        breaker.try_acquire().unwrap();
+    }
+}
@@ -0,0 +1,39 @@
+use std::sync::{Arc, Weak};
+
+use async_trait::async_trait;
+
+use super::Breaker;
+use crate::flow::*;
+
+pub struct StreamCircuitBreakerFactory {
+    pub breaker: Arc<Breaker>,
+    pub next: Weak<dyn StreamOutboundFactory>,
+}
+
+pub struct DatagramCircuitBreakerFactory {
+    pub breaker: Arc<Breaker>,
+    pub next: Weak<dyn DatagramSessionFactory>,
+}
+
+#[async_trait]
+impl StreamOutboundFactory for StreamCircuitBreakerFactory {
+    async fn create_outbound(
+        &self,
+        context: &mut FlowContext,
+        initial_data: &'_ [u8],
+    ) -> FlowResult<(Box<dyn Stream>, Buffer)> {
+        let next = self.next.upgrade().ok_or(FlowError::NoOutbound)?;
+        self.breaker.try_acquire()?;
+        self.breaker
+            .record(next.create_outbound(context, initial_data).await)
+    }
+}
+
+#[async_trait]
+impl DatagramSessionFactory for DatagramCircuitBreakerFactory {
+    async fn bind(&self, context: Box<FlowContext>) -> FlowResult<Box<dyn DatagramSession>> {
+        let next = self.next.upgrade().ok_or(FlowError::NoOutbound)?;
+        self.breaker.try_acquire()?;
+        self.breaker.record(next.bind(context).await)
+    }
+}
@@ -0,0 +1,70 @@
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+
+use cbor4ii::serde::to_vec;
+use serde::Serialize;
+
+use super::Breaker;
+use crate::control::{PluginRequestError, PluginRequestResult, PluginResponder};
+
+#[derive(Clone, Default, Serialize, PartialEq, Eq)]
+struct Info {
+    open: bool,
+    consecutive_failures: u32,
+    trip_count: u32,
+    short_circuited: u64,
+}
+
+pub struct Responder {
+    breaker: Arc<Breaker>,
+    last_info: Mutex<(Info, u32)>,
+}
+
+impl Responder {
+    pub fn new(breaker: Arc<Breaker>) -> Self {
+        Self {
+            breaker,
+            last_info: Mutex::new((Info::default(), 1)),
+        }
+    }
+}
+
+fn info_snapshot(breaker: &Breaker) -> Info {
+    Info {
+        open: breaker.is_open(),
+        consecutive_failures: breaker.consecutive_failures(),
+        trip_count: breaker.trip_count.load(Ordering::Relaxed),
+        short_circuited: breaker.short_circuited.load(Ordering::Relaxed),
+    }
+}
+
+impl PluginResponder for Responder {
+    fn collect_info(&self, hashcode: &mut u32) -> Option<Vec<u8>> {
+        let info = {
+            let mut last_info_guard = self.last_info.lock().unwrap();
+            let (last_info, last_hashcode) = &mut *last_info_guard;
+            let new_info = info_snapshot(&self.breaker);
+            if new_info == *last_info {
+                if *last_hashcode == *hashcode {
+                    return None;
+                }
+            } else {
+                *last_info = new_info.clone();
+                *last_hashcode = (*last_hashcode).wrapping_add(1);
+            }
+            *hashcode = *last_hashcode;
+            new_info
+        };
+        Some(to_vec(vec![], &info).unwrap())
+    }
+
+    fn on_request(&self, func: &str, _params: &[u8]) -> PluginRequestResult<Vec<u8>> {
+        match func {
+            "reset" => {
+                self.breaker.reset();
+                Ok(to_vec(vec![], &()).unwrap())
+            }
+            _ => Err(PluginRequestError::NoSuchFunc),
+        }
+    }
+}
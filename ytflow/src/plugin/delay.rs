@@ -0,0 +1,7 @@
+mod impairment;
+mod outbound;
+mod responder;
+
+pub use impairment::{Impairment, Impairments};
+pub use outbound::{DatagramDelayFactory, StreamDelayFactory};
+pub use responder::Responder;
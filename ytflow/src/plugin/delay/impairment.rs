@@ -0,0 +1,168 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+/// Network conditions applied to the traffic passing through a delay plugin.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Impairment {
+    /// Extra latency in milliseconds.
+    #[serde(default)]
+    pub latency: u32,
+    /// Maximum random latency in milliseconds added on top of `latency`.
+    #[serde(default)]
+    pub jitter: u32,
+    /// Probability of dropping a datagram or failing a connection attempt,
+    /// ranging from 0 to 1.
+    #[serde(default)]
+    pub loss: f32,
+    /// Bandwidth cap in bytes per second in each direction. `0` means unlimited.
+    #[serde(default)]
+    pub bandwidth: u64,
+}
+
+impl Impairment {
+    pub fn normalized(mut self) -> Self {
+        self.loss = if self.loss.is_nan() {
+            0.
+        } else {
+            self.loss.clamp(0., 1.)
+        };
+        self
+    }
+
+    pub(super) fn sample_delay(&self) -> Duration {
+        let jitter = match self.jitter {
+            0 => 0,
+            j => rand::thread_rng().gen_range(0..=j),
+        };
+        Duration::from_millis(self.latency as u64 + jitter as u64)
+    }
+
+    pub(super) fn should_drop(&self) -> bool {
+        self.loss > 0. && rand::thread_rng().gen::<f32>() < self.loss
+    }
+
+    /// Time needed to transfer `len` bytes under the bandwidth cap.
+    pub(super) fn transfer_time(&self, len: usize) -> Duration {
+        match self.bandwidth {
+            0 => Duration::ZERO,
+            bw => Duration::from_secs_f64(len as f64 / bw as f64),
+        }
+    }
+}
+
+/// The static impairment from the plugin config, optionally overridden by a
+/// temporary one injected via RPC.
+pub struct Impairments {
+    base: Impairment,
+    injected: Mutex<Option<(Impairment, Instant)>>,
+}
+
+impl Impairments {
+    pub fn new(base: Impairment) -> Self {
+        Self {
+            base: base.normalized(),
+            injected: Mutex::new(None),
+        }
+    }
+
+    pub fn base(&self) -> Impairment {
+        self.base
+    }
+
+    /// The injected impairment and its remaining duration, if not expired yet.
+    pub fn injected(&self) -> Option<(Impairment, Duration)> {
+        let mut guard = self.injected.lock().unwrap();
+        let (impairment, expires_at) = (*guard)?;
+        let now = Instant::now();
+        if now >= expires_at {
+            *guard = None;
+            return None;
+        }
+        Some((impairment, expires_at - now))
+    }
+
+    pub fn current(&self) -> Impairment {
+        self.injected().map_or(self.base, |(i, _)| i)
+    }
+
+    /// Override the base impairment for `duration`. Any previously injected
+    /// impairment is replaced.
+    pub fn inject(&self, impairment: Impairment, duration: Duration) {
+        *self.injected.lock().unwrap() = Some((impairment.normalized(), Instant::now() + duration));
+    }
+
+    /// Remove the injected impairment. Returns whether one was active.
+    pub fn clear(&self) -> bool {
+        let was_active = self.injected().is_some();
+        *self.injected.lock().unwrap() = None;
+        was_active
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOSSY: Impairment = Impairment {
+        latency: 100,
+        jitter: 0,
+        loss: 0.5,
+        bandwidth: 0,
+    };
+
+    #[test]
+    fn test_normalized_loss() {
+        let loss = |loss| Impairment { loss, ..LOSSY }.normalized().loss;
+        assert_eq!(loss(-1.), 0.);
+        assert_eq!(loss(2.), 1.);
+        assert_eq!(loss(f32::NAN), 0.);
+        assert_eq!(loss(0.5), 0.5);
+    }
+
+    #[test]
+    fn test_loss_extremes() {
+        let never = Impairment::default();
+        let always = Impairment { loss: 1., ..LOSSY };
+        assert!((0..100).all(|_| !never.should_drop()));
+        assert!((0..100).all(|_| always.should_drop()));
+    }
+
+    #[test]
+    fn test_transfer_time() {
+        assert_eq!(Impairment::default().transfer_time(1000), Duration::ZERO);
+        let capped = Impairment {
+            bandwidth: 1000,
+            ..Default::default()
+        };
+        assert_eq!(capped.transfer_time(500), Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn test_inject_and_clear() {
+        let impairments = Impairments::new(Impairment::default());
+        assert!(!impairments.clear());
+        impairments.inject(LOSSY, Duration::from_secs(60));
+        assert_eq!(impairments.current(), LOSSY);
+        assert_eq!(impairments.base(), Impairment::default());
+        let (_, remaining) = impairments.injected().unwrap();
+        assert!(remaining <= Duration::from_secs(60));
+        assert!(impairments.clear());
+        assert_eq!(impairments.current(), Impairment::default());
+        assert!(!impairments.clear());
+    }
+
+    #[tokio::test]
+    async fn test_injected_expires() {
+        let impairments = Impairments::new(Impairment::default());
+        impairments.inject(LOSSY, Duration::from_millis(50));
+        assert_eq!(impairments.current(), LOSSY);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(impairments.injected().is_none());
+        assert_eq!(impairments.current(), Impairment::default());
+        assert!(!impairments.clear());
+    }
+}
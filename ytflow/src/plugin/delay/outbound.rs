@@ -0,0 +1,327 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::num::NonZeroUsize;
+use std::pin::Pin;
+use std::sync::{Arc, Weak};
+use std::task::{ready, Context, Poll};
+
+use async_trait::async_trait;
+use tokio::time::{sleep, sleep_until, Instant, Sleep};
+
+use super::Impairments;
+use crate::flow::*;
+
+/// Datagrams received beyond this number while waiting to be released are
+/// dropped, resembling a bottleneck queue.
+const MAX_QUEUED_DATAGRAMS: usize = 256;
+
+pub struct StreamDelayFactory {
+    pub impairments: Arc<Impairments>,
+    pub next: Weak<dyn StreamOutboundFactory>,
+}
+
+pub struct DatagramDelayFactory {
+    pub impairments: Arc<Impairments>,
+    pub next: Weak<dyn DatagramSessionFactory>,
+}
+
+#[async_trait]
+impl StreamOutboundFactory for StreamDelayFactory {
+    async fn create_outbound(
+        &self,
+        context: &mut FlowContext,
+        initial_data: &'_ [u8],
+    ) -> FlowResult<(Box<dyn Stream>, Buffer)> {
+        let next = self.next.upgrade().ok_or(FlowError::NoOutbound)?;
+        let impairment = self.impairments.current();
+        sleep(impairment.sample_delay()).await;
+        if impairment.should_drop() {
+            return Err(std::io::Error::from(std::io::ErrorKind::TimedOut).into());
+        }
+        let (lower, initial_res) = next.create_outbound(context, initial_data).await?;
+        Ok((
+            Box::new(DelayStream {
+                lower,
+                impairments: self.impairments.clone(),
+                rx_pending: None,
+                tx_sleep: None,
+            }),
+            initial_res,
+        ))
+    }
+}
+
+#[async_trait]
+impl DatagramSessionFactory for DatagramDelayFactory {
+    async fn bind(&self, context: Box<FlowContext>) -> FlowResult<Box<dyn DatagramSession>> {
+        let next = self.next.upgrade().ok_or(FlowError::NoOutbound)?;
+        let lower = next.bind(context).await?;
+        Ok(Box::new(DelayDatagramSession {
+            lower,
+            impairments: self.impairments.clone(),
+            rx_queue: VecDeque::new(),
+            rx_timer: None,
+            last_release: Instant::now(),
+            lower_closed: false,
+            tx_sleep: None,
+        }))
+    }
+}
+
+/// Streams only get the bandwidth cap applied. Latency and loss are applied
+/// when connecting, since TCP hides per-packet delays and drops from us.
+struct DelayStream {
+    lower: Box<dyn Stream>,
+    impairments: Arc<Impairments>,
+    rx_pending: Option<(Buffer, Pin<Box<Sleep>>)>,
+    tx_sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl Stream for DelayStream {
+    fn poll_request_size(&mut self, cx: &mut Context<'_>) -> Poll<FlowResult<SizeHint>> {
+        self.lower.poll_request_size(cx)
+    }
+
+    fn commit_rx_buffer(&mut self, buffer: Buffer) -> Result<(), (Buffer, FlowError)> {
+        self.lower.commit_rx_buffer(buffer)
+    }
+
+    fn poll_rx_buffer(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Buffer, (Buffer, FlowError)>> {
+        if let Some((_, timer)) = &mut self.rx_pending {
+            ready!(timer.as_mut().poll(cx));
+            let (buf, _) = self.rx_pending.take().unwrap();
+            return Poll::Ready(Ok(buf));
+        }
+        let buf = ready!(self.lower.poll_rx_buffer(cx))?;
+        let wait = self.impairments.current().transfer_time(buf.len());
+        if wait.is_zero() {
+            return Poll::Ready(Ok(buf));
+        }
+        let mut timer = Box::pin(sleep(wait));
+        if timer.as_mut().poll(cx).is_ready() {
+            return Poll::Ready(Ok(buf));
+        }
+        self.rx_pending = Some((buf, timer));
+        Poll::Pending
+    }
+
+    fn poll_tx_buffer(
+        &mut self,
+        cx: &mut Context<'_>,
+        size: NonZeroUsize,
+    ) -> Poll<FlowResult<Buffer>> {
+        if let Some(timer) = &mut self.tx_sleep {
+            ready!(timer.as_mut().poll(cx));
+            self.tx_sleep = None;
+        }
+        self.lower.poll_tx_buffer(cx, size)
+    }
+
+    fn commit_tx_buffer(&mut self, buffer: Buffer) -> FlowResult<()> {
+        let wait = self.impairments.current().transfer_time(buffer.len());
+        if !wait.is_zero() {
+            self.tx_sleep = Some(Box::pin(sleep(wait)));
+        }
+        self.lower.commit_tx_buffer(buffer)
+    }
+
+    fn poll_flush_tx(&mut self, cx: &mut Context<'_>) -> Poll<FlowResult<()>> {
+        self.lower.poll_flush_tx(cx)
+    }
+
+    fn poll_close_tx(&mut self, cx: &mut Context<'_>) -> Poll<FlowResult<()>> {
+        self.lower.poll_close_tx(cx)
+    }
+}
+
+/// Incoming datagrams are held in a queue until their release time, which
+/// accounts for latency, jitter and the bandwidth cap while keeping the order.
+/// Outgoing datagrams are paced by the bandwidth cap through
+/// `poll_send_ready`.
+struct DelayDatagramSession {
+    lower: Box<dyn DatagramSession>,
+    impairments: Arc<Impairments>,
+    rx_queue: VecDeque<(Instant, DestinationAddr, Buffer)>,
+    rx_timer: Option<Pin<Box<Sleep>>>,
+    last_release: Instant,
+    lower_closed: bool,
+    tx_sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl DelayDatagramSession {
+    fn fill_rx_queue(&mut self, cx: &mut Context) {
+        while !self.lower_closed {
+            match self.lower.poll_recv_from(cx) {
+                Poll::Ready(Some((dest, buf))) => {
+                    let impairment = self.impairments.current();
+                    if impairment.should_drop() || self.rx_queue.len() >= MAX_QUEUED_DATAGRAMS {
+                        continue;
+                    }
+                    let release = (Instant::now() + impairment.sample_delay())
+                        .max(self.last_release)
+                        + impairment.transfer_time(buf.len());
+                    self.last_release = release;
+                    self.rx_queue.push_back((release, dest, buf));
+                }
+                Poll::Ready(None) => self.lower_closed = true,
+                Poll::Pending => break,
+            }
+        }
+    }
+}
+
+impl DatagramSession for DelayDatagramSession {
+    fn poll_recv_from(&mut self, cx: &mut Context) -> Poll<Option<(DestinationAddr, Buffer)>> {
+        self.fill_rx_queue(cx);
+        let Some(&(release, _, _)) = self.rx_queue.front() else {
+            return if self.lower_closed {
+                Poll::Ready(None)
+            } else {
+                Poll::Pending
+            };
+        };
+        if release > Instant::now() {
+            let timer = self
+                .rx_timer
+                .get_or_insert_with(|| Box::pin(sleep_until(release)));
+            timer.as_mut().reset(release);
+            ready!(timer.as_mut().poll(cx));
+        }
+        let (_, dest, buf) = self.rx_queue.pop_front().unwrap();
+        Poll::Ready(Some((dest, buf)))
+    }
+    fn poll_send_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if let Some(timer) = &mut self.tx_sleep {
+            ready!(timer.as_mut().poll(cx));
+            self.tx_sleep = None;
+        }
+        self.lower.poll_send_ready(cx)
+    }
+    fn send_to(&mut self, remote_peer: DestinationAddr, buf: Buffer) {
+        let impairment = self.impairments.current();
+        if impairment.should_drop() {
+            return;
+        }
+        let wait = impairment.transfer_time(buf.len());
+        if !wait.is_zero() {
+            self.tx_sleep = Some(Box::pin(sleep(wait)));
+        }
+        self.lower.send_to(remote_peer, buf)
+    }
+    fn poll_shutdown(&mut self, cx: &mut Context<'_>) -> Poll<FlowResult<()>> {
+        self.lower.poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::future::poll_fn;
+
+    use super::super::Impairment;
+    use super::*;
+    use crate::flow::testing::*;
+
+    fn impairments(impairment: Impairment) -> Arc<Impairments> {
+        Arc::new(Impairments::new(impairment))
+    }
+
+    #[tokio::test]
+    async fn test_stream_loss_fails_connect() {
+        let mut aps = AccessPoints::new();
+        let next = MockStreamOutboundFactory::new();
+        let factory = StreamDelayFactory {
+            impairments: impairments(Impairment {
+                loss: 1.,
+                ..Default::default()
+            }),
+            next: aps.hold(next.clone()) as _,
+        };
+        let res = factory
+            .create_outbound(&mut context("example.com:80"), b"")
+            .await;
+        assert!(matches!(res, Err(FlowError::Io(e)) if e.kind() == std::io::ErrorKind::TimedOut));
+    }
+
+    #[tokio::test]
+    async fn test_datagram_loss_drops_both_directions() {
+        let mut aps = AccessPoints::new();
+        let (next, peers) = MockDatagramSessionFactory::queued();
+        let factory = DatagramDelayFactory {
+            impairments: impairments(Impairment {
+                loss: 1.,
+                ..Default::default()
+            }),
+            next: aps.hold(next.clone()) as _,
+        };
+        let mut session = factory.bind(context("example.com:53")).await.unwrap();
+        let (peer, _) = peers.recv_async().await.unwrap();
+        session.send_to(dest("example.com:53"), vec![1]);
+        peer.send_to(dest("example.com:53"), vec![2]);
+        let recv = poll_fn(|cx| Poll::Ready(session.poll_recv_from(cx))).await;
+        assert_eq!(recv, Poll::Pending);
+        drop(session);
+        assert_eq!(peer.recv_from().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_datagram_bandwidth_paces_both_directions() {
+        let mut aps = AccessPoints::new();
+        let (next, peers) = MockDatagramSessionFactory::queued();
+        let factory = DatagramDelayFactory {
+            // 100 bytes take 100 ms
+            impairments: impairments(Impairment {
+                bandwidth: 1000,
+                ..Default::default()
+            }),
+            next: aps.hold(next.clone()) as _,
+        };
+        let mut session = factory.bind(context("example.com:53")).await.unwrap();
+        let (peer, _) = peers.recv_async().await.unwrap();
+
+        let start = std::time::Instant::now();
+        session.send_to(dest("example.com:53"), vec![0; 100]);
+        poll_fn(|cx| session.poll_send_ready(cx)).await;
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert_eq!(
+            peer.recv_from().await,
+            Some((dest("example.com:53"), vec![0; 100]))
+        );
+
+        let start = std::time::Instant::now();
+        peer.send_to(dest("example.com:53"), vec![0; 100]);
+        let received = poll_fn(|cx| session.poll_recv_from(cx)).await;
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert_eq!(received, Some((dest("example.com:53"), vec![0; 100])));
+    }
+
+    #[tokio::test]
+    async fn test_stream_bandwidth_delays_rx() {
+        let mut aps = AccessPoints::new();
+        let next = MockStreamOutboundFactory::new();
+        let factory = StreamDelayFactory {
+            impairments: impairments(Impairment {
+                bandwidth: 1000,
+                ..Default::default()
+            }),
+            next: aps.hold(next.clone()) as _,
+        };
+        let (mut stream, _) = factory
+            .create_outbound(&mut context("example.com:80"), b"")
+            .await
+            .unwrap();
+        let mut outbound = next.connected().await;
+        tokio::io::AsyncWriteExt::write_all(&mut outbound.peer, &[0; 100])
+            .await
+            .unwrap();
+        let start = std::time::Instant::now();
+        stream.commit_rx_buffer(Vec::with_capacity(100)).unwrap();
+        let buf = crate::get_rx_buffer_boxed!(stream).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert_eq!(buf, [0; 100]);
+    }
+}
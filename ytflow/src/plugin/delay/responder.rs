@@ -0,0 +1,134 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use cbor4ii::serde::{from_slice, to_vec};
+use serde::{Deserialize, Serialize};
+
+use super::{Impairment, Impairments};
+use crate::control::{PluginRequestError, PluginRequestResult, PluginResponder};
+
+/// Injected impairments never last longer than this, so that a forgotten
+/// experiment does not degrade the connection forever.
+const MAX_INJECT_DURATION: Duration = Duration::from_secs(3600);
+
+fn default_inject_duration() -> u64 {
+    60_000
+}
+
+#[derive(Deserialize)]
+struct InjectRequest {
+    #[serde(flatten)]
+    impairment: Impairment,
+    /// Duration in milliseconds before the injected impairment expires.
+    #[serde(default = "default_inject_duration")]
+    duration: u64,
+}
+
+#[derive(Clone, Default, Serialize, PartialEq)]
+struct Info {
+    base: Impairment,
+    injected: Option<Impairment>,
+    /// Remaining seconds of the injected impairment, rounded up.
+    remaining: Option<u64>,
+}
+
+pub struct Responder {
+    impairments: Arc<Impairments>,
+    last_info: Mutex<(Info, u32)>,
+}
+
+impl Responder {
+    pub fn new(impairments: Arc<Impairments>) -> Self {
+        Self {
+            impairments,
+            last_info: Mutex::new((Info::default(), 1)),
+        }
+    }
+}
+
+fn info_snapshot(impairments: &Impairments) -> Info {
+    let injected = impairments.injected();
+    Info {
+        base: impairments.base(),
+        injected: injected.map(|(i, _)| i),
+        remaining: injected.map(|(_, d)| (d.as_millis() as u64).div_ceil(1000)),
+    }
+}
+
+impl PluginResponder for Responder {
+    fn collect_info(&self, hashcode: &mut u32) -> Option<Vec<u8>> {
+        let info = {
+            let mut last_info_guard = self.last_info.lock().unwrap();
+            let (last_info, last_hashcode) = &mut *last_info_guard;
+            let new_info = info_snapshot(&self.impairments);
+            if new_info == *last_info {
+                if *last_hashcode == *hashcode {
+                    return None;
+                }
+            } else {
+                *last_info = new_info.clone();
+                *last_hashcode = (*last_hashcode).wrapping_add(1);
+            }
+            *hashcode = *last_hashcode;
+            new_info
+        };
+        Some(to_vec(vec![], &info).unwrap())
+    }
+
+    fn on_request(&self, func: &str, params: &[u8]) -> PluginRequestResult<Vec<u8>> {
+        match func {
+            "inject" => {
+                let req: InjectRequest = from_slice(params)?;
+                let duration = Duration::from_millis(req.duration).min(MAX_INJECT_DURATION);
+                self.impairments.inject(req.impairment, duration);
+                Ok(to_vec(vec![], &(duration.as_millis() as u64)).unwrap())
+            }
+            "clear" => {
+                let was_active = self.impairments.clear();
+                Ok(to_vec(vec![], &was_active).unwrap())
+            }
+            _ => Err(PluginRequestError::NoSuchFunc),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inject_and_clear() {
+        let impairments = Arc::new(Impairments::new(Impairment::default()));
+        let responder = Responder::new(impairments.clone());
+        let mut hashcode = 0;
+        assert!(responder.collect_info(&mut hashcode).is_some());
+        assert!(responder.collect_info(&mut hashcode).is_none());
+
+        let injected = Impairment {
+            latency: 200,
+            ..Default::default()
+        };
+        let params = to_vec(
+            vec![],
+            &std::collections::BTreeMap::from([("latency", 200u64), ("duration", 7_200_000)]),
+        )
+        .unwrap();
+        let granted: u64 = from_slice(&responder.on_request("inject", &params).unwrap()).unwrap();
+        assert_eq!(granted, MAX_INJECT_DURATION.as_millis() as u64);
+        assert_eq!(impairments.current(), injected);
+        let info = info_snapshot(&impairments);
+        assert_eq!(info.injected, Some(injected));
+        assert_eq!(info.remaining, Some(3600));
+        assert!(responder.collect_info(&mut hashcode).is_some());
+
+        let cleared: bool = from_slice(&responder.on_request("clear", &[]).unwrap()).unwrap();
+        assert!(cleared);
+        assert_eq!(impairments.current(), Impairment::default());
+        let cleared: bool = from_slice(&responder.on_request("clear", &[]).unwrap()).unwrap();
+        assert!(!cleared);
+        assert!(matches!(
+            responder.on_request("foo", &[]),
+            Err(PluginRequestError::NoSuchFunc)
+        ));
+    }
+}
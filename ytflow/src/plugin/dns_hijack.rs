@@ -0,0 +1,83 @@
+use std::sync::Weak;
+
+use cidr::IpCidr;
+
+use crate::flow::*;
+
+const DNS_PORT: u16 = 53;
+
+/// Intercept DNS queries sent to any server, so that devices with hard-coded
+/// DNS servers still go through the local DNS server.
+pub struct DnsHijack {
+    /// Servers whose queries are never intercepted.
+    pub except: Vec<IpCidr>,
+    pub dns_tcp: Option<Weak<dyn StreamHandler>>,
+    pub dns_udp: Weak<dyn DatagramSessionHandler>,
+    pub tcp_next: Weak<dyn StreamHandler>,
+    pub udp_next: Weak<dyn DatagramSessionHandler>,
+}
+
+impl DnsHijack {
+    fn should_hijack(&self, dest: &DestinationAddr) -> bool {
+        if dest.port != DNS_PORT {
+            return false;
+        }
+        match &dest.host {
+            HostName::Ip(ip) => !self.except.iter().any(|c| c.contains(ip)),
+            HostName::DomainName(_) => true,
+        }
+    }
+}
+
+impl StreamHandler for DnsHijack {
+    fn on_stream(&self, lower: Box<dyn Stream>, initial_data: Buffer, context: Box<FlowContext>) {
+        let next = match &self.dns_tcp {
+            Some(dns_tcp) if self.should_hijack(&context.remote_peer) => dns_tcp,
+            _ => &self.tcp_next,
+        };
+        if let Some(next) = next.upgrade() {
+            next.on_stream(lower, initial_data, context)
+        }
+    }
+}
+
+impl DatagramSessionHandler for DnsHijack {
+    fn on_session(&self, session: Box<dyn DatagramSession>, context: Box<FlowContext>) {
+        let next = if self.should_hijack(&context.remote_peer) {
+            &self.dns_udp
+        } else {
+            &self.udp_next
+        };
+        if let Some(next) = next.upgrade() {
+            next.on_session(session, context)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin::reject::RejectHandler;
+
+    #[test]
+    fn test_should_hijack() {
+        let hijack = DnsHijack {
+            except: vec!["192.168.1.0/24".parse().unwrap()],
+            dns_tcp: None,
+            dns_udp: Weak::<RejectHandler>::new(),
+            tcp_next: Weak::<RejectHandler>::new(),
+            udp_next: Weak::<RejectHandler>::new(),
+        };
+        let dest = |host: &str, port| DestinationAddr {
+            host: host
+                .parse()
+                .map(HostName::Ip)
+                .unwrap_or_else(|_| HostName::DomainName(host.into())),
+            port,
+        };
+        assert!(hijack.should_hijack(&dest("8.8.8.8", 53)));
+        assert!(hijack.should_hijack(&dest("dns.google", 53)));
+        assert!(!hijack.should_hijack(&dest("8.8.8.8", 853)));
+        assert!(!hijack.should_hijack(&dest("192.168.1.1", 53)));
+    }
+}
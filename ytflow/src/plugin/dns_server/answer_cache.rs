@@ -0,0 +1,131 @@
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lru::LruCache;
+
+use crate::flow::{ResolvedV4, ResolvedV6};
+
+const ANSWER_CACHE_CAPACITY: NonZeroUsize = NonZeroUsize::new(1024).unwrap();
+
+/// Bounds applied to the TTL of answers sent to clients.
+#[derive(Debug, Clone, Copy)]
+pub struct TtlClamp {
+    pub min_ttl: u32,
+    pub max_ttl: u32,
+}
+
+impl TtlClamp {
+    pub fn apply(&self, ttl: u32) -> u32 {
+        ttl.clamp(self.min_ttl, self.max_ttl)
+    }
+}
+
+impl Default for TtlClamp {
+    fn default() -> Self {
+        Self {
+            min_ttl: 0,
+            max_ttl: u32::MAX,
+        }
+    }
+}
+
+type Entries<A> = Mutex<LruCache<String, (Instant, A)>>;
+
+/// Keeps answers around for their TTL, so that clients re-querying a name
+/// in quick succession do not reach the resolver every time.
+pub(super) struct AnswerCache {
+    lifetime: Duration,
+    v4: Entries<ResolvedV4>,
+    v6: Entries<ResolvedV6>,
+}
+
+fn get<A: Clone>(entries: &Entries<A>, name: &str) -> Option<(A, u32)> {
+    let mut entries = entries.lock().unwrap();
+    let (expires_at, ips) = entries.get(name)?;
+    let remaining = expires_at.saturating_duration_since(Instant::now());
+    if remaining.is_zero() {
+        entries.pop(name);
+        return None;
+    }
+    Some((ips.clone(), remaining.as_secs().max(1) as u32))
+}
+
+impl AnswerCache {
+    pub(super) fn new(ttl: u32) -> Self {
+        Self {
+            lifetime: Duration::from_secs(ttl as u64),
+            v4: Mutex::new(LruCache::new(ANSWER_CACHE_CAPACITY)),
+            v6: Mutex::new(LruCache::new(ANSWER_CACHE_CAPACITY)),
+        }
+    }
+
+    /// Returns cached addresses along with their remaining TTL.
+    pub(super) fn get_v4(&self, name: &str) -> Option<(ResolvedV4, u32)> {
+        get(&self.v4, name)
+    }
+
+    pub(super) fn get_v6(&self, name: &str) -> Option<(ResolvedV6, u32)> {
+        get(&self.v6, name)
+    }
+
+    pub(super) fn put_v4(&self, name: String, ips: ResolvedV4) {
+        let expires_at = Instant::now() + self.lifetime;
+        self.v4.lock().unwrap().put(name, (expires_at, ips));
+    }
+
+    pub(super) fn put_v6(&self, name: String, ips: ResolvedV6) {
+        let expires_at = Instant::now() + self.lifetime;
+        self.v6.lock().unwrap().put(name, (expires_at, ips));
+    }
+
+    pub(super) fn clear(&self) {
+        self.v4.lock().unwrap().clear();
+        self.v6.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use smallvec::smallvec;
+
+    use super::*;
+
+    #[test]
+    fn test_ttl_clamp() {
+        let clamp = TtlClamp {
+            min_ttl: 30,
+            max_ttl: 600,
+        };
+        assert_eq!(clamp.apply(1), 30);
+        assert_eq!(clamp.apply(60), 60);
+        assert_eq!(clamp.apply(86400), 600);
+    }
+
+    #[test]
+    fn test_answer_cache_hit() {
+        let cache = AnswerCache::new(60);
+        cache.put_v4("example.com.".into(), smallvec![Ipv4Addr::LOCALHOST]);
+        let (ips, ttl) = cache.get_v4("example.com.").unwrap();
+        assert_eq!(ips[..], [Ipv4Addr::LOCALHOST]);
+        assert!(ttl > 0 && ttl <= 60);
+        assert!(cache.get_v6("example.com.").is_none());
+    }
+
+    #[test]
+    fn test_answer_cache_clear() {
+        let cache = AnswerCache::new(60);
+        cache.put_v4("example.com.".into(), smallvec![Ipv4Addr::LOCALHOST]);
+        cache.clear();
+        assert!(cache.get_v4("example.com.").is_none());
+    }
+
+    #[test]
+    fn test_answer_cache_expired() {
+        let cache = AnswerCache::new(0);
+        cache.put_v6("example.com.".into(), smallvec![Ipv6Addr::LOCALHOST]);
+        assert!(cache.get_v6("example.com.").is_none());
+    }
+}
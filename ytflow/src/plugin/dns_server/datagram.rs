@@ -3,6 +3,7 @@ use std::hash::Hash;
 use std::net::{Ipv4Addr, Ipv6Addr};
 use std::num::NonZeroUsize;
 use std::sync::{Arc, Mutex, Weak};
+use std::time::Instant;
 
 use futures::future::poll_fn;
 use lru::LruCache;
@@ -12,10 +13,16 @@ use trust_dns_resolver::proto::op::{Message as DnsMessage, MessageType, Response
 use trust_dns_resolver::proto::rr::{RData, Record, RecordType};
 use trust_dns_resolver::proto::serialize::binary::BinDecodable;
 
+use super::answer_cache::{AnswerCache, TtlClamp};
+use super::query_log::{QueryLog, QueryRecord};
+use crate::control::CacheShrink;
 use crate::data::PluginCache;
 use crate::flow::*;
 
 const CACHE_CAPACITY: NonZeroUsize = NonZeroUsize::new(1024).unwrap();
+/// Number of most recently used reverse mappings kept when the cache is shrunk.
+/// Dropping all of them would break mapping back for ongoing connections.
+const SHRUNK_CACHE_LEN: usize = CACHE_CAPACITY.get() / 4;
 const REVERSE_MAPPING_V4_CACHE_KEY: &str = "rev_v4";
 const REVERSE_MAPPING_V6_CACHE_KEY: &str = "rev_v6";
 
@@ -23,10 +30,12 @@ pub struct DnsServer {
     concurrency_limit: Arc<Semaphore>,
     resolver: Weak<dyn Resolver>,
     ttl: u32,
+    answer_cache: Option<Arc<AnswerCache>>,
     pub(super) reverse_mapping_v4: Arc<Mutex<LruCache<Ipv4Addr, String>>>,
     pub(super) reverse_mapping_v6: Arc<Mutex<LruCache<Ipv6Addr, String>>>,
     plugin_cache: PluginCache,
     pub(super) new_notify: Arc<Notify>,
+    query_log: Option<Arc<QueryLog>>,
 }
 
 #[derive(Debug, Clone, Default, PartialOrd, Ord, PartialEq, Eq, Serialize, Deserialize)]
@@ -38,7 +47,9 @@ impl DnsServer {
         concurrency_limit: usize,
         resolver: Weak<dyn Resolver>,
         ttl: u32,
+        ttl_clamp: TtlClamp,
         plugin_cache: PluginCache,
+        query_log: Option<Arc<QueryLog>>,
     ) -> Self {
         let concurrency_limit = Arc::new(Semaphore::new(concurrency_limit));
         let mut reverse_mapping_v4 = LruCache::new(CACHE_CAPACITY);
@@ -61,14 +72,20 @@ impl DnsServer {
                 reverse_mapping_v6.put(k, v);
             }
         }
+        let ttl = ttl_clamp.apply(ttl);
+        // Only cache when asked to, so that answers always reflect the
+        // resolver otherwise.
+        let answer_cache = (ttl_clamp.min_ttl > 0).then(|| Arc::new(AnswerCache::new(ttl)));
         DnsServer {
             concurrency_limit,
             resolver,
             ttl,
+            answer_cache,
             reverse_mapping_v4: Arc::new(Mutex::new(reverse_mapping_v4)),
             reverse_mapping_v6: Arc::new(Mutex::new(reverse_mapping_v6)),
             plugin_cache,
             new_notify: Arc::new(Notify::new()),
+            query_log,
         }
     }
 
@@ -94,17 +111,37 @@ impl DnsServer {
     }
 }
 
+fn shrink_reverse_mapping<T: Hash + Eq>(cache: &Mutex<LruCache<T, String>>) {
+    let mut cache = cache.lock().unwrap();
+    while cache.len() > SHRUNK_CACHE_LEN {
+        cache.pop_lru();
+    }
+}
+
+impl CacheShrink for DnsServer {
+    fn shrink_cache(&self) {
+        if let Some(answer_cache) = &self.answer_cache {
+            answer_cache.clear();
+        }
+        shrink_reverse_mapping(&self.reverse_mapping_v4);
+        shrink_reverse_mapping(&self.reverse_mapping_v6);
+    }
+}
+
 impl DatagramSessionHandler for DnsServer {
-    fn on_session(&self, mut session: Box<dyn DatagramSession>, _context: Box<FlowContext>) {
+    fn on_session(&self, mut session: Box<dyn DatagramSession>, context: Box<FlowContext>) {
         let resolver = match self.resolver.upgrade() {
             Some(resolver) => resolver,
             None => return,
         };
         let concurrency_limit = self.concurrency_limit.clone();
         let ttl = self.ttl;
+        let answer_cache = self.answer_cache.clone();
         let reverse_mapping_v4 = self.reverse_mapping_v4.clone();
         let reverse_mapping_v6 = self.reverse_mapping_v6.clone();
         let new_notify = self.new_notify.clone();
+        let query_log = self.query_log.clone();
+        let client = context.local_peer.ip();
         tokio::spawn(async move {
             let mut send_ready = true;
             while let Some((dest, buf)) = poll_fn(|cx| {
@@ -130,15 +167,49 @@ impl DatagramSessionHandler for DnsServer {
                 for query in msg.queries() {
                     let name = query.name();
                     let name_str = name.to_lowercase().to_ascii();
-                    match query.query_type() {
+                    let query_type = query.query_type();
+                    let started = Instant::now();
+                    let log_query = |answers: Vec<std::net::IpAddr>, success: bool| {
+                        if let Some(query_log) = &query_log {
+                            query_log.record(QueryRecord {
+                                domain: &name_str,
+                                query_type: &query_type.to_string(),
+                                client,
+                                answers,
+                                success,
+                                latency: started.elapsed(),
+                            });
+                        }
+                    };
+                    match query_type {
                         RecordType::A => {
-                            let ips = match resolver.resolve_ipv4(name_str.clone()).await {
-                                Ok(addrs) => addrs,
-                                Err(_) => {
-                                    res_code = ResponseCode::NXDomain;
-                                    continue;
-                                }
+                            // Answer mapped domains without a round trip to the
+                            // async resolver.
+                            let cached = answer_cache
+                                .as_ref()
+                                .and_then(|c| c.get_v4(&name_str))
+                                .or_else(|| {
+                                    resolver
+                                        .try_resolve_ipv4_sync(&name_str)
+                                        .map(|ips| (ips, ttl))
+                                });
+                            let (ips, ans_ttl) = match cached {
+                                Some(hit) => hit,
+                                None => match resolver.resolve_ipv4(name_str.clone()).await {
+                                    Ok(addrs) => {
+                                        if let Some(c) = &answer_cache {
+                                            c.put_v4(name_str.clone(), addrs.clone());
+                                        }
+                                        (addrs, ttl)
+                                    }
+                                    Err(_) => {
+                                        log_query(vec![], false);
+                                        res_code = ResponseCode::NXDomain;
+                                        continue;
+                                    }
+                                },
                             };
+                            log_query(ips.iter().map(|ip| (*ip).into()).collect(), true);
                             let mut reverse_mapping = reverse_mapping_v4.lock().unwrap();
                             for ip in &ips {
                                 notify_cache_update |= reverse_mapping
@@ -147,20 +218,38 @@ impl DatagramSessionHandler for DnsServer {
                                     .is_none();
                                 reverse_mapping.get_or_insert(*ip, || name_str.clone());
                             }
-                            ans_records.extend(
-                                ips.into_iter().map(|addr| {
-                                    Record::from_rdata(name.clone(), ttl, RData::A(addr))
-                                }),
-                            )
+                            ans_records.extend(ips.into_iter().map(|addr| {
+                                Record::from_rdata(name.clone(), ans_ttl, RData::A(addr))
+                            }))
                         }
                         RecordType::AAAA => {
-                            let ips = match resolver.resolve_ipv6(name_str.clone()).await {
-                                Ok(addrs) => addrs,
-                                Err(_) => {
-                                    res_code = ResponseCode::NXDomain;
-                                    continue;
-                                }
+                            // Answer mapped domains without a round trip to the
+                            // async resolver.
+                            let cached = answer_cache
+                                .as_ref()
+                                .and_then(|c| c.get_v6(&name_str))
+                                .or_else(|| {
+                                    resolver
+                                        .try_resolve_ipv6_sync(&name_str)
+                                        .map(|ips| (ips, ttl))
+                                });
+                            let (ips, ans_ttl) = match cached {
+                                Some(hit) => hit,
+                                None => match resolver.resolve_ipv6(name_str.clone()).await {
+                                    Ok(addrs) => {
+                                        if let Some(c) = &answer_cache {
+                                            c.put_v6(name_str.clone(), addrs.clone());
+                                        }
+                                        (addrs, ttl)
+                                    }
+                                    Err(_) => {
+                                        log_query(vec![], false);
+                                        res_code = ResponseCode::NXDomain;
+                                        continue;
+                                    }
+                                },
                             };
+                            log_query(ips.iter().map(|ip| (*ip).into()).collect(), true);
                             let mut reverse_mapping = reverse_mapping_v6.lock().unwrap();
                             for ip in &ips {
                                 notify_cache_update |= reverse_mapping
@@ -170,11 +259,12 @@ impl DatagramSessionHandler for DnsServer {
                                 reverse_mapping.get_or_insert(*ip, || name_str.clone());
                             }
                             ans_records.extend(ips.into_iter().map(|addr| {
-                                Record::from_rdata(name.clone(), ttl, RData::AAAA(addr))
+                                Record::from_rdata(name.clone(), ans_ttl, RData::AAAA(addr))
                             }))
                         }
                         // TODO: SRV
                         _ => {
+                            log_query(vec![], false);
                             res_code = ResponseCode::NotImp;
                             continue;
                         }
@@ -1,10 +1,16 @@
+mod answer_cache;
 mod datagram;
 mod map_back;
+mod query_log;
+mod responder;
 
 use std::sync::Arc;
 
+pub use answer_cache::TtlClamp;
 pub use datagram::DnsServer;
 pub use map_back::{MapBackDatagramSessionHandler, MapBackStreamHandler};
+pub use query_log::{QueryLog, QueryLogEntry, QueryLogMode, QueryLogStats};
+pub use responder::Responder;
 
 pub async fn cache_writer(plugin: Arc<DnsServer>) {
     let (plugin, notify) = {
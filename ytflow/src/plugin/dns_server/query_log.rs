@@ -0,0 +1,224 @@
+use std::collections::VecDeque;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum QueryLogMode {
+    /// Record domains, clients and answers.
+    Full,
+    /// Record salted hashes of domains only. Clients and answers are omitted.
+    HashedDomains,
+    /// Only keep counters. No individual query is recorded.
+    AggregateOnly,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryLogEntry {
+    pub seq: u64,
+    /// Unix timestamp in milliseconds
+    pub time: u64,
+    pub domain: String,
+    pub query_type: String,
+    pub client: Option<IpAddr>,
+    pub answers: Vec<IpAddr>,
+    pub success: bool,
+    /// Time spent waiting for the upstream resolver in milliseconds
+    pub latency: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct QueryLogStats {
+    pub total: u64,
+    pub failed: u64,
+    pub a: u64,
+    pub aaaa: u64,
+    pub other: u64,
+    pub total_latency: u64,
+    pub max_latency: u32,
+}
+
+pub struct QueryLog {
+    pub mode: QueryLogMode,
+    capacity: usize,
+    salt: [u8; 16],
+    entries: Mutex<(VecDeque<QueryLogEntry>, u64)>,
+    stats: Mutex<QueryLogStats>,
+}
+
+pub(super) struct QueryRecord<'a> {
+    pub(super) domain: &'a str,
+    pub(super) query_type: &'a str,
+    pub(super) client: IpAddr,
+    pub(super) answers: Vec<IpAddr>,
+    pub(super) success: bool,
+    pub(super) latency: Duration,
+}
+
+impl QueryLog {
+    pub fn new(mode: QueryLogMode, capacity: usize) -> Self {
+        Self {
+            mode,
+            capacity,
+            salt: rand::random(),
+            entries: Mutex::new((VecDeque::with_capacity(capacity.min(1024)), 0)),
+            stats: Mutex::new(QueryLogStats::default()),
+        }
+    }
+
+    fn hash_domain(&self, domain: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.salt);
+        hasher.update(domain.as_bytes());
+        let digest = hasher.finalize();
+        digest[..8].iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    pub(super) fn record(&self, record: QueryRecord<'_>) {
+        let latency = record.latency.as_millis().min(u32::MAX as u128) as u32;
+        {
+            let mut stats = self.stats.lock().unwrap();
+            stats.total += 1;
+            stats.failed += !record.success as u64;
+            match record.query_type {
+                "A" => stats.a += 1,
+                "AAAA" => stats.aaaa += 1,
+                _ => stats.other += 1,
+            }
+            stats.total_latency += latency as u64;
+            stats.max_latency = stats.max_latency.max(latency);
+        }
+
+        let (domain, client, answers) = match self.mode {
+            QueryLogMode::AggregateOnly => return,
+            QueryLogMode::Full => (
+                record.domain.to_string(),
+                Some(record.client),
+                record.answers,
+            ),
+            QueryLogMode::HashedDomains => (self.hash_domain(record.domain), None, vec![]),
+        };
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64);
+        let mut guard = self.entries.lock().unwrap();
+        let (entries, next_seq) = &mut *guard;
+        if self.capacity == 0 {
+            return;
+        }
+        if entries.len() >= self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(QueryLogEntry {
+            seq: *next_seq,
+            time,
+            domain,
+            query_type: record.query_type.to_string(),
+            client,
+            answers,
+            success: record.success,
+            latency,
+        });
+        *next_seq += 1;
+    }
+
+    /// Entries with a sequence number greater than or equal to `from`, oldest first.
+    pub fn entries_from(&self, from: u64, limit: usize) -> Vec<QueryLogEntry> {
+        let guard = self.entries.lock().unwrap();
+        let start = guard.0.partition_point(|e| e.seq < from);
+        guard.0.range(start..).take(limit).cloned().collect()
+    }
+
+    pub fn stats(&self) -> QueryLogStats {
+        self.stats.lock().unwrap().clone()
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().0.clear();
+        *self.stats.lock().unwrap() = QueryLogStats::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(log: &QueryLog, domain: &str) {
+        log.record(QueryRecord {
+            domain,
+            query_type: "A",
+            client: "10.0.0.2".parse().unwrap(),
+            answers: vec!["10.0.0.1".parse().unwrap()],
+            success: true,
+            latency: Duration::from_millis(5),
+        });
+    }
+
+    fn domains(log: &QueryLog, from: u64) -> Vec<String> {
+        log.entries_from(from, usize::MAX)
+            .into_iter()
+            .map(|e| e.domain)
+            .collect()
+    }
+
+    #[test]
+    fn test_retention_drops_oldest() {
+        let log = QueryLog::new(QueryLogMode::Full, 2);
+        for domain in ["a.com", "b.com", "c.com"] {
+            record(&log, domain);
+        }
+        assert_eq!(domains(&log, 0), ["b.com", "c.com"]);
+        let entries = log.entries_from(2, 10);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].seq, 2);
+        assert_eq!(entries[0].client, Some("10.0.0.2".parse().unwrap()));
+        assert_eq!(log.entries_from(0, 1).len(), 1);
+        // Evicted entries are still counted.
+        assert_eq!(log.stats().total, 3);
+
+        log.clear();
+        assert!(domains(&log, 0).is_empty());
+        assert_eq!(log.stats(), QueryLogStats::default());
+        // Sequence numbers keep growing so that polling clients do not miss entries.
+        record(&log, "d.com");
+        assert_eq!(log.entries_from(0, 10)[0].seq, 3);
+    }
+
+    #[test]
+    fn test_nothing_retained_without_entries() {
+        for log in [
+            QueryLog::new(QueryLogMode::Full, 0),
+            QueryLog::new(QueryLogMode::AggregateOnly, 10),
+        ] {
+            record(&log, "a.com");
+            assert!(domains(&log, 0).is_empty());
+            assert_eq!(log.stats().total, 1);
+            assert_eq!(log.stats().a, 1);
+        }
+    }
+
+    #[test]
+    fn test_hashed_domains() {
+        let log = QueryLog::new(QueryLogMode::HashedDomains, 10);
+        for domain in ["a.com", "a.com", "b.com"] {
+            record(&log, domain);
+        }
+        let entries = log.entries_from(0, 10);
+        let hashes: Vec<_> = entries.iter().map(|e| e.domain.as_str()).collect();
+        assert_eq!(hashes[0], hashes[1]);
+        assert_ne!(hashes[0], hashes[2]);
+        assert!(hashes
+            .iter()
+            .all(|h| h.len() == 16 && h.chars().all(|c| c.is_ascii_hexdigit())));
+        assert!(entries
+            .iter()
+            .all(|e| e.client.is_none() && e.answers.is_empty()));
+        // Salted per log, so hashes cannot be matched across logs.
+        let other = QueryLog::new(QueryLogMode::HashedDomains, 10);
+        assert_ne!(other.hash_domain("a.com"), hashes[0]);
+    }
+}
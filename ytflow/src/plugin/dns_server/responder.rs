@@ -0,0 +1,137 @@
+use std::sync::{Arc, Mutex};
+
+use cbor4ii::serde::{from_slice, to_vec};
+use serde::{Deserialize, Serialize};
+
+use super::query_log::{QueryLog, QueryLogEntry, QueryLogMode, QueryLogStats};
+use crate::control::{PluginRequestError, PluginRequestResult, PluginResponder};
+
+const DEFAULT_QUERY_LIMIT: usize = 200;
+
+#[derive(Clone, PartialEq, Serialize)]
+struct Info {
+    mode: QueryLogMode,
+    stats: QueryLogStats,
+}
+
+#[derive(Deserialize)]
+struct QueryLogRequest {
+    #[serde(default)]
+    from: u64,
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct ExportedEntry<'a> {
+    /// RFC 3339 form of `time` in UTC, for reading the export without tools.
+    timestamp: String,
+    #[serde(flatten)]
+    entry: &'a QueryLogEntry,
+}
+
+/// Render entries as JSON lines, one query per line, for saving to a file or
+/// feeding into log tools.
+fn export_json_lines(entries: &[QueryLogEntry]) -> String {
+    let mut lines = String::new();
+    for entry in entries {
+        let timestamp = chrono::DateTime::<chrono::Utc>::from_timestamp_millis(entry.time as i64)
+            .unwrap_or_default()
+            .to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+        let line = serde_json::to_string(&ExportedEntry { timestamp, entry }).unwrap();
+        lines.push_str(&line);
+        lines.push('\n');
+    }
+    lines
+}
+
+pub struct Responder {
+    query_log: Arc<QueryLog>,
+    last_info: Mutex<(Option<Info>, u32)>,
+}
+
+impl Responder {
+    pub fn new(query_log: Arc<QueryLog>) -> Self {
+        Self {
+            query_log,
+            last_info: Mutex::new((None, 1)),
+        }
+    }
+}
+
+impl PluginResponder for Responder {
+    fn collect_info(&self, hashcode: &mut u32) -> Option<Vec<u8>> {
+        let info = {
+            let mut last_info_guard = self.last_info.lock().unwrap();
+            let (last_info, last_hashcode) = &mut *last_info_guard;
+            let new_info = Info {
+                mode: self.query_log.mode,
+                stats: self.query_log.stats(),
+            };
+            if last_info.as_ref() == Some(&new_info) {
+                if *last_hashcode == *hashcode {
+                    return None;
+                }
+            } else {
+                *last_info = Some(new_info.clone());
+                *last_hashcode = (*last_hashcode).wrapping_add(1);
+            }
+            *hashcode = *last_hashcode;
+            new_info
+        };
+        Some(to_vec(vec![], &info).unwrap())
+    }
+
+    fn on_request(&self, func: &str, params: &[u8]) -> PluginRequestResult<Vec<u8>> {
+        match func {
+            "query_log" => {
+                let req: QueryLogRequest = from_slice(params)?;
+                let entries = self
+                    .query_log
+                    .entries_from(req.from, req.limit.unwrap_or(DEFAULT_QUERY_LIMIT));
+                Ok(to_vec(vec![], &entries).unwrap())
+            }
+            "export" => {
+                let entries = self.query_log.entries_from(0, usize::MAX);
+                Ok(to_vec(vec![], &export_json_lines(&entries)).unwrap())
+            }
+            "clear" => {
+                self.query_log.clear();
+                Ok(to_vec(vec![], &()).unwrap())
+            }
+            _ => Err(PluginRequestError::NoSuchFunc),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_json_lines() {
+        let entry = |seq, time| QueryLogEntry {
+            seq,
+            time,
+            domain: "example.com".into(),
+            query_type: "A".into(),
+            client: Some("10.0.0.2".parse().unwrap()),
+            answers: vec!["93.184.216.34".parse().unwrap()],
+            success: true,
+            latency: 12,
+        };
+        let exported = export_json_lines(&[entry(0, 1_700_000_000_123), entry(1, 0)]);
+        let lines: Vec<serde_json::Value> = exported
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["timestamp"], "2023-11-14T22:13:20.123Z");
+        assert_eq!(lines[0]["time"], 1_700_000_000_123u64);
+        assert_eq!(lines[0]["domain"], "example.com");
+        assert_eq!(lines[0]["answers"][0], "93.184.216.34");
+        assert_eq!(lines[1]["seq"], 1);
+        assert_eq!(lines[1]["timestamp"], "1970-01-01T00:00:00.000Z");
+        assert!(exported.ends_with('\n'));
+        assert!(export_json_lines(&[]).is_empty());
+    }
+}
@@ -2,6 +2,12 @@ pub mod config;
 #[cfg(feature = "plugins")]
 mod dyn_outbound;
 #[cfg(feature = "plugins")]
+mod group;
+#[cfg(feature = "plugins")]
+mod nat;
+#[cfg(feature = "plugins")]
+mod quota;
+#[cfg(feature = "plugins")]
 mod responder;
 #[cfg(feature = "plugins")]
 mod select;
@@ -9,6 +15,10 @@ mod select;
 #[cfg(feature = "plugins")]
 pub use dyn_outbound::DynOutbound;
 #[cfg(feature = "plugins")]
+pub use group::{GroupPolicy, GroupSelector};
+#[cfg(feature = "plugins")]
+pub use nat::{default_nat_probe_servers, NatBehavior, NatVerdict};
+#[cfg(feature = "plugins")]
 pub use responder::Responder;
 
 pub const PLUGIN_CACHE_KEY_LAST_SELECT: &str = "last_select";
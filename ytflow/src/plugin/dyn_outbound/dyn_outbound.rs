@@ -1,12 +1,17 @@
-use std::sync::{Arc, Weak};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, Weak};
 
 use arc_swap::ArcSwap;
 use async_trait::async_trait;
 use itertools::Itertools;
 
+use super::quota::{QuotaDatagramSession, QuotaStream};
+use crate::control::events::{Event, EventBus, EventSubscription};
 use crate::data::{self, DataResult, Database, PluginCache};
 use crate::flow::*;
 
+const STATS_FLUSH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
 pub struct FixedOutbound {
     pub name: String,
     pub tcp_next: Weak<dyn StreamOutboundFactory>,
@@ -15,32 +20,74 @@ pub struct FixedOutbound {
 pub struct DynOutbound {
     pub(super) db: Database,
     pub(super) plugin_cache: PluginCache,
+    pub(super) profile_id: Option<data::ProfileId>,
     pub(super) fixed_outbounds: Vec<FixedOutbound>,
     pub(super) proxy_list: ArcSwap<(
         Vec<(data::Proxy, data::ProxyGroupId)>,
         Vec<data::ProxyGroup>,
     )>,
     pub(super) current: ArcSwap<Option<super::select::Selection>>,
+    pub(super) nat_verdicts: Mutex<BTreeMap<usize, super::nat::NatVerdict>>,
     pub(super) tcp_next: Weak<dyn StreamOutboundFactory>,
     pub(super) udp_next: Weak<dyn DatagramSessionFactory>,
+    pub(super) events: EventBus,
 }
 
 impl DynOutbound {
     pub fn new(
         db: Database,
         plugin_cache: PluginCache,
+        profile_id: Option<data::ProfileId>,
         fixed_outbounds: Vec<FixedOutbound>,
         tcp_next: Weak<dyn StreamOutboundFactory>,
         udp_next: Weak<dyn DatagramSessionFactory>,
+        events: EventBus,
     ) -> Self {
         Self {
             db,
             plugin_cache,
+            profile_id,
             fixed_outbounds,
             proxy_list: ArcSwap::new(Default::default()),
             current: ArcSwap::new(Arc::new(None)),
+            nat_verdicts: Mutex::new(BTreeMap::new()),
             tcp_next,
             udp_next,
+            events,
+        }
+    }
+
+    /// Write traffic of the current selection back to the database periodically
+    /// until the plugin is dropped.
+    pub async fn run_stats_flush(self: Weak<Self>) {
+        let mut interval = tokio::time::interval(STATS_FLUSH_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let Some(this) = self.upgrade() else {
+                break;
+            };
+            if let Some(quota) = (**this.current.load())
+                .as_ref()
+                .and_then(|s| s.quota.as_ref())
+            {
+                quota.flush();
+            }
+        }
+    }
+
+    /// Reload the proxy list whenever a frontend reports updated subscriptions,
+    /// and hot-swap the current proxy if its credentials have been rotated.
+    pub async fn run_event_listener(self: Weak<Self>, mut subscription: EventSubscription) {
+        while let Some(record) = subscription.recv().await {
+            if !matches!(record.event, Event::SubscriptionUpdated { .. }) {
+                continue;
+            }
+            let Some(this) = self.upgrade() else {
+                break;
+            };
+            // TODO: log error
+            let _ = this.reload_current_proxy();
         }
     }
 
@@ -69,23 +116,46 @@ impl StreamOutboundFactory for DynOutbound {
         context: &mut FlowContext,
         initial_data: &'_ [u8],
     ) -> FlowResult<(Box<dyn Stream>, Buffer)> {
-        let next = (**self.current.load())
+        let (next, quota) = (**self.current.load())
             .as_ref()
-            .ok_or(FlowError::NoOutbound)?
-            .tcp
-            .clone();
-        next.create_outbound(context, initial_data).await
+            .map(|s| (s.tcp.clone(), s.quota.clone()))
+            .ok_or(FlowError::NoOutbound)?;
+        if let Some(counter) = &quota {
+            counter.check()?;
+        }
+        let (stream, initial_res) = next.create_outbound(context, initial_data).await?;
+        let Some(counter) = quota else {
+            return Ok((stream, initial_res));
+        };
+        counter.add_upload(initial_data.len());
+        counter.add_download(initial_res.len());
+        Ok((
+            Box::new(QuotaStream {
+                lower: stream,
+                counter,
+            }),
+            initial_res,
+        ))
     }
 }
 
 #[async_trait]
 impl DatagramSessionFactory for DynOutbound {
     async fn bind(&self, context: Box<FlowContext>) -> FlowResult<Box<dyn DatagramSession>> {
-        let next = (**self.current.load())
+        let (next, quota) = (**self.current.load())
             .as_ref()
-            .ok_or(FlowError::NoOutbound)?
-            .udp
-            .clone();
-        next.bind(context).await
+            .map(|s| (s.udp.clone(), s.quota.clone()))
+            .ok_or(FlowError::NoOutbound)?;
+        if let Some(counter) = &quota {
+            counter.check()?;
+        }
+        let session = next.bind(context).await?;
+        let Some(counter) = quota else {
+            return Ok(session);
+        };
+        Ok(Box::new(QuotaDatagramSession {
+            lower: session,
+            counter,
+        }))
     }
 }
@@ -0,0 +1,146 @@
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+
+use rand::seq::SliceRandom;
+
+use super::select::{SelectError, Selection};
+use super::DynOutbound;
+use crate::flow::*;
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone)]
+pub enum GroupPolicy {
+    /// The first matching proxy in the order of the group.
+    First,
+    Random,
+    /// The matching proxy with the lowest TCP handshake latency to `probe`.
+    /// Until the first probing round completes, the first matching proxy is used.
+    LowestLatency {
+        probe: DestinationAddr,
+        interval: Duration,
+    },
+}
+
+/// Proxies a [`DynOutbound`] may pick from: those in a named proxy group,
+/// optionally narrowed down to names containing `filter`.
+#[derive(Debug, Clone)]
+pub struct GroupSelector {
+    pub group: String,
+    pub filter: Option<String>,
+    pub policy: GroupPolicy,
+}
+
+impl DynOutbound {
+    /// Indices of matching proxies, suitable for [`DynOutbound::manual_select`].
+    fn group_candidates(&self, selector: &GroupSelector) -> Vec<usize> {
+        let list = self.proxy_list.load();
+        let (proxies, groups) = &**list;
+        let Some(group_id) = groups
+            .iter()
+            .find(|g| g.name == selector.group)
+            .map(|g| g.id)
+        else {
+            return vec![];
+        };
+        let filter = selector.filter.as_ref().map(|f| f.to_lowercase());
+        proxies
+            .iter()
+            .enumerate()
+            .filter(|(_, (p, gid))| {
+                *gid == group_id
+                    && filter
+                        .as_ref()
+                        .map_or(true, |f| p.name.to_lowercase().contains(f))
+            })
+            .map(|(idx, _)| idx + self.fixed_outbounds.len())
+            .collect()
+    }
+
+    fn swap_selection(&self, selection: Selection) {
+        let old_selection = self.current.swap(Arc::new(Some(selection)));
+        if let Some(quota) = (*old_selection).as_ref().and_then(|s| s.quota.as_ref()) {
+            quota.flush();
+        }
+    }
+
+    /// Select a proxy from a group according to the policy of `selector`.
+    pub fn select_from_group(&self, selector: &GroupSelector) -> Result<(), SelectError> {
+        self.load_proxies().map_err(SelectError::LoadProxiesError)?;
+        let candidates = self.group_candidates(selector);
+        let idx = match selector.policy {
+            GroupPolicy::First | GroupPolicy::LowestLatency { .. } => candidates.first(),
+            GroupPolicy::Random => candidates.choose(&mut rand::thread_rng()),
+        }
+        .ok_or(SelectError::NoMatchingProxy)?;
+        let selection = self.load_proxy(*idx)?;
+        self.swap_selection(selection);
+        Ok(())
+    }
+
+    async fn probe_latency(selection: &Selection, probe: &DestinationAddr) -> Option<Duration> {
+        let mut context = FlowContext::new(
+            std::net::SocketAddr::new(std::net::Ipv4Addr::UNSPECIFIED.into(), 0),
+            probe.clone(),
+        );
+        let started = Instant::now();
+        match tokio::time::timeout(
+            PROBE_TIMEOUT,
+            selection.tcp.create_outbound(&mut context, &[]),
+        )
+        .await
+        {
+            Ok(Ok(_)) => Some(started.elapsed()),
+            _ => None,
+        }
+    }
+
+    /// Periodically switch to the matching proxy with the lowest latency.
+    /// Returns immediately unless the policy is [`GroupPolicy::LowestLatency`].
+    pub async fn run_group_prober(self: Weak<Self>, selector: GroupSelector) {
+        let GroupPolicy::LowestLatency { probe, interval } = &selector.policy else {
+            return;
+        };
+        let mut ticker = tokio::time::interval(*interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            let Some(this) = self.upgrade() else {
+                break;
+            };
+            // TODO: log error
+            let _ = this.load_proxies();
+            let candidates = this.group_candidates(&selector);
+            drop(this);
+
+            let mut best: Option<(Duration, Selection)> = None;
+            for idx in candidates {
+                let Some(this) = self.upgrade() else {
+                    return;
+                };
+                let Ok(selection) = this.load_proxy(idx) else {
+                    continue;
+                };
+                drop(this);
+                let Some(latency) = Self::probe_latency(&selection, probe).await else {
+                    continue;
+                };
+                if best.as_ref().map_or(true, |(l, _)| latency < *l) {
+                    best = Some((latency, selection));
+                }
+            }
+            let Some(this) = self.upgrade() else {
+                break;
+            };
+            let Some((_, selection)) = best else {
+                continue;
+            };
+            let unchanged = (**this.current.load())
+                .as_ref()
+                .is_some_and(|s| s.idx == selection.idx);
+            if !unchanged {
+                this.swap_selection(selection);
+            }
+        }
+    }
+}
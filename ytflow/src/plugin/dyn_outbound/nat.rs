@@ -0,0 +1,198 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures::future::poll_fn;
+use serde::Serialize;
+
+use super::select::Selection;
+use super::DynOutbound;
+use crate::flow::*;
+use crate::plugin::stun_keepalive::codec::{
+    encode_binding_request, parse_binding_response, TransactionId,
+};
+
+const NAT_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+const NAT_PROBE_RETRANSMIT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// STUN servers operated by different parties, so that they are unlikely to
+/// share an IP address.
+pub fn default_nat_probe_servers() -> [DestinationAddr; 2] {
+    [("stun.l.google.com", 19302), ("stun.cloudflare.com", 3478)].map(|(host, port)| {
+        DestinationAddr {
+            host: HostName::from_domain_name(host.into()).unwrap(),
+            port,
+        }
+    })
+}
+
+/// How the upstream path of a proxy maps UDP sessions, as seen by two STUN
+/// servers from the same session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NatBehavior {
+    /// Both servers saw the same mapped address.
+    EndpointIndependent,
+    /// Each server saw a different mapped address. Peer-to-peer traffic such
+    /// as games and voice calls is likely to fail.
+    Symmetric,
+    /// Neither server responded. The proxy may not relay UDP at all.
+    UdpBlocked,
+    /// Only one server responded.
+    Inconclusive,
+}
+
+impl NatBehavior {
+    pub fn classify(mapped: &[Option<SocketAddr>; 2]) -> Self {
+        match mapped {
+            [Some(a), Some(b)] if a == b => Self::EndpointIndependent,
+            [Some(_), Some(_)] => Self::Symmetric,
+            [None, None] => Self::UdpBlocked,
+            _ => Self::Inconclusive,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NatVerdict {
+    pub proxy_name: String,
+    /// `None` while the probe is running.
+    pub behavior: Option<NatBehavior>,
+    /// Mapped addresses reported by each server.
+    pub mapped: [Option<SocketAddr>; 2],
+    /// Seconds since the Unix epoch when the probe started.
+    pub probed_at: u64,
+}
+
+/// Send STUN Binding Requests to both `servers` from a single session bound via
+/// `selection`, and collect the mapped addresses they report.
+async fn probe_mapped_addrs(
+    selection: &Selection,
+    servers: &[DestinationAddr; 2],
+) -> [Option<SocketAddr>; 2] {
+    let context = FlowContext::new(
+        SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0),
+        servers[0].clone(),
+    );
+    let mut mapped = [None, None];
+    let Ok(Ok(mut session)) =
+        tokio::time::timeout(NAT_PROBE_TIMEOUT, selection.udp.bind(Box::new(context))).await
+    else {
+        return mapped;
+    };
+    let txids: [TransactionId; 2] = [rand::random(), rand::random()];
+    let deadline = tokio::time::sleep(NAT_PROBE_TIMEOUT);
+    tokio::pin!(deadline);
+    let mut retransmit = tokio::time::interval(NAT_PROBE_RETRANSMIT_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = &mut deadline => break,
+            _ = retransmit.tick() => {
+                for ((server, txid), addr) in servers.iter().zip(&txids).zip(&mapped) {
+                    if addr.is_some() {
+                        continue;
+                    }
+                    poll_fn(|cx| session.poll_send_ready(cx)).await;
+                    session.send_to(server.clone(), encode_binding_request(txid));
+                }
+            }
+            res = poll_fn(|cx| session.poll_recv_from(cx)) => {
+                let Some((_, buf)) = res else {
+                    break;
+                };
+                for (txid, addr) in txids.iter().zip(&mut mapped) {
+                    if let Some(a) = parse_binding_response(&buf, txid) {
+                        *addr = Some(a);
+                    }
+                }
+                if mapped.iter().all(Option::is_some) {
+                    break;
+                }
+            }
+        }
+    }
+    let _ = poll_fn(|cx| session.poll_shutdown(cx)).await;
+    mapped
+}
+
+impl DynOutbound {
+    /// Start probing the NAT behavior of the proxy at `idx`. The verdict can be
+    /// retrieved from [`DynOutbound::nat_verdicts`] once the probe completes.
+    pub fn probe_nat(
+        self: &Arc<Self>,
+        idx: usize,
+        servers: [DestinationAddr; 2],
+    ) -> Result<(), super::select::SelectError> {
+        let selection = if idx >= self.fixed_outbounds.len() {
+            self.load_proxy(idx)?
+        } else {
+            self.load_fixed_outbound(idx)?
+        };
+        let probed_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        self.nat_verdicts.lock().unwrap().insert(
+            idx,
+            NatVerdict {
+                proxy_name: selection.name.clone(),
+                behavior: None,
+                mapped: [None, None],
+                probed_at,
+            },
+        );
+        let this: Weak<Self> = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mapped = probe_mapped_addrs(&selection, &servers).await;
+            let Some(this) = this.upgrade() else {
+                return;
+            };
+            let mut verdicts = this.nat_verdicts.lock().unwrap();
+            // Skip if a newer probe has taken over
+            if let Some(verdict) = verdicts
+                .get_mut(&idx)
+                .filter(|v| v.probed_at == probed_at && v.behavior.is_none())
+            {
+                verdict.behavior = Some(NatBehavior::classify(&mapped));
+                verdict.mapped = mapped;
+            }
+        });
+        Ok(())
+    }
+
+    /// Results of NAT probes keyed by proxy index.
+    pub fn nat_verdicts(&self) -> Vec<(usize, NatVerdict)> {
+        self.nat_verdicts
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(idx, v)| (*idx, v.clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let a: SocketAddr = "203.0.113.1:40000".parse().unwrap();
+        let b: SocketAddr = "203.0.113.1:40001".parse().unwrap();
+        assert_eq!(
+            NatBehavior::classify(&[Some(a), Some(a)]),
+            NatBehavior::EndpointIndependent
+        );
+        assert_eq!(
+            NatBehavior::classify(&[Some(a), Some(b)]),
+            NatBehavior::Symmetric
+        );
+        assert_eq!(
+            NatBehavior::classify(&[None, None]),
+            NatBehavior::UdpBlocked
+        );
+        assert_eq!(
+            NatBehavior::classify(&[None, Some(b)]),
+            NatBehavior::Inconclusive
+        );
+    }
+}
@@ -0,0 +1,439 @@
+use std::io;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use crate::atomic::AtomicU64;
+use crate::control::events::{Event, EventBus};
+use crate::data::{Database, ProfileId, ProxyGroupId, ProxyId, TrafficQuota, TrafficStat};
+use crate::flow::*;
+
+/// Pending traffic is written back to the database once it reaches this size,
+/// or when a connection carrying it is closed.
+const FLUSH_THRESHOLD: u64 = 4 * 1024 * 1024;
+
+pub(super) struct QuotaCounter {
+    db: Database,
+    proxy_id: ProxyId,
+    proxy_group_id: ProxyGroupId,
+    profile_id: Option<ProfileId>,
+    pending_upload: AtomicU64,
+    pending_download: AtomicU64,
+    // Tracked separately so that a failure to write quota usage does not
+    // count the same traffic twice in the statistics, and vice versa.
+    pending_stat_upload: AtomicU64,
+    pending_stat_download: AtomicU64,
+    flushing: AtomicBool,
+    // Set under the lock of `quotas` once they hold values from the database,
+    // so that a slow initial load cannot overwrite fresher usage from a flush.
+    quotas_loaded: AtomicBool,
+    pub(super) quotas: Mutex<Vec<TrafficQuota>>,
+    events: EventBus,
+}
+
+impl QuotaCounter {
+    pub(super) fn new(
+        db: Database,
+        proxy_id: ProxyId,
+        proxy_group_id: ProxyGroupId,
+        profile_id: Option<ProfileId>,
+        events: EventBus,
+    ) -> Self {
+        Self {
+            db,
+            proxy_id,
+            proxy_group_id,
+            profile_id,
+            pending_upload: AtomicU64::new(0),
+            pending_download: AtomicU64::new(0),
+            pending_stat_upload: AtomicU64::new(0),
+            pending_stat_download: AtomicU64::new(0),
+            flushing: AtomicBool::new(false),
+            quotas_loaded: AtomicBool::new(false),
+            quotas: Mutex::new(vec![]),
+            events,
+        }
+    }
+
+    /// Read the quotas of the proxy and its group from the database in the
+    /// background. Flows are not limited until the quotas are loaded.
+    pub(super) fn load_quotas(self: &Arc<Self>) {
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            self.load_quotas_blocking();
+            return;
+        };
+        let this = self.clone();
+        handle.spawn_blocking(move || this.load_quotas_blocking());
+    }
+
+    fn load_quotas_blocking(&self) {
+        let res = self.db.connect().and_then(|conn| {
+            Ok([
+                TrafficQuota::query_by_proxy(self.proxy_id, &conn)?,
+                TrafficQuota::query_by_proxy_group(self.proxy_group_id, &conn)?,
+            ])
+        });
+        let Ok(quotas) = res else {
+            // TODO: log error
+            return;
+        };
+        let mut guard = self.quotas.lock().unwrap();
+        if !self.quotas_loaded.swap(true, Ordering::Relaxed) {
+            *guard = quotas.into_iter().flatten().collect();
+        }
+    }
+
+    pub(super) fn is_exceeded(&self) -> bool {
+        self.quotas.lock().unwrap().iter().any(|q| q.is_exceeded())
+    }
+
+    /// Fails if any quota of the proxy or its group has been used up.
+    pub(super) fn check(&self) -> FlowResult<()> {
+        if self.is_exceeded() {
+            return Err(quota_exceeded_error());
+        }
+        Ok(())
+    }
+
+    pub(super) fn add_upload(self: &Arc<Self>, len: usize) {
+        self.pending_stat_upload
+            .fetch_add(len as u64, Ordering::Relaxed);
+        let pending = self
+            .pending_upload
+            .fetch_add(len as u64, Ordering::Relaxed)
+            .saturating_add(len as u64);
+        if pending >= FLUSH_THRESHOLD {
+            self.flush();
+        }
+    }
+
+    pub(super) fn add_download(self: &Arc<Self>, len: usize) {
+        self.pending_stat_download
+            .fetch_add(len as u64, Ordering::Relaxed);
+        let pending = self
+            .pending_download
+            .fetch_add(len as u64, Ordering::Relaxed)
+            .saturating_add(len as u64);
+        if pending >= FLUSH_THRESHOLD {
+            self.flush();
+        }
+    }
+
+    pub(super) fn flush(self: &Arc<Self>) {
+        if self.flushing.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            self.flushing.store(false, Ordering::Release);
+            return;
+        };
+        let this = self.clone();
+        handle.spawn_blocking(move || {
+            this.flush_blocking();
+            this.flushing.store(false, Ordering::Release);
+        });
+    }
+
+    pub(super) fn flush_blocking(&self) {
+        self.flush_stats_blocking();
+        let upload = self.pending_upload.swap(0, Ordering::Relaxed);
+        let download = self.pending_download.swap(0, Ordering::Relaxed);
+        if upload == 0 && download == 0 {
+            return;
+        }
+        let res = self.db.connect().and_then(|mut conn| {
+            TrafficQuota::add_usage(
+                self.proxy_id,
+                self.proxy_group_id,
+                upload,
+                download,
+                &mut conn,
+            )
+        });
+        match res {
+            Ok(quotas) => {
+                let mut guard = self.quotas.lock().unwrap();
+                self.quotas_loaded.store(true, Ordering::Relaxed);
+                let old_quotas = std::mem::replace(&mut *guard, quotas.clone());
+                drop(guard);
+                for event in threshold_events(&old_quotas, &quotas) {
+                    self.events.publish(event);
+                }
+            }
+            Err(_) => {
+                // TODO: log error
+                // Keep the traffic around so that it can be written next time.
+                self.pending_upload.fetch_add(upload, Ordering::Relaxed);
+                self.pending_download.fetch_add(download, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn flush_stats_blocking(&self) {
+        let upload = self.pending_stat_upload.swap(0, Ordering::Relaxed);
+        let download = self.pending_stat_download.swap(0, Ordering::Relaxed);
+        if upload == 0 && download == 0 {
+            return;
+        }
+        let date = chrono::Local::now().date_naive();
+        let res = self.db.connect().and_then(|mut conn| {
+            TrafficStat::add(
+                date,
+                self.profile_id,
+                Some(self.proxy_id),
+                upload,
+                download,
+                &mut conn,
+            )
+        });
+        if res.is_err() {
+            // TODO: log error
+            self.pending_stat_upload
+                .fetch_add(upload, Ordering::Relaxed);
+            self.pending_stat_download
+                .fetch_add(download, Ordering::Relaxed);
+        }
+    }
+}
+
+fn quota_exceeded_error() -> FlowError {
+    io::Error::new(io::ErrorKind::ConnectionRefused, "Traffic quota exceeded").into()
+}
+
+/// Events for quotas whose usage has crossed the warning level or the limit
+/// between `old` and `new`.
+fn threshold_events(old: &[TrafficQuota], new: &[TrafficQuota]) -> Vec<Event> {
+    new.iter()
+        .filter_map(|quota| {
+            let old = old.iter().find(|q| q.id == quota.id);
+            let exceeded = quota.is_exceeded();
+            let crossed = if exceeded {
+                !old.is_some_and(|q| q.is_exceeded())
+            } else {
+                quota.is_approaching_limit() && !old.is_some_and(|q| q.is_approaching_limit())
+            };
+            crossed.then(|| Event::QuotaThresholdReached {
+                quota_id: quota.id.0,
+                bytes_used: quota.bytes_used(),
+                bytes_limit: quota.bytes_limit,
+                exceeded,
+            })
+        })
+        .collect()
+}
+
+impl Drop for QuotaCounter {
+    fn drop(&mut self) {
+        // Last chance to persist traffic when the selection is switched away
+        // and all connections are gone, or when the plugin set shuts down.
+        self.flush_blocking();
+    }
+}
+
+pub(super) struct QuotaStream {
+    pub(super) lower: Box<dyn Stream>,
+    pub(super) counter: Arc<QuotaCounter>,
+}
+
+impl Stream for QuotaStream {
+    fn poll_request_size(&mut self, cx: &mut Context<'_>) -> Poll<FlowResult<SizeHint>> {
+        self.lower.poll_request_size(cx)
+    }
+
+    fn commit_rx_buffer(&mut self, buffer: Buffer) -> Result<(), (Buffer, FlowError)> {
+        self.lower.commit_rx_buffer(buffer)
+    }
+
+    fn poll_rx_buffer(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Buffer, (Buffer, FlowError)>> {
+        let res = self.lower.poll_rx_buffer(cx);
+        if let Poll::Ready(Ok(buf)) = &res {
+            self.counter.add_download(buf.len());
+        }
+        res
+    }
+
+    fn poll_tx_buffer(
+        &mut self,
+        cx: &mut Context<'_>,
+        size: NonZeroUsize,
+    ) -> Poll<FlowResult<Buffer>> {
+        if let Err(e) = self.counter.check() {
+            return Poll::Ready(Err(e));
+        }
+        self.lower.poll_tx_buffer(cx, size)
+    }
+
+    fn commit_tx_buffer(&mut self, buffer: Buffer) -> FlowResult<()> {
+        self.counter.add_upload(buffer.len());
+        self.lower.commit_tx_buffer(buffer)
+    }
+
+    fn poll_flush_tx(&mut self, cx: &mut Context<'_>) -> Poll<FlowResult<()>> {
+        self.lower.poll_flush_tx(cx)
+    }
+
+    fn poll_close_tx(&mut self, cx: &mut Context<'_>) -> Poll<FlowResult<()>> {
+        self.lower.poll_close_tx(cx)
+    }
+}
+
+impl Drop for QuotaStream {
+    fn drop(&mut self) {
+        self.counter.flush();
+    }
+}
+
+pub(super) struct QuotaDatagramSession {
+    pub(super) lower: Box<dyn DatagramSession>,
+    pub(super) counter: Arc<QuotaCounter>,
+}
+
+impl DatagramSession for QuotaDatagramSession {
+    fn poll_recv_from(&mut self, cx: &mut Context) -> Poll<Option<(DestinationAddr, Buffer)>> {
+        let res = self.lower.poll_recv_from(cx);
+        if let Poll::Ready(Some((_, buf))) = &res {
+            self.counter.add_download(buf.len());
+        }
+        res
+    }
+    fn poll_send_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        self.lower.poll_send_ready(cx)
+    }
+    fn send_to(&mut self, remote_peer: DestinationAddr, buf: Buffer) {
+        if self.counter.is_exceeded() {
+            return;
+        }
+        self.counter.add_upload(buf.len());
+        self.lower.send_to(remote_peer, buf)
+    }
+    fn poll_shutdown(&mut self, cx: &mut Context<'_>) -> Poll<FlowResult<()>> {
+        self.lower.poll_shutdown(cx)
+    }
+}
+
+impl Drop for QuotaDatagramSession {
+    fn drop(&mut self) {
+        self.counter.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::data::{Proxy, ProxyGroup};
+
+    struct TempDb(PathBuf);
+
+    impl Drop for TempDb {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn counter_with_quota(name: &str, bytes_limit: u64) -> (TempDb, Arc<QuotaCounter>) {
+        let path =
+            std::env::temp_dir().join(format!("ytflow-quota-{}-{}.db", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let temp = TempDb(path);
+        let db = Database::open(&temp.0).unwrap();
+        let conn = db.connect().unwrap();
+        let group_id = ProxyGroup::create("g".into(), "manual".into(), &conn).unwrap();
+        let proxy_id = Proxy::create(group_id.into(), "p".into(), vec![], 0, &conn).unwrap();
+        TrafficQuota::create_for_proxy(proxy_id.into(), bytes_limit, 80, 1, &conn).unwrap();
+        let counter = Arc::new(QuotaCounter::new(
+            db,
+            proxy_id.into(),
+            group_id.into(),
+            None,
+            EventBus::new(),
+        ));
+        (temp, counter)
+    }
+
+    #[test]
+    fn test_counter_refuses_when_exceeded() {
+        let (_temp, counter) = counter_with_quota("exceeded", 100);
+        assert!(counter.quotas.lock().unwrap().is_empty());
+        counter.load_quotas();
+        assert_eq!(counter.quotas.lock().unwrap().len(), 1);
+        assert!(counter.check().is_ok());
+
+        counter.add_upload(60);
+        counter.add_download(50);
+        counter.flush_blocking();
+        assert!(counter.is_exceeded());
+        let err = counter.check().unwrap_err();
+        assert!(matches!(err, FlowError::Io(e) if e.kind() == io::ErrorKind::ConnectionRefused));
+    }
+
+    #[test]
+    fn test_load_does_not_overwrite_flushed_usage() {
+        let (_temp, counter) = counter_with_quota("overwrite", 100);
+        counter.add_upload(120);
+        counter.flush_blocking();
+        assert!(counter.is_exceeded());
+        // A load that finishes after the flush must not bring back stale usage.
+        *counter.quotas.lock().unwrap() = vec![quota(0)];
+        counter.load_quotas_blocking();
+        assert_eq!(counter.quotas.lock().unwrap()[0].bytes_used(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_quota_stream_fails_when_exceeded() {
+        let (_temp, counter) = counter_with_quota("stream", 100);
+        counter.load_quotas_blocking();
+        let (lower, _remote) = crate::flow::testing::stream_pair();
+        let mut stream = QuotaStream {
+            lower,
+            counter: counter.clone(),
+        };
+        let size = NonZeroUsize::new(1).unwrap();
+        let res = futures::future::poll_fn(|cx| stream.poll_tx_buffer(cx, size)).await;
+        assert!(res.is_ok());
+
+        counter.add_upload(100);
+        counter.flush_blocking();
+        let res = futures::future::poll_fn(|cx| stream.poll_tx_buffer(cx, size)).await;
+        assert!(res.is_err());
+    }
+
+    fn quota(used: u64) -> TrafficQuota {
+        TrafficQuota {
+            id: 1.into(),
+            proxy_id: None,
+            proxy_group_id: None,
+            bytes_limit: 100,
+            warn_percent: 80,
+            reset_day: 1,
+            upload_bytes_used: used,
+            download_bytes_used: 0,
+            period_started_at: Default::default(),
+        }
+    }
+
+    fn exceeded_flags(old: u64, new: u64) -> Vec<bool> {
+        threshold_events(&[quota(old)], &[quota(new)])
+            .into_iter()
+            .map(|e| match e {
+                Event::QuotaThresholdReached { exceeded, .. } => exceeded,
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_threshold_events() {
+        assert!(exceeded_flags(10, 20).is_empty());
+        assert_eq!(exceeded_flags(70, 80), [false]);
+        assert!(exceeded_flags(80, 90).is_empty());
+        assert_eq!(exceeded_flags(90, 100), [true]);
+        assert_eq!(exceeded_flags(10, 120), [true]);
+        assert!(exceeded_flags(100, 120).is_empty());
+    }
+}
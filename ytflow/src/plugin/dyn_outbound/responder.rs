@@ -1,9 +1,11 @@
 use std::sync::Arc;
 
 use cbor4ii::serde::{from_slice, to_vec};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use crate::control::{PluginRequestError, PluginRequestResult, PluginResponder};
+use crate::data::TrafficQuota;
+use crate::flow::DestinationAddr;
 
 pub struct Responder {
     dyn_outbound: Arc<super::DynOutbound>,
@@ -19,6 +21,10 @@ impl Responder {
 struct Info {
     current_proxy_idx: Option<u32>,
     current_proxy_name: Option<String>,
+    quotas: Vec<TrafficQuota>,
+    quota_alert: bool,
+    /// NAT behavior of the current proxy, if it has been probed.
+    current_proxy_nat: Option<super::NatBehavior>,
 }
 #[derive(Serialize)]
 struct ProxyListItem<'a> {
@@ -33,6 +39,19 @@ struct FixedOutboundItem<'a> {
     name: &'a str,
     idx: u32,
 }
+#[derive(Deserialize)]
+struct ProbeNatParams {
+    idx: u32,
+    /// Two STUN servers with distinct IP addresses.
+    #[serde(default)]
+    servers: Option<[DestinationAddr; 2]>,
+}
+#[derive(Serialize)]
+struct NatVerdictItem {
+    idx: u32,
+    #[serde(flatten)]
+    verdict: super::NatVerdict,
+}
 #[derive(Serialize)]
 struct ListProxiesRes<'a> {
     proxies: Vec<ProxyListItem<'a>>,
@@ -41,13 +60,31 @@ struct ListProxiesRes<'a> {
 
 impl PluginResponder for Responder {
     fn collect_info(&self, _hashcode: &mut u32) -> Option<Vec<u8>> {
-        let (current_proxy_idx, current_proxy_name) = (**self.dyn_outbound.current.load())
+        let current = self.dyn_outbound.current.load();
+        let (current_proxy_idx, current_proxy_name) = (**current)
             .as_ref()
             .map(|c| (c.idx as u32, c.name.clone()))
             .unzip();
+        let quotas = (**current)
+            .as_ref()
+            .and_then(|c| c.quota.as_ref())
+            .map(|q| q.quotas.lock().unwrap().clone())
+            .unwrap_or_default();
+        let quota_alert = quotas.iter().any(|q| q.is_approaching_limit());
+        let current_proxy_nat = current_proxy_idx.and_then(|idx| {
+            self.dyn_outbound
+                .nat_verdicts
+                .lock()
+                .unwrap()
+                .get(&(idx as usize))
+                .and_then(|v| v.behavior)
+        });
         let info = Info {
             current_proxy_idx,
             current_proxy_name,
+            quotas,
+            quota_alert,
+            current_proxy_nat,
         };
         Some(to_vec(vec![], &info).unwrap())
     }
@@ -63,6 +100,35 @@ impl PluginResponder for Responder {
                     .map(|e| format!("{}", e));
                 to_vec(vec![], &err).unwrap()
             }
+            "reload_current_proxy" => {
+                let res = self
+                    .dyn_outbound
+                    .reload_current_proxy()
+                    .map_err(|e| format!("{}", e));
+                to_vec(vec![], &res).unwrap()
+            }
+            "probe_nat" => {
+                let ProbeNatParams { idx, servers } = from_slice(params)?;
+                let servers = servers.unwrap_or_else(super::default_nat_probe_servers);
+                let err = self
+                    .dyn_outbound
+                    .probe_nat(idx as usize, servers)
+                    .err()
+                    .map(|e| format!("{}", e));
+                to_vec(vec![], &err).unwrap()
+            }
+            "list_nat_verdicts" => {
+                let verdicts = self
+                    .dyn_outbound
+                    .nat_verdicts()
+                    .into_iter()
+                    .map(|(idx, verdict)| NatVerdictItem {
+                        idx: idx as u32,
+                        verdict,
+                    })
+                    .collect::<Vec<_>>();
+                to_vec(vec![], &verdicts).unwrap()
+            }
             "list_proxies" => {
                 // TODO: log errors
                 let _ = self.dyn_outbound.load_proxies();
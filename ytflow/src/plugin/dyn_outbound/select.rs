@@ -5,8 +5,10 @@ use itertools::Itertools;
 use thiserror::Error;
 
 use super::config::v1;
+use super::quota::QuotaCounter;
 use super::PLUGIN_CACHE_KEY_LAST_SELECT;
 use crate::config::PluginSet;
+use crate::data;
 use crate::flow::{DatagramSessionFactory, StreamOutboundFactory};
 use crate::plugin::null::Null;
 
@@ -15,6 +17,9 @@ pub(super) struct Selection {
     pub(super) name: String,
     pub(super) tcp: Arc<dyn StreamOutboundFactory>,
     pub(super) udp: Arc<dyn DatagramSessionFactory>,
+    pub(super) quota: Option<Arc<QuotaCounter>>,
+    /// The proxy ID and the proxy data this selection was loaded from.
+    pub(super) proxy: Option<(data::ProxyId, serde_bytes::ByteBuf)>,
     _plugin_set: Option<PluginSet>, // Keep dependent plugins alive
 }
 
@@ -24,6 +29,8 @@ pub enum SelectError {
     BadProxyVersion(u16),
     #[error("proxy not found")]
     ProxyNotFound,
+    #[error("no proxy in the group matches")]
+    NoMatchingProxy,
     #[error("failed to parse proxy")]
     ProxyParseError,
     #[error("no outbound")]
@@ -36,6 +43,8 @@ pub enum SelectError {
     BadPluginName,
     #[error("tcp/udp entry point not found: {0}")]
     EntrypointNotFound(String),
+    #[error("error loading proxies: {0}")]
+    LoadProxiesError(data::DataError),
 }
 
 impl super::DynOutbound {
@@ -45,11 +54,52 @@ impl super::DynOutbound {
         } else {
             self.load_fixed_outbound(idx)?
         };
-        self.current.store(Arc::new(Some(new_selection)));
+        let old_selection = self.current.swap(Arc::new(Some(new_selection)));
+        if let Some(quota) = (*old_selection).as_ref().and_then(|s| s.quota.as_ref()) {
+            quota.flush();
+        }
         // TODO: log error
         let _ = self.plugin_cache.set(PLUGIN_CACHE_KEY_LAST_SELECT, &idx);
         Ok(())
     }
+    /// Reload the currently selected proxy if its data has changed in the
+    /// database, e.g. after credentials are rotated by a subscription update.
+    /// Traffic statistics of the current proxy are carried over. Returns
+    /// whether the selection has been swapped.
+    pub fn reload_current_proxy(&self) -> Result<bool, SelectError> {
+        self.load_proxies().map_err(SelectError::LoadProxiesError)?;
+        let current = self.current.load_full();
+        let Some((current_sel, (proxy_id, proxy))) = (*current)
+            .as_ref()
+            .and_then(|s| s.proxy.as_ref().map(|p| (s, p)))
+        else {
+            return Ok(false);
+        };
+        let list = self.proxy_list.load();
+        let Some(pos) = list.0.iter().position(|(p, _)| p.id == *proxy_id) else {
+            return Ok(false);
+        };
+        if list.0[pos].0.proxy == *proxy {
+            return Ok(false);
+        }
+        drop(list);
+        let mut new_selection = self.load_proxy(pos + self.fixed_outbounds.len())?;
+        if let Some(quota) = current_sel.quota.clone() {
+            new_selection.quota = Some(quota);
+        }
+        let prev = self
+            .current
+            .compare_and_swap(&current, Arc::new(Some(new_selection)));
+        if !Arc::ptr_eq(&*prev, &current) {
+            // Another selection took place in the meantime
+            return Ok(false);
+        }
+        let _ = self.plugin_cache.set(
+            PLUGIN_CACHE_KEY_LAST_SELECT,
+            &(pos + self.fixed_outbounds.len()),
+        );
+        Ok(true)
+    }
     pub(super) fn load_fixed_outbound(&self, idx: usize) -> Result<Selection, SelectError> {
         let outbound = self
             .fixed_outbounds
@@ -62,16 +112,18 @@ impl super::DynOutbound {
             name: outbound.name.to_owned(),
             tcp,
             udp,
+            quota: None,
+            proxy: None,
             _plugin_set: None,
         })
     }
     pub(super) fn load_proxy(&self, idx: usize) -> Result<Selection, SelectError> {
-        let (proxy, version, name) = self
+        let (proxy, version, name, proxy_id, group_id) = self
             .proxy_list
             .load()
             .0
             .get(idx - self.fixed_outbounds.len())
-            .map(|(p, _)| (p.proxy.clone(), p.proxy_version, p.name.clone()))
+            .map(|(p, gid)| (p.proxy.clone(), p.proxy_version, p.name.clone(), p.id, *gid))
             .ok_or(SelectError::ProxyNotFound)?;
         if version != 0 {
             return Err(SelectError::BadProxyVersion(version));
@@ -81,6 +133,7 @@ impl super::DynOutbound {
             tcp_entry,
             udp_entry,
         } = cbor4ii::serde::from_slice(&proxy).map_err(|_| SelectError::ProxyParseError)?;
+        let proxy = Some((proxy_id, proxy));
         if plugins
             .iter()
             .any(|p| p.name.is_empty() || v1::BUILTIN_PLUGIN_NAMES.contains(&&*p.name))
@@ -130,11 +183,22 @@ impl super::DynOutbound {
             .transpose()?
             .unwrap_or(udp_next);
 
+        let quota = Arc::new(QuotaCounter::new(
+            self.db.clone(),
+            proxy_id,
+            group_id,
+            self.profile_id,
+            self.events.clone(),
+        ));
+        quota.load_quotas();
+
         Ok(Selection {
             idx,
             name,
             tcp,
             udp,
+            quota: Some(quota),
+            proxy,
             _plugin_set: Some(load_res.plugin_set),
         })
     }
@@ -1,4 +1,5 @@
 use std::collections::BTreeMap;
+use std::net::{Ipv4Addr, Ipv6Addr};
 use std::num::NonZeroUsize;
 use std::sync::{Arc, Mutex};
 
@@ -8,10 +9,13 @@ use serde::{Deserialize, Serialize};
 use smallvec::smallvec;
 use tokio::sync::Notify;
 
+use crate::control::CacheShrink;
 use crate::data::PluginCache;
 use crate::flow::*;
 
 const CACHE_CAPACITY: NonZeroUsize = NonZeroUsize::new(1000).unwrap();
+/// Number of most recently used mappings kept when the cache is shrunk.
+const SHRUNK_CACHE_LEN: usize = CACHE_CAPACITY.get() / 4;
 const PLUGIN_CACHE_KEY: &str = "map";
 
 struct Inner {
@@ -63,6 +67,9 @@ impl FakeIp {
             new_notify: Arc::new(Notify::new()),
         }
     }
+    fn lookup(&self, domain: &str) -> Option<u16> {
+        self.inner.lock().unwrap().cache.get(domain).copied()
+    }
     fn lookup_or_alloc(&self, domain: String) -> u16 {
         let ret = {
             let mut inner = self.inner.lock().unwrap();
@@ -88,23 +95,40 @@ impl FakeIp {
         };
         self.plugin_cache.set(PLUGIN_CACHE_KEY, &cache).ok();
     }
+
+    fn to_ipv4(&self, index: u16) -> Ipv4Addr {
+        (((self.prefix_v4 as u32) << 16) | (index as u32)).into()
+    }
+    fn to_ipv6(&self, index: u16) -> Ipv6Addr {
+        let mut bytes = [0; 16];
+        bytes[..14].copy_from_slice(&self.prefix_v6);
+        bytes[14..].copy_from_slice(&index.to_be_bytes());
+        bytes.into()
+    }
 }
 
 #[async_trait]
 impl Resolver for FakeIp {
     async fn resolve_ipv4(&self, domain: String) -> ResolveResultV4 {
-        Ok(smallvec![(((self.prefix_v4 as u32) << 16)
-            | (self.lookup_or_alloc(domain) as u32))
-            .to_be_bytes()
-            .into()])
+        Ok(smallvec![self.to_ipv4(self.lookup_or_alloc(domain))])
     }
     async fn resolve_ipv6(&self, domain: String) -> ResolveResultV6 {
-        let mut bytes = [0; 16];
-        bytes[..14].copy_from_slice(&self.prefix_v6);
-        let index = self.lookup_or_alloc(domain);
-        bytes[14] = (index >> 8) as u8;
-        bytes[15] = (index & 0xFF) as u8;
-        Ok(smallvec![bytes.into()])
+        Ok(smallvec![self.to_ipv6(self.lookup_or_alloc(domain))])
+    }
+    fn try_resolve_ipv4_sync(&self, domain: &str) -> Option<ResolvedV4> {
+        Some(smallvec![self.to_ipv4(self.lookup(domain)?)])
+    }
+    fn try_resolve_ipv6_sync(&self, domain: &str) -> Option<ResolvedV6> {
+        Some(smallvec![self.to_ipv6(self.lookup(domain)?)])
+    }
+}
+
+impl CacheShrink for FakeIp {
+    fn shrink_cache(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        while inner.cache.len() > SHRUNK_CACHE_LEN {
+            inner.cache.pop_lru();
+        }
     }
 }
 
@@ -69,12 +69,7 @@ pub struct FallbackHandler {
 impl StreamHandler for FallbackHandler {
     fn on_stream(&self, lower: Box<dyn Stream>, initial_data: Buffer, context: Box<FlowContext>) {
         let fallback = self.fallback.clone();
-        let context_clone = Box::new(FlowContext {
-            local_peer: context.local_peer,
-            remote_peer: context.remote_peer.clone(),
-            af_sensitive: context.af_sensitive,
-            application_layer_protocol: context.application_layer_protocol.clone(),
-        });
+        let context_clone = Box::new(context.fork());
         let next = match self.next.upgrade() {
             Some(n) => n,
             None => return,
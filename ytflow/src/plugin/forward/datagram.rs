@@ -1,5 +1,5 @@
 use std::sync::atomic::Ordering;
-use std::sync::Weak;
+use std::sync::{Arc, Weak};
 use std::task::Poll;
 
 use futures::future::poll_fn;
@@ -8,8 +8,11 @@ use super::StatHandle;
 use crate::flow::*;
 
 pub struct DatagramForwardHandler {
+    pub plugin_name: Arc<str>,
     pub outbound: Weak<dyn DatagramSessionFactory>,
+    pub outbound_name: Arc<str>,
     pub stat: StatHandle,
+    pub connections: ConnectionTracker,
 }
 
 impl DatagramSessionHandler for DatagramForwardHandler {
@@ -18,8 +21,18 @@ impl DatagramSessionHandler for DatagramForwardHandler {
             Some(o) => o,
             None => return,
         };
+        let conn = self.connections.track(
+            "udp",
+            context.local_peer,
+            context.remote_peer.clone(),
+            self.plugin_name.clone(),
+            self.outbound_name.clone(),
+        );
+        let closed = conn.close_signal();
         let stat = self.stat.clone();
-        tokio::spawn(async move {
+        let plugin_name = self.plugin_name.clone();
+        let crash_stat = self.stat.clone();
+        let task = async move {
             let mut lower = outbound.bind(context).await?;
             struct StatCountGuard(StatHandle);
             impl Drop for StatCountGuard {
@@ -49,6 +62,8 @@ impl DatagramSessionHandler for DatagramForwardHandler {
                                     .inner
                                     .uplink_written
                                     .fetch_add(len as u64, Ordering::Relaxed);
+                                conn.uplink().fetch_add(len as u64, Ordering::Relaxed);
+                                crate::log::probe::outbound_send(len);
                                 continue;
                             }
                             Poll::Pending => uplink_buf = Some((addr, buf)),
@@ -70,6 +85,8 @@ impl DatagramSessionHandler for DatagramForwardHandler {
                                     .inner
                                     .downlink_written
                                     .fetch_add(len as u64, Ordering::Relaxed);
+                                conn.downlink().fetch_add(len as u64, Ordering::Relaxed);
+                                crate::log::probe::inbound_send(len);
                                 continue;
                             }
                             Poll::Pending => downlink_buf = Some((addr, buf)),
@@ -92,6 +109,17 @@ impl DatagramSessionHandler for DatagramForwardHandler {
             )
             .await?;
             FlowResult::Ok(())
+        };
+        // Closing the session from the tracker drops both ends.
+        let task = async move { closed.guard(task).await };
+        tokio::spawn(async move {
+            let session_id = crate::log::next_session_id();
+            if crate::log::isolate(&plugin_name, session_id, task)
+                .await
+                .is_none()
+            {
+                crash_stat.inner.crash_count.fetch_add(1, Ordering::Relaxed);
+            }
         });
     }
 }
@@ -12,6 +12,7 @@ struct StatInfo {
     downlink_written: u64,
     tcp_connection_count: u32,
     udp_session_count: u32,
+    crash_count: u32,
 }
 
 pub struct Responder {
@@ -35,6 +36,7 @@ fn stat_snapshot(stat: &StatHandle) -> StatInfo {
         downlink_written: inner.downlink_written.load(Ordering::Relaxed),
         tcp_connection_count: inner.tcp_connection_count.load(Ordering::Relaxed),
         udp_session_count: inner.udp_session_count.load(Ordering::Relaxed),
+        crash_count: inner.crash_count.load(Ordering::Relaxed),
     }
 }
 
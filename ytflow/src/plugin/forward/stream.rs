@@ -1,7 +1,7 @@
 use std::future::Future;
 use std::num::NonZeroUsize;
 use std::pin::Pin;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::Ordering;
 use std::sync::{Arc, Weak};
 use std::task::{Context, Poll};
 
@@ -9,6 +9,7 @@ use futures::{future::poll_fn, ready};
 use tokio::time::timeout;
 
 use super::StatHandle;
+use crate::atomic::AtomicU64;
 use crate::flow::*;
 
 enum ForwardState {
@@ -27,6 +28,7 @@ struct StreamForward<'l, 'r> {
     uplink_state: ForwardState,
     downlink_state: ForwardState,
     stat: StatGuard,
+    conn: &'l TrackedConnection,
 }
 
 impl Drop for StatGuard {
@@ -38,12 +40,19 @@ impl Drop for StatGuard {
     }
 }
 
+fn add_len(counters: [&AtomicU64; 2], len: usize) {
+    for counter in counters {
+        counter.fetch_add(len as u64, Ordering::Relaxed);
+    }
+}
+
 fn poll_forward_oneway(
     cx: &mut Context<'_>,
     rx: &mut dyn Stream,
     tx: &mut dyn Stream,
     state: &mut ForwardState,
-    counter: &AtomicU64,
+    counters: [&AtomicU64; 2],
+    probe: fn(usize),
 ) -> Poll<FlowResult<()>> {
     loop {
         *state = match state {
@@ -74,14 +83,16 @@ fn poll_forward_oneway(
                 Ok(buf) => {
                     let len = buf.len();
                     tx.commit_tx_buffer(buf)?;
-                    counter.fetch_add(len as u64, Ordering::Relaxed);
+                    add_len(counters, len);
+                    probe(len);
                     ForwardState::AwatingSizeHint
                 }
                 Err((buf, FlowError::Eof)) => {
                     // Return buffer
                     let len = buf.len();
                     tx.commit_tx_buffer(buf)?;
-                    counter.fetch_add(len as u64, Ordering::Relaxed);
+                    add_len(counters, len);
+                    probe(len);
                     ForwardState::Closing
                 }
                 Err((buf, e)) => {
@@ -109,6 +120,7 @@ impl<'l, 'r> Future for StreamForward<'l, 'r> {
             uplink_state,
             downlink_state,
             stat,
+            conn,
         } = &mut *self;
         match (
             poll_forward_oneway(
@@ -116,14 +128,16 @@ impl<'l, 'r> Future for StreamForward<'l, 'r> {
                 *stream_remote,
                 *stream_local,
                 downlink_state,
-                &stat.0.inner.downlink_written,
+                [&stat.0.inner.downlink_written, conn.downlink()],
+                crate::log::probe::inbound_send,
             ),
             poll_forward_oneway(
                 cx,
                 *stream_local,
                 *stream_remote,
                 uplink_state,
-                &stat.0.inner.uplink_written,
+                [&stat.0.inner.uplink_written, conn.uplink()],
+                crate::log::probe::outbound_send,
             ),
         ) {
             (Poll::Ready(Ok(())), Poll::Ready(Ok(()))) => Poll::Ready(Ok(())),
@@ -135,9 +149,12 @@ impl<'l, 'r> Future for StreamForward<'l, 'r> {
 
 #[derive(Clone)]
 pub struct StreamForwardHandler {
+    pub plugin_name: Arc<str>,
     pub request_timeout: u64,
     pub outbound: Weak<dyn StreamOutboundFactory>,
+    pub outbound_name: Arc<str>,
     pub stat: StatHandle,
+    pub connections: ConnectionTracker,
 }
 
 impl StreamForwardHandler {
@@ -147,6 +164,7 @@ impl StreamForwardHandler {
         request_timeout: u64,
         initial_data: Vec<u8>,
         stat: StatGuard,
+        conn: TrackedConnection,
         mut context: Box<FlowContext>,
     ) -> FlowResult<()> {
         let mut initial_uplink_state = ForwardState::AwatingSizeHint;
@@ -171,13 +189,27 @@ impl StreamForwardHandler {
 
         // TODO: outbound handshake timeout
         let initial_data_ref = initial_data.as_deref().unwrap_or(&[]);
-        let outbound = outbound_factory
-            .create_outbound(&mut context, initial_data_ref)
-            .await;
-        stat.0
-            .inner
-            .uplink_written
-            .fetch_add(initial_data_ref.len() as u64, Ordering::Relaxed);
+        let watch_inbound = matches!(initial_uplink_state, ForwardState::AwatingSizeHint);
+        let mut early = EarlyRead::Idle;
+        let outbound = tokio::select! {
+            outbound = outbound_factory.create_outbound(&mut context, initial_data_ref) => outbound,
+            // Give up setting up the outbound once the client is gone, along with work
+            // spawned on behalf of this flow.
+            e = inbound_closed(lower.as_mut(), &mut early), if watch_inbound => {
+                context.abort.abort();
+                return Err(e);
+            }
+        };
+        let mut early_data = None;
+        match early {
+            EarlyRead::Idle => {}
+            EarlyRead::Committed(size) => initial_uplink_state = ForwardState::PollingTxBuf(size),
+            EarlyRead::Received(buf, eof) => early_data = Some((buf, eof)),
+        }
+        add_len(
+            [&stat.0.inner.uplink_written, conn.uplink()],
+            initial_data_ref.len(),
+        );
         drop(initial_data);
         let (mut outbound, initial_res) = match outbound {
             Ok(outbound) => outbound,
@@ -192,10 +224,10 @@ impl StreamForwardHandler {
             let mut buf = crate::get_tx_buffer_boxed!(lower, initial_res_len)?;
             buf.extend_from_slice(&initial_res);
             lower.as_mut().commit_tx_buffer(buf)?;
-            stat.0
-                .inner
-                .downlink_written
-                .fetch_add(initial_res_len.get() as u64, Ordering::Relaxed);
+            add_len(
+                [&stat.0.inner.downlink_written, conn.downlink()],
+                initial_res_len.get(),
+            );
         }
 
         let mut initial_downlink_state = ForwardState::AwatingSizeHint;
@@ -204,7 +236,14 @@ impl StreamForwardHandler {
             // buffer out, and forward downlink at the same time.
             poll_fn(|cx| {
                 match lower.as_mut().poll_rx_buffer(cx) {
-                    Poll::Ready(Ok(_)) => return Poll::Ready(Ok(())),
+                    Poll::Ready(Ok(buf)) => {
+                        early_data = Some((buf, false));
+                        return Poll::Ready(Ok(()));
+                    }
+                    Poll::Ready(Err((buf, FlowError::Eof))) => {
+                        early_data = Some((buf, true));
+                        return Poll::Ready(Ok(()));
+                    }
                     Poll::Ready(Err((_, e))) => return Poll::Ready(Err(e)),
                     _ => {}
                 }
@@ -213,13 +252,26 @@ impl StreamForwardHandler {
                     outbound.as_mut(),
                     lower.as_mut(),
                     &mut initial_downlink_state,
-                    &stat.0.inner.downlink_written,
+                    [&stat.0.inner.downlink_written, conn.downlink()],
+                    crate::log::probe::inbound_send,
                 ) {
                     return r;
                 };
                 Poll::Pending
             })
             .await?;
+            initial_uplink_state = ForwardState::AwatingSizeHint;
+        }
+        if let Some((buf, eof)) = early_data {
+            if let Ok(len) = NonZeroUsize::try_from(buf.len()) {
+                let mut tx_buf = crate::get_tx_buffer_boxed!(outbound, len)?;
+                tx_buf.extend_from_slice(&buf);
+                outbound.as_mut().commit_tx_buffer(tx_buf)?;
+                add_len([&stat.0.inner.uplink_written, conn.uplink()], len.get());
+            }
+            if eof {
+                initial_uplink_state = ForwardState::Closing;
+            }
         }
 
         // Drop earlier to prevent StreamForward outliving outbound
@@ -229,6 +281,7 @@ impl StreamForwardHandler {
             downlink_state: initial_downlink_state,
             uplink_state: initial_uplink_state,
             stat,
+            conn: &conn,
         }
         .await?;
         Ok(())
@@ -243,14 +296,130 @@ impl StreamHandler for StreamForwardHandler {
                 .inner
                 .tcp_connection_count
                 .fetch_add(1, Ordering::Relaxed);
-            tokio::spawn(Self::handle_stream(
+            let conn = self.connections.track(
+                "tcp",
+                context.local_peer,
+                context.remote_peer.clone(),
+                self.plugin_name.clone(),
+                self.outbound_name.clone(),
+            );
+            let closed = conn.close_signal();
+            let plugin_name = self.plugin_name.clone();
+            let crash_stat = self.stat.clone();
+            let task = Self::handle_stream(
                 outbound,
                 lower,
                 self.request_timeout,
                 initial_data,
                 stat,
+                conn,
                 context,
-            ));
+            );
+            // Closing the connection from the tracker drops both ends.
+            let task = async move { closed.guard(task).await };
+            tokio::spawn(async move {
+                let session_id = crate::log::next_session_id();
+                if crate::log::isolate(&plugin_name, session_id, task)
+                    .await
+                    .is_none()
+                {
+                    crash_stat.inner.crash_count.fetch_add(1, Ordering::Relaxed);
+                }
+            });
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::sync::Notify;
+
+    use super::*;
+    use crate::flow::testing::*;
+
+    /// Hands out the abort signal of each flow, then waits for `ready` before
+    /// connecting through `inner`.
+    struct SlowOutbound {
+        inner: Arc<MockStreamOutboundFactory>,
+        ready: Notify,
+        signals: flume::Sender<AbortSignal>,
+    }
+
+    #[async_trait]
+    impl StreamOutboundFactory for SlowOutbound {
+        async fn create_outbound(
+            &self,
+            context: &mut FlowContext,
+            initial_data: &'_ [u8],
+        ) -> FlowResult<(Box<dyn Stream>, Buffer)> {
+            let _ = self.signals.send(context.abort.signal());
+            self.ready.notified().await;
+            self.inner.create_outbound(context, initial_data).await
+        }
+    }
+
+    fn handler(
+        aps: &mut AccessPoints,
+    ) -> (
+        StreamForwardHandler,
+        Arc<SlowOutbound>,
+        flume::Receiver<AbortSignal>,
+    ) {
+        let (signals, signal_rx) = flume::unbounded();
+        let outbound = Arc::new(SlowOutbound {
+            inner: MockStreamOutboundFactory::new(),
+            ready: Notify::new(),
+            signals,
+        });
+        let handler = StreamForwardHandler {
+            plugin_name: "forward".into(),
+            request_timeout: 0,
+            outbound: aps.hold(outbound.clone()) as _,
+            outbound_name: "outbound".into(),
+            stat: StatHandle::default(),
+            connections: ConnectionTracker::default(),
+        };
+        (handler, outbound, signal_rx)
+    }
+
+    #[tokio::test]
+    async fn test_inbound_close_aborts_outbound_setup() {
+        let mut aps = AccessPoints::new();
+        let (handler, _outbound, signals) = handler(&mut aps);
+        let (stream, peer) = stream_pair();
+        handler.on_stream(stream, vec![], context("example.com:80"));
+        let signal = signals.recv_async().await.unwrap();
+        assert_eq!(handler.connections.len(), 1);
+
+        drop(peer);
+        tokio::time::timeout(Duration::from_secs(1), signal.aborted())
+            .await
+            .expect("outbound setup should be aborted");
+        tokio::task::yield_now().await;
+        assert!(handler.connections.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_data_during_outbound_setup_is_forwarded() {
+        let mut aps = AccessPoints::new();
+        let (handler, outbound, signals) = handler(&mut aps);
+        let (stream, mut peer) = stream_pair();
+        handler.on_stream(stream, vec![], context("example.com:80"));
+        let signal = signals.recv_async().await.unwrap();
+
+        peer.write_all(b"GET / HTTP/1.0\r\n\r\n").await.unwrap();
+        peer.shutdown().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!signal.is_aborted());
+
+        outbound.ready.notify_one();
+        let mut remote = outbound.inner.connected().await;
+        let mut sent = vec![];
+        remote.peer.read_to_end(&mut sent).await.unwrap();
+        assert_eq!(sent, b"GET / HTTP/1.0\r\n\r\n");
+    }
+}
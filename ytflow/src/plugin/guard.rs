@@ -0,0 +1,9 @@
+mod allowlist;
+#[cfg(feature = "plugins")]
+mod outbound;
+mod sniff;
+
+pub use allowlist::{AllowRule, Allowlist};
+#[cfg(feature = "plugins")]
+pub use outbound::{DatagramGuardFactory, StreamGuardFactory};
+pub use sniff::{sniff_tls_alpn, sniff_tls_sni};
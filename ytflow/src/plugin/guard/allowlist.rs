@@ -0,0 +1,125 @@
+use std::net::IpAddr;
+
+use cidr::IpCidr;
+
+use crate::flow::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AllowRule {
+    /// Matches the domain name exactly.
+    Domain(String),
+    /// Matches any subdomain, written as `*.example.com`.
+    DomainSuffix(String),
+    /// Matches an IP address or a range of them.
+    Cidr(IpCidr),
+}
+
+impl AllowRule {
+    pub fn parse(rule: &str) -> Option<Self> {
+        if let Ok(cidr) = rule.parse() {
+            return Some(Self::Cidr(cidr));
+        }
+        let (is_suffix, domain) = match rule.strip_prefix("*.") {
+            Some(domain) => (true, domain),
+            None => (false, rule),
+        };
+        let HostName::DomainName(domain) = HostName::from_domain_name(domain.into()).ok()? else {
+            return None;
+        };
+        let domain = domain.trim_end_matches('.').to_string();
+        if domain.is_empty() {
+            return None;
+        }
+        Some(if is_suffix {
+            Self::DomainSuffix(domain)
+        } else {
+            Self::Domain(domain)
+        })
+    }
+
+    fn matches_domain(&self, domain: &str) -> bool {
+        match self {
+            Self::Domain(d) => d == domain,
+            Self::DomainSuffix(suffix) => domain
+                .strip_suffix(suffix.as_str())
+                .is_some_and(|prefix| prefix.ends_with('.')),
+            Self::Cidr(_) => false,
+        }
+    }
+
+    fn matches_ip(&self, ip: IpAddr) -> bool {
+        match self {
+            Self::Cidr(cidr) => cidr.contains(&ip.to_canonical()),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Allowlist(pub Vec<AllowRule>);
+
+impl Allowlist {
+    /// Domain names are compared in lowercase without the trailing dot.
+    pub fn allows_domain(&self, domain: &str) -> bool {
+        let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+        self.0.iter().any(|r| r.matches_domain(&domain))
+    }
+
+    pub fn allows_ip(&self, ip: IpAddr) -> bool {
+        self.0.iter().any(|r| r.matches_ip(ip))
+    }
+
+    pub fn allows_host(&self, host: &HostName) -> bool {
+        match host {
+            HostName::DomainName(domain) => self.allows_domain(domain),
+            HostName::Ip(ip) => self.allows_ip(*ip),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allowlist(rules: &[&str]) -> Allowlist {
+        Allowlist(rules.iter().map(|r| AllowRule::parse(r).unwrap()).collect())
+    }
+
+    #[test]
+    fn test_parse_rules() {
+        assert_eq!(
+            AllowRule::parse("Example.COM"),
+            Some(AllowRule::Domain("example.com".into()))
+        );
+        assert_eq!(
+            AllowRule::parse("*.example.com"),
+            Some(AllowRule::DomainSuffix("example.com".into()))
+        );
+        assert_eq!(
+            AllowRule::parse("10.0.0.0/8"),
+            Some(AllowRule::Cidr("10.0.0.0/8".parse().unwrap()))
+        );
+        assert_eq!(AllowRule::parse("*."), None);
+    }
+
+    #[test]
+    fn test_allows_domain() {
+        let list = allowlist(&["example.com", "*.example.net"]);
+        assert!(list.allows_domain("example.com"));
+        assert!(list.allows_domain("EXAMPLE.com."));
+        assert!(!list.allows_domain("a.example.com"));
+        assert!(list.allows_domain("a.b.example.net"));
+        assert!(!list.allows_domain("example.net"));
+        assert!(!list.allows_domain("badexample.net"));
+    }
+
+    #[test]
+    fn test_allows_ip() {
+        let list = allowlist(&["192.0.2.0/24", "2001:db8::1"]);
+        assert!(list.allows_ip("192.0.2.7".parse().unwrap()));
+        assert!(list.allows_ip("::ffff:192.0.2.7".parse().unwrap()));
+        assert!(list.allows_ip("2001:db8::1".parse().unwrap()));
+        assert!(!list.allows_ip("2001:db8::2".parse().unwrap()));
+        assert!(!list.allows_ip("198.51.100.1".parse().unwrap()));
+    }
+}
@@ -0,0 +1,78 @@
+use std::sync::{Arc, Weak};
+use std::task::{Context, Poll};
+
+use async_trait::async_trait;
+
+use super::{sniff_tls_sni, Allowlist};
+use crate::flow::*;
+
+pub struct StreamGuardFactory {
+    pub allowlist: Arc<Allowlist>,
+    pub next: Weak<dyn StreamOutboundFactory>,
+}
+
+pub struct DatagramGuardFactory {
+    pub allowlist: Arc<Allowlist>,
+    pub next: Weak<dyn DatagramSessionFactory>,
+}
+
+#[async_trait]
+impl StreamOutboundFactory for StreamGuardFactory {
+    async fn create_outbound(
+        &self,
+        context: &mut FlowContext,
+        initial_data: &'_ [u8],
+    ) -> FlowResult<(Box<dyn Stream>, Buffer)> {
+        if !self.allowlist.allows_host(&context.remote_peer.host) {
+            // TODO: log rejected destination
+            return Err(FlowError::NoOutbound);
+        }
+        // A ClientHello may carry a different name than the destination, e.g.
+        // domain fronting through an allowed server.
+        if let Some(sni) = sniff_tls_sni(initial_data) {
+            if !self.allowlist.allows_domain(sni) {
+                return Err(FlowError::NoOutbound);
+            }
+        }
+        let next = self.next.upgrade().ok_or(FlowError::NoOutbound)?;
+        next.create_outbound(context, initial_data).await
+    }
+}
+
+#[async_trait]
+impl DatagramSessionFactory for DatagramGuardFactory {
+    async fn bind(&self, context: Box<FlowContext>) -> FlowResult<Box<dyn DatagramSession>> {
+        if !self.allowlist.allows_host(&context.remote_peer.host) {
+            return Err(FlowError::NoOutbound);
+        }
+        let next = self.next.upgrade().ok_or(FlowError::NoOutbound)?;
+        Ok(Box::new(GuardDatagramSession {
+            allowlist: self.allowlist.clone(),
+            lower: next.bind(context).await?,
+        }))
+    }
+}
+
+struct GuardDatagramSession {
+    allowlist: Arc<Allowlist>,
+    lower: Box<dyn DatagramSession>,
+}
+
+impl DatagramSession for GuardDatagramSession {
+    fn poll_recv_from(&mut self, cx: &mut Context) -> Poll<Option<(DestinationAddr, Buffer)>> {
+        self.lower.poll_recv_from(cx)
+    }
+    fn poll_send_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        self.lower.poll_send_ready(cx)
+    }
+    fn send_to(&mut self, remote_peer: DestinationAddr, buf: Buffer) {
+        // A session may send to destinations other than the one it is bound to.
+        if !self.allowlist.allows_host(&remote_peer.host) {
+            return;
+        }
+        self.lower.send_to(remote_peer, buf)
+    }
+    fn poll_shutdown(&mut self, cx: &mut Context<'_>) -> Poll<FlowResult<()>> {
+        self.lower.poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,170 @@
+const CONTENT_TYPE_HANDSHAKE: u8 = 0x16;
+const HANDSHAKE_TYPE_CLIENT_HELLO: u8 = 0x01;
+const EXT_SERVER_NAME: u16 = 0x0000;
+const EXT_ALPN: u16 = 0x0010;
+const NAME_TYPE_HOST_NAME: u8 = 0x00;
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (head, tail) = self.0.split_at(len);
+        self.0 = tail;
+        Some(head)
+    }
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+    fn vec_u8(&mut self) -> Option<&'a [u8]> {
+        let len = self.u8()?;
+        self.take(len as usize)
+    }
+    fn vec_u16(&mut self) -> Option<&'a [u8]> {
+        let len = self.u16()?;
+        self.take(len as usize)
+    }
+}
+
+/// Find the extension of `ty` in a TLS ClientHello at the beginning of
+/// `data`. Only the first record is inspected, so a ClientHello fragmented
+/// across records or truncated by the caller yields `None`.
+fn find_extension(data: &[u8], ty: u16) -> Option<&[u8]> {
+    let mut record = Reader(data);
+    if record.u8()? != CONTENT_TYPE_HANDSHAKE {
+        return None;
+    }
+    record.take(2)?; // legacy_record_version
+    let mut handshake = Reader(record.vec_u16()?);
+    if handshake.u8()? != HANDSHAKE_TYPE_CLIENT_HELLO {
+        return None;
+    }
+    handshake.take(3)?; // length
+    handshake.take(2 + 32)?; // legacy_version, random
+    handshake.vec_u8()?; // legacy_session_id
+    handshake.vec_u16()?; // cipher_suites
+    handshake.vec_u8()?; // legacy_compression_methods
+    let mut extensions = Reader(handshake.vec_u16()?);
+    while !extensions.0.is_empty() {
+        let ext_type = extensions.u16()?;
+        let ext_data = extensions.vec_u16()?;
+        if ext_type == ty {
+            return Some(ext_data);
+        }
+    }
+    None
+}
+
+/// Extract the SNI from a TLS ClientHello at the beginning of `data`. Only the
+/// first record is inspected, so a ClientHello fragmented across records or
+/// truncated by the caller yields `None`.
+pub fn sniff_tls_sni(data: &[u8]) -> Option<&str> {
+    let ext_data = find_extension(data, EXT_SERVER_NAME)?;
+    let mut names = Reader(Reader(ext_data).vec_u16()?);
+    while !names.0.is_empty() {
+        let name_type = names.u8()?;
+        let name = names.vec_u16()?;
+        if name_type == NAME_TYPE_HOST_NAME {
+            return std::str::from_utf8(name).ok();
+        }
+    }
+    None
+}
+
+/// Extract the application layer protocols offered in a TLS ClientHello at
+/// the beginning of `data`, in the order of client preference. Empty if
+/// `data` is not a ClientHello or offers no ALPN.
+pub fn sniff_tls_alpn(data: &[u8]) -> Vec<&[u8]> {
+    let mut protocols = vec![];
+    let Some(ext_data) = find_extension(data, EXT_ALPN) else {
+        return protocols;
+    };
+    let Some(list) = Reader(ext_data).vec_u16() else {
+        return protocols;
+    };
+    let mut list = Reader(list);
+    while let Some(protocol) = list.vec_u8() {
+        protocols.push(protocol);
+    }
+    protocols
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client_hello_with_alpn(sni: Option<&str>, alpn: &[&str]) -> Vec<u8> {
+        let mut exts = vec![];
+        if !alpn.is_empty() {
+            let list: Vec<u8> = alpn
+                .iter()
+                .flat_map(|p| std::iter::once(p.len() as u8).chain(p.bytes()))
+                .collect();
+            exts.extend_from_slice(&EXT_ALPN.to_be_bytes());
+            exts.extend_from_slice(&(list.len() as u16 + 2).to_be_bytes());
+            exts.extend_from_slice(&(list.len() as u16).to_be_bytes());
+            exts.extend_from_slice(&list);
+        }
+        // supported_versions, to make sure unrelated extensions are skipped
+        exts.extend_from_slice(&[0x00, 0x2b, 0x00, 0x03, 0x02, 0x03, 0x04]);
+        if let Some(sni) = sni {
+            let len = sni.len() as u16;
+            exts.extend_from_slice(&[0x00, 0x00]);
+            exts.extend_from_slice(&(len + 5).to_be_bytes());
+            exts.extend_from_slice(&(len + 3).to_be_bytes());
+            exts.push(0x00);
+            exts.extend_from_slice(&len.to_be_bytes());
+            exts.extend_from_slice(sni.as_bytes());
+        }
+        let mut body = vec![0x03, 0x03];
+        body.extend_from_slice(&[0; 32]);
+        body.push(0); // session id
+        body.extend_from_slice(&[0x00, 0x02, 0x13, 0x01]);
+        body.extend_from_slice(&[0x01, 0x00]);
+        body.extend_from_slice(&(exts.len() as u16).to_be_bytes());
+        body.extend_from_slice(&exts);
+        let mut handshake = vec![HANDSHAKE_TYPE_CLIENT_HELLO];
+        handshake.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        handshake.extend_from_slice(&body);
+        let mut record = vec![CONTENT_TYPE_HANDSHAKE, 0x03, 0x01];
+        record.extend_from_slice(&(handshake.len() as u16).to_be_bytes());
+        record.extend_from_slice(&handshake);
+        record
+    }
+
+    fn client_hello(sni: Option<&str>) -> Vec<u8> {
+        client_hello_with_alpn(sni, &[])
+    }
+
+    #[test]
+    fn test_sniff_sni() {
+        assert_eq!(
+            sniff_tls_sni(&client_hello(Some("example.com"))),
+            Some("example.com")
+        );
+        assert_eq!(sniff_tls_sni(&client_hello(None)), None);
+    }
+
+    #[test]
+    fn test_sniff_alpn() {
+        let hello = client_hello_with_alpn(Some("example.com"), &["h2", "http/1.1"]);
+        assert_eq!(sniff_tls_alpn(&hello), [&b"h2"[..], b"http/1.1"]);
+        assert_eq!(sniff_tls_sni(&hello), Some("example.com"));
+        assert!(sniff_tls_alpn(&client_hello(Some("example.com"))).is_empty());
+        assert!(sniff_tls_alpn(b"GET / HTTP/1.1\r\n\r\n").is_empty());
+    }
+
+    #[test]
+    fn test_sniff_sni_not_tls() {
+        assert_eq!(sniff_tls_sni(b"GET / HTTP/1.1\r\n\r\n"), None);
+        assert_eq!(
+            sniff_tls_sni(&client_hello(Some("example.com"))[..20]),
+            None
+        );
+    }
+}
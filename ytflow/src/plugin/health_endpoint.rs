@@ -0,0 +1,189 @@
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::control::KillSwitch;
+use crate::flow::*;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_REQUEST_LEN: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Readiness {
+    pub reloading: bool,
+    /// Designated outbound chains reported as unhealthy.
+    pub unhealthy: Vec<String>,
+}
+
+impl Readiness {
+    pub fn is_ready(&self) -> bool {
+        !self.reloading && self.unhealthy.is_empty()
+    }
+
+    fn describe(&self) -> String {
+        if self.is_ready() {
+            return "ready".into();
+        }
+        let mut reasons = vec![];
+        if self.reloading {
+            reasons.push("profile reloading".to_string());
+        }
+        if !self.unhealthy.is_empty() {
+            reasons.push(format!("unhealthy: {}", self.unhealthy.join(", ")));
+        }
+        format!("not ready ({})", reasons.join("; "))
+    }
+}
+
+/// Answers liveness and readiness probes from container orchestrators.
+/// Accepts an HTTP `GET /livez` or `GET /readyz` request, or a Redis-style
+/// `PING` either inline or as a RESP array. Any other request is treated as
+/// a readiness probe.
+pub struct HealthEndpointHandler {
+    kill_switch: KillSwitch,
+    probers: Vec<String>,
+}
+
+impl HealthEndpointHandler {
+    /// `probers` are names of plugins whose health is taken into account for
+    /// readiness, such as ping-probers in front of the outbound chains.
+    pub fn new(kill_switch: KillSwitch, probers: Vec<String>) -> Self {
+        Self {
+            kill_switch,
+            probers,
+        }
+    }
+
+    fn readiness(&self) -> Readiness {
+        let status = self.kill_switch.status();
+        Readiness {
+            reloading: status.reloading,
+            unhealthy: status
+                .unhealthy
+                .into_iter()
+                .filter(|p| self.probers.contains(p))
+                .collect(),
+        }
+    }
+}
+
+fn is_complete(req: &[u8]) -> bool {
+    if req.starts_with(b"*") {
+        // RESP array: "*1\r\n$4\r\nPING\r\n"
+        return req.split(|&b| b == b'\n').count() > 3;
+    }
+    if req.starts_with(b"GET ") {
+        return req.windows(4).any(|w| w == b"\r\n\r\n");
+    }
+    req.contains(&b'\n')
+}
+
+/// Build the response to a complete request.
+pub fn respond(req: &[u8], readiness: &Readiness) -> Vec<u8> {
+    if req.starts_with(b"GET ") {
+        let path = req[4..]
+            .split(|&b| b == b' ' || b == b'\r' || b == b'\n')
+            .next()
+            .unwrap_or_default();
+        let (status, body) = match path {
+            b"/livez" | b"/healthz" => ("200 OK", "ok".to_string()),
+            _ if readiness.is_ready() => ("200 OK", readiness.describe()),
+            _ => ("503 Service Unavailable", readiness.describe()),
+        };
+        return format!(
+            "HTTP/1.1 {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}\n",
+            status,
+            body.len() + 1,
+            body
+        )
+        .into_bytes();
+    }
+    let is_ping = String::from_utf8_lossy(req)
+        .lines()
+        .any(|l| l.trim().eq_ignore_ascii_case("PING"));
+    match (is_ping, readiness.is_ready()) {
+        (true, true) => b"+PONG\r\n".to_vec(),
+        (false, true) => b"+OK\r\n".to_vec(),
+        (_, false) => format!("-ERR {}\r\n", readiness.describe()).into_bytes(),
+    }
+}
+
+impl StreamHandler for HealthEndpointHandler {
+    fn on_stream(&self, lower: Box<dyn Stream>, initial_data: Buffer, _context: Box<FlowContext>) {
+        let readiness = self.readiness();
+        tokio::spawn(async move {
+            let mut req = initial_data;
+            let mut stream = CompatStream {
+                reader: StreamReader::new(4096, vec![]),
+                inner: lower,
+            };
+            let read_req = async {
+                let mut buf = [0; 256];
+                while !is_complete(&req) && req.len() < MAX_REQUEST_LEN {
+                    let len = stream.read(&mut buf).await?;
+                    if len == 0 {
+                        break;
+                    }
+                    req.extend_from_slice(&buf[..len]);
+                }
+                std::io::Result::Ok(())
+            };
+            if !matches!(
+                tokio::time::timeout(REQUEST_TIMEOUT, read_req).await,
+                Ok(Ok(()))
+            ) {
+                return;
+            }
+            let _ = stream.write_all(&respond(&req, &readiness)).await;
+            let _ = stream.shutdown().await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ready() -> Readiness {
+        Readiness {
+            reloading: false,
+            unhealthy: vec![],
+        }
+    }
+
+    fn unhealthy() -> Readiness {
+        Readiness {
+            reloading: false,
+            unhealthy: vec!["prober".into()],
+        }
+    }
+
+    #[test]
+    fn test_is_complete() {
+        assert!(!is_complete(b"GET /readyz HTTP/1.1\r\n"));
+        assert!(is_complete(b"GET /readyz HTTP/1.1\r\nHost: a\r\n\r\n"));
+        assert!(!is_complete(b"*1\r\n$4\r\n"));
+        assert!(is_complete(b"*1\r\n$4\r\nPING\r\n"));
+        assert!(is_complete(b"PING\r\n"));
+    }
+
+    #[test]
+    fn test_respond_http() {
+        let res = respond(b"GET /readyz HTTP/1.1\r\n\r\n", &unhealthy());
+        assert!(res.starts_with(b"HTTP/1.1 503 "));
+        let res = respond(b"GET /livez HTTP/1.1\r\n\r\n", &unhealthy());
+        assert!(res.starts_with(b"HTTP/1.1 200 "));
+        let res = respond(b"GET /readyz HTTP/1.1\r\n\r\n", &ready());
+        assert!(res.starts_with(b"HTTP/1.1 200 "));
+    }
+
+    #[test]
+    fn test_respond_resp() {
+        assert_eq!(respond(b"*1\r\n$4\r\nPING\r\n", &ready()), b"+PONG\r\n");
+        assert_eq!(respond(b"ping\r\n", &ready()), b"+PONG\r\n");
+        assert_eq!(
+            respond(b"PING\r\n", &unhealthy()),
+            b"-ERR not ready (unhealthy: prober)\r\n"
+        );
+    }
+}
@@ -1,47 +1,255 @@
+use std::error::Error as StdError;
+use std::future::Future;
 use std::pin::Pin;
-use std::sync::Weak;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
 use std::task::{ready, Context, Poll};
+use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
+use base64::Engine;
+use futures::future::poll_fn;
 use futures::{FutureExt, SinkExt};
-use http::header::{ACCEPT, CONTENT_TYPE};
-use http::uri::Uri;
-use http::{Method, Request};
+use http::header::{HeaderMap, ACCEPT, AGE, CACHE_CONTROL, CONTENT_TYPE};
+use http::uri::{PathAndQuery, Uri};
+use http::{Method, Request, Response};
 use hyper::body::{Bytes, HttpBody};
-use hyper::client::ResponseFuture;
+use hyper::client::conn::{Builder as ConnBuilder, SendRequest};
+use hyper::client::connect::Connection;
+use hyper::service::Service as TowerService;
 use hyper::{Body, Client as HyperClient};
-use tokio::sync::mpsc;
+use serde::Serialize;
+use tokio::sync::{mpsc, Mutex as AsyncMutex};
 use tokio_util::sync::PollSender;
+use trust_dns_resolver::proto::op::Message as DnsMessage;
+use trust_dns_resolver::proto::rr::Record;
+use trust_dns_resolver::proto::serialize::binary::BinDecodable;
 
+use crate::atomic::AtomicU64;
 use crate::flow::*;
 use crate::plugin::h2::{FlowAdapterConnector, TokioHyperExecutor};
 
-pub struct DohDatagramAdapterFactory {
-    client: HyperClient<FlowAdapterConnector, Body>,
+type DohError = Box<dyn StdError + Send + Sync>;
+type DohResponseFuture = Pin<Box<dyn Future<Output = Result<Response<Body>, DohError>> + Send>>;
+
+/// Counters of queries sent to a DoH upstream.
+#[derive(Default)]
+pub struct DohStats {
+    queries: AtomicU64,
+    successes: AtomicU64,
+    errors: AtomicU64,
+    /// Sum of latencies of successful queries, in microseconds.
+    total_latency_us: AtomicU64,
+    last_latency_us: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct DohStatsSnapshot {
+    pub queries: u64,
+    pub errors: u64,
+    pub avg_latency_ms: u32,
+    pub last_latency_ms: u32,
+}
+
+impl DohStats {
+    fn record_success(&self, latency: Duration) {
+        let latency_us = latency.as_micros() as u64;
+        self.successes.fetch_add(1, Ordering::Relaxed);
+        self.total_latency_us
+            .fetch_add(latency_us, Ordering::Relaxed);
+        self.last_latency_us.store(latency_us, Ordering::Relaxed);
+    }
+
+    fn record_error(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> DohStatsSnapshot {
+        let queries = self.queries.load(Ordering::Relaxed);
+        let errors = self.errors.load(Ordering::Relaxed);
+        let avg_latency_us = self
+            .total_latency_us
+            .load(Ordering::Relaxed)
+            .checked_div(self.successes.load(Ordering::Relaxed))
+            .unwrap_or(0);
+        DohStatsSnapshot {
+            queries,
+            errors,
+            avg_latency_ms: (avg_latency_us / 1000) as u32,
+            last_latency_ms: (self.last_latency_us.load(Ordering::Relaxed) / 1000) as u32,
+        }
+    }
+}
+
+struct DohUpstream {
     url: Uri,
+    use_get: bool,
+    connector: FlowAdapterConnector,
+    /// Used instead of `h2_sender` once the server turns out not to speak
+    /// HTTP/2. Connections are pooled by hyper.
+    http1_client: HyperClient<FlowAdapterConnector, Body>,
+    /// The HTTP/2 connection shared by all queries. While a connection is
+    /// being set up, other queries wait on the lock instead of racing to
+    /// set up their own.
+    h2_sender: AsyncMutex<Option<SendRequest<Body>>>,
+    h2_unsupported: AtomicBool,
+    stats: Arc<DohStats>,
+}
+
+impl DohUpstream {
+    async fn send(self: Arc<Self>, req: Request<Body>) -> Result<Response<Body>, DohError> {
+        if !self.h2_unsupported.load(Ordering::Relaxed) {
+            let mut sender_guard = self.h2_sender.lock().await;
+            if let Some(sender) = &mut *sender_guard {
+                if poll_fn(|cx| sender.poll_ready(cx)).await.is_err() {
+                    // The connection is gone. Set up another one below.
+                    *sender_guard = None;
+                }
+            }
+            if sender_guard.is_none() {
+                let stream = self.connector.clone().call(self.url.clone()).await?;
+                if stream.connected().is_negotiated_h2() {
+                    let (sender, conn) = ConnBuilder::new()
+                        .http2_only(true)
+                        .executor(TokioHyperExecutor::new_current())
+                        .handshake(stream)
+                        .await?;
+                    tokio::spawn(conn);
+                    *sender_guard = Some(sender);
+                } else {
+                    self.h2_unsupported.store(true, Ordering::Relaxed);
+                }
+            }
+            if let Some(sender) = &mut *sender_guard {
+                let fut = sender.send_request(req);
+                drop(sender_guard);
+                return Ok(fut.await?);
+            }
+        }
+        Ok(self.http1_client.request(req).await?)
+    }
+
+    fn build_request(&self, query: Buffer) -> Request<Body> {
+        let builder = Request::builder().header(ACCEPT, "application/dns-message");
+        if self.use_get {
+            let dns = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(&query);
+            builder
+                .method(Method::GET)
+                .uri(with_dns_param(&self.url, &dns))
+                .body(Body::empty())
+        } else {
+            builder
+                .method(Method::POST)
+                .uri(self.url.clone())
+                .header(CONTENT_TYPE, "application/dns-message")
+                .body(query.into())
+        }
+        .unwrap()
+    }
+}
+
+/// Append the `dns` parameter of a GET request to the query string of `url`.
+fn with_dns_param(url: &Uri, dns: &str) -> Uri {
+    let path_and_query = match url.query() {
+        Some(query) if !query.is_empty() => format!("{}?{}&dns={}", url.path(), query, dns),
+        _ => format!("{}?dns={}", url.path(), dns),
+    };
+    let mut parts = url.clone().into_parts();
+    parts.path_and_query =
+        Some(PathAndQuery::try_from(path_and_query).expect("base64url is valid in a query"));
+    Uri::from_parts(parts).expect("url with dns param should be valid")
+}
+
+/// How long the response may still be considered fresh according to its
+/// `Cache-Control` and `Age` headers, which may have been served from an HTTP
+/// cache somewhere along the way.
+fn parse_freshness(headers: &HeaderMap) -> Option<u32> {
+    let max_age = headers
+        .get_all(CACHE_CONTROL)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .find_map(|d| {
+            let (name, value) = d.trim().split_once('=')?;
+            name.eq_ignore_ascii_case("max-age")
+                .then(|| value.trim_matches('"').parse::<u32>().ok())?
+        })?;
+    let age = headers
+        .get(AGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u32>().ok())
+        .unwrap_or(0);
+    Some(max_age.saturating_sub(age))
+}
+
+/// Cap TTLs of all records in a DNS response at `max_ttl`, so that the
+/// resolver cache does not outlive the HTTP freshness of the response.
+fn cap_ttls(response: Vec<u8>, max_ttl: u32) -> Vec<u8> {
+    let Ok(mut msg) = DnsMessage::from_bytes(&response) else {
+        return response;
+    };
+    let mut changed = false;
+    let mut cap = |records: &mut Vec<Record>| {
+        for record in records.iter_mut().filter(|r| r.ttl() > max_ttl) {
+            record.set_ttl(max_ttl);
+            changed = true;
+        }
+    };
+    cap(msg.answers_mut());
+    cap(msg.name_servers_mut());
+    cap(msg.additionals_mut());
+    if !changed {
+        return response;
+    }
+    msg.to_vec().unwrap_or(response)
+}
+
+pub struct DohDatagramAdapterFactory {
+    upstream: Arc<DohUpstream>,
 }
 
 #[derive(Default)]
 enum DohDatagramAdapterTxState {
     #[default]
     Idle,
-    PendingResponse(ResponseFuture),
-    ReadingResponse(Body, Vec<Bytes>),
+    PendingResponse(DohResponseFuture, Instant),
+    ReadingResponse(Body, Vec<Bytes>, Option<u32>, Instant),
 }
 
 struct DohDatagramAdapter {
-    url: Uri,
-    client: HyperClient<FlowAdapterConnector, Body>,
+    upstream: Arc<DohUpstream>,
     tx_state: DohDatagramAdapterTxState,
+    /// ID of the query in flight, which is zeroed for GET requests to make
+    /// them cacheable.
+    query_id: [u8; 2],
     rx_chan: (Option<PollSender<Buffer>>, mpsc::Receiver<Buffer>),
 }
 
 impl DohDatagramAdapterFactory {
-    pub fn new(url: Uri, next: Weak<dyn StreamOutboundFactory>) -> Self {
-        let client = hyper::Client::builder()
+    pub fn new(url: Uri, use_get: bool, next: Weak<dyn StreamOutboundFactory>) -> Self {
+        let connector = FlowAdapterConnector { next };
+        let http1_client = hyper::Client::builder()
             .executor(TokioHyperExecutor::new_current())
-            .build(FlowAdapterConnector { next });
-        Self { client, url }
+            .build(connector.clone());
+        Self {
+            upstream: Arc::new(DohUpstream {
+                url,
+                use_get,
+                connector,
+                http1_client,
+                h2_sender: AsyncMutex::new(None),
+                h2_unsupported: AtomicBool::new(false),
+                stats: Default::default(),
+            }),
+        }
+    }
+
+    pub fn url(&self) -> &Uri {
+        &self.upstream.url
+    }
+
+    pub fn stats(&self) -> Arc<DohStats> {
+        self.upstream.stats.clone()
     }
 }
 
@@ -50,14 +258,22 @@ impl DatagramSessionFactory for DohDatagramAdapterFactory {
     async fn bind(&self, _context: Box<FlowContext>) -> FlowResult<Box<dyn DatagramSession>> {
         let (rx_tx, rx_rx) = mpsc::channel(4);
         Ok(Box::new(DohDatagramAdapter {
-            client: self.client.clone(),
+            upstream: self.upstream.clone(),
             tx_state: Default::default(),
+            query_id: [0; 2],
             rx_chan: (Some(PollSender::new(rx_tx)), rx_rx),
-            url: self.url.clone(),
         }))
     }
 }
 
+impl DohDatagramAdapter {
+    fn fail(&mut self) {
+        // TODO: log error
+        self.upstream.stats.record_error();
+        self.rx_chan.0 = None;
+    }
+}
+
 impl DatagramSession for DohDatagramAdapter {
     fn poll_recv_from(&mut self, cx: &mut Context) -> Poll<Option<(DestinationAddr, Buffer)>> {
         let buf = match ready!(self.rx_chan.1.poll_recv(cx)) {
@@ -79,28 +295,34 @@ impl DatagramSession for DohDatagramAdapter {
             let _ = ready!(tx.poll_ready_unpin(cx)).ok();
             match std::mem::take(&mut self.tx_state) {
                 DohDatagramAdapterTxState::Idle => break Poll::Ready(()),
-                DohDatagramAdapterTxState::PendingResponse(mut fut) => match fut.poll_unpin(cx) {
-                    Poll::Ready(Ok(resp)) => {
-                        if resp.status().is_success() {
-                            self.tx_state = DohDatagramAdapterTxState::ReadingResponse(
-                                resp.into_body(),
-                                Vec::new(),
-                            );
-                        } else {
-                            // TODO: log res error
-                            self.rx_chan.0 = None;
+                DohDatagramAdapterTxState::PendingResponse(mut fut, start) => {
+                    match fut.poll_unpin(cx) {
+                        Poll::Ready(Ok(resp)) => {
+                            if resp.status().is_success() {
+                                let freshness = parse_freshness(resp.headers());
+                                self.tx_state = DohDatagramAdapterTxState::ReadingResponse(
+                                    resp.into_body(),
+                                    Vec::new(),
+                                    freshness,
+                                    start,
+                                );
+                            } else {
+                                self.fail();
+                            }
+                        }
+                        Poll::Ready(Err(_)) => self.fail(),
+                        Poll::Pending => {
+                            self.tx_state = DohDatagramAdapterTxState::PendingResponse(fut, start);
+                            break Poll::Pending;
                         }
                     }
-                    Poll::Ready(Err(_)) => {
-                        // TODO: log error
-                        self.rx_chan.0 = None;
-                    }
-                    Poll::Pending => {
-                        self.tx_state = DohDatagramAdapterTxState::PendingResponse(fut);
-                        break Poll::Pending;
-                    }
-                },
-                DohDatagramAdapterTxState::ReadingResponse(mut body, mut byte_bufs) => {
+                }
+                DohDatagramAdapterTxState::ReadingResponse(
+                    mut body,
+                    mut byte_bufs,
+                    freshness,
+                    start,
+                ) => {
                     let current_buf_len = byte_bufs.iter().map(|c| c.len()).sum();
                     match Pin::new(&mut body).poll_data(cx) {
                         Poll::Ready(None) => {
@@ -108,29 +330,34 @@ impl DatagramSession for DohDatagramAdapter {
                             for b in byte_bufs {
                                 buf.extend_from_slice(&b[..]);
                             }
+                            if let Some(freshness) = freshness {
+                                buf = cap_ttls(buf, freshness);
+                            }
+                            if buf.len() >= 2 {
+                                buf[..2].copy_from_slice(&self.query_id);
+                            }
+                            self.upstream.stats.record_success(start.elapsed());
                             if tx.start_send_unpin(buf).is_err() {
                                 self.rx_chan.0 = None;
                             }
                             self.tx_state = DohDatagramAdapterTxState::Idle;
                         }
-                        Poll::Ready(Some(Err(_))) => {
-                            // TODO: log error
-                            self.rx_chan.0 = None;
-                        }
+                        Poll::Ready(Some(Err(_))) => self.fail(),
                         Poll::Ready(Some(Ok(buf))) => {
                             if current_buf_len + buf.len() > 4096 {
                                 // Body too long
-                                // TODO: log error
-                                self.rx_chan.0 = None;
+                                self.fail();
                             } else {
                                 byte_bufs.push(buf);
-                                self.tx_state =
-                                    DohDatagramAdapterTxState::ReadingResponse(body, byte_bufs);
+                                self.tx_state = DohDatagramAdapterTxState::ReadingResponse(
+                                    body, byte_bufs, freshness, start,
+                                );
                             }
                         }
                         Poll::Pending => {
-                            self.tx_state =
-                                DohDatagramAdapterTxState::ReadingResponse(body, byte_bufs);
+                            self.tx_state = DohDatagramAdapterTxState::ReadingResponse(
+                                body, byte_bufs, freshness, start,
+                            );
                             break Poll::Pending;
                         }
                     }
@@ -139,19 +366,78 @@ impl DatagramSession for DohDatagramAdapter {
         }
     }
 
-    fn send_to(&mut self, _remote_peer: DestinationAddr, buf: Buffer) {
-        let req = Request::builder()
-            .method(Method::POST)
-            .uri(self.url.clone())
-            .header(ACCEPT, "application/dns-message")
-            .header(CONTENT_TYPE, "application/dns-message")
-            .body(buf.into())
-            .unwrap();
-        let fut = self.client.request(req);
-        self.tx_state = DohDatagramAdapterTxState::PendingResponse(fut);
+    fn send_to(&mut self, _remote_peer: DestinationAddr, mut buf: Buffer) {
+        if buf.len() >= 2 {
+            self.query_id = [buf[0], buf[1]];
+            if self.upstream.use_get {
+                // RFC 8484 section 4.1: use ID 0 to maximize HTTP cache hits
+                buf[..2].fill(0);
+            }
+        }
+        let req = self.upstream.build_request(buf);
+        self.upstream.stats.queries.fetch_add(1, Ordering::Relaxed);
+        self.tx_state = DohDatagramAdapterTxState::PendingResponse(
+            Box::pin(self.upstream.clone().send(req)),
+            Instant::now(),
+        );
     }
 
     fn poll_shutdown(&mut self, _cx: &mut Context<'_>) -> Poll<FlowResult<()>> {
         Poll::Ready(Ok(()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use http::HeaderValue;
+    use trust_dns_resolver::proto::op::Query;
+    use trust_dns_resolver::proto::rr::{Name, RData, RecordType};
+
+    use super::*;
+
+    #[test]
+    fn test_with_dns_param() {
+        let url: Uri = "https://dns.example/dns-query".parse().unwrap();
+        assert_eq!(
+            with_dns_param(&url, "AAAB").to_string(),
+            "https://dns.example/dns-query?dns=AAAB"
+        );
+        let url: Uri = "https://dns.example/q?ct=1".parse().unwrap();
+        assert_eq!(
+            with_dns_param(&url, "AAAB").to_string(),
+            "https://dns.example/q?ct=1&dns=AAAB"
+        );
+    }
+
+    #[test]
+    fn test_parse_freshness() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(parse_freshness(&headers), None);
+        headers.insert(
+            CACHE_CONTROL,
+            HeaderValue::from_static("public, max-age=300"),
+        );
+        assert_eq!(parse_freshness(&headers), Some(300));
+        headers.insert(AGE, HeaderValue::from_static("100"));
+        assert_eq!(parse_freshness(&headers), Some(200));
+        headers.insert(AGE, HeaderValue::from_static("400"));
+        assert_eq!(parse_freshness(&headers), Some(0));
+    }
+
+    #[test]
+    fn test_cap_ttls() {
+        let name = Name::from_ascii("example.com.").unwrap();
+        let mut msg = DnsMessage::new();
+        msg.add_query(Query::query(name.clone(), RecordType::A));
+        msg.add_answer(Record::from_rdata(
+            name.clone(),
+            600,
+            RData::A([1, 2, 3, 4].into()),
+        ));
+        msg.add_answer(Record::from_rdata(name, 60, RData::A([5, 6, 7, 8].into())));
+        let capped = cap_ttls(msg.to_vec().unwrap(), 120);
+        let capped = DnsMessage::from_bytes(&capped).unwrap();
+        let ttls: Vec<_> = capped.answers().iter().map(|r| r.ttl()).collect();
+        assert_eq!(ttls, [120, 60]);
+    }
+}
@@ -1,8 +1,10 @@
 pub mod doh_adapter;
+mod responder;
 mod udp_adapter;
 
 use std::net::SocketAddr;
 use std::sync::{Arc, Weak};
+use std::time::Duration;
 
 use async_trait::async_trait;
 use trust_dns_resolver::config::{
@@ -15,6 +17,7 @@ use trust_dns_resolver::name_server::{
 use trust_dns_resolver::AsyncResolver;
 
 use crate::flow::*;
+pub use responder::Responder;
 use udp_adapter::*;
 
 #[derive(Clone)]
@@ -37,6 +40,17 @@ impl HostResolver {
     pub fn new(
         datagram_hosts: impl IntoIterator<Item = Weak<dyn DatagramSessionFactory>>,
         doh: impl IntoIterator<Item = doh_adapter::DohDatagramAdapterFactory>,
+    ) -> Self {
+        Self::with_ttl_clamp(datagram_hosts, doh, None, None)
+    }
+
+    /// Like [`Self::new`], but bounds how long positive answers stay in the
+    /// resolver cache regardless of their TTLs.
+    pub fn with_ttl_clamp(
+        datagram_hosts: impl IntoIterator<Item = Weak<dyn DatagramSessionFactory>>,
+        doh: impl IntoIterator<Item = doh_adapter::DohDatagramAdapterFactory>,
+        min_ttl: Option<Duration>,
+        max_ttl: Option<Duration>,
     ) -> Self {
         let datagram_hosts = datagram_hosts.into_iter();
         let doh = doh.into_iter();
@@ -79,7 +93,11 @@ impl HostResolver {
         let inner =
             AsyncResolver::<GenericConnection, GenericConnectionProvider<FlowRuntime>>::new(
                 ResolverConfig::from_parts(None, vec![], NameServerConfigGroup::from(dns_configs)),
-                ResolverOpts::default(),
+                ResolverOpts {
+                    positive_min_ttl: min_ttl,
+                    positive_max_ttl: max_ttl,
+                    ..ResolverOpts::default()
+                },
                 TokioHandle,
             )
             .unwrap();
@@ -0,0 +1,67 @@
+use std::sync::{Arc, Mutex};
+
+use cbor4ii::serde::to_vec;
+use serde::Serialize;
+
+use super::doh_adapter::{DohStats, DohStatsSnapshot};
+use crate::control::{PluginRequestError, PluginRequestResult, PluginResponder};
+
+#[derive(Clone, PartialEq, Serialize)]
+struct DohInfo {
+    url: String,
+    #[serde(flatten)]
+    stats: DohStatsSnapshot,
+}
+
+#[derive(Clone, PartialEq, Serialize)]
+struct Info {
+    doh: Vec<DohInfo>,
+}
+
+pub struct Responder {
+    doh: Vec<(String, Arc<DohStats>)>,
+    last_info: Mutex<(Option<Info>, u32)>,
+}
+
+impl Responder {
+    pub fn new(doh: Vec<(String, Arc<DohStats>)>) -> Self {
+        Self {
+            doh,
+            last_info: Mutex::new((None, 1)),
+        }
+    }
+}
+
+impl PluginResponder for Responder {
+    fn collect_info(&self, hashcode: &mut u32) -> Option<Vec<u8>> {
+        let info = {
+            let mut last_info_guard = self.last_info.lock().unwrap();
+            let (last_info, last_hashcode) = &mut *last_info_guard;
+            let new_info = Info {
+                doh: self
+                    .doh
+                    .iter()
+                    .map(|(url, stats)| DohInfo {
+                        url: url.clone(),
+                        stats: stats.snapshot(),
+                    })
+                    .collect(),
+            };
+            if last_info.as_ref() == Some(&new_info) {
+                if *last_hashcode == *hashcode {
+                    return None;
+                }
+            } else {
+                *last_info = Some(new_info.clone());
+                *last_hashcode = (*last_hashcode).wrapping_add(1);
+            }
+            *hashcode = *last_hashcode;
+            new_info
+        };
+        Some(to_vec(vec![], &info).unwrap())
+    }
+
+    fn on_request(&self, _func: &str, _params: &[u8]) -> PluginRequestResult<Vec<u8>> {
+        Err(PluginRequestError::NoSuchFunc)
+    }
+}
@@ -1,19 +1,24 @@
+mod server;
 pub(crate) mod util;
 
 use std::io::Write;
 use std::sync::Weak;
+use std::time::Duration;
 
 use async_trait::async_trait;
 use base64::prelude::*;
 
 use crate::flow::*;
 
+pub use server::HttpProxyHandler;
+
 const REQ_BEFORE_ADDR: &[u8] = b"CONNECT ";
 const REQ_AFTER_ADDR_PART: &[u8] = b" HTTP/1.1";
 const BASIC_AUTH_HEADER: &[u8] = b"\r\nAuthorization: Basic ";
 
 pub struct HttpProxyOutboundFactory {
     req_after_addr: Vec<u8>,
+    handshake_timeout: Duration,
     next: Weak<dyn StreamOutboundFactory>,
 }
 
@@ -21,6 +26,7 @@ impl HttpProxyOutboundFactory {
     pub fn new(
         cred: Option<(&'_ [u8], &'_ [u8])>,
         next: Weak<dyn StreamOutboundFactory>,
+        handshake_timeout: Duration,
     ) -> HttpProxyOutboundFactory {
         fn estimate_b64_len(l: usize) -> usize {
             l * 4 / 3 + 4
@@ -52,6 +58,7 @@ impl HttpProxyOutboundFactory {
         req_after_addr.extend_from_slice(b"\r\n\r\n");
         HttpProxyOutboundFactory {
             req_after_addr,
+            handshake_timeout,
             next,
         }
     }
@@ -85,38 +92,16 @@ impl StreamOutboundFactory for HttpProxyOutboundFactory {
             req.extend_from_slice(initial_data);
             outbound_factory.create_outbound(context, &req[..]).await?
         };
-        let initial_res = {
-            let mut reader = StreamReader::new(4096, initial_res);
-            let mut expected_header_size = 1;
-            let mut code = None;
-            let mut res_header_size = 0;
-            let mut on_data = |data: &mut [u8]| {
-                if data.len() > 1024 {
-                    return Err(FlowError::UnexpectedData);
-                }
-                let mut res_headers = [httparse::EMPTY_HEADER; 4];
-                let mut res = httparse::Response::new(&mut res_headers[..]);
-                let ret = res.parse(data).map_err(|_| FlowError::UnexpectedData)?;
-                Ok(match ret {
-                    httparse::Status::Partial => Some(data.len()),
-                    httparse::Status::Complete(len) => {
-                        res_header_size = len;
-                        code = res.code;
-                        None
-                    }
-                })
-            };
-            while let Some(read_len) = reader
-                .peek_at_least(&mut *lower, expected_header_size, &mut on_data)
-                .await??
-            {
-                expected_header_size = read_len + 1;
-            }
-            code.filter(|c| (200..=299).contains(c))
-                .ok_or(FlowError::UnexpectedData)?;
-            reader.advance(res_header_size);
-            reader.into_buffer().unwrap_or_default()
-        };
+        let abort = context.abort.signal();
+        let (code, initial_res) = with_flow_deadline(
+            self.handshake_timeout,
+            &abort,
+            util::read_response_head(&mut *lower, initial_res),
+        )
+        .await?;
+        if !(200..=299).contains(&code) {
+            return Err(FlowError::UnexpectedData);
+        }
         Ok((lower, initial_res))
     }
 }
@@ -0,0 +1,309 @@
+use std::net::IpAddr;
+use std::sync::Weak;
+
+use base64::prelude::*;
+use futures::future::poll_fn;
+
+use crate::flow::*;
+
+const MAX_REQUEST_HEAD_LEN: usize = 8 * 1024;
+const RES_ESTABLISHED: &[u8] = b"HTTP/1.1 200 Connection established\r\n\r\n";
+const RES_BAD_REQUEST: &[u8] = b"HTTP/1.1 400 Bad Request\r\nConnection: close\r\n\r\n";
+const RES_AUTH_REQUIRED: &[u8] = b"HTTP/1.1 407 Proxy Authentication Required\r\n\
+    Proxy-Authenticate: Basic realm=\"ytflow\"\r\n\
+    Connection: close\r\n\r\n";
+
+/// Accepts `CONNECT` requests and plain HTTP requests in absolute form from
+/// HTTP proxy clients.
+///
+/// Plain HTTP requests are rewritten into origin form with `Connection: close`,
+/// so that each connection carries requests to a single origin.
+pub struct HttpProxyHandler {
+    /// Base64-encoded `user:pass` expected in `Proxy-Authorization`.
+    auth_token: Option<Vec<u8>>,
+    next: Weak<dyn StreamHandler>,
+}
+
+struct RequestHead {
+    method: String,
+    target: String,
+    minor_version: u8,
+    headers: Vec<(String, Vec<u8>)>,
+}
+
+impl RequestHead {
+    fn header(&self, name: &str) -> Option<&[u8]> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| &**v)
+    }
+}
+
+impl HttpProxyHandler {
+    pub fn new(cred: Option<(&[u8], &[u8])>, next: Weak<dyn StreamHandler>) -> Self {
+        let auth_token = cred.map(|(user, pass)| {
+            let mut cred_plain = Vec::with_capacity(user.len() + pass.len() + 1);
+            cred_plain.extend_from_slice(user);
+            cred_plain.push(b':');
+            cred_plain.extend_from_slice(pass);
+            BASE64_STANDARD.encode(cred_plain).into_bytes()
+        });
+        Self { auth_token, next }
+    }
+}
+
+async fn read_request_head(
+    lower: &mut dyn Stream,
+    reader: &mut StreamReader,
+) -> FlowResult<RequestHead> {
+    let mut expected_head_size = 1;
+    let mut head = None;
+    let mut head_size = 0;
+    let mut on_data = |data: &mut [u8]| {
+        let mut headers = [httparse::EMPTY_HEADER; 64];
+        let mut req = httparse::Request::new(&mut headers[..]);
+        let ret = req.parse(data).map_err(|_| FlowError::UnexpectedData)?;
+        Ok(match ret {
+            httparse::Status::Partial if data.len() >= MAX_REQUEST_HEAD_LEN => {
+                return Err(FlowError::UnexpectedData)
+            }
+            httparse::Status::Partial => Some(data.len()),
+            httparse::Status::Complete(len) => {
+                head_size = len;
+                head = Some(RequestHead {
+                    method: req.method.unwrap_or_default().to_string(),
+                    target: req.path.unwrap_or_default().to_string(),
+                    minor_version: req.version.unwrap_or(1),
+                    headers: req
+                        .headers
+                        .iter()
+                        .map(|h| (h.name.to_string(), h.value.to_vec()))
+                        .collect(),
+                });
+                None
+            }
+        })
+    };
+    while let Some(read_len) = reader
+        .peek_at_least(lower, expected_head_size, &mut on_data)
+        .await??
+    {
+        expected_head_size = read_len + 1;
+    }
+    reader.advance(head_size);
+    head.ok_or(FlowError::UnexpectedData)
+}
+
+fn parse_authority(authority: &str, default_port: u16) -> Option<DestinationAddr> {
+    let authority: http::uri::Authority = authority.parse().ok()?;
+    let host = authority
+        .host()
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    let host = match host.parse::<IpAddr>() {
+        Ok(ip) => HostName::Ip(ip),
+        Err(_) => HostName::from_domain_name(host.to_string()).ok()?,
+    };
+    Some(DestinationAddr {
+        host,
+        port: authority.port_u16().unwrap_or(default_port),
+    })
+}
+
+/// Rewrite a request in absolute form into origin form, dropping headers meant
+/// for the proxy. Returns the destination and the new request head.
+fn rewrite_absolute_request(head: &RequestHead) -> Option<(DestinationAddr, Buffer)> {
+    let uri: http::Uri = head.target.parse().ok()?;
+    if uri.scheme() != Some(&http::uri::Scheme::HTTP) {
+        return None;
+    }
+    let authority = uri.authority()?;
+    let dest = parse_authority(authority.as_str(), 80)?;
+    let path = uri.path_and_query().map_or("/", |p| p.as_str());
+
+    let mut req = Vec::with_capacity(MAX_REQUEST_HEAD_LEN);
+    req.extend_from_slice(head.method.as_bytes());
+    req.push(b' ');
+    req.extend_from_slice(path.as_bytes());
+    req.extend_from_slice(format!(" HTTP/1.{}\r\n", head.minor_version).as_bytes());
+    if head.header("host").is_none() {
+        req.extend_from_slice(b"Host: ");
+        req.extend_from_slice(authority.as_str().as_bytes());
+        req.extend_from_slice(b"\r\n");
+    }
+    for (name, value) in &head.headers {
+        if [
+            "proxy-authorization",
+            "proxy-connection",
+            "connection",
+            "keep-alive",
+        ]
+        .iter()
+        .any(|h| name.eq_ignore_ascii_case(h))
+        {
+            continue;
+        }
+        req.extend_from_slice(name.as_bytes());
+        req.extend_from_slice(b": ");
+        req.extend_from_slice(value);
+        req.extend_from_slice(b"\r\n");
+    }
+    req.extend_from_slice(b"Connection: close\r\n\r\n");
+    Some((dest, req))
+}
+
+fn is_authorized(auth_token: &Option<Vec<u8>>, head: &RequestHead) -> bool {
+    let Some(expected) = auth_token else {
+        return true;
+    };
+    let Some(value) = head.header("proxy-authorization") else {
+        return false;
+    };
+    let Some(pos) = value.iter().position(|&b| b == b' ') else {
+        return false;
+    };
+    let (scheme, token) = value.split_at(pos);
+    let start = token.iter().position(|b| !b.is_ascii_whitespace());
+    let end = token.iter().rposition(|b| !b.is_ascii_whitespace());
+    let token = match (start, end) {
+        (Some(start), Some(end)) => &token[start..=end],
+        _ => &[][..],
+    };
+    scheme.eq_ignore_ascii_case(b"basic") && token == &expected[..]
+}
+
+/// Returns the destination and data to be sent to it.
+async fn serve_handshake(
+    auth_token: &Option<Vec<u8>>,
+    lower: &mut dyn Stream,
+    initial_data: Buffer,
+) -> FlowResult<(DestinationAddr, Buffer)> {
+    let mut reader = StreamReader::new(4096, initial_data);
+    let head = read_request_head(lower, &mut reader).await?;
+    if !is_authorized(auth_token, &head) {
+        send_response(lower, RES_AUTH_REQUIRED).await?;
+        return Err(FlowError::UnexpectedData);
+    }
+    let req = if head.method.eq_ignore_ascii_case("CONNECT") {
+        parse_authority(&head.target, 443).map(|dest| (dest, None))
+    } else {
+        rewrite_absolute_request(&head).map(|(dest, req)| (dest, Some(req)))
+    };
+    let Some((dest, rewritten)) = req else {
+        send_response(lower, RES_BAD_REQUEST).await?;
+        return Err(FlowError::UnexpectedData);
+    };
+    let remaining = reader.into_buffer().unwrap_or_default();
+    let initial_data = match rewritten {
+        Some(mut req) => {
+            req.extend_from_slice(&remaining);
+            req
+        }
+        None => {
+            send_response(lower, RES_ESTABLISHED).await?;
+            remaining
+        }
+    };
+    Ok((dest, initial_data))
+}
+
+async fn send_response(lower: &mut dyn Stream, data: &[u8]) -> FlowResult<()> {
+    let len = data.len().try_into().unwrap();
+    let mut tx_buf = poll_fn(|cx| lower.poll_tx_buffer(cx, len)).await?;
+    tx_buf.extend_from_slice(data);
+    lower.commit_tx_buffer(tx_buf)?;
+    poll_fn(|cx| lower.poll_flush_tx(cx)).await
+}
+
+impl StreamHandler for HttpProxyHandler {
+    fn on_stream(
+        &self,
+        mut lower: Box<dyn Stream>,
+        initial_data: Buffer,
+        mut context: Box<FlowContext>,
+    ) {
+        let Some(next) = self.next.upgrade() else {
+            return;
+        };
+        let auth_token = self.auth_token.clone();
+        tokio::spawn(async move {
+            let abort = context.abort.signal();
+            let Ok((dest, initial_data)) = with_flow_deadline(
+                DEFAULT_HANDSHAKE_TIMEOUT,
+                &abort,
+                serve_handshake(&auth_token, &mut *lower, initial_data),
+            )
+            .await
+            else {
+                return;
+            };
+            context.remote_peer = dest;
+            context.af_sensitive = false;
+            next.on_stream(lower, initial_data, context)
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn head(target: &str, headers: &[(&str, &str)]) -> RequestHead {
+        RequestHead {
+            method: "GET".into(),
+            target: target.into(),
+            minor_version: 1,
+            headers: headers
+                .iter()
+                .map(|(n, v)| (n.to_string(), v.as_bytes().to_vec()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_parse_authority() {
+        let dest = parse_authority("[::1]:8080", 443).unwrap();
+        assert_eq!(dest.host, HostName::Ip("::1".parse().unwrap()));
+        assert_eq!(dest.port, 8080);
+        let dest = parse_authority("example.com", 443).unwrap();
+        assert_eq!(dest.host.to_string(), "example.com.");
+        assert_eq!(dest.port, 443);
+    }
+
+    #[test]
+    fn test_rewrite_absolute_request() {
+        let (dest, req) = rewrite_absolute_request(&head(
+            "http://example.com:8080/a?b=c",
+            &[
+                ("Host", "example.com:8080"),
+                ("Proxy-Connection", "keep-alive"),
+                ("Proxy-Authorization", "Basic dTpw"),
+                ("Accept", "*/*"),
+            ],
+        ))
+        .unwrap();
+        assert_eq!(dest.port, 8080);
+        assert_eq!(
+            std::str::from_utf8(&req).unwrap(),
+            "GET /a?b=c HTTP/1.1\r\nHost: example.com:8080\r\nAccept: */*\r\nConnection: close\r\n\r\n"
+        );
+        assert!(rewrite_absolute_request(&head("/relative", &[])).is_none());
+        assert!(rewrite_absolute_request(&head("https://example.com/", &[])).is_none());
+    }
+
+    #[test]
+    fn test_is_authorized() {
+        let token = Some(b"dTpw".to_vec());
+        assert!(is_authorized(&None, &head("/", &[])));
+        assert!(!is_authorized(&token, &head("/", &[])));
+        assert!(is_authorized(
+            &token,
+            &head("/", &[("proxy-authorization", "basic dTpw")])
+        ));
+        assert!(!is_authorized(
+            &token,
+            &head("/", &[("Proxy-Authorization", "Basic eDp5")])
+        ));
+    }
+}
@@ -1,3 +1,5 @@
+use crate::flow::*;
+
 pub fn format_u16(mut port: u16, buf: &mut [u8; 5]) -> usize {
     let mut cursor = 0;
     if port >= 10 {
@@ -23,3 +25,41 @@ pub fn format_u16(mut port: u16, buf: &mut [u8; 5]) -> usize {
     buf[cursor] = (b'0' as u16 + port) as u8;
     cursor + 1
 }
+
+/// Read an HTTP/1.1 response head from `lower`. Returns the status code and
+/// any data received after the head.
+pub(super) async fn read_response_head(
+    lower: &mut dyn Stream,
+    initial_res: Buffer,
+) -> FlowResult<(u16, Buffer)> {
+    let mut reader = StreamReader::new(4096, initial_res);
+    let mut expected_header_size = 1;
+    let mut code = None;
+    let mut res_header_size = 0;
+    let mut on_data = |data: &mut [u8]| {
+        if data.len() > 4096 {
+            return Err(FlowError::UnexpectedData);
+        }
+        // Proxies commonly add headers like Date, Server or Via to the response.
+        let mut res_headers = [httparse::EMPTY_HEADER; 32];
+        let mut res = httparse::Response::new(&mut res_headers[..]);
+        let ret = res.parse(data).map_err(|_| FlowError::UnexpectedData)?;
+        Ok(match ret {
+            httparse::Status::Partial => Some(data.len()),
+            httparse::Status::Complete(len) => {
+                res_header_size = len;
+                code = res.code;
+                None
+            }
+        })
+    };
+    while let Some(read_len) = reader
+        .peek_at_least(lower, expected_header_size, &mut on_data)
+        .await??
+    {
+        expected_header_size = read_len + 1;
+    }
+    let code = code.ok_or(FlowError::UnexpectedData)?;
+    reader.advance(res_header_size);
+    Ok((code, reader.into_buffer().unwrap_or_default()))
+}
@@ -0,0 +1,10 @@
+//! Per-user accounting shared by server-side plugins that accept several
+//! passwords or UUIDs on a single endpoint.
+
+mod responder;
+mod stream;
+mod table;
+
+pub use responder::Responder;
+pub use stream::{UserDatagramSession, UserStream};
+pub use table::{AuthError, InboundUserTable, UserSession, UserStatus};
@@ -0,0 +1,72 @@
+use std::sync::Arc;
+
+use cbor4ii::serde::{from_slice, to_vec};
+use serde::{Deserialize, Serialize};
+
+use super::{InboundUserTable, UserStatus};
+use crate::control::{PluginRequestError, PluginRequestResult, PluginResponder};
+use crate::data::{DataResult, InboundUser};
+
+pub struct Responder {
+    table: Arc<InboundUserTable>,
+}
+
+impl Responder {
+    pub fn new(table: Arc<InboundUserTable>) -> Self {
+        Self { table }
+    }
+
+    fn update_and_reload(
+        &self,
+        update: impl FnOnce(&crate::data::Connection) -> DataResult<()>,
+    ) -> Vec<u8> {
+        let res = self
+            .table
+            .db
+            .connect()
+            .and_then(|conn| update(&conn))
+            .and_then(|()| self.table.reload())
+            .err()
+            .map(|e| format!("{}", e));
+        to_vec(vec![], &res).unwrap()
+    }
+}
+
+#[derive(Serialize)]
+struct Info {
+    users: Vec<UserStatus>,
+}
+
+#[derive(Deserialize)]
+struct SetEnabledParams {
+    id: u32,
+    enabled: bool,
+}
+
+impl PluginResponder for Responder {
+    fn collect_info(&self, _hashcode: &mut u32) -> Option<Vec<u8>> {
+        let info = Info {
+            users: self.table.statuses(),
+        };
+        Some(to_vec(vec![], &info).unwrap())
+    }
+
+    fn on_request(&self, func: &str, params: &[u8]) -> PluginRequestResult<Vec<u8>> {
+        Ok(match func {
+            "set_enabled" => {
+                let SetEnabledParams { id, enabled } = from_slice(params)?;
+                self.update_and_reload(|conn| InboundUser::set_enabled(id, enabled, conn))
+            }
+            "reset_usage" => {
+                let id: u32 = from_slice(params)?;
+                // Write pending traffic first so that it does not survive the reset
+                self.table.flush_blocking();
+                self.update_and_reload(|conn| InboundUser::reset_usage(id, conn))
+            }
+            "reload" => self.update_and_reload(|_| Ok(())),
+            _ => {
+                return Err(PluginRequestError::NoSuchFunc);
+            }
+        })
+    }
+}
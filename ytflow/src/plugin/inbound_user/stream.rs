@@ -0,0 +1,93 @@
+use std::num::NonZeroUsize;
+use std::task::{Context, Poll};
+
+use super::UserSession;
+use crate::flow::*;
+
+/// A stream from an authenticated client. Data received from the client is
+/// counted as upload, and data sent to it as download. Once the user is
+/// disabled, the stream is cut off in both directions.
+pub struct UserStream {
+    pub lower: Box<dyn Stream>,
+    pub session: UserSession,
+}
+
+impl Stream for UserStream {
+    fn poll_request_size(&mut self, cx: &mut Context<'_>) -> Poll<FlowResult<SizeHint>> {
+        if !self.session.is_enabled() {
+            return Poll::Ready(Err(FlowError::Eof));
+        }
+        self.lower.poll_request_size(cx)
+    }
+
+    fn commit_rx_buffer(&mut self, buffer: Buffer) -> Result<(), (Buffer, FlowError)> {
+        self.lower.commit_rx_buffer(buffer)
+    }
+
+    fn poll_rx_buffer(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Buffer, (Buffer, FlowError)>> {
+        let res = self.lower.poll_rx_buffer(cx);
+        if let Poll::Ready(Ok(buf)) = &res {
+            self.session.state.add_upload(buf.len());
+        }
+        res
+    }
+
+    fn poll_tx_buffer(
+        &mut self,
+        cx: &mut Context<'_>,
+        size: NonZeroUsize,
+    ) -> Poll<FlowResult<Buffer>> {
+        if !self.session.is_enabled() {
+            return Poll::Ready(Err(FlowError::Eof));
+        }
+        self.lower.poll_tx_buffer(cx, size)
+    }
+
+    fn commit_tx_buffer(&mut self, buffer: Buffer) -> FlowResult<()> {
+        self.session.state.add_download(buffer.len());
+        self.lower.commit_tx_buffer(buffer)
+    }
+
+    fn poll_flush_tx(&mut self, cx: &mut Context<'_>) -> Poll<FlowResult<()>> {
+        self.lower.poll_flush_tx(cx)
+    }
+
+    fn poll_close_tx(&mut self, cx: &mut Context<'_>) -> Poll<FlowResult<()>> {
+        self.lower.poll_close_tx(cx)
+    }
+}
+
+/// The datagram counterpart of [`UserStream`].
+pub struct UserDatagramSession {
+    pub lower: Box<dyn DatagramSession>,
+    pub session: UserSession,
+}
+
+impl DatagramSession for UserDatagramSession {
+    fn poll_recv_from(&mut self, cx: &mut Context) -> Poll<Option<(DestinationAddr, Buffer)>> {
+        if !self.session.is_enabled() {
+            return Poll::Ready(None);
+        }
+        let res = self.lower.poll_recv_from(cx);
+        if let Poll::Ready(Some((_, buf))) = &res {
+            self.session.state.add_upload(buf.len());
+        }
+        res
+    }
+    fn poll_send_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        self.lower.poll_send_ready(cx)
+    }
+    fn send_to(&mut self, remote_peer: DestinationAddr, buf: Buffer) {
+        if !self.session.is_enabled() {
+            return;
+        }
+        self.session.state.add_download(buf.len());
+        self.lower.send_to(remote_peer, buf)
+    }
+    fn poll_shutdown(&mut self, cx: &mut Context<'_>) -> Poll<FlowResult<()>> {
+        self.lower.poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,249 @@
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+
+use chrono::{NaiveDateTime, Utc};
+use serde::Serialize;
+
+use crate::atomic::AtomicU64;
+use crate::data::{DataResult, Database, InboundUser, InboundUserId, PluginId};
+
+const FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum AuthError {
+    #[error("Unknown user")]
+    UnknownUser,
+    #[error("User is disabled")]
+    Disabled,
+    #[error("Too many connections")]
+    TooManyConnections,
+}
+
+pub(super) struct UserState {
+    /// Snapshot of the database row, with usage updated on each flush.
+    user: Mutex<InboundUser>,
+    enabled: AtomicBool,
+    connections: AtomicU32,
+    seen: AtomicBool,
+    pending_upload: AtomicU64,
+    pending_download: AtomicU64,
+}
+
+impl UserState {
+    fn new(user: InboundUser) -> Self {
+        Self {
+            enabled: AtomicBool::new(user.enabled),
+            user: Mutex::new(user),
+            connections: AtomicU32::new(0),
+            seen: AtomicBool::new(false),
+            pending_upload: AtomicU64::new(0),
+            pending_download: AtomicU64::new(0),
+        }
+    }
+
+    pub(super) fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub(super) fn add_upload(&self, len: usize) {
+        self.pending_upload.fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    pub(super) fn add_download(&self, len: usize) {
+        self.pending_download
+            .fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    fn flush_blocking(&self, db: &Database) {
+        let seen = self.seen.swap(false, Ordering::Relaxed);
+        let upload = self.pending_upload.swap(0, Ordering::Relaxed);
+        let download = self.pending_download.swap(0, Ordering::Relaxed);
+        if !seen && upload == 0 && download == 0 {
+            return;
+        }
+        let id = self.user.lock().unwrap().id;
+        let res = db
+            .connect()
+            .and_then(|conn| InboundUser::add_usage(id.0, upload, download, &conn));
+        if res.is_err() {
+            // TODO: log error
+            // Keep the traffic around so that it can be written next time.
+            self.seen.store(true, Ordering::Relaxed);
+            self.pending_upload.fetch_add(upload, Ordering::Relaxed);
+            self.pending_download.fetch_add(download, Ordering::Relaxed);
+            return;
+        }
+        let mut user = self.user.lock().unwrap();
+        user.upload_bytes = user.upload_bytes.saturating_add(upload);
+        user.download_bytes = user.download_bytes.saturating_add(download);
+        user.last_seen_at = Some(Utc::now().naive_utc());
+    }
+}
+
+/// A connection of an authenticated user. The connection slot is released
+/// when dropped.
+pub struct UserSession {
+    pub(super) state: Arc<UserState>,
+}
+
+impl UserSession {
+    pub fn user_id(&self) -> InboundUserId {
+        self.state.user.lock().unwrap().id
+    }
+    /// Whether the user is still enabled. Server plugins should stop relaying
+    /// traffic once it turns `false`.
+    pub fn is_enabled(&self) -> bool {
+        self.state.is_enabled()
+    }
+}
+
+impl Drop for UserSession {
+    fn drop(&mut self) {
+        self.state.connections.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UserStatus {
+    pub id: InboundUserId,
+    pub name: String,
+    pub enabled: bool,
+    pub max_connections: Option<u32>,
+    pub connections: u32,
+    /// Including traffic not yet written to the database.
+    pub upload_bytes: u64,
+    pub download_bytes: u64,
+    pub last_seen_at: Option<NaiveDateTime>,
+}
+
+/// Users of a server-side plugin loaded from the database, along with their
+/// live connection counts and traffic not yet written back.
+pub struct InboundUserTable {
+    pub(super) db: Database,
+    plugin_id: PluginId,
+    users: Mutex<Vec<Arc<UserState>>>,
+}
+
+impl InboundUserTable {
+    pub fn new(db: Database, plugin_id: PluginId) -> DataResult<Self> {
+        let this = Self {
+            db,
+            plugin_id,
+            users: Mutex::new(vec![]),
+        };
+        this.reload()?;
+        Ok(this)
+    }
+
+    /// Pick up changes made to the database. Connection counts and pending
+    /// traffic of existing users are kept.
+    pub fn reload(&self) -> DataResult<()> {
+        let conn = self.db.connect()?;
+        let fresh = InboundUser::query_all_by_plugin(self.plugin_id, &conn)?;
+        let mut users = self.users.lock().unwrap();
+        let next = fresh
+            .into_iter()
+            .map(|user| {
+                let existing = users
+                    .iter()
+                    .find(|s| s.user.lock().unwrap().id == user.id)
+                    .cloned();
+                match existing {
+                    Some(state) => {
+                        state.enabled.store(user.enabled, Ordering::Relaxed);
+                        *state.user.lock().unwrap() = user;
+                        state
+                    }
+                    None => Arc::new(UserState::new(user)),
+                }
+            })
+            .collect();
+        *users = next;
+        Ok(())
+    }
+
+    /// Authenticate a client presenting `credential` verbatim.
+    pub fn authenticate(&self, credential: &[u8]) -> Result<UserSession, AuthError> {
+        self.authenticate_with(|c| c == credential)
+    }
+
+    /// Authenticate a client by the first user whose credential satisfies
+    /// `matches`, for protocols that only carry a digest of the credential.
+    pub fn authenticate_with(
+        &self,
+        mut matches: impl FnMut(&[u8]) -> bool,
+    ) -> Result<UserSession, AuthError> {
+        let state = self
+            .users
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|s| matches(&s.user.lock().unwrap().credential))
+            .cloned()
+            .ok_or(AuthError::UnknownUser)?;
+        if !state.is_enabled() {
+            return Err(AuthError::Disabled);
+        }
+        let max_connections = state.user.lock().unwrap().max_connections;
+        let prev = state.connections.fetch_add(1, Ordering::AcqRel);
+        if max_connections.is_some_and(|max| prev >= max) {
+            state.connections.fetch_sub(1, Ordering::AcqRel);
+            return Err(AuthError::TooManyConnections);
+        }
+        state.seen.store(true, Ordering::Relaxed);
+        Ok(UserSession { state })
+    }
+
+    pub fn statuses(&self) -> Vec<UserStatus> {
+        self.users
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|s| {
+                let user = s.user.lock().unwrap();
+                UserStatus {
+                    id: user.id,
+                    name: user.name.clone(),
+                    enabled: s.is_enabled(),
+                    max_connections: user.max_connections,
+                    connections: s.connections.load(Ordering::Relaxed),
+                    upload_bytes: user
+                        .upload_bytes
+                        .saturating_add(s.pending_upload.load(Ordering::Relaxed)),
+                    download_bytes: user
+                        .download_bytes
+                        .saturating_add(s.pending_download.load(Ordering::Relaxed)),
+                    last_seen_at: user.last_seen_at,
+                }
+            })
+            .collect()
+    }
+
+    pub fn flush_blocking(&self) {
+        let users = self.users.lock().unwrap().clone();
+        for state in users {
+            state.flush_blocking(&self.db);
+        }
+    }
+
+    /// Write traffic of all users back to the database periodically until the
+    /// table is dropped.
+    pub async fn run_flush(self: Weak<Self>) {
+        let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let Some(this) = self.upgrade() else {
+                break;
+            };
+            let _ = tokio::task::spawn_blocking(move || this.flush_blocking()).await;
+        }
+    }
+}
+
+impl Drop for InboundUserTable {
+    fn drop(&mut self) {
+        self.flush_blocking();
+    }
+}
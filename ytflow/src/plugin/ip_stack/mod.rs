@@ -1,34 +1,68 @@
 mod datagram;
+mod responder;
 mod stream;
 mod tcp_socket_entry;
+mod tcp_stack;
+mod tcp_tuning;
 
 use std::collections::btree_map::{BTreeMap, Entry};
 use std::future::Future;
 use std::mem::ManuallyDrop;
-use std::net::{IpAddr, SocketAddr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::pin::Pin;
-use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::atomic::Ordering;
 use std::sync::{Arc, Mutex, Weak};
 use std::time::{Duration, Instant};
 
+use cidr::{Ipv4Inet, Ipv6Inet};
 use flume::{bounded, Sender, TrySendError};
 use smoltcp::iface::{Config as InterfaceConfig, Interface, SocketHandle, SocketSet};
 use smoltcp::phy::{Checksum, ChecksumCapabilities, DeviceCapabilities, Medium};
-use smoltcp::socket::tcp::Socket as TcpSocket;
-use smoltcp::storage::RingBuffer;
 use smoltcp::time::Instant as SmolInstant;
 use smoltcp::wire::{
-    HardwareAddress, IpAddress, IpCidr, IpEndpoint, IpProtocol, Ipv4Address, Ipv4Packet,
-    Ipv6Address, Ipv6Packet, TcpPacket, UdpPacket,
+    HardwareAddress, IpAddress, IpCidr, IpEndpoint, IpProtocol, Ipv4Packet, Ipv6Packet, TcpPacket,
+    UdpPacket,
 };
 use tokio::time::sleep_until;
 
+use crate::atomic::AtomicI64;
+use crate::control::KillSwitch;
 use crate::flow::*;
+pub use responder::Responder;
+pub use tcp_stack::{TcpCongestion, TcpCongestionReport, TcpCongestionStats};
+pub use tcp_tuning::TcpOptions;
+
+/// Addresses and MTU of the userspace network interface facing the TUN.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InterfaceOptions {
+    pub ipv4: Ipv4Inet,
+    pub ipv6: Ipv6Inet,
+    /// Must be one of the interface addresses for smoltcp to accept packets
+    /// destined to arbitrary addresses.
+    pub ipv4_gateway: Ipv4Addr,
+    pub ipv6_gateway: Ipv6Addr,
+    pub mtu: usize,
+}
+
+impl Default for InterfaceOptions {
+    fn default() -> Self {
+        let ipv4 = Ipv4Addr::new(192, 168, 3, 1);
+        let ipv6 = Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 2);
+        Self {
+            ipv4: Ipv4Inet::new(ipv4, 0).expect("0 is a valid IPv4 prefix length"),
+            ipv6: Ipv6Inet::new(ipv6, 0).expect("0 is a valid IPv6 prefix length"),
+            ipv4_gateway: ipv4,
+            ipv6_gateway: ipv6,
+            mtu: 1500,
+        }
+    }
+}
 
 struct Device {
     tx: Option<TunBufferToken>,
     rx: Option<Buffer>,
     tun: Arc<dyn Tun>,
+    mtu: usize,
 }
 
 impl smoltcp::phy::Device for Device {
@@ -57,7 +91,7 @@ impl smoltcp::phy::Device for Device {
         checksum.icmpv4 = Checksum::Tx;
         let mut dev = DeviceCapabilities::default();
         dev.medium = Medium::Ip;
-        dev.max_transmission_unit = 1500;
+        dev.max_transmission_unit = self.mtu;
         dev.checksum = checksum;
         dev
     }
@@ -121,6 +155,7 @@ impl<'d> smoltcp::phy::TxToken for TxToken<'d> {
 type IpStack = Arc<Mutex<IpStackInner>>;
 
 struct IpStackInner {
+    plugin_name: Arc<str>,
     netif: Interface,
     dev: Device,
     socket_set: SocketSet<'static>,
@@ -129,17 +164,34 @@ struct IpStackInner {
     udp_sockets: BTreeMap<SocketAddr, Sender<(DestinationAddr, Buffer)>>,
     tcp_next: Weak<dyn StreamHandler>,
     udp_next: Weak<dyn DatagramSessionHandler>,
+    tcp_options: TcpOptions,
+    buffer_tuner: tcp_tuning::BufferTuner,
+    udp_session_timeout: Duration,
+    tcp_congestions: Vec<(TcpCongestion, Arc<TcpCongestionStats>)>,
+    /// Index of the congestion controller to accept the next connection with.
+    tcp_congestion_next: usize,
 }
 
 pub fn run(
+    plugin_name: Arc<str>,
     tun: Arc<dyn Tun>,
     tcp_next: Weak<dyn StreamHandler>,
     udp_next: Weak<dyn DatagramSessionHandler>,
+    interface: InterfaceOptions,
+    tcp_options: TcpOptions,
+    tcp_congestions: Vec<(TcpCongestion, Arc<TcpCongestionStats>)>,
+    udp_session_timeout: Duration,
+    kill_switch: Option<KillSwitch>,
 ) -> tokio::task::JoinHandle<()> {
+    assert!(
+        !tcp_congestions.is_empty(),
+        "ip-stack requires a TCP congestion controller"
+    );
     let mut dev = Device {
         tx: None,
         rx: None,
         tun: tun.clone(),
+        mtu: interface.mtu,
     };
     let mut netif = Interface::new(
         InterfaceConfig::new(HardwareAddress::Ip),
@@ -148,19 +200,28 @@ pub fn run(
     );
     netif.set_any_ip(true);
     netif.update_ip_addrs(|ips| {
-        ips.push(IpCidr::new(Ipv4Address::new(192, 168, 3, 1).into(), 0))
-            .expect("IPv4 address should not exceed capacity");
+        ips.push(IpCidr::new(
+            IpAddress::Ipv4(interface.ipv4.address().into()),
+            interface.ipv4.network_length(),
+        ))
+        .expect("IPv4 address should not exceed capacity");
+        ips.push(IpCidr::new(
+            IpAddress::Ipv6(interface.ipv6.address().into()),
+            interface.ipv6.network_length(),
+        ))
+        .expect("IPv6 address should not exceed capacity");
     });
     netif
         .routes_mut()
-        .add_default_ipv4_route(Ipv4Address::new(192, 168, 3, 1))
+        .add_default_ipv4_route(interface.ipv4_gateway.into())
         .expect("IPv4 route should not exceed capacity");
     netif
         .routes_mut()
-        .add_default_ipv6_route(Ipv6Address::new(0xfd00, 0, 0, 0, 0, 0, 0, 2))
+        .add_default_ipv6_route(interface.ipv6_gateway.into())
         .expect("IPv6 route should not exceed capacity");
 
     let stack = Arc::new(Mutex::new(IpStackInner {
+        plugin_name,
         netif,
         dev,
         socket_set: SocketSet::new(vec![]),
@@ -168,9 +229,19 @@ pub fn run(
         udp_sockets: BTreeMap::new(),
         tcp_next,
         udp_next,
+        tcp_options,
+        buffer_tuner: tcp_tuning::BufferTuner::new(&tcp_options),
+        udp_session_timeout,
+        tcp_congestions,
+        tcp_congestion_next: 0,
     }));
     tokio::runtime::Handle::current().spawn_blocking(move || {
         while let Some(recv_buf) = tun.blocking_recv() {
+            crate::log::probe::tun_recv(recv_buf.len());
+            if kill_switch.as_ref().is_some_and(|k| k.is_engaged()) {
+                tun.return_recv_buffer(recv_buf);
+                continue;
+            }
             process_packet(&stack, recv_buf);
         }
     })
@@ -182,6 +253,7 @@ fn process_packet(stack: &IpStack, packet: Buffer) {
     }
     match packet[0] >> 4 {
         0b0100 => {
+            let traffic_class = packet[1];
             let mut ipv4_packet = match Ipv4Packet::new_checked(packet) {
                 Ok(p) => p,
                 Err(_) => return,
@@ -200,6 +272,7 @@ fn process_packet(stack: &IpStack, packet: Buffer) {
                         dst_addr.into(),
                         dst_port,
                         is_syn,
+                        traffic_class,
                         ipv4_packet.into_inner(),
                     );
                 }
@@ -214,6 +287,7 @@ fn process_packet(stack: &IpStack, packet: Buffer) {
                         SocketAddr::new(smoltcp_addr_to_std(src_addr.into()), src_port),
                         dst_addr.into(),
                         dst_port,
+                        traffic_class,
                         p.payload_mut(),
                     );
                 }
@@ -221,6 +295,7 @@ fn process_packet(stack: &IpStack, packet: Buffer) {
             }
         }
         0b0110 => {
+            let traffic_class = (packet[0] << 4) | (packet[1] >> 4);
             let mut ipv6_packet = match Ipv6Packet::new_checked(packet) {
                 Ok(p) => p,
                 Err(_) => return,
@@ -239,6 +314,7 @@ fn process_packet(stack: &IpStack, packet: Buffer) {
                         dst_addr.into(),
                         dst_port,
                         is_syn,
+                        traffic_class,
                         ipv6_packet.into_inner(),
                     );
                 }
@@ -253,6 +329,7 @@ fn process_packet(stack: &IpStack, packet: Buffer) {
                         SocketAddr::new(smoltcp_addr_to_std(src_addr.into()), src_port),
                         dst_addr.into(),
                         dst_port,
+                        traffic_class,
                         p.payload_mut(),
                     );
                 }
@@ -269,15 +346,21 @@ fn process_tcp(
     dst_addr: smoltcp::wire::IpAddress,
     dst_port: u16,
     is_syn: bool,
+    traffic_class: u8,
     packet: Buffer,
 ) {
     let mut guard = stack.lock().unwrap();
     let IpStackInner {
+        plugin_name,
         netif,
         tcp_sockets,
         tcp_next,
         dev,
         socket_set,
+        tcp_options,
+        buffer_tuner,
+        tcp_congestions,
+        tcp_congestion_next,
         ..
     } = &mut *guard;
 
@@ -285,39 +368,40 @@ fn process_tcp(
 
     let tcp_socket_count = tcp_sockets.len();
     if let Entry::Vacant(vac) = tcp_sockets.entry(src_addr) {
-        if !is_syn || tcp_socket_count >= 1 << 10 {
+        if !is_syn || tcp_socket_count >= tcp_options.max_sockets {
             return;
         }
         let next = match tcp_next.upgrade() {
             Some(n) => n,
             None => return,
         };
-        let mut socket = TcpSocket::new(
-            // Note: The buffer sizes effectively affect overall throughput.
-            RingBuffer::new(vec![0; 1024 * 14]),
-            RingBuffer::new(vec![0; 10240]),
+        // When evaluating multiple congestion controllers, connections are
+        // spread evenly so that their stats can be compared.
+        let tcp_congestion = *tcp_congestion_next;
+        *tcp_congestion_next = (tcp_congestion + 1) % tcp_congestions.len();
+        let buffer_size = buffer_tuner.acquire();
+        let socket = tcp_stack::listen(
+            tcp_congestions[tcp_congestion].0,
+            IpEndpoint::new(dst_addr, dst_port),
+            buffer_size,
+            tcp_options,
         );
-        socket
-            .listen(IpEndpoint::new(dst_addr, dst_port))
-            // This unwrap cannot panic for a valid TCP packet because:
-            // 1) The socket is just created
-            // 2) dst_port != 0
-            .unwrap();
-        socket.set_nagle_enabled(false);
-        // The default ACK delay (10ms) significantly reduces uplink throughput.
-        // Maybe due to the delay when sending ACK packets?
-        socket.set_ack_delay(None);
         let socket_handle = socket_set.add(socket);
         vac.insert(socket_handle);
-        let ctx = FlowContext::new(
+        let mut ctx = FlowContext::new(
             src_addr,
             DestinationAddr {
                 host: HostName::Ip(smoltcp_addr_to_std(dst_addr)),
                 port: dst_port,
             },
         );
+        ctx.traffic_class = Some(traffic_class);
+        // The tuner learns from how connections fill their send buffers.
+        let mut stats = tcp_tuning::TcpConnStats::new(tcp_options.tx_buffer.unwrap_or(buffer_size));
+        stats.reserved = buffer_size;
         tokio::spawn({
             let stack = stack.clone();
+            let plugin_name = plugin_name.clone();
             async move {
                 let mut stream = stream::IpStackStream {
                     socket_entry: tcp_socket_entry::TcpSocketEntry {
@@ -328,15 +412,21 @@ fn process_tcp(
                     },
                     rx_buf: None,
                     tx_buf: Some((Vec::with_capacity(4 * 1024), 0)),
+                    stats,
+                    tcp_congestion,
+                    tx_closed: false,
                 };
                 if stream.handshake().await.is_ok() {
-                    next.on_stream(Box::new(stream) as _, Buffer::new(), Box::new(ctx));
+                    let session_id = crate::log::next_session_id();
+                    crate::log::isolate_sync(&plugin_name, session_id, || {
+                        next.on_stream(Box::new(stream) as _, Buffer::new(), Box::new(ctx))
+                    });
                 }
             }
         });
     };
     let now = Instant::now();
-    let _ = netif.poll(now.into(), dev, socket_set);
+    let _ = crate::log::probe::ip_stack_poll(|| netif.poll(now.into(), dev, socket_set));
     // Polling the socket may wake a read/write waker. When a task polls the tx/rx
     // buffer from the corresponding stream, a delayed poll will be rescheduled.
     // Therefore, we don't have to poll the socket here.
@@ -347,12 +437,15 @@ fn process_udp(
     src_addr: SocketAddr,
     dst_addr: smoltcp::wire::IpAddress,
     dst_port: u16,
+    traffic_class: u8,
     payload: &mut [u8],
 ) {
     let mut guard = stack.lock().unwrap();
     let IpStackInner {
+        plugin_name,
         udp_sockets,
         udp_next,
+        udp_session_timeout,
         ..
     } = &mut *guard;
     let tx = match udp_sockets.entry(src_addr) {
@@ -364,24 +457,31 @@ fn process_udp(
             };
             let (tx, rx) = bounded(48);
             let stack_inner = stack.clone();
+            let plugin_name = plugin_name.clone();
+            let udp_session_timeout = *udp_session_timeout;
+            let mut ctx = FlowContext::new_af_sensitive(
+                src_addr,
+                DestinationAddr {
+                    host: HostName::Ip(smoltcp_addr_to_std(dst_addr)),
+                    port: dst_port,
+                },
+            );
+            ctx.traffic_class = Some(traffic_class);
             tokio::spawn(async move {
-                next.on_session(
-                    Box::new(MultiplexedDatagramSessionAdapter::new(
-                        datagram::IpStackDatagramSession {
-                            stack: stack_inner,
-                            local_endpoint: src_addr,
-                        },
-                        rx.into_stream(),
-                        120,
-                    )),
-                    Box::new(FlowContext::new_af_sensitive(
-                        src_addr,
-                        DestinationAddr {
-                            host: HostName::Ip(smoltcp_addr_to_std(dst_addr)),
-                            port: dst_port,
-                        },
-                    )),
-                );
+                let session_id = crate::log::next_session_id();
+                crate::log::isolate_sync(&plugin_name, session_id, || {
+                    next.on_session(
+                        Box::new(MultiplexedDatagramSessionAdapter::new(
+                            datagram::IpStackDatagramSession {
+                                stack: stack_inner,
+                                local_endpoint: src_addr,
+                            },
+                            rx.into_stream(),
+                            udp_session_timeout,
+                        )),
+                        Box::new(ctx),
+                    );
+                });
             });
             vac.insert(tx)
         }
@@ -419,7 +519,7 @@ fn schedule_repoll(
             dev,
             ..
         } = &mut *stack_guard;
-        let _ = netif.poll(poll_at.into(), dev, socket_set);
+        let _ = crate::log::probe::ip_stack_poll(|| netif.poll(poll_at.into(), dev, socket_set));
         if let Some(delay) = netif.poll_delay(poll_at.into(), socket_set) {
             let scheduled_poll_milli =
                 (smoltcp::time::Instant::from(Instant::now()) + delay).total_millis();
@@ -0,0 +1,75 @@
+use std::sync::{Arc, Mutex};
+
+use cbor4ii::serde::to_vec;
+use serde::Serialize;
+
+use super::tcp_stack::{TcpCongestion, TcpCongestionReport, TcpCongestionStats};
+use crate::control::{
+    KillSwitch, KillSwitchStatus, PluginRequestError, PluginRequestResult, PluginResponder,
+};
+
+#[derive(Clone, PartialEq, Serialize)]
+struct Info {
+    kill_switch: Option<KillSwitchStatus>,
+    tcp_congestions: Vec<TcpCongestionReport>,
+}
+
+pub struct Responder {
+    kill_switch: Option<KillSwitch>,
+    tcp_congestions: Vec<(TcpCongestion, Arc<TcpCongestionStats>)>,
+    last_info: Mutex<(Option<Info>, u32)>,
+}
+
+impl Responder {
+    pub fn new(
+        kill_switch: Option<KillSwitch>,
+        tcp_congestions: Vec<(TcpCongestion, Arc<TcpCongestionStats>)>,
+    ) -> Self {
+        Self {
+            kill_switch,
+            tcp_congestions,
+            last_info: Mutex::new((None, 1)),
+        }
+    }
+}
+
+impl PluginResponder for Responder {
+    fn collect_info(&self, hashcode: &mut u32) -> Option<Vec<u8>> {
+        let info = {
+            let mut last_info_guard = self.last_info.lock().unwrap();
+            let (last_info, last_hashcode) = &mut *last_info_guard;
+            let new_info = Info {
+                kill_switch: self.kill_switch.as_ref().map(|k| k.status()),
+                tcp_congestions: self
+                    .tcp_congestions
+                    .iter()
+                    .map(|(kind, stats)| stats.report(*kind))
+                    .collect(),
+            };
+            if last_info.as_ref() == Some(&new_info) {
+                if *last_hashcode == *hashcode {
+                    return None;
+                }
+            } else {
+                *last_info = Some(new_info.clone());
+                *last_hashcode = (*last_hashcode).wrapping_add(1);
+            }
+            *hashcode = *last_hashcode;
+            new_info
+        };
+        Some(to_vec(vec![], &info).unwrap())
+    }
+
+    fn on_request(&self, func: &str, _params: &[u8]) -> PluginRequestResult<Vec<u8>> {
+        let kill_switch = self
+            .kill_switch
+            .as_ref()
+            .ok_or(PluginRequestError::NoSuchFunc)?;
+        match func {
+            "engage_kill_switch" => kill_switch.set_manual(true),
+            "release_kill_switch" => kill_switch.set_manual(false),
+            _ => return Err(PluginRequestError::NoSuchFunc),
+        }
+        Ok(to_vec(vec![], &()).unwrap())
+    }
+}
@@ -4,20 +4,36 @@ use std::time::Duration;
 
 use futures::future::poll_fn;
 use futures::ready;
-use tokio::time::timeout;
+use tokio::time::{sleep, timeout, Instant};
 
 use super::tcp_socket_entry::*;
+use super::tcp_tuning::TcpConnStats;
+use super::IpStackInner;
 use crate::flow::*;
 
 pub(super) struct IpStackStream {
     pub(super) socket_entry: TcpSocketEntry,
     pub(super) rx_buf: Option<Buffer>,
     pub(super) tx_buf: Option<(Buffer, usize)>,
+    pub(super) stats: TcpConnStats,
+    /// Index of the congestion controller in
+    /// [`super::IpStackInner::tcp_congestions`] of this connection.
+    pub(super) tcp_congestion: usize,
+    /// Whether a FIN has been queued, after which the socket is kept around
+    /// on drop to deliver the remaining data instead of being reset.
+    pub(super) tx_closed: bool,
 }
 
+/// How long a socket closed by us may take to deliver its remaining data and
+/// complete the FIN exchange before it is reset, as `tcp_fin_timeout` on Linux.
+const LINGER_TIMEOUT: Duration = Duration::from_secs(60);
+const LINGER_CHECK_INTERVAL: Duration = Duration::from_millis(200);
+
 impl IpStackStream {
     pub(super) async fn handshake(&mut self) -> Result<(), ()> {
-        timeout(
+        // SYN-ACK is sent right after the socket is created. The handshake
+        // completes one round trip later.
+        let res = timeout(
             Duration::from_millis(1000 * 60),
             poll_fn(|cx| {
                 self.socket_entry.lock().with_socket(|s| {
@@ -30,7 +46,12 @@ impl IpStackStream {
             }),
         )
         .await
-        .map_err(|_| ())
+        .map_err(|_| ());
+        // A handshake that timed out says nothing about the path.
+        if res.is_ok() {
+            self.stats.rtt = Some(self.stats.syn_at.elapsed());
+        }
+        res
     }
 }
 
@@ -71,7 +92,7 @@ impl Stream for IpStackStream {
                     buffer.truncate(offset + s);
                     Poll::Ready(Ok(()))
                 }
-                Err(_) => Poll::Ready(Err((rx_buf.take().unwrap(), FlowError::Eof))),
+                Err(e) => Poll::Ready(Err((rx_buf.take().unwrap(), e))),
             }
         }))?;
         socket_guard.poll();
@@ -88,6 +109,7 @@ impl Stream for IpStackStream {
         let Self {
             tx_buf,
             socket_entry,
+            stats,
             ..
         } = &mut *self;
         let (buffer, read_at) = tx_buf
@@ -99,15 +121,17 @@ impl Stream for IpStackStream {
             let mut socket_guard = socket_entry.lock();
             match socket_guard.with_socket(|s| s.send_slice(&buffer[*read_at..])) {
                 Ok(0) => {
+                    stats.send_blocked = true;
                     socket_guard.with_socket(|s| s.register_send_waker(cx.waker()));
                     return Poll::Pending;
                 }
                 Ok(len) => {
                     *read_at += len;
+                    stats.bytes_sent += len as u64;
                     socket_guard.poll();
                     continue;
                 }
-                Err(_) => return Poll::Ready(Err(FlowError::Eof)),
+                Err(e) => return Poll::Ready(Err(e)),
             }
         }
         let (mut buf, read_at) = tx_buf.take().unwrap();
@@ -125,6 +149,7 @@ impl Stream for IpStackStream {
         let Self {
             tx_buf,
             socket_entry,
+            stats,
             ..
         } = &mut *self;
         let (tx_buf, read_at) = tx_buf
@@ -134,47 +159,91 @@ impl Stream for IpStackStream {
         while tx_buf.len() > *read_at {
             match socket_guard.with_socket(|s| s.send_slice(&tx_buf[*read_at..])) {
                 Ok(0) => {
+                    stats.send_blocked = true;
                     socket_guard.with_socket(|s| s.register_send_waker(cx.waker()));
                     return Poll::Pending;
                 }
                 Ok(s) => {
                     *read_at += s;
+                    stats.bytes_sent += s as u64;
                     socket_guard.poll();
                 }
-                Err(_) => return Poll::Ready(Err(FlowError::Eof)),
+                Err(e) => return Poll::Ready(Err(e)),
             }
         }
         Poll::Ready(Ok(()))
     }
 
     fn poll_close_tx(&mut self, cx: &mut Context<'_>) -> Poll<FlowResult<()>> {
-        // Send remaining data in tx buf
+        if self.tx_closed {
+            return Poll::Ready(Ok(()));
+        }
+        // Move remaining data in tx buf into the socket buffer
         if self.tx_buf.is_some() {
             ready!(self.poll_flush_tx(cx))?;
         }
 
-        // Send remaining data in socket buffer and FIN
+        // smoltcp sends FIN after the data in the socket buffer. The receiving
+        // half stays open until the peer closes too.
         let mut socket_guard = self.socket_entry.lock();
-        let res = socket_guard.with_socket(|s| {
-            if s.send_queue() > 0 {
-                s.register_send_waker(cx.waker());
-                Poll::Pending
-            } else {
-                s.close();
-                Poll::Ready(Ok(()))
-            }
-        });
+        socket_guard.with_socket(|s| s.close());
         socket_guard.poll();
-        res
+        self.tx_closed = true;
+        Poll::Ready(Ok(()))
     }
 }
 
 impl Drop for IpStackStream {
     fn drop(&mut self) {
-        let local_endpoint = self.socket_entry.local_endpoint;
         let mut socket_guard = self.socket_entry.lock();
-        socket_guard.with_socket(|s| s.abort());
-        socket_guard.poll();
-        socket_guard.guard.tcp_sockets.remove(&local_endpoint);
+        let linger = self.tx_closed
+            && !socket_guard.with_socket(|s| s.is_closed())
+            && tokio::runtime::Handle::try_current().is_ok();
+        if linger {
+            let TcpSocketEntry {
+                socket_handle,
+                local_endpoint,
+                stack,
+                most_recent_scheduled_poll,
+            } = &self.socket_entry;
+            tokio::spawn(linger_closed(TcpSocketEntry {
+                socket_handle: *socket_handle,
+                local_endpoint: *local_endpoint,
+                stack: stack.clone(),
+                most_recent_scheduled_poll: most_recent_scheduled_poll.clone(),
+            }));
+        } else {
+            socket_guard.with_socket(|s| s.abort());
+            socket_guard.poll();
+            socket_guard.release();
+        }
+        let IpStackInner {
+            buffer_tuner,
+            tcp_congestions,
+            ..
+        } = &mut *socket_guard.guard;
+        buffer_tuner.record(&self.stats);
+        tcp_congestions[self.tcp_congestion].1.record(&self.stats);
+    }
+}
+
+/// Keep driving a socket closed by us until the FIN exchange completes, so
+/// that the peer receives all data and a FIN rather than a RST. Incoming
+/// segments are still routed to the socket meanwhile.
+async fn linger_closed(socket_entry: TcpSocketEntry) {
+    let deadline = Instant::now() + LINGER_TIMEOUT;
+    loop {
+        sleep(LINGER_CHECK_INTERVAL).await;
+        let mut socket_guard = socket_entry.lock();
+        if !socket_guard.with_socket(|s| s.is_closed()) {
+            if Instant::now() < deadline {
+                socket_guard.poll();
+                continue;
+            }
+            socket_guard.with_socket(|s| s.abort());
+            socket_guard.poll();
+        }
+        socket_guard.release();
+        return;
     }
 }
@@ -1,11 +1,13 @@
-use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::atomic::Ordering;
 use std::sync::{Arc, Mutex, MutexGuard};
 use std::time::Instant;
 
 use smoltcp::iface::SocketHandle;
 use smoltcp::socket::tcp::Socket as TcpSocket;
 
+use super::tcp_stack::TcpConnection;
 use super::*;
+use crate::atomic::AtomicI64;
 
 pub(super) struct TcpSocketEntry {
     pub(super) socket_handle: SocketHandle,
@@ -29,12 +31,31 @@ pub(super) struct SocketEntryGuard<'s> {
 }
 
 impl<'s> SocketEntryGuard<'s> {
-    pub fn with_socket<R>(&mut self, f: impl FnOnce(&mut TcpSocket) -> R) -> R {
+    pub fn with_socket<R>(&mut self, f: impl FnOnce(&mut dyn TcpConnection) -> R) -> R {
         let handle = self.entry.socket_handle;
         let socket = self.guard.socket_set.get_mut::<TcpSocket<'static>>(handle);
         f(socket)
     }
 
+    /// Remove the socket from the stack. Segments arriving afterwards are
+    /// answered with RST by smoltcp.
+    pub fn release(&mut self) {
+        let TcpSocketEntry {
+            socket_handle,
+            local_endpoint,
+            ..
+        } = self.entry;
+        let IpStackInner {
+            socket_set,
+            tcp_sockets,
+            ..
+        } = &mut *self.guard;
+        socket_set.remove(*socket_handle);
+        if tcp_sockets.get(local_endpoint) == Some(socket_handle) {
+            tcp_sockets.remove(local_endpoint);
+        }
+    }
+
     pub fn poll(&mut self) {
         let now = Instant::now();
         let Self { entry, guard } = self;
@@ -49,7 +70,7 @@ impl<'s> SocketEntryGuard<'s> {
             socket_set,
             ..
         } = &mut **guard;
-        let _ = netif.poll(now.into(), dev, socket_set);
+        let _ = crate::log::probe::ip_stack_poll(|| netif.poll(now.into(), dev, socket_set));
         if let Some(delay) = netif.poll_delay(now.into(), socket_set) {
             let scheduled_poll_milli = (smoltcp::time::Instant::from(now) + delay).total_millis();
             if scheduled_poll_milli >= most_recent_scheduled_poll.load(Ordering::Relaxed) {
@@ -0,0 +1,249 @@
+use std::io;
+use std::sync::atomic::Ordering;
+use std::task::Waker;
+
+use serde::Serialize;
+use smoltcp::socket::tcp::{CongestionControl, RecvError, Socket as TcpSocket, State};
+use smoltcp::storage::RingBuffer;
+use smoltcp::wire::IpEndpoint;
+
+use super::tcp_tuning::{self, TcpConnStats, TcpOptions};
+use crate::atomic::AtomicU64;
+use crate::flow::{FlowError, FlowResult};
+
+/// Congestion controllers the ip-stack can run its smoltcp TCP sockets with.
+/// Everything else about the sockets is the same.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcpCongestion {
+    /// No congestion control, sending as much as the peer's window allows.
+    None,
+    #[cfg(feature = "tcp-congestion-eval")]
+    Reno,
+    #[cfg(feature = "tcp-congestion-eval")]
+    Cubic,
+}
+
+impl TcpCongestion {
+    pub fn name(self) -> &'static str {
+        match self {
+            TcpCongestion::None => "none",
+            #[cfg(feature = "tcp-congestion-eval")]
+            TcpCongestion::Reno => "reno",
+            #[cfg(feature = "tcp-congestion-eval")]
+            TcpCongestion::Cubic => "cubic",
+        }
+    }
+}
+
+/// Operations performed on an accepted TCP connection. Streams only go
+/// through this trait, so that they do not depend on a specific TCP
+/// implementation.
+pub(super) trait TcpConnection {
+    fn may_send(&self) -> bool;
+    fn recv_queue(&self) -> usize;
+    /// Whether the connection is gone, or only waits for stray segments
+    /// after both sides have closed.
+    fn is_closed(&self) -> bool;
+    /// Fails with [`FlowError::Eof`] once the peer's FIN is consumed, or an
+    /// I/O error if the connection is reset.
+    fn recv_slice(&mut self, data: &mut [u8]) -> FlowResult<usize>;
+    fn send_slice(&mut self, data: &[u8]) -> FlowResult<usize>;
+    fn register_recv_waker(&mut self, waker: &Waker);
+    fn register_send_waker(&mut self, waker: &Waker);
+    fn close(&mut self);
+    fn abort(&mut self);
+}
+
+/// Create a socket listening on `local` with `congestion`, so that the SYN
+/// being processed will be accepted. `buffer_size` applies to both directions
+/// unless `options` fixes their sizes.
+pub(super) fn listen(
+    congestion: TcpCongestion,
+    local: IpEndpoint,
+    buffer_size: usize,
+    options: &TcpOptions,
+) -> TcpSocket<'static> {
+    // Note: The buffer sizes effectively affect overall throughput.
+    let mut socket = TcpSocket::new(
+        RingBuffer::new(vec![0; options.rx_buffer.unwrap_or(buffer_size)]),
+        RingBuffer::new(vec![0; options.tx_buffer.unwrap_or(buffer_size)]),
+    );
+    socket
+        .listen(local)
+        // This unwrap cannot panic for a valid TCP packet because:
+        // 1) The socket is just created
+        // 2) dst_port != 0
+        .unwrap();
+    socket.set_nagle_enabled(false);
+    // The default ACK delay (10ms) significantly reduces uplink throughput.
+    // Maybe due to the delay when sending ACK packets?
+    socket.set_ack_delay(None);
+    // SACK is negotiated by smoltcp whenever the peer offers it.
+    if options.timestamps {
+        socket.set_tsval_generator(Some(tcp_tuning::tcp_timestamp));
+    }
+    // smoltcp picks a congestion controller by default once one is
+    // compiled in. Always set it to keep the default unchanged.
+    socket.set_congestion_control(match congestion {
+        TcpCongestion::None => CongestionControl::None,
+        #[cfg(feature = "tcp-congestion-eval")]
+        TcpCongestion::Reno => CongestionControl::Reno,
+        #[cfg(feature = "tcp-congestion-eval")]
+        TcpCongestion::Cubic => CongestionControl::Cubic,
+    });
+    socket
+}
+
+impl TcpConnection for TcpSocket<'static> {
+    fn may_send(&self) -> bool {
+        TcpSocket::may_send(self)
+    }
+    fn recv_queue(&self) -> usize {
+        TcpSocket::recv_queue(self)
+    }
+    fn is_closed(&self) -> bool {
+        matches!(self.state(), State::Closed | State::TimeWait)
+    }
+    fn recv_slice(&mut self, data: &mut [u8]) -> FlowResult<usize> {
+        TcpSocket::recv_slice(self, data).map_err(|e| match e {
+            RecvError::Finished => FlowError::Eof,
+            RecvError::InvalidState => io::Error::from(io::ErrorKind::ConnectionReset).into(),
+        })
+    }
+    fn send_slice(&mut self, data: &[u8]) -> FlowResult<usize> {
+        TcpSocket::send_slice(self, data)
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe).into())
+    }
+    fn register_recv_waker(&mut self, waker: &Waker) {
+        TcpSocket::register_recv_waker(self, waker)
+    }
+    fn register_send_waker(&mut self, waker: &Waker) {
+        TcpSocket::register_send_waker(self, waker)
+    }
+    fn close(&mut self) {
+        TcpSocket::close(self)
+    }
+    fn abort(&mut self) {
+        TcpSocket::abort(self)
+    }
+}
+
+/// Accumulated measurements of connections accepted with one congestion
+/// controller, to compare controllers serving the same TUN.
+#[derive(Default)]
+pub struct TcpCongestionStats {
+    connections: AtomicU64,
+    bytes_sent: AtomicU64,
+    bulk_bytes: AtomicU64,
+    bulk_micros: AtomicU64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TcpCongestionReport {
+    pub congestion_control: &'static str,
+    pub connections: u64,
+    pub bytes_sent: u64,
+    /// Average throughput in bytes per second of connections that filled
+    /// their send buffer at least once.
+    pub bulk_throughput: u64,
+}
+
+impl TcpCongestionStats {
+    pub(super) fn record(&self, stats: &TcpConnStats) {
+        self.connections.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent
+            .fetch_add(stats.bytes_sent, Ordering::Relaxed);
+        let Some(rtt) = stats.rtt else {
+            return;
+        };
+        let elapsed = stats.syn_at.elapsed().saturating_sub(rtt);
+        // Same criteria as the buffer tuner: short connections are bound by
+        // latency rather than congestion control.
+        if elapsed.is_zero() || stats.bytes_sent < stats.buffer_size as u64 {
+            return;
+        }
+        self.bulk_bytes
+            .fetch_add(stats.bytes_sent, Ordering::Relaxed);
+        self.bulk_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn report(&self, congestion: TcpCongestion) -> TcpCongestionReport {
+        let bulk_bytes = self.bulk_bytes.load(Ordering::Relaxed);
+        let bulk_micros = self.bulk_micros.load(Ordering::Relaxed);
+        TcpCongestionReport {
+            congestion_control: congestion.name(),
+            connections: self.connections.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bulk_throughput: (bulk_bytes as u128 * 1_000_000)
+                .checked_div(bulk_micros as u128)
+                .unwrap_or_default() as u64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    #[test]
+    fn test_stats_report_bulk_throughput() {
+        let stats = TcpCongestionStats::default();
+        let mut conn = TcpConnStats::new(16 * 1024);
+        conn.syn_at = Instant::now() - Duration::from_millis(2050);
+        conn.rtt = Some(Duration::from_millis(50));
+        conn.bytes_sent = 2 * 1024 * 1024;
+        stats.record(&conn);
+        // Too short to count as bulk
+        let mut short = TcpConnStats::new(16 * 1024);
+        short.rtt = Some(Duration::from_millis(50));
+        short.bytes_sent = 1024;
+        stats.record(&short);
+
+        let report = stats.report(TcpCongestion::None);
+        assert_eq!(report.congestion_control, "none");
+        assert_eq!(report.connections, 2);
+        assert_eq!(report.bytes_sent, 2 * 1024 * 1024 + 1024);
+        // Roughly 1 MiB/s, minus the time spent running the test
+        assert!((1000 * 1024..=1024 * 1024).contains(&report.bulk_throughput));
+    }
+
+    #[test]
+    fn test_connection_errors() {
+        let local = IpEndpoint::new(smoltcp::wire::Ipv4Address::new(10, 0, 0, 1).into(), 80);
+        let mut socket = listen(TcpCongestion::None, local, 1024, &TcpOptions::default());
+        assert!(!TcpConnection::is_closed(&socket));
+        // Not connected, as if reset by the peer
+        let mut buf = [0; 16];
+        assert!(matches!(
+            TcpConnection::recv_slice(&mut socket, &mut buf),
+            Err(FlowError::Io(e)) if e.kind() == io::ErrorKind::ConnectionReset
+        ));
+        assert!(matches!(
+            TcpConnection::send_slice(&mut socket, b"x"),
+            Err(FlowError::Io(e)) if e.kind() == io::ErrorKind::BrokenPipe
+        ));
+        TcpConnection::abort(&mut socket);
+        assert!(TcpConnection::is_closed(&socket));
+    }
+
+    #[test]
+    fn test_listen_fixed_buffer_sizes() {
+        let local = IpEndpoint::new(smoltcp::wire::Ipv4Address::new(10, 0, 0, 1).into(), 80);
+        let options = TcpOptions {
+            tx_buffer: Some(4096),
+            ..TcpOptions::default()
+        };
+        let socket = listen(TcpCongestion::None, local, 16 * 1024, &options);
+        assert_eq!(socket.recv_capacity(), 16 * 1024);
+        assert_eq!(socket.send_capacity(), 4096);
+    }
+
+    #[test]
+    fn test_stats_report_empty() {
+        let report = TcpCongestionStats::default().report(TcpCongestion::None);
+        assert_eq!(report.bulk_throughput, 0);
+    }
+}
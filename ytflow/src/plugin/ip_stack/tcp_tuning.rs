@@ -0,0 +1,188 @@
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy)]
+pub struct TcpOptions {
+    /// Whether to send TCP timestamps (RFC 7323) when the peer offers them.
+    pub timestamps: bool,
+    pub min_buffer: usize,
+    pub max_buffer: usize,
+    /// Fixed size of the receive buffer of each socket, in place of the
+    /// tuned size.
+    pub rx_buffer: Option<usize>,
+    /// Fixed size of the send buffer of each socket, in place of the tuned
+    /// size.
+    pub tx_buffer: Option<usize>,
+    /// SYNs beyond this number of open sockets are dropped.
+    pub max_sockets: usize,
+}
+
+impl Default for TcpOptions {
+    fn default() -> Self {
+        Self {
+            timestamps: true,
+            min_buffer: 16 * 1024,
+            max_buffer: 1024 * 1024,
+            rx_buffer: None,
+            tx_buffer: None,
+            max_sockets: 1024,
+        }
+    }
+}
+
+/// Upper bound of tuned buffers held by all live sockets of a stack. Once
+/// reached, new sockets get the minimum size until others are closed.
+const TUNED_BUFFER_BUDGET: usize = 32 * 1024 * 1024;
+
+pub(super) fn tcp_timestamp() -> u32 {
+    static EPOCH: LazyLock<Instant> = LazyLock::new(Instant::now);
+    EPOCH.elapsed().as_millis() as u32
+}
+
+/// Measurements of a finished TCP connection.
+pub(super) struct TcpConnStats {
+    pub(super) buffer_size: usize,
+    pub(super) syn_at: Instant,
+    pub(super) rtt: Option<Duration>,
+    pub(super) bytes_sent: u64,
+    pub(super) send_blocked: bool,
+    /// Tuned buffer size taken from the budget by [`BufferTuner::acquire`].
+    pub(super) reserved: usize,
+}
+
+impl TcpConnStats {
+    pub(super) fn new(buffer_size: usize) -> Self {
+        Self {
+            buffer_size,
+            syn_at: Instant::now(),
+            rtt: None,
+            bytes_sent: 0,
+            send_blocked: false,
+            reserved: 0,
+        }
+    }
+}
+
+/// Picks socket buffer sizes from the bandwidth-delay product measured on
+/// recent connections.
+///
+/// smoltcp cannot resize the buffers of a live socket, so the estimate only
+/// applies to connections accepted afterwards.
+pub(super) struct BufferTuner {
+    min: usize,
+    max: usize,
+    current: usize,
+    /// Bytes of tuned buffers held by live sockets, counting both directions.
+    reserved: usize,
+}
+
+impl BufferTuner {
+    pub(super) fn new(options: &TcpOptions) -> Self {
+        Self {
+            min: options.min_buffer,
+            max: options.max_buffer,
+            current: options.min_buffer,
+            reserved: 0,
+        }
+    }
+
+    pub(super) fn buffer_size(&self) -> usize {
+        self.current
+    }
+
+    /// Take the buffer size for a new socket out of the budget. It is given
+    /// back by [`BufferTuner::record`] with [`TcpConnStats::reserved`].
+    pub(super) fn acquire(&mut self) -> usize {
+        let available = TUNED_BUFFER_BUDGET.saturating_sub(self.reserved) / 2;
+        let size = self.current.min(available).max(self.min);
+        self.reserved += size * 2;
+        size
+    }
+
+    pub(super) fn record(&mut self, stats: &TcpConnStats) {
+        self.reserved = self.reserved.saturating_sub(stats.reserved * 2);
+        let Some(rtt) = stats.rtt else {
+            return;
+        };
+        let elapsed = stats.syn_at.elapsed().saturating_sub(rtt);
+        // Connections that never filled their buffer tell nothing about the
+        // bandwidth available.
+        if elapsed.is_zero() || stats.bytes_sent < stats.buffer_size as u64 {
+            return;
+        }
+        let bandwidth = stats.bytes_sent as f64 / elapsed.as_secs_f64();
+        let bdp = (bandwidth * rtt.as_secs_f64()) as usize;
+        let mut target = bdp.saturating_mul(2);
+        if stats.send_blocked {
+            target = target.max(stats.buffer_size.saturating_mul(2));
+        }
+        let target = target.clamp(self.min, self.max);
+        self.current = if target >= self.current {
+            target
+        } else {
+            // Shrink slowly so that a single idle connection does not undo
+            // what bulk transfers have learned.
+            (self.current * 3 + target) / 4
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(buffer_size: usize, bytes_sent: u64, send_blocked: bool) -> TcpConnStats {
+        TcpConnStats {
+            buffer_size,
+            syn_at: Instant::now() - Duration::from_secs(2),
+            rtt: Some(Duration::from_millis(50)),
+            bytes_sent,
+            send_blocked,
+            reserved: 0,
+        }
+    }
+
+    #[test]
+    fn test_tuner_grows_when_blocked() {
+        let mut tuner = BufferTuner::new(&TcpOptions::default());
+        tuner.record(&stats(16 * 1024, 100 * 1024, true));
+        assert_eq!(tuner.buffer_size(), 32 * 1024);
+    }
+
+    #[test]
+    fn test_tuner_ignores_short_connections() {
+        let mut tuner = BufferTuner::new(&TcpOptions::default());
+        tuner.record(&stats(16 * 1024, 1024, true));
+        assert_eq!(tuner.buffer_size(), 16 * 1024);
+    }
+
+    #[test]
+    fn test_tuner_clamps_to_max() {
+        let options = TcpOptions::default();
+        let mut tuner = BufferTuner::new(&options);
+        tuner.record(&stats(16 * 1024, 1 << 30, true));
+        assert_eq!(tuner.buffer_size(), options.max_buffer);
+    }
+
+    #[test]
+    fn test_tuner_budget() {
+        let options = TcpOptions::default();
+        let mut tuner = BufferTuner::new(&options);
+        tuner.record(&stats(16 * 1024, 1 << 30, true));
+        let count = TUNED_BUFFER_BUDGET / options.max_buffer / 2;
+        let mut conns: Vec<_> = (0..count)
+            .map(|_| {
+                let mut conn = stats(0, 0, false);
+                conn.reserved = tuner.acquire();
+                assert_eq!(conn.reserved, options.max_buffer);
+                conn
+            })
+            .collect();
+        // Budget exhausted
+        assert_eq!(tuner.acquire(), options.min_buffer);
+        let mut conn = conns.pop().unwrap();
+        conn.rtt = None;
+        tuner.record(&conn);
+        assert_eq!(tuner.acquire(), options.max_buffer - options.min_buffer);
+    }
+}
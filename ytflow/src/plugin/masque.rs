@@ -0,0 +1,312 @@
+mod capsule;
+mod datagram;
+mod h3;
+
+use std::io;
+use std::net::IpAddr;
+use std::sync::Weak;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use base64::prelude::*;
+use http::header::{CONNECTION, HOST, PROXY_AUTHORIZATION, UPGRADE};
+use http::uri::Authority;
+use http::{HeaderValue, Method, Request, StatusCode, Uri};
+use hyper::client::conn::Builder as ConnBuilder;
+use hyper::client::connect::Connection;
+use hyper::service::Service;
+use hyper::Body;
+
+use super::h2::{FlowAdapterConnector, TokioHyperExecutor};
+use super::http_proxy::HttpProxyOutboundFactory;
+use super::quic;
+use crate::flow::*;
+use datagram::MasqueDatagramSession;
+
+/// A MASQUE client, which proxies UDP using CONNECT-UDP (RFC 9298) and TCP
+/// using plain HTTP CONNECT.
+///
+/// With [`MasqueH3`] settings, UDP requests are sent over HTTP/3 (RFC 9298
+/// Section 3.4), and datagrams are carried in QUIC DATAGRAM frames. Each UDP
+/// session uses a QUIC connection of its own. Otherwise, UDP requests use
+/// extended CONNECT (Section 3.3) when HTTP/2 is negotiated with the proxy,
+/// or the HTTP/1.1 Upgrade mapping (Section 3.2), and datagrams are carried
+/// in DATAGRAM capsules over the request stream. TCP always goes through
+/// `next`.
+pub struct MasqueClient {
+    host: String,
+    udp_path_template: String,
+    basic_auth: Option<HeaderValue>,
+    tcp: HttpProxyOutboundFactory,
+    next: Weak<dyn StreamOutboundFactory>,
+    h3: Option<MasqueH3>,
+}
+
+/// How to reach the proxy over QUIC for HTTP/3.
+pub struct MasqueH3 {
+    pub client_config: quinn::ClientConfig,
+    pub sni: Option<String>,
+    pub handshake_timeout: Duration,
+    pub next: Weak<dyn DatagramSessionFactory>,
+}
+
+/// The proxy server of `host`, which is an authority with an optional port.
+fn proxy_addr(host: &str) -> Option<DestinationAddr> {
+    let authority = Authority::try_from(host).ok()?;
+    let name = authority
+        .host()
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    let host = match name.parse::<IpAddr>() {
+        Ok(ip) => HostName::Ip(ip),
+        Err(_) => HostName::from_domain_name(format!("{}.", name.trim_end_matches('.'))).ok()?,
+    };
+    Some(DestinationAddr {
+        host,
+        port: authority.port_u16().unwrap_or(443),
+    })
+}
+
+fn hyper_err(e: hyper::Error) -> FlowError {
+    FlowError::Io(io::Error::new(io::ErrorKind::Other, e))
+}
+
+impl MasqueClient {
+    pub fn new(
+        host: String,
+        udp_path_template: String,
+        cred: Option<(&'_ [u8], &'_ [u8])>,
+        next: Weak<dyn StreamOutboundFactory>,
+        h3: Option<MasqueH3>,
+    ) -> Self {
+        let basic_auth = cred.map(|(user, pass)| {
+            let mut cred_plain = Vec::with_capacity(user.len() + pass.len() + 1);
+            cred_plain.extend_from_slice(user);
+            cred_plain.push(b':');
+            cred_plain.extend_from_slice(pass);
+            HeaderValue::from_str(&format!("Basic {}", BASE64_STANDARD.encode(cred_plain)))
+                .expect("base64 is a valid header value")
+        });
+        Self {
+            host,
+            udp_path_template,
+            basic_auth,
+            tcp: HttpProxyOutboundFactory::new(cred, next.clone(), DEFAULT_HANDSHAKE_TIMEOUT),
+            next,
+            h3,
+        }
+    }
+
+    fn udp_path(&self, peer: &DestinationAddr) -> String {
+        let host = match &peer.host {
+            HostName::DomainName(domain) => domain.trim_end_matches('.').to_string(),
+            // Colons are not allowed in a path segment, see RFC 9298 Section 2.
+            HostName::Ip(IpAddr::V6(ip)) => ip.to_string().replace(':', "%3A"),
+            HostName::Ip(ip) => ip.to_string(),
+        };
+        self.udp_path_template
+            .replace("{target_host}", &host)
+            .replace("{target_port}", &peer.port.to_string())
+    }
+
+    fn create_udp_req(&self, uri: Uri, is_h2: bool) -> Request<Body> {
+        let mut req = Request::new(Body::empty());
+        if is_h2 {
+            *req.method_mut() = Method::CONNECT;
+            req.extensions_mut()
+                .insert(hyper::ext::Protocol::from_static("connect-udp"));
+            *req.uri_mut() = uri;
+        } else {
+            *req.uri_mut() = uri.path_and_query().unwrap().as_str().parse().unwrap();
+            let headers = req.headers_mut();
+            headers.insert(HOST, HeaderValue::from_str(&self.host).unwrap());
+            headers.insert(CONNECTION, HeaderValue::from_static("Upgrade"));
+            headers.insert(UPGRADE, HeaderValue::from_static("connect-udp"));
+        }
+        let headers = req.headers_mut();
+        headers.insert("capsule-protocol", HeaderValue::from_static("?1"));
+        if let Some(auth) = &self.basic_auth {
+            headers.insert(PROXY_AUTHORIZATION, auth.clone());
+        }
+        req
+    }
+
+    /// Send a CONNECT-UDP request for `peer` and return the request stream.
+    async fn connect_udp(&self, peer: &DestinationAddr) -> FlowResult<Box<dyn Stream>> {
+        let uri = Uri::builder()
+            .scheme("https")
+            .authority(self.host.as_str())
+            .path_and_query(self.udp_path(peer))
+            .build()
+            .map_err(|_| FlowError::UnexpectedData)?;
+        let mut connector = FlowAdapterConnector {
+            next: self.next.clone(),
+        };
+        let io = connector
+            .call(uri.clone())
+            .await
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        let is_h2 = io.connected().is_negotiated_h2();
+        let (mut sender, conn) = ConnBuilder::new()
+            .http2_only(is_h2)
+            .executor(TokioHyperExecutor::new_current())
+            .handshake(io)
+            .await
+            .map_err(hyper_err)?;
+        // Drives the connection, and hands it over to the upgraded stream
+        // after a successful HTTP/1.1 Upgrade.
+        tokio::spawn(conn);
+        let res = sender
+            .send_request(self.create_udp_req(uri, is_h2))
+            .await
+            .map_err(hyper_err)?;
+        let accepted = if is_h2 {
+            res.status().is_success()
+        } else {
+            res.status() == StatusCode::SWITCHING_PROTOCOLS
+        };
+        if !accepted {
+            return Err(FlowError::UnexpectedData);
+        }
+        let upgraded = hyper::upgrade::on(res).await.map_err(hyper_err)?;
+        Ok(Box::new(CompatFlow::new(upgraded, 4096)))
+    }
+}
+
+#[async_trait]
+impl StreamOutboundFactory for MasqueClient {
+    async fn create_outbound(
+        &self,
+        context: &mut FlowContext,
+        initial_data: &'_ [u8],
+    ) -> FlowResult<(Box<dyn Stream>, Buffer)> {
+        self.tcp.create_outbound(context, initial_data).await
+    }
+}
+
+#[async_trait]
+impl DatagramSessionFactory for MasqueClient {
+    async fn bind(&self, context: Box<FlowContext>) -> FlowResult<Box<dyn DatagramSession>> {
+        let abort = context.abort.signal();
+        if let Some(h3) = &self.h3 {
+            let server = proxy_addr(&self.host).ok_or(FlowError::UnexpectedData)?;
+            let connection = quic::connect_via(
+                &h3.next,
+                h3.client_config.clone(),
+                h3.sni.as_deref(),
+                false,
+                h3.handshake_timeout,
+                &FlowContext::new(context.local_peer, server),
+            )
+            .await?;
+            let session = with_flow_deadline(
+                DEFAULT_HANDSHAKE_TIMEOUT,
+                &abort,
+                h3::connect_udp(
+                    connection,
+                    context.remote_peer.clone(),
+                    &self.host,
+                    &self.udp_path(&context.remote_peer),
+                    self.basic_auth.as_ref().map(|a| a.as_bytes()),
+                ),
+            )
+            .await?;
+            return Ok(Box::new(session));
+        }
+        let lower = with_flow_deadline(
+            DEFAULT_HANDSHAKE_TIMEOUT,
+            &abort,
+            self.connect_udp(&context.remote_peer),
+        )
+        .await?;
+        Ok(Box::new(MasqueDatagramSession::new(
+            context.remote_peer,
+            lower,
+            Buffer::new(),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future::poll_fn;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::capsule::write_datagram_capsule_header;
+    use super::*;
+    use crate::flow::testing::*;
+
+    #[tokio::test]
+    async fn test_bind_http1_upgrade() {
+        let mut aps = AccessPoints::new();
+        let next = MockStreamOutboundFactory::new();
+        let client = MasqueClient::new(
+            "proxy.example:443".into(),
+            "/.well-known/masque/udp/{target_host}/{target_port}/".into(),
+            Some((b"u", b"p")),
+            aps.hold(next.clone()) as _,
+            None,
+        );
+        let server = tokio::spawn(async move {
+            let mut conn = next.connected().await;
+            let mut req = vec![];
+            let mut buf = [0; 1024];
+            while !req.windows(4).any(|w| w == b"\r\n\r\n") {
+                let len = conn.peer.read(&mut buf).await.unwrap();
+                req.extend_from_slice(&buf[..len]);
+            }
+            let req = String::from_utf8(req).unwrap().to_ascii_lowercase();
+            assert!(req.starts_with("get /.well-known/masque/udp/1.1.1.1/53/ http/1.1\r\n"));
+            assert!(req.contains("upgrade: connect-udp\r\n"));
+            assert!(req.contains("capsule-protocol: ?1\r\n"));
+            assert!(req.contains("proxy-authorization: basic dtpw\r\n"));
+            // More headers than a minimal response would carry
+            let mut res = b"HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: connect-udp\r\nCapsule-Protocol: ?1\r\nServer: test\r\nDate: Thu, 01 Jan 1970 00:00:00 GMT\r\n\r\n".to_vec();
+            write_datagram_capsule_header(&mut res, 2);
+            res.extend_from_slice(b"hi");
+            conn.peer.write_all(&res).await.unwrap();
+            conn
+        });
+
+        let mut session = client.bind(context("1.1.1.1:53")).await.unwrap();
+        let received = poll_fn(|cx| session.poll_recv_from(cx)).await;
+        assert_eq!(received, Some((dest("1.1.1.1:53"), b"hi".to_vec())));
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_bind_rejected() {
+        let mut aps = AccessPoints::new();
+        let next = MockStreamOutboundFactory::new();
+        let client = MasqueClient::new(
+            "proxy.example:443".into(),
+            "/{target_host}/{target_port}/".into(),
+            None,
+            aps.hold(next.clone()) as _,
+            None,
+        );
+        let server = tokio::spawn(async move {
+            let mut conn = next.connected().await;
+            let mut buf = [0; 1024];
+            let _ = conn.peer.read(&mut buf).await.unwrap();
+            conn.peer
+                .write_all(b"HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\n\r\n")
+                .await
+                .unwrap();
+            conn
+        });
+        assert!(client.bind(context("1.1.1.1:53")).await.is_err());
+        server.await.unwrap();
+    }
+
+    #[test]
+    fn test_proxy_addr() {
+        assert_eq!(
+            proxy_addr("proxy.example"),
+            Some(dest("proxy.example.:443"))
+        );
+        assert_eq!(proxy_addr("1.2.3.4:8443"), Some(dest("1.2.3.4:8443")));
+        assert_eq!(proxy_addr("[::1]:443"), Some(dest("[::1]:443")));
+        assert_eq!(proxy_addr("a b"), None);
+    }
+}
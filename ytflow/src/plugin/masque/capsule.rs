@@ -0,0 +1,165 @@
+use crate::flow::*;
+
+/// Capsule type of HTTP Datagrams, see RFC 9297.
+const CAPSULE_TYPE_DATAGRAM: u64 = 0x00;
+/// Context ID for UDP payloads, see RFC 9298.
+const CONTEXT_ID_UDP_PAYLOAD: u64 = 0;
+/// Capsules larger than this cannot carry a UDP datagram and are rejected
+/// instead of being buffered.
+const MAX_CAPSULE_LEN: u64 = 65535 + 16;
+
+pub(super) enum ParsedCapsule {
+    /// At least this many bytes are required to decode the next capsule.
+    Incomplete(usize),
+    Complete {
+        len: usize,
+        datagram: Option<Buffer>,
+    },
+}
+
+/// Decode a QUIC variable-length integer along with the length of its
+/// encoding, or return the number of bytes required if `buf` is too short.
+/// The encoding is not necessarily the shortest one for the value.
+pub(super) fn read_varint(buf: &[u8]) -> Result<(u64, usize), usize> {
+    let first = *buf.first().ok_or(1usize)?;
+    let len = 1 << (first >> 6);
+    if buf.len() < len {
+        return Err(len);
+    }
+    let mut value = (first & 0x3f) as u64;
+    for b in &buf[1..len] {
+        value = (value << 8) | *b as u64;
+    }
+    Ok((value, len))
+}
+
+fn varint_len(value: u64) -> usize {
+    match value {
+        0..=0x3f => 1,
+        0x40..=0x3fff => 2,
+        0x4000..=0x3fff_ffff => 4,
+        _ => 8,
+    }
+}
+
+pub(super) fn write_varint(buf: &mut Vec<u8>, value: u64) {
+    match varint_len(value) {
+        1 => buf.push(value as u8),
+        2 => buf.extend_from_slice(&(value as u16 | 0x4000).to_be_bytes()),
+        4 => buf.extend_from_slice(&(value as u32 | 0x8000_0000).to_be_bytes()),
+        _ => buf.extend_from_slice(&(value | 0xc000_0000_0000_0000).to_be_bytes()),
+    }
+}
+
+/// Write the header of a DATAGRAM capsule carrying a UDP payload of `payload_len` bytes.
+pub(super) fn write_datagram_capsule_header(buf: &mut Vec<u8>, payload_len: usize) {
+    write_varint(buf, CAPSULE_TYPE_DATAGRAM);
+    write_varint(
+        buf,
+        (varint_len(CONTEXT_ID_UDP_PAYLOAD) + payload_len) as u64,
+    );
+    write_varint(buf, CONTEXT_ID_UDP_PAYLOAD);
+}
+
+/// Try to decode one capsule from the beginning of `buf`. Capsules other than
+/// DATAGRAM capsules carrying UDP payloads are consumed silently.
+pub(super) fn parse_capsule(buf: &[u8]) -> FlowResult<ParsedCapsule> {
+    let (r#type, type_len) = match read_varint(buf) {
+        Ok(t) => t,
+        Err(need) => return Ok(ParsedCapsule::Incomplete(need)),
+    };
+    let (payload_len, len_len) = match read_varint(&buf[type_len..]) {
+        Ok(l) => l,
+        Err(need) => return Ok(ParsedCapsule::Incomplete(type_len + need)),
+    };
+    if payload_len > MAX_CAPSULE_LEN {
+        return Err(FlowError::UnexpectedData);
+    }
+    let header_len = type_len + len_len;
+    let len = header_len + payload_len as usize;
+    if buf.len() < len {
+        return Ok(ParsedCapsule::Incomplete(len));
+    }
+    let payload = &buf[header_len..len];
+    let datagram = if r#type == CAPSULE_TYPE_DATAGRAM {
+        let (context_id, context_id_len) =
+            read_varint(payload).map_err(|_| FlowError::UnexpectedData)?;
+        (context_id == CONTEXT_ID_UDP_PAYLOAD).then(|| payload[context_id_len..].to_vec())
+    } else {
+        None
+    };
+    Ok(ParsedCapsule::Complete { len, datagram })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_varint_roundtrip() {
+        for value in [0, 0x3f, 0x40, 0x3fff, 0x4000, 0x3fff_ffff, 0x4000_0000] {
+            let mut buf = vec![];
+            write_varint(&mut buf, value);
+            assert_eq!(buf.len(), varint_len(value));
+            assert_eq!(read_varint(&buf), Ok((value, buf.len())));
+        }
+    }
+
+    #[test]
+    fn test_parse_datagram_capsule() {
+        let mut buf = vec![];
+        write_datagram_capsule_header(&mut buf, 3);
+        buf.extend_from_slice(b"abc");
+        // An unknown capsule afterwards
+        buf.extend_from_slice(&[0x3f, 0x01, 0xff]);
+
+        for partial in 0..buf.len() - 3 {
+            assert!(matches!(
+                parse_capsule(&buf[..partial]).unwrap(),
+                ParsedCapsule::Incomplete(need) if need > partial
+            ));
+        }
+        let ParsedCapsule::Complete { len, datagram } = parse_capsule(&buf).unwrap() else {
+            panic!("capsule should be complete");
+        };
+        assert_eq!(datagram.as_deref(), Some(&b"abc"[..]));
+        let ParsedCapsule::Complete { datagram, .. } = parse_capsule(&buf[len..]).unwrap() else {
+            panic!("capsule should be complete");
+        };
+        assert_eq!(datagram, None);
+    }
+
+    #[test]
+    fn test_parse_non_minimal_length() {
+        // Type 0, length 4 in two bytes, context ID 0 in two bytes, "ab"
+        let buf = [0x00, 0x40, 0x04, 0x40, 0x00, b'a', b'b', 0xff];
+        let ParsedCapsule::Complete { len, datagram } = parse_capsule(&buf).unwrap() else {
+            panic!("capsule should be complete");
+        };
+        assert_eq!(len, 7);
+        assert_eq!(datagram.as_deref(), Some(&b"ab"[..]));
+    }
+
+    #[test]
+    fn test_datagram_capsule_roundtrip() {
+        for payload_len in [0, 1, 62, 63, 64, 1500, 16383, 16384, 65535] {
+            let payload: Vec<u8> = (0..payload_len).map(|i| i as u8).collect();
+            let mut buf = vec![];
+            write_datagram_capsule_header(&mut buf, payload.len());
+            buf.extend_from_slice(&payload);
+            let ParsedCapsule::Complete { len, datagram } = parse_capsule(&buf).unwrap() else {
+                panic!("capsule should be complete");
+            };
+            assert_eq!(len, buf.len());
+            assert_eq!(datagram, Some(payload));
+        }
+    }
+
+    #[test]
+    fn test_reject_oversized_capsule() {
+        let mut buf = vec![];
+        write_varint(&mut buf, CAPSULE_TYPE_DATAGRAM);
+        write_varint(&mut buf, MAX_CAPSULE_LEN + 1);
+        assert!(parse_capsule(&buf).is_err());
+    }
+}
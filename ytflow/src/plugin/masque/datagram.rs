@@ -0,0 +1,153 @@
+use std::num::NonZeroUsize;
+use std::task::{ready, Context, Poll};
+
+use super::capsule::{parse_capsule, write_datagram_capsule_header, ParsedCapsule};
+use crate::flow::*;
+
+pub(super) struct MasqueDatagramSession {
+    remote_peer: DestinationAddr,
+    lower: Box<dyn Stream>,
+    reader: StreamReader,
+    rx_expected: usize,
+    tx_buffer: Option<Buffer>,
+    broken: bool,
+}
+
+impl MasqueDatagramSession {
+    pub(super) fn new(
+        remote_peer: DestinationAddr,
+        lower: Box<dyn Stream>,
+        initial_res: Buffer,
+    ) -> Self {
+        Self {
+            remote_peer,
+            lower,
+            reader: StreamReader::new(4096, initial_res),
+            rx_expected: 1,
+            tx_buffer: None,
+            broken: false,
+        }
+    }
+}
+
+impl DatagramSession for MasqueDatagramSession {
+    fn poll_recv_from(&mut self, cx: &mut Context) -> Poll<Option<(DestinationAddr, Buffer)>> {
+        while !self.broken {
+            let res =
+                self.reader
+                    .poll_peek_at_least(cx, &mut *self.lower, self.rx_expected, |buf| {
+                        parse_capsule(buf)
+                    });
+            let parsed = match res {
+                Poll::Pending => {
+                    // Push out datagrams queued by `send_to`
+                    let _ = self.lower.poll_flush_tx(cx);
+                    return Poll::Pending;
+                }
+                Poll::Ready(Ok(Ok(parsed))) => parsed,
+                Poll::Ready(Ok(Err(_)) | Err(_)) => {
+                    self.broken = true;
+                    break;
+                }
+            };
+            match parsed {
+                ParsedCapsule::Incomplete(need) => self.rx_expected = need,
+                ParsedCapsule::Complete { len, datagram } => {
+                    self.reader.advance(len);
+                    self.rx_expected = 1;
+                    if let Some(datagram) = datagram {
+                        return Poll::Ready(Some((self.remote_peer.clone(), datagram)));
+                    }
+                }
+            }
+        }
+        Poll::Ready(None)
+    }
+
+    fn poll_send_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.broken || self.tx_buffer.is_some() {
+            return Poll::Ready(());
+        }
+        match ready!(self
+            .lower
+            .poll_tx_buffer(cx, NonZeroUsize::new(1500).unwrap()))
+        {
+            Ok(buf) => self.tx_buffer = Some(buf),
+            Err(_) => self.broken = true,
+        }
+        Poll::Ready(())
+    }
+
+    fn send_to(&mut self, _remote_peer: DestinationAddr, buf: Buffer) {
+        if self.broken {
+            return;
+        }
+        // Without a tx buffer from `poll_send_ready`, the stream may not be
+        // ready for more data. Drop the datagram as a full socket would.
+        let Some(mut tx_buf) = self.tx_buffer.take() else {
+            return;
+        };
+        tx_buf.reserve(buf.len() + 8);
+        write_datagram_capsule_header(&mut tx_buf, buf.len());
+        tx_buf.extend_from_slice(&buf);
+        if self.lower.commit_tx_buffer(tx_buf).is_err() {
+            self.broken = true;
+        }
+    }
+
+    fn poll_shutdown(&mut self, cx: &mut Context<'_>) -> Poll<FlowResult<()>> {
+        self.lower.poll_close_tx(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future::poll_fn;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::super::capsule::write_varint;
+    use super::*;
+    use crate::flow::testing::*;
+
+    #[tokio::test]
+    async fn test_datagram_roundtrip() {
+        let (stream, mut peer) = stream_pair();
+        // A partial capsule received along with the response head
+        let mut initial_res = vec![];
+        write_datagram_capsule_header(&mut initial_res, 5);
+        initial_res.extend_from_slice(b"he");
+        let mut session = MasqueDatagramSession::new(dest("1.1.1.1:53"), stream, initial_res);
+
+        // The rest, followed by an unknown capsule and a datagram with another
+        // context ID, which are skipped.
+        let mut rest = b"llo".to_vec();
+        write_varint(&mut rest, 0x2f);
+        write_varint(&mut rest, 1);
+        rest.push(0);
+        write_varint(&mut rest, 0);
+        write_varint(&mut rest, 3);
+        rest.extend_from_slice(&[0x02, b'x', b'y']);
+        write_datagram_capsule_header(&mut rest, 2);
+        rest.extend_from_slice(b"hi");
+        peer.write_all(&rest).await.unwrap();
+        for expected in [&b"hello"[..], b"hi"] {
+            let received = poll_fn(|cx| session.poll_recv_from(cx)).await;
+            assert_eq!(received, Some((dest("1.1.1.1:53"), expected.to_vec())));
+        }
+
+        // Not ready yet, hence dropped.
+        session.send_to(dest("1.1.1.1:53"), b"dropped".to_vec());
+        poll_fn(|cx| session.poll_send_ready(cx)).await;
+        session.send_to(dest("1.1.1.1:53"), b"query".to_vec());
+        poll_fn(|cx| session.lower.poll_flush_tx(cx)).await.unwrap();
+        let mut expected = vec![];
+        write_datagram_capsule_header(&mut expected, 5);
+        expected.extend_from_slice(b"query");
+        let mut sent = vec![0; expected.len()];
+        peer.read_exact(&mut sent).await.unwrap();
+        assert_eq!(sent, expected);
+
+        drop(peer);
+        assert_eq!(poll_fn(|cx| session.poll_recv_from(cx)).await, None);
+    }
+}
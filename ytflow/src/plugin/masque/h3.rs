@@ -0,0 +1,522 @@
+//! Just enough HTTP/3 (RFC 9114) to send a CONNECT-UDP request and exchange
+//! HTTP Datagrams (RFC 9297) in QUIC DATAGRAM frames. The QPACK dynamic table
+//! is disabled in both directions, so field sections only refer to the
+//! static table (RFC 9204 Appendix A) or carry literals.
+
+use std::task::{ready, Context, Poll};
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use tokio::io::AsyncReadExt;
+
+use super::capsule::{read_varint, write_varint};
+use crate::flow::*;
+use crate::plugin::quic::connection_err;
+
+const STREAM_TYPE_CONTROL: u64 = 0x00;
+const FRAME_DATA: u64 = 0x00;
+const FRAME_HEADERS: u64 = 0x01;
+const FRAME_SETTINGS: u64 = 0x04;
+const SETTINGS_ENABLE_CONNECT_PROTOCOL: u64 = 0x08;
+const SETTINGS_H3_DATAGRAM: u64 = 0x33;
+const H3_NO_ERROR: u32 = 0x100;
+/// Context ID for UDP payloads, see RFC 9298.
+const CONTEXT_ID_UDP_PAYLOAD: u64 = 0;
+/// Frames on the control or request stream larger than this are rejected
+/// instead of being buffered.
+const MAX_FRAME_LEN: u64 = 16384;
+
+const STATIC_METHOD_CONNECT: u64 = 15;
+const STATIC_SCHEME_HTTPS: u64 = 23;
+const STATIC_AUTHORITY: u64 = 0;
+const STATIC_PATH: u64 = 1;
+
+/// Status codes in the QPACK static table, all of which are named `:status`.
+fn static_status(index: u64) -> Option<u16> {
+    Some(match index {
+        24 => 103,
+        25 => 200,
+        26 => 304,
+        27 => 404,
+        28 => 503,
+        63 => 100,
+        64 => 204,
+        65 => 206,
+        66 => 302,
+        67 => 400,
+        68 => 403,
+        69 => 421,
+        70 => 425,
+        71 => 500,
+        _ => return None,
+    })
+}
+
+/// Write an integer with an N-bit prefix (RFC 7541 Section 5.1) after `flags`
+/// in the first byte.
+fn write_prefixed_int(buf: &mut Vec<u8>, flags: u8, prefix: u32, value: u64) {
+    let max = (1u64 << prefix) - 1;
+    if value < max {
+        buf.push(flags | value as u8);
+        return;
+    }
+    buf.push(flags | max as u8);
+    let mut rest = value - max;
+    while rest >= 0x80 {
+        buf.push(rest as u8 | 0x80);
+        rest >>= 7;
+    }
+    buf.push(rest as u8);
+}
+
+/// Decode an integer with an N-bit prefix along with the length of its
+/// encoding.
+fn read_prefixed_int(buf: &[u8], prefix: u32) -> Option<(u64, usize)> {
+    let max = (1u64 << prefix) - 1;
+    let first = (*buf.first()? as u64) & max;
+    if first < max {
+        return Some((first, 1));
+    }
+    let mut value = max;
+    for (i, b) in buf[1..].iter().enumerate().take(8) {
+        value += ((b & 0x7f) as u64) << (7 * i);
+        if b & 0x80 == 0 {
+            return Some((value, i + 2));
+        }
+    }
+    None
+}
+
+/// Decode a Huffman-encoded string (RFC 7541 Appendix B) made of digits only,
+/// which is all a status code can be.
+fn decode_huffman_digits(buf: &[u8]) -> Option<String> {
+    let total = buf.len() * 8;
+    let bit = |i: usize| ((buf[i / 8] >> (7 - i % 8)) & 1) as u8;
+    let mut out = String::new();
+    let mut pos = 0;
+    while pos < total {
+        let rest = total - pos;
+        // Padding is the most significant bits of EOS, which are all ones.
+        if rest < 8 && (pos..total).all(|i| bit(i) == 1) {
+            break;
+        }
+        if rest < 5 {
+            return None;
+        }
+        // '0'..='2' are 5 bits long, '3'..='9' are 6 bits long.
+        let code5 = (pos..pos + 5).fold(0, |acc, i| acc << 1 | bit(i));
+        if code5 <= 2 {
+            out.push((b'0' + code5) as char);
+            pos += 5;
+            continue;
+        }
+        if rest < 6 {
+            return None;
+        }
+        let code6 = code5 << 1 | bit(pos + 5);
+        if !(0x19..=0x1f).contains(&code6) {
+            return None;
+        }
+        out.push((b'0' + code6 - 0x16) as char);
+        pos += 6;
+    }
+    Some(out)
+}
+
+/// Decode a string literal with an N-bit length prefix, whose Huffman flag is
+/// the bit right before the prefix. Returns the raw bytes, whether they are
+/// Huffman-encoded and the length of the whole literal.
+fn read_string(buf: &[u8], prefix: u32) -> Option<(&[u8], bool, usize)> {
+    let huffman = buf.first()? & (1 << prefix) != 0;
+    let (len, len_len) = read_prefixed_int(buf, prefix)?;
+    let end = len_len.checked_add(usize::try_from(len).ok()?)?;
+    Some((buf.get(len_len..end)?, huffman, end))
+}
+
+fn parse_status(value: &[u8], huffman: bool) -> Option<u16> {
+    if huffman {
+        decode_huffman_digits(value)?.parse().ok()
+    } else {
+        std::str::from_utf8(value).ok()?.parse().ok()
+    }
+}
+
+/// Find `:status` in a QPACK-encoded field section.
+fn decode_status(buf: &[u8]) -> Option<u16> {
+    // Required Insert Count, which must be 0 without a dynamic table, and Base.
+    let (required_insert_count, len) = read_prefixed_int(buf, 8)?;
+    if required_insert_count != 0 {
+        return None;
+    }
+    let (_, base_len) = read_prefixed_int(buf.get(len..)?, 7)?;
+    let mut buf = buf.get(len + base_len..)?;
+    while let Some(&first) = buf.first() {
+        if first & 0x80 != 0 {
+            // Indexed field line, which must refer to the static table.
+            if first & 0x40 == 0 {
+                return None;
+            }
+            let (index, len) = read_prefixed_int(buf, 6)?;
+            if let Some(status) = static_status(index) {
+                return Some(status);
+            }
+            buf = &buf[len..];
+        } else if first & 0x40 != 0 {
+            // Literal field line with a name reference.
+            if first & 0x10 == 0 {
+                return None;
+            }
+            let (index, len) = read_prefixed_int(buf, 4)?;
+            let (value, huffman, value_len) = read_string(buf.get(len..)?, 7)?;
+            if static_status(index).is_some() {
+                return parse_status(value, huffman);
+            }
+            buf = &buf[len + value_len..];
+        } else if first & 0x20 != 0 {
+            // Literal field line with a literal name.
+            let (name, name_huffman, name_len) = read_string(buf, 3)?;
+            let (value, huffman, value_len) = read_string(buf.get(name_len..)?, 7)?;
+            if !name_huffman && name == b":status" {
+                return parse_status(value, huffman);
+            }
+            buf = &buf[name_len + value_len..];
+        } else {
+            // Post-base references into the dynamic table.
+            return None;
+        }
+    }
+    None
+}
+
+fn write_frame(buf: &mut Vec<u8>, r#type: u64, payload: &[u8]) {
+    write_varint(buf, r#type);
+    write_varint(buf, payload.len() as u64);
+    buf.extend_from_slice(payload);
+}
+
+/// The control stream, opened by its stream type and our SETTINGS.
+pub(super) fn encode_control_stream_header() -> Vec<u8> {
+    let mut settings = vec![];
+    write_varint(&mut settings, SETTINGS_H3_DATAGRAM);
+    write_varint(&mut settings, 1);
+    let mut buf = vec![];
+    write_varint(&mut buf, STREAM_TYPE_CONTROL);
+    write_frame(&mut buf, FRAME_SETTINGS, &settings);
+    buf
+}
+
+/// A HEADERS frame of an extended CONNECT request for `connect-udp`
+/// (RFC 9298 Section 3.4).
+pub(super) fn encode_connect_udp_request(
+    authority: &str,
+    path: &str,
+    proxy_authorization: Option<&[u8]>,
+) -> Vec<u8> {
+    fn write_static_name_field(buf: &mut Vec<u8>, index: u64, value: &[u8]) {
+        write_prefixed_int(buf, 0x50, 4, index);
+        write_prefixed_int(buf, 0, 7, value.len() as u64);
+        buf.extend_from_slice(value);
+    }
+    fn write_literal_field(buf: &mut Vec<u8>, name: &[u8], value: &[u8]) {
+        write_prefixed_int(buf, 0x20, 3, name.len() as u64);
+        buf.extend_from_slice(name);
+        write_prefixed_int(buf, 0, 7, value.len() as u64);
+        buf.extend_from_slice(value);
+    }
+
+    // Required Insert Count and Base
+    let mut fields = vec![0, 0];
+    write_prefixed_int(&mut fields, 0xc0, 6, STATIC_METHOD_CONNECT);
+    write_literal_field(&mut fields, b":protocol", b"connect-udp");
+    write_prefixed_int(&mut fields, 0xc0, 6, STATIC_SCHEME_HTTPS);
+    write_static_name_field(&mut fields, STATIC_AUTHORITY, authority.as_bytes());
+    write_static_name_field(&mut fields, STATIC_PATH, path.as_bytes());
+    write_literal_field(&mut fields, b"capsule-protocol", b"?1");
+    if let Some(auth) = proxy_authorization {
+        write_literal_field(&mut fields, b"proxy-authorization", auth);
+    }
+    let mut buf = vec![];
+    write_frame(&mut buf, FRAME_HEADERS, &fields);
+    buf
+}
+
+/// Decode the settings carried in a SETTINGS frame payload, and check that
+/// the server accepts extended CONNECT and HTTP Datagrams.
+pub(super) fn check_settings(mut payload: &[u8]) -> FlowResult<()> {
+    let (mut connect, mut datagram) = (false, false);
+    while !payload.is_empty() {
+        let (id, id_len) = read_varint(payload).map_err(|_| FlowError::UnexpectedData)?;
+        let (value, value_len) =
+            read_varint(&payload[id_len..]).map_err(|_| FlowError::UnexpectedData)?;
+        match id {
+            SETTINGS_ENABLE_CONNECT_PROTOCOL => connect = value == 1,
+            SETTINGS_H3_DATAGRAM => datagram = value == 1,
+            _ => {}
+        }
+        payload = &payload[id_len + value_len..];
+    }
+    if connect && datagram {
+        Ok(())
+    } else {
+        Err(FlowError::UnexpectedData)
+    }
+}
+
+async fn read_stream_varint(recv: &mut quinn::RecvStream) -> FlowResult<u64> {
+    let mut buf = [0; 8];
+    AsyncReadExt::read_exact(recv, &mut buf[..1]).await?;
+    let len = 1 << (buf[0] >> 6);
+    AsyncReadExt::read_exact(recv, &mut buf[1..len]).await?;
+    read_varint(&buf[..len])
+        .map(|(v, _)| v)
+        .map_err(|_| FlowError::UnexpectedData)
+}
+
+/// Read the next frame on `recv`, skipping frames of unknown types.
+async fn read_frame(recv: &mut quinn::RecvStream) -> FlowResult<(u64, Vec<u8>)> {
+    loop {
+        let r#type = read_stream_varint(recv).await?;
+        let len = read_stream_varint(recv).await?;
+        if len > MAX_FRAME_LEN {
+            return Err(FlowError::UnexpectedData);
+        }
+        let mut payload = vec![0; len as usize];
+        AsyncReadExt::read_exact(recv, &mut payload).await?;
+        if matches!(r#type, FRAME_DATA | FRAME_HEADERS | FRAME_SETTINGS) {
+            return Ok((r#type, payload));
+        }
+    }
+}
+
+/// Wait for the control stream of the server and check its SETTINGS. All
+/// unidirectional streams of the server are returned, since stopping any
+/// critical stream would be a connection error.
+async fn accept_server_streams(
+    connection: &quinn::Connection,
+) -> FlowResult<Vec<quinn::RecvStream>> {
+    let mut streams = vec![];
+    loop {
+        let mut recv = connection.accept_uni().await.map_err(connection_err)?;
+        let r#type = read_stream_varint(&mut recv).await?;
+        if r#type != STREAM_TYPE_CONTROL {
+            streams.push(recv);
+            continue;
+        }
+        match read_frame(&mut recv).await? {
+            (FRAME_SETTINGS, payload) => check_settings(&payload)?,
+            _ => return Err(FlowError::UnexpectedData),
+        }
+        streams.push(recv);
+        return Ok(streams);
+    }
+}
+
+/// Send a CONNECT-UDP request on a new HTTP/3 connection and return a session
+/// of the datagrams in the tunnel.
+pub(super) async fn connect_udp(
+    connection: quinn::Connection,
+    remote_peer: DestinationAddr,
+    authority: &str,
+    path: &str,
+    proxy_authorization: Option<&[u8]>,
+) -> FlowResult<H3DatagramSession> {
+    let mut control = connection.open_uni().await.map_err(connection_err)?;
+    control
+        .write_all(&encode_control_stream_header())
+        .await
+        .map_err(|e| FlowError::Io(e.into()))?;
+    let server_streams = accept_server_streams(&connection).await?;
+
+    let (mut send, mut recv) = connection.open_bi().await.map_err(connection_err)?;
+    send.write_all(&encode_connect_udp_request(
+        authority,
+        path,
+        proxy_authorization,
+    ))
+    .await
+    .map_err(|e| FlowError::Io(e.into()))?;
+    loop {
+        let (FRAME_HEADERS, fields) = read_frame(&mut recv).await? else {
+            return Err(FlowError::UnexpectedData);
+        };
+        match decode_status(&fields) {
+            Some(100..=199) => continue,
+            Some(200..=299) => break,
+            _ => return Err(FlowError::UnexpectedData),
+        }
+    }
+    Ok(H3DatagramSession {
+        quarter_stream_id: send.id().index(),
+        remote_peer,
+        connection,
+        _streams: (control, send, recv, server_streams),
+        recv_future: None,
+    })
+}
+
+/// Datagrams of a CONNECT-UDP tunnel, carried in QUIC DATAGRAM frames
+/// prefixed by the Quarter Stream ID of the request (RFC 9297 Section 2.1).
+/// Each session uses a QUIC connection of its own.
+pub(super) struct H3DatagramSession {
+    quarter_stream_id: u64,
+    remote_peer: DestinationAddr,
+    connection: quinn::Connection,
+    /// Dropping the request stream would end the tunnel, and dropping the
+    /// control streams would end the connection.
+    _streams: (
+        quinn::SendStream,
+        quinn::SendStream,
+        quinn::RecvStream,
+        Vec<quinn::RecvStream>,
+    ),
+    recv_future: Option<BoxFuture<'static, Result<Vec<u8>, quinn::ConnectionError>>>,
+}
+
+pub(super) fn encode_datagram(quarter_stream_id: u64, payload: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(payload.len() + 9);
+    write_varint(&mut buf, quarter_stream_id);
+    write_varint(&mut buf, CONTEXT_ID_UDP_PAYLOAD);
+    buf.extend_from_slice(payload);
+    buf
+}
+
+/// The UDP payload of an HTTP Datagram for the request, if any.
+pub(super) fn decode_datagram(quarter_stream_id: u64, datagram: &[u8]) -> Option<&[u8]> {
+    let (id, id_len) = read_varint(datagram).ok()?;
+    let (context_id, context_len) = read_varint(&datagram[id_len..]).ok()?;
+    (id == quarter_stream_id && context_id == CONTEXT_ID_UDP_PAYLOAD)
+        .then(|| &datagram[id_len + context_len..])
+}
+
+impl DatagramSession for H3DatagramSession {
+    fn poll_recv_from(&mut self, cx: &mut Context) -> Poll<Option<(DestinationAddr, Buffer)>> {
+        loop {
+            let fut = self.recv_future.get_or_insert_with(|| {
+                let connection = self.connection.clone();
+                async move { connection.read_datagram().await.map(|d| d.to_vec()) }.boxed()
+            });
+            let res = ready!(fut.poll_unpin(cx));
+            self.recv_future = None;
+            let Ok(datagram) = res else {
+                return Poll::Ready(None);
+            };
+            if let Some(payload) = decode_datagram(self.quarter_stream_id, &datagram) {
+                return Poll::Ready(Some((self.remote_peer.clone(), payload.to_vec())));
+            }
+        }
+    }
+
+    fn poll_send_ready(&mut self, _cx: &mut Context<'_>) -> Poll<()> {
+        // QUIC DATAGRAM frames are never retransmitted, so quinn drops them
+        // instead of queueing when congested.
+        Poll::Ready(())
+    }
+
+    fn send_to(&mut self, remote_peer: DestinationAddr, buf: Buffer) {
+        // The tunnel only reaches the target it was requested for.
+        if remote_peer != self.remote_peer {
+            return;
+        }
+        // Datagrams too large for the path are dropped as a full socket would.
+        let _ = self
+            .connection
+            .send_datagram(encode_datagram(self.quarter_stream_id, &buf).into());
+    }
+
+    fn poll_shutdown(&mut self, _cx: &mut Context<'_>) -> Poll<FlowResult<()>> {
+        self.connection.close(H3_NO_ERROR.into(), b"");
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefixed_int_roundtrip() {
+        for (prefix, value) in [(4, 0), (4, 14), (4, 15), (7, 1337), (6, 1 << 40)] {
+            let mut buf = vec![];
+            write_prefixed_int(&mut buf, 0, prefix, value);
+            assert_eq!(read_prefixed_int(&buf, prefix), Some((value, buf.len())));
+        }
+    }
+
+    #[test]
+    fn test_decode_huffman_digits() {
+        // "302" = 011001 00000 00010
+        assert_eq!(
+            decode_huffman_digits(&[0b0110_0100, 0b0000_0010]).as_deref(),
+            Some("302")
+        );
+        // "200" = 00010 00000 00000, padded with a one
+        assert_eq!(
+            decode_huffman_digits(&[0b0001_0000, 0b0000_0001]).as_deref(),
+            Some("200")
+        );
+        assert_eq!(decode_huffman_digits(&[0b1111_1111]), None);
+    }
+
+    #[test]
+    fn test_decode_status() {
+        // Indexed :status 200
+        assert_eq!(decode_status(&[0, 0, 0xc0 | 25]), Some(200));
+        // A literal field, then :status with a static name and a literal value
+        let mut fields = vec![0, 0];
+        fields.extend_from_slice(&[0x23, b'a', b'b', b'c', 0x01, b'x']);
+        fields.extend_from_slice(&[0x50 | 0x0f, 24 - 15, 0x03, b'2', b'0', b'1']);
+        assert_eq!(decode_status(&fields), Some(201));
+        // Huffman-encoded "302"
+        let fields = [0, 0, 0x5f, 24 - 15, 0x82, 0b0110_0100, 0b0000_0010];
+        assert_eq!(decode_status(&fields), Some(302));
+        // Dynamic table references
+        assert_eq!(decode_status(&[1, 0, 0x80]), None);
+        assert_eq!(decode_status(&[0, 0, 0x10]), None);
+    }
+
+    #[test]
+    fn test_encode_connect_udp_request() {
+        let frame = encode_connect_udp_request("proxy.example:443", "/a", Some(b"Basic x"));
+        let (r#type, type_len) = read_varint(&frame).unwrap();
+        let (len, len_len) = read_varint(&frame[type_len..]).unwrap();
+        assert_eq!(r#type, FRAME_HEADERS);
+        assert_eq!(len as usize, frame.len() - type_len - len_len);
+        let fields = &frame[type_len + len_len..];
+        assert_eq!(&fields[..3], &[0, 0, 0xc0 | 15]);
+        let fields = String::from_utf8_lossy(fields);
+        for expected in [
+            ":protocol",
+            "connect-udp",
+            "proxy.example:443",
+            "capsule-protocol",
+            "proxy-authorization",
+            "Basic x",
+        ] {
+            assert!(fields.contains(expected), "{expected} missing");
+        }
+    }
+
+    #[test]
+    fn test_check_settings() {
+        let mut payload = vec![];
+        for (id, value) in [(0x21, 7), (SETTINGS_ENABLE_CONNECT_PROTOCOL, 1)] {
+            write_varint(&mut payload, id);
+            write_varint(&mut payload, value);
+        }
+        assert!(check_settings(&payload).is_err());
+        write_varint(&mut payload, SETTINGS_H3_DATAGRAM);
+        write_varint(&mut payload, 1);
+        assert!(check_settings(&payload).is_ok());
+    }
+
+    #[test]
+    fn test_datagram_roundtrip() {
+        let datagram = encode_datagram(1, b"query");
+        assert_eq!(decode_datagram(1, &datagram), Some(&b"query"[..]));
+        assert_eq!(decode_datagram(2, &datagram), None);
+        let mut other_context = vec![];
+        write_varint(&mut other_context, 1);
+        write_varint(&mut other_context, 2);
+        assert_eq!(decode_datagram(1, &other_context), None);
+    }
+}
@@ -0,0 +1,7 @@
+mod responder;
+mod sys;
+mod watchdog;
+
+pub use responder::Responder;
+pub use sys::{sample_memory_usage, MemoryUsage};
+pub use watchdog::{MemoryWatchdog, WatchdogStatus};
@@ -0,0 +1,51 @@
+use std::sync::{Arc, Mutex};
+
+use cbor4ii::serde::to_vec;
+
+use super::watchdog::{MemoryWatchdog, WatchdogStatus};
+use crate::control::{PluginRequestError, PluginRequestResult, PluginResponder};
+
+pub struct Responder {
+    watchdog: Arc<MemoryWatchdog>,
+    last_info: Mutex<(WatchdogStatus, u32)>,
+}
+
+impl Responder {
+    pub fn new(watchdog: Arc<MemoryWatchdog>) -> Self {
+        Self {
+            watchdog,
+            last_info: Mutex::new((WatchdogStatus::default(), 1)),
+        }
+    }
+}
+
+impl PluginResponder for Responder {
+    fn collect_info(&self, hashcode: &mut u32) -> Option<Vec<u8>> {
+        let info = {
+            let mut last_info_guard = self.last_info.lock().unwrap();
+            let (last_info, last_hashcode) = &mut *last_info_guard;
+            let new_info = self.watchdog.status();
+            if new_info == *last_info {
+                if *last_hashcode == *hashcode {
+                    return None;
+                }
+            } else {
+                *last_info = new_info.clone();
+                *last_hashcode = (*last_hashcode).wrapping_add(1);
+            }
+            *hashcode = *last_hashcode;
+            new_info
+        };
+        Some(to_vec(vec![], &info).unwrap())
+    }
+
+    fn on_request(&self, func: &str, _params: &[u8]) -> PluginRequestResult<Vec<u8>> {
+        match func {
+            "shrink" => {
+                let shrunk = self.watchdog.shrink() as u32;
+                Ok(to_vec(vec![], &shrunk).unwrap())
+            }
+            _ => Err(PluginRequestError::NoSuchFunc),
+        }
+    }
+}
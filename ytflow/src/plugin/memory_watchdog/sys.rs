@@ -0,0 +1,69 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// Memory currently used by the process in bytes.
+    pub usage: u64,
+    /// Memory the process is allowed to use in bytes, if the platform imposes
+    /// a limit on it, e.g. the budget of a UWP VPN background task.
+    pub limit: Option<u64>,
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn sample_memory_usage() -> Option<MemoryUsage> {
+    // The second field is the resident set size in pages.
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let resident: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    if page_size <= 0 {
+        return None;
+    }
+    // Limit set by cgroup v2, e.g. in a container. "max" means unlimited.
+    let limit = std::fs::read_to_string("/sys/fs/cgroup/memory.max")
+        .ok()
+        .and_then(|s| s.trim().parse().ok());
+    Some(MemoryUsage {
+        usage: resident * page_size as u64,
+        limit,
+    })
+}
+
+#[cfg(windows)]
+pub fn sample_memory_usage() -> Option<MemoryUsage> {
+    use windows::System::MemoryManager;
+
+    let usage = MemoryManager::AppMemoryUsage().ok()?;
+    let limit = MemoryManager::AppMemoryUsageLimit().ok();
+    Some(MemoryUsage { usage, limit })
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+pub fn sample_memory_usage() -> Option<MemoryUsage> {
+    let mut info: libc::proc_taskinfo = unsafe { std::mem::zeroed() };
+    let size = std::mem::size_of::<libc::proc_taskinfo>() as libc::c_int;
+    let ret = unsafe {
+        libc::proc_pidinfo(
+            libc::getpid(),
+            libc::PROC_PIDTASKINFO,
+            0,
+            &mut info as *mut _ as *mut libc::c_void,
+            size,
+        )
+    };
+    if ret != size {
+        return None;
+    }
+    Some(MemoryUsage {
+        usage: info.pti_resident_size,
+        limit: None,
+    })
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    windows,
+    target_os = "macos",
+    target_os = "ios"
+)))]
+pub fn sample_memory_usage() -> Option<MemoryUsage> {
+    None
+}
@@ -0,0 +1,117 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use super::sys::{sample_memory_usage, MemoryUsage};
+use crate::control::events::{Event, EventBus};
+use crate::control::CacheShrinkers;
+
+/// Usage must drop below this percentage of the threshold before another
+/// shrink is triggered, so that hovering around the threshold does not
+/// shrink caches over and over.
+const REARM_PERCENT: u64 = 90;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct WatchdogStatus {
+    pub usage: Option<u64>,
+    pub limit: Option<u64>,
+    pub threshold: Option<u64>,
+    pub under_pressure: bool,
+    pub shrink_count: u32,
+}
+
+pub struct MemoryWatchdog {
+    plugin_name: String,
+    threshold: Option<u64>,
+    limit_percent: u8,
+    interval: Duration,
+    shrinkers: CacheShrinkers,
+    events: EventBus,
+    status: Mutex<WatchdogStatus>,
+}
+
+impl MemoryWatchdog {
+    /// Without an explicit `threshold`, caches are shrunk when usage reaches
+    /// `limit_percent` of the limit reported by the platform.
+    pub fn new(
+        plugin_name: String,
+        threshold: Option<u64>,
+        limit_percent: u8,
+        interval: Duration,
+        shrinkers: CacheShrinkers,
+        events: EventBus,
+    ) -> Self {
+        Self {
+            plugin_name,
+            threshold,
+            limit_percent,
+            interval,
+            shrinkers,
+            events,
+            status: Mutex::new(WatchdogStatus::default()),
+        }
+    }
+
+    pub fn status(&self) -> WatchdogStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    fn threshold_for(&self, sample: &MemoryUsage) -> Option<u64> {
+        self.threshold
+            .or_else(|| sample.limit.map(|l| l / 100 * self.limit_percent as u64))
+    }
+
+    /// Shrink all registered caches regardless of memory usage.
+    pub fn shrink(&self) -> usize {
+        let shrunk = self.shrinkers.shrink_all();
+        self.status.lock().unwrap().shrink_count += 1;
+        shrunk
+    }
+
+    fn check(&self) {
+        let Some(sample) = sample_memory_usage() else {
+            return;
+        };
+        let threshold = self.threshold_for(&sample);
+        let should_shrink = {
+            let mut status = self.status.lock().unwrap();
+            status.usage = Some(sample.usage);
+            status.limit = sample.limit;
+            status.threshold = threshold;
+            let Some(threshold) = threshold else {
+                return;
+            };
+            if status.under_pressure {
+                status.under_pressure = sample.usage >= threshold / 100 * REARM_PERCENT;
+                false
+            } else {
+                status.under_pressure = sample.usage >= threshold;
+                status.under_pressure
+            }
+        };
+        if !should_shrink {
+            return;
+        }
+        let threshold = threshold.unwrap_or_default();
+        let shrunk = self.shrink();
+        crate::log::debug_log(format!(
+            "{}: memory usage {} bytes reached threshold {} bytes, shrunk {} caches",
+            self.plugin_name, sample.usage, threshold, shrunk
+        ));
+        self.events.publish(Event::MemoryPressure {
+            plugin: self.plugin_name.clone(),
+            usage: sample.usage,
+            threshold,
+        });
+    }
+
+    pub async fn run(&self) {
+        let mut ticker = tokio::time::interval(self.interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            self.check();
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use std::sync::Weak;
+use std::time::Duration;
+
+use crate::flow::*;
+
+/// Streams that do not send anything within this period are closed.
+const SNIFF_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SniffedProtocol {
+    Socks5,
+    Http,
+    Tls,
+    Unknown,
+}
+
+impl SniffedProtocol {
+    pub fn from_first_byte(b: u8) -> Self {
+        match b {
+            0x05 => SniffedProtocol::Socks5,
+            // TLS handshake record
+            0x16 => SniffedProtocol::Tls,
+            // HTTP methods such as GET, POST and CONNECT
+            b'A'..=b'Z' => SniffedProtocol::Http,
+            _ => SniffedProtocol::Unknown,
+        }
+    }
+}
+
+/// Dispatch inbound streams to SOCKS5, HTTP proxy or TLS servers by the first
+/// byte sent by the client, so that one listening port serves all of them.
+#[derive(Clone)]
+pub struct MixedListener {
+    pub socks5_next: Option<Weak<dyn StreamHandler>>,
+    pub http_next: Option<Weak<dyn StreamHandler>>,
+    pub tls_next: Option<Weak<dyn StreamHandler>>,
+    pub fallback: Weak<dyn StreamHandler>,
+}
+
+impl MixedListener {
+    fn match_next(&self, protocol: SniffedProtocol) -> &Weak<dyn StreamHandler> {
+        match protocol {
+            SniffedProtocol::Socks5 => self.socks5_next.as_ref(),
+            SniffedProtocol::Http => self.http_next.as_ref(),
+            SniffedProtocol::Tls => self.tls_next.as_ref(),
+            SniffedProtocol::Unknown => None,
+        }
+        .unwrap_or(&self.fallback)
+    }
+}
+
+impl StreamHandler for MixedListener {
+    fn on_stream(
+        &self,
+        mut lower: Box<dyn Stream>,
+        initial_data: Buffer,
+        context: Box<FlowContext>,
+    ) {
+        if let Some(&first) = initial_data.first() {
+            let Some(next) = self
+                .match_next(SniffedProtocol::from_first_byte(first))
+                .upgrade()
+            else {
+                return;
+            };
+            next.on_stream(lower, initial_data, context);
+            return;
+        }
+
+        let this = self.clone();
+        tokio::spawn(async move {
+            let mut reader = StreamReader::new(4096, initial_data);
+            let Ok(Ok(protocol)) = tokio::time::timeout(
+                SNIFF_TIMEOUT,
+                reader.peek_at_least(&mut *lower, 1, |buf| {
+                    SniffedProtocol::from_first_byte(buf[0])
+                }),
+            )
+            .await
+            else {
+                return;
+            };
+            let next = this.match_next(protocol);
+            let Some(next) = next.upgrade() else {
+                return;
+            };
+            next.on_stream(lower, reader.into_buffer().unwrap_or_default(), context);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+    use crate::flow::testing::*;
+
+    struct Handlers {
+        socks5: Arc<MockStreamHandler>,
+        http: Arc<MockStreamHandler>,
+        tls: Arc<MockStreamHandler>,
+        fallback: Arc<MockStreamHandler>,
+    }
+
+    impl Handlers {
+        fn new() -> Self {
+            Self {
+                socks5: MockStreamHandler::new(),
+                http: MockStreamHandler::new(),
+                tls: MockStreamHandler::new(),
+                fallback: MockStreamHandler::new(),
+            }
+        }
+
+        fn listener(&self, aps: &mut AccessPoints) -> MixedListener {
+            MixedListener {
+                socks5_next: Some(aps.hold(self.socks5.clone()) as _),
+                http_next: Some(aps.hold(self.http.clone()) as _),
+                tls_next: Some(aps.hold(self.tls.clone()) as _),
+                fallback: aps.hold(self.fallback.clone()) as _,
+            }
+        }
+
+        fn accepted(&self) -> [bool; 4] {
+            [&self.socks5, &self.http, &self.tls, &self.fallback].map(|h| h.try_accept().is_some())
+        }
+    }
+
+    #[test]
+    fn test_sniff_first_byte() {
+        assert_eq!(
+            SniffedProtocol::from_first_byte(0x05),
+            SniffedProtocol::Socks5
+        );
+        assert_eq!(SniffedProtocol::from_first_byte(0x16), SniffedProtocol::Tls);
+        for b in *b"GPCDHOTA" {
+            assert_eq!(SniffedProtocol::from_first_byte(b), SniffedProtocol::Http);
+        }
+        for b in [0x04, b'g', 0, 0xff] {
+            assert_eq!(
+                SniffedProtocol::from_first_byte(b),
+                SniffedProtocol::Unknown
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_by_initial_data() {
+        let mut aps = AccessPoints::new();
+        let handlers = Handlers::new();
+        let listener = handlers.listener(&mut aps);
+        for (initial_data, expected) in [
+            (&b"\x05\x01\x00"[..], [true, false, false, false]),
+            (
+                b"CONNECT example.com:443 HTTP/1.1\r\n",
+                [false, true, false, false],
+            ),
+            (b"\x16\x03\x01", [false, false, true, false]),
+            (b"ssh-2.0", [false, false, false, true]),
+        ] {
+            let (stream, _peer) = stream_pair();
+            listener.on_stream(stream, initial_data.to_vec(), context("127.0.0.1:1080"));
+            assert_eq!(handlers.accepted(), expected, "{:?}", initial_data);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_missing_handler_falls_back() {
+        let mut aps = AccessPoints::new();
+        let handlers = Handlers::new();
+        let mut listener = handlers.listener(&mut aps);
+        listener.http_next = None;
+        let (stream, _peer) = stream_pair();
+        listener.on_stream(
+            stream,
+            b"GET / HTTP/1.1\r\n".to_vec(),
+            context("127.0.0.1:1080"),
+        );
+        let accepted = handlers.fallback.try_accept().unwrap();
+        assert_eq!(accepted.initial_data, b"GET / HTTP/1.1\r\n");
+        assert!(handlers.http.try_accept().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_sniff_without_initial_data() {
+        let mut aps = AccessPoints::new();
+        let handlers = Handlers::new();
+        let listener = handlers.listener(&mut aps);
+        let (stream, mut peer) = stream_pair();
+        listener.on_stream(stream, vec![], context("127.0.0.1:1080"));
+        peer.write_all(b"\x05\x01\x00").await.unwrap();
+
+        let mut accepted = handlers.socks5.accept().await;
+        // The peeked bytes are handed over instead of being lost.
+        assert_eq!(accepted.initial_data, b"\x05\x01\x00");
+        assert_eq!(handlers.accepted(), [false; 4]);
+        peer.write_all(b"more").await.unwrap();
+        accepted
+            .stream
+            .commit_rx_buffer(Vec::with_capacity(16))
+            .unwrap();
+        let buf = crate::get_rx_buffer_boxed!(accepted.stream).unwrap();
+        assert_eq!(buf, b"more");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_silent_client_dropped() {
+        let mut aps = AccessPoints::new();
+        let handlers = Handlers::new();
+        let listener = handlers.listener(&mut aps);
+        let (stream, mut peer) = stream_pair();
+        let start = tokio::time::Instant::now();
+        listener.on_stream(stream, vec![], context("127.0.0.1:1080"));
+
+        let mut buf = [0; 1];
+        assert_eq!(peer.read(&mut buf).await.unwrap(), 0);
+        assert!(start.elapsed() >= SNIFF_TIMEOUT);
+        assert_eq!(handlers.accepted(), [false; 4]);
+    }
+}
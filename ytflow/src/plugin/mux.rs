@@ -0,0 +1,99 @@
+mod frame;
+mod session;
+mod stream;
+
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::io::AsyncWriteExt;
+
+use crate::flow::*;
+use session::MuxSession;
+
+/// Connections to this destination are taken as mux sessions by sing-box and
+/// compatible servers.
+const SESSION_DESTINATION_HOST: &str = "sp.mux.sing-box.arpa.";
+const SESSION_DESTINATION_PORT: u16 = 444;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MuxProtocol {
+    #[default]
+    Smux,
+    Yamux,
+}
+
+/// Multiplexes outbound streams over a pool of connections created by `next`,
+/// in the format of sing-box mux.
+pub struct MuxOutboundFactory {
+    protocol: MuxProtocol,
+    max_connections: usize,
+    max_streams: usize,
+    idle_timeout: Duration,
+    next: Weak<dyn StreamOutboundFactory>,
+    sessions: tokio::sync::Mutex<Vec<Arc<MuxSession>>>,
+}
+
+impl MuxOutboundFactory {
+    /// A new connection is opened only when every connection is carrying
+    /// `max_streams` streams and there are fewer than `max_connections`
+    /// connections. Otherwise the least busy connection is picked. Idle
+    /// connections are closed after `idle_timeout`.
+    pub fn new(
+        protocol: MuxProtocol,
+        max_connections: usize,
+        max_streams: usize,
+        idle_timeout: Duration,
+        next: Weak<dyn StreamOutboundFactory>,
+    ) -> Self {
+        Self {
+            protocol,
+            max_connections,
+            max_streams,
+            idle_timeout,
+            next,
+            sessions: tokio::sync::Mutex::new(vec![]),
+        }
+    }
+
+    async fn pick_session(&self, context: &FlowContext) -> FlowResult<Arc<MuxSession>> {
+        let mut sessions = self.sessions.lock().await;
+        sessions.retain(|s| s.is_available());
+        if let Some(session) = sessions.iter().min_by_key(|s| s.stream_count()) {
+            if session.stream_count() < self.max_streams || sessions.len() >= self.max_connections {
+                return Ok(session.clone());
+            }
+        }
+        let next = self.next.upgrade().ok_or(FlowError::NoOutbound)?;
+        let mut next_context = FlowContext::new(
+            context.local_peer,
+            DestinationAddr {
+                host: HostName::DomainName(SESSION_DESTINATION_HOST.into()),
+                port: SESSION_DESTINATION_PORT,
+            },
+        );
+        let (lower, initial_data) = next.create_outbound(&mut next_context, &[]).await?;
+        let session = MuxSession::start(self.protocol, lower, initial_data, self.idle_timeout);
+        sessions.push(session.clone());
+        Ok(session)
+    }
+}
+
+#[async_trait]
+impl StreamOutboundFactory for MuxOutboundFactory {
+    async fn create_outbound(
+        &self,
+        context: &mut FlowContext,
+        initial_data: &[u8],
+    ) -> FlowResult<(Box<dyn Stream>, Buffer)> {
+        let session = self.pick_session(context).await?;
+        let mut stream = session.open_stream()?;
+        let mut request = Vec::with_capacity(2 + 259 + initial_data.len());
+        // Flags: a TCP stream, without the destination echoed back
+        request.extend_from_slice(&0u16.to_be_bytes());
+        crate::plugin::shadowsocks::util::write_dest(&mut request, &context.remote_peer);
+        request.extend_from_slice(initial_data);
+        stream.write_all(&request).await?;
+        Ok((Box::new(CompatFlow::new(stream, 4096)), Buffer::new()))
+    }
+}
@@ -0,0 +1,217 @@
+use std::io;
+
+use super::MuxProtocol;
+
+/// Largest payload carried by a single data frame.
+pub(super) const MAX_DATA_LEN: usize = 32 * 1024;
+pub(super) const YAMUX_INITIAL_WINDOW: u32 = 256 * 1024;
+
+const SMUX_VERSION: u8 = 1;
+const SMUX_CMD_SYN: u8 = 0;
+const SMUX_CMD_FIN: u8 = 1;
+const SMUX_CMD_PSH: u8 = 2;
+const SMUX_CMD_NOP: u8 = 3;
+
+const YAMUX_VERSION: u8 = 0;
+const YAMUX_TYPE_DATA: u8 = 0;
+const YAMUX_TYPE_WINDOW_UPDATE: u8 = 1;
+const YAMUX_TYPE_PING: u8 = 2;
+const YAMUX_TYPE_GO_AWAY: u8 = 3;
+const YAMUX_FLAG_SYN: u16 = 1;
+const YAMUX_FLAG_ACK: u16 = 2;
+const YAMUX_FLAG_FIN: u16 = 4;
+const YAMUX_FLAG_RST: u16 = 8;
+
+/// What a received frame asks the session to do.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(super) struct FrameHeader {
+    pub sid: u32,
+    /// Length of stream data following the header.
+    pub data_len: usize,
+    pub window_delta: u32,
+    pub fin: bool,
+    pub rst: bool,
+    /// Opaque value of a ping to be acknowledged.
+    pub ping: Option<u32>,
+    pub go_away: bool,
+}
+
+fn put_smux(buf: &mut Vec<u8>, cmd: u8, sid: u32, len: u16) {
+    buf.extend_from_slice(&[SMUX_VERSION, cmd]);
+    buf.extend_from_slice(&len.to_le_bytes());
+    buf.extend_from_slice(&sid.to_le_bytes());
+}
+
+fn put_yamux(buf: &mut Vec<u8>, ty: u8, flags: u16, sid: u32, len: u32) {
+    buf.extend_from_slice(&[YAMUX_VERSION, ty]);
+    buf.extend_from_slice(&flags.to_be_bytes());
+    buf.extend_from_slice(&sid.to_be_bytes());
+    buf.extend_from_slice(&len.to_be_bytes());
+}
+
+fn invalid_header() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "Invalid mux frame header")
+}
+
+fn frame_too_long() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "Mux frame exceeds the receive window",
+    )
+}
+
+impl MuxProtocol {
+    pub(super) fn header_len(self) -> usize {
+        match self {
+            MuxProtocol::Smux => 8,
+            MuxProtocol::Yamux => 12,
+        }
+    }
+
+    pub(super) fn encode_open(self, sid: u32, buf: &mut Vec<u8>) {
+        match self {
+            MuxProtocol::Smux => put_smux(buf, SMUX_CMD_SYN, sid, 0),
+            MuxProtocol::Yamux => put_yamux(buf, YAMUX_TYPE_WINDOW_UPDATE, YAMUX_FLAG_SYN, sid, 0),
+        }
+    }
+
+    pub(super) fn encode_data(self, sid: u32, data: &[u8], buf: &mut Vec<u8>) {
+        debug_assert!(data.len() <= MAX_DATA_LEN);
+        match self {
+            MuxProtocol::Smux => put_smux(buf, SMUX_CMD_PSH, sid, data.len() as u16),
+            MuxProtocol::Yamux => put_yamux(buf, YAMUX_TYPE_DATA, 0, sid, data.len() as u32),
+        }
+        buf.extend_from_slice(data);
+    }
+
+    pub(super) fn encode_fin(self, sid: u32, buf: &mut Vec<u8>) {
+        match self {
+            MuxProtocol::Smux => put_smux(buf, SMUX_CMD_FIN, sid, 0),
+            MuxProtocol::Yamux => put_yamux(buf, YAMUX_TYPE_WINDOW_UPDATE, YAMUX_FLAG_FIN, sid, 0),
+        }
+    }
+
+    pub(super) fn encode_reset(self, sid: u32, buf: &mut Vec<u8>) {
+        match self {
+            // smux v1 has no way to abort a stream other than closing it.
+            MuxProtocol::Smux => put_smux(buf, SMUX_CMD_FIN, sid, 0),
+            MuxProtocol::Yamux => put_yamux(buf, YAMUX_TYPE_WINDOW_UPDATE, YAMUX_FLAG_RST, sid, 0),
+        }
+    }
+
+    /// No-op for smux, which has no flow control.
+    pub(super) fn encode_window_update(self, sid: u32, delta: u32, buf: &mut Vec<u8>) {
+        if let MuxProtocol::Yamux = self {
+            put_yamux(buf, YAMUX_TYPE_WINDOW_UPDATE, 0, sid, delta);
+        }
+    }
+
+    pub(super) fn encode_ping_ack(self, opaque: u32, buf: &mut Vec<u8>) {
+        if let MuxProtocol::Yamux = self {
+            put_yamux(buf, YAMUX_TYPE_PING, YAMUX_FLAG_ACK, 0, opaque);
+        }
+    }
+
+    /// `header` must be exactly [`MuxProtocol::header_len`] bytes long.
+    pub(super) fn decode_header(self, header: &[u8]) -> io::Result<FrameHeader> {
+        let mut frame = FrameHeader::default();
+        match self {
+            MuxProtocol::Smux => {
+                if header[0] != SMUX_VERSION {
+                    return Err(invalid_header());
+                }
+                let len = u16::from_le_bytes([header[2], header[3]]) as usize;
+                frame.sid = u32::from_le_bytes(header[4..8].try_into().unwrap());
+                match header[1] {
+                    SMUX_CMD_PSH => frame.data_len = len,
+                    SMUX_CMD_FIN => frame.fin = true,
+                    // Streams opened by the server are not supported and
+                    // thus ignored.
+                    SMUX_CMD_SYN | SMUX_CMD_NOP if len == 0 => {}
+                    _ => return Err(invalid_header()),
+                }
+            }
+            MuxProtocol::Yamux => {
+                if header[0] != YAMUX_VERSION {
+                    return Err(invalid_header());
+                }
+                let flags = u16::from_be_bytes([header[2], header[3]]);
+                let len = u32::from_be_bytes(header[8..12].try_into().unwrap());
+                frame.sid = u32::from_be_bytes(header[4..8].try_into().unwrap());
+                frame.fin = flags & YAMUX_FLAG_FIN != 0;
+                frame.rst = flags & YAMUX_FLAG_RST != 0;
+                match header[1] {
+                    // Data is buffered before being handed to the stream, so
+                    // never trust the peer beyond the window we advertised.
+                    YAMUX_TYPE_DATA if len > YAMUX_INITIAL_WINDOW => return Err(frame_too_long()),
+                    YAMUX_TYPE_DATA => frame.data_len = len as usize,
+                    YAMUX_TYPE_WINDOW_UPDATE => frame.window_delta = len,
+                    YAMUX_TYPE_PING => {
+                        // Acknowledgements of our own pings need no action.
+                        if flags & YAMUX_FLAG_SYN != 0 {
+                            frame.ping = Some(len);
+                        }
+                    }
+                    YAMUX_TYPE_GO_AWAY => frame.go_away = true,
+                    _ => return Err(invalid_header()),
+                }
+            }
+        }
+        Ok(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_smux_data_roundtrip() {
+        let mut buf = vec![];
+        MuxProtocol::Smux.encode_data(3, b"abc", &mut buf);
+        assert_eq!(buf, [1, 2, 3, 0, 3, 0, 0, 0, b'a', b'b', b'c']);
+        let header = MuxProtocol::Smux.decode_header(&buf[..8]).unwrap();
+        assert_eq!(
+            header,
+            FrameHeader {
+                sid: 3,
+                data_len: 3,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_yamux_headers() {
+        let mut buf = vec![];
+        MuxProtocol::Yamux.encode_open(1, &mut buf);
+        assert_eq!(buf, [0, 1, 0, 1, 0, 0, 0, 1, 0, 0, 0, 0]);
+
+        let fin_with_data = [0, 0, 0, 4, 0, 0, 0, 1, 0, 0, 0, 5];
+        let header = MuxProtocol::Yamux.decode_header(&fin_with_data).unwrap();
+        assert_eq!((header.sid, header.data_len, header.fin), (1, 5, true));
+
+        let ping = [0, 2, 0, 1, 0, 0, 0, 0, 0, 0, 0, 42];
+        let header = MuxProtocol::Yamux.decode_header(&ping).unwrap();
+        assert_eq!(header.ping, Some(42));
+        let mut ack = vec![];
+        MuxProtocol::Yamux.encode_ping_ack(42, &mut ack);
+        assert_eq!(ack, [0, 2, 0, 2, 0, 0, 0, 0, 0, 0, 0, 42]);
+
+        assert!(MuxProtocol::Yamux
+            .decode_header(&[1, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0])
+            .is_err());
+    }
+
+    #[test]
+    fn test_yamux_data_len_limit() {
+        let mut header = [0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0];
+        header[8..].copy_from_slice(&YAMUX_INITIAL_WINDOW.to_be_bytes());
+        let frame = MuxProtocol::Yamux.decode_header(&header).unwrap();
+        assert_eq!(frame.data_len, YAMUX_INITIAL_WINDOW as usize);
+
+        header[8..].copy_from_slice(&u32::MAX.to_be_bytes());
+        let err = MuxProtocol::Yamux.decode_header(&header).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
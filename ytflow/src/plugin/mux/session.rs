@@ -0,0 +1,402 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{mpsc, Notify};
+use tokio::time::Instant;
+
+use super::frame::{MAX_DATA_LEN, YAMUX_INITIAL_WINDOW};
+use super::stream::MuxStream;
+use super::MuxProtocol;
+use crate::flow::*;
+
+/// Data frames waiting in the writer queue of a session.
+const DATA_QUEUE_LEN: usize = 16;
+/// Stop reading from the connection once streams have this many bytes not yet
+/// consumed. Only matters for smux, which has no flow control.
+const MAX_SESSION_BUFFER: usize = 4 * 1024 * 1024;
+const SESSION_VERSION: u8 = 0;
+
+struct StreamState {
+    rx_chunks: VecDeque<Buffer>,
+    rx_eof: bool,
+    reset: bool,
+    rx_waker: Option<Waker>,
+    send_window: u32,
+    tx_waker: Option<Waker>,
+}
+
+impl StreamState {
+    fn wake_all(&mut self) {
+        if let Some(waker) = self.rx_waker.take() {
+            waker.wake();
+        }
+        if let Some(waker) = self.tx_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+struct SessionState {
+    next_sid: u32,
+    streams: BTreeMap<u32, StreamState>,
+    /// Bytes received but not yet consumed by any stream.
+    buffered: usize,
+    closed: bool,
+    /// The server asked not to open new streams.
+    draining: bool,
+    idle_since: Instant,
+}
+
+/// A connection carrying multiplexed streams. It is closed when the underlying
+/// connection fails, or after having no streams for a while.
+pub(super) struct MuxSession {
+    pub(super) protocol: MuxProtocol,
+    state: Mutex<SessionState>,
+    pub(super) data_tx: mpsc::Sender<Buffer>,
+    ctrl_tx: mpsc::UnboundedSender<Buffer>,
+    rx_drained: Notify,
+}
+
+fn broken_pipe() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "Mux session closed")
+}
+
+impl MuxSession {
+    pub(super) fn start(
+        protocol: MuxProtocol,
+        lower: Box<dyn Stream>,
+        initial_data: Buffer,
+        idle_timeout: Duration,
+    ) -> Arc<Self> {
+        let (data_tx, data_rx) = mpsc::channel(DATA_QUEUE_LEN);
+        let (ctrl_tx, ctrl_rx) = mpsc::unbounded_channel();
+        let session = Arc::new(Self {
+            protocol,
+            state: Mutex::new(SessionState {
+                // Streams opened by clients have odd IDs.
+                next_sid: 1,
+                streams: BTreeMap::new(),
+                buffered: 0,
+                closed: false,
+                draining: false,
+                idle_since: Instant::now(),
+            }),
+            data_tx,
+            ctrl_tx,
+            rx_drained: Notify::new(),
+        });
+        let lower = CompatStream {
+            inner: lower,
+            reader: StreamReader::new(4096, initial_data),
+        };
+        tokio::spawn(session.clone().drive(lower, data_rx, ctrl_rx, idle_timeout));
+        session
+    }
+
+    /// Whether new streams can be opened in this session.
+    pub(super) fn is_available(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        !state.closed && !state.draining
+    }
+
+    pub(super) fn stream_count(&self) -> usize {
+        self.state.lock().unwrap().streams.len()
+    }
+
+    pub(super) fn open_stream(self: &Arc<Self>) -> io::Result<MuxStream> {
+        let sid = {
+            let mut state = self.state.lock().unwrap();
+            if state.closed {
+                return Err(broken_pipe());
+            }
+            let sid = state.next_sid;
+            state.next_sid = sid.wrapping_add(2);
+            state.streams.insert(
+                sid,
+                StreamState {
+                    rx_chunks: VecDeque::new(),
+                    rx_eof: false,
+                    reset: false,
+                    rx_waker: None,
+                    send_window: YAMUX_INITIAL_WINDOW,
+                    tx_waker: None,
+                },
+            );
+            sid
+        };
+        self.send_ctrl(|buf| self.protocol.encode_open(sid, buf));
+        Ok(MuxStream::new(self.clone(), sid))
+    }
+
+    /// Queue a control frame, which may overtake queued data frames.
+    pub(super) fn send_ctrl(&self, encode: impl FnOnce(&mut Buffer)) {
+        let mut buf = Vec::with_capacity(self.protocol.header_len());
+        encode(&mut buf);
+        if !buf.is_empty() {
+            let _ = self.ctrl_tx.send(buf);
+        }
+    }
+
+    /// Take the next chunk of data received by a stream. `None` means the
+    /// server has closed the stream.
+    pub(super) fn poll_rx_chunk(
+        &self,
+        sid: u32,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<Option<Buffer>>> {
+        let mut state = self.state.lock().unwrap();
+        let SessionState {
+            streams,
+            buffered,
+            closed,
+            ..
+        } = &mut *state;
+        let Some(stream) = streams.get_mut(&sid) else {
+            return Poll::Ready(Err(broken_pipe()));
+        };
+        if let Some(chunk) = stream.rx_chunks.pop_front() {
+            let was_full = *buffered >= MAX_SESSION_BUFFER;
+            *buffered -= chunk.len();
+            if was_full && *buffered < MAX_SESSION_BUFFER {
+                self.rx_drained.notify_waiters();
+            }
+            return Poll::Ready(Ok(Some(chunk)));
+        }
+        if stream.reset {
+            return Poll::Ready(Err(io::ErrorKind::ConnectionReset.into()));
+        }
+        if stream.rx_eof {
+            return Poll::Ready(Ok(None));
+        }
+        if *closed {
+            return Poll::Ready(Err(broken_pipe()));
+        }
+        stream.rx_waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+
+    /// Reserve up to `len` bytes of the send window of a stream.
+    pub(super) fn poll_send_window(
+        &self,
+        sid: u32,
+        cx: &mut Context<'_>,
+        len: usize,
+    ) -> Poll<io::Result<usize>> {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return Poll::Ready(Err(broken_pipe()));
+        }
+        let Some(stream) = state.streams.get_mut(&sid) else {
+            return Poll::Ready(Err(broken_pipe()));
+        };
+        if stream.reset {
+            return Poll::Ready(Err(io::ErrorKind::ConnectionReset.into()));
+        }
+        let len = len.min(MAX_DATA_LEN);
+        if let MuxProtocol::Smux = self.protocol {
+            return Poll::Ready(Ok(len));
+        }
+        if stream.send_window == 0 {
+            stream.tx_waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        let len = len.min(stream.send_window as usize);
+        stream.send_window -= len as u32;
+        Poll::Ready(Ok(len))
+    }
+
+    /// Forget a stream, resetting it unless both directions have been closed
+    /// cleanly.
+    pub(super) fn release_stream(&self, sid: u32, fin_sent: bool) {
+        let mut state = self.state.lock().unwrap();
+        let Some(stream) = state.streams.remove(&sid) else {
+            return;
+        };
+        state.buffered -= stream.rx_chunks.iter().map(|c| c.len()).sum::<usize>();
+        if state.streams.is_empty() {
+            state.idle_since = Instant::now();
+        }
+        let closed = state.closed;
+        drop(state);
+        self.rx_drained.notify_waiters();
+        let clean = match self.protocol {
+            MuxProtocol::Smux => fin_sent,
+            MuxProtocol::Yamux => fin_sent && stream.rx_eof,
+        };
+        if !clean && !closed && !stream.reset {
+            self.send_ctrl(|buf| self.protocol.encode_reset(sid, buf));
+        }
+    }
+
+    async fn drive(
+        self: Arc<Self>,
+        lower: CompatStream,
+        data_rx: mpsc::Receiver<Buffer>,
+        ctrl_rx: mpsc::UnboundedReceiver<Buffer>,
+        idle_timeout: Duration,
+    ) {
+        let (mut reader, mut writer) = tokio::io::split(lower);
+        let _res = tokio::select! {
+            res = self.read_loop(&mut reader) => res,
+            res = self.write_loop(&mut writer, data_rx, ctrl_rx) => res,
+            _ = self.wait_idle(idle_timeout) => Ok(()),
+        };
+        // TODO: log error
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        state.streams.values_mut().for_each(StreamState::wake_all);
+        drop(state);
+        self.rx_drained.notify_waiters();
+    }
+
+    async fn read_loop(&self, reader: &mut (impl AsyncRead + Unpin)) -> io::Result<()> {
+        let mut header = vec![0; self.protocol.header_len()];
+        loop {
+            loop {
+                let drained = self.rx_drained.notified();
+                if self.state.lock().unwrap().buffered < MAX_SESSION_BUFFER {
+                    break;
+                }
+                drained.await;
+            }
+            reader.read_exact(&mut header).await?;
+            let frame = self.protocol.decode_header(&header)?;
+            let mut data = vec![0; frame.data_len];
+            reader.read_exact(&mut data).await?;
+            if let Some(opaque) = frame.ping {
+                self.send_ctrl(|buf| self.protocol.encode_ping_ack(opaque, buf));
+            }
+
+            let mut state = self.state.lock().unwrap();
+            if frame.go_away {
+                state.draining = true;
+            }
+            let data_len = data.len();
+            // Frames of streams already released are dropped.
+            let Some(stream) = state.streams.get_mut(&frame.sid) else {
+                continue;
+            };
+            if frame.window_delta > 0 {
+                stream.send_window = stream.send_window.saturating_add(frame.window_delta);
+                if let Some(waker) = stream.tx_waker.take() {
+                    waker.wake();
+                }
+            }
+            if data_len > 0 {
+                stream.rx_chunks.push_back(data);
+                if let Some(waker) = stream.rx_waker.take() {
+                    waker.wake();
+                }
+            }
+            if frame.fin {
+                stream.rx_eof = true;
+                if let Some(waker) = stream.rx_waker.take() {
+                    waker.wake();
+                }
+            }
+            if frame.rst {
+                stream.reset = true;
+                stream.wake_all();
+            }
+            state.buffered += data_len;
+        }
+    }
+
+    async fn write_loop(
+        &self,
+        writer: &mut (impl AsyncWrite + Unpin),
+        mut data_rx: mpsc::Receiver<Buffer>,
+        mut ctrl_rx: mpsc::UnboundedReceiver<Buffer>,
+    ) -> io::Result<()> {
+        let protocol_id = match self.protocol {
+            MuxProtocol::Smux => 0,
+            MuxProtocol::Yamux => 1,
+        };
+        writer.write_all(&[SESSION_VERSION, protocol_id]).await?;
+        loop {
+            let buf = tokio::select! {
+                biased;
+                Some(buf) = ctrl_rx.recv() => buf,
+                Some(buf) = data_rx.recv() => buf,
+                else => return Ok(()),
+            };
+            writer.write_all(&buf).await?;
+            // Coalesce frames queued in the meantime into one flush.
+            while let Ok(buf) = ctrl_rx.try_recv().or_else(|_| data_rx.try_recv()) {
+                writer.write_all(&buf).await?;
+            }
+            writer.flush().await?;
+        }
+    }
+
+    async fn wait_idle(&self, idle_timeout: Duration) {
+        loop {
+            let deadline = {
+                let state = self.state.lock().unwrap();
+                if state.streams.is_empty() {
+                    if state.idle_since.elapsed() >= idle_timeout {
+                        return;
+                    }
+                    state.idle_since + idle_timeout
+                } else {
+                    Instant::now() + idle_timeout
+                }
+            };
+            tokio::time::sleep_until(deadline).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future::poll_fn;
+    use tokio::io::DuplexStream;
+
+    use super::*;
+    use crate::flow::testing::stream_pair;
+
+    async fn yamux_session() -> (Arc<MuxSession>, MuxStream, DuplexStream) {
+        let (lower, mut peer) = stream_pair();
+        let session = MuxSession::start(MuxProtocol::Yamux, lower, vec![], Duration::from_secs(60));
+        let stream = session.open_stream().unwrap();
+        // Session preamble and the SYN of the stream.
+        let mut preamble = [0; 14];
+        peer.read_exact(&mut preamble).await.unwrap();
+        assert_eq!(&preamble[..2], &[SESSION_VERSION, 1]);
+        (session, stream, peer)
+    }
+
+    #[tokio::test]
+    async fn test_read_loop_data_and_fin() {
+        let (session, _stream, mut peer) = yamux_session().await;
+        let mut frame = vec![];
+        MuxProtocol::Yamux.encode_data(1, b"hello", &mut frame);
+        MuxProtocol::Yamux.encode_fin(1, &mut frame);
+        peer.write_all(&frame).await.unwrap();
+
+        let chunk = poll_fn(|cx| session.poll_rx_chunk(1, cx)).await.unwrap();
+        assert_eq!(chunk.as_deref(), Some(&b"hello"[..]));
+        let chunk = poll_fn(|cx| session.poll_rx_chunk(1, cx)).await.unwrap();
+        assert_eq!(chunk, None);
+        assert_eq!(session.state.lock().unwrap().buffered, 0);
+    }
+
+    #[tokio::test]
+    async fn test_read_loop_rejects_oversized_frame() {
+        let (session, _stream, mut peer) = yamux_session().await;
+        // A data frame claiming 4 GiB of payload.
+        peer.write_all(&[0, 0, 0, 0, 0, 0, 0, 1, 0xff, 0xff, 0xff, 0xff])
+            .await
+            .unwrap();
+
+        let err = poll_fn(|cx| session.poll_rx_chunk(1, cx))
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+        assert!(!session.is_available());
+    }
+}
@@ -0,0 +1,186 @@
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_util::sync::PollSender;
+
+use super::frame::YAMUX_INITIAL_WINDOW;
+use super::session::MuxSession;
+use super::MuxProtocol;
+use crate::flow::*;
+
+const STATUS_SUCCESS: u8 = 0;
+const STATUS_ERROR: u8 = 1;
+
+/// A logical stream in a mux session. The server prepends a status byte to the
+/// data it sends back, which is stripped here.
+pub(super) struct MuxStream {
+    session: Arc<MuxSession>,
+    sid: u32,
+    data_tx: PollSender<Buffer>,
+    rx_chunk: Option<(Buffer, usize)>,
+    /// Bytes consumed since the last window update.
+    rx_unacked: u32,
+    awaiting_status: bool,
+    fin_sent: bool,
+}
+
+fn closed_err() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "Mux session closed")
+}
+
+/// Parse the error message following [`STATUS_ERROR`] in a stream response.
+fn parse_status_error(data: &[u8]) -> io::Error {
+    let mut len = 0usize;
+    let mut pos = 0;
+    // The length is encoded as an unsigned varint.
+    while let Some(&b) = data.get(pos) {
+        len |= ((b & 0x7f) as usize) << (7 * pos);
+        pos += 1;
+        if b & 0x80 == 0 || pos >= 4 {
+            break;
+        }
+    }
+    let end = data.len().min(pos + len);
+    let msg = String::from_utf8_lossy(&data[pos.min(end)..end]);
+    io::Error::new(
+        io::ErrorKind::ConnectionRefused,
+        format!("Mux server refused the stream: {}", msg),
+    )
+}
+
+impl MuxStream {
+    pub(super) fn new(session: Arc<MuxSession>, sid: u32) -> Self {
+        Self {
+            data_tx: PollSender::new(session.data_tx.clone()),
+            session,
+            sid,
+            rx_chunk: None,
+            rx_unacked: 0,
+            awaiting_status: true,
+            fin_sent: false,
+        }
+    }
+
+    fn ack_rx(&mut self, len: usize) {
+        if let MuxProtocol::Smux = self.session.protocol {
+            return;
+        }
+        self.rx_unacked += len as u32;
+        if self.rx_unacked >= YAMUX_INITIAL_WINDOW / 2 {
+            let (sid, delta) = (self.sid, std::mem::take(&mut self.rx_unacked));
+            let protocol = self.session.protocol;
+            self.session
+                .send_ctrl(|buf| protocol.encode_window_update(sid, delta, buf));
+        }
+    }
+}
+
+impl AsyncRead for MuxStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        loop {
+            if let Some((chunk, offset)) = &mut this.rx_chunk {
+                let len = buf.remaining().min(chunk.len() - *offset);
+                buf.put_slice(&chunk[*offset..*offset + len]);
+                *offset += len;
+                if *offset == chunk.len() {
+                    this.rx_chunk = None;
+                }
+                return Poll::Ready(Ok(()));
+            }
+            let Some(chunk) = ready!(this.session.poll_rx_chunk(this.sid, cx))? else {
+                return Poll::Ready(Ok(()));
+            };
+            this.ack_rx(chunk.len());
+            let mut offset = 0;
+            if std::mem::take(&mut this.awaiting_status) {
+                match chunk[0] {
+                    STATUS_SUCCESS => offset = 1,
+                    STATUS_ERROR => return Poll::Ready(Err(parse_status_error(&chunk[1..]))),
+                    _ => {
+                        return Poll::Ready(Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "Invalid mux stream status",
+                        )))
+                    }
+                }
+            }
+            if offset < chunk.len() {
+                this.rx_chunk = Some((chunk, offset));
+            }
+        }
+    }
+}
+
+impl AsyncWrite for MuxStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        let this = &mut *self;
+        ready!(this.data_tx.poll_reserve(cx)).map_err(|_| closed_err())?;
+        let len = match this.session.poll_send_window(this.sid, cx, buf.len()) {
+            Poll::Ready(Ok(len)) => len,
+            res => {
+                this.data_tx.abort_send();
+                return res;
+            }
+        };
+        let protocol = this.session.protocol;
+        let mut frame = Vec::with_capacity(protocol.header_len() + len);
+        protocol.encode_data(this.sid, &buf[..len], &mut frame);
+        this.data_tx.send_item(frame).map_err(|_| closed_err())?;
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        // The session flushes queued frames on its own.
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        if this.fin_sent {
+            return Poll::Ready(Ok(()));
+        }
+        ready!(this.data_tx.poll_reserve(cx)).map_err(|_| closed_err())?;
+        let mut frame = vec![];
+        this.session.protocol.encode_fin(this.sid, &mut frame);
+        this.data_tx.send_item(frame).map_err(|_| closed_err())?;
+        this.fin_sent = true;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for MuxStream {
+    fn drop(&mut self) {
+        self.session.release_stream(self.sid, self.fin_sent);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_status_error() {
+        let err = parse_status_error(b"\x05hello");
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+        assert!(err.to_string().ends_with(": hello"));
+        // Truncated messages are kept as is
+        assert!(parse_status_error(b"\x09bad")
+            .to_string()
+            .ends_with(": bad"));
+    }
+}
@@ -1,19 +1,19 @@
 // Windows does not provide per-link hostname resolution.
 // On Linux, fallback to resolver when sytemd-resolved is not available.
-#[cfg(all(feature = "plugins", any(windows, target_os = "linux")))]
+#[cfg(all(feature = "netif", any(windows, target_os = "linux")))]
 mod resolver;
-#[cfg(feature = "plugins")]
+#[cfg(feature = "netif")]
 mod responder;
-#[cfg(feature = "plugins")]
+#[cfg(feature = "netif")]
 mod selector;
-#[cfg(feature = "plugins")]
+#[cfg(feature = "netif")]
 mod sys;
 
 use serde::{Deserialize, Serialize};
 
-#[cfg(feature = "plugins")]
+#[cfg(feature = "netif")]
 pub use responder::Responder;
-#[cfg(feature = "plugins")]
+#[cfg(feature = "netif")]
 pub use selector::NetifSelector;
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -21,6 +21,12 @@ pub use selector::NetifSelector;
 pub enum SelectionMode {
     Auto,
     Manual(String),
+    /// Use `preferred` for direct traffic, and switch to `alternate` when
+    /// connecting through `preferred` fails.
+    Failover {
+        preferred: String,
+        alternate: String,
+    },
 }
 
 #[derive(Clone, Copy, Serialize, Deserialize)]
@@ -2,6 +2,7 @@ use std::net::IpAddr;
 use std::sync::{Arc, Weak};
 
 use arc_swap::AsRaw;
+use http::Uri;
 use tokio::sync::RwLock;
 
 use super::{FamilyPreference, NetifSelector};
@@ -14,6 +15,7 @@ pub(super) struct NetifHostResolver {
         usize,
         Vec<Arc<dyn StreamOutboundFactory>>,
         Vec<Arc<dyn DatagramSessionFactory>>,
+        Vec<String>,
     )>,
     selector: Weak<NetifSelector>,
 }
@@ -21,7 +23,7 @@ pub(super) struct NetifHostResolver {
 impl NetifHostResolver {
     pub fn new(selector: Weak<NetifSelector>) -> Self {
         Self {
-            inner: RwLock::new((HostResolver::new([], []), 0, vec![], vec![])),
+            inner: RwLock::new((HostResolver::new([], []), 0, vec![], vec![], vec![])),
             selector,
         }
     }
@@ -47,39 +49,122 @@ impl NetifHostResolver {
         }
 
         let preference = selector.selection.load().1;
-        let servers = netif.dns_servers().await;
-        let servers = servers.iter().cloned().filter(|dns| match preference {
+        let matches_preference = |ip: &IpAddr| match preference {
             FamilyPreference::Both => true,
-            FamilyPreference::Ipv4Only => dns.is_ipv4(),
-            FamilyPreference::Ipv6Only => dns.is_ipv6(),
-        });
-        let (resolver, tcp_next, udp_next) = create_host_resolver(self.selector.clone(), servers);
-        *guard = (resolver, new_ptr, tcp_next, udp_next);
+            FamilyPreference::Ipv4Only => ip.is_ipv4(),
+            FamilyPreference::Ipv6Only => ip.is_ipv6(),
+        };
+        let servers = netif.dns_servers().await;
+        let servers = servers.iter().cloned().filter(matches_preference);
+        let doh_templates = netif.doh_templates().await;
+        let doh_templates = doh_templates
+            .iter()
+            .filter(|(server, _)| matches_preference(server))
+            .cloned();
+        let suffixes = netif.dns_search_suffixes().await.to_vec();
+        let (resolver, tcp_next, udp_next) = create_host_resolver(
+            self.selector.clone(),
+            self.selector.clone(),
+            servers,
+            doh_templates,
+        );
+        *guard = (resolver, new_ptr, tcp_next, udp_next, suffixes);
     }
 
     pub async fn resolve_ipv4(&self, domain: String) -> ResolveResultV4 {
         self.ensure_up_to_date().await;
         let guard = self.inner.read().await;
+        for candidate in complete_with_suffixes(&domain, &guard.4) {
+            match guard.0.resolve_ipv4(candidate).await {
+                Ok(res) if !res.is_empty() => return Ok(res),
+                _ => continue,
+            }
+        }
         guard.0.resolve_ipv4(domain).await
     }
     pub async fn resolve_ipv6(&self, domain: String) -> ResolveResultV6 {
         self.ensure_up_to_date().await;
         let guard = self.inner.read().await;
+        for candidate in complete_with_suffixes(&domain, &guard.4) {
+            match guard.0.resolve_ipv6(candidate).await {
+                Ok(res) if !res.is_empty() => return Ok(res),
+                _ => continue,
+            }
+        }
         guard.0.resolve_ipv6(domain).await
     }
 }
 
+/// Yields `domain.suffix` for each DNS search suffix of the link if `domain` is a single-label
+/// name. The bare name is left to the caller as the last resort.
+fn complete_with_suffixes<'a>(
+    domain: &'a str,
+    suffixes: &'a [String],
+) -> impl Iterator<Item = String> + 'a {
+    let single_label = !domain.is_empty() && !domain.contains('.');
+    suffixes
+        .iter()
+        .filter(move |_| single_label)
+        .map(move |suffix| format!("{}.{}", domain, suffix.trim_matches('.')))
+}
+
+fn parse_doh_template(template: &str) -> Option<Uri> {
+    // Templates may come in the form of RFC 8484 URI Templates, e.g.
+    // https://dns.example/dns-query{?dns}
+    let template = template
+        .split_once('{')
+        .map_or(template, |(base, _)| base)
+        .trim();
+    let url: Uri = template.parse().ok()?;
+    (url.scheme_str() == Some("https") && url.host().is_some()).then_some(url)
+}
+
 fn create_host_resolver(
+    tcp_next: Weak<dyn StreamOutboundFactory>,
     udp_next: Weak<dyn DatagramSessionFactory>,
     servers: impl IntoIterator<Item = IpAddr>,
+    doh_templates: impl IntoIterator<Item = (IpAddr, String)>,
 ) -> (
     HostResolver,
     Vec<Arc<dyn StreamOutboundFactory>>,
     Vec<Arc<dyn DatagramSessionFactory>>,
 ) {
-    // TODO: tcp factories
+    let mut tcp_factories: Vec<Arc<dyn StreamOutboundFactory>> = vec![];
     let mut udp_factories = vec![];
     let mut weak_udp_factories = vec![];
+    let mut doh_factories = vec![];
+
+    for (server, template) in doh_templates {
+        let Some(url) = parse_doh_template(&template) else {
+            continue;
+        };
+        // Connect to the server IP directly so that DoH requests never rely on the resolver
+        // being constructed.
+        let remote_peer = DestinationAddr {
+            host: HostName::Ip(server),
+            port: url.port_u16().unwrap_or(443),
+        };
+        let redirect = Arc::new(crate::plugin::redirect::StreamRedirectOutboundFactory {
+            remote_peer: move || remote_peer.clone(),
+            next: tcp_next.clone(),
+        });
+        let tls = Arc::new(crate::plugin::tls::SslStreamFactory::new(
+            Arc::downgrade(&redirect) as _,
+            vec!["h2", "http/1.1"],
+            false,
+            url.host().map(|h| h.to_string()),
+            DEFAULT_HANDSHAKE_TIMEOUT,
+        ));
+        doh_factories.push(
+            crate::plugin::host_resolver::doh_adapter::DohDatagramAdapterFactory::new(
+                url,
+                false,
+                Arc::downgrade(&tls) as _,
+            ),
+        );
+        tcp_factories.push(redirect);
+        tcp_factories.push(tls);
+    }
 
     for server in servers {
         let remote_peer = DestinationAddr {
@@ -95,8 +180,8 @@ fn create_host_resolver(
     }
 
     (
-        HostResolver::new(weak_udp_factories, []),
-        vec![],
+        HostResolver::new(weak_udp_factories, doh_factories),
+        tcp_factories,
         udp_factories,
     )
 }
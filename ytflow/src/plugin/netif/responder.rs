@@ -14,6 +14,7 @@ struct Info<'a> {
     selection: &'a super::SelectionMode,
     preference: super::FamilyPreference,
     netif: &'a super::sys::Netif,
+    failed_over: bool,
 }
 
 #[derive(Deserialize)]
@@ -45,6 +46,7 @@ impl PluginResponder for Responder {
             selection: &selection.0,
             preference: selection.1,
             netif: &netif,
+            failed_over: self.selector.is_failed_over(),
         };
         Some(to_vec(vec![], &info).unwrap())
     }
@@ -56,6 +58,7 @@ impl PluginResponder for Responder {
                 self.selector
                     .selection
                     .store(Arc::new((info.selection, info.preference)));
+                *self.selector.preferred_failed_at.lock().unwrap() = None;
                 self.selector.update();
                 vec![]
             }
@@ -1,17 +1,109 @@
-use std::sync::{Arc, Weak};
+use std::future::Future;
+use std::net::{Ipv6Addr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
 use arc_swap::ArcSwap;
 use async_trait::async_trait;
+use futures::future::BoxFuture;
+use futures::ready;
 
 use super::*;
+use crate::control::events::{Event, EventBus};
+use crate::control::KillSwitch;
 use crate::flow::*;
 
+/// How long to stay on the alternate netif before trying the preferred one again.
+const FAILOVER_RECHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Whether `e` was caused by the local interface, e.g. it went down or lost its
+/// address, rather than by the remote end refusing or timing out.
+fn is_local_netif_error(e: &FlowError) -> bool {
+    let FlowError::Io(e) = e else {
+        return false;
+    };
+    if e.kind() == std::io::ErrorKind::AddrNotAvailable {
+        return true;
+    }
+    let Some(code) = e.raw_os_error() else {
+        return false;
+    };
+    #[cfg(unix)]
+    {
+        [libc::ENETDOWN, libc::ENETUNREACH, libc::ENODEV, libc::ENXIO].contains(&code)
+    }
+    #[cfg(windows)]
+    {
+        use windows::Win32::Networking::WinSock::{WSAENETDOWN, WSAENETUNREACH};
+        [WSAENETDOWN.0, WSAENETUNREACH.0].contains(&code)
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = code;
+        false
+    }
+}
+
+/// Whether sockets bound to `netif` can be routed out, checked by connecting
+/// UDP sockets to documentation addresses without sending anything.
+fn is_netif_routable(netif: &sys::Netif, preference: FamilyPreference) -> bool {
+    let try_route =
+        |domain, bind: fn(&sys::Netif, &mut socket2::Socket) -> FlowResult<()>, dst: SocketAddr| {
+            let Ok(mut socket) =
+                socket2::Socket::new(domain, socket2::Type::DGRAM, Some(socket2::Protocol::UDP))
+            else {
+                return false;
+            };
+            bind(netif, &mut socket).is_ok() && socket.connect(&dst.into()).is_ok()
+        };
+    let v4 = || {
+        try_route(
+            socket2::Domain::IPV4,
+            sys::bind_socket_v4,
+            SocketAddr::from(([192, 0, 2, 1], 9)),
+        )
+    };
+    let v6 = || {
+        try_route(
+            socket2::Domain::IPV6,
+            sys::bind_socket_v6,
+            SocketAddr::from((Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1), 9)),
+        )
+    };
+    match preference {
+        FamilyPreference::Both => v4() || v6(),
+        FamilyPreference::Ipv4Only => v4(),
+        FamilyPreference::Ipv6Only => v6(),
+    }
+}
+
+/// Whether failover mode should avoid the preferred netif: it failed recently,
+/// or one of the probers checking it reports it down.
+fn preferred_is_down(
+    failed_at: Option<Instant>,
+    kill_switch: &KillSwitch,
+    probers: &[String],
+) -> bool {
+    failed_at.is_some() || probers.iter().any(|p| !kill_switch.is_healthy(p))
+}
+
 pub struct NetifSelector {
     pub(super) selection: ArcSwap<(SelectionMode, FamilyPreference)>,
     pub(super) cached_netif: ArcSwap<sys::Netif>,
     provider: sys::NetifProvider,
     resolver: sys::Resolver,
     outbound_resolver: Option<Weak<dyn Resolver>>,
+    /// When the preferred netif of failover mode last failed.
+    pub(super) preferred_failed_at: Mutex<Option<Instant>>,
+    /// Names of probers checking the preferred netif of failover mode, looked
+    /// up in the kill switch.
+    probers: Vec<String>,
+    probers_were_healthy: AtomicBool,
+    kill_switch: KillSwitch,
+    plugin_name: String,
+    events: EventBus,
     me: Weak<Self>,
 }
 
@@ -19,6 +111,10 @@ impl NetifSelector {
     pub fn new(
         selection: SelectionMode,
         prefer: FamilyPreference,
+        probers: Vec<String>,
+        kill_switch: KillSwitch,
+        plugin_name: String,
+        events: EventBus,
         create_outbound_resolver: impl FnOnce(&Weak<Self>) -> Option<Weak<dyn Resolver>>,
     ) -> Arc<Self> {
         let dummy_netif = sys::Netif {
@@ -42,6 +138,12 @@ impl NetifSelector {
                 provider,
                 resolver: sys::Resolver::new(this.clone()),
                 outbound_resolver,
+                preferred_failed_at: Mutex::new(None),
+                probers,
+                probers_were_healthy: AtomicBool::new(true),
+                kill_switch,
+                plugin_name,
+                events,
                 me: this,
             }
         })
@@ -56,7 +158,14 @@ impl NetifSelector {
         if netif == **guard {
             return;
         }
-        self.cached_netif.compare_and_swap(guard, Arc::new(netif));
+        let name = netif.name.clone();
+        let prev = self.cached_netif.compare_and_swap(&*guard, Arc::new(netif));
+        if Arc::ptr_eq(&prev, &guard) {
+            self.events.publish(Event::NetifChanged {
+                plugin: self.plugin_name.clone(),
+                netif: name,
+            });
+        }
     }
 
     fn pick_netif(&self) -> Option<sys::Netif> {
@@ -65,68 +174,166 @@ impl NetifSelector {
         let netif = match selection {
             SelectionMode::Auto => self.provider.select_best(),
             SelectionMode::Manual(name) => self.provider.select(name.as_str()),
+            SelectionMode::Failover {
+                preferred,
+                alternate,
+            } => {
+                let preferred_failed = preferred_is_down(
+                    *self.preferred_failed_at.lock().unwrap(),
+                    &self.kill_switch,
+                    &self.probers,
+                );
+                let preferred = || self.provider.select(preferred.as_str());
+                let alternate = || self.provider.select(alternate.as_str());
+                if preferred_failed {
+                    alternate().or_else(preferred)
+                } else {
+                    preferred().or_else(alternate)
+                }
+            }
         }?;
         Some(netif)
     }
-}
 
-#[async_trait]
-impl StreamOutboundFactory for NetifSelector {
-    async fn create_outbound(
+    fn preferred_netif_name(&self) -> Option<String> {
+        match &self.selection.load().0 {
+            SelectionMode::Failover { preferred, .. } => Some(preferred.clone()),
+            _ => None,
+        }
+    }
+
+    /// Whether the preferred netif of failover mode is present and routable,
+    /// so that traffic can be switched back to it.
+    fn is_preferred_healthy(&self) -> bool {
+        let selection = self.selection.load();
+        let (SelectionMode::Failover { preferred, .. }, preference) = &**selection else {
+            return true;
+        };
+        self.provider
+            .select(preferred.as_str())
+            .map_or(false, |netif| is_netif_routable(&netif, *preference))
+    }
+
+    /// Give the preferred netif another chance once the re-check interval has
+    /// passed since it failed and it passes a health check, and follow changes
+    /// reported by the probers.
+    fn recheck_preferred(&self) {
+        let probers_healthy = !preferred_is_down(None, &self.kill_switch, &self.probers);
+        if self
+            .probers_were_healthy
+            .swap(probers_healthy, Ordering::Relaxed)
+            != probers_healthy
+        {
+            self.update();
+        }
+        let mut failed_at = self.preferred_failed_at.lock().unwrap();
+        if failed_at.map_or(false, |t| t.elapsed() >= FAILOVER_RECHECK_INTERVAL) {
+            if !self.is_preferred_healthy() {
+                // Stay on the alternate netif for another interval.
+                *failed_at = Some(Instant::now());
+                return;
+            }
+            *failed_at = None;
+            drop(failed_at);
+            self.update();
+        }
+    }
+
+    /// Switch to the alternate netif if `netif` is the preferred one in
+    /// failover mode. Returns whether a switch happened.
+    fn fail_over_from(&self, netif: &sys::Netif) -> bool {
+        if self.preferred_netif_name().as_deref() != Some(netif.name.as_str()) {
+            return false;
+        }
+        *self.preferred_failed_at.lock().unwrap() = Some(Instant::now());
+        self.update();
+        self.cached_netif.load().name != netif.name
+    }
+
+    pub(super) fn is_failed_over(&self) -> bool {
+        matches!(self.selection.load().0, SelectionMode::Failover { .. })
+            && preferred_is_down(
+                *self.preferred_failed_at.lock().unwrap(),
+                &self.kill_switch,
+                &self.probers,
+            )
+    }
+
+    async fn dial_stream_via(
         &self,
+        netif: &sys::Netif,
         context: &mut FlowContext,
         initial_data: &'_ [u8],
     ) -> FlowResult<(Box<dyn Stream>, Buffer)> {
         let preference = self.selection.load().1;
-        let netif = self.cached_netif.load();
         let resolver = self
             .outbound_resolver
             .as_ref()
             .map(|r| r.upgrade().ok_or(FlowError::NoOutbound))
             .transpose()?
             .unwrap_or_else(|| self.me.upgrade().unwrap());
-        crate::plugin::socket::dial_stream(
+        let traffic_class = crate::plugin::socket::traffic_class_for(None, context, false);
+        let dial = crate::plugin::socket::dial_stream(
             context,
             resolver,
             // A workaround for E0308 "one type is more general than the other"
             // https://github.com/rust-lang/rust/issues/70263
-            Some(|s: &mut _| sys::bind_socket_v4(&netif, s)).filter(|_| {
+            Some(|s: &mut _| {
+                if let Some(traffic_class) = traffic_class {
+                    crate::plugin::socket::set_traffic_class(s, false, traffic_class);
+                }
+                sys::bind_socket_v4(netif, s)
+            })
+            .filter(|_| {
                 matches!(
                     preference,
                     FamilyPreference::Both | FamilyPreference::Ipv4Only,
                 )
             }),
-            Some(|s: &mut _| sys::bind_socket_v6(&netif, s)).filter(|_| {
+            Some(|s: &mut _| {
+                if let Some(traffic_class) = traffic_class {
+                    crate::plugin::socket::set_traffic_class(s, true, traffic_class);
+                }
+                sys::bind_socket_v6(netif, s)
+            })
+            .filter(|_| {
                 matches!(
                     preference,
                     FamilyPreference::Both | FamilyPreference::Ipv6Only,
                 )
             }),
             initial_data,
-        )
-        .await
+        );
+        with_flow_deadline(DEFAULT_CONNECT_TIMEOUT, &context.abort.signal(), dial).await
     }
-}
 
-#[async_trait]
-impl DatagramSessionFactory for NetifSelector {
-    async fn bind(&self, context: Box<FlowContext>) -> FlowResult<Box<dyn DatagramSession>> {
+    async fn bind_datagram_via(
+        &self,
+        netif: Arc<sys::Netif>,
+        context: &FlowContext,
+        netif_failed: Arc<AtomicBool>,
+    ) -> FlowResult<Box<dyn DatagramSession>> {
         let preference = self.selection.load().1;
-        let netif = self.cached_netif.load_full();
         let resolver = self
             .outbound_resolver
             .as_ref()
             .map(|r| r.upgrade().ok_or(FlowError::NoOutbound))
             .transpose()?
             .unwrap_or_else(|| self.me.upgrade().unwrap());
+        let traffic_class = crate::plugin::socket::traffic_class_for(None, context, true);
         crate::plugin::socket::dial_datagram_session(
-            &context,
+            context,
             resolver,
             // A workaround for E0308 "one type is more general than the other"
             // https://github.com/rust-lang/rust/issues/70263
             Some({
                 let netif = netif.clone();
-                move |s: &mut _| sys::bind_socket_v4(&netif, s)
+                move |s: &mut _| {
+                    if let Some(traffic_class) = traffic_class {
+                        crate::plugin::socket::set_traffic_class(s, false, traffic_class);
+                    }
+                    sys::bind_socket_v4(&netif, s)
+                }
             })
             .filter(|_| {
                 matches!(
@@ -134,17 +341,166 @@ impl DatagramSessionFactory for NetifSelector {
                     FamilyPreference::Both | FamilyPreference::Ipv4Only,
                 )
             }),
-            Some(move |s: &mut _| sys::bind_socket_v6(&netif, s)).filter(|_| {
+            Some(move |s: &mut _| {
+                if let Some(traffic_class) = traffic_class {
+                    crate::plugin::socket::set_traffic_class(s, true, traffic_class);
+                }
+                sys::bind_socket_v6(&netif, s)
+            })
+            .filter(|_| {
                 matches!(
                     preference,
                     FamilyPreference::Both | FamilyPreference::Ipv6Only,
                 )
             }),
+            false,
+            Some(Arc::new(move |e: &FlowError| {
+                if is_local_netif_error(e) {
+                    netif_failed.store(true, Ordering::Relaxed);
+                }
+            })),
         )
         .await
     }
 }
 
+/// Moves a datagram session to another netif once its sockets fail because of
+/// the local netif, e.g. the preferred one of failover mode went down, so that
+/// long-lived sessions follow the failover instead of dying with the netif.
+struct FailoverDatagramSession {
+    selector: Weak<NetifSelector>,
+    netif: Arc<sys::Netif>,
+    context: Box<FlowContext>,
+    inner: Box<dyn DatagramSession>,
+    netif_failed: Arc<AtomicBool>,
+    rebinding: Option<BoxFuture<'static, FlowResult<(Arc<sys::Netif>, Box<dyn DatagramSession>)>>>,
+}
+
+impl FailoverDatagramSession {
+    /// Start rebinding if the inner session reported an error of the local
+    /// netif and the selector has moved away from it. Returns whether a rebind
+    /// has started.
+    fn start_rebind(&mut self) -> bool {
+        if self.rebinding.is_some() || !self.netif_failed.swap(false, Ordering::Relaxed) {
+            return false;
+        }
+        let Some(selector) = self.selector.upgrade() else {
+            return false;
+        };
+        selector.fail_over_from(&self.netif);
+        let netif = selector.cached_netif.load_full();
+        if *netif == *self.netif {
+            return false;
+        }
+        let context = Box::new(self.context.fork());
+        let netif_failed = self.netif_failed.clone();
+        self.rebinding = Some(Box::pin(async move {
+            let session = selector
+                .bind_datagram_via(netif.clone(), &context, netif_failed)
+                .await?;
+            Ok((netif, session))
+        }));
+        true
+    }
+
+    fn poll_rebinding(&mut self, cx: &mut Context<'_>) -> Poll<FlowResult<()>> {
+        let Some(rebinding) = &mut self.rebinding else {
+            return Poll::Ready(Ok(()));
+        };
+        let res = ready!(rebinding.as_mut().poll(cx));
+        self.rebinding = None;
+        let (netif, inner) = res?;
+        self.netif = netif;
+        self.inner = inner;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl DatagramSession for FailoverDatagramSession {
+    fn poll_recv_from(&mut self, cx: &mut Context) -> Poll<Option<(DestinationAddr, Buffer)>> {
+        loop {
+            if ready!(self.poll_rebinding(cx)).is_err() {
+                return Poll::Ready(None);
+            }
+            match ready!(self.inner.poll_recv_from(cx)) {
+                // The inner session ends on receive errors. Carry on through
+                // another netif if the local one caused it.
+                None if self.start_rebind() => continue,
+                res => return Poll::Ready(res),
+            }
+        }
+    }
+
+    fn poll_send_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        // Datagrams keep going through the failing netif if rebinding fails,
+        // until the receiving side ends the session.
+        let _ = ready!(self.poll_rebinding(cx));
+        ready!(self.inner.poll_send_ready(cx));
+        // The datagram lost to an error of the local netif is not resent, but
+        // the following ones go through the new netif.
+        if self.start_rebind() {
+            let _ = ready!(self.poll_rebinding(cx));
+        }
+        Poll::Ready(())
+    }
+
+    fn send_to(&mut self, remote_peer: DestinationAddr, buf: Buffer) {
+        self.inner.send_to(remote_peer, buf);
+    }
+
+    fn poll_shutdown(&mut self, cx: &mut Context<'_>) -> Poll<FlowResult<()>> {
+        self.rebinding = None;
+        self.inner.poll_shutdown(cx)
+    }
+}
+
+#[async_trait]
+impl StreamOutboundFactory for NetifSelector {
+    async fn create_outbound(
+        &self,
+        context: &mut FlowContext,
+        initial_data: &'_ [u8],
+    ) -> FlowResult<(Box<dyn Stream>, Buffer)> {
+        self.recheck_preferred();
+        let netif = self.cached_netif.load_full();
+        match self.dial_stream_via(&netif, context, initial_data).await {
+            Err(e) if is_local_netif_error(&e) && self.fail_over_from(&netif) => {
+                let netif = self.cached_netif.load_full();
+                self.dial_stream_via(&netif, context, initial_data).await
+            }
+            res => res,
+        }
+    }
+}
+
+#[async_trait]
+impl DatagramSessionFactory for NetifSelector {
+    async fn bind(&self, context: Box<FlowContext>) -> FlowResult<Box<dyn DatagramSession>> {
+        self.recheck_preferred();
+        let mut netif = self.cached_netif.load_full();
+        let netif_failed = Arc::new(AtomicBool::new(false));
+        let inner = match self
+            .bind_datagram_via(netif.clone(), &context, netif_failed.clone())
+            .await
+        {
+            Err(e) if is_local_netif_error(&e) && self.fail_over_from(&netif) => {
+                netif = self.cached_netif.load_full();
+                self.bind_datagram_via(netif.clone(), &context, netif_failed.clone())
+                    .await?
+            }
+            res => res?,
+        };
+        Ok(Box::new(FailoverDatagramSession {
+            selector: self.me.clone(),
+            netif,
+            context,
+            inner,
+            netif_failed,
+            rebinding: None,
+        }))
+    }
+}
+
 #[async_trait]
 impl Resolver for NetifSelector {
     async fn resolve_ipv4(&self, domain: String) -> ResolveResultV4 {
@@ -155,3 +511,61 @@ impl Resolver for NetifSelector {
         self.resolver.resolve_ipv6(domain).await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::*;
+
+    #[test]
+    fn test_local_netif_errors() {
+        let addr_not_available = io::Error::from(io::ErrorKind::AddrNotAvailable);
+        assert!(is_local_netif_error(&addr_not_available.into()));
+        #[cfg(unix)]
+        for code in [libc::ENETDOWN, libc::ENETUNREACH, libc::ENODEV] {
+            assert!(is_local_netif_error(
+                &io::Error::from_raw_os_error(code).into()
+            ));
+        }
+    }
+
+    #[test]
+    fn test_remote_errors_are_not_local() {
+        for kind in [
+            io::ErrorKind::ConnectionRefused,
+            io::ErrorKind::ConnectionReset,
+            io::ErrorKind::TimedOut,
+        ] {
+            assert!(!is_local_netif_error(&io::Error::from(kind).into()));
+        }
+        #[cfg(unix)]
+        for code in [libc::ECONNREFUSED, libc::EHOSTUNREACH, libc::ETIMEDOUT] {
+            assert!(!is_local_netif_error(
+                &io::Error::from_raw_os_error(code).into()
+            ));
+        }
+        assert!(!is_local_netif_error(&FlowError::NoOutbound));
+        assert!(!is_local_netif_error(&FlowError::UnexpectedData));
+    }
+
+    #[test]
+    fn test_failing_prober_demotes_preferred() {
+        let kill_switch = KillSwitch::default();
+        let probers = vec![String::from("wan-prober")];
+        assert!(!preferred_is_down(None, &kill_switch, &probers));
+        assert!(preferred_is_down(
+            Some(Instant::now()),
+            &kill_switch,
+            &probers
+        ));
+
+        kill_switch.report_health("wan-prober", false);
+        assert!(preferred_is_down(None, &kill_switch, &probers));
+        // Other probers do not affect this netif.
+        assert!(!preferred_is_down(None, &kill_switch, &[]));
+
+        kill_switch.report_health("wan-prober", true);
+        assert!(!preferred_is_down(None, &kill_switch, &probers));
+    }
+}
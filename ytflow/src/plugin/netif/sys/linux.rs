@@ -22,12 +22,22 @@ pub struct Netif {
 }
 
 impl Netif {
-    pub async fn dns_servers(&self) -> Vec<IpAddr> {
-        dns::retrieve_all_link_dns_servers()
+    async fn dns_config(&self) -> dns::LinkDnsConfig {
+        dns::retrieve_all_link_dns_configs()
             .await
             .remove(self.bsd_name.to_str().unwrap_or_default())
             .unwrap_or_default()
     }
+    pub async fn dns_servers(&self) -> Vec<IpAddr> {
+        self.dns_config().await.servers
+    }
+    pub async fn dns_search_suffixes(&self) -> Vec<String> {
+        self.dns_config().await.search_domains
+    }
+    /// NetworkManager does not expose DoH settings of a link.
+    pub async fn doh_templates(&self) -> Vec<(IpAddr, String)> {
+        vec![]
+    }
 }
 
 #[derive(Debug)]
@@ -140,7 +140,14 @@ impl Resolver {
     }
 }
 
-pub async fn retrieve_all_link_dns_servers() -> HashMap<String, Vec<IpAddr>> {
+#[derive(Debug, Default)]
+pub struct LinkDnsConfig {
+    pub servers: Vec<IpAddr>,
+    /// Search domains of the link. Routing-only domains (prefixed by `~`) are excluded.
+    pub search_domains: Vec<String>,
+}
+
+pub async fn retrieve_all_link_dns_configs() -> HashMap<String, LinkDnsConfig> {
     let Ok(conn) = init_dbus_system_conn().await else {
         return Default::default();
     };
@@ -165,7 +172,7 @@ pub async fn retrieve_all_link_dns_servers() -> HashMap<String, Vec<IpAddr>> {
         return Default::default();
     };
 
-    let mut res = HashMap::<String, Vec<IpAddr>>::new();
+    let mut res = HashMap::<String, LinkDnsConfig>::new();
     for netif_dict in body.into_iter().filter_map(|v| {
         if let Value::Dict(d) = v {
             Some(d)
@@ -179,7 +186,7 @@ pub async fn retrieve_all_link_dns_servers() -> HashMap<String, Vec<IpAddr>> {
             .flatten()
             .unwrap_or_default()
             .to_string();
-        let servers = res.entry(netif_name).or_default();
+        let config = res.entry(netif_name).or_default();
         let netif_servers = netif_dict
             .get::<_, Array>("nameservers")
             .ok()
@@ -193,7 +200,23 @@ pub async fn retrieve_all_link_dns_servers() -> HashMap<String, Vec<IpAddr>> {
                 }?;
                 IpAddr::from_str(&s).ok()
             });
-        servers.extend(netif_servers);
+        config.servers.extend(netif_servers);
+        let netif_domains = netif_dict
+            .get::<_, Array>("domains")
+            .ok()
+            .flatten()
+            .into_iter()
+            .flat_map(|a| a.to_vec())
+            .filter_map(|v| match v {
+                Value::Str(s) => Some(s.as_str().trim_matches('.').to_string()),
+                _ => None,
+            })
+            .filter(|d| !d.is_empty() && !d.starts_with('~'));
+        for domain in netif_domains {
+            if !config.search_domains.contains(&domain) {
+                config.search_domains.push(domain);
+            }
+        }
     }
     res
 }
@@ -1,3 +1,5 @@
+mod reg;
+
 use std::net::{IpAddr, SocketAddrV4, SocketAddrV6};
 
 use serde::{Serialize, Serializer};
@@ -15,6 +17,11 @@ pub struct Netif {
     /// DNSServiceGetAddrInfo since we can specify which interface to query DNS on.
     #[serde(serialize_with = "serialize_ipaddrs")]
     pub dns_servers: Vec<IpAddr>,
+    /// DNS suffixes appended to single-label names, in the order of preference.
+    pub dns_search_suffixes: Vec<String>,
+    /// DoH templates configured for some of the DNS servers.
+    #[serde(serialize_with = "serialize_doh_templates")]
+    pub doh_templates: Vec<(IpAddr, String)>,
 }
 
 pub(crate) fn serialize_ipaddrs<S>(ipaddrs: &[IpAddr], serializer: S) -> Result<S::Ok, S::Error>
@@ -24,10 +31,26 @@ where
     serializer.collect_seq(ipaddrs.iter().map(|ip| ip.to_string()))
 }
 
+fn serialize_doh_templates<S>(
+    templates: &[(IpAddr, String)],
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.collect_map(templates.iter().map(|(ip, t)| (ip.to_string(), t)))
+}
+
 impl Netif {
     pub async fn dns_servers(&self) -> &[IpAddr] {
         &self.dns_servers
     }
+    pub async fn dns_search_suffixes(&self) -> &[String] {
+        &self.dns_search_suffixes
+    }
+    pub async fn doh_templates(&self) -> &[(IpAddr, String)] {
+        &self.doh_templates
+    }
 }
 
 pub(in super::super) type Resolver = super::super::resolver::NetifHostResolver;
@@ -56,6 +79,12 @@ fn enum_adapters() -> Vec<(Netif, Rate)> {
             } else {
                 Rate::Recommended
             };
+            let adapter_guid = adapter.adapter_name();
+            let doh_templates = adapter
+                .dns_servers()
+                .iter()
+                .filter_map(|&server| Some((server, reg::doh_template(adapter_guid, server)?)))
+                .collect();
             (
                 Netif {
                     name: adapter.friendly_name().to_owned(),
@@ -77,6 +106,8 @@ fn enum_adapters() -> Vec<(Netif, Rate)> {
                         _ => None,
                     }),
                     dns_servers: adapter.dns_servers().to_vec(),
+                    dns_search_suffixes: reg::dns_search_suffixes(adapter_guid),
+                    doh_templates,
                 },
                 rate,
             )
@@ -0,0 +1,108 @@
+use std::net::IpAddr;
+
+use windows::core::HSTRING;
+use windows::Win32::Foundation::ERROR_SUCCESS;
+use windows::Win32::System::Registry::{
+    RegCloseKey, RegGetValueW, RegOpenKeyExW, HKEY, HKEY_LOCAL_MACHINE, KEY_READ, RRF_RT_REG_SZ,
+};
+
+const TCPIP_PARAMETERS: &str = r"SYSTEM\CurrentControlSet\Services\Tcpip\Parameters";
+const DNSCACHE: &str = r"SYSTEM\CurrentControlSet\Services\Dnscache";
+
+fn read_string(subkey: &str, value: &str) -> Option<String> {
+    let subkey = HSTRING::from(subkey);
+    let value = HSTRING::from(value);
+    let mut len = 0u32;
+    unsafe {
+        if RegGetValueW(
+            HKEY_LOCAL_MACHINE,
+            &subkey,
+            &value,
+            RRF_RT_REG_SZ,
+            None,
+            None,
+            Some(&mut len),
+        ) != ERROR_SUCCESS
+        {
+            return None;
+        }
+        let mut buf = vec![0u16; (len as usize + 1) / 2];
+        if RegGetValueW(
+            HKEY_LOCAL_MACHINE,
+            &subkey,
+            &value,
+            RRF_RT_REG_SZ,
+            None,
+            Some(buf.as_mut_ptr() as _),
+            Some(&mut len),
+        ) != ERROR_SUCCESS
+        {
+            return None;
+        }
+        buf.truncate(len as usize / 2);
+        while buf.last() == Some(&0) {
+            buf.pop();
+        }
+        let s = String::from_utf16_lossy(&buf);
+        (!s.trim().is_empty()).then_some(s)
+    }
+}
+
+fn key_exists(subkey: &str) -> bool {
+    let subkey = HSTRING::from(subkey);
+    let mut hkey = HKEY::default();
+    unsafe {
+        if RegOpenKeyExW(HKEY_LOCAL_MACHINE, &subkey, 0, KEY_READ, &mut hkey) != ERROR_SUCCESS {
+            return false;
+        }
+        let _ = RegCloseKey(hkey);
+    }
+    true
+}
+
+/// Collect DNS suffixes of an adapter identified by its GUID, followed by the global search list.
+pub(super) fn dns_search_suffixes(adapter_guid: &str) -> Vec<String> {
+    let if_key = format!(r"{}\Interfaces\{}", TCPIP_PARAMETERS, adapter_guid);
+    let mut suffixes: Vec<String> = vec![];
+    let candidates = [
+        read_string(&if_key, "SearchList"),
+        read_string(&if_key, "Domain"),
+        read_string(&if_key, "DhcpDomain"),
+        read_string(TCPIP_PARAMETERS, "SearchList"),
+    ];
+    for suffix in candidates
+        .iter()
+        .flatten()
+        .flat_map(|l| l.split([',', ' ']))
+        .map(|s| s.trim().trim_matches('.'))
+        .filter(|s| !s.is_empty())
+    {
+        if !suffixes.iter().any(|s| s.eq_ignore_ascii_case(suffix)) {
+            suffixes.push(suffix.to_string());
+        }
+    }
+    suffixes
+}
+
+/// Look up the DoH template the OS associates with a DNS server of an adapter. Windows 11 stores
+/// either a custom template on the interface, or marks the server as auto-upgraded, in which case
+/// the template comes from the list of well-known DoH servers.
+pub(super) fn doh_template(adapter_guid: &str, server: IpAddr) -> Option<String> {
+    let family = match server {
+        IpAddr::V4(_) => "Doh",
+        IpAddr::V6(_) => "Doh6",
+    };
+    let server_key = format!(
+        r"{}\InterfaceSpecificParameters\{}\DohInterfaceSettings\{}\{}",
+        DNSCACHE, adapter_guid, family, server
+    );
+    if !key_exists(&server_key) {
+        return None;
+    }
+    read_string(&server_key, "DohTemplate").or_else(|| {
+        read_string(
+            &format!(r"{}\Parameters\DohWellKnownServers\{}", DNSCACHE, server),
+            "Template",
+        )
+    })
+}
@@ -1,2 +1,3 @@
+pub mod scramble;
 pub mod simple_http;
 pub mod simple_tls;
@@ -0,0 +1,107 @@
+use aes_gcm::aes::Aes256;
+use cipher::{KeyIvInit, StreamCipher};
+use ctr::Ctr128BE;
+use getrandom::getrandom;
+use sha2::{Digest, Sha256};
+
+use crate::flow::*;
+
+const AES_CTR_IV_LEN: usize = 16;
+
+/// XOR every payload with the key repeated. Offers no confidentiality, only
+/// hides plaintext patterns from naive classifiers.
+pub struct XorScramble {
+    key: Vec<u8>,
+}
+
+impl XorScramble {
+    pub fn new(key: &[u8]) -> Self {
+        assert!(!key.is_empty(), "XOR key must not be empty");
+        Self { key: key.to_vec() }
+    }
+
+    fn apply(&self, buf: &mut [u8]) {
+        for (b, k) in buf.iter_mut().zip(self.key.iter().cycle()) {
+            *b ^= k;
+        }
+    }
+}
+
+impl DatagramTransform for XorScramble {
+    fn encode(&self, buf: &mut Buffer) {
+        self.apply(buf)
+    }
+    fn decode(&self, buf: &mut Buffer) -> bool {
+        self.apply(buf);
+        true
+    }
+}
+
+/// Encrypt every payload with AES-256-CTR under a random IV prepended to the
+/// datagram. The key is derived from the shared secret with SHA-256. There is
+/// no integrity check: tampered datagrams are passed on as garbage.
+pub struct AesCtrScramble {
+    key: [u8; 32],
+}
+
+impl AesCtrScramble {
+    pub fn new(secret: &[u8]) -> Self {
+        Self {
+            key: Sha256::digest(secret).into(),
+        }
+    }
+
+    fn apply(&self, iv: &[u8; AES_CTR_IV_LEN], buf: &mut [u8]) {
+        Ctr128BE::<Aes256>::new(&self.key.into(), iv.into()).apply_keystream(buf);
+    }
+}
+
+impl DatagramTransform for AesCtrScramble {
+    fn encode(&self, buf: &mut Buffer) {
+        let mut iv = [0; AES_CTR_IV_LEN];
+        getrandom(&mut iv).unwrap();
+        self.apply(&iv, buf);
+        buf.splice(0..0, iv);
+    }
+    fn decode(&self, buf: &mut Buffer) -> bool {
+        if buf.len() < AES_CTR_IV_LEN {
+            return false;
+        }
+        let iv: [u8; AES_CTR_IV_LEN] = buf[..AES_CTR_IV_LEN].try_into().unwrap();
+        buf.drain(..AES_CTR_IV_LEN);
+        self.apply(&iv, buf);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xor_roundtrip() {
+        let t = XorScramble::new(b"key");
+        let mut buf = b"hello world".to_vec();
+        t.encode(&mut buf);
+        assert_eq!(buf[..3], [b'h' ^ b'k', b'e' ^ b'e', b'l' ^ b'y']);
+        assert!(t.decode(&mut buf));
+        assert_eq!(buf, b"hello world");
+    }
+
+    #[test]
+    fn test_aes_ctr_roundtrip() {
+        let t = AesCtrScramble::new(b"secret");
+        let mut buf = b"hello world".to_vec();
+        t.encode(&mut buf);
+        assert_eq!(buf.len(), AES_CTR_IV_LEN + 11);
+        assert_ne!(&buf[AES_CTR_IV_LEN..], b"hello world");
+        assert!(t.decode(&mut buf));
+        assert_eq!(buf, b"hello world");
+    }
+
+    #[test]
+    fn test_aes_ctr_rejects_short() {
+        let t = AesCtrScramble::new(b"secret");
+        assert!(!t.decode(&mut vec![0; AES_CTR_IV_LEN - 1]));
+    }
+}
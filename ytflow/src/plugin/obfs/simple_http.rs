@@ -2,10 +2,13 @@ use std::sync::{Arc, Weak};
 
 use async_trait::async_trait;
 use base64::prelude::*;
+use http::header::USER_AGENT;
+use http::HeaderMap;
 use memchr::memmem;
 use rand::{thread_rng, RngCore};
 
 use crate::flow::*;
+use crate::plugin::rotation::Rotation;
 
 pub struct SimpleHttpHandler {
     server_line: Arc<[u8]>,
@@ -13,7 +16,10 @@ pub struct SimpleHttpHandler {
 }
 
 pub struct SimpleHttpOutbound {
-    req_line: Arc<[u8]>,
+    paths: Rotation<String>,
+    hosts: Rotation<String>,
+    /// Everything between the Host header and the value of Sec-Websocket-Key.
+    header_lines: Box<[u8]>,
     next: Weak<dyn StreamOutboundFactory>,
 }
 
@@ -34,22 +40,33 @@ impl SimpleHttpHandler {
 }
 
 impl SimpleHttpOutbound {
-    pub fn new(path: &[u8], host: &[u8], next: Weak<dyn StreamOutboundFactory>) -> Self {
-        let mut req_line = Vec::with_capacity(120 + path.len() + host.len());
-        req_line.extend_from_slice(b"GET ");
-        req_line.extend_from_slice(path);
-        req_line.extend_from_slice(b" HTTP/1.1\r\nHost: ");
-        req_line.extend_from_slice(host);
-        req_line.extend_from_slice(b"\r\nUser-Agent: curl/7.");
-        let mut thread_rng = thread_rng();
-        req_line.extend_from_slice((thread_rng.next_u32() % 51).to_string().as_bytes());
-        req_line.push(b'.');
-        req_line.extend_from_slice((thread_rng.next_u32() % 2).to_string().as_bytes());
-        req_line.extend_from_slice(
+    pub fn new(
+        paths: Rotation<String>,
+        hosts: Rotation<String>,
+        headers: HeaderMap,
+        next: Weak<dyn StreamOutboundFactory>,
+    ) -> Self {
+        let mut header_lines = Vec::with_capacity(120);
+        if !headers.contains_key(USER_AGENT) {
+            header_lines.extend_from_slice(b"\r\nUser-Agent: curl/7.");
+            let mut thread_rng = thread_rng();
+            header_lines.extend_from_slice((thread_rng.next_u32() % 51).to_string().as_bytes());
+            header_lines.push(b'.');
+            header_lines.extend_from_slice((thread_rng.next_u32() % 2).to_string().as_bytes());
+        }
+        for (name, value) in &headers {
+            header_lines.extend_from_slice(b"\r\n");
+            header_lines.extend_from_slice(name.as_str().as_bytes());
+            header_lines.extend_from_slice(b": ");
+            header_lines.extend_from_slice(value.as_bytes());
+        }
+        header_lines.extend_from_slice(
             b"\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-Websocket-Key: ",
         );
         Self {
-            req_line: req_line.into(),
+            paths,
+            hosts,
+            header_lines: header_lines.into(),
             next,
         }
     }
@@ -133,8 +150,15 @@ impl StreamOutboundFactory for SimpleHttpOutbound {
             None => return Err(FlowError::UnexpectedData),
         };
         let (mut stream, initial_req) = {
-            let mut req = Vec::with_capacity(self.req_line.len() + 120);
-            req.extend_from_slice(&self.req_line);
+            let (path, host) = (self.paths.pick(), self.hosts.pick());
+            let mut req = Vec::with_capacity(
+                path.len() + host.len() + self.header_lines.len() + 120 + initial_data.len(),
+            );
+            req.extend_from_slice(b"GET ");
+            req.extend_from_slice(path.as_bytes());
+            req.extend_from_slice(b" HTTP/1.1\r\nHost: ");
+            req.extend_from_slice(host.as_bytes());
+            req.extend_from_slice(&self.header_lines);
             let mut ws_key = [0; 16];
             thread_rng().fill_bytes(&mut ws_key);
             let mut b64 = [0; 32];
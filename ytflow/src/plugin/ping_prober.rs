@@ -0,0 +1,8 @@
+mod gate;
+mod icmp;
+mod prober;
+mod responder;
+
+pub use gate::{DatagramGateFactory, StreamGateFactory};
+pub use prober::{ProbeMethod, ProbeMode, Prober, TargetStatus};
+pub use responder::Responder;
@@ -0,0 +1,53 @@
+use std::sync::{Arc, Weak};
+
+use async_trait::async_trait;
+
+use super::Prober;
+use crate::flow::*;
+
+/// Passes connections through to the next outbound only while the prober
+/// considers the path healthy, so that selectors in front of it see
+/// failures early instead of waiting for a connection to time out.
+pub struct StreamGateFactory {
+    pub prober: Arc<Prober>,
+    pub next: Weak<dyn StreamOutboundFactory>,
+}
+
+pub struct DatagramGateFactory {
+    pub prober: Arc<Prober>,
+    pub next: Weak<dyn DatagramSessionFactory>,
+}
+
+fn unhealthy_error() -> FlowError {
+    std::io::Error::new(
+        std::io::ErrorKind::Other,
+        "ping-prober: all targets unreachable",
+    )
+    .into()
+}
+
+#[async_trait]
+impl StreamOutboundFactory for StreamGateFactory {
+    async fn create_outbound(
+        &self,
+        context: &mut FlowContext,
+        initial_data: &'_ [u8],
+    ) -> FlowResult<(Box<dyn Stream>, Buffer)> {
+        let next = self.next.upgrade().ok_or(FlowError::NoOutbound)?;
+        if !self.prober.is_healthy() {
+            return Err(unhealthy_error());
+        }
+        next.create_outbound(context, initial_data).await
+    }
+}
+
+#[async_trait]
+impl DatagramSessionFactory for DatagramGateFactory {
+    async fn bind(&self, context: Box<FlowContext>) -> FlowResult<Box<dyn DatagramSession>> {
+        let next = self.next.upgrade().ok_or(FlowError::NoOutbound)?;
+        if !self.prober.is_healthy() {
+            return Err(unhealthy_error());
+        }
+        next.bind(context).await
+    }
+}
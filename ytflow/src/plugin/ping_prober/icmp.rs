@@ -0,0 +1,167 @@
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::UdpSocket;
+
+use crate::plugin::socket::SocketKind;
+
+const ICMPV4_ECHO_REQUEST: u8 = 8;
+const ICMPV4_ECHO_REPLY: u8 = 0;
+const ICMPV6_ECHO_REQUEST: u8 = 128;
+const ICMPV6_ECHO_REPLY: u8 = 129;
+const PAYLOAD: &[u8] = b"ytflow-ping-prober";
+/// Datagram ICMPv4 sockets on Darwin and the BSDs deliver replies with the IP
+/// header in front, like raw sockets do. Linux strips it, and ICMPv6 sockets
+/// never include it.
+const IPV4_REPLY_HAS_IP_HEADER: bool = cfg!(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "openbsd",
+    target_os = "netbsd",
+    target_os = "dragonfly"
+));
+
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum = data
+        .chunks(2)
+        .map(|c| u16::from_be_bytes([c[0], c.get(1).copied().unwrap_or(0)]) as u32)
+        .sum::<u32>();
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+/// Whether `packet` received on an ICMP datagram socket is the echo reply of
+/// type `reply_type` for `seq`. `has_ip_header` tells if the IPv4 header has
+/// to be skipped first.
+fn is_echo_reply(packet: &[u8], reply_type: u8, seq: u16, has_ip_header: bool) -> bool {
+    let reply = if has_ip_header {
+        let Some(&first) = packet.first() else {
+            return false;
+        };
+        let header_len = (first & 0x0f) as usize * 4;
+        if first >> 4 != 4 || header_len < 20 {
+            return false;
+        }
+        packet.get(header_len..).unwrap_or_default()
+    } else {
+        packet
+    };
+    reply.len() >= 8 && reply[0] == reply_type && reply[6..8] == seq.to_be_bytes()
+}
+
+/// Create an unprivileged ICMP socket. Failing to create one usually means
+/// the platform does not allow ICMP without raw socket privileges.
+pub(super) fn create_socket(ip: IpAddr, bind_device: Option<&str>) -> io::Result<UdpSocket> {
+    let (domain, protocol) = match ip {
+        IpAddr::V4(_) => (Domain::IPV4, Protocol::ICMPV4),
+        IpAddr::V6(_) => (Domain::IPV6, Protocol::ICMPV6),
+    };
+    // Datagram ICMP sockets do not require raw socket privileges on Linux,
+    // Android and macOS.
+    let socket = Socket::new(domain, Type::DGRAM, Some(protocol))?;
+    crate::plugin::socket::run_socket_hook(&socket, SocketKind::Icmp, ip.is_ipv6())?;
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    if let Some(device) = bind_device {
+        socket.bind_device(Some(device.as_bytes()))?;
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    let _ = bind_device;
+    socket.set_nonblocking(true)?;
+    UdpSocket::from_std(std::net::UdpSocket::from(socket))
+}
+
+/// Send an ICMP echo request to `target` and return the round-trip time of
+/// the matching echo reply.
+pub(super) async fn ping(
+    socket: &UdpSocket,
+    target: IpAddr,
+    seq: u16,
+    timeout: Duration,
+) -> io::Result<Duration> {
+    let (request_type, reply_type) = match target {
+        IpAddr::V4(_) => (ICMPV4_ECHO_REQUEST, ICMPV4_ECHO_REPLY),
+        IpAddr::V6(_) => (ICMPV6_ECHO_REQUEST, ICMPV6_ECHO_REPLY),
+    };
+    let mut packet = Vec::with_capacity(8 + PAYLOAD.len());
+    packet.extend_from_slice(&[request_type, 0, 0, 0]);
+    // The identifier is overwritten by the kernel with the local port.
+    packet.extend_from_slice(&0u16.to_be_bytes());
+    packet.extend_from_slice(&seq.to_be_bytes());
+    packet.extend_from_slice(PAYLOAD);
+    if target.is_ipv4() {
+        // The kernel fills in ICMPv6 checksums by itself.
+        let sum = checksum(&packet);
+        packet[2..4].copy_from_slice(&sum.to_be_bytes());
+    }
+
+    let start = Instant::now();
+    socket.send_to(&packet, SocketAddr::new(target, 0)).await?;
+    tokio::time::timeout(timeout, async {
+        let mut buf = [0u8; 1500];
+        loop {
+            let (len, from) = socket.recv_from(&mut buf).await?;
+            if from.ip() != target {
+                continue;
+            }
+            let has_ip_header = target.is_ipv4() && IPV4_REPLY_HAS_IP_HEADER;
+            if is_echo_reply(&buf[..len], reply_type, seq, has_ip_header) {
+                return Ok(start.elapsed());
+            }
+        }
+    })
+    .await
+    .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn echo_reply(seq: u16) -> Vec<u8> {
+        let mut reply = vec![ICMPV4_ECHO_REPLY, 0, 0, 0, 0x12, 0x34];
+        reply.extend_from_slice(&seq.to_be_bytes());
+        reply.extend_from_slice(PAYLOAD);
+        reply
+    }
+
+    #[test]
+    fn test_is_echo_reply_without_ip_header() {
+        assert!(is_echo_reply(&echo_reply(7), ICMPV4_ECHO_REPLY, 7, false));
+        assert!(!is_echo_reply(&echo_reply(8), ICMPV4_ECHO_REPLY, 7, false));
+        assert!(!is_echo_reply(&echo_reply(7), ICMPV6_ECHO_REPLY, 7, false));
+        assert!(!is_echo_reply(
+            &[ICMPV4_ECHO_REPLY, 0, 0],
+            ICMPV4_ECHO_REPLY,
+            0,
+            false
+        ));
+    }
+
+    #[test]
+    fn test_is_echo_reply_with_ip_header() {
+        // IPv4 header with a 4-byte option, IHL = 6
+        let mut packet = vec![0x46, 0, 0, 0, 0, 0, 0, 0, 64, 1, 0, 0];
+        packet.extend_from_slice(&[192, 0, 2, 1, 192, 0, 2, 2, 1, 1, 1, 0]);
+        packet.extend_from_slice(&echo_reply(7));
+        assert!(is_echo_reply(&packet, ICMPV4_ECHO_REPLY, 7, true));
+        assert!(!is_echo_reply(&packet, ICMPV4_ECHO_REPLY, 7, false));
+        assert!(!is_echo_reply(&echo_reply(7), ICMPV4_ECHO_REPLY, 7, true));
+        assert!(!is_echo_reply(&[], ICMPV4_ECHO_REPLY, 7, true));
+    }
+
+    #[test]
+    fn test_checksum() {
+        // Echo request with id 0, seq 1 and no payload
+        let packet = [ICMPV4_ECHO_REQUEST, 0, 0, 0, 0, 0, 0, 1];
+        let sum = checksum(&packet);
+        assert_eq!(sum, 0xf7fe);
+        let mut packet = packet;
+        packet[2..4].copy_from_slice(&sum.to_be_bytes());
+        assert_eq!(checksum(&packet), 0);
+    }
+}
@@ -0,0 +1,248 @@
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+use std::sync::{Mutex, Weak};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use futures::future::join_all;
+use serde::Serialize;
+use tokio::sync::Notify;
+
+use super::icmp;
+use crate::control::events::{Event, EventBus};
+use crate::control::KillSwitch;
+use crate::flow::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeMode {
+    /// ICMP echo, falling back to TCP connect when ICMP sockets are not
+    /// permitted.
+    Auto,
+    Icmp,
+    Tcp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProbeMethod {
+    Icmp,
+    Tcp,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TargetStatus {
+    pub target: SocketAddr,
+    /// Method used by the last probe.
+    pub method: Option<ProbeMethod>,
+    /// Round-trip time of the last successful probe in milliseconds.
+    pub rtt: Option<u32>,
+    /// Unix timestamp in seconds of the last probe.
+    pub last_probe: Option<u64>,
+    pub consecutive_failures: u32,
+}
+
+pub struct Prober {
+    plugin_name: String,
+    targets: Vec<SocketAddr>,
+    mode: ProbeMode,
+    interval: Duration,
+    timeout: Duration,
+    failure_threshold: u32,
+    bind_device: Option<String>,
+    tcp_next: Weak<dyn StreamOutboundFactory>,
+    icmp_unavailable: AtomicBool,
+    seq: AtomicU16,
+    statuses: Mutex<Vec<TargetStatus>>,
+    probe_now: Notify,
+    kill_switch: KillSwitch,
+    events: EventBus,
+}
+
+impl Prober {
+    pub fn new(
+        plugin_name: String,
+        targets: Vec<SocketAddr>,
+        mode: ProbeMode,
+        interval: Duration,
+        timeout: Duration,
+        failure_threshold: u32,
+        bind_device: Option<String>,
+        tcp_next: Weak<dyn StreamOutboundFactory>,
+        kill_switch: KillSwitch,
+        events: EventBus,
+    ) -> Self {
+        let statuses = targets
+            .iter()
+            .map(|&target| TargetStatus {
+                target,
+                method: None,
+                rtt: None,
+                last_probe: None,
+                consecutive_failures: 0,
+            })
+            .collect();
+        Self {
+            plugin_name,
+            targets,
+            mode,
+            interval,
+            timeout,
+            failure_threshold,
+            bind_device,
+            tcp_next,
+            icmp_unavailable: AtomicBool::new(false),
+            seq: AtomicU16::new(0),
+            statuses: Mutex::new(statuses),
+            probe_now: Notify::new(),
+            kill_switch,
+            events,
+        }
+    }
+
+    pub fn statuses(&self) -> Vec<TargetStatus> {
+        self.statuses.lock().unwrap().clone()
+    }
+
+    /// A prober is healthy as long as at least one target has not failed for
+    /// `failure_threshold` consecutive rounds. Targets that have never been
+    /// probed count as healthy.
+    pub fn is_healthy(&self) -> bool {
+        let statuses = self.statuses.lock().unwrap();
+        statuses.is_empty()
+            || statuses
+                .iter()
+                .any(|s| s.consecutive_failures < self.failure_threshold)
+    }
+
+    /// Wake up the probing task to start a new round immediately.
+    pub fn trigger(&self) {
+        self.probe_now.notify_one();
+    }
+
+    pub async fn run(&self) {
+        let mut was_healthy = true;
+        loop {
+            self.probe_all().await;
+            let healthy = self.is_healthy();
+            self.kill_switch.report_health(&self.plugin_name, healthy);
+            if healthy != was_healthy {
+                self.events.publish(Event::OutboundHealthChanged {
+                    plugin: self.plugin_name.clone(),
+                    healthy,
+                });
+                was_healthy = healthy;
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(self.interval) => {}
+                _ = self.probe_now.notified() => {}
+            }
+        }
+    }
+
+    async fn probe_all(&self) {
+        let results = join_all(self.targets.iter().map(|&t| self.probe(t))).await;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .ok();
+        let mut statuses = self.statuses.lock().unwrap();
+        for (status, (method, res)) in statuses.iter_mut().zip(results) {
+            status.method = Some(method);
+            status.last_probe = now;
+            match res {
+                Ok(rtt) => {
+                    status.rtt = Some(rtt.as_millis().min(u32::MAX as _) as u32);
+                    status.consecutive_failures = 0;
+                }
+                Err(()) => {
+                    status.rtt = None;
+                    status.consecutive_failures = status.consecutive_failures.saturating_add(1);
+                }
+            }
+        }
+    }
+
+    async fn probe(&self, target: SocketAddr) -> (ProbeMethod, Result<Duration, ()>) {
+        let use_icmp = match self.mode {
+            ProbeMode::Icmp => true,
+            ProbeMode::Tcp => false,
+            ProbeMode::Auto => !self.icmp_unavailable.load(Ordering::Relaxed),
+        };
+        if use_icmp {
+            match icmp::create_socket(target.ip(), self.bind_device.as_deref()) {
+                Ok(socket) => {
+                    let seq = self.seq.fetch_add(1, Ordering::Relaxed);
+                    let res = icmp::ping(&socket, target.ip(), seq, self.timeout).await;
+                    return (ProbeMethod::Icmp, res.map_err(|_| ()));
+                }
+                Err(_) if self.mode == ProbeMode::Auto => {
+                    self.icmp_unavailable.store(true, Ordering::Relaxed);
+                }
+                Err(_) => return (ProbeMethod::Icmp, Err(())),
+            }
+        }
+        (ProbeMethod::Tcp, self.probe_tcp(target).await)
+    }
+
+    async fn probe_tcp(&self, target: SocketAddr) -> Result<Duration, ()> {
+        let next = self.tcp_next.upgrade().ok_or(())?;
+        let mut ctx = FlowContext::new(
+            SocketAddr::new(std::net::Ipv4Addr::UNSPECIFIED.into(), 0),
+            target.into(),
+        );
+        let start = Instant::now();
+        match tokio::time::timeout(self.timeout, next.create_outbound(&mut ctx, &[])).await {
+            Ok(Ok(_)) => Ok(start.elapsed()),
+            _ => Err(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::flow::testing::*;
+
+    fn tcp_prober(next: &Arc<MockStreamOutboundFactory>) -> Prober {
+        let next: Arc<dyn StreamOutboundFactory> = next.clone();
+        Prober::new(
+            "p".into(),
+            vec!["192.0.2.1:443".parse().unwrap()],
+            ProbeMode::Tcp,
+            Duration::from_secs(30),
+            Duration::from_secs(1),
+            2,
+            None,
+            Arc::downgrade(&next),
+            KillSwitch::default(),
+            EventBus::new(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_tcp_probe_success() {
+        let next = MockStreamOutboundFactory::new();
+        let prober = tcp_prober(&next);
+        prober.probe_all().await;
+        assert_eq!(next.connected().await.remote_peer, dest("192.0.2.1:443"));
+        let status = &prober.statuses()[0];
+        assert_eq!(status.method, Some(ProbeMethod::Tcp));
+        assert!(status.rtt.is_some());
+        assert_eq!(status.consecutive_failures, 0);
+        assert!(prober.is_healthy());
+    }
+
+    #[tokio::test]
+    async fn test_unhealthy_after_failure_threshold() {
+        let next = MockStreamOutboundFactory::refusing();
+        let prober = tcp_prober(&next);
+        assert!(prober.is_healthy());
+        prober.probe_all().await;
+        assert!(prober.is_healthy());
+        prober.probe_all().await;
+        assert!(!prober.is_healthy());
+        assert_eq!(prober.statuses()[0].consecutive_failures, 2);
+        assert_eq!(prober.statuses()[0].rtt, None);
+    }
+}
@@ -0,0 +1,61 @@
+use std::sync::{Arc, Mutex};
+
+use cbor4ii::serde::to_vec;
+use serde::Serialize;
+
+use super::{Prober, TargetStatus};
+use crate::control::{PluginRequestError, PluginRequestResult, PluginResponder};
+
+#[derive(Clone, Default, Serialize, PartialEq)]
+struct Info {
+    healthy: bool,
+    targets: Vec<TargetStatus>,
+}
+
+pub struct Responder {
+    prober: Arc<Prober>,
+    last_info: Mutex<(Info, u32)>,
+}
+
+impl Responder {
+    pub fn new(prober: Arc<Prober>) -> Self {
+        Self {
+            prober,
+            last_info: Mutex::new((Info::default(), 1)),
+        }
+    }
+}
+
+impl PluginResponder for Responder {
+    fn collect_info(&self, hashcode: &mut u32) -> Option<Vec<u8>> {
+        let info = {
+            let mut last_info_guard = self.last_info.lock().unwrap();
+            let (last_info, last_hashcode) = &mut *last_info_guard;
+            let new_info = Info {
+                healthy: self.prober.is_healthy(),
+                targets: self.prober.statuses(),
+            };
+            if new_info == *last_info {
+                if *last_hashcode == *hashcode {
+                    return None;
+                }
+            } else {
+                *last_info = new_info.clone();
+                *last_hashcode = (*last_hashcode).wrapping_add(1);
+            }
+            *hashcode = *last_hashcode;
+            new_info
+        };
+        Some(to_vec(vec![], &info).unwrap())
+    }
+
+    fn on_request(&self, func: &str, _params: &[u8]) -> PluginRequestResult<Vec<u8>> {
+        match func {
+            "probe" => {
+                self.prober.trigger();
+                Ok(to_vec(vec![], &()).unwrap())
+            }
+            _ => Err(PluginRequestError::NoSuchFunc),
+        }
+    }
+}
@@ -0,0 +1,194 @@
+mod socket;
+mod stream;
+mod tls;
+
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use quinn::crypto::rustls::QuicClientConfig;
+
+use crate::flow::*;
+use crate::plugin::tls::RootTrust;
+use socket::FlowUdpSocket;
+use stream::QuicBiStream;
+
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+/// Reported to quinn as the address of servers given by domain names. Packets
+/// are always sent to the original destination regardless.
+const PLACEHOLDER_PEER_IP: Ipv4Addr = Ipv4Addr::new(198, 18, 0, 1);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CongestionController {
+    #[default]
+    Cubic,
+    NewReno,
+    Bbr,
+}
+
+/// Opens a bidirectional QUIC stream for each outbound stream. Streams to the
+/// same server share one connection, which is carried by datagram sessions
+/// created by `next`.
+pub struct QuicOutboundFactory {
+    client_config: quinn::ClientConfig,
+    sni: Option<String>,
+    zero_rtt: bool,
+    handshake_timeout: Duration,
+    next: Weak<dyn DatagramSessionFactory>,
+    connection: tokio::sync::Mutex<Option<(DestinationAddr, quinn::Connection)>>,
+}
+
+pub(crate) fn connection_err(e: quinn::ConnectionError) -> FlowError {
+    FlowError::Io(e.into())
+}
+
+/// Client settings shared by plugins speaking QUIC, offering `alpn` in the TLS handshake.
+pub(crate) fn build_client_config(
+    alpn: Vec<Vec<u8>>,
+    skip_cert_check: bool,
+    trust: &RootTrust,
+    congestion_controller: CongestionController,
+    zero_rtt: bool,
+) -> quinn::ClientConfig {
+    let tls = tls::build_tls_config(alpn, skip_cert_check, trust, zero_rtt);
+    let crypto = QuicClientConfig::try_from(tls).expect("TLS 1.3 config must suit QUIC");
+    let mut transport = quinn::TransportConfig::default();
+    transport.keep_alive_interval(Some(KEEP_ALIVE_INTERVAL));
+    match congestion_controller {
+        CongestionController::Cubic => transport
+            .congestion_controller_factory(Arc::new(quinn::congestion::CubicConfig::default())),
+        CongestionController::NewReno => transport
+            .congestion_controller_factory(Arc::new(quinn::congestion::NewRenoConfig::default())),
+        CongestionController::Bbr => transport
+            .congestion_controller_factory(Arc::new(quinn::congestion::BbrConfig::default())),
+    };
+    let mut client_config = quinn::ClientConfig::new(Arc::new(crypto));
+    client_config.transport_config(Arc::new(transport));
+    client_config
+}
+
+/// Connect to `context.remote_peer` over a datagram session created by `next`.
+pub(crate) async fn connect_via(
+    next: &Weak<dyn DatagramSessionFactory>,
+    client_config: quinn::ClientConfig,
+    sni: Option<&str>,
+    zero_rtt: bool,
+    handshake_timeout: Duration,
+    context: &FlowContext,
+) -> FlowResult<quinn::Connection> {
+    let next = next.upgrade().ok_or(FlowError::NoOutbound)?;
+    let server = context.remote_peer.clone();
+    let session = next
+        .bind(Box::new(FlowContext::new(
+            context.local_peer,
+            server.clone(),
+        )))
+        .await?;
+    let peer = match &server.host {
+        HostName::Ip(ip) => SocketAddr::new(*ip, server.port),
+        HostName::DomainName(_) => SocketAddr::new(PLACEHOLDER_PEER_IP.into(), server.port),
+    };
+    let sni = sni
+        .map(str::to_string)
+        .unwrap_or_else(|| match &server.host {
+            HostName::DomainName(domain) => domain.trim_end_matches('.').to_string(),
+            HostName::Ip(ip) => ip.to_string(),
+        });
+    let socket = Arc::new(FlowUdpSocket::new(session, server, peer));
+    let endpoint = quinn::Endpoint::new_with_abstract_socket(
+        quinn::EndpointConfig::default(),
+        None,
+        socket,
+        Arc::new(quinn::TokioRuntime),
+    )?;
+    let connecting = endpoint
+        .connect_with(client_config, peer, &sni)
+        .map_err(|e| FlowError::Io(io::Error::new(io::ErrorKind::InvalidInput, e)))?;
+    let connecting = if zero_rtt {
+        match connecting.into_0rtt() {
+            Ok((connection, _)) => return Ok(connection),
+            Err(connecting) => connecting,
+        }
+    } else {
+        connecting
+    };
+    with_flow_deadline(handshake_timeout, &context.abort.signal(), async {
+        connecting.await.map_err(connection_err)
+    })
+    .await
+}
+
+impl QuicOutboundFactory {
+    /// With `zero_rtt` enabled, streams opened while resuming a connection
+    /// carry their initial data in 0-RTT packets, which can be replayed by an
+    /// attacker. Such streams fail if the server rejects 0-RTT.
+    pub fn new(
+        sni: Option<String>,
+        alpn: Vec<Vec<u8>>,
+        skip_cert_check: bool,
+        trust: &RootTrust,
+        congestion_controller: CongestionController,
+        zero_rtt: bool,
+        handshake_timeout: Duration,
+        next: Weak<dyn DatagramSessionFactory>,
+    ) -> Self {
+        Self {
+            client_config: build_client_config(
+                alpn,
+                skip_cert_check,
+                trust,
+                congestion_controller,
+                zero_rtt,
+            ),
+            sni,
+            zero_rtt,
+            handshake_timeout,
+            next,
+            connection: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// Reuse the connection to the same server unless it has been closed.
+    async fn get_or_connect(&self, context: &FlowContext) -> FlowResult<quinn::Connection> {
+        let mut guard = self.connection.lock().await;
+        if let Some((server, connection)) = &*guard {
+            if *server == context.remote_peer && connection.close_reason().is_none() {
+                return Ok(connection.clone());
+            }
+        }
+        let connection = connect_via(
+            &self.next,
+            self.client_config.clone(),
+            self.sni.as_deref(),
+            self.zero_rtt,
+            self.handshake_timeout,
+            context,
+        )
+        .await?;
+        *guard = Some((context.remote_peer.clone(), connection.clone()));
+        Ok(connection)
+    }
+}
+
+#[async_trait]
+impl StreamOutboundFactory for QuicOutboundFactory {
+    async fn create_outbound(
+        &self,
+        context: &mut FlowContext,
+        initial_data: &[u8],
+    ) -> FlowResult<(Box<dyn Stream>, Buffer)> {
+        let connection = self.get_or_connect(context).await?;
+        let (mut send, recv) = connection.open_bi().await.map_err(connection_err)?;
+        if !initial_data.is_empty() {
+            send.write_all(initial_data)
+                .await
+                .map_err(|e| FlowError::Io(e.into()))?;
+        }
+        Ok((
+            Box::new(CompatFlow::new(QuicBiStream { send, recv }, 4096)),
+            Buffer::new(),
+        ))
+    }
+}
@@ -0,0 +1,152 @@
+use std::fmt::{self, Debug, Formatter};
+use std::io::{self, IoSliceMut};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use futures::ready;
+use quinn::udp::{RecvMeta, Transmit};
+use quinn::{AsyncUdpSocket, UdpPoller};
+
+use crate::flow::*;
+
+/// Lets quinn drive a datagram session created by the next plugin instead of
+/// a socket of its own, so that QUIC traffic goes through the rest of the
+/// chain. All packets are sent to `server`, and all packets received are
+/// reported to come from `peer`.
+pub(super) struct FlowUdpSocket {
+    session: Mutex<Box<dyn DatagramSession>>,
+    server: DestinationAddr,
+    peer: SocketAddr,
+}
+
+impl FlowUdpSocket {
+    pub(super) fn new(
+        session: Box<dyn DatagramSession>,
+        server: DestinationAddr,
+        peer: SocketAddr,
+    ) -> Self {
+        Self {
+            session: Mutex::new(session),
+            server,
+            peer,
+        }
+    }
+}
+
+impl Debug for FlowUdpSocket {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FlowUdpSocket")
+            .field("server", &self.server)
+            .field("peer", &self.peer)
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+struct SendReadyPoller(Arc<FlowUdpSocket>);
+
+impl UdpPoller for SendReadyPoller {
+    fn poll_writable(self: Pin<&mut Self>, cx: &mut Context) -> Poll<io::Result<()>> {
+        self.0.session.lock().unwrap().poll_send_ready(cx).map(Ok)
+    }
+}
+
+impl AsyncUdpSocket for FlowUdpSocket {
+    fn create_io_poller(self: Arc<Self>) -> Pin<Box<dyn UdpPoller>> {
+        Box::pin(SendReadyPoller(self))
+    }
+
+    fn try_send(&self, transmit: &Transmit) -> io::Result<()> {
+        let mut session = self.session.lock().unwrap();
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        if session.poll_send_ready(&mut cx).is_pending() {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        session.send_to(self.server.clone(), transmit.contents.to_vec());
+        Ok(())
+    }
+
+    fn poll_recv(
+        &self,
+        cx: &mut Context,
+        bufs: &mut [IoSliceMut<'_>],
+        meta: &mut [RecvMeta],
+    ) -> Poll<io::Result<usize>> {
+        let mut session = self.session.lock().unwrap();
+        let Some((_, buf)) = ready!(session.poll_recv_from(cx)) else {
+            return Poll::Ready(Err(io::ErrorKind::ConnectionAborted.into()));
+        };
+        let len = buf.len().min(bufs[0].len());
+        bufs[0][..len].copy_from_slice(&buf[..len]);
+        meta[0] = RecvMeta {
+            addr: self.peer,
+            len,
+            stride: len,
+            ecn: None,
+            dst_ip: None,
+        };
+        Poll::Ready(Ok(1))
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future::poll_fn;
+
+    use super::*;
+    use crate::flow::testing::*;
+
+    fn socket() -> (FlowUdpSocket, DatagramPeer, SocketAddr) {
+        let (session, peer) = datagram_pair();
+        let addr = "198.18.0.1:443".parse().unwrap();
+        (
+            FlowUdpSocket::new(session, dest("example.com:443"), addr),
+            peer,
+            addr,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_send_goes_to_server() {
+        let (socket, peer, addr) = socket();
+        socket
+            .try_send(&Transmit {
+                destination: addr,
+                ecn: None,
+                contents: b"initial",
+                segment_size: None,
+                src_ip: None,
+            })
+            .unwrap();
+        assert_eq!(
+            peer.recv_from().await,
+            Some((dest("example.com:443"), b"initial".to_vec()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_recv_comes_from_peer() {
+        let (socket, peer, addr) = socket();
+        peer.send_to(dest("example.com:443"), b"handshake".to_vec());
+        let mut buf = [0; 64];
+        let mut meta = [RecvMeta::default()];
+        let n = poll_fn(|cx| socket.poll_recv(cx, &mut [IoSliceMut::new(&mut buf)], &mut meta))
+            .await
+            .unwrap();
+        assert_eq!(n, 1);
+        assert_eq!(meta[0].addr, addr);
+        assert_eq!(&buf[..meta[0].len], b"handshake");
+
+        drop(peer);
+        let err = poll_fn(|cx| socket.poll_recv(cx, &mut [IoSliceMut::new(&mut buf)], &mut meta))
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionAborted);
+    }
+}
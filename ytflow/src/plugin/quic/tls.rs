@@ -0,0 +1,118 @@
+use std::sync::Arc;
+
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, RootCertStore, SignatureScheme};
+
+use crate::plugin::tls::RootTrust;
+
+/// Accepts any certificate as long as the handshake signatures are valid.
+#[derive(Debug)]
+struct SkipCertCheck(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for SkipCertCheck {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// The certificates of `trust` that rustls can parse.
+fn root_store(trust: &RootTrust) -> RootCertStore {
+    let mut roots = RootCertStore::empty();
+    roots.add_parsable_certificates(trust.to_der().into_iter().map(CertificateDer::from));
+    roots
+}
+
+/// TLS 1.3 settings for QUIC. quinn only speaks rustls, so servers are
+/// verified against the roots `tls-client` would use for the same trust
+/// settings.
+pub(super) fn build_tls_config(
+    alpn: Vec<Vec<u8>>,
+    skip_cert_check: bool,
+    trust: &RootTrust,
+    zero_rtt: bool,
+) -> rustls::ClientConfig {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let roots = root_store(trust);
+    let mut config = rustls::ClientConfig::builder_with_provider(provider.clone())
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .expect("ring must support TLS 1.3")
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    if skip_cert_check {
+        config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(SkipCertCheck(provider)));
+    }
+    config.alpn_protocols = alpn;
+    config.enable_early_data = zero_rtt;
+    config
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_root_store_follows_trust() {
+        assert!(root_store(&RootTrust::Bundled).len() > 100);
+        assert!(root_store(&RootTrust::Custom(Arc::from(Vec::new()))).is_empty());
+    }
+
+    #[test]
+    fn test_build_tls_config() {
+        let config = build_tls_config(vec![b"h3".to_vec()], false, &RootTrust::Bundled, true);
+        assert_eq!(config.alpn_protocols, [b"h3".to_vec()]);
+        assert!(config.enable_early_data);
+        assert!(quinn::crypto::rustls::QuicClientConfig::try_from(config).is_ok());
+
+        let config = build_tls_config(
+            vec![],
+            true,
+            &RootTrust::Custom(Arc::from(Vec::new())),
+            false,
+        );
+        assert!(!config.enable_early_data);
+        assert!(quinn::crypto::rustls::QuicClientConfig::try_from(config).is_ok());
+    }
+}
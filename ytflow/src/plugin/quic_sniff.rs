@@ -0,0 +1,136 @@
+use std::sync::Weak;
+use std::task::{Context, Poll};
+
+use futures::future::poll_fn;
+
+use crate::flow::*;
+
+const QUIC_V1: u32 = 0x0000_0001;
+const QUIC_V2: u32 = 0x6b33_43cf;
+/// Clients must pad datagrams carrying Initial packets to at least this size.
+/// See RFC 9000 section 14.1.
+const MIN_INITIAL_DATAGRAM_SIZE: usize = 1200;
+const MAX_CID_LEN: u8 = 20;
+
+/// Whether a datagram from a client starts with a QUIC Initial packet of a
+/// known version, regardless of the destination port.
+pub fn is_quic_initial(buf: &[u8]) -> bool {
+    if buf.len() < MIN_INITIAL_DATAGRAM_SIZE {
+        return false;
+    }
+    // Long header with the fixed bit set
+    if buf[0] & 0xc0 != 0xc0 {
+        return false;
+    }
+    let version = u32::from_be_bytes([buf[1], buf[2], buf[3], buf[4]]);
+    let packet_type = (buf[0] >> 4) & 0x03;
+    let is_initial = match version {
+        QUIC_V1 => packet_type == 0,
+        QUIC_V2 => packet_type == 1,
+        // IETF drafts
+        v if v >> 8 == 0x00ff_0000 => packet_type == 0,
+        _ => false,
+    };
+    if !is_initial {
+        return false;
+    }
+    let dcid_len = buf[5];
+    let scid_len_at = 6 + dcid_len as usize;
+    dcid_len <= MAX_CID_LEN && buf[scid_len_at] <= MAX_CID_LEN
+}
+
+/// Replays the first datagram received from the client before reading from
+/// the underlying session again.
+struct PeekedDatagramSession {
+    first: Option<(DestinationAddr, Buffer)>,
+    inner: Box<dyn DatagramSession>,
+}
+
+impl DatagramSession for PeekedDatagramSession {
+    fn poll_recv_from(&mut self, cx: &mut Context) -> Poll<Option<(DestinationAddr, Buffer)>> {
+        if let Some(first) = self.first.take() {
+            return Poll::Ready(Some(first));
+        }
+        self.inner.poll_recv_from(cx)
+    }
+    fn poll_send_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        self.inner.poll_send_ready(cx)
+    }
+    fn send_to(&mut self, remote_peer: DestinationAddr, buf: Buffer) {
+        self.inner.send_to(remote_peer, buf)
+    }
+    fn poll_shutdown(&mut self, cx: &mut Context<'_>) -> Poll<FlowResult<()>> {
+        self.inner.poll_shutdown(cx)
+    }
+}
+
+/// Hands sessions that start with a QUIC Initial packet to a dedicated
+/// handler. Pointing it to a reject handler makes most clients fall back to
+/// TCP.
+pub struct QuicSniffer {
+    pub quic_next: Weak<dyn DatagramSessionHandler>,
+}
+
+impl QuicSniffer {
+    /// Wait for the first datagram of `session`. QUIC sessions go to
+    /// `quic_next`, and the others are passed to `otherwise` untouched.
+    pub fn dispatch(
+        &self,
+        mut session: Box<dyn DatagramSession>,
+        context: Box<FlowContext>,
+        otherwise: impl FnOnce(Box<dyn DatagramSession>, Box<FlowContext>) + Send + 'static,
+    ) {
+        let quic_next = self.quic_next.clone();
+        tokio::spawn(async move {
+            let Some(first) = poll_fn(|cx| session.poll_recv_from(cx)).await else {
+                return;
+            };
+            let is_quic = is_quic_initial(&first.1);
+            let session = Box::new(PeekedDatagramSession {
+                first: Some(first),
+                inner: session,
+            });
+            if !is_quic {
+                return otherwise(session, context);
+            }
+            if let Some(quic_next) = quic_next.upgrade() {
+                quic_next.on_session(session, context)
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn initial(first_byte: u8, version: u32) -> Vec<u8> {
+        let mut buf = vec![0; MIN_INITIAL_DATAGRAM_SIZE];
+        buf[0] = first_byte;
+        buf[1..5].copy_from_slice(&version.to_be_bytes());
+        buf[5] = 8;
+        buf
+    }
+
+    #[test]
+    fn test_is_quic_initial() {
+        assert!(is_quic_initial(&initial(0xc3, QUIC_V1)));
+        assert!(is_quic_initial(&initial(0xd3, QUIC_V2)));
+        assert!(is_quic_initial(&initial(0xc3, 0xff00_001d)));
+    }
+
+    #[test]
+    fn test_is_quic_initial_rejects_others() {
+        // Handshake packet
+        assert!(!is_quic_initial(&initial(0xe3, QUIC_V1)));
+        // Short header
+        assert!(!is_quic_initial(&initial(0x43, QUIC_V1)));
+        // Version negotiation
+        assert!(!is_quic_initial(&initial(0xc3, 0)));
+        // Unpadded
+        assert!(!is_quic_initial(&initial(0xc3, QUIC_V1)[..100]));
+        let mut buf = initial(0xc3, QUIC_V1);
+        buf[5] = 21;
+        assert!(!is_quic_initial(&buf));
+    }
+}
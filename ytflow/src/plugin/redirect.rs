@@ -1,5 +1,7 @@
+use std::ops::RangeInclusive;
 use std::sync::Weak;
 use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
 use pin_project_lite::pin_project;
@@ -16,6 +18,58 @@ impl<R: 'static + Send + Sync + Clone + Fn() -> DestinationAddr> PeerProvider fo
     }
 }
 
+/// Rotates the destination port within a range every `interval`. Since the
+/// port is looked up for each datagram sent, a datagram session keeps using the
+/// same socket while hopping, and new streams connect to the current port.
+#[derive(Clone)]
+pub struct PortHoppingPeer {
+    dest: DestinationAddr,
+    ports: RangeInclusive<u16>,
+    interval: Duration,
+    epoch: Instant,
+    seed: u64,
+}
+
+impl PortHoppingPeer {
+    pub fn new(dest: DestinationAddr, ports: RangeInclusive<u16>, interval: Duration) -> Self {
+        Self {
+            dest,
+            ports,
+            interval: interval.max(Duration::from_millis(1)),
+            epoch: Instant::now(),
+            seed: rand::random(),
+        }
+    }
+
+    fn current_port(&self) -> u16 {
+        let slot = (self.epoch.elapsed().as_nanos() / self.interval.as_nanos()) as u64;
+        self.port_at(slot)
+    }
+
+    /// The port used during the `slot`-th interval since `epoch`.
+    fn port_at(&self, slot: u64) -> u16 {
+        let (start, end) = (*self.ports.start(), *self.ports.end());
+        let len = (end as u64).saturating_sub(start as u64) + 1;
+        // splitmix64, so that consecutive slots land on unrelated ports
+        let mut z = self
+            .seed
+            .wrapping_add(slot.wrapping_mul(0x9e3779b97f4a7c15));
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^= z >> 31;
+        start + (z % len) as u16
+    }
+}
+
+impl PeerProvider for PortHoppingPeer {
+    fn get_peer(&self) -> DestinationAddr {
+        DestinationAddr {
+            host: self.dest.host.clone(),
+            port: self.current_port(),
+        }
+    }
+}
+
 pub struct StreamRedirectHandler<R: PeerProvider> {
     pub remote_peer: R,
     pub next: Weak<dyn StreamHandler>,
@@ -123,3 +177,98 @@ impl<R: PeerProvider> DatagramSessionFactory for DatagramSessionRedirectFactory<
         }))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+    use crate::flow::testing::*;
+
+    const HOUR: Duration = Duration::from_secs(3600);
+
+    #[tokio::test]
+    async fn test_stream_redirect_handler() {
+        let mut aps = AccessPoints::new();
+        let next = MockStreamHandler::new();
+        let handler = StreamRedirectHandler {
+            remote_peer: || dest("10.0.0.1:8080"),
+            next: aps.hold(next.clone()) as _,
+        };
+        let (stream, _peer) = stream_pair();
+        handler.on_stream(stream, b"hello".to_vec(), context("example.com:80"));
+        let accepted = next.try_accept().unwrap();
+        assert_eq!(accepted.context.remote_peer, dest("10.0.0.1:8080"));
+        assert_eq!(accepted.initial_data, b"hello");
+    }
+
+    #[tokio::test]
+    async fn test_stream_redirect_outbound() {
+        let mut aps = AccessPoints::new();
+        let next = MockStreamOutboundFactory::new();
+        let factory = StreamRedirectOutboundFactory {
+            remote_peer: || dest("10.0.0.1:8080"),
+            next: aps.hold(next.clone()) as _,
+        };
+        let mut ctx = context("example.com:80");
+        let (mut stream, _) = factory.create_outbound(&mut ctx, b"hi").await.unwrap();
+        assert_eq!(ctx.remote_peer, dest("10.0.0.1:8080"));
+
+        let mut outbound = next.connected().await;
+        assert_eq!(outbound.remote_peer, dest("10.0.0.1:8080"));
+        assert_eq!(outbound.initial_data, b"hi");
+        outbound.peer.write_all(b"pong").await.unwrap();
+        stream.commit_rx_buffer(Vec::with_capacity(16)).unwrap();
+        let buf = crate::get_rx_buffer_boxed!(stream).unwrap();
+        assert_eq!(buf, b"pong");
+        drop(stream);
+        let mut rest = vec![];
+        outbound.peer.read_to_end(&mut rest).await.unwrap();
+        assert!(rest.is_empty());
+    }
+
+    #[test]
+    fn test_port_hopping_stays_in_range() {
+        for ports in [20000..=20009, 0..=65535, 65530..=65535] {
+            let peer = PortHoppingPeer::new(dest("example.com:443"), ports.clone(), HOUR);
+            let hopped: std::collections::BTreeSet<_> =
+                (0..1000).map(|s| peer.port_at(s)).collect();
+            assert!(hopped.iter().all(|p| ports.contains(p)));
+            // Consecutive slots do not stick to one port.
+            assert!(hopped.len() > 1);
+        }
+    }
+
+    #[test]
+    fn test_port_hopping_single_port() {
+        let peer = PortHoppingPeer::new(dest("example.com:443"), 8443..=8443, HOUR);
+        assert!((0..1000).all(|s| peer.port_at(s) == 8443));
+        assert_eq!(peer.get_peer(), dest("example.com:8443"));
+    }
+
+    #[test]
+    fn test_port_hopping_stable_within_interval() {
+        let peer = PortHoppingPeer::new(dest("example.com:443"), 20000..=29999, HOUR);
+        let first = peer.get_peer();
+        assert_eq!(first.host, dest("example.com:443").host);
+        assert_eq!(first.port, peer.port_at(0));
+        assert_eq!(peer.get_peer(), first);
+        // A clone hops along with the original.
+        assert_eq!(peer.clone().get_peer(), first);
+    }
+
+    #[tokio::test]
+    async fn test_datagram_redirect_factory_rewrites_destination() {
+        let mut aps = AccessPoints::new();
+        let (next, peers) = MockDatagramSessionFactory::queued();
+        let factory = DatagramSessionRedirectFactory {
+            remote_peer: || dest("10.0.0.1:53"),
+            next: aps.hold(next.clone()) as _,
+        };
+        let mut session = factory.bind(context("example.com:53")).await.unwrap();
+        let (peer, ctx) = peers.recv_async().await.unwrap();
+        assert_eq!(ctx.remote_peer, dest("10.0.0.1:53"));
+        session.send_to(dest("example.com:53"), vec![1]);
+        assert_eq!(peer.recv_from().await, Some((dest("10.0.0.1:53"), vec![1])));
+    }
+}
@@ -17,29 +17,39 @@ pub struct DatagramForwardResolver {
     pub next: Weak<dyn DatagramSessionHandler>,
 }
 
-fn handle_context(
+fn handle_context<I: PendingInbound>(
     resolver: &Weak<dyn Resolver>,
     mut context: Box<FlowContext>,
-    on_context: impl FnOnce(Box<FlowContext>) + Send + 'static,
+    mut inbound: I,
+    on_context: impl FnOnce(I, Box<FlowContext>) + Send + 'static,
 ) {
     let domain = match &mut context.remote_peer.host {
         HostName::DomainName(domain) => std::mem::take(domain),
-        _ => return on_context(context),
+        _ => return on_context(inbound, context),
     };
     let resolver = match resolver.upgrade() {
         Some(resolver) => resolver,
         None => return,
     };
     tokio::spawn(async move {
-        context.remote_peer = super::try_resolve_forward(
+        let options = context.resolve_options(DEFAULT_RESOLVE_TIMEOUT);
+        let resolving = super::try_resolve_forward(
             context.local_peer.is_ipv6(),
             resolver,
             domain,
             context.remote_peer.port,
-        )
-        .await;
-        on_context(context);
-        FlowResult::Ok(())
+            options,
+        );
+        context.remote_peer = tokio::select! {
+            dest = resolving => dest,
+            // The client is gone. Stop resolving along with other work spawned on
+            // behalf of this flow.
+            () = inbound.closed() => {
+                context.abort.abort();
+                return;
+            }
+        };
+        on_context(inbound, context);
     });
 }
 
@@ -49,7 +59,9 @@ impl StreamHandler for StreamForwardResolver {
             Some(next) => next,
             None => return,
         };
-        handle_context(&self.resolver, context, move |c| {
+        let pending = PendingStream::new(lower, initial_data);
+        handle_context(&self.resolver, context, pending, move |pending, c| {
+            let (lower, initial_data) = pending.into_parts();
             next.on_stream(lower, initial_data, c)
         });
     }
@@ -66,7 +78,7 @@ impl DatagramSessionHandler for DatagramForwardResolver {
             None => return,
         };
         let old_remote_host = context.remote_peer.host.clone();
-        handle_context(&self.resolver, context, move |c| {
+        handle_context(&self.resolver, context, lower, move |lower, c| {
             let mut reverse_mapping = BTreeMap::new();
             if let (HostName::DomainName(domain), HostName::Ip(resolved_ip)) =
                 (old_remote_host, c.remote_peer.host.clone())
@@ -120,7 +132,18 @@ impl DatagramSession for DatagramForwardSession {
         let is_ipv6 = self.is_ipv6;
         self.resolving = Some(Box::pin(async move {
             (
-                super::try_resolve_forward(is_ipv6, resolver, domain, port).await,
+                // Dropped along with the session, so only a deadline is needed here.
+                super::try_resolve_forward(
+                    is_ipv6,
+                    resolver,
+                    domain,
+                    port,
+                    ResolveOptions {
+                        timeout: Some(DEFAULT_RESOLVE_TIMEOUT),
+                        abort: None,
+                    },
+                )
+                .await,
                 buf,
             )
         }));
@@ -144,3 +167,95 @@ impl DatagramSession for DatagramForwardSession {
         self.lower.as_mut().poll_shutdown(cx)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flow::testing::*;
+
+    fn resolver() -> Arc<MockResolver> {
+        Arc::new(
+            MockResolver::new()
+                .with_ipv4("example.com.", [93, 184, 216, 34].into())
+                .with_ipv6("example.com.", "2606:2800:220:1::1".parse().unwrap()),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_stream_forward_resolver() {
+        let mut aps = AccessPoints::new();
+        let resolver = resolver();
+        let next = MockStreamHandler::new();
+        let handler = StreamForwardResolver {
+            resolver: aps.hold(resolver.clone()) as _,
+            next: aps.hold(next.clone()) as _,
+        };
+
+        let (stream, _peer) = stream_pair();
+        handler.on_stream(stream, vec![], context("example.com.:443"));
+        let accepted = next.accept().await;
+        assert_eq!(accepted.context.remote_peer, dest("93.184.216.34:443"));
+
+        // IP destinations are passed through without querying.
+        let (stream, _peer) = stream_pair();
+        handler.on_stream(stream, vec![], context("1.1.1.1:443"));
+        let accepted = next.accept().await;
+        assert_eq!(accepted.context.remote_peer, dest("1.1.1.1:443"));
+        assert_eq!(resolver.queries(), 1);
+
+        // Unresolvable domains are kept as is.
+        let (stream, _peer) = stream_pair();
+        handler.on_stream(stream, vec![], context("unknown.test.:443"));
+        let accepted = next.accept().await;
+        assert_eq!(accepted.context.remote_peer, dest("unknown.test.:443"));
+    }
+
+    #[tokio::test]
+    async fn test_stream_close_cancels_resolving() {
+        let mut aps = AccessPoints::new();
+        let (resolver, cancelled) = StallingResolver::new();
+        let next = MockStreamHandler::new();
+        let handler = StreamForwardResolver {
+            resolver: aps.hold(resolver) as _,
+            next: aps.hold(next.clone()) as _,
+        };
+
+        let (stream, peer) = stream_pair();
+        let ctx = context("example.com.:443");
+        let abort = ctx.abort.signal();
+        handler.on_stream(stream, vec![], ctx);
+        tokio::task::yield_now().await;
+        assert!(!abort.is_aborted());
+        drop(peer);
+
+        cancelled.recv_async().await.unwrap();
+        abort.aborted().await;
+        assert!(next.try_accept().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_datagram_forward_resolver_maps_back() {
+        let mut aps = AccessPoints::new();
+        let next = MockDatagramSessionHandler::new();
+        let handler = DatagramForwardResolver {
+            resolver: aps.hold(resolver()) as _,
+            next: aps.hold(next.clone()) as _,
+        };
+
+        let (session, peer) = datagram_pair();
+        handler.on_session(session, context("example.com.:53"));
+        let (mut session, ctx) = next.accept().await;
+        assert_eq!(ctx.remote_peer, dest("93.184.216.34:53"));
+
+        // Replies from the resolved address appear to come from the domain.
+        session.send_to(dest("93.184.216.34:53"), vec![1]);
+        assert_eq!(
+            peer.recv_from().await,
+            Some((dest("example.com.:53"), vec![1]))
+        );
+        // Datagrams from domains are resolved before being handed over.
+        peer.send_to(dest("example.com.:53"), vec![2]);
+        let received = futures::future::poll_fn(|cx| session.poll_recv_from(cx)).await;
+        assert_eq!(received, Some((dest("93.184.216.34:53"), vec![2])));
+    }
+}
@@ -11,17 +11,18 @@ async fn try_resolve_forward(
     resolver: Arc<dyn Resolver>,
     domain: String,
     port: u16,
+    options: ResolveOptions,
 ) -> DestinationAddr {
     match if is_ipv6 {
         resolver
-            .resolve_ipv6(domain.clone())
+            .resolve_ipv6_with(domain.clone(), options)
             .await
             .ok()
             .and_then(|ips| ips.first().cloned())
             .map(Into::into)
     } else {
         resolver
-            .resolve_ipv4(domain.clone())
+            .resolve_ipv4_with(domain.clone(), options)
             .await
             .ok()
             .and_then(|ips| ips.first().cloned())
@@ -0,0 +1,58 @@
+//! Picking one of several configured values, such as Host headers or paths,
+//! for each new connection.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use rand::{thread_rng, Rng};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RotationMode {
+    #[default]
+    RoundRobin,
+    Random,
+}
+
+pub struct Rotation<T> {
+    items: Box<[T]>,
+    mode: RotationMode,
+    next: AtomicUsize,
+}
+
+impl<T> Rotation<T> {
+    /// Panics if `items` is empty.
+    pub fn new(items: Vec<T>, mode: RotationMode) -> Self {
+        assert!(!items.is_empty(), "Nothing to rotate among");
+        Self {
+            items: items.into(),
+            mode,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn pick(&self) -> &T {
+        let idx = match (self.items.len(), self.mode) {
+            (1, _) => 0,
+            (len, RotationMode::RoundRobin) => self.next.fetch_add(1, Ordering::Relaxed) % len,
+            (len, RotationMode::Random) => thread_rng().gen_range(0..len),
+        };
+        &self.items[idx]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_robin() {
+        let rotation = Rotation::new(vec!["a", "b", "c"], RotationMode::RoundRobin);
+        let picked: Vec<_> = (0..4).map(|_| *rotation.pick()).collect();
+        assert_eq!(picked, ["a", "b", "c", "a"]);
+    }
+
+    #[test]
+    fn test_random() {
+        let rotation = Rotation::new(vec![1, 2], RotationMode::Random);
+        assert!((0..16).all(|_| [1, 2].contains(rotation.pick())));
+    }
+}
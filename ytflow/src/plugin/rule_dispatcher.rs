@@ -2,12 +2,17 @@ use std::sync::Weak;
 
 #[cfg(feature = "plugins")]
 mod builder;
+pub mod cidr_trie;
 #[cfg(feature = "plugins")]
 mod dispatcher;
 #[cfg(feature = "plugins")]
+mod responder;
+#[cfg(feature = "plugins")]
 mod rules;
 #[cfg(feature = "plugins")]
 mod set;
+#[cfg(feature = "plugins")]
+mod verdict_cache;
 
 use crate::flow::*;
 #[cfg(feature = "plugins")]
@@ -15,7 +20,11 @@ pub use builder::RuleDispatcherBuilder;
 #[cfg(feature = "plugins")]
 pub use dispatcher::RuleDispatcher;
 #[cfg(feature = "plugins")]
+pub use responder::Responder;
+#[cfg(feature = "plugins")]
 pub use set::RuleSet;
+#[cfg(feature = "plugins")]
+pub use verdict_cache::VerdictCache;
 
 pub const ACTION_LIMIT: usize = 15;
 
@@ -1,20 +1,44 @@
 use std::sync::{Arc, Weak};
 
+mod cidr_list;
+mod clash_rule_provider;
+mod domain_list;
 mod geoip;
+mod geosite;
 mod quanx_filter;
 mod surge_domainset;
 
 use crate::flow::Resolver;
+use crate::plugin::quic_sniff::QuicSniffer;
 
 use super::dispatcher::ActionSet;
 use super::rules::GeoIpSet;
-use super::set::RuleSet;
-use super::{Action, ActionHandle, RuleDispatcher, RuleHandle, RuleId, ACTION_LIMIT};
+use super::set::{IdRangeHandle, RuleSet};
+use super::{Action, ActionHandle, RuleDispatcher, RuleHandle, RuleId, VerdictCache, ACTION_LIMIT};
+
+/// Extend the range of the last handle if it has the same action, so that
+/// patterns can be mapped back to their rules with few ranges.
+fn push_id_range_handle_into_sorted(
+    ranges: &mut Vec<IdRangeHandle>,
+    idx: usize,
+    handle: RuleHandle,
+) {
+    if let Some((idx_range, _)) = ranges
+        .last_mut()
+        .filter(|(_, last)| last.action() == handle.action())
+    {
+        *idx_range = idx_range.start..idx_range.end.max(idx + 1);
+        return;
+    }
+    ranges.push((idx..idx + 1, handle));
+}
 
 #[derive(Default)]
 pub struct RuleDispatcherBuilder {
     resolver: Option<Weak<dyn Resolver>>,
     actions: ActionSet,
+    verdict_cache: Option<Arc<VerdictCache>>,
+    quic_sniffer: Option<QuicSniffer>,
 }
 
 impl RuleDispatcherBuilder {
@@ -32,18 +56,33 @@ impl RuleDispatcherBuilder {
         self.resolver = resolver;
     }
 
+    pub fn set_verdict_cache(&mut self, verdict_cache: Option<Arc<VerdictCache>>) {
+        self.verdict_cache = verdict_cache;
+    }
+
+    pub fn set_quic_sniffer(&mut self, quic_sniffer: Option<QuicSniffer>) {
+        self.quic_sniffer = quic_sniffer;
+    }
+
     pub fn build(
         self,
         rule_set: RuleSet,
         fallback: Action,
         me: Weak<RuleDispatcher>,
     ) -> RuleDispatcher {
-        let Self { resolver, actions } = self;
+        let Self {
+            resolver,
+            actions,
+            verdict_cache,
+            quic_sniffer,
+        } = self;
         RuleDispatcher {
             resolver,
             rule_set,
             actions,
             fallback,
+            verdict_cache,
+            quic_sniffer,
             me,
         }
     }
@@ -0,0 +1,75 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use cidr::{Ipv4Cidr, Ipv6Cidr};
+
+use crate::plugin::rule_dispatcher::cidr_trie::{CidrTrie, DEFAULT_TAG};
+use crate::plugin::rule_dispatcher::rules::CidrTrieSet;
+
+use super::*;
+
+impl RuleSet {
+    /// Build from a plain CIDR list, one `CIDR[,tag]` per line. Lines whose tag
+    /// is not mapped to an action are ignored. Returns `None` if a line is not
+    /// a valid CIDR.
+    pub fn build_cidr_list<'s>(
+        lines: impl Iterator<Item = &'s str>,
+        tag_action_map: &BTreeMap<&str, ActionHandle>,
+    ) -> Option<Self> {
+        let rule_id = 1;
+        let mut ipv4_rules = vec![];
+        let mut ipv6_rules = vec![];
+        for line in lines.map(|l| l.trim()) {
+            if line.is_empty() || line.starts_with(['#', ';']) {
+                continue;
+            }
+            let (cidr, tag) = line
+                .split_once(',')
+                .map(|(c, t)| (c.trim(), t.trim()))
+                .unwrap_or((line, DEFAULT_TAG));
+            let Some(action) = tag_action_map.get(tag) else {
+                continue;
+            };
+            let handle = RuleHandle::new(*action, rule_id);
+            if let Ok(cidr) = Ipv4Cidr::from_str(cidr) {
+                ipv4_rules.push((cidr, handle));
+            } else {
+                ipv6_rules.push((Ipv6Cidr::from_str(cidr).ok()?, handle));
+            }
+        }
+        ipv4_rules.sort_by_key(|(cidr, _)| *cidr);
+        ipv6_rules.sort_by_key(|(cidr, _)| *cidr);
+
+        Some(Self {
+            dst_ipv4_ordered_set: ipv4_rules,
+            dst_ipv6_ordered_set: ipv6_rules,
+            first_resolving_rule_id: Some(rule_id),
+            ..Default::default()
+        })
+    }
+
+    /// Build from a CIDR trie compiled by `ytflow-app-util`. The trie is
+    /// queried in place.
+    pub fn build_cidr_trie(
+        tag_action_map: &BTreeMap<&str, ActionHandle>,
+        trie: Arc<[u8]>,
+    ) -> Option<Self> {
+        let rule_id = 1;
+        let trie = CidrTrie::parse(trie)?;
+        let tag_rules = trie
+            .tags()
+            .iter()
+            .map(|tag| {
+                tag_action_map
+                    .get(tag.as_str())
+                    .map(|action| RuleHandle::new(*action, rule_id))
+            })
+            .collect();
+
+        Some(Self {
+            dst_cidr_trie: Some(CidrTrieSet { trie, tag_rules }),
+            first_resolving_rule_id: Some(rule_id),
+            ..Default::default()
+        })
+    }
+}
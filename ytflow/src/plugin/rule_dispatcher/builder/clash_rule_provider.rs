@@ -0,0 +1,95 @@
+use std::collections::BTreeMap;
+
+use itertools::Itertools;
+
+use crate::plugin::rule_dispatcher::cidr_trie::DEFAULT_TAG;
+use crate::plugin::rule_dispatcher::rules::logical::split_top_level;
+
+use super::*;
+
+/// Turn an entry of a classical rule provider, e.g. `- 'IP-CIDR,1.0.0.0/8,no-resolve'`,
+/// into a QuanX filter line routed to [`DEFAULT_TAG`].
+fn to_quanx_line(line: &str) -> Option<String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') || line.starts_with("payload:") {
+        return None;
+    }
+    let line = line.strip_prefix('-').unwrap_or(line);
+    let line = line.split(" #").next().unwrap_or_default().trim();
+    let line = ['\'', '"']
+        .iter()
+        .find_map(|q| line.strip_prefix(*q)?.strip_suffix(*q))
+        .unwrap_or(line);
+    let parts = split_top_level(line)?;
+    let [ty, value, rest @ ..] = &parts[..] else {
+        return None;
+    };
+    Some([ty, value, &DEFAULT_TAG].into_iter().chain(rest).join(","))
+}
+
+impl RuleSet {
+    /// Build from a Clash rule provider of the `classical` behavior, either
+    /// the YAML `payload` or the plain text list. All entries are routed to
+    /// the action of rule `default`. Entries of types unknown to QuanX
+    /// filters, such as `PROCESS-NAME`, are skipped since the process of a
+    /// flow is not known here.
+    pub fn load_clash_rule_provider<'s>(
+        lines: impl Iterator<Item = &'s str>,
+        tag_action_map: &BTreeMap<&str, ActionHandle>,
+        geoip_db: Option<Arc<[u8]>>,
+    ) -> Option<Self> {
+        let lines: Vec<_> = lines.filter_map(to_quanx_line).collect();
+        Self::load_quanx_filter(lines.iter().map(|l| l.as_str()), tag_action_map, geoip_db)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    #[test]
+    fn test_to_quanx_line() {
+        assert_eq!(
+            to_quanx_line("  - DOMAIN-SUFFIX,example.com").as_deref(),
+            Some("DOMAIN-SUFFIX,example.com,default")
+        );
+        assert_eq!(
+            to_quanx_line("  - 'IP-CIDR,10.0.0.0/8,no-resolve' # private").as_deref(),
+            Some("IP-CIDR,10.0.0.0/8,default,no-resolve")
+        );
+        assert_eq!(
+            to_quanx_line(r#"- "AND,((DOMAIN,a.com),(DST-PORT,443))""#).as_deref(),
+            Some("AND,((DOMAIN,a.com),(DST-PORT,443)),default")
+        );
+        assert_eq!(to_quanx_line("payload:"), None);
+        assert_eq!(to_quanx_line("# comment"), None);
+    }
+
+    #[test]
+    fn test_load_clash_rule_provider() {
+        let action = ActionHandle(0);
+        let map = BTreeMap::from([("default", action)]);
+        let provider = "\
+            payload:\n\
+            \x20 - DOMAIN,www.example.com\n\
+            \x20 - DOMAIN-SUFFIX,example.org\n\
+            \x20 - DOMAIN-KEYWORD,tracker\n\
+            \x20 - IP-CIDR,10.0.0.0/8,no-resolve\n\
+            \x20 - PROCESS-NAME,curl\n";
+        let set = RuleSet::load_clash_rule_provider(provider.lines(), &map, None).unwrap();
+        let match_domain = |domain| set.r#match(None, None, None, Some(domain), Some(443), None);
+        assert_eq!(match_domain("www.example.com"), Some(action));
+        assert_eq!(match_domain("a.example.com"), None);
+        assert_eq!(match_domain("a.example.org"), Some(action));
+        assert_eq!(match_domain("eu.tracker.io"), Some(action));
+        assert_eq!(match_domain("curl"), None);
+        let ip = Some(Ipv4Addr::new(10, 1, 2, 3));
+        assert_eq!(
+            set.r#match(None, ip, None, None, Some(443), None),
+            Some(action)
+        );
+        assert!(!set.should_resolve(None, "www.example.net", Some(443), None));
+    }
+}
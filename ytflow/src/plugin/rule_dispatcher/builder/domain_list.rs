@@ -0,0 +1,208 @@
+use std::collections::BTreeMap;
+
+use aho_corasick::AhoCorasick;
+use regex::bytes::{Regex, RegexSet};
+
+use crate::plugin::rule_dispatcher::cidr_trie::DEFAULT_TAG;
+use crate::plugin::rule_dispatcher::rules::DomainTrieBuilder;
+use crate::plugin::rule_dispatcher::set::{
+    IdRangeHandle, RuleMappedAhoCorasick, RuleMappedRegexSet,
+};
+
+use super::*;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(super) enum DomainKind {
+    Keyword,
+    Regex,
+    /// The domain and all its subdomains.
+    Suffix,
+    Full,
+}
+
+/// A rule key in the form of `category[@attribute]`, e.g. `cn` or
+/// `category-ads-all@ads`.
+pub(super) struct Selector<'k> {
+    category: &'k str,
+    attribute: Option<&'k str>,
+    action: ActionHandle,
+}
+
+pub(super) fn parse_selectors<'k>(
+    tag_action_map: &BTreeMap<&'k str, ActionHandle>,
+    prefix: &str,
+) -> Vec<Selector<'k>> {
+    tag_action_map
+        .iter()
+        .map(|(key, action)| {
+            let key = key.strip_prefix(prefix).unwrap_or(key);
+            let (category, attribute) = match key.split_once('@') {
+                Some((c, a)) => (c, Some(a)),
+                None => (key, None),
+            };
+            Selector {
+                category,
+                attribute,
+                action: *action,
+            }
+        })
+        .collect()
+}
+
+pub(super) fn has_category(selectors: &[Selector], category: &str) -> bool {
+    selectors
+        .iter()
+        .any(|s| s.category.eq_ignore_ascii_case(category))
+}
+
+/// Pick the rule for an entry of `category` with `attributes`. Selectors
+/// with an attribute take precedence over those without, so that e.g.
+/// `cn@ads` overrides `cn` for subdomains of a `cn` domain.
+pub(super) fn select(
+    selectors: &[Selector],
+    category: &str,
+    attributes: &[&str],
+) -> Option<RuleHandle> {
+    let mut it = selectors
+        .iter()
+        .filter(|s| s.category.eq_ignore_ascii_case(category));
+    let by_attribute = it.clone().find(|s| {
+        s.attribute
+            .is_some_and(|a| attributes.iter().any(|attr| attr.eq_ignore_ascii_case(a)))
+    });
+    match by_attribute {
+        Some(s) => Some(RuleHandle::new(s.action, 1)),
+        None => it
+            .find(|s| s.attribute.is_none())
+            .map(|s| RuleHandle::new(s.action, 2)),
+    }
+}
+
+#[derive(Default)]
+pub(super) struct DomainRules {
+    trie: DomainTrieBuilder,
+    keywords: Vec<String>,
+    keyword_ranges: Vec<IdRangeHandle>,
+    regexes: Vec<String>,
+    regex_ranges: Vec<IdRangeHandle>,
+}
+
+impl DomainRules {
+    pub(super) fn insert(&mut self, kind: DomainKind, value: &str, handle: RuleHandle) {
+        match kind {
+            DomainKind::Suffix | DomainKind::Full => self.trie.insert(
+                &value.to_ascii_lowercase(),
+                handle,
+                kind == DomainKind::Suffix,
+            ),
+            DomainKind::Keyword => {
+                push_id_range_handle_into_sorted(
+                    &mut self.keyword_ranges,
+                    self.keywords.len(),
+                    handle,
+                );
+                self.keywords.push(value.to_ascii_lowercase());
+            }
+            DomainKind::Regex => {
+                // One invalid pattern would fail the whole set.
+                if Regex::new(value).is_err() {
+                    return;
+                }
+                push_id_range_handle_into_sorted(
+                    &mut self.regex_ranges,
+                    self.regexes.len(),
+                    handle,
+                );
+                self.regexes.push(value.to_string());
+            }
+        }
+    }
+
+    pub(super) fn build(self) -> Option<RuleSet> {
+        let dst_domain_keyword = if self.keywords.is_empty() {
+            None
+        } else {
+            Some(RuleMappedAhoCorasick {
+                handle_map: self.keyword_ranges,
+                ac: AhoCorasick::new(&self.keywords).ok()?,
+            })
+        };
+        let dst_domain_regex = if self.regexes.is_empty() {
+            None
+        } else {
+            Some(RuleMappedRegexSet {
+                handle_map: self.regex_ranges,
+                regex_set: RegexSet::new(&self.regexes).ok()?,
+            })
+        };
+        Some(RuleSet {
+            dst_domain_trie: Some(self.trie.build()),
+            dst_domain_keyword,
+            dst_domain_regex,
+            ..Default::default()
+        })
+    }
+}
+
+impl RuleSet {
+    /// Build from a domain list in the format of v2fly domain-list-community,
+    /// e.g. `full:example.com @ads`. Rule `default` selects all entries, and
+    /// `default@attribute` those with the attribute. `include:` lines are
+    /// ignored since other lists are not available.
+    pub fn build_domain_list<'s>(
+        lines: impl Iterator<Item = &'s str>,
+        tag_action_map: &BTreeMap<&str, ActionHandle>,
+    ) -> Option<Self> {
+        let selectors = parse_selectors(tag_action_map, "");
+        let mut rules = DomainRules::default();
+        let mut attributes = vec![];
+        for line in lines {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let mut segs = line.split_whitespace();
+            let Some(rule) = segs.next() else {
+                continue;
+            };
+            let (kind, value) = match rule.split_once(':') {
+                None => (DomainKind::Suffix, rule),
+                Some(("domain", v)) => (DomainKind::Suffix, v),
+                Some(("full", v)) => (DomainKind::Full, v),
+                Some(("keyword", v)) => (DomainKind::Keyword, v),
+                Some(("regexp", v)) => (DomainKind::Regex, v),
+                Some(_) => continue,
+            };
+            attributes.clear();
+            attributes.extend(segs.filter_map(|s| s.strip_prefix('@')));
+            if let Some(handle) = select(&selectors, DEFAULT_TAG, &attributes) {
+                rules.insert(kind, value, handle);
+            }
+        }
+        rules.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_domain_list() {
+        let direct = ActionHandle(0);
+        let reject = ActionHandle(1);
+        let map = BTreeMap::from([("default", direct), ("default@ads", reject)]);
+        let list = "\
+            # comment\n\
+            example.com\n\
+            full:www.example.org @cn\n\
+            keyword:tracker @ads\n\
+            regexp:^ad[0-9]+\\.example\\.net$ @ads # trailing comment\n\
+            include:other\n";
+        let set = RuleSet::build_domain_list(list.lines(), &map).unwrap();
+        let r#match = |domain| set.r#match(None, None, None, Some(domain), Some(443), None);
+        assert_eq!(r#match("a.example.com."), Some(direct));
+        assert_eq!(r#match("www.example.org"), Some(direct));
+        assert_eq!(r#match("a.www.example.org"), None);
+        assert_eq!(r#match("eu.tracker.io"), Some(reject));
+        assert_eq!(r#match("ad42.example.net"), Some(reject));
+        assert_eq!(r#match("other"), None);
+    }
+}
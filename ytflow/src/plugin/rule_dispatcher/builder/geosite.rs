@@ -0,0 +1,169 @@
+use std::collections::BTreeMap;
+
+use super::domain_list::{has_category, parse_selectors, select, DomainKind, DomainRules};
+use super::*;
+
+enum Field<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+fn read_varint(buf: &mut &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = buf.split_first()?;
+        *buf = rest;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// Walk through fields of a protobuf message. Returns `None` if the message
+/// is malformed or `f` fails.
+fn for_each_field<'a>(
+    mut buf: &'a [u8],
+    mut f: impl FnMut(u64, Field<'a>) -> Option<()>,
+) -> Option<()> {
+    while !buf.is_empty() {
+        let key = read_varint(&mut buf)?;
+        let field = match key & 0x7 {
+            0 => Field::Varint(read_varint(&mut buf)?),
+            1 | 5 => {
+                let len = if key & 0x7 == 1 { 8 } else { 4 };
+                buf = buf.get(len..)?;
+                Field::Fixed
+            }
+            2 => {
+                let len = usize::try_from(read_varint(&mut buf)?).ok()?;
+                let (bytes, rest) = (buf.get(..len)?, buf.get(len..)?);
+                buf = rest;
+                Field::Bytes(bytes)
+            }
+            _ => return None,
+        };
+        f(key >> 3, field)?;
+    }
+    Some(())
+}
+
+impl RuleSet {
+    /// Build from a v2ray `geosite.dat`. Rules are keyed by category, with an
+    /// optional `geosite:` prefix and `@attribute` suffix, e.g.
+    /// `geosite:geolocation-!cn` or `category-ads-all@ads`.
+    pub fn build_geosite(
+        data: &[u8],
+        tag_action_map: &BTreeMap<&str, ActionHandle>,
+    ) -> Option<Self> {
+        let selectors = parse_selectors(tag_action_map, "geosite:");
+        let mut rules = DomainRules::default();
+        let mut attributes = vec![];
+        // message GeoSiteList { repeated GeoSite entry = 1; }
+        for_each_field(data, |field, value| {
+            let (1, Field::Bytes(entry)) = (field, value) else {
+                return Some(());
+            };
+            // message GeoSite { string country_code = 1; repeated Domain domain = 2; }
+            let mut category = None;
+            for_each_field(entry, |field, value| {
+                if let (1, Field::Bytes(code)) = (field, value) {
+                    category = Some(std::str::from_utf8(code).ok()?);
+                }
+                Some(())
+            })?;
+            let category = category?;
+            // Most categories are not referenced by any rule.
+            if !has_category(&selectors, category) {
+                return Some(());
+            }
+            for_each_field(entry, |field, value| {
+                let (2, Field::Bytes(domain)) = (field, value) else {
+                    return Some(());
+                };
+                // message Domain { Type type = 1; string value = 2; repeated Attribute attribute = 3; }
+                let mut kind = 0;
+                let mut value = "";
+                attributes.clear();
+                for_each_field(domain, |field, v| {
+                    match (field, v) {
+                        (1, Field::Varint(t)) => kind = t,
+                        (2, Field::Bytes(v)) => value = std::str::from_utf8(v).ok()?,
+                        // message Attribute { string key = 1; ... }
+                        (3, Field::Bytes(attr)) => for_each_field(attr, |field, v| {
+                            if let (1, Field::Bytes(key)) = (field, v) {
+                                attributes.push(std::str::from_utf8(key).ok()?);
+                            }
+                            Some(())
+                        })?,
+                        _ => {}
+                    }
+                    Some(())
+                })?;
+                let kind = match kind {
+                    0 => DomainKind::Keyword,
+                    1 => DomainKind::Regex,
+                    2 => DomainKind::Suffix,
+                    3 => DomainKind::Full,
+                    _ => return Some(()),
+                };
+                if let Some(handle) = select(&selectors, category, &attributes) {
+                    rules.insert(kind, value, handle);
+                }
+                Some(())
+            })
+        })?;
+        rules.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes_field(field: u8, data: &[u8]) -> Vec<u8> {
+        assert!(data.len() < 0x80);
+        let mut ret = vec![field << 3 | 2, data.len() as u8];
+        ret.extend_from_slice(data);
+        ret
+    }
+
+    fn domain(kind: u8, value: &str, attrs: &[&str]) -> Vec<u8> {
+        let mut ret = vec![1 << 3, kind];
+        ret.extend(bytes_field(2, value.as_bytes()));
+        for attr in attrs {
+            // Attribute { key = 1; bool_value = 2; }
+            let mut attr = bytes_field(1, attr.as_bytes());
+            attr.extend([2 << 3, 1]);
+            ret.extend(bytes_field(3, &attr));
+        }
+        bytes_field(2, &ret)
+    }
+
+    #[test]
+    fn test_geosite() {
+        let mut cn = bytes_field(1, b"CN");
+        cn.extend(domain(2, "example.cn", &[]));
+        cn.extend(domain(3, "www.example.com", &[]));
+        cn.extend(domain(2, "ads.example.cn", &["ads"]));
+        let mut google = domain(0, "google", &[]);
+        // Category code may come after domains.
+        google.extend(bytes_field(1, b"GOOGLE"));
+        let mut data = bytes_field(1, &cn);
+        data.extend(bytes_field(1, &google));
+
+        let direct = ActionHandle(0);
+        let reject = ActionHandle(1);
+        let map = BTreeMap::from([("geosite:cn", direct), ("cn@ads", reject)]);
+        let set = RuleSet::build_geosite(&data, &map).unwrap();
+        let r#match = |domain| set.r#match(None, None, None, Some(domain), Some(443), None);
+        assert_eq!(r#match("a.example.cn"), Some(direct));
+        assert_eq!(r#match("www.example.com"), Some(direct));
+        assert_eq!(r#match("x.ads.example.cn"), Some(reject));
+        assert_eq!(r#match("www.google.com"), None);
+
+        assert!(RuleSet::build_geosite(&data[..data.len() - 1], &map).is_none());
+    }
+}
@@ -5,6 +5,7 @@ use aho_corasick::AhoCorasick;
 use cidr::{Ipv4Cidr, Ipv6Cidr};
 use itertools::Itertools;
 
+use crate::plugin::rule_dispatcher::rules::{Condition, DomainTrie, DomainTrieBuilder};
 use crate::plugin::rule_dispatcher::set::{IdRangeHandle, RuleMappedAhoCorasick};
 
 use super::*;
@@ -59,21 +60,6 @@ impl<I> QuanxIpRule<I> {
     }
 }
 
-fn push_id_range_handle_into_sorted(
-    ranges: &mut Vec<IdRangeHandle>,
-    idx: usize,
-    handle: RuleHandle,
-) {
-    if let Some((idx_range, _)) = ranges
-        .last_mut()
-        .filter(|(_, last)| last.action() == handle.action())
-    {
-        *idx_range = idx_range.start..idx_range.end.max(idx + 1);
-        return;
-    }
-    ranges.push((idx..idx + 1, handle));
-}
-
 fn build_ac_from_line_segs<'s, S: Iterator<Item = &'s str>>(
     lines: impl Iterator<Item = (RuleId, S)>,
     accepted_rule_types: &'static [&'static str],
@@ -97,6 +83,41 @@ fn build_ac_from_line_segs<'s, S: Iterator<Item = &'s str>>(
     AhoCorasick::builder().build(it).ok()
 }
 
+fn build_domain_trie_from_line_segs<'s, S: Iterator<Item = &'s str>>(
+    lines: impl Iterator<Item = (RuleId, S)>,
+    action_map: &BTreeMap<&str, ActionHandle>,
+) -> DomainTrie {
+    let mut builder = DomainTrieBuilder::default();
+    for (rule_id, mut segs) in lines {
+        let Some(rule_type) = segs.next() else {
+            continue;
+        };
+        let include_subdomains = if ["host", "domain"]
+            .iter()
+            .any(|r| rule_type.eq_ignore_ascii_case(r))
+        {
+            false
+        } else if ["host-suffix", "domain-suffix"]
+            .iter()
+            .any(|r| rule_type.eq_ignore_ascii_case(r))
+        {
+            true
+        } else {
+            continue;
+        };
+        let Some(QuanxDomainRule { domain, action }) =
+            QuanxDomainRule::parse_line(segs, action_map)
+        else {
+            continue;
+        };
+        let Ok(domain) = std::str::from_utf8(&domain) else {
+            continue;
+        };
+        builder.insert(domain, RuleHandle::new(action, rule_id), include_subdomains);
+    }
+    builder.build()
+}
+
 fn build_ip_rules_from_line_segs<'s, 'r, 'f: 'r, S: Iterator<Item = &'s str>, I>(
     lines: impl Iterator<Item = (RuleId, S)> + 'r,
     accepted_rule_types: &'static [&'static str],
@@ -143,33 +164,22 @@ impl RuleSet {
         action_map: &BTreeMap<&'a str, ActionHandle>,
         geoip_db: Option<Arc<[u8]>>,
     ) -> Option<Self> {
-        let lines = lines
+        let raw_lines = lines
             .map(|l| l.trim())
             .filter(|l| !l.starts_with(['#', ';']) && !l.is_empty())
             .enumerate()
-            .map(|(idx, l)| (idx as u32 + 1, l.split(',').map(|s| s.trim())));
-        let (mut full_rule_ranges, mut sub_rule_ranges, mut keyword_rule_ranges) =
-            (vec![], vec![], vec![]);
-        let (full_ac, sub_ac, keyword_ac) = (
-            build_ac_from_line_segs(
-                lines.clone(),
-                &["host", "domain"],
-                action_map,
-                &mut full_rule_ranges,
-            )?,
-            build_ac_from_line_segs(
-                lines.clone(),
-                &["host-suffix", "domain-suffix"],
-                action_map,
-                &mut sub_rule_ranges,
-            )?,
-            build_ac_from_line_segs(
-                lines.clone(),
-                &["host-keyword", "domain-keyword"],
-                action_map,
-                &mut keyword_rule_ranges,
-            )?,
-        );
+            .map(|(idx, l)| (idx as u32 + 1, l));
+        let lines = raw_lines
+            .clone()
+            .map(|(id, l)| (id, l.split(',').map(|s| s.trim())));
+        let domain_trie = build_domain_trie_from_line_segs(lines.clone(), action_map);
+        let mut keyword_rule_ranges = vec![];
+        let keyword_ac = build_ac_from_line_segs(
+            lines.clone(),
+            &["host-keyword", "domain-keyword"],
+            action_map,
+            &mut keyword_rule_ranges,
+        )?;
 
         let mut first_resolving_rule_id = None;
         let mut ipv4_rules = build_ip_rules_from_line_segs(
@@ -209,6 +219,18 @@ impl RuleSet {
             }
         };
 
+        let logical_rules = raw_lines
+            .filter_map(|(rule_id, line)| {
+                let (cond, action, no_resolve) = Condition::parse_rule_line(line)?;
+                let action = action_map.get(action)?;
+                if cond.needs_ip() && !no_resolve {
+                    first_resolving_rule_id =
+                        Some(first_resolving_rule_id.unwrap_or(rule_id).min(rule_id));
+                }
+                Some((cond, RuleHandle::new(*action, rule_id)))
+            })
+            .collect();
+
         let final_rule = lines
             .filter_map(|(id, mut segs)| {
                 if !segs.next()?.eq_ignore_ascii_case("final") {
@@ -220,14 +242,7 @@ impl RuleSet {
             .next();
 
         Some(Self {
-            dst_domain_full: Some(RuleMappedAhoCorasick {
-                handle_map: full_rule_ranges,
-                ac: full_ac,
-            }),
-            dst_domain_sub: Some(RuleMappedAhoCorasick {
-                handle_map: sub_rule_ranges,
-                ac: sub_ac,
-            }),
+            dst_domain_trie: Some(domain_trie),
             dst_domain_keyword: Some(RuleMappedAhoCorasick {
                 handle_map: keyword_rule_ranges,
                 ac: keyword_ac,
@@ -235,6 +250,7 @@ impl RuleSet {
             dst_ipv4_ordered_set: ipv4_rules,
             dst_ipv6_ordered_set: ipv6_rules,
             dst_geoip: geoip_rules,
+            logical_rules,
             r#final: final_rule,
             first_resolving_rule_id,
             ..Default::default()
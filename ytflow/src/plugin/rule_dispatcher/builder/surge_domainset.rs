@@ -1,35 +1,28 @@
-use aho_corasick::AhoCorasick;
-
-use crate::plugin::rule_dispatcher::set::RuleMappedAhoCorasick;
+use crate::plugin::rule_dispatcher::rules::DomainTrieBuilder;
 
 use super::*;
 
 impl RuleSet {
     pub fn build_surge_domainset<'s>(
-        lines: impl Iterator<Item = &'s str> + Clone,
+        lines: impl Iterator<Item = &'s str>,
         action: ActionHandle,
     ) -> Option<Self> {
-        let lines = lines
-            .map(|l| l.trim())
-            .filter(|l| !l.is_empty() && !l.starts_with('#'));
-        let full_ac = AhoCorasick::builder()
-            .build(lines.clone().filter(|l| !l.starts_with('.')))
-            .ok()?;
-        let sub_ac = AhoCorasick::builder()
-            .build(lines.clone().filter_map(|l| l.strip_prefix('.')))
-            .ok()?;
-
         // TODO: observe order
         let rule_id = 1;
+        let handle = RuleHandle::new(action, rule_id);
+        let mut builder = DomainTrieBuilder::default();
+        for line in lines
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        {
+            match line.strip_prefix('.') {
+                Some(suffix) => builder.insert(suffix, handle, true),
+                None => builder.insert(line, handle, false),
+            }
+        }
+
         Some(Self {
-            dst_domain_full: Some(RuleMappedAhoCorasick {
-                handle_map: vec![(0..full_ac.patterns_len(), RuleHandle::new(action, rule_id))],
-                ac: full_ac,
-            }),
-            dst_domain_sub: Some(RuleMappedAhoCorasick {
-                handle_map: vec![(0..sub_ac.patterns_len(), RuleHandle::new(action, rule_id))],
-                ac: sub_ac,
-            }),
+            dst_domain_trie: Some(builder.build()),
             ..Default::default()
         })
     }
@@ -0,0 +1,130 @@
+//! A precompiled binary trie for huge IP lists, which can be queried in place
+//! without parsing every CIDR at startup.
+//!
+//! Layout, with all integers in little endian:
+//!
+//! | Field         | Size                                |
+//! |---------------|-------------------------------------|
+//! | Magic `YTCT`  | 4                                   |
+//! | Version       | 1                                   |
+//! | Reserved      | 3                                   |
+//! | Tag count     | 2                                   |
+//! | Tags          | 1-byte length + UTF-8 bytes, each   |
+//! | IPv4 nodes    | 4 (count) + 8 × count               |
+//! | IPv6 nodes    | 4 (count) + 8 × count               |
+//!
+//! Each node holds two child references for bit 0 and bit 1, starting from the
+//! most significant bit of an address. A reference is either [`EMPTY`], a leaf
+//! (`LEAF_FLAG | tag index`), or the index of a node in the same address family.
+//! Node 0 is the root, and a child always comes after its parent.
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::ops::Range;
+
+pub const MAGIC: [u8; 4] = *b"YTCT";
+pub const VERSION: u8 = 1;
+pub const EMPTY: u32 = 0;
+pub const LEAF_FLAG: u32 = 0x8000_0000;
+pub const NODE_SIZE: usize = 8;
+/// Tag of lines in a CIDR list without an explicit `,tag` suffix.
+pub const DEFAULT_TAG: &str = "default";
+
+pub struct CidrTrie<B> {
+    data: B,
+    tags: Vec<String>,
+    v4_nodes: Range<usize>,
+    v6_nodes: Range<usize>,
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn read_nodes(data: &[u8], offset: &mut usize, tag_count: usize) -> Option<Range<usize>> {
+    let count = read_u32(data, *offset)? as usize;
+    let start = *offset + 4;
+    let end = start.checked_add(count.checked_mul(NODE_SIZE)?)?;
+    if end > data.len() {
+        return None;
+    }
+    for idx in 0..count {
+        for child in [0, 4] {
+            let r = read_u32(data, start + idx * NODE_SIZE + child)?;
+            let valid = if r == EMPTY {
+                true
+            } else if r & LEAF_FLAG != 0 {
+                ((r & !LEAF_FLAG) as usize) < tag_count
+            } else {
+                (r as usize) > idx && (r as usize) < count
+            };
+            if !valid {
+                return None;
+            }
+        }
+    }
+    *offset = end;
+    Some(start..end)
+}
+
+impl<B: AsRef<[u8]>> CidrTrie<B> {
+    /// Validate and wrap a compiled trie. Returns `None` if the data is malformed.
+    pub fn parse(data: B) -> Option<Self> {
+        let bytes = data.as_ref();
+        if bytes.get(..4)? != MAGIC || *bytes.get(4)? != VERSION {
+            return None;
+        }
+        let tag_count = u16::from_le_bytes(bytes.get(8..10)?.try_into().ok()?) as usize;
+        let mut offset = 10;
+        let mut tags = Vec::with_capacity(tag_count);
+        for _ in 0..tag_count {
+            let len = *bytes.get(offset)? as usize;
+            let tag = bytes.get(offset + 1..offset + 1 + len)?;
+            tags.push(String::from_utf8(tag.to_vec()).ok()?);
+            offset += 1 + len;
+        }
+        let v4_nodes = read_nodes(bytes, &mut offset, tag_count)?;
+        let v6_nodes = read_nodes(bytes, &mut offset, tag_count)?;
+        Some(Self {
+            data,
+            tags,
+            v4_nodes,
+            v6_nodes,
+        })
+    }
+
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// Find the tag index of the longest prefix containing `ip`.
+    pub fn lookup_v4(&self, ip: Ipv4Addr) -> Option<u16> {
+        self.lookup(self.v4_nodes.clone(), (u32::from(ip) as u128) << 96, 32)
+    }
+
+    /// Find the tag index of the longest prefix containing `ip`.
+    pub fn lookup_v6(&self, ip: Ipv6Addr) -> Option<u16> {
+        self.lookup(self.v6_nodes.clone(), u128::from(ip), 128)
+    }
+
+    fn lookup(&self, nodes: Range<usize>, bits: u128, width: u32) -> Option<u16> {
+        let data = &self.data.as_ref()[nodes];
+        if data.is_empty() {
+            return None;
+        }
+        let mut node = 0;
+        for depth in 0..width {
+            let bit = (bits >> (127 - depth)) & 1;
+            let r = read_u32(data, node * NODE_SIZE + bit as usize * 4)?;
+            if r == EMPTY {
+                return None;
+            }
+            if r & LEAF_FLAG != 0 {
+                return Some((r & !LEAF_FLAG) as u16);
+            }
+            node = r as usize;
+        }
+        None
+    }
+}
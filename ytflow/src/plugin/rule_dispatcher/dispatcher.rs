@@ -6,6 +6,7 @@ use futures::future::join;
 use smallvec::SmallVec;
 
 use super::*;
+use crate::plugin::quic_sniff::QuicSniffer;
 
 pub type ActionSet = SmallVec<[Action; 8]>;
 
@@ -14,6 +15,9 @@ pub struct RuleDispatcher {
     pub rule_set: set::RuleSet,
     pub actions: ActionSet,
     pub fallback: Action,
+    pub verdict_cache: Option<Arc<VerdictCache>>,
+    /// Sessions recognized as QUIC skip rule matching.
+    pub quic_sniffer: Option<QuicSniffer>,
     pub me: Weak<Self>,
 }
 
@@ -21,28 +25,31 @@ struct AsyncMatchContext {
     src: Option<SocketAddr>,
     dst_domain: String,
     dst_port: Option<u16>,
+    is_udp: Option<bool>,
     resolver: Arc<dyn Resolver>,
+    options: ResolveOptions,
 }
 
 impl AsyncMatchContext {
-    async fn try_match<'m>(&self, me: &'m RuleDispatcher) -> FlowResult<&'m Action> {
+    async fn try_match(&self, me: &RuleDispatcher) -> Option<ActionHandle> {
         let (v4_res, v6_res) = join(
-            self.resolver.resolve_ipv4(self.dst_domain.clone()),
-            self.resolver.resolve_ipv6(self.dst_domain.clone()),
+            self.resolver
+                .resolve_ipv4_with(self.dst_domain.clone(), self.options.clone()),
+            self.resolver
+                .resolve_ipv6_with(self.dst_domain.clone(), self.options.clone()),
         )
         .await;
         let dst_ip_v4 = v4_res.unwrap_or_default().first().copied();
         let dst_ip_v6 = v6_res.unwrap_or_default().first().copied();
         let dst_domain = Some(self.dst_domain.as_str());
-        let res = me
-            .rule_set
-            .r#match(self.src, dst_ip_v4, dst_ip_v6, dst_domain, self.dst_port)
-            .map(|id| me.actions.get(id.0 as usize));
-        match res {
-            Some(Some(a)) => Ok(a),
-            Some(None) => Err(FlowError::NoOutbound),
-            None => Ok(&me.fallback),
-        }
+        me.rule_set.r#match(
+            self.src,
+            dst_ip_v4,
+            dst_ip_v6,
+            dst_domain,
+            self.dst_port,
+            self.is_udp,
+        )
     }
 }
 
@@ -53,7 +60,26 @@ enum TryMatchResult<'a> {
 }
 
 impl RuleDispatcher {
-    fn try_match(&'_ self, context: &FlowContext) -> TryMatchResult<'_> {
+    fn action(&self, handle: Option<ActionHandle>) -> FlowResult<&Action> {
+        match handle {
+            Some(handle) => self
+                .actions
+                .get(handle.0 as usize)
+                .ok_or(FlowError::NoOutbound),
+            None => Ok(&self.fallback),
+        }
+    }
+    fn try_match(&'_ self, context: &FlowContext, is_udp: bool) -> TryMatchResult<'_> {
+        if let Some(handle) = self
+            .verdict_cache
+            .as_ref()
+            .and_then(|c| c.get(context, is_udp))
+        {
+            return match self.action(handle) {
+                Ok(a) => TryMatchResult::Matched(a),
+                Err(e) => TryMatchResult::Err(e),
+            };
+        }
         let src = Some(context.local_peer);
         let dst_port = Some(context.remote_peer.port);
         let mut dst_ip_v4 = None;
@@ -61,7 +87,9 @@ impl RuleDispatcher {
         let mut dst_domain = None;
         match (&context.remote_peer.host, &self.resolver) {
             (HostName::DomainName(domain), Some(resolver))
-                if self.rule_set.should_resolve(src, domain, dst_port) =>
+                if self
+                    .rule_set
+                    .should_resolve(src, domain, dst_port, Some(is_udp)) =>
             {
                 let Some(resolver) = resolver.upgrade() else {
                     return TryMatchResult::Err(FlowError::NoOutbound);
@@ -70,35 +98,57 @@ impl RuleDispatcher {
                     src,
                     dst_domain: domain.clone(),
                     dst_port,
+                    is_udp: Some(is_udp),
                     resolver,
+                    options: context.resolve_options(DEFAULT_RESOLVE_TIMEOUT),
                 });
             }
             (HostName::DomainName(domain), _) => dst_domain = Some(domain.as_str()),
             (HostName::Ip(IpAddr::V4(v4)), _) => dst_ip_v4 = Some(*v4),
             (HostName::Ip(IpAddr::V6(v6)), _) => dst_ip_v6 = Some(*v6),
         }
-        let res = self
-            .rule_set
-            .r#match(src, dst_ip_v4, dst_ip_v6, dst_domain, dst_port)
-            .map(|id| self.actions.get(id.0 as usize));
-        match res {
-            Some(Some(a)) => TryMatchResult::Matched(a),
-            Some(None) => TryMatchResult::Err(FlowError::NoOutbound),
-            None => TryMatchResult::Matched(&self.fallback),
+        let handle = self.rule_set.r#match(
+            src,
+            dst_ip_v4,
+            dst_ip_v6,
+            dst_domain,
+            dst_port,
+            Some(is_udp),
+        );
+        if let Some(cache) = &self.verdict_cache {
+            cache.put(context, is_udp, handle);
+        }
+        match self.action(handle) {
+            Ok(a) => TryMatchResult::Matched(a),
+            Err(e) => TryMatchResult::Err(e),
         }
     }
-    fn try_match_with(
+    fn try_match_with<I: PendingInbound>(
         &self,
         context: Box<FlowContext>,
-        cb: impl FnOnce(Box<FlowContext>, &Action) + Send + 'static,
+        mut inbound: I,
+        is_udp: bool,
+        cb: impl FnOnce(I, Box<FlowContext>, &Action) + Send + 'static,
     ) {
-        match self.try_match(&context) {
-            TryMatchResult::Matched(a) => cb(context, a),
+        match self.try_match(&context, is_udp) {
+            TryMatchResult::Matched(a) => cb(inbound, context, a),
             TryMatchResult::NeedAsync(a) => {
                 let me = self.me.upgrade().unwrap();
                 tokio::spawn(async move {
-                    match a.try_match(&me).await {
-                        Ok(a) => cb(context, a),
+                    let handle = tokio::select! {
+                        handle = a.try_match(&me) => handle,
+                        // The client is gone. Stop resolving along with other work
+                        // spawned on behalf of this flow.
+                        () = inbound.closed() => {
+                            context.abort.abort();
+                            return;
+                        }
+                    };
+                    if let Some(cache) = &me.verdict_cache {
+                        cache.put(&context, is_udp, handle);
+                    }
+                    match me.action(handle) {
+                        Ok(a) => cb(inbound, context, a),
                         Err(_) => {
                             // TODO: log error
                             return;
@@ -115,34 +165,44 @@ impl RuleDispatcher {
     async fn match_domain(&self, domain: &str) -> FlowResult<&Action> {
         if let (Some(resolver), true) = (
             self.resolver.as_ref(),
-            self.rule_set.should_resolve(None, domain, None),
+            self.rule_set.should_resolve(None, domain, None, None),
         ) {
-            AsyncMatchContext {
+            let handle = AsyncMatchContext {
                 src: None,
                 dst_domain: domain.into(),
                 dst_port: None,
+                is_udp: None,
                 resolver: resolver.upgrade().ok_or(FlowError::NoOutbound)?,
+                options: ResolveOptions {
+                    timeout: Some(DEFAULT_RESOLVE_TIMEOUT),
+                    abort: None,
+                },
             }
             .try_match(self)
-            .await
+            .await;
+            self.action(handle)
         } else {
-            let res = self
-                .rule_set
-                .r#match(None, None, None, Some(domain), None)
-                .map(|id| self.actions.get(id.0 as usize));
-            match res {
-                Some(Some(a)) => Ok(a),
-                Some(None) => Err(FlowError::NoOutbound),
-                None => Ok(&self.fallback),
-            }
+            self.action(
+                self.rule_set
+                    .r#match(None, None, None, Some(domain), None, None),
+            )
         }
     }
+    fn dispatch_session(&self, session: Box<dyn DatagramSession>, context: Box<FlowContext>) {
+        self.try_match_with(context, session, true, |session, context, a| {
+            if let Some(udp_next) = a.udp_next.upgrade() {
+                udp_next.on_session(session, context)
+            }
+        })
+    }
 }
 
 impl StreamHandler for RuleDispatcher {
     fn on_stream(&self, lower: Box<dyn Stream>, initial_data: Buffer, context: Box<FlowContext>) {
-        self.try_match_with(context, |context, a| {
+        let pending = PendingStream::new(lower, initial_data);
+        self.try_match_with(context, pending, false, |pending, context, a| {
             if let Some(tcp_next) = a.tcp_next.upgrade() {
+                let (lower, initial_data) = pending.into_parts();
                 tcp_next.on_stream(lower, initial_data, context)
             }
         })
@@ -151,9 +211,13 @@ impl StreamHandler for RuleDispatcher {
 
 impl DatagramSessionHandler for RuleDispatcher {
     fn on_session(&self, session: Box<dyn DatagramSession>, context: Box<FlowContext>) {
-        self.try_match_with(context, |context, a| {
-            if let Some(udp_next) = a.udp_next.upgrade() {
-                udp_next.on_session(session, context)
+        let Some(quic_sniffer) = &self.quic_sniffer else {
+            return self.dispatch_session(session, context);
+        };
+        let me = self.me.clone();
+        quic_sniffer.dispatch(session, context, move |session, context| {
+            if let Some(me) = me.upgrade() {
+                me.dispatch_session(session, context)
             }
         })
     }
@@ -172,3 +236,58 @@ impl Resolver for RuleDispatcher {
         resolver.resolve_ipv6(domain).await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::flow::testing::*;
+    use crate::plugin::reject::RejectHandler;
+    use crate::plugin::rule_dispatcher::cidr_trie::DEFAULT_TAG;
+
+    #[tokio::test]
+    async fn test_stream_close_cancels_dispatch() {
+        let mut aps = AccessPoints::new();
+        let (resolver, cancelled) = StallingResolver::new();
+        let resolver = aps.hold(resolver);
+        let next = MockStreamHandler::new();
+        let fallback = MockStreamHandler::new();
+        let mut builder = RuleDispatcherBuilder::default();
+        builder.set_resolver(Some(resolver.clone() as _));
+        let handle = builder
+            .add_action(Action {
+                tcp_next: aps.hold(next.clone()) as _,
+                udp_next: Weak::<RejectHandler>::new(),
+                resolver: resolver.clone() as _,
+            })
+            .unwrap();
+        // An IP rule makes the dispatcher resolve domains before matching.
+        let rule_set = RuleSet::build_cidr_list(
+            ["10.0.0.0/8"].into_iter(),
+            &BTreeMap::from([(DEFAULT_TAG, handle)]),
+        )
+        .unwrap();
+        let fallback_action = Action {
+            tcp_next: aps.hold(fallback.clone()) as _,
+            udp_next: Weak::<RejectHandler>::new(),
+            resolver: resolver as _,
+        };
+        let dispatcher = Arc::new_cyclic(|me| builder.build(rule_set, fallback_action, me.clone()));
+
+        let (stream, peer) = stream_pair();
+        let ctx = context("example.com.:443");
+        let abort = ctx.abort.signal();
+        dispatcher.on_stream(stream, vec![], ctx);
+        tokio::task::yield_now().await;
+        assert!(!abort.is_aborted());
+        drop(peer);
+
+        // Both the A and AAAA queries are given up.
+        cancelled.recv_async().await.unwrap();
+        cancelled.recv_async().await.unwrap();
+        abort.aborted().await;
+        assert!(next.try_accept().is_none());
+        assert!(fallback.try_accept().is_none());
+    }
+}
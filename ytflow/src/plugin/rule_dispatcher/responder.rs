@@ -0,0 +1,62 @@
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+
+use cbor4ii::serde::to_vec;
+use serde::Serialize;
+
+use super::VerdictCache;
+use crate::control::{PluginRequestError, PluginRequestResult, PluginResponder};
+
+#[derive(Clone, Default, Serialize, PartialEq, Eq)]
+struct Info {
+    verdict_cache_len: usize,
+    verdict_cache_hits: u64,
+    verdict_cache_misses: u64,
+}
+
+pub struct Responder {
+    verdict_cache: Arc<VerdictCache>,
+    last_info: Mutex<(Info, u32)>,
+}
+
+impl Responder {
+    pub fn new(verdict_cache: Arc<VerdictCache>) -> Self {
+        Self {
+            verdict_cache,
+            last_info: Mutex::new((Info::default(), 1)),
+        }
+    }
+}
+
+fn info_snapshot(cache: &VerdictCache) -> Info {
+    Info {
+        verdict_cache_len: cache.len(),
+        verdict_cache_hits: cache.hits.load(Ordering::Relaxed),
+        verdict_cache_misses: cache.misses.load(Ordering::Relaxed),
+    }
+}
+
+impl PluginResponder for Responder {
+    fn collect_info(&self, hashcode: &mut u32) -> Option<Vec<u8>> {
+        let info = {
+            let mut last_info_guard = self.last_info.lock().unwrap();
+            let (last_info, last_hashcode) = &mut *last_info_guard;
+            let new_info = info_snapshot(&self.verdict_cache);
+            if new_info == *last_info {
+                if *last_hashcode == *hashcode {
+                    return None;
+                }
+            } else {
+                *last_info = new_info.clone();
+                *last_hashcode = (*last_hashcode).wrapping_add(1);
+            }
+            *hashcode = *last_hashcode;
+            new_info
+        };
+        Some(to_vec(vec![], &info).unwrap())
+    }
+
+    fn on_request(&self, _func: &str, _params: &[u8]) -> PluginRequestResult<Vec<u8>> {
+        Err(PluginRequestError::NoSuchFunc)
+    }
+}
@@ -1,5 +1,11 @@
+pub(super) mod cidr_trie;
 pub(super) mod domain;
+pub(super) mod domain_trie;
 pub(super) mod geoip;
 pub(super) mod ip;
+pub(super) mod logical;
 
+pub use cidr_trie::CidrTrieSet;
+pub use domain_trie::{DomainTrie, DomainTrieBuilder};
 pub use geoip::GeoIpSet;
+pub use logical::Condition;
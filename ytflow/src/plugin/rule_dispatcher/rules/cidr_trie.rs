@@ -0,0 +1,26 @@
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
+
+use crate::plugin::rule_dispatcher::cidr_trie::CidrTrie;
+use crate::plugin::rule_dispatcher::RuleHandle;
+
+pub struct CidrTrieSet {
+    pub(crate) trie: CidrTrie<Arc<[u8]>>,
+    /// Indexed by tag index in the trie.
+    pub(crate) tag_rules: Vec<Option<RuleHandle>>,
+}
+
+impl CidrTrieSet {
+    pub fn query_v4(&self, ip: Ipv4Addr) -> impl Iterator<Item = RuleHandle> {
+        self.trie
+            .lookup_v4(ip)
+            .and_then(|tag| *self.tag_rules.get(tag as usize)?)
+            .into_iter()
+    }
+    pub fn query_v6(&self, ip: Ipv6Addr) -> impl Iterator<Item = RuleHandle> {
+        self.trie
+            .lookup_v6(ip)
+            .and_then(|tag| *self.tag_rules.get(tag as usize)?)
+            .into_iter()
+    }
+}
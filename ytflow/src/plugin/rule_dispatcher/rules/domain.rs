@@ -24,16 +24,10 @@ impl RuleSet {
         mut domain: &'a str,
     ) -> impl Iterator<Item = RuleHandle> + 'a {
         domain = domain.strip_suffix('.').unwrap_or(domain);
-        let full_it = self
-            .dst_domain_full
+        let trie_it = self
+            .dst_domain_trie
             .iter()
-            .flat_map(|ac_set| match_ac(domain, ac_set, |m| m.range() == (0..domain.len())));
-        let sub_it = self.dst_domain_sub.iter().flat_map(|ac_set| {
-            match_ac(domain, ac_set, |m| {
-                m.end() == domain.len()
-                    && (m.start() == 0 || domain.as_bytes()[m.start() - 1] == b'.')
-            })
-        });
+            .flat_map(|trie| trie.query(domain));
         let keyword_it = self
             .dst_domain_keyword
             .iter()
@@ -50,6 +44,6 @@ impl RuleSet {
             });
             handle_it
         });
-        full_it.chain(sub_it).chain(keyword_it).chain(regex_it)
+        trie_it.chain(keyword_it).chain(regex_it)
     }
 }
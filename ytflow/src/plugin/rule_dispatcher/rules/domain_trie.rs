@@ -0,0 +1,252 @@
+use std::collections::{BTreeMap, HashMap};
+
+use smallvec::SmallVec;
+
+use crate::plugin::rule_dispatcher::RuleHandle;
+
+#[derive(Clone, Copy)]
+struct Edge {
+    label_start: u32,
+    label_len: u32,
+    child: u32,
+}
+
+/// Domain rules stored in a trie keyed by labels from the TLD. Looking up a
+/// domain walks one node per label, regardless of the number of rules, unlike
+/// an Aho-Corasick scan whose matches have to be filtered afterwards.
+///
+/// Nodes are flattened into arrays, with edges of the same node sorted by label
+/// for binary search. Labels are deduplicated into a shared pool.
+pub struct DomainTrie {
+    label_pool: Box<str>,
+    /// Edges of node `i` are `edges[node_edges[i]..node_edges[i + 1]]`.
+    node_edges: Box<[u32]>,
+    edges: Box<[Edge]>,
+    full: Box<[Option<RuleHandle>]>,
+    sub: Box<[Option<RuleHandle>]>,
+}
+
+#[derive(Default)]
+struct BuilderNode {
+    children: BTreeMap<Box<str>, usize>,
+    full: Option<RuleHandle>,
+    sub: Option<RuleHandle>,
+}
+
+pub struct DomainTrieBuilder {
+    nodes: Vec<BuilderNode>,
+}
+
+fn keep_first_rule(slot: &mut Option<RuleHandle>, handle: RuleHandle) {
+    match slot {
+        Some(existing) if existing.rule_id() <= handle.rule_id() => {}
+        _ => *slot = Some(handle),
+    }
+}
+
+impl Default for DomainTrieBuilder {
+    fn default() -> Self {
+        Self {
+            nodes: vec![BuilderNode::default()],
+        }
+    }
+}
+
+impl DomainTrieBuilder {
+    /// Add a rule matching `domain` exactly, or also all its subdomains if
+    /// `include_subdomains` is set.
+    pub fn insert(&mut self, domain: &str, handle: RuleHandle, include_subdomains: bool) {
+        let domain = domain.strip_suffix('.').unwrap_or(domain);
+        if domain.is_empty() {
+            return;
+        }
+        let mut node = 0;
+        for label in domain.rsplit('.') {
+            node = match self.nodes[node].children.get(label) {
+                Some(&child) => child,
+                None => {
+                    let child = self.nodes.len();
+                    self.nodes.push(BuilderNode::default());
+                    self.nodes[node].children.insert(label.into(), child);
+                    child
+                }
+            };
+        }
+        let node = &mut self.nodes[node];
+        if include_subdomains {
+            keep_first_rule(&mut node.sub, handle);
+        } else {
+            keep_first_rule(&mut node.full, handle);
+        }
+    }
+
+    pub fn build(self) -> DomainTrie {
+        let node_count = self.nodes.len();
+        let mut label_pool = String::new();
+        let mut label_offsets: HashMap<&str, u32> = HashMap::new();
+        let mut node_edges = Vec::with_capacity(node_count + 1);
+        let mut edges = Vec::with_capacity(node_count.saturating_sub(1));
+        let mut full = Vec::with_capacity(node_count);
+        let mut sub = Vec::with_capacity(node_count);
+        for node in &self.nodes {
+            node_edges.push(edges.len() as u32);
+            // BTreeMap iterates in label order, ready for binary search
+            for (label, &child) in &node.children {
+                let label_start = *label_offsets.entry(&**label).or_insert_with(|| {
+                    let start = label_pool.len() as u32;
+                    label_pool.push_str(label);
+                    start
+                });
+                edges.push(Edge {
+                    label_start,
+                    label_len: label.len() as u32,
+                    child: child as u32,
+                });
+            }
+            full.push(node.full);
+            sub.push(node.sub);
+        }
+        node_edges.push(edges.len() as u32);
+        DomainTrie {
+            label_pool: label_pool.into_boxed_str(),
+            node_edges: node_edges.into_boxed_slice(),
+            edges: edges.into_boxed_slice(),
+            full: full.into_boxed_slice(),
+            sub: sub.into_boxed_slice(),
+        }
+    }
+}
+
+impl DomainTrie {
+    fn label(&self, edge: &Edge) -> &str {
+        let start = edge.label_start as usize;
+        &self.label_pool[start..start + edge.label_len as usize]
+    }
+
+    fn find_child(&self, node: usize, label: &str) -> Option<usize> {
+        let edges = &self.edges[self.node_edges[node] as usize..self.node_edges[node + 1] as usize];
+        edges
+            .binary_search_by(|e| self.label(e).cmp(label))
+            .ok()
+            .map(|idx| edges[idx].child as usize)
+    }
+
+    pub fn query(&self, domain: &str) -> impl Iterator<Item = RuleHandle> {
+        let mut ret = SmallVec::<[RuleHandle; 4]>::new();
+        let mut node = 0;
+        for label in domain.rsplit('.') {
+            match self.find_child(node, label) {
+                Some(child) => node = child,
+                None => return ret.into_iter(),
+            }
+            ret.extend(self.sub[node]);
+        }
+        ret.extend(self.full[node]);
+        ret.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use aho_corasick::AhoCorasick;
+
+    use super::*;
+    use crate::plugin::rule_dispatcher::ActionHandle;
+
+    fn handle(rule_id: u32) -> RuleHandle {
+        RuleHandle::new(ActionHandle(0), rule_id)
+    }
+
+    fn rule_ids(trie: &DomainTrie, domain: &str) -> Vec<u32> {
+        let mut ids: Vec<_> = trie.query(domain).map(|h| h.rule_id()).collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn test_domain_trie_query() {
+        let mut builder = DomainTrieBuilder::default();
+        builder.insert("example.com", handle(1), false);
+        builder.insert("example.com", handle(2), true);
+        builder.insert("a.example.com", handle(3), false);
+        builder.insert("org", handle(4), true);
+        let trie = builder.build();
+
+        assert_eq!(rule_ids(&trie, "example.com"), [1, 2]);
+        assert_eq!(rule_ids(&trie, "a.example.com"), [2, 3]);
+        assert_eq!(rule_ids(&trie, "b.a.example.com"), [2]);
+        assert_eq!(rule_ids(&trie, "notexample.com"), Vec::<u32>::new());
+        assert_eq!(rule_ids(&trie, "com"), Vec::<u32>::new());
+        assert_eq!(rule_ids(&trie, "x.y.org"), [4]);
+        assert_eq!(rule_ids(&trie, ""), Vec::<u32>::new());
+    }
+
+    #[test]
+    fn test_domain_trie_keeps_first_rule() {
+        let mut builder = DomainTrieBuilder::default();
+        builder.insert("example.com", handle(5), true);
+        builder.insert("example.com", handle(3), true);
+        builder.insert("example.com", handle(7), true);
+        let trie = builder.build();
+        assert_eq!(rule_ids(&trie, "example.com"), [3]);
+    }
+
+    /// Compare against the Aho-Corasick implementation with a large domain
+    /// set. Run with `cargo test --release -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_domain_trie_vs_aho_corasick() {
+        const RULE_COUNT: usize = 1_000_000;
+        const QUERY_COUNT: usize = 100_000;
+        let domains: Vec<_> = (0..RULE_COUNT)
+            .map(|i| format!("host{}.site{}.com", i, i % 1000))
+            .collect();
+        let queries: Vec<_> = (0..QUERY_COUNT)
+            .map(|i| format!("www.host{}.site{}.com", i * 7, (i * 7) % 1000))
+            .collect();
+
+        let start = Instant::now();
+        let ac = AhoCorasick::builder().build(&domains).unwrap();
+        let ac_build = start.elapsed();
+        let start = Instant::now();
+        let mut ac_hits = 0;
+        for q in &queries {
+            ac_hits += ac
+                .find_overlapping_iter(q.as_str())
+                .filter(|m| {
+                    m.end() == q.len() && (m.start() == 0 || q.as_bytes()[m.start() - 1] == b'.')
+                })
+                .count();
+        }
+        let ac_query = start.elapsed();
+
+        let start = Instant::now();
+        let mut builder = DomainTrieBuilder::default();
+        for d in &domains {
+            builder.insert(d, handle(1), true);
+        }
+        let trie = builder.build();
+        let trie_build = start.elapsed();
+        let start = Instant::now();
+        let mut trie_hits = 0;
+        for q in &queries {
+            trie_hits += trie.query(q).count();
+        }
+        let trie_query = start.elapsed();
+
+        assert_eq!(ac_hits, trie_hits);
+        println!(
+            "Aho-Corasick: build {:?}, {} queries {:?}, heap {} bytes",
+            ac_build,
+            QUERY_COUNT,
+            ac_query,
+            ac.memory_usage()
+        );
+        println!(
+            "Domain trie:  build {:?}, {} queries {:?}",
+            trie_build, QUERY_COUNT, trie_query
+        );
+    }
+}
@@ -0,0 +1,348 @@
+use std::borrow::Cow;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use cidr::{Cidr, Ipv4Cidr, Ipv6Cidr};
+use maxminddb::geoip2;
+
+use super::super::{RuleHandle, RuleSet};
+
+/// What is known about a flow when matching logical rules. `None` fields are
+/// unknown, such as IP addresses of a domain name not resolved yet, except for
+/// `dst_domain`: a flow to an IP address has no domain name to match.
+#[derive(Clone, Copy, Default)]
+pub(in super::super) struct MatchInput<'a> {
+    pub(in super::super) dst_ip_v4: Option<Ipv4Addr>,
+    pub(in super::super) dst_ip_v6: Option<Ipv6Addr>,
+    /// Without the trailing dot.
+    pub(in super::super) dst_domain: Option<&'a str>,
+    pub(in super::super) dst_port: Option<u16>,
+    pub(in super::super) is_udp: Option<bool>,
+}
+
+/// A condition of a logical rule such as
+/// `AND,((DOMAIN-SUFFIX,example.com),(NOT,((DST-PORT,443)))),direct`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Condition {
+    Domain(Box<str>),
+    DomainSuffix(Box<str>),
+    DomainKeyword(Box<str>),
+    Ipv4Cidr(Ipv4Cidr),
+    Ipv6Cidr(Ipv6Cidr),
+    GeoIp(Box<str>),
+    DstPort(RangeInclusive<u16>),
+    Network { udp: bool },
+    And(Vec<Condition>),
+    Or(Vec<Condition>),
+    Not(Box<Condition>),
+}
+
+/// Split `s` at commas outside parentheses.
+pub(in super::super) fn split_top_level(s: &str) -> Option<Vec<&str>> {
+    let mut depth = 0u32;
+    let mut start = 0;
+    let mut parts = vec![];
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.checked_sub(1)?,
+            ',' if depth == 0 => {
+                parts.push(s[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        return None;
+    }
+    parts.push(s[start..].trim());
+    Some(parts)
+}
+
+fn strip_parens(s: &str) -> Option<&str> {
+    s.strip_prefix('(')?.strip_suffix(')').map(|s| s.trim())
+}
+
+fn parse_port_range(s: &str) -> Option<RangeInclusive<u16>> {
+    let (start, end) = s.split_once('-').unwrap_or((s, s));
+    let (start, end) = (start.trim().parse().ok()?, end.trim().parse().ok()?);
+    (start <= end).then_some(start..=end)
+}
+
+fn cidr_contains<C: Cidr>(cidr: &C, ip: Option<C::Address>) -> bool {
+    ip.is_some_and(|ip| cidr.contains(&ip))
+}
+
+/// Combine results of `conds` in three-valued logic, where `None` means
+/// unknown. `all` selects AND over OR.
+fn combine(mut results: impl Iterator<Item = Option<bool>>, all: bool) -> Option<bool> {
+    let mut unknown = false;
+    let decisive = results.any(|r| match r {
+        Some(r) => r != all,
+        None => {
+            unknown = true;
+            false
+        }
+    });
+    if decisive {
+        Some(!all)
+    } else if unknown {
+        None
+    } else {
+        Some(all)
+    }
+}
+
+impl Condition {
+    /// Parse a line of a logical rule. Returns the condition, the action name
+    /// and whether `no-resolve` is set.
+    pub fn parse_rule_line(line: &str) -> Option<(Self, &str, bool)> {
+        let parts = split_top_level(line)?;
+        let [ty, group, action, rest @ ..] = &parts[..] else {
+            return None;
+        };
+        if !["and", "or", "not"]
+            .iter()
+            .any(|t| ty.eq_ignore_ascii_case(t))
+        {
+            return None;
+        }
+        let cond = Self::parse_parts(ty, &[group])?;
+        let no_resolve = rest
+            .first()
+            .is_some_and(|s| s.eq_ignore_ascii_case("no-resolve"));
+        Some((cond, action, no_resolve))
+    }
+
+    fn parse_parts(ty: &str, args: &[&str]) -> Option<Self> {
+        let ty = ty.to_ascii_lowercase();
+        if let "and" | "or" | "not" = &*ty {
+            let [group] = args else {
+                return None;
+            };
+            let mut children = split_top_level(strip_parens(group)?)?
+                .into_iter()
+                .map(|c| {
+                    let parts = split_top_level(strip_parens(c)?)?;
+                    let (ty, args) = parts.split_first()?;
+                    Self::parse_parts(ty, args)
+                })
+                .collect::<Option<Vec<_>>>()?;
+            return match &*ty {
+                "and" if !children.is_empty() => Some(Self::And(children)),
+                "or" if !children.is_empty() => Some(Self::Or(children)),
+                "not" if children.len() == 1 => Some(Self::Not(Box::new(children.pop()?))),
+                _ => None,
+            };
+        }
+        let [value] = args else {
+            return None;
+        };
+        let domain = || value.trim_end_matches('.').to_ascii_lowercase().into();
+        Some(match &*ty {
+            "host" | "domain" => Self::Domain(domain()),
+            "host-suffix" | "domain-suffix" => Self::DomainSuffix(domain()),
+            "host-keyword" | "domain-keyword" => Self::DomainKeyword(domain()),
+            "ip-cidr" => Self::Ipv4Cidr(Ipv4Cidr::from_str(value).ok()?),
+            "ip6-cidr" | "ip-cidr6" => Self::Ipv6Cidr(Ipv6Cidr::from_str(value).ok()?),
+            "geoip" => Self::GeoIp(value.to_ascii_uppercase().into()),
+            "dst-port" => Self::DstPort(parse_port_range(value)?),
+            "network" | "protocol" => Self::Network {
+                udp: match &*value.to_ascii_lowercase() {
+                    "tcp" => false,
+                    "udp" => true,
+                    _ => return None,
+                },
+            },
+            _ => return None,
+        })
+    }
+
+    /// Whether IP addresses of the destination are needed to evaluate.
+    pub fn needs_ip(&self) -> bool {
+        match self {
+            Self::Ipv4Cidr(_) | Self::Ipv6Cidr(_) | Self::GeoIp(_) => true,
+            Self::And(conds) | Self::Or(conds) => conds.iter().any(Self::needs_ip),
+            Self::Not(cond) => cond.needs_ip(),
+            _ => false,
+        }
+    }
+
+    fn eval(
+        &self,
+        input: &MatchInput,
+        geoip_reader: Option<&maxminddb::Reader<Arc<[u8]>>>,
+    ) -> Option<bool> {
+        let has_ip = input.dst_ip_v4.is_some() || input.dst_ip_v6.is_some();
+        match self {
+            Self::Domain(d) => Some(input.dst_domain == Some(&**d)),
+            Self::DomainSuffix(s) => Some(input.dst_domain.is_some_and(|domain| {
+                domain
+                    .strip_suffix(&**s)
+                    .is_some_and(|rest| rest.is_empty() || rest.ends_with('.'))
+            })),
+            Self::DomainKeyword(k) => Some(input.dst_domain.is_some_and(|d| d.contains(&**k))),
+            Self::Ipv4Cidr(cidr) => has_ip.then(|| cidr_contains(cidr, input.dst_ip_v4)),
+            Self::Ipv6Cidr(cidr) => has_ip.then(|| cidr_contains(cidr, input.dst_ip_v6)),
+            Self::GeoIp(code) => has_ip.then(|| {
+                let Some(reader) = geoip_reader else {
+                    return false;
+                };
+                let ips = [
+                    input.dst_ip_v4.map(IpAddr::from),
+                    input.dst_ip_v6.map(IpAddr::from),
+                ];
+                ips.into_iter().flatten().any(|ip| {
+                    let country: Option<geoip2::Country> = reader.lookup(ip).ok();
+                    country
+                        .and_then(|c| c.country)
+                        .and_then(|c| c.iso_code)
+                        .is_some_and(|c| c == &**code)
+                })
+            }),
+            Self::DstPort(range) => input.dst_port.map(|port| range.contains(&port)),
+            Self::Network { udp } => input.is_udp.map(|is_udp| is_udp == *udp),
+            Self::And(conds) => combine(conds.iter().map(|c| c.eval(input, geoip_reader)), true),
+            Self::Or(conds) => combine(conds.iter().map(|c| c.eval(input, geoip_reader)), false),
+            Self::Not(cond) => cond.eval(input, geoip_reader).map(|r| !r),
+        }
+    }
+}
+
+impl RuleSet {
+    /// Logical rules certainly matching `input`.
+    pub(in super::super) fn match_logical_impl<'a>(
+        &'a self,
+        input: MatchInput<'a>,
+    ) -> impl Iterator<Item = RuleHandle> + 'a {
+        let geoip_reader = self.dst_geoip.as_ref().map(|g| &g.geoip_reader);
+        // Conditions are lowercased when parsed.
+        let dst_domain = input.dst_domain.map(|d| {
+            if d.bytes().any(|b| b.is_ascii_uppercase()) {
+                Cow::Owned(d.to_ascii_lowercase())
+            } else {
+                Cow::Borrowed(d)
+            }
+        });
+        self.logical_rules
+            .iter()
+            .filter(move |(cond, _)| {
+                let input = MatchInput {
+                    dst_domain: dst_domain.as_deref(),
+                    ..input
+                };
+                cond.eval(&input, geoip_reader) == Some(true)
+            })
+            .map(|(_, handle)| *handle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rule_line() {
+        let (cond, action, no_resolve) = Condition::parse_rule_line(
+            "AND,((DOMAIN-SUFFIX,Example.com),(NOT,((DST-PORT,443)))),direct",
+        )
+        .unwrap();
+        assert_eq!(
+            cond,
+            Condition::And(vec![
+                Condition::DomainSuffix("example.com".into()),
+                Condition::Not(Box::new(Condition::DstPort(443..=443))),
+            ])
+        );
+        assert_eq!((action, no_resolve), ("direct", false));
+
+        let (cond, _, no_resolve) = Condition::parse_rule_line(
+            "OR,((IP-CIDR,10.0.0.0/8),(NETWORK,UDP),(DST-PORT,8000-9000)),proxy,no-resolve",
+        )
+        .unwrap();
+        assert!(cond.needs_ip());
+        assert!(no_resolve);
+
+        assert!(Condition::parse_rule_line("AND,((DOMAIN,a.com),direct").is_none());
+        assert!(Condition::parse_rule_line("NOT,((DOMAIN,a.com),(DOMAIN,b.com)),direct").is_none());
+        assert!(Condition::parse_rule_line("AND,((PROCESS-NAME,a.exe)),direct").is_none());
+        assert!(Condition::parse_rule_line("DOMAIN,a.com,direct").is_none());
+    }
+
+    #[test]
+    fn test_eval() {
+        let (cond, _, _) = Condition::parse_rule_line(
+            "AND,((DOMAIN-SUFFIX,example.com),(NOT,((DST-PORT,443)))),direct",
+        )
+        .unwrap();
+        let input = MatchInput {
+            dst_domain: Some("www.example.com"),
+            dst_port: Some(80),
+            ..Default::default()
+        };
+        assert_eq!(cond.eval(&input, None), Some(true));
+        let https = MatchInput {
+            dst_port: Some(443),
+            ..input
+        };
+        assert_eq!(cond.eval(&https, None), Some(false));
+        let other = MatchInput {
+            dst_domain: Some("notexample.com"),
+            ..input
+        };
+        assert_eq!(cond.eval(&other, None), Some(false));
+
+        let (cond, _, _) =
+            Condition::parse_rule_line("OR,((IP-CIDR,10.0.0.0/8),(NETWORK,UDP)),direct").unwrap();
+        let tcp = MatchInput {
+            dst_domain: Some("example.com"),
+            is_udp: Some(false),
+            ..Default::default()
+        };
+        // Unknown until the domain is resolved
+        assert_eq!(cond.eval(&tcp, None), None);
+        let resolved = MatchInput {
+            dst_ip_v4: Some(Ipv4Addr::new(10, 1, 2, 3)),
+            ..tcp
+        };
+        assert_eq!(cond.eval(&resolved, None), Some(true));
+    }
+
+    #[test]
+    fn test_eval_without_domain() {
+        let (cond, _, _) =
+            Condition::parse_rule_line("NOT,((DOMAIN-SUFFIX,example.com)),direct").unwrap();
+        let ip_only = MatchInput {
+            dst_ip_v4: Some(Ipv4Addr::new(1, 1, 1, 1)),
+            dst_port: Some(443),
+            ..Default::default()
+        };
+        assert_eq!(cond.eval(&ip_only, None), Some(true));
+        let (cond, _, _) =
+            Condition::parse_rule_line("AND,((DOMAIN-KEYWORD,google),(DST-PORT,443)),proxy")
+                .unwrap();
+        assert_eq!(cond.eval(&ip_only, None), Some(false));
+    }
+
+    #[test]
+    fn test_match_mixed_case_domain() {
+        let (cond, action, _) =
+            Condition::parse_rule_line("OR,((DOMAIN,www.example.com)),direct").unwrap();
+        assert_eq!(action, "direct");
+        let handle = RuleHandle::new(super::super::super::ActionHandle(0), 1);
+        let rule_set = RuleSet {
+            logical_rules: vec![(cond, handle)],
+            ..Default::default()
+        };
+        let input = MatchInput {
+            dst_domain: Some("WWW.Example.com"),
+            ..Default::default()
+        };
+        let matched: Vec<_> = rule_set.match_logical_impl(input).collect();
+        assert_eq!(matched.len(), 1);
+        assert_eq!(matched[0].rule_id(), 1);
+    }
+}
@@ -5,6 +5,7 @@ use aho_corasick::AhoCorasick;
 use cidr::{Ipv4Cidr, Ipv6Cidr};
 use regex::bytes::RegexSet;
 
+use super::rules::logical::MatchInput;
 use super::{rules, ActionHandle, RuleHandle, RuleId};
 
 fn reduce_rules(it: impl Iterator<Item = RuleHandle>) -> Option<RuleHandle> {
@@ -26,12 +27,15 @@ pub(super) struct RuleMappedAhoCorasick {
 #[derive(Default)]
 pub struct RuleSet {
     pub(super) dst_domain_regex: Option<RuleMappedRegexSet>,
-    pub(super) dst_domain_full: Option<RuleMappedAhoCorasick>,
-    pub(super) dst_domain_sub: Option<RuleMappedAhoCorasick>,
+    /// Full and suffix domain rules
+    pub(super) dst_domain_trie: Option<rules::DomainTrie>,
     pub(super) dst_domain_keyword: Option<RuleMappedAhoCorasick>,
     pub(super) dst_geoip: Option<rules::GeoIpSet>,
+    pub(super) dst_cidr_trie: Option<rules::CidrTrieSet>,
     pub(super) dst_ipv4_ordered_set: Vec<(Ipv4Cidr, RuleHandle)>,
     pub(super) dst_ipv6_ordered_set: Vec<(Ipv6Cidr, RuleHandle)>,
+    /// AND/OR/NOT compositions of other rules
+    pub(super) logical_rules: Vec<(rules::Condition, RuleHandle)>,
     pub(super) r#final: Option<RuleHandle>,
     pub(super) first_resolving_rule_id: Option<RuleId>,
 }
@@ -41,11 +45,22 @@ impl RuleSet {
         &self,
         _src: Option<SocketAddr>,
         dst_domain: &str,
-        _dst_port: Option<u16>,
+        dst_port: Option<u16>,
+        is_udp: Option<bool>,
     ) -> bool {
+        let logical_it = self.match_logical_impl(MatchInput {
+            dst_domain: Some(dst_domain.strip_suffix('.').unwrap_or(dst_domain)),
+            dst_port,
+            is_udp,
+            ..Default::default()
+        });
         match (
             self.first_resolving_rule_id,
-            reduce_rules(self.match_domain_impl(dst_domain).chain(self.r#final)),
+            reduce_rules(
+                self.match_domain_impl(dst_domain)
+                    .chain(logical_it)
+                    .chain(self.r#final),
+            ),
         ) {
             (None, _) => false,
             (Some(_), None) => true,
@@ -58,7 +73,8 @@ impl RuleSet {
         dst_ip_v4: Option<Ipv4Addr>,
         dst_ip_v6: Option<Ipv6Addr>,
         dst_domain: Option<&str>,
-        _dst_port: Option<u16>,
+        dst_port: Option<u16>,
+        is_udp: Option<bool>,
     ) -> Option<ActionHandle> {
         let min_rule_id = if let (Some(_), Some(_), _) | (Some(_), _, Some(_)) =
             (&dst_domain, &dst_ip_v4, &dst_ip_v6)
@@ -83,7 +99,17 @@ impl RuleSet {
                 .as_ref()
                 .into_iter()
                 .flat_map(|geoip| geoip.query(ip.into()));
-            reduce_rules(ip_it.chain(geoip_it).filter(min_rule_id_filter))
+            let trie_it = self
+                .dst_cidr_trie
+                .as_ref()
+                .into_iter()
+                .flat_map(|trie| trie.query_v4(ip));
+            reduce_rules(
+                ip_it
+                    .chain(geoip_it)
+                    .chain(trie_it)
+                    .filter(min_rule_id_filter),
+            )
         });
         let v6_res = dst_ip_v6.and_then(|ip| {
             let ip_it = self.match_ipv6_impl(ip);
@@ -92,13 +118,34 @@ impl RuleSet {
                 .as_ref()
                 .into_iter()
                 .flat_map(|geoip| geoip.query(ip.into()));
-            reduce_rules(ip_it.chain(geoip_it).filter(min_rule_id_filter))
+            let trie_it = self
+                .dst_cidr_trie
+                .as_ref()
+                .into_iter()
+                .flat_map(|trie| trie.query_v6(ip));
+            reduce_rules(
+                ip_it
+                    .chain(geoip_it)
+                    .chain(trie_it)
+                    .filter(min_rule_id_filter),
+            )
         });
+        let logical_res = reduce_rules(
+            self.match_logical_impl(MatchInput {
+                dst_ip_v4,
+                dst_ip_v6,
+                dst_domain: dst_domain.map(|d| d.strip_suffix('.').unwrap_or(d)),
+                dst_port,
+                is_udp,
+            })
+            .filter(min_rule_id_filter),
+        );
         let final_res = reduce_rules(
             v4_res
                 .into_iter()
                 .chain(v6_res)
                 .chain(domain_res)
+                .chain(logical_res)
                 .chain(self.r#final.filter(min_rule_id_filter)),
         );
         final_res.map(|r| r.action())
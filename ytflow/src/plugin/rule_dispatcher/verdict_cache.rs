@@ -0,0 +1,128 @@
+use std::num::NonZeroUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lru::LruCache;
+
+use super::ActionHandle;
+use crate::atomic::AtomicU64;
+use crate::flow::*;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct VerdictKey {
+    host: HostName,
+    port: u16,
+    is_udp: bool,
+}
+
+#[derive(Clone, Copy)]
+struct Verdict {
+    /// `None` for the fallback action.
+    action: Option<ActionHandle>,
+    expires_at: Instant,
+}
+
+/// Remembers which action a destination was dispatched to, so that repeated
+/// connections skip matching against the rule set, which may be huge.
+pub struct VerdictCache {
+    entries: Mutex<LruCache<VerdictKey, Verdict>>,
+    ttl: Duration,
+    pub(super) hits: AtomicU64,
+    pub(super) misses: AtomicU64,
+}
+
+impl VerdictCache {
+    pub fn new(capacity: NonZeroUsize, ttl: Duration) -> Self {
+        Self {
+            entries: Mutex::new(LruCache::new(capacity)),
+            ttl,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    fn key(context: &FlowContext, is_udp: bool) -> VerdictKey {
+        VerdictKey {
+            host: context.remote_peer.host.clone(),
+            port: context.remote_peer.port,
+            is_udp,
+        }
+    }
+
+    /// The cached action for the destination of `context`. The outer `None`
+    /// means a miss.
+    pub(super) fn get(&self, context: &FlowContext, is_udp: bool) -> Option<Option<ActionHandle>> {
+        let key = Self::key(context, is_udp);
+        let mut entries = self.entries.lock().unwrap();
+        let verdict = match entries.get(&key) {
+            Some(v) if v.expires_at > Instant::now() => Some(v.action),
+            Some(_) => {
+                entries.pop(&key);
+                None
+            }
+            None => None,
+        };
+        drop(entries);
+        let counter = if verdict.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        verdict
+    }
+
+    pub(super) fn put(&self, context: &FlowContext, is_udp: bool, action: Option<ActionHandle>) {
+        self.entries.lock().unwrap().put(
+            Self::key(context, is_udp),
+            Verdict {
+                action,
+                expires_at: Instant::now() + self.ttl,
+            },
+        );
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(host: &str, port: u16) -> FlowContext {
+        FlowContext::new(
+            ([127, 0, 0, 1], 1234).into(),
+            DestinationAddr {
+                host: HostName::from_domain_name(host.into()).unwrap(),
+                port,
+            },
+        )
+    }
+
+    #[test]
+    fn test_get_put() {
+        let cache = VerdictCache::new(NonZeroUsize::new(2).unwrap(), Duration::from_secs(60));
+        let ctx = context("example.com", 443);
+        assert_eq!(cache.get(&ctx, false), None);
+        cache.put(&ctx, false, Some(ActionHandle(1)));
+        cache.put(&context("example.org", 443), false, None);
+        assert_eq!(cache.get(&ctx, false), Some(Some(ActionHandle(1))));
+        assert_eq!(cache.get(&ctx, true), None);
+        assert_eq!(cache.get(&context("example.com", 80), false), None);
+        assert_eq!(cache.get(&context("example.org", 443), false), Some(None));
+        assert_eq!(cache.hits.load(Ordering::Relaxed), 2);
+        assert_eq!(cache.misses.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn test_expiry() {
+        let cache = VerdictCache::new(NonZeroUsize::new(2).unwrap(), Duration::ZERO);
+        let ctx = context("example.com", 443);
+        cache.put(&ctx, false, Some(ActionHandle(0)));
+        assert_eq!(cache.get(&ctx, false), None);
+        assert_eq!(cache.len(), 0);
+    }
+}
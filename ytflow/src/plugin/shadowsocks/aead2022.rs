@@ -0,0 +1,394 @@
+//! Shared parts of the AEAD-2022 ciphers, as specified in
+//! [SIP022](https://github.com/Shadowsocks-NET/shadowsocks-specs/blob/main/2022-1-shadowsocks-2022-edition.md).
+
+use std::collections::{HashSet, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use aes_gcm::aes::{Aes128, Aes256};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use cipher::generic_array::GenericArray;
+use cipher::{BlockDecrypt, BlockEncrypt, KeyInit};
+
+use super::util::{parse_dest, write_dest};
+use crate::flow::DestinationAddr;
+
+pub const HEADER_TYPE_CLIENT: u8 = 0;
+pub const HEADER_TYPE_SERVER: u8 = 1;
+/// Maximum difference allowed between the timestamp in a header and the local clock.
+const MAX_TIME_DIFF: u64 = 30;
+/// Salts must be remembered for at least twice the allowed time difference.
+const SALT_POOL_TTL: Duration = Duration::from_secs(MAX_TIME_DIFF * 2);
+const MAX_PADDING_LEN: u16 = 900;
+
+const SESSION_SUBKEY_CONTEXT: &str = "shadowsocks 2022 session subkey";
+const IDENTITY_SUBKEY_CONTEXT: &str = "shadowsocks 2022 identity subkey";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidPsk;
+
+/// Pre-shared keys of an AEAD-2022 server. With multiple users or relays, the
+/// password is a colon-separated list of base64-encoded keys ending with the user
+/// key, each preceding key being an identity key of a hop (iPSK).
+pub struct PskList {
+    pub identity_keys: Vec<Box<[u8]>>,
+    pub user_key: Box<[u8]>,
+}
+
+impl PskList {
+    pub fn parse(
+        password: &[u8],
+        key_len: usize,
+        allow_identity: bool,
+    ) -> Result<Self, InvalidPsk> {
+        let mut keys = password
+            .split(|&b| b == b':')
+            .map(|k| {
+                STANDARD
+                    .decode(k)
+                    .ok()
+                    .filter(|k| k.len() == key_len)
+                    .map(Vec::into_boxed_slice)
+                    .ok_or(InvalidPsk)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let user_key = keys.pop().ok_or(InvalidPsk)?;
+        if !allow_identity && !keys.is_empty() {
+            return Err(InvalidPsk);
+        }
+        Ok(Self {
+            identity_keys: keys,
+            user_key,
+        })
+    }
+
+    /// The key used to encrypt the first layer sent to the server.
+    pub fn first_key(&self) -> &[u8] {
+        self.identity_keys.first().unwrap_or(&self.user_key)
+    }
+
+    /// Pairs of each identity key with the key of the next hop.
+    fn identity_chain(&self) -> impl Iterator<Item = (&[u8], &[u8])> {
+        let next_keys = self
+            .identity_keys
+            .iter()
+            .skip(1)
+            .chain(std::iter::once(&self.user_key));
+        self.identity_keys
+            .iter()
+            .zip(next_keys)
+            .map(|(k, n)| (&**k, &**n))
+    }
+
+    /// Extensible identity headers of a TCP request.
+    pub fn stream_identity_headers(&self, salt: &[u8], buf: &mut Vec<u8>) {
+        for (key, next_key) in self.identity_chain() {
+            let mut subkey = [0u8; 32];
+            let subkey = &mut subkey[..key.len()];
+            derive_key(IDENTITY_SUBKEY_CONTEXT, key, salt, subkey);
+            let mut block = psk_hash(next_key);
+            aes_encrypt_block(subkey, &mut block);
+            buf.extend_from_slice(&block);
+        }
+    }
+
+    /// Extensible identity headers of a UDP packet with the given separate header.
+    pub fn datagram_identity_headers(&self, separate_header: &[u8; 16], buf: &mut Vec<u8>) {
+        for (key, next_key) in self.identity_chain() {
+            let mut block = psk_hash(next_key);
+            block
+                .iter_mut()
+                .zip(separate_header)
+                .for_each(|(b, h)| *b ^= h);
+            aes_encrypt_block(key, &mut block);
+            buf.extend_from_slice(&block);
+        }
+    }
+}
+
+fn derive_key(context: &str, key: &[u8], salt: &[u8], out: &mut [u8]) {
+    let mut material = Vec::with_capacity(key.len() + salt.len());
+    material.extend_from_slice(key);
+    material.extend_from_slice(salt);
+    // The output of BLAKE3 for a shorter length is a prefix of the longer one.
+    let derived = blake3::derive_key(context, &material);
+    out.copy_from_slice(&derived[..out.len()]);
+}
+
+pub fn derive_session_subkey(key: &[u8], salt: &[u8], subkey: &mut [u8]) {
+    derive_key(SESSION_SUBKEY_CONTEXT, key, salt, subkey)
+}
+
+fn psk_hash(key: &[u8]) -> [u8; 16] {
+    let hash = blake3::hash(key);
+    hash.as_bytes()[..16].try_into().unwrap()
+}
+
+pub fn aes_encrypt_block(key: &[u8], block: &mut [u8; 16]) {
+    let block = GenericArray::from_mut_slice(block);
+    match key.len() {
+        16 => Aes128::new_from_slice(key).unwrap().encrypt_block(block),
+        32 => Aes256::new_from_slice(key).unwrap().encrypt_block(block),
+        _ => unreachable!("AES-2022 keys are either 16 or 32 bytes"),
+    }
+}
+
+pub fn aes_decrypt_block(key: &[u8], block: &mut [u8; 16]) {
+    let block = GenericArray::from_mut_slice(block);
+    match key.len() {
+        16 => Aes128::new_from_slice(key).unwrap().decrypt_block(block),
+        32 => Aes256::new_from_slice(key).unwrap().decrypt_block(block),
+        _ => unreachable!("AES-2022 keys are either 16 or 32 bytes"),
+    }
+}
+
+pub fn now_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+pub fn is_timestamp_valid(timestamp: u64) -> bool {
+    now_timestamp().abs_diff(timestamp) <= MAX_TIME_DIFF
+}
+
+pub fn random_padding_len() -> u16 {
+    let mut buf = [0u8; 2];
+    getrandom::getrandom(&mut buf).unwrap();
+    u16::from_ne_bytes(buf) % MAX_PADDING_LEN + 1
+}
+
+/// Fixed-length header of a TCP request: type, timestamp and the length of the
+/// variable-length header that follows.
+pub fn write_request_fixed_header(buf: &mut Vec<u8>, var_header_len: u16) {
+    buf.push(HEADER_TYPE_CLIENT);
+    buf.extend_from_slice(&now_timestamp().to_be_bytes());
+    buf.extend_from_slice(&var_header_len.to_be_bytes());
+}
+
+/// Variable-length header of a TCP request: destination, padding and initial payload.
+/// Padding is mandatory when there is no initial payload. Returns the length of
+/// the header, or `None` if it does not fit into the length field of the
+/// fixed-length header.
+pub fn write_request_var_header(
+    buf: &mut Vec<u8>,
+    dest: &DestinationAddr,
+    initial_data: &[u8],
+) -> Option<u16> {
+    let start = buf.len();
+    write_dest(buf, dest);
+    let padding_len = if initial_data.is_empty() {
+        random_padding_len()
+    } else {
+        0
+    };
+    buf.extend_from_slice(&padding_len.to_be_bytes());
+    let padding_start = buf.len();
+    buf.resize(padding_start + padding_len as usize, 0);
+    getrandom::getrandom(&mut buf[padding_start..]).unwrap();
+    buf.extend_from_slice(initial_data);
+    u16::try_from(buf.len() - start).ok()
+}
+
+pub const fn response_fixed_header_len(salt_len: usize) -> usize {
+    1 + 8 + salt_len + 2
+}
+
+/// Validates a decrypted fixed-length response header against the request salt.
+/// Returns the length of the first payload chunk.
+pub fn parse_response_fixed_header(header: &[u8], request_salt: &[u8]) -> Option<u16> {
+    let salt_len = request_salt.len();
+    if header.len() != response_fixed_header_len(salt_len) || header[0] != HEADER_TYPE_SERVER {
+        return None;
+    }
+    let timestamp = u64::from_be_bytes(header[1..9].try_into().unwrap());
+    if !is_timestamp_valid(timestamp) || header[9..9 + salt_len] != *request_salt {
+        return None;
+    }
+    Some(u16::from_be_bytes(
+        header[9 + salt_len..].try_into().unwrap(),
+    ))
+}
+
+/// Main header of a UDP packet from the client: type, timestamp, padding and destination.
+pub fn write_client_datagram_header(buf: &mut Vec<u8>, dest: &DestinationAddr) {
+    buf.push(HEADER_TYPE_CLIENT);
+    buf.extend_from_slice(&now_timestamp().to_be_bytes());
+    buf.extend_from_slice(&0u16.to_be_bytes());
+    write_dest(buf, dest);
+}
+
+/// Validates the decrypted main header of a UDP packet from the server. Returns the
+/// source of the packet and where the payload starts.
+pub fn parse_server_datagram_header(
+    body: &[u8],
+    client_session_id: u64,
+) -> Option<(DestinationAddr, usize)> {
+    if body.len() < 1 + 8 + 8 + 2 || body[0] != HEADER_TYPE_SERVER {
+        return None;
+    }
+    let timestamp = u64::from_be_bytes(body[1..9].try_into().unwrap());
+    let session_id = u64::from_be_bytes(body[9..17].try_into().unwrap());
+    if !is_timestamp_valid(timestamp) || session_id != client_session_id {
+        return None;
+    }
+    let padding_len = u16::from_be_bytes(body[17..19].try_into().unwrap());
+    let dest_offset = 19 + padding_len as usize;
+    let (dest, dest_len) = parse_dest(body.get(dest_offset..)?)?;
+    Some((dest, dest_offset + dest_len))
+}
+
+/// Salts seen in the last minute, to reject replayed responses.
+#[derive(Default)]
+pub struct SaltPool {
+    inner: Mutex<(HashSet<Box<[u8]>>, VecDeque<(Instant, Box<[u8]>)>)>,
+}
+
+impl SaltPool {
+    /// Returns `false` if the salt has been seen before.
+    pub fn check_and_insert(&self, salt: &[u8]) -> bool {
+        let now = Instant::now();
+        let mut guard = self.inner.lock().unwrap();
+        let (set, queue) = &mut *guard;
+        while let Some((time, _)) = queue.front() {
+            if now.duration_since(*time) < SALT_POOL_TTL {
+                break;
+            }
+            let (_, expired) = queue.pop_front().unwrap();
+            set.remove(&expired);
+        }
+        if !set.insert(salt.into()) {
+            return false;
+        }
+        queue.push_back((now, salt.into()));
+        true
+    }
+}
+
+/// Sliding window over packet IDs of a UDP session to reject replayed packets.
+#[derive(Default)]
+pub struct ReplayWindow {
+    max: Option<u64>,
+    /// Bit `i` is set if packet `max - i` has been received.
+    bitmap: u64,
+}
+
+impl ReplayWindow {
+    /// Returns `false` if the packet has been received or is too old.
+    pub fn check_and_set(&mut self, packet_id: u64) -> bool {
+        let Some(max) = self.max else {
+            self.max = Some(packet_id);
+            self.bitmap = 1;
+            return true;
+        };
+        if packet_id > max {
+            let shift = packet_id - max;
+            self.bitmap = if shift >= 64 { 0 } else { self.bitmap << shift };
+            self.bitmap |= 1;
+            self.max = Some(packet_id);
+            return true;
+        }
+        let offset = max - packet_id;
+        if offset >= 64 || self.bitmap & (1 << offset) != 0 {
+            return false;
+        }
+        self.bitmap |= 1 << offset;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flow::HostName;
+
+    #[test]
+    fn test_psk_list_parse() {
+        let k1 = STANDARD.encode([1u8; 16]);
+        let k2 = STANDARD.encode([2u8; 16]);
+        let psk = PskList::parse(format!("{k1}:{k2}").as_bytes(), 16, true).unwrap();
+        assert_eq!(&*psk.identity_keys[0], &[1u8; 16]);
+        assert_eq!(&*psk.user_key, &[2u8; 16]);
+        assert_eq!(psk.first_key(), &[1u8; 16]);
+        assert!(PskList::parse(format!("{k1}:{k2}").as_bytes(), 16, false).is_err());
+        assert!(PskList::parse(k1.as_bytes(), 32, true).is_err());
+        assert!(PskList::parse(b"not base64", 16, true).is_err());
+    }
+
+    #[test]
+    fn test_aes_block_roundtrip() {
+        let key = [7u8; 32];
+        let mut block = [3u8; 16];
+        aes_encrypt_block(&key, &mut block);
+        assert_ne!(block, [3u8; 16]);
+        aes_decrypt_block(&key, &mut block);
+        assert_eq!(block, [3u8; 16]);
+    }
+
+    #[test]
+    fn test_request_var_header_len() {
+        let dest = DestinationAddr {
+            host: HostName::Ip([127, 0, 0, 1].into()),
+            port: 443,
+        };
+        let mut buf = vec![];
+        let len = write_request_var_header(&mut buf, &dest, b"hello").unwrap();
+        assert_eq!(len as usize, buf.len());
+
+        let initial_data = vec![0; u16::MAX as usize];
+        assert_eq!(
+            write_request_var_header(&mut vec![], &dest, &initial_data),
+            None
+        );
+    }
+
+    #[test]
+    fn test_parse_response_fixed_header() {
+        let salt = [5u8; 16];
+        let mut header = vec![HEADER_TYPE_SERVER];
+        header.extend_from_slice(&now_timestamp().to_be_bytes());
+        header.extend_from_slice(&salt);
+        header.extend_from_slice(&1234u16.to_be_bytes());
+        assert_eq!(parse_response_fixed_header(&header, &salt), Some(1234));
+        assert_eq!(parse_response_fixed_header(&header, &[6u8; 16]), None);
+        header[1..9].copy_from_slice(&0u64.to_be_bytes());
+        assert_eq!(parse_response_fixed_header(&header, &salt), None);
+    }
+
+    #[test]
+    fn test_parse_server_datagram_header() {
+        let mut body = vec![HEADER_TYPE_SERVER];
+        body.extend_from_slice(&now_timestamp().to_be_bytes());
+        body.extend_from_slice(&42u64.to_be_bytes());
+        body.extend_from_slice(&3u16.to_be_bytes());
+        body.extend_from_slice(&[0; 3]);
+        body.extend_from_slice(&[0x01, 1, 1, 1, 1, 0, 53]);
+        body.extend_from_slice(b"payload");
+        let (dest, offset) = parse_server_datagram_header(&body, 42).unwrap();
+        assert_eq!(dest.to_string(), "1.1.1.1:53");
+        assert_eq!(&body[offset..], b"payload");
+        assert!(parse_server_datagram_header(&body, 43).is_none());
+    }
+
+    #[test]
+    fn test_salt_pool() {
+        let pool = SaltPool::default();
+        assert!(pool.check_and_insert(b"salt"));
+        assert!(!pool.check_and_insert(b"salt"));
+        assert!(pool.check_and_insert(b"pepper"));
+    }
+
+    #[test]
+    fn test_replay_window() {
+        let mut window = ReplayWindow::default();
+        assert!(window.check_and_set(5));
+        assert!(!window.check_and_set(5));
+        assert!(window.check_and_set(3));
+        assert!(window.check_and_set(70));
+        assert!(!window.check_and_set(3));
+        assert!(window.check_and_set(69));
+        assert!(!window.check_and_set(69));
+    }
+}
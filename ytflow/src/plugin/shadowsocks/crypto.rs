@@ -20,7 +20,7 @@ use hkdf::Hkdf;
 use sha1::Sha1;
 
 use super::util::increase_num_buf;
-use aead::RustCryptoAead;
+use aead::{Blake3Subkey, RustCryptoAead};
 use cfb128::RustCryptoCfb128;
 use ctor::{KeyIvCtor, KeyOnlyCtor, Rc4Md5Ctor};
 pub use plain::Plain;
@@ -54,6 +54,11 @@ pub type Aes256Gcm = RustCryptoAead<aes_gcm::AesGcm<Aes256, U12>, 32>;
 pub type Chacha20IetfPoly1305 = RustCryptoAead<chacha20poly1305::ChaCha20Poly1305, 32>;
 pub type XChacha20IetfPoly1305 = RustCryptoAead<chacha20poly1305::XChaCha20Poly1305, 32>;
 
+pub type Aes128Gcm2022 = RustCryptoAead<aes_gcm::AesGcm<Aes128, U12>, 16, Blake3Subkey>;
+pub type Aes256Gcm2022 = RustCryptoAead<aes_gcm::AesGcm<Aes256, U12>, 32, Blake3Subkey>;
+pub type Chacha20Poly13052022 =
+    RustCryptoAead<chacha20poly1305::ChaCha20Poly1305, 32, Blake3Subkey>;
+
 pub type Rc4 = RustCryptoStream<KeyOnlyCtor<rc4::Rc4<U16>>, 0>;
 pub type Rc4Md5 = RustCryptoStream<Rc4Md5Ctor<rc4::Rc4<U16>>, 16>;
 pub type Chacha20Ietf = RustCryptoStream<KeyIvCtor<chacha20::ChaCha20>, 12>;
@@ -1,11 +1,42 @@
 use super::*;
 
-pub struct RustCryptoAead<Inner: AeadCore, const SALT_LEN: usize> {
+/// How a session subkey is derived from the pre-shared key and the salt.
+pub trait DeriveSubkey: Send + Sync + Unpin + 'static {
+    /// Mask applied on the decrypted length of a chunk.
+    const SIZE_MASK: u16;
+    fn derive_subkey(key: &[u8], salt: &[u8], subkey: &mut [u8]);
+}
+
+/// HKDF-SHA1 as used by the original AEAD ciphers (SIP004).
+pub struct HkdfSha1Subkey;
+
+impl DeriveSubkey for HkdfSha1Subkey {
+    const SIZE_MASK: u16 = 0x3fff;
+    fn derive_subkey(key: &[u8], salt: &[u8], subkey: &mut [u8]) {
+        Hkdf::<Sha1>::new(Some(salt), key)
+            .expand(b"ss-subkey", subkey)
+            .unwrap();
+    }
+}
+
+/// BLAKE3 key derivation as used by the AEAD-2022 ciphers (SIP022).
+pub struct Blake3Subkey;
+
+impl DeriveSubkey for Blake3Subkey {
+    const SIZE_MASK: u16 = 0xffff;
+    fn derive_subkey(key: &[u8], salt: &[u8], subkey: &mut [u8]) {
+        crate::plugin::shadowsocks::aead2022::derive_session_subkey(key, salt, subkey);
+    }
+}
+
+pub struct RustCryptoAead<Inner: AeadCore, const SALT_LEN: usize, D = HkdfSha1Subkey> {
     inner: Inner,
     nonce: GenericArray<u8, Inner::NonceSize>,
+    derive_phantom: PhantomData<D>,
 }
 
-impl<Inner, const SALT_LEN: usize> ShadowCrypto for RustCryptoAead<Inner, SALT_LEN>
+impl<Inner, const SALT_LEN: usize, D: DeriveSubkey> ShadowCrypto
+    for RustCryptoAead<Inner, SALT_LEN, D>
 where
     Inner: AeadCore<TagSize = U16> + KeyInit + AeadInPlace + Send + Sync + Unpin + 'static,
     GenericArray<u8, Inner::NonceSize>: Send + Sync + Unpin + 'static,
@@ -17,13 +48,12 @@ where
 
     fn create_crypto(key: &[u8; Self::KEY_LEN], iv: &[u8; Self::IV_LEN]) -> Self {
         let mut subkey = [0u8; Self::KEY_LEN];
-        Hkdf::<Sha1>::new(Some(iv), key)
-            .expand(b"ss-subkey", &mut subkey)
-            .unwrap();
+        D::derive_subkey(key, iv, &mut subkey);
 
         Self {
             inner: Inner::new_from_slice(&subkey).unwrap(),
             nonce: Default::default(),
+            derive_phantom: PhantomData,
         }
     }
 
@@ -73,7 +103,7 @@ where
             return None;
         }
         increase_num_buf(&mut self.nonce);
-        let size = u16::from_be_bytes(size_buf.try_into().unwrap()) & 0x3fff;
+        let size = u16::from_be_bytes(size_buf.try_into().unwrap()) & D::SIZE_MASK;
         NonZeroUsize::new(size as usize)
     }
 
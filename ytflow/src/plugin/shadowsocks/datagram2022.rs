@@ -0,0 +1,214 @@
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use aes_gcm::{AeadInPlace, KeyInit};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use futures::ready;
+
+use super::aead2022::{
+    aes_decrypt_block, aes_encrypt_block, derive_session_subkey, parse_server_datagram_header,
+    write_client_datagram_header, PskList, ReplayWindow,
+};
+use crate::flow::*;
+
+const SEPARATE_HEADER_LEN: usize = 16;
+const TAG_LEN: usize = 16;
+const XCHACHA_NONCE_LEN: usize = 24;
+
+/// AEAD of a UDP session with the AES variants, keyed by the session ID.
+pub(super) enum SessionAead {
+    Aes128(aes_gcm::Aes128Gcm),
+    Aes256(aes_gcm::Aes256Gcm),
+}
+
+impl SessionAead {
+    pub(super) fn new(key: &[u8], session_id: u64) -> Self {
+        let mut subkey = [0u8; 32];
+        let subkey = &mut subkey[..key.len()];
+        derive_session_subkey(key, &session_id.to_be_bytes(), subkey);
+        match key.len() {
+            16 => Self::Aes128(aes_gcm::Aes128Gcm::new_from_slice(subkey).unwrap()),
+            _ => Self::Aes256(aes_gcm::Aes256Gcm::new_from_slice(subkey).unwrap()),
+        }
+    }
+
+    fn encrypt(&self, nonce: &[u8], buf: &mut [u8]) -> [u8; TAG_LEN] {
+        let nonce = nonce.into();
+        let tag = match self {
+            Self::Aes128(c) => c.encrypt_in_place_detached(nonce, &[], buf),
+            Self::Aes256(c) => c.encrypt_in_place_detached(nonce, &[], buf),
+        };
+        tag.unwrap().into()
+    }
+
+    #[must_use]
+    fn decrypt(&self, nonce: &[u8], buf: &mut [u8], tag: &[u8]) -> bool {
+        let (nonce, tag) = (nonce.into(), tag.into());
+        match self {
+            Self::Aes128(c) => c.decrypt_in_place_detached(nonce, &[], buf, tag),
+            Self::Aes256(c) => c.decrypt_in_place_detached(nonce, &[], buf, tag),
+        }
+        .is_ok()
+    }
+}
+
+struct ServerSession {
+    id: u64,
+    aead: Option<SessionAead>,
+    window: ReplayWindow,
+}
+
+pub struct Shadowsocks2022DatagramSession {
+    pub(super) psk: Arc<PskList>,
+    pub(super) session_id: u64,
+    pub(super) packet_id: u64,
+    /// `None` for 2022-blake3-chacha20-poly1305, which encrypts every packet with
+    /// XChaCha20-Poly1305 under the PSK instead.
+    pub(super) tx_aead: Option<SessionAead>,
+    server_session: Option<ServerSession>,
+    pub(super) lower: Box<dyn DatagramSession>,
+}
+
+impl Shadowsocks2022DatagramSession {
+    pub(super) fn new(psk: Arc<PskList>, is_aes: bool, lower: Box<dyn DatagramSession>) -> Self {
+        let mut session_id = [0u8; 8];
+        getrandom::getrandom(&mut session_id).unwrap();
+        let session_id = u64::from_ne_bytes(session_id);
+        Self {
+            tx_aead: is_aes.then(|| SessionAead::new(&psk.user_key, session_id)),
+            psk,
+            session_id,
+            packet_id: 0,
+            server_session: None,
+            lower,
+        }
+    }
+
+    fn decrypt_packet(&mut self, mut buf: Buffer) -> Option<(DestinationAddr, Buffer)> {
+        let is_aes = self.tx_aead.is_some();
+        let body_start = if is_aes {
+            SEPARATE_HEADER_LEN
+        } else {
+            XCHACHA_NONCE_LEN + SEPARATE_HEADER_LEN
+        };
+        if buf.len() < body_start + TAG_LEN {
+            return None;
+        }
+        let tag_start = buf.len() - TAG_LEN;
+        let (packet, tag) = buf.split_at_mut(tag_start);
+
+        let (separate_header, fresh_aead) = if is_aes {
+            let separate_header: &mut [u8; SEPARATE_HEADER_LEN] =
+                (&mut packet[..SEPARATE_HEADER_LEN]).try_into().unwrap();
+            // Packets from the server are always encrypted with the user PSK.
+            aes_decrypt_block(&self.psk.user_key, separate_header);
+            let server_session_id = u64::from_be_bytes(separate_header[..8].try_into().unwrap());
+            let fresh_aead = match &self.server_session {
+                Some(s) if s.id == server_session_id => None,
+                _ => Some(SessionAead::new(&self.psk.user_key, server_session_id)),
+            };
+            let aead = fresh_aead
+                .as_ref()
+                .or_else(|| self.server_session.as_ref()?.aead.as_ref())?;
+            let (separate_header, body) = packet.split_at_mut(SEPARATE_HEADER_LEN);
+            if !aead.decrypt(&separate_header[4..], body, tag) {
+                return None;
+            }
+            (*separate_header, fresh_aead)
+        } else {
+            let (nonce, payload) = packet.split_at_mut(XCHACHA_NONCE_LEN);
+            let cipher = XChaCha20Poly1305::new_from_slice(&self.psk.user_key).unwrap();
+            cipher
+                .decrypt_in_place_detached(XNonce::from_slice(nonce), &[], payload, (&*tag).into())
+                .ok()?;
+            (payload[..SEPARATE_HEADER_LEN].try_into().unwrap(), None)
+        };
+
+        let (dest, header_len) =
+            parse_server_datagram_header(&buf[body_start..tag_start], self.session_id)?;
+        let server_session_id = u64::from_be_bytes(separate_header[..8].try_into().unwrap());
+        let packet_id = u64::from_be_bytes(separate_header[8..].try_into().unwrap());
+        let session = match &mut self.server_session {
+            Some(s) if s.id == server_session_id => s,
+            s => s.insert(ServerSession {
+                id: server_session_id,
+                aead: fresh_aead,
+                window: ReplayWindow::default(),
+            }),
+        };
+        if !session.window.check_and_set(packet_id) {
+            return None;
+        }
+
+        buf.truncate(tag_start);
+        buf.drain(..body_start + header_len);
+        Some((dest, buf))
+    }
+}
+
+impl DatagramSession for Shadowsocks2022DatagramSession {
+    fn poll_recv_from(&mut self, cx: &mut Context) -> Poll<Option<(DestinationAddr, Buffer)>> {
+        loop {
+            let Some((_, buf)) = ready!(self.lower.poll_recv_from(cx)) else {
+                return Poll::Ready(None);
+            };
+            // Drop invalid or replayed packets without tearing down the session.
+            if let Some(res) = self.decrypt_packet(buf) {
+                return Poll::Ready(Some(res));
+            }
+        }
+    }
+
+    fn poll_send_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        self.lower.poll_send_ready(cx)
+    }
+
+    fn send_to(&mut self, remote_peer: DestinationAddr, buf: Buffer) {
+        let mut separate_header = [0u8; SEPARATE_HEADER_LEN];
+        separate_header[..8].copy_from_slice(&self.session_id.to_be_bytes());
+        separate_header[8..].copy_from_slice(&self.packet_id.to_be_bytes());
+        self.packet_id += 1;
+
+        let mut body = Vec::with_capacity(SEPARATE_HEADER_LEN + 1 + 8 + 2 + 259 + buf.len());
+        if self.tx_aead.is_none() {
+            body.extend_from_slice(&separate_header);
+        }
+        write_client_datagram_header(&mut body, &remote_peer);
+        body.extend_from_slice(&buf);
+
+        let mut packet = Vec::with_capacity(
+            XCHACHA_NONCE_LEN
+                + SEPARATE_HEADER_LEN * (1 + self.psk.identity_keys.len())
+                + body.len()
+                + TAG_LEN,
+        );
+        match &self.tx_aead {
+            Some(aead) => {
+                let tag = aead.encrypt(&separate_header[4..], &mut body);
+                let mut encrypted_header = separate_header;
+                aes_encrypt_block(self.psk.first_key(), &mut encrypted_header);
+                packet.extend_from_slice(&encrypted_header);
+                self.psk
+                    .datagram_identity_headers(&separate_header, &mut packet);
+                packet.extend_from_slice(&body);
+                packet.extend_from_slice(&tag);
+            }
+            None => {
+                let mut nonce = [0u8; XCHACHA_NONCE_LEN];
+                getrandom::getrandom(&mut nonce).unwrap();
+                let cipher = XChaCha20Poly1305::new_from_slice(&self.psk.user_key).unwrap();
+                let tag = cipher
+                    .encrypt_in_place_detached(XNonce::from_slice(&nonce), &[], &mut body)
+                    .unwrap();
+                packet.extend_from_slice(&nonce);
+                packet.extend_from_slice(&body);
+                packet.extend_from_slice(&tag);
+            }
+        }
+        self.lower.send_to(remote_peer, packet);
+    }
+
+    fn poll_shutdown(&mut self, cx: &mut Context<'_>) -> Poll<FlowResult<()>> {
+        self.lower.poll_shutdown(cx)
+    }
+}
@@ -2,13 +2,18 @@ use std::marker::PhantomData;
 use std::sync::{Arc, Weak};
 
 pub mod datagram;
+pub mod datagram2022;
 pub mod stream;
+pub mod stream2022;
 
+use super::aead2022::{InvalidPsk, PskList, SaltPool};
 use super::crypto::*;
 use super::SupportedCipher;
 use crate::flow::*;
 use datagram::ShadowsocksDatagramSessionFactory;
+use datagram2022::Shadowsocks2022DatagramSessionFactory;
 use stream::ShadowsocksStreamOutboundFactory;
+use stream2022::Shadowsocks2022StreamOutboundFactory;
 
 pub trait ReceiveFactory {
     fn receive_factory<F: CreateFactory>(self, factory: F);
@@ -60,7 +65,68 @@ where
     }
 }
 
-pub fn create_factory<R: ReceiveFactory>(method: SupportedCipher, password: &[u8], r: R) {
+struct FactoryCreator2022<C: ShadowCrypto>
+where
+    [(); C::KEY_LEN]:,
+{
+    psk: Arc<PskList>,
+    /// Only the AES variants support identity headers and separate headers in UDP.
+    is_aes: bool,
+    salt_pool: Arc<SaltPool>,
+    crypto_phantom: std::marker::PhantomData<C>,
+}
+
+impl<C: ShadowCrypto> FactoryCreator2022<C>
+where
+    [(); C::KEY_LEN]:,
+{
+    fn new(password: &[u8], is_aes: bool) -> Result<Self, InvalidPsk> {
+        Ok(Self {
+            psk: Arc::new(PskList::parse(password, C::KEY_LEN, is_aes)?),
+            is_aes,
+            salt_pool: Default::default(),
+            crypto_phantom: PhantomData,
+        })
+    }
+}
+
+impl<C: ShadowCrypto> CreateFactory for FactoryCreator2022<C>
+where
+    [(); C::KEY_LEN]:,
+    [(); C::IV_LEN]:,
+    [(); C::PRE_CHUNK_OVERHEAD]:,
+    [(); C::POST_CHUNK_OVERHEAD]:,
+{
+    type StreamFactory = Shadowsocks2022StreamOutboundFactory<C>;
+    type DatagramFactory = Shadowsocks2022DatagramSessionFactory;
+    fn create_stream_factory(&self, next: Weak<dyn StreamOutboundFactory>) -> Self::StreamFactory {
+        Shadowsocks2022StreamOutboundFactory {
+            key: (*self.psk.user_key).try_into().unwrap(),
+            psk: self.psk.clone(),
+            salt_pool: self.salt_pool.clone(),
+            next,
+            crypto_phantom: PhantomData,
+        }
+    }
+    fn create_datagram_session_factory(
+        &self,
+        next: Weak<dyn DatagramSessionFactory>,
+    ) -> Self::DatagramFactory {
+        Shadowsocks2022DatagramSessionFactory {
+            psk: self.psk.clone(),
+            is_aes: self.is_aes,
+            next,
+        }
+    }
+}
+
+/// Returns an error if the password of an AEAD-2022 cipher is not a valid list of
+/// base64-encoded keys.
+pub fn create_factory<R: ReceiveFactory>(
+    method: SupportedCipher,
+    password: &[u8],
+    r: R,
+) -> Result<(), InvalidPsk> {
     use super::util::openssl_bytes_to_key as bk;
 
     let p = password;
@@ -83,5 +149,9 @@ pub fn create_factory<R: ReceiveFactory>(method: SupportedCipher, password: &[u8
         SupportedCipher::Chacha20Ietf => r.receive_factory(FactoryCreator::<Chacha20Ietf> { key: bk(p), crypto_phantom: PhantomData }),
         SupportedCipher::Chacha20IetfPoly1305 => r.receive_factory(FactoryCreator::<Chacha20IetfPoly1305> { key: bk(p), crypto_phantom: PhantomData }),
         SupportedCipher::XChacha20IetfPoly1305 => r.receive_factory(FactoryCreator::<XChacha20IetfPoly1305> { key: bk(p), crypto_phantom: PhantomData }),
-    }
+        SupportedCipher::Blake3Aes128Gcm => r.receive_factory(FactoryCreator2022::<Aes128Gcm2022>::new(p, true)?),
+        SupportedCipher::Blake3Aes256Gcm => r.receive_factory(FactoryCreator2022::<Aes256Gcm2022>::new(p, true)?),
+        SupportedCipher::Blake3Chacha20Poly1305 => r.receive_factory(FactoryCreator2022::<Chacha20Poly13052022>::new(p, false)?),
+    };
+    Ok(())
 }
@@ -0,0 +1,25 @@
+use std::sync::{Arc, Weak};
+
+use async_trait::async_trait;
+
+use super::super::aead2022::PskList;
+use super::super::datagram2022::Shadowsocks2022DatagramSession;
+use crate::flow::*;
+
+pub struct Shadowsocks2022DatagramSessionFactory {
+    pub(super) psk: Arc<PskList>,
+    pub(super) is_aes: bool,
+    pub(super) next: Weak<dyn DatagramSessionFactory>,
+}
+
+#[async_trait]
+impl DatagramSessionFactory for Shadowsocks2022DatagramSessionFactory {
+    async fn bind(&self, context: Box<FlowContext>) -> FlowResult<Box<dyn DatagramSession>> {
+        let next = self.next.upgrade().ok_or(FlowError::NoOutbound)?;
+        Ok(Box::new(Shadowsocks2022DatagramSession::new(
+            self.psk.clone(),
+            self.is_aes,
+            next.bind(context).await?,
+        )))
+    }
+}
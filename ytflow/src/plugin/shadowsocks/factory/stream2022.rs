@@ -0,0 +1,115 @@
+use std::io;
+use std::sync::{Arc, Weak};
+
+use async_trait::async_trait;
+
+use super::super::aead2022::{
+    write_request_fixed_header, write_request_var_header, PskList, SaltPool,
+};
+use super::super::stream;
+use super::ShadowCrypto;
+use crate::flow::*;
+
+pub struct Shadowsocks2022StreamOutboundFactory<C: ShadowCrypto>
+where
+    [(); C::KEY_LEN]:,
+{
+    pub(super) key: [u8; C::KEY_LEN],
+    pub(super) psk: Arc<PskList>,
+    pub(super) salt_pool: Arc<SaltPool>,
+    pub(super) next: Weak<dyn StreamOutboundFactory>,
+    pub(super) crypto_phantom: std::marker::PhantomData<C>,
+}
+
+impl<C: ShadowCrypto> Shadowsocks2022StreamOutboundFactory<C>
+where
+    [(); C::KEY_LEN]:,
+    [(); C::IV_LEN]:,
+    [(); C::PRE_CHUNK_OVERHEAD]:,
+    [(); C::POST_CHUNK_OVERHEAD]:,
+{
+    fn get_req(
+        &self,
+        context: &FlowContext,
+        initial_data: &[u8],
+    ) -> FlowResult<(Vec<u8>, C, Box<[u8]>)> {
+        let mut salt = [0; C::IV_LEN];
+        getrandom::getrandom(&mut salt).unwrap();
+
+        let mut var_header = Vec::with_capacity(259 + 2 + 900 + initial_data.len());
+        let var_header_len =
+            write_request_var_header(&mut var_header, &context.remote_peer, initial_data)
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "Initial data too large for a Shadowsocks 2022 request header",
+                    )
+                })?;
+        let mut fixed_header = Vec::with_capacity(11);
+        write_request_fixed_header(&mut fixed_header, var_header_len);
+
+        let mut req_buf = Vec::with_capacity(
+            C::IV_LEN
+                + 16 * self.psk.identity_keys.len()
+                + fixed_header.len()
+                + var_header.len()
+                + 2 * C::POST_CHUNK_OVERHEAD,
+        );
+        req_buf.extend_from_slice(&salt);
+        self.psk.stream_identity_headers(&salt, &mut req_buf);
+
+        let mut tx_crypto = C::create_crypto(&self.key, &salt);
+        for mut chunk in [fixed_header, var_header] {
+            let mut post_overhead = [0; C::POST_CHUNK_OVERHEAD];
+            tx_crypto.encrypt_all(&mut chunk, &mut post_overhead);
+            req_buf.extend_from_slice(&chunk);
+            req_buf.extend_from_slice(&post_overhead);
+        }
+
+        Ok((req_buf, tx_crypto, salt.into()))
+    }
+}
+
+#[async_trait]
+impl<C: ShadowCrypto> StreamOutboundFactory for Shadowsocks2022StreamOutboundFactory<C>
+where
+    [(); C::KEY_LEN]:,
+    [(); C::IV_LEN]:,
+    [(); C::PRE_CHUNK_OVERHEAD]:,
+    [(); C::POST_CHUNK_OVERHEAD]:,
+{
+    async fn create_outbound(
+        &self,
+        context: &mut FlowContext,
+        initial_data: &'_ [u8],
+    ) -> FlowResult<(Box<dyn Stream>, Buffer)> {
+        let outbound_factory = self.next.upgrade().ok_or(FlowError::NoOutbound)?;
+        let ((next, initial_res), tx_crypto, request_salt) = {
+            let (tx_buffer, tx_crypto, request_salt) = self.get_req(context, initial_data)?;
+            (
+                outbound_factory
+                    .create_outbound(context, &tx_buffer)
+                    .await?,
+                tx_crypto,
+                request_salt,
+            )
+        };
+        // Must specify C explicitly due to https://github.com/rust-lang/rust/issues/83249
+        Ok((
+            Box::new(stream::ShadowsocksStream::<C> {
+                reader: StreamReader::new(4096, initial_res),
+                rx_buf: None,
+                rx_chunk_size: std::num::NonZeroUsize::new(4096).unwrap(),
+                lower: next,
+                tx_offset: 0,
+                rx_crypto: stream::RxCryptoState::ReadingResponseHeader {
+                    key: self.key,
+                    request_salt,
+                    salt_pool: self.salt_pool.clone(),
+                },
+                tx_crypto,
+            }),
+            Buffer::new(),
+        ))
+    }
+}
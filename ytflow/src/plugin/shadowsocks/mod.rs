@@ -2,11 +2,15 @@ use std::fmt::Display;
 
 use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "plugins")]
+mod aead2022;
 #[cfg(feature = "plugins")]
 mod crypto;
 #[cfg(feature = "plugins")]
 mod datagram;
 #[cfg(feature = "plugins")]
+mod datagram2022;
+#[cfg(feature = "plugins")]
 pub mod factory;
 #[cfg(feature = "plugins")]
 mod stream;
@@ -50,6 +54,25 @@ pub enum SupportedCipher {
     Chacha20IetfPoly1305,
     #[serde(rename = "xchacha20-ietf-poly1305")]
     XChacha20IetfPoly1305,
+    #[serde(rename = "2022-blake3-aes-128-gcm")]
+    Blake3Aes128Gcm,
+    #[serde(rename = "2022-blake3-aes-256-gcm")]
+    Blake3Aes256Gcm,
+    #[serde(rename = "2022-blake3-chacha20-poly1305")]
+    Blake3Chacha20Poly1305,
+}
+
+impl SupportedCipher {
+    /// Whether the cipher belongs to the AEAD-2022 family (SIP022), whose
+    /// password is a base64-encoded key rather than an arbitrary string.
+    pub fn is_aead_2022(self) -> bool {
+        matches!(
+            self,
+            SupportedCipher::Blake3Aes128Gcm
+                | SupportedCipher::Blake3Aes256Gcm
+                | SupportedCipher::Blake3Chacha20Poly1305
+        )
+    }
 }
 
 impl Display for SupportedCipher {
@@ -72,6 +95,9 @@ impl Display for SupportedCipher {
             SupportedCipher::Chacha20Ietf => "chacha20-ietf",
             SupportedCipher::Chacha20IetfPoly1305 => "chacha20-ietf-poly1305",
             SupportedCipher::XChacha20IetfPoly1305 => "xchacha20-ietf-poly1305",
+            SupportedCipher::Blake3Aes128Gcm => "2022-blake3-aes-128-gcm",
+            SupportedCipher::Blake3Aes256Gcm => "2022-blake3-aes-256-gcm",
+            SupportedCipher::Blake3Chacha20Poly1305 => "2022-blake3-chacha20-poly1305",
         })
     }
 }
@@ -1,10 +1,11 @@
 use std::convert::TryInto;
 use std::num::NonZeroUsize;
-
+use std::sync::Arc;
 use std::task::{Context, Poll};
 
 use futures::ready;
 
+use super::aead2022::{parse_response_fixed_header, response_fixed_header_len, SaltPool};
 use super::crypto::*;
 use crate::flow::*;
 
@@ -12,7 +13,16 @@ pub enum RxCryptoState<C: ShadowCrypto>
 where
     [(); C::KEY_LEN]:,
 {
-    ReadingIv { key: [u8; C::KEY_LEN] },
+    ReadingIv {
+        key: [u8; C::KEY_LEN],
+    },
+    /// AEAD-2022 only. The response starts with a salt and a fixed-length header
+    /// echoing the salt of the request.
+    ReadingResponseHeader {
+        key: [u8; C::KEY_LEN],
+        request_salt: Box<[u8]>,
+        salt_pool: Arc<SaltPool>,
+    },
     Ready(C),
 }
 
@@ -54,6 +64,35 @@ where
                     )?;
                     *crypto = RxCryptoState::Ready(C::create_crypto(key, &iv));
                 }
+                RxCryptoState::ReadingResponseHeader {
+                    key,
+                    request_salt,
+                    salt_pool,
+                } => {
+                    let header_len = response_fixed_header_len(C::IV_LEN);
+                    let res = ready!(reader.poll_read_exact(
+                        cx,
+                        lower.as_mut(),
+                        C::IV_LEN + header_len + C::POST_CHUNK_OVERHEAD,
+                        |buf| {
+                            let (salt, rem) = buf.split_at_mut(C::IV_LEN);
+                            let (header, tag) = rem.split_at_mut(header_len);
+                            let mut rx_crypto = C::create_crypto(key, (&*salt).try_into().unwrap());
+                            if !rx_crypto.decrypt(header, (&*tag).try_into().unwrap()) {
+                                return None;
+                            }
+                            let size = parse_response_fixed_header(header, request_salt)?;
+                            if !salt_pool.check_and_insert(salt) {
+                                return None;
+                            }
+                            Some((rx_crypto, NonZeroUsize::new(size as usize)?))
+                        }
+                    ))?;
+                    let (rx_crypto, size) = res.ok_or(FlowError::UnexpectedData)?;
+                    *crypto = RxCryptoState::Ready(rx_crypto);
+                    *rx_chunk_size = size;
+                    return Poll::Ready(Ok(SizeHint::AtLeast(size.get() + C::POST_CHUNK_OVERHEAD)));
+                }
                 RxCryptoState::Ready(_) if C::PRE_CHUNK_OVERHEAD == 0 => {
                     return Poll::Ready(Ok(SizeHint::Unknown { overhead: 0 }));
                 }
@@ -91,7 +130,9 @@ where
             ..
         } = &mut *self;
         let crypto = match crypto {
-            RxCryptoState::ReadingIv { .. } => panic!("Polling rx buffer when IV not ready"),
+            RxCryptoState::ReadingIv { .. } | RxCryptoState::ReadingResponseHeader { .. } => {
+                panic!("Polling rx buffer when IV not ready")
+            }
             RxCryptoState::Ready(c) => c,
         };
         let rx_buf = match rx_buf_opt.as_mut() {
@@ -2,16 +2,20 @@ use std::sync::Weak;
 
 use super::Rule;
 use crate::flow::*;
+use crate::plugin::quic_sniff::QuicSniffer;
 
 type DatagramRule = Rule<Weak<dyn DatagramSessionHandler>>;
 
 pub struct SimpleDatagramDispatcher {
     pub rules: Vec<DatagramRule>,
     pub fallback: Weak<dyn DatagramSessionHandler>,
+    /// Sessions recognized as QUIC skip rule matching.
+    pub quic_sniffer: Option<QuicSniffer>,
+    pub me: Weak<Self>,
 }
 
-impl DatagramSessionHandler for SimpleDatagramDispatcher {
-    fn on_session(&self, session: Box<dyn DatagramSession>, context: Box<FlowContext>) {
+impl SimpleDatagramDispatcher {
+    fn dispatch_session(&self, session: Box<dyn DatagramSession>, context: Box<FlowContext>) {
         let handler = self
             .rules
             .iter()
@@ -22,3 +26,17 @@ impl DatagramSessionHandler for SimpleDatagramDispatcher {
         }
     }
 }
+
+impl DatagramSessionHandler for SimpleDatagramDispatcher {
+    fn on_session(&self, session: Box<dyn DatagramSession>, context: Box<FlowContext>) {
+        let Some(quic_sniffer) = &self.quic_sniffer else {
+            return self.dispatch_session(session, context);
+        };
+        let me = self.me.clone();
+        quic_sniffer.dispatch(session, context, move |session, context| {
+            if let Some(me) = me.upgrade() {
+                me.dispatch_session(session, context)
+            }
+        })
+    }
+}
@@ -0,0 +1,104 @@
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::Mutex;
+
+use super::SocketKind;
+
+/// File descriptors passed by systemd start from here. See `sd_listen_fds(3)`.
+#[cfg(unix)]
+const SD_LISTEN_FDS_START: i32 = 3;
+
+struct ActivatedSocket {
+    kind: SocketKind,
+    addr: SocketAddr,
+    socket: socket2::Socket,
+}
+
+static ACTIVATED_SOCKETS: Mutex<Vec<ActivatedSocket>> = Mutex::new(vec![]);
+
+/// Parse `LISTEN_PID` and `LISTEN_FDS`, returning the number of sockets passed
+/// to this process.
+#[cfg(unix)]
+fn parse_listen_fds(listen_pid: Option<&str>, listen_fds: Option<&str>, pid: u32) -> usize {
+    let (Some(listen_pid), Some(listen_fds)) = (listen_pid, listen_fds) else {
+        return 0;
+    };
+    if listen_pid.trim().parse::<u32>().ok() != Some(pid) {
+        return 0;
+    }
+    listen_fds.trim().parse().unwrap_or(0)
+}
+
+/// Adopt listening sockets passed by systemd socket activation, so that TCP
+/// and UDP listeners bound to the same addresses reuse them instead of
+/// binding again. Must be called once on startup before any other thread may
+/// read the environment. Returns the number of sockets adopted.
+#[cfg(unix)]
+pub fn init_activated_sockets() -> usize {
+    use std::os::fd::FromRawFd;
+
+    let count = parse_listen_fds(
+        std::env::var("LISTEN_PID").ok().as_deref(),
+        std::env::var("LISTEN_FDS").ok().as_deref(),
+        std::process::id(),
+    );
+    std::env::remove_var("LISTEN_PID");
+    std::env::remove_var("LISTEN_FDS");
+    std::env::remove_var("LISTEN_FDNAMES");
+
+    let mut activated = ACTIVATED_SOCKETS.lock().unwrap();
+    for fd in SD_LISTEN_FDS_START..SD_LISTEN_FDS_START + count as i32 {
+        // Safety: systemd hands over the ownership of these fds to us.
+        let socket = unsafe { socket2::Socket::from_raw_fd(fd) };
+        let kind = match socket.r#type() {
+            Ok(socket2::Type::STREAM) => SocketKind::Tcp,
+            Ok(socket2::Type::DGRAM) => SocketKind::Udp,
+            _ => continue,
+        };
+        let Some(addr) = socket.local_addr().ok().and_then(|a| a.as_socket()) else {
+            continue;
+        };
+        if socket.set_cloexec(true).is_err() {
+            continue;
+        }
+        activated.push(ActivatedSocket { kind, addr, socket });
+    }
+    activated.len()
+}
+
+#[cfg(not(unix))]
+pub fn init_activated_sockets() -> usize {
+    0
+}
+
+/// Find an activated socket listening on one of the addresses. The socket is
+/// duplicated so that it survives plugins being reloaded.
+pub(super) fn take_activated_socket(
+    kind: SocketKind,
+    addr: &impl ToSocketAddrs,
+) -> io::Result<Option<socket2::Socket>> {
+    let activated = ACTIVATED_SOCKETS.lock().unwrap();
+    if activated.is_empty() {
+        return Ok(None);
+    }
+    for addr in addr.to_socket_addrs()? {
+        if let Some(s) = activated.iter().find(|s| s.kind == kind && s.addr == addr) {
+            return s.socket.try_clone().map(Some);
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_listen_fds() {
+        assert_eq!(parse_listen_fds(Some("42"), Some("2"), 42), 2);
+        assert_eq!(parse_listen_fds(Some("43"), Some("2"), 42), 0);
+        assert_eq!(parse_listen_fds(None, Some("2"), 42), 0);
+        assert_eq!(parse_listen_fds(Some("42"), None, 42), 0);
+        assert_eq!(parse_listen_fds(Some("42"), Some("x"), 42), 0);
+    }
+}
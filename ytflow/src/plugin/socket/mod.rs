@@ -1,4 +1,8 @@
+mod activation;
+mod preflight;
+mod proxy_protocol;
 mod tcp;
+mod tos;
 mod udp;
 mod udp_listener;
 
@@ -15,10 +19,36 @@ use tokio::{pin, select};
 
 use crate::flow::*;
 
+pub use activation::init_activated_sockets;
+pub use preflight::{find_port_owner, PortOwner};
+pub use proxy_protocol::ProxyProtocolVersion;
 pub use tcp::{dial_stream, listen_tcp};
-pub use udp::dial_datagram_session;
+pub use udp::{dial_datagram_session, DatagramErrorSink};
 pub use udp_listener::listen_udp;
 
+pub use super::socket_hook::{set_socket_hook, RawSocket, SocketHook, SocketKind};
+pub(crate) use tos::{set_traffic_class, traffic_class_for};
+
+pub(crate) fn run_socket_hook(
+    socket: &socket2::Socket,
+    kind: SocketKind,
+    is_ipv6: bool,
+) -> std::io::Result<()> {
+    #[cfg(unix)]
+    use std::os::fd::AsRawFd;
+    #[cfg(windows)]
+    use std::os::windows::io::AsRawSocket;
+
+    let Some(hook) = super::socket_hook::current_socket_hook() else {
+        return Ok(());
+    };
+    #[cfg(unix)]
+    let raw = socket.as_raw_fd();
+    #[cfg(windows)]
+    let raw = socket.as_raw_socket();
+    hook(raw, kind, is_ipv6)
+}
+
 // See https://datatracker.ietf.org/doc/html/rfc8305
 const CONN_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
 const RESOLUTION_DELAY: Duration = Duration::from_millis(50);
@@ -28,6 +58,15 @@ pub struct SocketOutboundFactory {
     pub resolver: Weak<dyn Resolver>,
     pub bind_addr_v4: Option<SocketAddrV4>,
     pub bind_addr_v6: Option<SocketAddrV6>,
+    pub connect_timeout: Duration,
+    /// A static DSCP to mark outbound packets with, overriding the one carried by the flow.
+    pub dscp: Option<u8>,
+    /// Send a PROXY protocol header announcing the client address of the flow.
+    pub proxy_protocol: Option<ProxyProtocolVersion>,
+    /// Connect UDP sockets to the first destination of a session, so that
+    /// ICMP errors such as port unreachable end the session. Replies from
+    /// other addresses are no longer received.
+    pub udp_connected: bool,
 }
 
 async fn resolve_dual_stack_ips(domain: String, resolver: &dyn Resolver, ip_tx: Sender<IpAddr>) {
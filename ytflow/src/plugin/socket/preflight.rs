@@ -0,0 +1,286 @@
+use std::fmt;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+
+use super::SocketKind;
+
+/// A process holding a port that a listener failed to bind.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortOwner {
+    pub pid: u32,
+    pub name: Option<String>,
+}
+
+impl fmt::Display for PortOwner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "process {} (PID {})", name, self.pid),
+            None => write!(f, "process with PID {}", self.pid),
+        }
+    }
+}
+
+/// Bind a listening socket with `SO_REUSEPORT` set, so that it can share the
+/// port with other sockets doing the same. `SO_REUSEPORT` is not available on
+/// Windows, where this is a plain bind.
+pub(super) fn bind_reuse_port(
+    kind: SocketKind,
+    addr: &impl ToSocketAddrs,
+) -> io::Result<socket2::Socket> {
+    let bind = |addr: SocketAddr| {
+        let r#type = match kind {
+            SocketKind::Tcp => socket2::Type::STREAM,
+            _ => socket2::Type::DGRAM,
+        };
+        let socket = socket2::Socket::new(socket2::Domain::for_address(addr), r#type, None)?;
+        #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+        {
+            socket.set_reuse_address(true)?;
+            socket.set_reuse_port(true)?;
+        }
+        socket.bind(&addr.into())?;
+        if kind == SocketKind::Tcp {
+            socket.listen(128)?;
+        }
+        Ok(socket)
+    };
+    let mut last_err = None;
+    for addr in addr.to_socket_addrs()? {
+        match bind(addr) {
+            Ok(socket) => return Ok(socket),
+            Err(e) => last_err = Some(e),
+        }
+    }
+    Err(last_err.unwrap_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "could not resolve to any addresses",
+        )
+    }))
+}
+
+/// Find the process listening on the port of `addr`. Only supported on Linux
+/// and Windows. On Linux, processes of other users are only visible to root.
+pub fn find_port_owner(kind: SocketKind, addr: &impl ToSocketAddrs) -> Option<PortOwner> {
+    let port = addr.to_socket_addrs().ok()?.next()?.port();
+    imp::find_port_owner(kind, port)
+}
+
+/// Socket inodes bound to `port` in the content of `/proc/net/{tcp,udp}{,6}`.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_proc_net(content: &str, port: u16, listening_only: bool) -> Vec<u64> {
+    // TCP_LISTEN in include/net/tcp_states.h
+    const TCP_LISTEN: &str = "0A";
+    content
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<_> = line.split_whitespace().collect();
+            let (local, state, inode) = (fields.get(1)?, fields.get(3)?, fields.get(9)?);
+            let (_, local_port) = local.rsplit_once(':')?;
+            if u16::from_str_radix(local_port, 16).ok()? != port
+                || (listening_only && *state != TCP_LISTEN)
+            {
+                return None;
+            }
+            inode.parse().ok().filter(|&i| i != 0)
+        })
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::fs;
+
+    use super::*;
+
+    fn find_inode_owner(inodes: &[u64]) -> Option<u32> {
+        for proc_entry in fs::read_dir("/proc").ok()?.flatten() {
+            let Some(pid) = proc_entry.file_name().to_str().and_then(|s| s.parse().ok()) else {
+                continue;
+            };
+            let Ok(fds) = fs::read_dir(proc_entry.path().join("fd")) else {
+                continue;
+            };
+            let owns = fds.flatten().any(|fd| {
+                let Ok(target) = fs::read_link(fd.path()) else {
+                    return false;
+                };
+                let target = target.to_string_lossy();
+                target
+                    .strip_prefix("socket:[")
+                    .and_then(|s| s.strip_suffix(']'))
+                    .and_then(|s| s.parse().ok())
+                    .is_some_and(|inode| inodes.contains(&inode))
+            });
+            if owns {
+                return Some(pid);
+            }
+        }
+        None
+    }
+
+    pub(super) fn find_port_owner(kind: SocketKind, port: u16) -> Option<PortOwner> {
+        let (tables, listening_only): (&[&str], _) = match kind {
+            SocketKind::Tcp => (&["/proc/net/tcp", "/proc/net/tcp6"], true),
+            _ => (&["/proc/net/udp", "/proc/net/udp6"], false),
+        };
+        let inodes: Vec<_> = tables
+            .iter()
+            .filter_map(|t| fs::read_to_string(t).ok())
+            .flat_map(|c| parse_proc_net(&c, port, listening_only))
+            .collect();
+        if inodes.is_empty() {
+            return None;
+        }
+        let pid = find_inode_owner(&inodes)?;
+        let name = fs::read_to_string(format!("/proc/{}/comm", pid))
+            .ok()
+            .map(|s| s.trim_end().to_string());
+        Some(PortOwner { pid, name })
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use std::ffi::c_void;
+
+    use windows::core::PWSTR;
+    use windows::Win32::Foundation::{CloseHandle, FALSE, NO_ERROR};
+    use windows::Win32::NetworkManagement::IpHelper::{
+        GetExtendedTcpTable, GetExtendedUdpTable, MIB_TCP6TABLE_OWNER_PID, MIB_TCPTABLE_OWNER_PID,
+        MIB_UDP6TABLE_OWNER_PID, MIB_UDPTABLE_OWNER_PID, TCP_TABLE_OWNER_PID_LISTENER,
+        UDP_TABLE_OWNER_PID,
+    };
+    use windows::Win32::Networking::WinSock::{AF_INET, AF_INET6};
+    use windows::Win32::System::Threading::{
+        OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32,
+        PROCESS_QUERY_LIMITED_INFORMATION,
+    };
+
+    use super::*;
+
+    /// Call `GetExtended{Tcp,Udp}Table` with a large enough buffer.
+    fn get_table(get: impl Fn(Option<*mut c_void>, *mut u32) -> u32) -> Option<Vec<u64>> {
+        let mut size = 0;
+        get(None, &mut size);
+        // Leave room for connections created in between
+        size += 4096;
+        // u64 for alignment of the tables
+        let mut buf = vec![0u64; size as usize / 8 + 1];
+        (get(Some(buf.as_mut_ptr() as _), &mut size) == NO_ERROR.0).then_some(buf)
+    }
+
+    /// Extract owner PIDs of rows with local port `port` from a table in `buf`,
+    /// laid out as `$table`.
+    macro_rules! find_in_table {
+        ($buf: expr, $table: ty, $port: expr) => {{
+            let table = $buf.as_ptr() as *const $table;
+            // Safety: the buffer is filled by the API as $table.
+            unsafe {
+                let rows = std::slice::from_raw_parts(
+                    (*table).table.as_ptr(),
+                    (*table).dwNumEntries as usize,
+                );
+                rows.iter()
+                    .find(|r| u16::from_be(r.dwLocalPort as u16) == $port)
+                    .map(|r| r.dwOwningPid)
+            }
+        }};
+    }
+
+    fn process_name(pid: u32) -> Option<String> {
+        // Safety: the handle is closed before returning, and the buffer size
+        // is passed in along with the buffer.
+        unsafe {
+            let handle = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, FALSE, pid).ok()?;
+            let mut buf = [0u16; 1024];
+            let mut len = buf.len() as u32;
+            let res = QueryFullProcessImageNameW(
+                handle,
+                PROCESS_NAME_WIN32,
+                PWSTR(buf.as_mut_ptr()),
+                &mut len,
+            );
+            let _ = CloseHandle(handle);
+            res.ok()?;
+            let path = String::from_utf16_lossy(&buf[..len as usize]);
+            Some(path.rsplit('\\').next().unwrap_or(&path).to_string())
+        }
+    }
+
+    pub(super) fn find_port_owner(kind: SocketKind, port: u16) -> Option<PortOwner> {
+        let pid = match kind {
+            SocketKind::Tcp => {
+                let tcp_table = |af: u32| {
+                    get_table(|buf, size| unsafe {
+                        GetExtendedTcpTable(buf, size, FALSE, af, TCP_TABLE_OWNER_PID_LISTENER, 0)
+                    })
+                };
+                tcp_table(AF_INET.0 as _)
+                    .and_then(|buf| find_in_table!(buf, MIB_TCPTABLE_OWNER_PID, port))
+                    .or_else(|| {
+                        tcp_table(AF_INET6.0 as _)
+                            .and_then(|buf| find_in_table!(buf, MIB_TCP6TABLE_OWNER_PID, port))
+                    })
+            }
+            _ => {
+                let udp_table = |af: u32| {
+                    get_table(|buf, size| unsafe {
+                        GetExtendedUdpTable(buf, size, FALSE, af, UDP_TABLE_OWNER_PID, 0)
+                    })
+                };
+                udp_table(AF_INET.0 as _)
+                    .and_then(|buf| find_in_table!(buf, MIB_UDPTABLE_OWNER_PID, port))
+                    .or_else(|| {
+                        udp_table(AF_INET6.0 as _)
+                            .and_then(|buf| find_in_table!(buf, MIB_UDP6TABLE_OWNER_PID, port))
+                    })
+            }
+        }?;
+        Some(PortOwner {
+            pid,
+            name: process_name(pid),
+        })
+    }
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+mod imp {
+    use super::*;
+
+    pub(super) fn find_port_owner(_kind: SocketKind, _port: u16) -> Option<PortOwner> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_proc_net() {
+        let content = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 0100007F:0438 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 12345 1 0000000000000000 100 0 0 10 0
+   1: 0100007F:0438 0100007F:D6D8 01 00000000:00000000 00:00000000 00000000  1000        0 23456 1 0000000000000000 20 4 30 10 -1
+   2: 00000000:0035 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 34567 1 0000000000000000 100 0 0 10 0";
+        assert_eq!(parse_proc_net(content, 1080, true), vec![12345]);
+        assert_eq!(parse_proc_net(content, 1080, false), vec![12345, 23456]);
+        assert_eq!(parse_proc_net(content, 53, true), vec![34567]);
+        assert!(parse_proc_net(content, 443, false).is_empty());
+    }
+
+    #[test]
+    fn test_port_owner_display() {
+        let owner = PortOwner {
+            pid: 42,
+            name: Some("nginx".into()),
+        };
+        assert_eq!(owner.to_string(), "process nginx (PID 42)");
+        let owner = PortOwner {
+            pid: 42,
+            name: None,
+        };
+        assert_eq!(owner.to_string(), "process with PID 42");
+    }
+}
@@ -0,0 +1,282 @@
+//! PROXY protocol headers, which carry the address of the original client
+//! across load balancers and chained instances.
+//! See https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+
+use crate::flow::*;
+
+const V1_PREFIX: &[u8] = b"PROXY ";
+/// Including the trailing CRLF.
+const V1_MAX_LEN: usize = 107;
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+const V2_HEADER_LEN: usize = 16;
+/// The client must send the header right after connecting.
+pub(super) const HEADER_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyProtocolVersion {
+    V1,
+    V2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum ProxyHeader {
+    /// The connection was not relayed on behalf of a client, such as health
+    /// checks from the load balancer. The socket addresses stay in effect.
+    Local,
+    Proxied {
+        src: SocketAddr,
+        dst: SocketAddr,
+    },
+}
+
+/// Parse a header of either version at the beginning of `buf`. Returns the
+/// header and its length, or `None` if more data is needed.
+pub(super) fn parse_header(buf: &[u8]) -> FlowResult<Option<(ProxyHeader, usize)>> {
+    if buf.starts_with(&V2_SIGNATURE) {
+        parse_v2(buf)
+    } else if buf.starts_with(V1_PREFIX) {
+        parse_v1(buf)
+    } else if V2_SIGNATURE.starts_with(buf) || V1_PREFIX.starts_with(buf) {
+        Ok(None)
+    } else {
+        Err(FlowError::UnexpectedData)
+    }
+}
+
+fn parse_v1(buf: &[u8]) -> FlowResult<Option<(ProxyHeader, usize)>> {
+    let search_len = buf.len().min(V1_MAX_LEN);
+    let Some(end) = buf[..search_len].windows(2).position(|w| w == b"\r\n") else {
+        return if buf.len() < V1_MAX_LEN {
+            Ok(None)
+        } else {
+            Err(FlowError::UnexpectedData)
+        };
+    };
+    let line =
+        std::str::from_utf8(&buf[V1_PREFIX.len()..end]).map_err(|_| FlowError::UnexpectedData)?;
+    let mut parts = line.split(' ');
+    let header = match parts.next() {
+        // The rest of the line is ignored
+        Some("UNKNOWN") => ProxyHeader::Local,
+        Some(proto @ ("TCP4" | "TCP6")) => {
+            let mut next = || parts.next().ok_or(FlowError::UnexpectedData);
+            let (src_ip, dst_ip, src_port, dst_port) = (next()?, next()?, next()?, next()?);
+            let parse_ip = |ip: &str| -> FlowResult<IpAddr> {
+                Ok(if proto == "TCP4" {
+                    ip.parse::<Ipv4Addr>()
+                        .map_err(|_| FlowError::UnexpectedData)?
+                        .into()
+                } else {
+                    ip.parse::<Ipv6Addr>()
+                        .map_err(|_| FlowError::UnexpectedData)?
+                        .into()
+                })
+            };
+            let parse_port =
+                |port: &str| port.parse::<u16>().map_err(|_| FlowError::UnexpectedData);
+            if parts.next().is_some() {
+                return Err(FlowError::UnexpectedData);
+            }
+            ProxyHeader::Proxied {
+                src: SocketAddr::new(parse_ip(src_ip)?, parse_port(src_port)?),
+                dst: SocketAddr::new(parse_ip(dst_ip)?, parse_port(dst_port)?),
+            }
+        }
+        _ => return Err(FlowError::UnexpectedData),
+    };
+    Ok(Some((header, end + 2)))
+}
+
+fn parse_v2(buf: &[u8]) -> FlowResult<Option<(ProxyHeader, usize)>> {
+    if buf.len() < V2_HEADER_LEN {
+        return Ok(None);
+    }
+    let (ver_cmd, fam) = (buf[12], buf[13]);
+    let len = V2_HEADER_LEN + u16::from_be_bytes([buf[14], buf[15]]) as usize;
+    if ver_cmd >> 4 != 2 {
+        return Err(FlowError::UnexpectedData);
+    }
+    if buf.len() < len {
+        return Ok(None);
+    }
+    let addrs = &buf[V2_HEADER_LEN..len];
+    let header = match (ver_cmd & 0xf, fam >> 4) {
+        (0, _) => ProxyHeader::Local,
+        // AF_INET
+        (1, 1) if addrs.len() >= 12 => {
+            let ip = |i: usize| IpAddr::from(<[u8; 4]>::try_from(&addrs[i..i + 4]).unwrap());
+            let port = |i: usize| u16::from_be_bytes([addrs[i], addrs[i + 1]]);
+            ProxyHeader::Proxied {
+                src: SocketAddr::new(ip(0), port(8)),
+                dst: SocketAddr::new(ip(4), port(10)),
+            }
+        }
+        // AF_INET6
+        (1, 2) if addrs.len() >= 36 => {
+            let ip = |i: usize| IpAddr::from(<[u8; 16]>::try_from(&addrs[i..i + 16]).unwrap());
+            let port = |i: usize| u16::from_be_bytes([addrs[i], addrs[i + 1]]);
+            ProxyHeader::Proxied {
+                src: SocketAddr::new(ip(0), port(32)),
+                dst: SocketAddr::new(ip(16), port(34)),
+            }
+        }
+        // AF_UNSPEC or AF_UNIX: addresses are not meaningful to us
+        (1, 0 | 3) => ProxyHeader::Local,
+        _ => return Err(FlowError::UnexpectedData),
+    };
+    Ok(Some((header, len)))
+}
+
+/// Read a header from a newly accepted connection. Data following the header
+/// is returned along with it.
+pub(super) async fn read_header(stream: &mut TcpStream) -> FlowResult<(ProxyHeader, Buffer)> {
+    let mut buf = Vec::with_capacity(256);
+    loop {
+        if stream.read_buf(&mut buf).await? == 0 {
+            return Err(FlowError::Eof);
+        }
+        if let Some((header, len)) = parse_header(&buf)? {
+            buf.drain(..len);
+            return Ok((header, buf));
+        }
+    }
+}
+
+/// Build a header announcing a connection from `src` to `dst`.
+pub(super) fn encode_header(
+    version: ProxyProtocolVersion,
+    src: SocketAddr,
+    dst: SocketAddr,
+) -> Vec<u8> {
+    // Both addresses must be of the same family.
+    let (src, dst) = match (src, dst) {
+        (SocketAddr::V4(s), SocketAddr::V6(_)) => (
+            SocketAddr::new(s.ip().to_ipv6_mapped().into(), s.port()),
+            dst,
+        ),
+        (SocketAddr::V6(_), SocketAddr::V4(d)) => (
+            src,
+            SocketAddr::new(d.ip().to_ipv6_mapped().into(), d.port()),
+        ),
+        _ => (src, dst),
+    };
+    match version {
+        ProxyProtocolVersion::V1 => format!(
+            "PROXY {} {} {} {} {}\r\n",
+            if src.is_ipv4() { "TCP4" } else { "TCP6" },
+            src.ip(),
+            dst.ip(),
+            src.port(),
+            dst.port()
+        )
+        .into_bytes(),
+        ProxyProtocolVersion::V2 => {
+            let mut header = Vec::with_capacity(V2_HEADER_LEN + 36);
+            header.extend_from_slice(&V2_SIGNATURE);
+            // Version 2, PROXY command
+            header.push(0x21);
+            match (src.ip(), dst.ip()) {
+                (IpAddr::V4(s), IpAddr::V4(d)) => {
+                    // AF_INET, STREAM
+                    header.push(0x11);
+                    header.extend_from_slice(&12u16.to_be_bytes());
+                    header.extend_from_slice(&s.octets());
+                    header.extend_from_slice(&d.octets());
+                }
+                (s, d) => {
+                    let to_v6 = |ip: IpAddr| match ip {
+                        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+                        IpAddr::V6(ip) => ip,
+                    };
+                    // AF_INET6, STREAM
+                    header.push(0x21);
+                    header.extend_from_slice(&36u16.to_be_bytes());
+                    header.extend_from_slice(&to_v6(s).octets());
+                    header.extend_from_slice(&to_v6(d).octets());
+                }
+            }
+            header.extend_from_slice(&src.port().to_be_bytes());
+            header.extend_from_slice(&dst.port().to_be_bytes());
+            header
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_v1() {
+        let buf = b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 443\r\nGET /";
+        assert_eq!(
+            parse_header(buf).unwrap(),
+            Some((
+                ProxyHeader::Proxied {
+                    src: "192.0.2.1:56324".parse().unwrap(),
+                    dst: "198.51.100.1:443".parse().unwrap(),
+                },
+                buf.len() - 5
+            ))
+        );
+        assert_eq!(
+            parse_header(b"PROXY UNKNOWN\r\n").unwrap(),
+            Some((ProxyHeader::Local, 15))
+        );
+    }
+
+    #[test]
+    fn test_parse_incomplete() {
+        assert_eq!(parse_header(b"PRO").unwrap(), None);
+        assert_eq!(parse_header(b"PROXY TCP6 2001:db8::1").unwrap(), None);
+        assert_eq!(parse_header(&V2_SIGNATURE[..5]).unwrap(), None);
+        let header = encode_header(
+            ProxyProtocolVersion::V2,
+            "192.0.2.1:1".parse().unwrap(),
+            "192.0.2.2:2".parse().unwrap(),
+        );
+        assert_eq!(parse_header(&header[..header.len() - 1]).unwrap(), None);
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(parse_header(b"GET / HTTP/1.1\r\n").is_err());
+        assert!(parse_header(b"PROXY TCP4 2001:db8::1 192.0.2.1 1 2\r\n").is_err());
+        assert!(parse_header(b"PROXY TCP4 192.0.2.1 192.0.2.2 1 65536\r\n").is_err());
+        assert!(parse_header(&[b"PROXY ".as_slice(), &[b'A'; 120]].concat()).is_err());
+    }
+
+    #[test]
+    fn test_encode_roundtrip() {
+        let cases: [(&str, &str); 3] = [
+            ("192.0.2.1:56324", "198.51.100.1:443"),
+            ("[2001:db8::1]:56324", "[2001:db8::2]:443"),
+            ("192.0.2.1:56324", "[2001:db8::2]:443"),
+        ];
+        for version in [ProxyProtocolVersion::V1, ProxyProtocolVersion::V2] {
+            for (src, dst) in cases {
+                let (src, dst): (SocketAddr, SocketAddr) =
+                    (src.parse().unwrap(), dst.parse().unwrap());
+                let header = encode_header(version, src, dst);
+                let (parsed, len) = parse_header(&header).unwrap().unwrap();
+                assert_eq!(len, header.len());
+                let ProxyHeader::Proxied {
+                    src: parsed_src,
+                    dst: parsed_dst,
+                } = parsed
+                else {
+                    panic!("expected a proxied header");
+                };
+                assert_eq!(parsed_src.port(), src.port());
+                assert_eq!(parsed_dst, dst);
+                assert_eq!(parsed_src.ip().to_canonical(), src.ip());
+            }
+        }
+    }
+}
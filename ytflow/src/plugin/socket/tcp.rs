@@ -1,5 +1,5 @@
 use std::io;
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6, ToSocketAddrs};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6, ToSocketAddrs};
 use std::sync::{Arc, Weak};
 
 use async_trait::async_trait;
@@ -18,11 +18,17 @@ fn prepare_socket(socket: &socket2::Socket) -> io::Result<()> {
 }
 
 pub fn listen_tcp(
+    plugin_name: Arc<str>,
     next: Weak<dyn StreamHandler>,
     addr: impl ToSocketAddrs + Send + 'static,
+    accept_proxy_protocol: bool,
+    reuse_port: bool,
 ) -> io::Result<tokio::task::JoinHandle<()>> {
-    let listener = std::net::TcpListener::bind(addr)?;
-    let socket = socket2::Socket::from(listener);
+    let socket = match super::activation::take_activated_socket(super::SocketKind::Tcp, &addr)? {
+        Some(socket) => socket,
+        None if reuse_port => super::preflight::bind_reuse_port(super::SocketKind::Tcp, &addr)?,
+        None => socket2::Socket::from(std::net::TcpListener::bind(addr)?),
+    };
     socket.set_reuse_address(true)?;
     prepare_socket(&socket)?;
     let listener = tokio::net::TcpListener::from_std(socket.into())?;
@@ -40,11 +46,19 @@ pub fn listen_tcp(
                         Err(_) => continue,
                     }
                     .into();
-                    next.on_stream(
-                        Box::new(CompatFlow::new(stream, 4096)),
-                        Buffer::new(),
-                        Box::new(FlowContext::new(connector, remote_peer)),
-                    )
+                    let context = FlowContext::new(connector, remote_peer);
+                    if accept_proxy_protocol {
+                        tokio::spawn(accept_proxied(plugin_name.clone(), next, stream, context));
+                        continue;
+                    }
+                    let session_id = crate::log::next_session_id();
+                    crate::log::isolate_sync(&plugin_name, session_id, || {
+                        next.on_stream(
+                            Box::new(CompatFlow::new(stream, 4096)),
+                            Buffer::new(),
+                            Box::new(context),
+                        )
+                    });
                 }
                 // TODO: log error
                 Err(_) => break,
@@ -53,6 +67,37 @@ pub fn listen_tcp(
     }))
 }
 
+/// Hand over a connection after reading the PROXY protocol header sent by
+/// the load balancer in front, replacing the socket addresses with the ones
+/// of the original client. Connections without a valid header are dropped.
+async fn accept_proxied(
+    plugin_name: Arc<str>,
+    next: Arc<dyn StreamHandler>,
+    mut stream: TcpStream,
+    mut context: FlowContext,
+) {
+    let Ok(Ok((header, initial_data))) = timeout(
+        super::proxy_protocol::HEADER_TIMEOUT,
+        super::proxy_protocol::read_header(&mut stream),
+    )
+    .await
+    else {
+        return;
+    };
+    if let super::proxy_protocol::ProxyHeader::Proxied { src, dst } = header {
+        context.local_peer = src;
+        context.remote_peer = dst.into();
+    }
+    let session_id = crate::log::next_session_id();
+    crate::log::isolate_sync(&plugin_name, session_id, || {
+        next.on_stream(
+            Box::new(CompatFlow::new(stream, 4096)),
+            initial_data,
+            Box::new(context),
+        )
+    });
+}
+
 async fn dial_socket_v4(
     ip: Ipv4Addr,
     port: u16,
@@ -64,6 +109,7 @@ async fn dial_socket_v4(
         Some(socket2::Protocol::TCP),
     )?;
     prepare_socket(&socket)?;
+    super::run_socket_hook(&socket, super::SocketKind::Tcp, false)?;
     if ip.is_loopback() {
         socket.bind(&SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0).into())?
     } else {
@@ -84,6 +130,7 @@ async fn dial_socket_v6(
         Some(socket2::Protocol::TCP),
     )?;
     prepare_socket(&socket)?;
+    super::run_socket_hook(&socket, super::SocketKind::Tcp, true)?;
     if ip.is_loopback() {
         socket.bind(&SocketAddrV6::new(Ipv6Addr::LOCALHOST, 0, 0, 0).into())?
     } else {
@@ -102,8 +149,18 @@ pub async fn dial_stream(
     bind_v6: Option<impl Fn(&mut socket2::Socket) -> FlowResult<()>>,
     initial_data: &[u8],
 ) -> FlowResult<(Box<dyn Stream>, Buffer)> {
+    let tcp_stream = connect_stream(context, resolver, bind_v4, bind_v6).await?;
+    into_stream(tcp_stream, initial_data).await
+}
+
+async fn connect_stream(
+    context: &FlowContext,
+    resolver: Arc<dyn Resolver>,
+    bind_v4: Option<impl Fn(&mut socket2::Socket) -> FlowResult<()>>,
+    bind_v6: Option<impl Fn(&mut socket2::Socket) -> FlowResult<()>>,
+) -> FlowResult<TcpStream> {
     let port = context.remote_peer.port;
-    let mut tcp_stream = match (context.remote_peer.host.clone(), bind_v4, bind_v6) {
+    let tcp_stream = match (context.remote_peer.host.clone(), bind_v4, bind_v6) {
         (HostName::Ip(IpAddr::V4(ip)), Some(bind_v4), _) => {
             dial_socket_v4(ip, port, &bind_v4).await?
         }
@@ -182,7 +239,17 @@ pub async fn dial_stream(
             let (ip_tx, mut ip_rx) = tokio::sync::mpsc::channel::<IpAddr>(1);
             tokio::spawn({
                 let resolver = resolver.clone();
-                async move { super::resolve_dual_stack_ips(domain, &*resolver, ip_tx).await }
+                let abort = context.abort.signal();
+                async move {
+                    // Stop resolving once the dial is dropped or the flow is torn down, rather
+                    // than leaving the queries running on their own.
+                    let dial_dropped = ip_tx.clone();
+                    tokio::select! {
+                        _ = super::resolve_dual_stack_ips(domain, &*resolver, ip_tx) => {}
+                        _ = dial_dropped.closed() => {}
+                        _ = abort.aborted() => {}
+                    }
+                }
             });
             let mut ret = Err(FlowError::NoOutbound);
             let mut futs = FuturesUnordered::new();
@@ -226,6 +293,13 @@ pub async fn dial_stream(
         }
         _ => return Err(FlowError::NoOutbound),
     };
+    Ok(tcp_stream)
+}
+
+async fn into_stream(
+    mut tcp_stream: TcpStream,
+    initial_data: &[u8],
+) -> FlowResult<(Box<dyn Stream>, Buffer)> {
     if !initial_data.is_empty() {
         tcp_stream.write_all(initial_data).await?;
     }
@@ -242,21 +316,50 @@ impl StreamOutboundFactory for super::SocketOutboundFactory {
         let Self {
             bind_addr_v4,
             bind_addr_v6,
+            connect_timeout,
+            dscp,
+            proxy_protocol,
             ..
         } = self;
 
         let resolver = self.resolver.upgrade().ok_or(FlowError::NoOutbound)?;
-        dial_stream(
+        let traffic_class = super::traffic_class_for(*dscp, context, false);
+        let connect = connect_stream(
             context,
             resolver,
             bind_addr_v4.map(|addr| {
-                move |s: &mut socket2::Socket| s.bind(&addr.into()).map_err(FlowError::from)
+                move |s: &mut socket2::Socket| {
+                    if let Some(traffic_class) = traffic_class {
+                        super::set_traffic_class(s, false, traffic_class);
+                    }
+                    s.bind(&addr.into()).map_err(FlowError::from)
+                }
             }),
             bind_addr_v6.map(|addr| {
-                move |s: &mut socket2::Socket| s.bind(&addr.into()).map_err(FlowError::from)
+                move |s: &mut socket2::Socket| {
+                    if let Some(traffic_class) = traffic_class {
+                        super::set_traffic_class(s, true, traffic_class);
+                    }
+                    s.bind(&addr.into()).map_err(FlowError::from)
+                }
             }),
-            initial_data,
-        )
-        .await
+        );
+        let dial = async {
+            let tcp_stream = connect.await?;
+            let Some(version) = proxy_protocol else {
+                return into_stream(tcp_stream, initial_data).await;
+            };
+            // The header announces where the client wanted to go. Domain names
+            // cannot be carried, so the resolved address is used instead.
+            let dst = match &context.remote_peer.host {
+                HostName::Ip(ip) => SocketAddr::new(*ip, context.remote_peer.port),
+                HostName::DomainName(_) => tcp_stream.peer_addr()?,
+            };
+            let mut header =
+                super::proxy_protocol::encode_header(*version, context.local_peer, dst);
+            header.extend_from_slice(initial_data);
+            into_stream(tcp_stream, &header).await
+        };
+        with_flow_deadline(*connect_timeout, &context.abort.signal(), dial).await
     }
 }
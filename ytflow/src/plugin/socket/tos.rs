@@ -0,0 +1,87 @@
+use std::io;
+
+use crate::flow::FlowContext;
+
+const DSCP_MASK: u8 = 0b1111_1100;
+const ECN_MASK: u8 = 0b0000_0011;
+
+/// Works out the traffic class octet to mark outbound packets of a flow with. A static `dscp`
+/// takes precedence over the DSCP carried by the flow. ECN bits are only passed through for UDP,
+/// since the kernel drives ECN on TCP connections by itself.
+pub(crate) fn traffic_class_for(
+    dscp: Option<u8>,
+    context: &FlowContext,
+    is_udp: bool,
+) -> Option<u8> {
+    let carried = context.traffic_class.unwrap_or(0);
+    let ecn = if is_udp { carried & ECN_MASK } else { 0 };
+    let traffic_class = match dscp {
+        Some(dscp) => (dscp << 2) | ecn,
+        None => (carried & DSCP_MASK) | ecn,
+    };
+    Some(traffic_class).filter(|&t| t != 0)
+}
+
+/// Sets `IP_TOS` or `IPV6_TCLASS` on `socket`. Marking is best-effort: the packets are simply
+/// left unmarked where the OS does not allow it.
+pub(crate) fn set_traffic_class(socket: &socket2::Socket, is_ipv6: bool, traffic_class: u8) {
+    let _ = if is_ipv6 {
+        set_tclass_v6(socket, traffic_class)
+    } else {
+        socket.set_tos(traffic_class as u32)
+    };
+}
+
+#[cfg(unix)]
+fn set_tclass_v6(socket: &socket2::Socket, traffic_class: u8) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let value = traffic_class as libc::c_int;
+    let ret = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::IPPROTO_IPV6,
+            libc::IPV6_TCLASS,
+            &value as *const _ as _,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if ret == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_tclass_v6(_socket: &socket2::Socket, _traffic_class: u8) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(traffic_class: Option<u8>) -> FlowContext {
+        let mut context = FlowContext::new(
+            ([127, 0, 0, 1], 1234).into(),
+            std::net::SocketAddr::from(([127, 0, 0, 1], 443)).into(),
+        );
+        context.traffic_class = traffic_class;
+        context
+    }
+
+    #[test]
+    fn test_traffic_class_for() {
+        // EF with ECT(0)
+        let ctx = context(Some(0b1011_1010));
+        assert_eq!(traffic_class_for(None, &ctx, true), Some(0b1011_1010));
+        assert_eq!(traffic_class_for(None, &ctx, false), Some(0b1011_1000));
+        assert_eq!(traffic_class_for(Some(8), &ctx, true), Some(0b0010_0010));
+        assert_eq!(traffic_class_for(Some(8), &ctx, false), Some(0b0010_0000));
+        assert_eq!(traffic_class_for(None, &context(None), true), None);
+        assert_eq!(
+            traffic_class_for(Some(0), &context(Some(0b10)), false),
+            None
+        );
+    }
+}
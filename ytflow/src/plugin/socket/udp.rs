@@ -1,9 +1,9 @@
 use std::future::Future;
 use std::io;
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
 use std::pin::Pin;
 use std::sync::Arc;
-use std::task::{Context, Poll};
+use std::task::{Context, Poll, Waker};
 
 use futures::ready;
 use tokio::io::ReadBuf;
@@ -16,6 +16,16 @@ use crate::flow::*;
 
 const IPV6_RESOLUTION_TIMEOUT: tokio::time::Duration = Duration::from_secs(30);
 
+/// Called with errors of the sockets of a datagram session, which would
+/// otherwise only drop a datagram or end the session silently.
+pub type DatagramErrorSink = Arc<dyn Fn(&FlowError) + Send + Sync>;
+
+fn report_error(on_error: Option<&DatagramErrorSink>, e: FlowError) {
+    if let Some(on_error) = on_error {
+        on_error(&e);
+    }
+}
+
 fn create_socket_v4(
     remote_ip_indicator: Ipv4Addr,
     bind_v4: &impl Fn(&mut socket2::Socket) -> FlowResult<()>,
@@ -26,6 +36,7 @@ fn create_socket_v4(
         Some(socket2::Protocol::UDP),
     )?;
     prepare_socket(&socket)?;
+    super::run_socket_hook(&socket, super::SocketKind::Udp, false)?;
     if remote_ip_indicator.is_loopback() {
         socket.bind(&SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0).into())?
     } else {
@@ -44,6 +55,7 @@ fn create_socket_v6(
         Some(socket2::Protocol::UDP),
     )?;
     prepare_socket(&socket)?;
+    super::run_socket_hook(&socket, super::SocketKind::Udp, true)?;
     if remote_ip_indicator.is_loopback() {
         socket.bind(&SocketAddrV6::new(Ipv6Addr::LOCALHOST, 0, 0, 0).into())?
     } else {
@@ -52,6 +64,14 @@ fn create_socket_v6(
     Ok(socket)
 }
 
+/// ICMP errors reported on connected sockets, such as port unreachable.
+fn is_icmp_error(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionReset
+    )
+}
+
 fn prepare_socket(socket: &socket2::Socket) -> io::Result<()> {
     socket.set_nonblocking(true)?;
     socket.set_reuse_address(true)?;
@@ -74,20 +94,31 @@ pub(super) enum ResolvingAddr {
 pub(super) enum MaybeBoundSocket<BindFn> {
     Disabled,
     Unbound(BindFn),
-    Bound(tokio::net::UdpSocket),
+    /// Along with the peer address if connected, and how to bind an
+    /// unconnected socket in case the session sends elsewhere later.
+    Bound(tokio::net::UdpSocket, Option<(SocketAddr, BindFn)>),
+    /// The peer of a connected socket is unreachable.
+    Refused,
 }
 
 impl<BindFn> MaybeBoundSocket<BindFn> {
     fn is_disabled(&self) -> bool {
         matches!(self, MaybeBoundSocket::Disabled)
     }
-    fn poll_recv_from(&mut self, cx: &mut Context<'_>) -> Poll<Option<(DestinationAddr, Buffer)>> {
+    fn is_refused(&self) -> bool {
+        matches!(self, MaybeBoundSocket::Refused)
+    }
+    fn poll_recv_from(
+        &mut self,
+        cx: &mut Context<'_>,
+        on_error: Option<&DatagramErrorSink>,
+    ) -> Poll<Option<(DestinationAddr, Buffer)>> {
         loop {
             break match self {
-                MaybeBoundSocket::Disabled => Poll::Ready(None),
+                MaybeBoundSocket::Disabled | MaybeBoundSocket::Refused => Poll::Ready(None),
                 // TODO: comment why
                 MaybeBoundSocket::Unbound(_) => Poll::Pending,
-                MaybeBoundSocket::Bound(socket) => {
+                MaybeBoundSocket::Bound(socket, peer) => {
                     let mut buf = Vec::with_capacity(1600);
                     let mut read_buf = ReadBuf::uninit(buf.spare_capacity_mut());
                     match ready!(socket.poll_recv_from(cx, &mut read_buf)) {
@@ -97,47 +128,84 @@ impl<BindFn> MaybeBoundSocket<BindFn> {
                             Poll::Ready(Some((from.into(), buf)))
                         }
                         Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
-                        Err(_) => Poll::Ready(None),
+                        Err(e) if peer.is_some() && is_icmp_error(&e) => {
+                            *self = MaybeBoundSocket::Refused;
+                            Poll::Ready(None)
+                        }
+                        Err(e) => {
+                            report_error(on_error, e.into());
+                            Poll::Ready(None)
+                        }
                     }
                 }
             };
         }
     }
-}
 
-impl<BindFn: Fn(Ipv4Addr) -> FlowResult<socket2::Socket>> MaybeBoundSocket<BindFn> {
-    fn bind_v4_and_get(&mut self, indicator: Ipv4Addr) -> FlowResult<&tokio::net::UdpSocket> {
+    /// Bind the socket with `bind` if not yet, connecting it to `dst` if
+    /// `connect` is set. Returns the socket along with its peer if connected.
+    ///
+    /// A connected socket cannot send elsewhere (`EISCONN` on BSDs), so once
+    /// the session sends to a second destination, the connected socket is
+    /// replaced with an unconnected one for the rest of the session.
+    fn bind_and_get(
+        &mut self,
+        dst: SocketAddr,
+        mut connect: bool,
+        bind: impl FnOnce(&BindFn) -> FlowResult<socket2::Socket>,
+    ) -> FlowResult<(&tokio::net::UdpSocket, Option<SocketAddr>)> {
+        if matches!(self, MaybeBoundSocket::Bound(_, Some((peer, _))) if *peer != dst) {
+            let MaybeBoundSocket::Bound(_, Some((_, bind_fn))) =
+                std::mem::replace(self, MaybeBoundSocket::Disabled)
+            else {
+                unreachable!()
+            };
+            *self = MaybeBoundSocket::Unbound(bind_fn);
+            connect = false;
+        }
         match self {
-            MaybeBoundSocket::Disabled => Err(FlowError::NoOutbound),
+            MaybeBoundSocket::Disabled | MaybeBoundSocket::Refused => Err(FlowError::NoOutbound),
             MaybeBoundSocket::Unbound(bind_fn) => {
-                let socket = bind_fn(indicator)?;
+                let socket = bind(bind_fn)?;
+                if connect {
+                    socket.connect(&dst.into())?;
+                }
                 let socket = tokio::net::UdpSocket::from_std(socket.into())?;
-                *self = MaybeBoundSocket::Bound(socket);
+                let MaybeBoundSocket::Unbound(bind_fn) =
+                    std::mem::replace(self, MaybeBoundSocket::Disabled)
+                else {
+                    unreachable!()
+                };
+                *self = MaybeBoundSocket::Bound(socket, connect.then_some((dst, bind_fn)));
                 match self {
-                    MaybeBoundSocket::Bound(socket) => Ok(socket),
+                    MaybeBoundSocket::Bound(socket, peer) => {
+                        Ok((socket, peer.as_ref().map(|(p, _)| *p)))
+                    }
                     _ => unreachable!(),
                 }
             }
-            MaybeBoundSocket::Bound(socket) => Ok(socket),
+            MaybeBoundSocket::Bound(socket, peer) => Ok((socket, peer.as_ref().map(|(p, _)| *p))),
         }
     }
 }
 
+impl<BindFn: Fn(Ipv4Addr) -> FlowResult<socket2::Socket>> MaybeBoundSocket<BindFn> {
+    fn bind_v4_and_get(
+        &mut self,
+        dst: SocketAddrV4,
+        connect: bool,
+    ) -> FlowResult<(&tokio::net::UdpSocket, Option<SocketAddr>)> {
+        self.bind_and_get(dst.into(), connect, |bind_fn| bind_fn(*dst.ip()))
+    }
+}
+
 impl<BindFn: Fn(Ipv6Addr) -> FlowResult<socket2::Socket>> MaybeBoundSocket<BindFn> {
-    fn bind_v6_and_get(&mut self, indicator: Ipv6Addr) -> FlowResult<&tokio::net::UdpSocket> {
-        match self {
-            MaybeBoundSocket::Disabled => Err(FlowError::NoOutbound),
-            MaybeBoundSocket::Unbound(bind_fn) => {
-                let socket = bind_fn(indicator)?;
-                let socket = tokio::net::UdpSocket::from_std(socket.into())?;
-                *self = MaybeBoundSocket::Bound(socket);
-                match self {
-                    MaybeBoundSocket::Bound(socket) => Ok(socket),
-                    _ => unreachable!(),
-                }
-            }
-            MaybeBoundSocket::Bound(socket) => Ok(socket),
-        }
+    fn bind_v6_and_get(
+        &mut self,
+        dst: SocketAddrV6,
+        connect: bool,
+    ) -> FlowResult<(&tokio::net::UdpSocket, Option<SocketAddr>)> {
+        self.bind_and_get(dst.into(), connect, |bind_fn| bind_fn(*dst.ip()))
     }
 }
 
@@ -148,18 +216,51 @@ struct UdpSocket<BindFnV4, BindFnV6> {
     bind_notify: (Option<oneshot::Sender<()>>, Option<oneshot::Receiver<()>>),
     tx_buf: Option<(ResolvingAddr, Buffer)>,
     rx_v6_next: bool,
+    /// Connect sockets to the first destination they send to, as long as the
+    /// session does not send elsewhere.
+    connect: bool,
+    recv_waker: Option<Waker>,
+    on_error: Option<DatagramErrorSink>,
+}
+
+/// Send `buf` to `dst` through `socket`, which is connected to `peer` if
+/// any. Returns whether the peer is reported unreachable. Other errors are
+/// reported to `on_error`.
+fn poll_send_via(
+    cx: &mut Context<'_>,
+    socket: &tokio::net::UdpSocket,
+    peer: Option<SocketAddr>,
+    buf: &[u8],
+    dst: SocketAddr,
+    on_error: Option<&DatagramErrorSink>,
+) -> Poll<bool> {
+    let _ = ready!(socket.poll_send_ready(cx));
+    let res = if peer == Some(dst) {
+        ready!(socket.poll_send(cx, buf))
+    } else {
+        ready!(socket.poll_send_to(cx, buf, dst))
+    };
+    Poll::Ready(match res {
+        Ok(_) => false,
+        Err(e) if peer.is_some() && is_icmp_error(&e) => true,
+        Err(e) => {
+            report_error(on_error, e.into());
+            false
+        }
+    })
 }
 
 fn poll_recv_from_two<BindA, BindB>(
     cx: &mut Context<'_>,
     socket_a: &mut MaybeBoundSocket<BindA>,
     socket_b: &mut MaybeBoundSocket<BindB>,
+    on_error: Option<&DatagramErrorSink>,
 ) -> Poll<Option<(DestinationAddr, Buffer)>> {
-    let res_a = socket_a.poll_recv_from(cx);
+    let res_a = socket_a.poll_recv_from(cx, on_error);
     if let ret @ Poll::Ready(Some(_)) = res_a {
         return ret;
     }
-    let res_b = socket_b.poll_recv_from(cx);
+    let res_b = socket_b.poll_recv_from(cx, on_error);
     match (res_a, res_b) {
         (a @ Poll::Ready(Some(_)), _) => a,
         (_, b @ Poll::Ready(Some(_))) => b,
@@ -179,8 +280,12 @@ impl<
             socket_v4,
             socket_v6,
             bind_notify: (bind_notify_tx, _),
+            connect,
+            recv_waker,
+            on_error,
             ..
         } = &mut *self;
+        let on_error = on_error.as_ref();
         let ((v4, v6, port), buf) = loop {
             match tx_buf.as_mut() {
                 Some((ResolvingAddr::Resolving(fut), _buf)) => {
@@ -207,22 +312,43 @@ impl<
         };
         *bind_notify_tx = None;
 
-        if let Some(v6) = v6 {
-            if let Ok(socket) = socket_v6.bind_v6_and_get(v6) {
-                let _ = ready!(socket.poll_send_ready(cx));
-                let _ =
-                    ready!(socket.poll_send_to(cx, buf, SocketAddrV6::new(v6, port, 0, 0).into()));
-                *tx_buf = None;
-                return Poll::Ready(());
+        let refused = if let Some(v6) = v6 {
+            let dst = SocketAddrV6::new(v6, port, 0, 0);
+            let (socket, peer) = match socket_v6.bind_v6_and_get(dst, *connect) {
+                Ok(bound) => bound,
+                Err(e) => {
+                    report_error(on_error, e);
+                    return Poll::Ready(());
+                }
+            };
+            let refused = ready!(poll_send_via(cx, socket, peer, buf, dst.into(), on_error));
+            if refused {
+                *socket_v6 = MaybeBoundSocket::Refused;
             }
+            refused
         } else if let Some(v4) = v4 {
-            if let Ok(socket) = socket_v4.bind_v4_and_get(v4) {
-                let _ = ready!(socket.poll_send_ready(cx));
-                let _ = ready!(socket.poll_send_to(cx, buf, SocketAddrV4::new(v4, port).into()));
-                *tx_buf = None;
-                return Poll::Ready(());
+            let dst = SocketAddrV4::new(v4, port);
+            let (socket, peer) = match socket_v4.bind_v4_and_get(dst, *connect) {
+                Ok(bound) => bound,
+                Err(e) => {
+                    report_error(on_error, e);
+                    return Poll::Ready(());
+                }
+            };
+            let refused = ready!(poll_send_via(cx, socket, peer, buf, dst.into(), on_error));
+            if refused {
+                *socket_v4 = MaybeBoundSocket::Refused;
+            }
+            refused
+        } else {
+            return Poll::Ready(());
+        };
+        if refused {
+            if let Some(waker) = recv_waker.take() {
+                waker.wake();
             }
         }
+        *tx_buf = None;
         Poll::Ready(())
     }
     fn send_to(&mut self, dst: DestinationAddr, buf: Buffer) {
@@ -289,11 +415,20 @@ impl<
         let rx_v6_next = self.rx_v6_next;
         self.rx_v6_next = !rx_v6_next;
         // For fairness
-        if rx_v6_next {
-            poll_recv_from_two(cx, &mut self.socket_v6, &mut self.socket_v4)
+        let on_error = self.on_error.as_ref();
+        let res = if rx_v6_next {
+            poll_recv_from_two(cx, &mut self.socket_v6, &mut self.socket_v4, on_error)
         } else {
-            poll_recv_from_two(cx, &mut self.socket_v4, &mut self.socket_v6)
+            poll_recv_from_two(cx, &mut self.socket_v4, &mut self.socket_v6, on_error)
+        };
+        // The session ends as soon as the peer of either socket is unreachable
+        if self.socket_v4.is_refused() || self.socket_v6.is_refused() {
+            return Poll::Ready(None);
+        }
+        if res.is_pending() {
+            self.recv_waker = Some(cx.waker().clone());
         }
+        res
     }
 }
 
@@ -302,6 +437,8 @@ pub async fn dial_datagram_session(
     resolver: Arc<dyn Resolver>,
     bind_v4: Option<impl Fn(&mut socket2::Socket) -> FlowResult<()> + Send + Sync + 'static>,
     bind_v6: Option<impl Fn(&mut socket2::Socket) -> FlowResult<()> + Send + Sync + 'static>,
+    connect: bool,
+    on_error: Option<DatagramErrorSink>,
 ) -> FlowResult<Box<dyn DatagramSession>> {
     let socket_v4 = if context.af_sensitive && !context.local_peer.is_ipv4() {
         MaybeBoundSocket::Disabled
@@ -334,6 +471,9 @@ pub async fn dial_datagram_session(
         tx_buf: None,
         resolver,
         rx_v6_next: false,
+        connect,
+        recv_waker: None,
+        on_error,
     }))
 }
 
@@ -343,6 +483,8 @@ impl DatagramSessionFactory for super::SocketOutboundFactory {
         let Self {
             bind_addr_v4,
             bind_addr_v6,
+            dscp,
+            udp_connected,
             ..
         } = self;
 
@@ -350,16 +492,139 @@ impl DatagramSessionFactory for super::SocketOutboundFactory {
             Some(r) => r,
             None => return Err(FlowError::NoOutbound),
         };
+        let traffic_class = super::traffic_class_for(*dscp, &context, true);
         dial_datagram_session(
             &context,
             resolver,
             bind_addr_v4.map(|addr| {
-                move |s: &mut socket2::Socket| s.bind(&addr.into()).map_err(FlowError::from)
+                move |s: &mut socket2::Socket| {
+                    if let Some(traffic_class) = traffic_class {
+                        super::set_traffic_class(s, false, traffic_class);
+                    }
+                    s.bind(&addr.into()).map_err(FlowError::from)
+                }
             }),
             bind_addr_v6.map(|addr| {
-                move |s: &mut socket2::Socket| s.bind(&addr.into()).map_err(FlowError::from)
+                move |s: &mut socket2::Socket| {
+                    if let Some(traffic_class) = traffic_class {
+                        super::set_traffic_class(s, true, traffic_class);
+                    }
+                    s.bind(&addr.into()).map_err(FlowError::from)
+                }
             }),
+            *udp_connected,
+            None,
         )
         .await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use futures::future::poll_fn;
+
+    use super::*;
+    use crate::flow::testing::*;
+
+    async fn connected_session() -> Box<dyn DatagramSession> {
+        let bind = |s: &mut socket2::Socket| {
+            s.bind(&SocketAddr::from((Ipv4Addr::LOCALHOST, 0)).into())
+                .map_err(FlowError::from)
+        };
+        dial_datagram_session(
+            &context("127.0.0.1:53"),
+            Arc::new(MockResolver::new()),
+            Some(bind),
+            None::<fn(&mut socket2::Socket) -> FlowResult<()>>,
+            true,
+            None,
+        )
+        .await
+        .unwrap()
+    }
+
+    async fn send(session: &mut Box<dyn DatagramSession>, dst: SocketAddr, buf: &[u8]) {
+        poll_fn(|cx| session.poll_send_ready(cx)).await;
+        session.send_to(dst.into(), buf.to_vec());
+        poll_fn(|cx| session.poll_send_ready(cx)).await;
+    }
+
+    async fn echo_once(peer: &tokio::net::UdpSocket) -> Vec<u8> {
+        let mut buf = [0; 64];
+        let (len, from) = peer.recv_from(&mut buf).await.unwrap();
+        peer.send_to(&buf[..len], from).await.unwrap();
+        buf[..len].to_vec()
+    }
+
+    #[tokio::test]
+    async fn test_connected_single_destination() {
+        let peer = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let peer_addr = peer.local_addr().unwrap();
+        let mut session = connected_session().await;
+
+        for msg in [&b"one"[..], b"two"] {
+            send(&mut session, peer_addr, msg).await;
+            assert_eq!(echo_once(&peer).await, msg);
+            let received = poll_fn(|cx| session.poll_recv_from(cx)).await;
+            assert_eq!(received, Some((peer_addr.into(), msg.to_vec())));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_connected_falls_back_for_second_destination() {
+        let peer_a = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let peer_b = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let (addr_a, addr_b) = (peer_a.local_addr().unwrap(), peer_b.local_addr().unwrap());
+        let mut session = connected_session().await;
+
+        send(&mut session, addr_a, b"a").await;
+        assert_eq!(echo_once(&peer_a).await, b"a");
+        assert_eq!(
+            poll_fn(|cx| session.poll_recv_from(cx)).await,
+            Some((addr_a.into(), b"a".to_vec()))
+        );
+
+        // Like a STUN client probing another server.
+        send(&mut session, addr_b, b"b").await;
+        assert_eq!(echo_once(&peer_b).await, b"b");
+        assert_eq!(
+            poll_fn(|cx| session.poll_recv_from(cx)).await,
+            Some((addr_b.into(), b"b".to_vec()))
+        );
+        // Both destinations are reachable from now on.
+        send(&mut session, addr_a, b"c").await;
+        assert_eq!(echo_once(&peer_a).await, b"c");
+        assert_eq!(
+            poll_fn(|cx| session.poll_recv_from(cx)).await,
+            Some((addr_a.into(), b"c".to_vec()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bind_error_reported() {
+        let errors = Arc::new(std::sync::Mutex::new(vec![]));
+        let on_error: DatagramErrorSink = {
+            let errors = errors.clone();
+            Arc::new(move |e: &FlowError| {
+                errors.lock().unwrap().push(
+                    matches!(e, FlowError::Io(e) if e.kind() == io::ErrorKind::AddrNotAvailable),
+                )
+            })
+        };
+        let mut session = dial_datagram_session(
+            &context("192.0.2.1:53"),
+            Arc::new(MockResolver::new()),
+            Some(|_: &mut socket2::Socket| -> FlowResult<()> {
+                Err(io::Error::from(io::ErrorKind::AddrNotAvailable).into())
+            }),
+            None::<fn(&mut socket2::Socket) -> FlowResult<()>>,
+            false,
+            Some(on_error),
+        )
+        .await
+        .unwrap();
+
+        send(&mut session, "192.0.2.1:53".parse().unwrap(), b"a").await;
+        assert_eq!(*errors.lock().unwrap(), [true]);
+    }
+}
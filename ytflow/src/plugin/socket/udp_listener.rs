@@ -3,17 +3,26 @@ use std::io;
 use std::net::{SocketAddr, ToSocketAddrs};
 use std::sync::{Arc, Weak};
 use std::task::{ready, Context, Poll};
+use std::time::Duration;
 
 use flume::{bounded, SendError};
 
 use crate::flow::*;
 
 pub fn listen_udp(
+    plugin_name: Arc<str>,
     next: Weak<dyn DatagramSessionHandler>,
     addr: impl ToSocketAddrs + Send + 'static,
+    reuse_port: bool,
 ) -> io::Result<tokio::task::JoinHandle<()>> {
     let mut session_map = BTreeMap::new();
-    let listener = std::net::UdpSocket::bind(addr)?;
+    let listener = match super::activation::take_activated_socket(super::SocketKind::Udp, &addr)? {
+        Some(socket) => socket.into(),
+        None if reuse_port => {
+            super::preflight::bind_reuse_port(super::SocketKind::Udp, &addr)?.into()
+        }
+        None => std::net::UdpSocket::bind(addr)?,
+    };
     listener.set_nonblocking(true)?;
     Ok(tokio::spawn(async move {
         let listener = Arc::new(
@@ -26,7 +35,8 @@ pub fn listen_udp(
             Err(_) => return,
         }
         .into();
-        let mut buf = [0u8; 4096];
+        // Large enough for any UDP payload, e.g. SOCKS5 datagrams up to 64 KiB.
+        let mut buf = vec![0u8; u16::MAX as usize];
         loop {
             let (size, from) = match listener.recv_from(&mut buf).await {
                 Ok(r) => r,
@@ -38,17 +48,20 @@ pub fn listen_udp(
             let tx = session_map.entry(from).or_insert_with(|| {
                 let (tx, rx) = bounded(64);
                 if let Some(next) = next.upgrade() {
-                    next.on_session(
-                        Box::new(MultiplexedDatagramSessionAdapter::new(
-                            InboundUdpSession {
-                                socket: listener.clone(),
-                                tx_buf: None,
-                            },
-                            rx.into_stream(),
-                            120,
-                        )),
-                        Box::new(FlowContext::new_af_sensitive(from, listen_addr.clone())),
-                    );
+                    let session_id = crate::log::next_session_id();
+                    crate::log::isolate_sync(&plugin_name, session_id, || {
+                        next.on_session(
+                            Box::new(MultiplexedDatagramSessionAdapter::new(
+                                InboundUdpSession {
+                                    socket: listener.clone(),
+                                    tx_buf: None,
+                                },
+                                rx.into_stream(),
+                                Duration::from_secs(120),
+                            )),
+                            Box::new(FlowContext::new_af_sensitive(from, listen_addr.clone())),
+                        )
+                    });
                 }
                 tx
             });
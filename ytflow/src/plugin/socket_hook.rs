@@ -0,0 +1,34 @@
+use std::io;
+use std::sync::{Arc, RwLock};
+
+#[cfg(unix)]
+pub type RawSocket = std::os::fd::RawFd;
+#[cfg(windows)]
+pub type RawSocket = std::os::windows::io::RawSocket;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum SocketKind {
+    Tcp = 1,
+    Udp = 2,
+    Icmp = 3,
+}
+
+/// Called with every outbound socket right after it is created, before it is
+/// bound or connected. Embedders may use it to call `VpnService.protect()` on
+/// Android, or to set a firewall mark or bind to a VRF on Linux. Returning an
+/// error aborts the connection attempt.
+pub type SocketHook = dyn Fn(RawSocket, SocketKind, bool) -> io::Result<()> + Send + Sync;
+
+static SOCKET_HOOK: RwLock<Option<Arc<SocketHook>>> = RwLock::new(None);
+
+/// Install a process-wide socket hook, replacing the previous one. Pass
+/// `None` to remove it.
+pub fn set_socket_hook(hook: Option<Arc<SocketHook>>) {
+    *SOCKET_HOOK.write().unwrap() = hook;
+}
+
+#[cfg_attr(not(feature = "plugins"), allow(dead_code))]
+pub(crate) fn current_socket_hook() -> Option<Arc<SocketHook>> {
+    SOCKET_HOOK.read().unwrap().clone()
+}
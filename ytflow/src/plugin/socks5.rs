@@ -1,32 +1,78 @@
+mod datagram;
+mod socks4;
+
 use std::sync::{Arc, Weak};
+use std::time::Duration;
 
 use async_trait::async_trait;
 use futures::future::poll_fn;
 
 use crate::flow::*;
 use crate::plugin::shadowsocks::util::{parse_dest, write_dest};
+pub use datagram::{Socks5DatagramHandler, Socks5DatagramOutbound, UdpAssociations};
+
+const CMD_CONNECT: u8 = 1;
+const CMD_UDP_ASSOCIATE: u8 = 3;
 
 pub struct Socks5Handler {
     auth_req: Option<Arc<[u8]>>,
+    udp_associations: UdpAssociations,
     next: Weak<dyn StreamHandler>,
 }
 
+/// The protocol version spoken by [`Socks5Outbound`] to the upstream server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SocksVersion {
+    #[default]
+    V5,
+    /// SOCKS4. Only IPv4 destinations can be connected to.
+    V4,
+    /// SOCKS4a, which lets the server resolve domain names.
+    V4a,
+}
+
 pub struct Socks5Outbound {
+    version: SocksVersion,
     auth_req: Option<Buffer>,
+    user_id: Buffer,
+    handshake_timeout: Duration,
     next: Weak<dyn StreamOutboundFactory>,
 }
 
 impl Socks5Handler {
-    pub fn new(cred: Option<(&[u8], &[u8])>, next: Weak<dyn StreamHandler>) -> Self {
+    pub fn new(
+        cred: Option<(&[u8], &[u8])>,
+        udp_associations: UdpAssociations,
+        next: Weak<dyn StreamHandler>,
+    ) -> Self {
         let auth_req = cred.map(|cred| get_cred_req(cred).into());
-        Self { auth_req, next }
+        Self {
+            auth_req,
+            udp_associations,
+            next,
+        }
     }
 }
 
 impl Socks5Outbound {
-    pub fn new(cred: Option<(&[u8], &[u8])>, next: Weak<dyn StreamOutboundFactory>) -> Self {
-        let auth_req = cred.map(get_cred_req);
-        Self { auth_req, next }
+    pub fn new(
+        cred: Option<(&[u8], &[u8])>,
+        next: Weak<dyn StreamOutboundFactory>,
+        handshake_timeout: Duration,
+        version: SocksVersion,
+    ) -> Self {
+        // SOCKS4 has no password authentication. The username is sent as USERID.
+        let user_id = cred.map(|(user, _)| user.to_vec()).unwrap_or_default();
+        let auth_req = cred
+            .filter(|_| version == SocksVersion::V5)
+            .map(get_cred_req);
+        Self {
+            version,
+            auth_req,
+            user_id,
+            handshake_timeout,
+            next,
+        }
     }
 }
 
@@ -39,11 +85,19 @@ fn get_cred_req(cred: (&[u8], &[u8])) -> Vec<u8> {
     buf
 }
 
+enum Socks5Request {
+    Connect(DestinationAddr, Buffer),
+    /// The control connection of a UDP association, to be held open until the
+    /// client closes it.
+    UdpAssociate(StreamReader),
+}
+
 async fn serve_handshake(
     auth_req: Option<Arc<[u8]>>,
     stream: &mut dyn Stream,
     initial_data: Buffer,
-) -> FlowResult<(DestinationAddr, Vec<u8>)> {
+    udp_relay_addr: &DestinationAddr,
+) -> FlowResult<Socks5Request> {
     let mut reader = StreamReader::new(128, initial_data);
     let nauth = reader
         .read_exact(stream, 2, |buf| {
@@ -106,7 +160,7 @@ async fn serve_handshake(
         }
     }
 
-    let req_len = match reader
+    let (cmd, req_len) = match reader
         .peek_at_least(stream, 5, |buf| {
             let dst_len = match buf[3] {
                 1 => 4,
@@ -121,85 +175,109 @@ async fn serve_handshake(
         })
         .await?
     {
-        Ok((cmd, _)) if cmd != 1 => {
-            // TCP bind and UDP and is not supported yet
+        // TCP bind is not supported yet
+        Ok((cmd, len)) if cmd == CMD_CONNECT || cmd == CMD_UDP_ASSOCIATE => (cmd, len),
+        Ok(_) | Err(_) => {
             send_response(stream, &[0x05, 0x07, 0, 0x01, 0, 0, 0, 0, 0, 0]).await?;
             return Err(FlowError::UnexpectedData);
         }
-        Err(_) => {
-            send_response(stream, &[0x05, 0x07, 0, 0x01, 0, 0, 0, 0, 0, 0]).await?;
-            return Err(FlowError::UnexpectedData);
-        }
-        Ok((_, len)) => len,
     };
     let dest = reader
         .read_exact(stream, req_len, |buf| parse_dest(&buf[3..]))
         .await?
         .ok_or(FlowError::UnexpectedData)?
         .0;
+    if cmd == CMD_UDP_ASSOCIATE {
+        // DST.ADDR is where the client will send datagrams from, which is
+        // usually left unspecified. Clients are told to send datagrams to the
+        // address they connected to, where a UDP listener is expected.
+        let mut res = Vec::with_capacity(3 + 1 + 1 + 255 + 2);
+        res.extend([0x05, 0, 0]);
+        write_dest(&mut res, udp_relay_addr);
+        send_response(stream, &res).await?;
+        return Ok(Socks5Request::UdpAssociate(reader));
+    }
     send_response(stream, &[0x05, 0, 0, 0x01, 0, 0, 0, 0, 0, 0]).await?;
-    Ok((dest, reader.into_buffer().unwrap_or_default()))
+    Ok(Socks5Request::Connect(
+        dest,
+        reader.into_buffer().unwrap_or_default(),
+    ))
 }
 
 async fn perform_handshake(
     context: &mut FlowContext,
     auth_req: &Option<Buffer>,
+    handshake_timeout: Duration,
     stream_factory: Arc<dyn StreamOutboundFactory>,
-) -> FlowResult<(Box<dyn Stream>, Buffer)> {
-    let dest = context.remote_peer.clone();
-    let (mut stream, auth_accepted, mut reader) = if let Some(auth_req) = auth_req {
-        let (mut stream, initial_res) = stream_factory
-            .create_outbound(context, &[0x05, 0x01, 0x02])
-            .await?;
-        let mut reader = StreamReader::new(32, initial_res);
+    cmd: u8,
+    dest: &DestinationAddr,
+) -> FlowResult<(Box<dyn Stream>, DestinationAddr, Buffer)> {
+    let greeting: &[u8] = if auth_req.is_some() {
+        &[0x05, 0x01, 0x02]
+    } else {
+        &[0x05, 0x01, 0]
+    };
+    let abort = context.abort.signal();
+    let (mut stream, initial_res) = stream_factory.create_outbound(context, greeting).await?;
+    let (bound, initial_res) = with_flow_deadline(
+        handshake_timeout,
+        &abort,
+        negotiate(&mut *stream, initial_res, auth_req, cmd, dest),
+    )
+    .await?;
+    Ok((stream, bound, initial_res))
+}
+
+/// Authenticate and send a request. Returns BND.ADDR and BND.PORT from the
+/// reply.
+async fn negotiate(
+    stream: &mut dyn Stream,
+    initial_res: Buffer,
+    auth_req: &Option<Buffer>,
+    cmd: u8,
+    dest: &DestinationAddr,
+) -> FlowResult<(DestinationAddr, Buffer)> {
+    let mut reader = StreamReader::new(32, initial_res);
+    let auth_accepted = if let Some(auth_req) = auth_req {
         let auth_accepted = reader
-            .read_exact(&mut *stream, 2, |buf| buf == [0x05, 0x02])
+            .read_exact(stream, 2, |buf| buf == [0x05, 0x02])
             .await?;
         if !auth_accepted {
             return Err(FlowError::UnexpectedData);
         }
-        send_response(&mut *stream, auth_req).await?;
-        let auth_accepted = reader
-            .read_exact(&mut *stream, 2, |buf| buf == [0x01, 0])
-            .await?;
-        (stream, auth_accepted, reader)
+        send_response(stream, auth_req).await?;
+        reader.read_exact(stream, 2, |buf| buf == [0x01, 0]).await?
     } else {
-        let (mut stream, initial_res) = stream_factory
-            .create_outbound(context, &[0x05, 0x01, 0])
-            .await?;
-        let mut reader = StreamReader::new(32, initial_res);
-        let auth_accepted = reader
-            .read_exact(&mut *stream, 2, |buf| buf == [0x05, 0])
-            .await?;
-        (stream, auth_accepted, reader)
+        reader.read_exact(stream, 2, |buf| buf == [0x05, 0]).await?
     };
     if !auth_accepted {
         return Err(FlowError::UnexpectedData);
     }
 
     let mut req = Vec::with_capacity(300);
-    req.extend([0x05, 0x01, 0]);
-    write_dest(&mut req, &dest);
-    send_response(&mut *stream, &req).await?;
-    let granted = reader
-        .read_exact(&mut *stream, 2, |buf| buf == [0x05, 0])
-        .await?;
-    if granted {
-        let remaining = reader
-            .read_exact(&mut *stream, 4, |buf| {
-                Ok(match buf[1] {
-                    1 => 4,
-                    4 => 16,
-                    3 => buf[2] as usize - 1 + 2,
-                    _ => return Err(FlowError::UnexpectedData),
-                })
-            })
-            .await??;
-        reader.read_exact(&mut *stream, remaining, |_| {}).await?;
-        Ok((stream, reader.into_buffer().unwrap_or_default()))
-    } else {
-        Err(FlowError::UnexpectedData)
+    req.extend([0x05, cmd, 0]);
+    write_dest(&mut req, dest);
+    send_response(stream, &req).await?;
+    let granted = reader.read_exact(stream, 2, |buf| buf == [0x05, 0]).await?;
+    if !granted {
+        return Err(FlowError::UnexpectedData);
     }
+    // RSV, ATYP, BND.ADDR and BND.PORT
+    let reply_len = reader
+        .peek_at_least(stream, 3, |buf| {
+            Ok(match buf[1] {
+                1 => 4,
+                4 => 16,
+                3 => buf[2] as usize + 1,
+                _ => return Err(FlowError::UnexpectedData),
+            } + 4)
+        })
+        .await??;
+    let (bound, _) = reader
+        .read_exact(stream, reply_len, |buf| parse_dest(&buf[1..]))
+        .await?
+        .ok_or(FlowError::UnexpectedData)?;
+    Ok((bound, reader.into_buffer().unwrap_or_default()))
 }
 
 impl StreamHandler for Socks5Handler {
@@ -214,12 +292,20 @@ impl StreamHandler for Socks5Handler {
             None => return,
         };
         let auth_req = self.auth_req.clone();
+        let udp_associations = self.udp_associations.clone();
         tokio::spawn(async move {
-            let (dest, initial_data) =
-                match serve_handshake(auth_req, &mut *lower, initial_data).await {
-                    Ok(dest) => dest,
-                    Err(_) => return,
-                };
+            let req =
+                serve_handshake(auth_req, &mut *lower, initial_data, &context.remote_peer).await;
+            let (dest, initial_data) = match req {
+                Ok(Socks5Request::Connect(dest, initial_data)) => (dest, initial_data),
+                Ok(Socks5Request::UdpAssociate(mut reader)) => {
+                    let _association = udp_associations.register(context.local_peer.ip());
+                    // The association terminates when the control connection closes.
+                    while reader.read_exact(&mut *lower, 1, |_| ()).await.is_ok() {}
+                    return;
+                }
+                Err(_) => return,
+            };
             context.remote_peer = dest;
             context.af_sensitive = false;
             next.on_stream(lower, initial_data, context)
@@ -238,7 +324,33 @@ impl StreamOutboundFactory for Socks5Outbound {
             Some(next) => next,
             None => return Err(FlowError::UnexpectedData),
         };
-        let (mut stream, initial_res) = perform_handshake(context, &self.auth_req, next).await?;
+        let dest = context.remote_peer.clone();
+        if self.version != SocksVersion::V5 {
+            let req = socks4::build_connect_request(
+                &dest,
+                &self.user_id,
+                self.version == SocksVersion::V4a,
+            )?;
+            let abort = context.abort.signal();
+            let (mut stream, initial_res) = next.create_outbound(context, &req).await?;
+            let initial_res = with_flow_deadline(
+                self.handshake_timeout,
+                &abort,
+                socks4::read_connect_reply(&mut *stream, initial_res),
+            )
+            .await?;
+            send(&mut *stream, initial_data).await?;
+            return Ok((stream, initial_res));
+        }
+        let (mut stream, _, initial_res) = perform_handshake(
+            context,
+            &self.auth_req,
+            self.handshake_timeout,
+            next,
+            CMD_CONNECT,
+            &dest,
+        )
+        .await?;
         send(&mut *stream, initial_data).await?;
         Ok((stream, initial_res))
     }
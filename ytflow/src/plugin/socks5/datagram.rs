@@ -0,0 +1,382 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{ready, Context, Poll};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use tokio::task::JoinHandle;
+
+use crate::flow::*;
+use crate::plugin::shadowsocks::util::{parse_dest, write_dest};
+
+/// The largest payload a SOCKS5 UDP datagram may carry after reassembly.
+const MAX_DATAGRAM_SIZE: usize = u16::MAX as usize;
+/// RFC 1928 requires the reassembly timer to be no less than 5 seconds.
+const REASSEMBLY_TIMEOUT: Duration = Duration::from_secs(5);
+const FRAG_END_OF_SEQUENCE: u8 = 0x80;
+
+/// Client IPs with an open UDP ASSOCIATE control connection. Datagrams from
+/// other addresses are dropped.
+#[derive(Clone, Default)]
+pub struct UdpAssociations(Arc<Mutex<HashMap<IpAddr, usize>>>);
+
+pub(super) struct AssociationGuard {
+    associations: UdpAssociations,
+    ip: IpAddr,
+}
+
+impl UdpAssociations {
+    pub(super) fn register(&self, ip: IpAddr) -> AssociationGuard {
+        let ip = ip.to_canonical();
+        *self.0.lock().unwrap().entry(ip).or_default() += 1;
+        AssociationGuard {
+            associations: self.clone(),
+            ip,
+        }
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        self.0.lock().unwrap().contains_key(&ip.to_canonical())
+    }
+}
+
+impl Drop for AssociationGuard {
+    fn drop(&mut self) {
+        let mut map = self.associations.0.lock().unwrap();
+        if let Some(count) = map.get_mut(&self.ip) {
+            *count -= 1;
+            if *count == 0 {
+                map.remove(&self.ip);
+            }
+        }
+    }
+}
+
+pub struct Socks5DatagramHandler {
+    associations: UdpAssociations,
+    next: Weak<dyn DatagramSessionHandler>,
+}
+
+impl Socks5DatagramHandler {
+    pub fn new(associations: UdpAssociations, next: Weak<dyn DatagramSessionHandler>) -> Self {
+        Self { associations, next }
+    }
+}
+
+impl DatagramSessionHandler for Socks5DatagramHandler {
+    fn on_session(&self, session: Box<dyn DatagramSession>, mut context: Box<FlowContext>) {
+        let Some(next) = self.next.upgrade() else {
+            return;
+        };
+        if !self.associations.contains(context.local_peer.ip()) {
+            // TODO: log rejected session
+            return;
+        }
+        context.af_sensitive = false;
+        next.on_session(
+            Box::new(Socks5DatagramSession {
+                lower: session,
+                client: context.local_peer.into(),
+                reassembly: Reassembly::default(),
+            }),
+            context,
+        )
+    }
+}
+
+/// Fragments of a datagram being reassembled, as described in RFC 1928 section 7.
+#[derive(Default)]
+struct Reassembly {
+    /// The destination and position of the last queued fragment, and when the
+    /// first fragment arrived.
+    state: Option<(DestinationAddr, u8, Instant)>,
+    buf: Buffer,
+}
+
+impl Reassembly {
+    fn reset(&mut self) {
+        self.state = None;
+        self.buf.clear();
+    }
+
+    /// Queue a fragment and return the whole datagram once the last fragment
+    /// arrives. A fragment out of order abandons the queue.
+    fn push(&mut self, frag: u8, dest: DestinationAddr, payload: &[u8]) -> Option<Buffer> {
+        let pos = frag & !FRAG_END_OF_SEQUENCE;
+        let continues = match &self.state {
+            Some((queued_dest, last_pos, started)) => {
+                pos == *last_pos + 1
+                    && *queued_dest == dest
+                    && started.elapsed() < REASSEMBLY_TIMEOUT
+            }
+            None => false,
+        };
+        if !continues {
+            // TODO: log abandoned fragments
+            self.reset();
+            if pos != 1 {
+                return None;
+            }
+        }
+        if self.buf.len() + payload.len() > MAX_DATAGRAM_SIZE {
+            self.reset();
+            return None;
+        }
+        self.buf.extend_from_slice(payload);
+        if frag & FRAG_END_OF_SEQUENCE == 0 {
+            let started = self.state.take().map_or_else(Instant::now, |(_, _, s)| s);
+            self.state = Some((dest, pos, started));
+            return None;
+        }
+        self.state = None;
+        Some(std::mem::take(&mut self.buf))
+    }
+}
+
+struct Socks5DatagramSession {
+    lower: Box<dyn DatagramSession>,
+    client: DestinationAddr,
+    reassembly: Reassembly,
+}
+
+/// Strip the header of a datagram from the relay, or queue it if it is a
+/// fragment.
+fn decode_datagram(
+    reassembly: &mut Reassembly,
+    mut buf: Buffer,
+) -> Option<(DestinationAddr, Buffer)> {
+    // +----+------+------+----------+----------+----------+
+    // |RSV | FRAG | ATYP | DST.ADDR | DST.PORT |   DATA   |
+    // +----+------+------+----------+----------+----------+
+    // | 2  |  1   |  1   | Variable |    2     | Variable |
+    // +----+------+------+----------+----------+----------+
+    if buf.len() < 4 || buf[..2] != [0, 0] {
+        return None;
+    }
+    let frag = buf[2];
+    let (dest, dest_len) = parse_dest(&buf[3..])?;
+    let header_len = 3 + dest_len;
+    if frag == 0 {
+        // A standalone datagram also abandons any pending fragments.
+        reassembly.reset();
+        buf.drain(..header_len);
+        return Some((dest, buf));
+    }
+    let buf = reassembly.push(frag, dest.clone(), &buf[header_len..])?;
+    Some((dest, buf))
+}
+
+/// Prepend the header for a datagram to or from `dest`. Returns `None` if it
+/// would not fit in a single datagram.
+fn encode_datagram(dest: &DestinationAddr, buf: &[u8]) -> Option<Buffer> {
+    let mut packet = Vec::with_capacity(3 + 1 + 1 + 255 + 2 + buf.len());
+    packet.extend_from_slice(&[0, 0, 0]);
+    write_dest(&mut packet, dest);
+    if packet.len() + buf.len() > MAX_DATAGRAM_SIZE {
+        return None;
+    }
+    packet.extend_from_slice(buf);
+    Some(packet)
+}
+
+impl DatagramSession for Socks5DatagramSession {
+    fn poll_recv_from(&mut self, cx: &mut Context) -> Poll<Option<(DestinationAddr, Buffer)>> {
+        loop {
+            let Some((_, buf)) = ready!(self.lower.poll_recv_from(cx)) else {
+                return Poll::Ready(None);
+            };
+            if let Some(res) = decode_datagram(&mut self.reassembly, buf) {
+                return Poll::Ready(Some(res));
+            }
+        }
+    }
+
+    fn poll_send_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        self.lower.as_mut().poll_send_ready(cx)
+    }
+
+    fn send_to(&mut self, remote_peer: DestinationAddr, buf: Buffer) {
+        if let Some(packet) = encode_datagram(&remote_peer, &buf) {
+            self.lower.send_to(self.client.clone(), packet)
+        }
+    }
+
+    fn poll_shutdown(&mut self, cx: &mut Context<'_>) -> Poll<FlowResult<()>> {
+        self.lower.as_mut().poll_shutdown(cx)
+    }
+}
+
+/// Relays datagrams through a SOCKS5 server with UDP ASSOCIATE.
+///
+/// Each session holds a control connection created by `tcp_next`, and sends
+/// datagrams to the relay address of the server through `udp_next`. A
+/// `udp_next` redirecting datagrams to the server works as long as the server
+/// relays on the same port it accepts connections on, which is common.
+pub struct Socks5DatagramOutbound {
+    auth_req: Option<Buffer>,
+    handshake_timeout: Duration,
+    tcp_next: Weak<dyn StreamOutboundFactory>,
+    udp_next: Weak<dyn DatagramSessionFactory>,
+}
+
+impl Socks5DatagramOutbound {
+    pub fn new(
+        cred: Option<(&[u8], &[u8])>,
+        tcp_next: Weak<dyn StreamOutboundFactory>,
+        udp_next: Weak<dyn DatagramSessionFactory>,
+        handshake_timeout: Duration,
+    ) -> Self {
+        Self {
+            auth_req: cred.map(super::get_cred_req),
+            handshake_timeout,
+            tcp_next,
+            udp_next,
+        }
+    }
+}
+
+#[async_trait]
+impl DatagramSessionFactory for Socks5DatagramOutbound {
+    async fn bind(&self, context: Box<FlowContext>) -> FlowResult<Box<dyn DatagramSession>> {
+        let tcp_next = self.tcp_next.upgrade().ok_or(FlowError::NoOutbound)?;
+        let udp_next = self.udp_next.upgrade().ok_or(FlowError::NoOutbound)?;
+        let mut tcp_context = FlowContext::new(context.local_peer, context.remote_peer.clone());
+        // The port we will send datagrams from is not known yet.
+        let unspecified = DestinationAddr {
+            host: HostName::Ip(Ipv4Addr::UNSPECIFIED.into()),
+            port: 0,
+        };
+        let (mut control, relay, initial_res) = super::perform_handshake(
+            &mut tcp_context,
+            &self.auth_req,
+            self.handshake_timeout,
+            tcp_next,
+            super::CMD_UDP_ASSOCIATE,
+            &unspecified,
+        )
+        .await?;
+        let relay = match relay.host {
+            // Many servers reply with an unspecified address, meaning the
+            // address of the control connection.
+            HostName::Ip(ip) if ip.is_unspecified() => DestinationAddr {
+                host: tcp_context.remote_peer.host,
+                port: relay.port,
+            },
+            _ => relay,
+        };
+        let lower = udp_next.bind(context).await?;
+        // The association terminates when the control connection closes.
+        let control = tokio::spawn(async move {
+            let mut reader = StreamReader::new(1, initial_res);
+            while reader.read_exact(&mut *control, 1, |_| ()).await.is_ok() {}
+        });
+        Ok(Box::new(Socks5ClientDatagramSession {
+            lower,
+            relay,
+            reassembly: Reassembly::default(),
+            control,
+        }))
+    }
+}
+
+struct Socks5ClientDatagramSession {
+    lower: Box<dyn DatagramSession>,
+    relay: DestinationAddr,
+    reassembly: Reassembly,
+    control: JoinHandle<()>,
+}
+
+impl DatagramSession for Socks5ClientDatagramSession {
+    fn poll_recv_from(&mut self, cx: &mut Context) -> Poll<Option<(DestinationAddr, Buffer)>> {
+        if self.control.is_finished() || Pin::new(&mut self.control).poll(cx).is_ready() {
+            return Poll::Ready(None);
+        }
+        loop {
+            let Some((_, buf)) = ready!(self.lower.poll_recv_from(cx)) else {
+                return Poll::Ready(None);
+            };
+            if let Some(res) = decode_datagram(&mut self.reassembly, buf) {
+                return Poll::Ready(Some(res));
+            }
+        }
+    }
+
+    fn poll_send_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        self.lower.as_mut().poll_send_ready(cx)
+    }
+
+    fn send_to(&mut self, remote_peer: DestinationAddr, buf: Buffer) {
+        if let Some(packet) = encode_datagram(&remote_peer, &buf) {
+            self.lower.send_to(self.relay.clone(), packet)
+        }
+    }
+
+    fn poll_shutdown(&mut self, cx: &mut Context<'_>) -> Poll<FlowResult<()>> {
+        self.control.abort();
+        self.lower.as_mut().poll_shutdown(cx)
+    }
+}
+
+impl Drop for Socks5ClientDatagramSession {
+    fn drop(&mut self) {
+        self.control.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dest() -> DestinationAddr {
+        DestinationAddr {
+            host: HostName::Ip([1, 1, 1, 1].into()),
+            port: 53,
+        }
+    }
+
+    #[test]
+    fn test_reassembly_in_order() {
+        let mut r = Reassembly::default();
+        assert_eq!(r.push(1, dest(), b"ab"), None);
+        assert_eq!(r.push(2, dest(), b"cd"), None);
+        assert_eq!(
+            r.push(3 | 0x80, dest(), b"ef").as_deref(),
+            Some(&b"abcdef"[..])
+        );
+        assert!(r.state.is_none());
+    }
+
+    #[test]
+    fn test_reassembly_out_of_order_abandons_queue() {
+        let mut r = Reassembly::default();
+        assert_eq!(r.push(1, dest(), b"ab"), None);
+        assert_eq!(r.push(3 | 0x80, dest(), b"ef"), None);
+        assert!(r.buf.is_empty());
+        assert_eq!(r.push(1, dest(), b"xy"), None);
+        assert_eq!(r.push(2 | 0x80, dest(), b"z").as_deref(), Some(&b"xyz"[..]));
+    }
+
+    #[test]
+    fn test_datagram_roundtrip() {
+        let packet = encode_datagram(&dest(), b"query").unwrap();
+        assert_eq!(&packet[..4], [0, 0, 0, 1]);
+        let mut r = Reassembly::default();
+        assert_eq!(
+            decode_datagram(&mut r, packet),
+            Some((dest(), b"query".to_vec()))
+        );
+        assert!(encode_datagram(&dest(), &vec![0; MAX_DATAGRAM_SIZE]).is_none());
+    }
+
+    #[test]
+    fn test_reassembly_rejects_oversized() {
+        let mut r = Reassembly::default();
+        let chunk = vec![0; MAX_DATAGRAM_SIZE / 2 + 1];
+        assert_eq!(r.push(1, dest(), &chunk), None);
+        assert_eq!(r.push(2 | 0x80, dest(), &chunk), None);
+        assert!(r.buf.is_empty());
+    }
+}
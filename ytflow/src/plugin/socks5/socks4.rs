@@ -0,0 +1,75 @@
+use std::net::IpAddr;
+
+use crate::flow::*;
+
+const REQUEST_GRANTED: u8 = 0x5a;
+
+/// Build a SOCKS4 CONNECT request. Domain names are only allowed in SOCKS4a,
+/// where they follow the user ID after an invalid IP `0.0.0.1`.
+pub(super) fn build_connect_request(
+    dest: &DestinationAddr,
+    user_id: &[u8],
+    allow_domain: bool,
+) -> FlowResult<Vec<u8>> {
+    let mut req = Vec::with_capacity(8 + user_id.len() + 1 + 256);
+    req.extend([0x04, 0x01]);
+    req.extend_from_slice(&dest.port.to_be_bytes());
+    match &dest.host {
+        HostName::Ip(IpAddr::V4(ip)) => {
+            req.extend_from_slice(&ip.octets());
+            req.extend_from_slice(user_id);
+            req.push(0);
+        }
+        HostName::DomainName(domain) if allow_domain => {
+            req.extend([0, 0, 0, 1]);
+            req.extend_from_slice(user_id);
+            req.push(0);
+            req.extend_from_slice(domain.trim_end_matches('.').as_bytes());
+            req.push(0);
+        }
+        // SOCKS4 cannot express IPv6 destinations, nor domain names without 4a
+        HostName::Ip(IpAddr::V6(_)) | HostName::DomainName(_) => return Err(FlowError::NoOutbound),
+    }
+    Ok(req)
+}
+
+/// Read the 8-byte reply to a CONNECT request.
+pub(super) async fn read_connect_reply(
+    stream: &mut dyn Stream,
+    initial_res: Buffer,
+) -> FlowResult<Buffer> {
+    let mut reader = StreamReader::new(16, initial_res);
+    let granted = reader
+        .read_exact(stream, 8, |buf| buf[0] == 0 && buf[1] == REQUEST_GRANTED)
+        .await?;
+    if !granted {
+        return Err(FlowError::UnexpectedData);
+    }
+    Ok(reader.into_buffer().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_connect_request() {
+        let dest = DestinationAddr {
+            host: HostName::Ip([1, 2, 3, 4].into()),
+            port: 80,
+        };
+        assert_eq!(
+            build_connect_request(&dest, b"u", false).unwrap(),
+            [4, 1, 0, 80, 1, 2, 3, 4, b'u', 0]
+        );
+        let dest = DestinationAddr {
+            host: HostName::DomainName("a.co.".into()),
+            port: 80,
+        };
+        assert_eq!(
+            build_connect_request(&dest, b"", true).unwrap(),
+            [4, 1, 0, 80, 0, 0, 0, 1, 0, b'a', b'.', b'c', b'o', 0]
+        );
+        assert!(build_connect_request(&dest, b"", false).is_err());
+    }
+}
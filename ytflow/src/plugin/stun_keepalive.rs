@@ -0,0 +1,8 @@
+pub(crate) mod codec;
+mod monitor;
+mod responder;
+mod session;
+
+pub use monitor::{AddressChange, ExternalAddressMonitor};
+pub use responder::Responder;
+pub use session::StunKeepaliveFactory;
@@ -0,0 +1,105 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+const BINDING_REQUEST: u16 = 0x0001;
+const BINDING_SUCCESS: u16 = 0x0101;
+const MAGIC_COOKIE: u32 = 0x2112_a442;
+const HEADER_LEN: usize = 20;
+const ATTR_MAPPED_ADDRESS: u16 = 0x0001;
+const ATTR_XOR_MAPPED_ADDRESS: u16 = 0x0020;
+
+pub(crate) type TransactionId = [u8; 12];
+
+/// Encode a STUN Binding Request without any attributes, see RFC 8489.
+pub(crate) fn encode_binding_request(txid: &TransactionId) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(HEADER_LEN);
+    buf.extend_from_slice(&BINDING_REQUEST.to_be_bytes());
+    buf.extend_from_slice(&0u16.to_be_bytes());
+    buf.extend_from_slice(&MAGIC_COOKIE.to_be_bytes());
+    buf.extend_from_slice(txid);
+    buf
+}
+
+/// Whether `buf` looks like a STUN message, so that it can be told apart from
+/// the payload of the transport sharing the same session.
+pub(crate) fn is_stun_message(buf: &[u8]) -> bool {
+    buf.len() >= HEADER_LEN && buf[0] & 0xc0 == 0 && buf[4..8] == MAGIC_COOKIE.to_be_bytes()
+}
+
+fn parse_address(value: &[u8], xor_key: Option<&[u8; 16]>) -> Option<SocketAddr> {
+    let family = *value.get(1)?;
+    let mut port = u16::from_be_bytes(value.get(2..4)?.try_into().unwrap());
+    let mut addr: [u8; 16] = [0; 16];
+    let addr_len = match family {
+        0x01 => 4,
+        0x02 => 16,
+        _ => return None,
+    };
+    addr[..addr_len].copy_from_slice(value.get(4..4 + addr_len)?);
+    if let Some(key) = xor_key {
+        port ^= u16::from_be_bytes([key[0], key[1]]);
+        for (b, k) in addr[..addr_len].iter_mut().zip(key) {
+            *b ^= k;
+        }
+    }
+    let ip = if addr_len == 4 {
+        IpAddr::V4(Ipv4Addr::new(addr[0], addr[1], addr[2], addr[3]))
+    } else {
+        IpAddr::V6(Ipv6Addr::from(addr))
+    };
+    Some(SocketAddr::new(ip, port))
+}
+
+/// Extract the mapped address from a Binding Success Response matching `txid`.
+pub(crate) fn parse_binding_response(buf: &[u8], txid: &TransactionId) -> Option<SocketAddr> {
+    if !is_stun_message(buf)
+        || u16::from_be_bytes([buf[0], buf[1]]) != BINDING_SUCCESS
+        || buf[8..20] != txid[..]
+    {
+        return None;
+    }
+    let len = u16::from_be_bytes([buf[2], buf[3]]) as usize;
+    let mut attrs = buf.get(HEADER_LEN..HEADER_LEN + len)?;
+    let mut xor_key = [0u8; 16];
+    xor_key[..4].copy_from_slice(&MAGIC_COOKIE.to_be_bytes());
+    xor_key[4..].copy_from_slice(txid);
+    let mut mapped = None;
+    while attrs.len() >= 4 {
+        let r#type = u16::from_be_bytes([attrs[0], attrs[1]]);
+        let attr_len = u16::from_be_bytes([attrs[2], attrs[3]]) as usize;
+        let value = attrs.get(4..4 + attr_len)?;
+        match r#type {
+            ATTR_XOR_MAPPED_ADDRESS => return parse_address(value, Some(&xor_key)),
+            ATTR_MAPPED_ADDRESS => mapped = parse_address(value, None),
+            _ => {}
+        }
+        // Attributes are padded to 4 bytes
+        let padded_len = (4 + attr_len + 3) & !3;
+        attrs = attrs.get(padded_len..).unwrap_or_default();
+    }
+    mapped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_xor_mapped_address() {
+        let txid = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
+        let mut res = vec![0x01, 0x01, 0x00, 0x0c];
+        res.extend_from_slice(&MAGIC_COOKIE.to_be_bytes());
+        res.extend_from_slice(&txid);
+        // XOR-MAPPED-ADDRESS of 192.0.2.1:32853
+        res.extend_from_slice(&[0x00, 0x20, 0x00, 0x08, 0x00, 0x01]);
+        res.extend_from_slice(&(32853u16 ^ 0x2112).to_be_bytes());
+        res.extend_from_slice(&[192 ^ 0x21, 0x12, 2 ^ 0xa4, 1 ^ 0x42]);
+
+        assert!(is_stun_message(&res));
+        assert_eq!(
+            parse_binding_response(&res, &txid),
+            Some("192.0.2.1:32853".parse().unwrap())
+        );
+        assert_eq!(parse_binding_response(&res, &[0; 12]), None);
+        assert!(is_stun_message(&encode_binding_request(&txid)));
+    }
+}
@@ -0,0 +1,48 @@
+use std::net::SocketAddr;
+use std::sync::Mutex;
+
+/// The external address of a session observed by the STUN server changed,
+/// usually because the NAT mapping expired or the network changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddressChange {
+    pub previous: SocketAddr,
+    pub current: SocketAddr,
+}
+
+type ChangeCallback = Box<dyn Fn(AddressChange) + Send + Sync>;
+
+/// Shared by all sessions of a `stun-keepalive` plugin. Transports that need to
+/// re-handshake after their external address changes register a callback here.
+#[derive(Default)]
+pub struct ExternalAddressMonitor {
+    last_address: Mutex<Option<SocketAddr>>,
+    change_count: Mutex<u32>,
+    callbacks: Mutex<Vec<ChangeCallback>>,
+}
+
+impl ExternalAddressMonitor {
+    /// The external address most recently reported by any session.
+    pub fn last_address(&self) -> Option<SocketAddr> {
+        *self.last_address.lock().unwrap()
+    }
+
+    pub fn change_count(&self) -> u32 {
+        *self.change_count.lock().unwrap()
+    }
+
+    pub fn on_change(&self, callback: impl Fn(AddressChange) + Send + Sync + 'static) {
+        self.callbacks.lock().unwrap().push(Box::new(callback));
+    }
+
+    pub(super) fn report(&self, previous: Option<SocketAddr>, current: SocketAddr) {
+        *self.last_address.lock().unwrap() = Some(current);
+        let Some(previous) = previous.filter(|p| *p != current) else {
+            return;
+        };
+        *self.change_count.lock().unwrap() += 1;
+        let change = AddressChange { previous, current };
+        for callback in &*self.callbacks.lock().unwrap() {
+            callback(change);
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use std::sync::{Arc, Mutex};
+
+use cbor4ii::serde::to_vec;
+use serde::Serialize;
+
+use super::ExternalAddressMonitor;
+use crate::control::{PluginRequestError, PluginRequestResult, PluginResponder};
+
+#[derive(Clone, Default, Serialize, PartialEq)]
+struct Info {
+    external_address: Option<String>,
+    change_count: u32,
+}
+
+pub struct Responder {
+    monitor: Arc<ExternalAddressMonitor>,
+    last_info: Mutex<(Info, u32)>,
+}
+
+impl Responder {
+    pub fn new(monitor: Arc<ExternalAddressMonitor>) -> Self {
+        Self {
+            monitor,
+            last_info: Mutex::new((Info::default(), 1)),
+        }
+    }
+}
+
+impl PluginResponder for Responder {
+    fn collect_info(&self, hashcode: &mut u32) -> Option<Vec<u8>> {
+        let info = {
+            let mut last_info_guard = self.last_info.lock().unwrap();
+            let (last_info, last_hashcode) = &mut *last_info_guard;
+            let new_info = Info {
+                external_address: self.monitor.last_address().map(|a| a.to_string()),
+                change_count: self.monitor.change_count(),
+            };
+            if new_info == *last_info {
+                if *last_hashcode == *hashcode {
+                    return None;
+                }
+            } else {
+                *last_info = new_info.clone();
+                *last_hashcode = (*last_hashcode).wrapping_add(1);
+            }
+            *hashcode = *last_hashcode;
+            new_info
+        };
+        Some(to_vec(vec![], &info).unwrap())
+    }
+
+    fn on_request(&self, _func: &str, _params: &[u8]) -> PluginRequestResult<Vec<u8>> {
+        Err(PluginRequestError::NoSuchFunc)
+    }
+}
@@ -0,0 +1,239 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::future::poll_fn;
+use tokio::task::JoinHandle;
+use tokio::time::{interval, MissedTickBehavior};
+
+use super::codec::{
+    encode_binding_request, is_stun_message, parse_binding_response, TransactionId,
+};
+use super::ExternalAddressMonitor;
+use crate::flow::*;
+
+pub struct StunKeepaliveFactory {
+    server: DestinationAddr,
+    interval: Duration,
+    monitor: Arc<ExternalAddressMonitor>,
+    next: Weak<dyn DatagramSessionFactory>,
+}
+
+impl StunKeepaliveFactory {
+    pub fn new(
+        server: DestinationAddr,
+        interval: Duration,
+        monitor: Arc<ExternalAddressMonitor>,
+        next: Weak<dyn DatagramSessionFactory>,
+    ) -> Self {
+        Self {
+            server,
+            interval,
+            monitor,
+            next,
+        }
+    }
+}
+
+#[async_trait]
+impl DatagramSessionFactory for StunKeepaliveFactory {
+    async fn bind(&self, context: Box<FlowContext>) -> FlowResult<Box<dyn DatagramSession>> {
+        let next = self.next.upgrade().ok_or(FlowError::NoOutbound)?;
+        let lower = next.bind(context).await?;
+        let shared = Arc::new(Mutex::new(Shared {
+            lower,
+            txid: None,
+            send_waker: None,
+        }));
+        let prober = tokio::spawn(probe(
+            Arc::downgrade(&shared),
+            self.server.clone(),
+            self.interval,
+        ));
+        Ok(Box::new(StunKeepaliveSession {
+            shared,
+            monitor: self.monitor.clone(),
+            prober,
+            mapped: None,
+        }))
+    }
+}
+
+struct Shared {
+    lower: Box<dyn DatagramSession>,
+    txid: Option<TransactionId>,
+    /// Waker of the transport waiting for the lower session to be ready to
+    /// send, which may have been replaced by the prober's when both wait.
+    send_waker: Option<Waker>,
+}
+
+/// Sends a Binding Request through the session every `interval`, starting
+/// right away to learn the initial mapping. Runs on its own so that probes go
+/// out even when the transport neither sends nor receives anything.
+async fn probe(shared: Weak<Mutex<Shared>>, server: DestinationAddr, period: Duration) {
+    let mut ticker = interval(period);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        let sent = poll_fn(|cx| {
+            let Some(shared) = shared.upgrade() else {
+                return Poll::Ready(false);
+            };
+            let mut shared = shared.lock().unwrap();
+            if shared.lower.poll_send_ready(cx).is_pending() {
+                return Poll::Pending;
+            }
+            let txid: TransactionId = rand::random();
+            shared
+                .lower
+                .send_to(server.clone(), encode_binding_request(&txid));
+            shared.txid = Some(txid);
+            if let Some(waker) = shared.send_waker.take() {
+                waker.wake();
+            }
+            Poll::Ready(true)
+        })
+        .await;
+        if !sent {
+            break;
+        }
+    }
+}
+
+/// Wraps a session with a prober task sending STUN Binding Requests, so that
+/// the NAT mapping of the session stays alive even when the transport is idle.
+/// Responses are consumed and never reach the transport.
+struct StunKeepaliveSession {
+    shared: Arc<Mutex<Shared>>,
+    monitor: Arc<ExternalAddressMonitor>,
+    prober: JoinHandle<()>,
+    mapped: Option<SocketAddr>,
+}
+
+impl DatagramSession for StunKeepaliveSession {
+    fn poll_recv_from(&mut self, cx: &mut Context) -> Poll<Option<(DestinationAddr, Buffer)>> {
+        let mut shared = self.shared.lock().unwrap();
+        loop {
+            let (from, buf) = match shared.lower.poll_recv_from(cx) {
+                Poll::Ready(Some(r)) => r,
+                r => return r,
+            };
+            let addr = shared
+                .txid
+                .filter(|_| is_stun_message(&buf))
+                .and_then(|txid| parse_binding_response(&buf, &txid));
+            let Some(addr) = addr else {
+                return Poll::Ready(Some((from, buf)));
+            };
+            shared.txid = None;
+            self.monitor.report(self.mapped, addr);
+            self.mapped = Some(addr);
+        }
+    }
+    fn poll_send_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut shared = self.shared.lock().unwrap();
+        let res = shared.lower.poll_send_ready(cx);
+        shared.send_waker = res.is_pending().then(|| cx.waker().clone());
+        res
+    }
+    fn send_to(&mut self, remote_peer: DestinationAddr, buf: Buffer) {
+        self.shared.lock().unwrap().lower.send_to(remote_peer, buf)
+    }
+    fn poll_shutdown(&mut self, cx: &mut Context<'_>) -> Poll<FlowResult<()>> {
+        self.prober.abort();
+        self.shared.lock().unwrap().lower.poll_shutdown(cx)
+    }
+}
+
+impl Drop for StunKeepaliveSession {
+    fn drop(&mut self) {
+        self.prober.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::AddressChange;
+    use super::*;
+    use crate::flow::testing::*;
+
+    fn binding_response(txid: &[u8], mapped: SocketAddr) -> Vec<u8> {
+        let SocketAddr::V4(mapped) = mapped else {
+            unreachable!()
+        };
+        // Binding Success Response with a plain MAPPED-ADDRESS
+        let mut res = vec![0x01, 0x01, 0x00, 0x0c, 0x21, 0x12, 0xa4, 0x42];
+        res.extend_from_slice(txid);
+        res.extend_from_slice(&[0x00, 0x01, 0x00, 0x08, 0x00, 0x01]);
+        res.extend_from_slice(&mapped.port().to_be_bytes());
+        res.extend_from_slice(&mapped.ip().octets());
+        res
+    }
+
+    #[tokio::test]
+    async fn test_probes_without_polling_session() {
+        let (next, peers) = MockDatagramSessionFactory::queued();
+        let monitor = Arc::new(ExternalAddressMonitor::default());
+        let next: Arc<dyn DatagramSessionFactory> = next;
+        let factory = StunKeepaliveFactory::new(
+            dest("stun.example.com:3478"),
+            Duration::from_millis(50),
+            monitor.clone(),
+            Arc::downgrade(&next),
+        );
+        let _session = factory.bind(context("127.0.0.1:1")).await.unwrap();
+        let (peer, _) = peers.recv_async().await.unwrap();
+
+        // Nobody polls the session, yet probes keep going out.
+        for _ in 0..2 {
+            let (to, req) = peer.recv_from().await.unwrap();
+            assert_eq!(to, dest("stun.example.com:3478"));
+            assert!(is_stun_message(&req));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_consumes_responses_and_reports_changes() {
+        let (next, peers) = MockDatagramSessionFactory::queued();
+        let monitor = Arc::new(ExternalAddressMonitor::default());
+        let changes = Arc::new(Mutex::new(vec![]));
+        monitor.on_change({
+            let changes = changes.clone();
+            move |c| changes.lock().unwrap().push(c)
+        });
+        let next: Arc<dyn DatagramSessionFactory> = next;
+        let factory = StunKeepaliveFactory::new(
+            dest("stun.example.com:3478"),
+            Duration::from_millis(50),
+            monitor.clone(),
+            Arc::downgrade(&next),
+        );
+        let mut session = factory.bind(context("127.0.0.1:1")).await.unwrap();
+        let (peer, _) = peers.recv_async().await.unwrap();
+
+        let first: SocketAddr = "192.0.2.1:1000".parse().unwrap();
+        let second: SocketAddr = "192.0.2.1:2000".parse().unwrap();
+        for mapped in [first, second] {
+            let (_, req) = peer.recv_from().await.unwrap();
+            peer.send_to(
+                dest("stun.example.com:3478"),
+                binding_response(&req[8..20], mapped),
+            );
+            peer.send_to(dest("1.1.1.1:53"), b"payload".to_vec());
+            let (from, buf) = poll_fn(|cx| session.poll_recv_from(cx)).await.unwrap();
+            assert_eq!(from, dest("1.1.1.1:53"));
+            assert_eq!(&buf[..], b"payload");
+            assert_eq!(monitor.last_address(), Some(mapped));
+        }
+        assert_eq!(monitor.change_count(), 1);
+        assert_eq!(
+            *changes.lock().unwrap(),
+            [AddressChange {
+                previous: first,
+                current: second
+            }]
+        );
+    }
+}
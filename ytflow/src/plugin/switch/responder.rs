@@ -1,6 +1,7 @@
 use serde::Serialize;
 
 use super::*;
+use crate::control::events::{Event, EventBus};
 use crate::control::{PluginRequestError, PluginRequestResult, PluginResponder};
 use crate::data::PluginCache;
 
@@ -17,9 +18,11 @@ pub struct Choice {
 }
 
 pub struct Responder {
+    pub plugin_name: String,
     pub choices: Vec<Choice>,
     pub switch: Arc<Switch>,
     pub cache: PluginCache,
+    pub events: EventBus,
 }
 
 #[derive(Serialize)]
@@ -38,6 +41,13 @@ impl Responder {
         };
         let old_choice = self.switch.current_choice.swap(Arc::new(new_choice));
         let _ = self.cache.set(PLUGIN_CACHE_KEY_LAST_SELECT, &idx).ok();
+        if old_choice.idx != idx {
+            self.events.publish(Event::SwitchChoiceChanged {
+                plugin: self.plugin_name.clone(),
+                idx,
+                name: self.choices[idx as usize].name.clone(),
+            });
+        }
         Some(old_choice.idx)
     }
 }
@@ -73,6 +83,17 @@ impl PluginResponder for Responder {
                 let ret = self.switch(choice_idx);
                 Ok(cbor4ii::serde::to_vec(Vec::with_capacity(4), &ret).unwrap())
             }
+            // Select by name, for callers that do not know the order of
+            // choices, such as scheduled transitions.
+            "sn" => {
+                let choice_name: String = cbor4ii::serde::from_slice(params)?;
+                let ret = self
+                    .choices
+                    .iter()
+                    .position(|c| c.name == choice_name)
+                    .and_then(|idx| self.switch(idx as u32));
+                Ok(cbor4ii::serde::to_vec(Vec::with_capacity(4), &ret).unwrap())
+            }
             _ => Err(PluginRequestError::NoSuchFunc),
         }
     }
@@ -1,5 +1,6 @@
 use std::os::raw::{c_int, c_void};
-use std::sync::OnceLock;
+use std::sync::Mutex;
+use std::time::Instant;
 
 use foreign_types_shared::ForeignType;
 use openssl::ssl::SslConnectorBuilder;
@@ -10,7 +11,9 @@ use windows::Security::Cryptography::Certificates;
 use windows::Storage::Streams::IBuffer;
 use windows::Win32::System::WinRT::IBufferByteAccess;
 
-static CERT_STORE: OnceLock<X509Store> = OnceLock::new();
+use super::trust::SYSTEM_TRUST_REFRESH_INTERVAL;
+
+static CERT_STORE: Mutex<Option<(Instant, X509Store)>> = Mutex::new(None);
 
 pub(crate) fn query_slice_from_ibuffer_mut(buf: &mut IBuffer) -> &mut [u8] {
     let len = buf.Length().unwrap() as _;
@@ -44,8 +47,14 @@ extern "C" {
 }
 
 pub(super) fn load(builder: &mut SslConnectorBuilder) {
-    let store_ref = CERT_STORE.get_or_init(load_store);
-    let store_ptr = store_ref.as_ptr();
+    let mut cache = CERT_STORE.lock().unwrap();
+    let stale = cache.as_ref().map_or(true, |(at, _)| {
+        at.elapsed() >= SYSTEM_TRUST_REFRESH_INTERVAL
+    });
+    if stale {
+        *cache = Some((Instant::now(), load_store()));
+    }
+    let store_ptr = cache.as_ref().unwrap().1.as_ptr();
     let store = unsafe {
         if unsafe { X509_STORE_up_ref(store_ptr as _) } != 1 {
             panic!("Failed to clone x509 store ref");
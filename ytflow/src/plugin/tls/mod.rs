@@ -2,5 +2,7 @@ mod initial_data_extract_stream;
 #[cfg(windows)]
 mod load_certs_windows;
 mod stream;
+mod trust;
 
 pub use stream::SslStreamFactory;
+pub use trust::RootTrust;
@@ -1,6 +1,7 @@
 use std::pin::Pin;
 use std::sync::{Arc, Mutex, Weak};
 use std::task::Poll;
+use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
 use futures::future::poll_fn;
@@ -8,12 +9,17 @@ use openssl::ssl;
 use tokio::io::AsyncWriteExt;
 
 use super::initial_data_extract_stream::InitialDataExtractStream;
+use super::trust::{RootTrust, SYSTEM_TRUST_REFRESH_INTERVAL};
 use crate::flow::*;
 
 pub struct SslStreamFactory {
-    ctx: ssl::SslConnector,
+    /// The connector and when it was built.
+    ctx: Mutex<(Instant, ssl::SslConnector)>,
+    alpn: Vec<u8>,
+    skip_cert_check: bool,
+    trust: RootTrust,
     sni: Option<String>,
-    alpn_set: bool,
+    handshake_timeout: Duration,
     next: Weak<dyn StreamOutboundFactory>,
 }
 
@@ -33,29 +39,68 @@ impl SslStreamFactory {
         alpn: Vec<&str>,
         skip_cert_check: bool,
         sni: Option<String>,
+        handshake_timeout: Duration,
+    ) -> Self {
+        Self::with_root_trust(
+            next,
+            alpn,
+            skip_cert_check,
+            sni,
+            handshake_timeout,
+            RootTrust::System,
+        )
+    }
+
+    pub fn with_root_trust(
+        next: Weak<dyn StreamOutboundFactory>,
+        alpn: Vec<&str>,
+        skip_cert_check: bool,
+        sni: Option<String>,
+        handshake_timeout: Duration,
+        trust: RootTrust,
     ) -> Self {
         let alpn = encode_alpn(&alpn);
-        let mut alpn_set = false;
-        let mut builder = ssl::SslConnector::builder(ssl::SslMethod::tls())
-            .expect("Failed to create SSL Context builder");
-        if !alpn.is_empty() {
-            builder.set_alpn_protos(&alpn).expect("Failed to set ALPN");
-            alpn_set = true;
-        }
-        if skip_cert_check {
-            builder.set_verify_callback(openssl::ssl::SslVerifyMode::NONE, |_, _| true);
-        }
-        #[cfg(windows)]
-        if !skip_cert_check {
-            super::load_certs_windows::load(&mut builder);
-        }
+        let ctx = build_connector(&alpn, skip_cert_check, &trust);
         Self {
-            ctx: builder.build(),
+            ctx: Mutex::new((Instant::now(), ctx)),
+            alpn,
+            skip_cert_check,
+            trust,
             sni,
-            alpn_set,
+            handshake_timeout,
             next,
         }
     }
+
+    /// Get the connector, rebuilding it first if the system store may have
+    /// changed since.
+    fn connector(&self) -> ssl::SslConnector {
+        let mut ctx = self.ctx.lock().unwrap();
+        if !self.skip_cert_check
+            && self.trust.is_system()
+            && ctx.0.elapsed() >= SYSTEM_TRUST_REFRESH_INTERVAL
+        {
+            *ctx = (
+                Instant::now(),
+                build_connector(&self.alpn, self.skip_cert_check, &self.trust),
+            );
+        }
+        ctx.1.clone()
+    }
+}
+
+fn build_connector(alpn: &[u8], skip_cert_check: bool, trust: &RootTrust) -> ssl::SslConnector {
+    let mut builder = ssl::SslConnector::builder(ssl::SslMethod::tls())
+        .expect("Failed to create SSL Context builder");
+    if !alpn.is_empty() {
+        builder.set_alpn_protos(alpn).expect("Failed to set ALPN");
+    }
+    if skip_cert_check {
+        builder.set_verify_callback(openssl::ssl::SslVerifyMode::NONE, |_, _| true);
+    } else {
+        trust.apply(&mut builder);
+    }
+    builder.build()
 }
 
 #[async_trait]
@@ -66,14 +111,18 @@ impl StreamOutboundFactory for SslStreamFactory {
         initial_data: &'_ [u8],
     ) -> FlowResult<(Box<dyn Stream>, Buffer)> {
         let Self {
-            ctx,
+            alpn,
             sni,
-            alpn_set,
+            handshake_timeout,
             next,
+            ..
         } = self;
         let outbound_factory = next.upgrade().ok_or(FlowError::NoOutbound)?;
 
-        let ssl_config = ctx.configure().expect("Cannot create SSL config");
+        let ssl_config = self
+            .connector()
+            .configure()
+            .expect("Cannot create SSL config");
         let mut ssl = if let Some(sni) = sni.as_ref() {
             ssl_config.into_ssl(sni)
         } else {
@@ -81,7 +130,7 @@ impl StreamOutboundFactory for SslStreamFactory {
             ssl_config.into_ssl(&host)
         }
         .expect("Cannot create SSL");
-        if !alpn_set {
+        if alpn.is_empty() {
             let alpn = encode_alpn(&context.application_layer_protocol);
             if !alpn.is_empty() {
                 ssl.set_alpn_protos(&alpn).expect("Failed to set ALPN");
@@ -129,13 +178,14 @@ impl StreamOutboundFactory for SslStreamFactory {
             };
         }
 
-        Pin::new(&mut ssl_stream)
-            .do_handshake()
-            .await
-            .map_err(|_| {
+        let abort = context.abort.signal();
+        with_flow_deadline(*handshake_timeout, &abort, async {
+            Pin::new(&mut ssl_stream).do_handshake().await.map_err(|_| {
                 // TODO: log error
                 FlowError::UnexpectedData
-            })?;
+            })
+        })
+        .await?;
 
         if let Some(alpn) = ssl_stream.ssl().selected_alpn_protocol() {
             context
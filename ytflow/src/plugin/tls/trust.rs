@@ -0,0 +1,118 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use openssl::ssl::{SslConnector, SslConnectorBuilder, SslMethod};
+use openssl::x509::store::X509StoreBuilder;
+use openssl::x509::X509;
+
+/// The system store is reloaded at most this often, so that certificates
+/// installed or removed from the OS take effect without a restart.
+pub(super) const SYSTEM_TRUST_REFRESH_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// Root certificates used to verify servers.
+#[derive(Clone, Default)]
+pub enum RootTrust {
+    /// The certificate store of the OS.
+    #[default]
+    System,
+    /// Mozilla's root certificates shipped with the binary, for targets
+    /// without a usable OS store.
+    Bundled,
+    /// Only the given CA certificates, such as the root of a corporate
+    /// TLS inspection proxy.
+    Custom(Arc<[X509]>),
+}
+
+impl RootTrust {
+    /// Parse a PEM encoded CA bundle. Returns `None` if no certificate can be
+    /// found.
+    pub fn from_pem(pem: &[u8]) -> Option<Self> {
+        let certs = X509::stack_from_pem(pem).ok()?;
+        if certs.is_empty() {
+            return None;
+        }
+        Some(Self::Custom(certs.into()))
+    }
+
+    pub(super) fn is_system(&self) -> bool {
+        matches!(self, RootTrust::System)
+    }
+
+    pub(super) fn apply(&self, builder: &mut SslConnectorBuilder) {
+        let certs = match self {
+            RootTrust::System => {
+                // Elsewhere OpenSSL already uses the default verify paths.
+                #[cfg(windows)]
+                super::load_certs_windows::load(builder);
+                return;
+            }
+            RootTrust::Bundled => webpki_root_certs::TLS_SERVER_ROOT_CERTS
+                .iter()
+                .filter_map(|der| X509::from_der(der).ok())
+                .collect(),
+            RootTrust::Custom(certs) => certs.to_vec(),
+        };
+        let mut store = X509StoreBuilder::new().expect("Failed to create X509 store builder");
+        for cert in certs {
+            // Duplicate certificates are rejected, which is harmless.
+            let _ = store.add_cert(cert);
+        }
+        builder.set_cert_store(store.build());
+    }
+
+    /// DER encoded root certificates, for TLS stacks other than OpenSSL such
+    /// as the one QUIC is built on. The system store is read the same way as
+    /// for `tls-client`, except that certificates OpenSSL would only look up
+    /// from a hashed directory on demand are not included.
+    pub fn to_der(&self) -> Vec<Vec<u8>> {
+        match self {
+            RootTrust::System => {
+                let mut builder = SslConnector::builder(SslMethod::tls_client())
+                    .expect("Failed to create SSL connector builder");
+                self.apply(&mut builder);
+                let context = builder.into_inner().build();
+                context
+                    .cert_store()
+                    .objects()
+                    .iter()
+                    .filter_map(|o| o.x509())
+                    .filter_map(|c| c.to_der().ok())
+                    .collect()
+            }
+            RootTrust::Bundled => webpki_root_certs::TLS_SERVER_ROOT_CERTS
+                .iter()
+                .map(|der| der.to_vec())
+                .collect(),
+            RootTrust::Custom(certs) => certs.iter().filter_map(|c| c.to_der().ok()).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_pem_rejects_empty() {
+        assert!(RootTrust::from_pem(b"").is_none());
+        assert!(RootTrust::from_pem(b"not a certificate").is_none());
+    }
+
+    #[test]
+    fn test_bundled_roots_parse() {
+        let parsed = webpki_root_certs::TLS_SERVER_ROOT_CERTS
+            .iter()
+            .filter(|der| X509::from_der(der).is_ok())
+            .count();
+        assert!(parsed > 100);
+    }
+
+    #[test]
+    fn test_to_der() {
+        assert!(RootTrust::Bundled.to_der().len() > 100);
+        assert!(RootTrust::Custom(Arc::from(Vec::new())).to_der().is_empty());
+        let der = &webpki_root_certs::TLS_SERVER_ROOT_CERTS[0];
+        let custom = RootTrust::Custom(Arc::from(vec![X509::from_der(der).unwrap()]));
+        assert_eq!(custom.to_der(), [der.to_vec()]);
+    }
+}
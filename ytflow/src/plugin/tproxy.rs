@@ -0,0 +1,12 @@
+//! Inbound for connections and packets diverted by iptables/nftables
+//! `REDIRECT` or `TPROXY` rules on Linux.
+//!
+//! Outbound traffic of ytflow itself must be excluded from these rules, e.g.
+//! by matching a firewall mark set through a socket hook, to avoid loops.
+
+mod sys;
+mod tcp;
+mod udp;
+
+pub use tcp::listen_tcp;
+pub use udp::listen_udp;
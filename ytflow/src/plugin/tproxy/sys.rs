@@ -0,0 +1,139 @@
+use std::io;
+use std::mem::{size_of, size_of_val, zeroed};
+use std::net::SocketAddr;
+use std::os::fd::{AsRawFd, RawFd};
+
+use socket2::{SockAddr, SockRef};
+
+fn setsockopt_int(
+    socket: &impl AsRawFd,
+    level: libc::c_int,
+    name: libc::c_int,
+    value: libc::c_int,
+) -> io::Result<()> {
+    let ret = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            level,
+            name,
+            &value as *const _ as _,
+            size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if ret == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Allow `socket` to accept traffic to, or to bind to, non-local addresses as
+/// required by `TPROXY`. Requires `CAP_NET_ADMIN`.
+pub(super) fn set_transparent(socket: &impl AsRawFd, is_ipv6: bool) -> io::Result<()> {
+    if is_ipv6 {
+        setsockopt_int(socket, libc::SOL_IPV6, libc::IPV6_TRANSPARENT, 1)
+    } else {
+        setsockopt_int(socket, libc::SOL_IP, libc::IP_TRANSPARENT, 1)
+    }
+}
+
+/// Receive the original destination of each datagram as ancillary data.
+pub(super) fn set_recv_orig_dst(socket: &impl AsRawFd, is_ipv6: bool) -> io::Result<()> {
+    if is_ipv6 {
+        setsockopt_int(socket, libc::SOL_IPV6, libc::IPV6_RECVORIGDSTADDR, 1)
+    } else {
+        setsockopt_int(socket, libc::SOL_IP, libc::IP_RECVORIGDSTADDR, 1)
+    }
+}
+
+/// The destination of a `REDIRECT`-ed connection before NAT. For connections
+/// not subject to NAT, such as those diverted by `TPROXY`, this is the local
+/// address.
+pub(super) fn original_dst(stream: &tokio::net::TcpStream) -> io::Result<SocketAddr> {
+    let local_addr = stream.local_addr()?;
+    let socket = SockRef::from(stream);
+    let original_dst = match local_addr {
+        SocketAddr::V4(_) => socket.original_dst(),
+        SocketAddr::V6(_) => socket.original_dst_ipv6(),
+    };
+    Ok(original_dst
+        .ok()
+        .and_then(|a| a.as_socket())
+        .unwrap_or(local_addr))
+}
+
+/// Receive a datagram along with its source address and original destination.
+pub(super) fn recv_with_orig_dst(
+    fd: RawFd,
+    buf: &mut [u8],
+) -> io::Result<(usize, SocketAddr, Option<SocketAddr>)> {
+    // Safety: all pointers in msghdr point to live buffers of the given sizes,
+    // and control messages are only read within the length set by the kernel.
+    unsafe {
+        let mut from: libc::sockaddr_storage = zeroed();
+        let mut iov = libc::iovec {
+            iov_base: buf.as_mut_ptr() as _,
+            iov_len: buf.len(),
+        };
+        // u64 for alignment of cmsghdr
+        let mut control = [0u64; 16];
+        let mut msg: libc::msghdr = zeroed();
+        msg.msg_name = &mut from as *mut _ as _;
+        msg.msg_namelen = size_of_val(&from) as _;
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as _;
+        msg.msg_controllen = size_of_val(&control) as _;
+        let len = libc::recvmsg(fd, &mut msg, 0);
+        if len == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let from = SockAddr::new(from, msg.msg_namelen)
+            .as_socket()
+            .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidData))?;
+
+        let mut orig_dst = None;
+        let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+        while !cmsg.is_null() {
+            let hdr = &*cmsg;
+            if let (libc::SOL_IP, libc::IP_RECVORIGDSTADDR)
+            | (libc::SOL_IPV6, libc::IPV6_RECVORIGDSTADDR) = (hdr.cmsg_level, hdr.cmsg_type)
+            {
+                let mut addr: libc::sockaddr_storage = zeroed();
+                let addr_len =
+                    (hdr.cmsg_len as usize - libc::CMSG_LEN(0) as usize).min(size_of_val(&addr));
+                std::ptr::copy_nonoverlapping(
+                    libc::CMSG_DATA(cmsg),
+                    &mut addr as *mut _ as *mut u8,
+                    addr_len,
+                );
+                orig_dst = SockAddr::new(addr, addr_len as _).as_socket();
+            }
+            cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+        }
+        Ok((len as usize, from, orig_dst))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::UdpSocket;
+
+    use super::*;
+
+    #[test]
+    fn test_recv_with_orig_dst() {
+        let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+        set_recv_orig_dst(&listener, false).unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sender
+            .send_to(b"hello", listener.local_addr().unwrap())
+            .unwrap();
+
+        let mut buf = [0; 16];
+        let (len, from, orig_dst) = recv_with_orig_dst(listener.as_raw_fd(), &mut buf).unwrap();
+        assert_eq!(&buf[..len], b"hello");
+        assert_eq!(from, sender.local_addr().unwrap());
+        // Without TPROXY the original destination is the local address
+        assert_eq!(orig_dst, Some(listener.local_addr().unwrap()));
+    }
+}
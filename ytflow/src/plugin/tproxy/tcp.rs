@@ -0,0 +1,53 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Weak};
+
+use crate::flow::*;
+
+/// Accept TCP connections diverted by `REDIRECT` or `TPROXY`, taking their
+/// original destinations as the remote peers.
+pub fn listen_tcp(
+    plugin_name: Arc<str>,
+    next: Weak<dyn StreamHandler>,
+    addr: SocketAddr,
+) -> io::Result<tokio::task::JoinHandle<()>> {
+    let socket = socket2::Socket::new(
+        socket2::Domain::for_address(addr),
+        socket2::Type::STREAM,
+        None,
+    )?;
+    socket.set_reuse_address(true)?;
+    // Only TPROXY needs this. REDIRECT works without CAP_NET_ADMIN.
+    let _ = super::sys::set_transparent(&socket, addr.is_ipv6());
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    socket.set_nonblocking(true)?;
+    let listener = tokio::net::TcpListener::from_std(socket.into())?;
+    Ok(tokio::spawn(async move {
+        loop {
+            let (stream, connector) = match listener.accept().await {
+                Ok(r) => r,
+                // TODO: log error
+                Err(_) => break,
+            };
+            let Some(next) = next.upgrade() else {
+                break;
+            };
+            let remote_peer = match super::sys::original_dst(&stream) {
+                Ok(addr) => addr,
+                // TODO: log error
+                Err(_) => continue,
+            };
+            let _ = stream.set_nodelay(true);
+            let context = FlowContext::new(connector, remote_peer.into());
+            let session_id = crate::log::next_session_id();
+            crate::log::isolate_sync(&plugin_name, session_id, || {
+                next.on_stream(
+                    Box::new(CompatFlow::new(stream, 4096)),
+                    Buffer::new(),
+                    Box::new(context),
+                )
+            });
+        }
+    }))
+}
@@ -0,0 +1,138 @@
+use std::collections::{btree_map::Entry, BTreeMap};
+use std::io;
+use std::net::SocketAddr;
+use std::os::fd::AsRawFd;
+use std::sync::{Arc, Weak};
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+
+use flume::{bounded, SendError};
+use tokio::io::Interest;
+
+use crate::flow::*;
+
+/// Replies to a client are sent from sockets bound to the remote addresses.
+/// The sockets are dropped all at once when a session has talked to too many
+/// remote addresses.
+const MAX_REPLY_SOCKETS: usize = 64;
+
+/// Receive UDP packets diverted by `TPROXY`, grouping them into sessions by
+/// the client address. Packets in a session may go to different original
+/// destinations. Requires `CAP_NET_ADMIN`.
+pub fn listen_udp(
+    plugin_name: Arc<str>,
+    next: Weak<dyn DatagramSessionHandler>,
+    addr: SocketAddr,
+) -> io::Result<tokio::task::JoinHandle<()>> {
+    let socket = socket2::Socket::new(
+        socket2::Domain::for_address(addr),
+        socket2::Type::DGRAM,
+        None,
+    )?;
+    super::sys::set_transparent(&socket, addr.is_ipv6())?;
+    super::sys::set_recv_orig_dst(&socket, addr.is_ipv6())?;
+    socket.bind(&addr.into())?;
+    socket.set_nonblocking(true)?;
+    let listener = tokio::net::UdpSocket::from_std(socket.into())?;
+    Ok(tokio::spawn(async move {
+        let mut session_map = BTreeMap::new();
+        let mut buf = vec![0u8; u16::MAX as usize];
+        loop {
+            let (size, from, orig_dst) = match listener
+                .async_io(Interest::READABLE, || {
+                    super::sys::recv_with_orig_dst(listener.as_raw_fd(), &mut buf)
+                })
+                .await
+            {
+                Ok(r) => r,
+                Err(_) => {
+                    // TODO: log error
+                    break;
+                }
+            };
+            let Some(orig_dst) = orig_dst else {
+                continue;
+            };
+            let tx = session_map.entry(from).or_insert_with(|| {
+                let (tx, rx) = bounded(64);
+                if let Some(next) = next.upgrade() {
+                    let session_id = crate::log::next_session_id();
+                    crate::log::isolate_sync(&plugin_name, session_id, || {
+                        next.on_session(
+                            Box::new(MultiplexedDatagramSessionAdapter::new(
+                                TproxyUdpSession {
+                                    client: from,
+                                    reply_sockets: BTreeMap::new(),
+                                    tx_buf: None,
+                                },
+                                rx.into_stream(),
+                                Duration::from_secs(120),
+                            )),
+                            Box::new(FlowContext::new_af_sensitive(from, orig_dst.into())),
+                        )
+                    });
+                }
+                tx
+            });
+            if let Err(SendError(_)) = tx.send_async((orig_dst.into(), buf[..size].to_vec())).await
+            {
+                session_map.remove(&from);
+            }
+        }
+    }))
+}
+
+fn bind_reply_socket(src: SocketAddr) -> io::Result<tokio::net::UdpSocket> {
+    let socket = socket2::Socket::new(
+        socket2::Domain::for_address(src),
+        socket2::Type::DGRAM,
+        None,
+    )?;
+    socket.set_reuse_address(true)?;
+    super::sys::set_transparent(&socket, src.is_ipv6())?;
+    socket.bind(&src.into())?;
+    socket.set_nonblocking(true)?;
+    tokio::net::UdpSocket::from_std(socket.into())
+}
+
+struct TproxyUdpSession {
+    client: SocketAddr,
+    reply_sockets: BTreeMap<SocketAddr, tokio::net::UdpSocket>,
+    tx_buf: Option<(SocketAddr, Buffer)>,
+}
+
+impl MultiplexedDatagramSession for TproxyUdpSession {
+    fn on_close(&mut self) {
+        self.reply_sockets.clear();
+    }
+
+    fn poll_send_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        let Some((src, buf)) = &self.tx_buf else {
+            return Poll::Ready(());
+        };
+        if self.reply_sockets.len() >= MAX_REPLY_SOCKETS && !self.reply_sockets.contains_key(src) {
+            self.reply_sockets.clear();
+        }
+        let socket = match self.reply_sockets.entry(*src) {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => match bind_reply_socket(*src) {
+                Ok(socket) => e.insert(socket),
+                Err(_) => {
+                    // TODO: log error
+                    self.tx_buf = None;
+                    return Poll::Ready(());
+                }
+            },
+        };
+        let _ = ready!(socket.poll_send_to(cx, buf, self.client));
+        self.tx_buf = None;
+        Poll::Ready(())
+    }
+
+    fn send_to(&mut self, src: DestinationAddr, buf: Buffer) {
+        let HostName::Ip(ip) = &src.host else {
+            return;
+        };
+        self.tx_buf = Some((SocketAddr::new(*ip, src.port), buf));
+    }
+}
@@ -0,0 +1,318 @@
+use std::future::Future;
+use std::num::NonZeroUsize;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use lru::LruCache;
+use tokio::time::{sleep, Instant, Sleep};
+
+use crate::flow::*;
+
+/// How long a verdict is trusted before UDP is probed again.
+const VERDICT_TTL: Duration = Duration::from_secs(600);
+const VERDICT_CAPACITY: NonZeroUsize = NonZeroUsize::new(256).unwrap();
+/// Datagrams sent while probing are kept to be resent over the fallback
+/// transport. Anything beyond this is dropped as if lost on the wire.
+const MAX_REPLAYED_DATAGRAMS: usize = 8;
+
+#[derive(Debug, Clone, Copy)]
+struct Verdict {
+    blocked: bool,
+    expires_at: Instant,
+}
+
+type Verdicts = Arc<Mutex<LruCache<DestinationAddr, Verdict>>>;
+
+/// Binds sessions with `udp`, the plugin's own UDP transport, and switches a session over to `tcp`, a datagram
+/// transport carried by a stream, when UDP egress looks blocked: the UDP session
+/// closes or nothing comes back within the probe window after the first send.
+/// The verdict is remembered per destination so that later sessions skip probing.
+pub struct UdpFallbackFactory {
+    udp: Arc<dyn DatagramSessionFactory>,
+    tcp: Weak<dyn DatagramSessionFactory>,
+    probe_window: Duration,
+    verdicts: Verdicts,
+}
+
+impl UdpFallbackFactory {
+    pub fn new(
+        udp: Arc<dyn DatagramSessionFactory>,
+        tcp: Weak<dyn DatagramSessionFactory>,
+        probe_window: Duration,
+    ) -> Self {
+        Self {
+            udp,
+            tcp,
+            probe_window,
+            verdicts: Arc::new(Mutex::new(LruCache::new(VERDICT_CAPACITY))),
+        }
+    }
+
+    fn verdict(&self, dest: &DestinationAddr) -> Option<bool> {
+        let mut verdicts = self.verdicts.lock().unwrap();
+        match verdicts.get(dest) {
+            Some(v) if v.expires_at > Instant::now() => Some(v.blocked),
+            Some(_) => {
+                verdicts.pop(dest);
+                None
+            }
+            None => None,
+        }
+    }
+}
+
+fn record_verdict(verdicts: &Verdicts, dest: DestinationAddr, blocked: bool) {
+    verdicts.lock().unwrap().put(
+        dest,
+        Verdict {
+            blocked,
+            expires_at: Instant::now() + VERDICT_TTL,
+        },
+    );
+}
+
+async fn bind_with(
+    next: &Weak<dyn DatagramSessionFactory>,
+    context: Box<FlowContext>,
+) -> FlowResult<Box<dyn DatagramSession>> {
+    let next = next.upgrade().ok_or(FlowError::NoOutbound)?;
+    next.bind(context).await
+}
+
+#[async_trait]
+impl DatagramSessionFactory for UdpFallbackFactory {
+    async fn bind(&self, context: Box<FlowContext>) -> FlowResult<Box<dyn DatagramSession>> {
+        let dest = context.remote_peer.clone();
+        match self.verdict(&dest) {
+            Some(true) => return bind_with(&self.tcp, context).await,
+            Some(false) => return self.udp.bind(context).await,
+            None => {}
+        }
+        let fallback_context = Box::new(context.fork());
+        let udp = match self.udp.bind(context).await {
+            Ok(udp) => udp,
+            Err(_) => {
+                record_verdict(&self.verdicts, dest, true);
+                return bind_with(&self.tcp, fallback_context).await;
+            }
+        };
+        Ok(Box::new(ProbingSession {
+            dest: Some(dest),
+            verdicts: self.verdicts.clone(),
+            tcp: self.tcp.clone(),
+            probe_window: self.probe_window,
+            state: SessionState::Probing(udp),
+            fallback_context: Some(fallback_context),
+            replay: Vec::new(),
+            deadline: None,
+            recv_waker: None,
+        }))
+    }
+}
+
+enum SessionState {
+    Probing(Box<dyn DatagramSession>),
+    Binding(BoxFuture<'static, FlowResult<Box<dyn DatagramSession>>>),
+    Decided(Box<dyn DatagramSession>),
+    Failed,
+}
+
+struct ProbingSession {
+    // Taken once the verdict is recorded.
+    dest: Option<DestinationAddr>,
+    verdicts: Verdicts,
+    tcp: Weak<dyn DatagramSessionFactory>,
+    probe_window: Duration,
+    state: SessionState,
+    fallback_context: Option<Box<FlowContext>>,
+    replay: Vec<(DestinationAddr, Buffer)>,
+    deadline: Option<Pin<Box<Sleep>>>,
+    recv_waker: Option<Waker>,
+}
+
+impl ProbingSession {
+    fn decide(&mut self, blocked: bool) {
+        if let Some(dest) = self.dest.take() {
+            record_verdict(&self.verdicts, dest, blocked);
+        }
+        self.deadline = None;
+        if !blocked {
+            self.replay.clear();
+            self.fallback_context = None;
+        }
+    }
+
+    fn fall_back(&mut self) {
+        self.decide(true);
+        let tcp = self.tcp.clone();
+        let context = self
+            .fallback_context
+            .take()
+            .expect("fallback context is only taken once");
+        self.state = SessionState::Binding(async move { bind_with(&tcp, context).await }.boxed());
+    }
+
+    /// Drive the fallback binding and resend datagrams queued while probing.
+    /// Returns `Ready` when the session can carry new datagrams.
+    fn poll_fallback_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if let SessionState::Binding(fut) = &mut self.state {
+            self.state = match fut.poll_unpin(cx) {
+                Poll::Ready(Ok(session)) => SessionState::Decided(session),
+                Poll::Ready(Err(_)) => {
+                    self.replay.clear();
+                    SessionState::Failed
+                }
+                Poll::Pending => return Poll::Pending,
+            };
+        }
+        if let SessionState::Decided(session) = &mut self.state {
+            while !self.replay.is_empty() {
+                if session.poll_send_ready(cx).is_pending() {
+                    return Poll::Pending;
+                }
+                let (dest, buf) = self.replay.remove(0);
+                session.send_to(dest, buf);
+            }
+        }
+        Poll::Ready(())
+    }
+}
+
+impl DatagramSession for ProbingSession {
+    fn poll_recv_from(&mut self, cx: &mut Context) -> Poll<Option<(DestinationAddr, Buffer)>> {
+        loop {
+            match &mut self.state {
+                SessionState::Probing(udp) => match udp.poll_recv_from(cx) {
+                    Poll::Ready(Some(r)) => {
+                        self.decide(false);
+                        let SessionState::Probing(udp) =
+                            std::mem::replace(&mut self.state, SessionState::Failed)
+                        else {
+                            unreachable!()
+                        };
+                        self.state = SessionState::Decided(udp);
+                        return Poll::Ready(Some(r));
+                    }
+                    Poll::Ready(None) => self.fall_back(),
+                    Poll::Pending => {
+                        self.recv_waker = Some(cx.waker().clone());
+                        match &mut self.deadline {
+                            Some(deadline) if deadline.as_mut().poll(cx).is_ready() => {
+                                self.fall_back()
+                            }
+                            _ => return Poll::Pending,
+                        }
+                    }
+                },
+                SessionState::Binding(_) => {
+                    // Sending side may be idle, so drive the binding from here as well.
+                    let _ = self.poll_fallback_ready(cx);
+                    if matches!(self.state, SessionState::Binding(_)) {
+                        return Poll::Pending;
+                    }
+                }
+                SessionState::Decided(session) => return session.poll_recv_from(cx),
+                SessionState::Failed => return Poll::Ready(None),
+            }
+        }
+    }
+
+    fn poll_send_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        match &mut self.state {
+            SessionState::Probing(udp) => udp.poll_send_ready(cx),
+            SessionState::Binding(_) => self.poll_fallback_ready(cx),
+            SessionState::Decided(_) => {
+                futures::ready!(self.poll_fallback_ready(cx));
+                match &mut self.state {
+                    SessionState::Decided(session) => session.poll_send_ready(cx),
+                    _ => Poll::Ready(()),
+                }
+            }
+            SessionState::Failed => Poll::Ready(()),
+        }
+    }
+
+    fn send_to(&mut self, remote_peer: DestinationAddr, buf: Buffer) {
+        match &mut self.state {
+            SessionState::Probing(udp) => {
+                if self.replay.len() < MAX_REPLAYED_DATAGRAMS {
+                    self.replay.push((remote_peer.clone(), buf.clone()));
+                }
+                if self.deadline.is_none() {
+                    self.deadline = Some(Box::pin(sleep(self.probe_window)));
+                    // The receiving side has to poll the new deadline.
+                    if let Some(waker) = self.recv_waker.take() {
+                        waker.wake();
+                    }
+                }
+                udp.send_to(remote_peer, buf);
+            }
+            SessionState::Binding(_) => {
+                if self.replay.len() < MAX_REPLAYED_DATAGRAMS {
+                    self.replay.push((remote_peer, buf));
+                }
+            }
+            SessionState::Decided(session) => session.send_to(remote_peer, buf),
+            SessionState::Failed => {}
+        }
+    }
+
+    fn poll_shutdown(&mut self, cx: &mut Context<'_>) -> Poll<FlowResult<()>> {
+        match &mut self.state {
+            SessionState::Probing(session) | SessionState::Decided(session) => {
+                session.poll_shutdown(cx)
+            }
+            SessionState::Binding(_) | SessionState::Failed => Poll::Ready(Ok(())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flow::testing::{context, dest, MockDatagramSessionFactory};
+
+    #[tokio::test]
+    async fn test_fall_back_and_remember() {
+        let tcp = MockDatagramSessionFactory::echo();
+        let weak_tcp: Weak<dyn DatagramSessionFactory> = Arc::downgrade(&tcp) as _;
+        let factory = UdpFallbackFactory::new(
+            MockDatagramSessionFactory::blackhole(),
+            weak_tcp,
+            Duration::from_millis(10),
+        );
+        let mut session = factory.bind(context("1.1.1.1:53")).await.unwrap();
+        session.send_to(dest("1.1.1.1:53"), vec![1, 2, 3]);
+        let (_, buf) = futures::future::poll_fn(|cx| session.poll_recv_from(cx))
+            .await
+            .unwrap();
+        // Replayed over the fallback transport after the probe window.
+        assert_eq!(buf, vec![1, 2, 3]);
+        assert_eq!(factory.verdict(&dest("1.1.1.1:53")), Some(true));
+        assert_eq!(tcp.binds(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_udp_reachable() {
+        let tcp = MockDatagramSessionFactory::blackhole();
+        let weak_tcp: Weak<dyn DatagramSessionFactory> = Arc::downgrade(&tcp) as _;
+        let factory = UdpFallbackFactory::new(
+            MockDatagramSessionFactory::echo(),
+            weak_tcp,
+            Duration::from_millis(10),
+        );
+        let mut session = factory.bind(context("1.1.1.1:53")).await.unwrap();
+        session.send_to(dest("1.1.1.1:53"), vec![4]);
+        let (_, buf) = futures::future::poll_fn(|cx| session.poll_recv_from(cx))
+            .await
+            .unwrap();
+        assert_eq!(buf, vec![4]);
+        assert_eq!(factory.verdict(&dest("1.1.1.1:53")), Some(false));
+        assert_eq!(tcp.binds(), 0);
+    }
+}
@@ -0,0 +1,7 @@
+mod outbound;
+mod responder;
+mod tester;
+
+pub use outbound::{DatagramUrlTestFactory, StreamUrlTestFactory};
+pub use responder::Responder;
+pub use tester::{Candidate, ProbeResult, SelectMode, Sticky, StickyKey, UrlTest};
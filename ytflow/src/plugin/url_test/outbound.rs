@@ -0,0 +1,44 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use super::UrlTest;
+use crate::flow::*;
+
+pub struct StreamUrlTestFactory {
+    pub url_test: Arc<UrlTest>,
+}
+
+pub struct DatagramUrlTestFactory {
+    pub url_test: Arc<UrlTest>,
+}
+
+#[async_trait]
+impl StreamOutboundFactory for StreamUrlTestFactory {
+    async fn create_outbound(
+        &self,
+        context: &mut FlowContext,
+        initial_data: &'_ [u8],
+    ) -> FlowResult<(Box<dyn Stream>, Buffer)> {
+        let next = self
+            .url_test
+            .select_for(context)
+            .tcp_next
+            .upgrade()
+            .ok_or(FlowError::NoOutbound)?;
+        next.create_outbound(context, initial_data).await
+    }
+}
+
+#[async_trait]
+impl DatagramSessionFactory for DatagramUrlTestFactory {
+    async fn bind(&self, context: Box<FlowContext>) -> FlowResult<Box<dyn DatagramSession>> {
+        let next = self
+            .url_test
+            .select_for(&context)
+            .udp_next
+            .upgrade()
+            .ok_or(FlowError::NoOutbound)?;
+        next.bind(context).await
+    }
+}
@@ -0,0 +1,83 @@
+use std::sync::{Arc, Mutex};
+
+use cbor4ii::serde::to_vec;
+use serde::Serialize;
+
+use super::UrlTest;
+use crate::control::{PluginRequestError, PluginRequestResult, PluginResponder};
+
+#[derive(Clone, Default, Serialize, PartialEq, Eq)]
+struct CandidateInfo {
+    name: String,
+    /// Latency of the last probe in milliseconds, or `None` if it failed or
+    /// has not completed yet.
+    latency: Option<u32>,
+    error: Option<String>,
+}
+
+#[derive(Clone, Default, Serialize, PartialEq, Eq)]
+struct Info {
+    selected: u32,
+    candidates: Vec<CandidateInfo>,
+}
+
+pub struct Responder {
+    url_test: Arc<UrlTest>,
+    last_info: Mutex<(Info, u32)>,
+}
+
+impl Responder {
+    pub fn new(url_test: Arc<UrlTest>) -> Self {
+        Self {
+            url_test,
+            last_info: Mutex::new((Info::default(), 1)),
+        }
+    }
+}
+
+fn info_snapshot(url_test: &UrlTest) -> Info {
+    Info {
+        selected: url_test.selected_index() as u32,
+        candidates: url_test
+            .candidates
+            .iter()
+            .zip(url_test.results())
+            .map(|(c, r)| CandidateInfo {
+                name: c.name.clone(),
+                latency: r.latency.map(|l| l.as_millis() as u32),
+                error: r.error,
+            })
+            .collect(),
+    }
+}
+
+impl PluginResponder for Responder {
+    fn collect_info(&self, hashcode: &mut u32) -> Option<Vec<u8>> {
+        let info = {
+            let mut last_info_guard = self.last_info.lock().unwrap();
+            let (last_info, last_hashcode) = &mut *last_info_guard;
+            let new_info = info_snapshot(&self.url_test);
+            if new_info == *last_info {
+                if *last_hashcode == *hashcode {
+                    return None;
+                }
+            } else {
+                *last_info = new_info.clone();
+                *last_hashcode = (*last_hashcode).wrapping_add(1);
+            }
+            *hashcode = *last_hashcode;
+            new_info
+        };
+        Some(to_vec(vec![], &info).unwrap())
+    }
+
+    fn on_request(&self, func: &str, _params: &[u8]) -> PluginRequestResult<Vec<u8>> {
+        match func {
+            "probe" => {
+                self.url_test.probe_now();
+                Ok(to_vec(vec![], &()).unwrap())
+            }
+            _ => Err(PluginRequestError::NoSuchFunc),
+        }
+    }
+}
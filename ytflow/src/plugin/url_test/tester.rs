@@ -0,0 +1,514 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+use http::header::USER_AGENT;
+use http::uri::Scheme;
+use http::{Request, Uri};
+use hyper::{Body, Client as HyperClient};
+use tokio::sync::Notify;
+
+use crate::control::events::{Event, EventBus};
+use crate::control::KillSwitch;
+use crate::flow::*;
+use crate::plugin::h2::{FlowAdapterConnector, TokioHyperExecutor};
+use crate::plugin::tls::SslStreamFactory;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectMode {
+    /// The healthy candidate with the lowest latency. The current candidate is
+    /// kept unless another one is faster by more than the tolerance.
+    Fastest,
+    /// The first healthy candidate in the order of the group.
+    Fallback,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StickyKey {
+    /// Flows to the same destination host share a candidate.
+    Destination,
+    /// Flows from the same client address share a candidate.
+    Source,
+}
+
+/// Keeps flows with the same key on the candidate the previous one went
+/// through, so that applications opening several connections to a service
+/// are not scattered over exit nodes when the selected candidate changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sticky {
+    pub key: StickyKey,
+    /// A pin is dropped when no flow has used it for this long.
+    pub ttl: Duration,
+}
+
+pub struct Candidate {
+    pub name: String,
+    pub tcp_next: Weak<dyn StreamOutboundFactory>,
+    pub udp_next: Weak<dyn DatagramSessionFactory>,
+    /// Names of probers in front of this candidate, looked up in the kill
+    /// switch. The candidate is skipped while any of them is down.
+    pub probers: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProbeResult {
+    pub latency: Option<Duration>,
+    pub error: Option<String>,
+}
+
+/// Sends test requests through a candidate. Connections are not reused, so
+/// that each probe measures the handshakes of the whole outbound chain.
+struct ProbeClient {
+    plain_client: HyperClient<FlowAdapterConnector, Body>,
+    tls_client: HyperClient<FlowAdapterConnector, Body>,
+    _tls: Arc<dyn StreamOutboundFactory>,
+}
+
+impl ProbeClient {
+    fn new(next: Weak<dyn StreamOutboundFactory>) -> Self {
+        let tls: Arc<dyn StreamOutboundFactory> = Arc::new(SslStreamFactory::new(
+            next.clone(),
+            vec![],
+            false,
+            None,
+            DEFAULT_HANDSHAKE_TIMEOUT,
+        ));
+        let build_client = |next: Weak<dyn StreamOutboundFactory>| {
+            HyperClient::builder()
+                .executor(TokioHyperExecutor::new_current())
+                .pool_max_idle_per_host(0)
+                .build(FlowAdapterConnector { next })
+        };
+        Self {
+            plain_client: build_client(next),
+            tls_client: build_client(Arc::downgrade(&tls)),
+            _tls: tls,
+        }
+    }
+}
+
+/// Probes a group of outbounds with a test URL and routes traffic through
+/// the one picked by [`SelectMode`].
+pub struct UrlTest {
+    pub(super) candidates: Vec<Candidate>,
+    clients: Vec<ProbeClient>,
+    url: Uri,
+    interval: Duration,
+    timeout: Duration,
+    tolerance: Duration,
+    mode: SelectMode,
+    sticky: Option<Sticky>,
+    pins: Mutex<HashMap<HostName, (usize, Instant)>>,
+    selected: AtomicUsize,
+    results: Mutex<Vec<ProbeResult>>,
+    probe_now: Notify,
+    kill_switch: KillSwitch,
+}
+
+/// Index of the candidate to switch to after a probing round. The current
+/// candidate is kept when none is healthy.
+fn choose(
+    mode: SelectMode,
+    current: usize,
+    latencies: &[Option<Duration>],
+    tolerance: Duration,
+) -> usize {
+    match mode {
+        SelectMode::Fallback => latencies
+            .iter()
+            .position(Option::is_some)
+            .unwrap_or(current),
+        SelectMode::Fastest => {
+            let Some((best, best_latency)) = latencies
+                .iter()
+                .enumerate()
+                .filter_map(|(idx, l)| Some((idx, (*l)?)))
+                .min_by_key(|(_, l)| *l)
+            else {
+                return current;
+            };
+            match latencies.get(current).copied().flatten() {
+                Some(l) if l <= best_latency + tolerance => current,
+                _ => best,
+            }
+        }
+    }
+}
+
+impl UrlTest {
+    pub fn new(
+        candidates: Vec<Candidate>,
+        url: Uri,
+        interval: Duration,
+        timeout: Duration,
+        tolerance: Duration,
+        mode: SelectMode,
+        sticky: Option<Sticky>,
+        kill_switch: KillSwitch,
+    ) -> Self {
+        let clients = candidates
+            .iter()
+            .map(|c| ProbeClient::new(c.tcp_next.clone()))
+            .collect();
+        let results = Mutex::new(vec![ProbeResult::default(); candidates.len()]);
+        Self {
+            candidates,
+            clients,
+            url,
+            interval,
+            timeout,
+            tolerance,
+            mode,
+            sticky,
+            pins: Mutex::new(HashMap::new()),
+            selected: AtomicUsize::new(0),
+            results,
+            probe_now: Notify::new(),
+            kill_switch,
+        }
+    }
+
+    /// The candidate traffic is currently routed through. Until the first
+    /// probing round completes, it is the first one in the group.
+    pub fn selected(&self) -> &Candidate {
+        &self.candidates[self.selected.load(Ordering::Relaxed)]
+    }
+
+    pub fn selected_index(&self) -> usize {
+        self.selected.load(Ordering::Relaxed)
+    }
+
+    /// The candidate to route a new flow through. With sticky routing, the
+    /// flow keeps the candidate pinned to its key unless the pin has expired
+    /// or that candidate failed the last probe.
+    pub fn select_for(&self, context: &FlowContext) -> &Candidate {
+        let Some(sticky) = self.sticky else {
+            return self.selected();
+        };
+        let key = match sticky.key {
+            StickyKey::Destination => context.remote_peer.host.clone(),
+            StickyKey::Source => HostName::Ip(context.local_peer.ip()),
+        };
+        let now = Instant::now();
+        let mut pins = self.pins.lock().unwrap();
+        let idx = match pins.get(&key) {
+            Some(&(idx, last_used))
+                if now.duration_since(last_used) < sticky.ttl
+                    && self.results.lock().unwrap()[idx].error.is_none()
+                    && self.probers_healthy(&self.candidates[idx]) =>
+            {
+                idx
+            }
+            _ => self.selected_index(),
+        };
+        pins.insert(key, (idx, now));
+        &self.candidates[idx]
+    }
+
+    pub fn results(&self) -> Vec<ProbeResult> {
+        self.results.lock().unwrap().clone()
+    }
+
+    fn probers_healthy(&self, candidate: &Candidate) -> bool {
+        candidate
+            .probers
+            .iter()
+            .all(|p| self.kill_switch.is_healthy(p))
+    }
+
+    /// Start a probing round without waiting for the interval to elapse.
+    pub fn probe_now(&self) {
+        self.probe_now.notify_one();
+    }
+
+    async fn probe(&self, idx: usize) -> Result<Duration, String> {
+        let client = match self.url.scheme() {
+            Some(s) if *s == Scheme::HTTPS => &self.clients[idx].tls_client,
+            _ => &self.clients[idx].plain_client,
+        };
+        let req = Request::get(self.url.clone())
+            .header(USER_AGENT, concat!("ytflow/", env!("CARGO_PKG_VERSION")))
+            .body(Body::empty())
+            .map_err(|e| e.to_string())?;
+        let started = Instant::now();
+        // Any response proves the outbound works, whatever the status is.
+        tokio::time::timeout(self.timeout, client.request(req))
+            .await
+            .map_err(|_| String::from("timed out"))?
+            .map_err(|e| e.to_string())?;
+        Ok(started.elapsed())
+    }
+
+    /// Probe all candidates and pick one. Returns whether any candidate is
+    /// healthy.
+    async fn probe_all(&self) -> bool {
+        let results = futures::future::join_all((0..self.candidates.len()).map(|idx| async move {
+            match self.probe(idx).await {
+                Ok(latency) => ProbeResult {
+                    latency: Some(latency),
+                    error: None,
+                },
+                Err(e) => ProbeResult {
+                    latency: None,
+                    error: Some(e),
+                },
+            }
+        }))
+        .await;
+        *self.results.lock().unwrap() = results;
+        if let Some(sticky) = self.sticky {
+            let now = Instant::now();
+            self.pins
+                .lock()
+                .unwrap()
+                .retain(|_, (_, last_used)| now.duration_since(*last_used) < sticky.ttl);
+        }
+        self.reselect()
+    }
+
+    /// Pick a candidate from the last probe results, skipping those whose
+    /// probers are down. Returns whether any candidate is healthy.
+    fn reselect(&self) -> bool {
+        let latencies: Vec<_> = self
+            .results
+            .lock()
+            .unwrap()
+            .iter()
+            .zip(&self.candidates)
+            .map(|(r, c)| r.latency.filter(|_| self.probers_healthy(c)))
+            .collect();
+        let selected = choose(
+            self.mode,
+            self.selected.load(Ordering::Relaxed),
+            &latencies,
+            self.tolerance,
+        );
+        self.selected.store(selected, Ordering::Relaxed);
+        latencies.iter().any(Option::is_some)
+    }
+
+    /// Keep probing, reporting to the kill switch and publishing
+    /// [`Event::OutboundHealthChanged`] for `plugin_name` when all candidates
+    /// go down or one of them comes back. A candidate is reselected as soon as
+    /// one of its probers changes health.
+    pub async fn run(self: Arc<Self>, plugin_name: String, events: EventBus) {
+        let (_, mut subscription) = events.subscribe(u64::MAX);
+        let mut was_healthy = true;
+        let mut report = |healthy| {
+            self.kill_switch.report_health(&plugin_name, healthy);
+            if healthy != was_healthy {
+                events.publish(Event::OutboundHealthChanged {
+                    plugin: plugin_name.clone(),
+                    healthy,
+                });
+                was_healthy = healthy;
+            }
+        };
+        loop {
+            report(self.probe_all().await);
+            let next_round = tokio::time::sleep(self.interval);
+            tokio::pin!(next_round);
+            loop {
+                tokio::select! {
+                    _ = &mut next_round => break,
+                    _ = self.probe_now.notified() => break,
+                    Some(record) = subscription.recv() => {
+                        let Event::OutboundHealthChanged { plugin, .. } = record.event else {
+                            continue;
+                        };
+                        if self.candidates.iter().any(|c| c.probers.contains(&plugin)) {
+                            report(self.reselect());
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+    use crate::flow::testing::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    /// A candidate that is never probed, for tests of the selection only.
+    fn idle_candidate(name: &str, probers: &[&str]) -> Candidate {
+        Candidate {
+            name: name.into(),
+            tcp_next: Weak::<crate::plugin::null::Null>::new(),
+            udp_next: Weak::<crate::plugin::null::Null>::new(),
+            probers: probers.iter().map(|p| p.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_choose_fastest() {
+        let tolerance = 50 * MS;
+        let latencies = [Some(300 * MS), Some(100 * MS), Some(120 * MS), None];
+        assert_eq!(choose(SelectMode::Fastest, 0, &latencies, tolerance), 1);
+        // Within tolerance of the fastest one.
+        assert_eq!(choose(SelectMode::Fastest, 2, &latencies, tolerance), 2);
+        assert_eq!(choose(SelectMode::Fastest, 3, &latencies, tolerance), 1);
+        assert_eq!(choose(SelectMode::Fastest, 2, &[None, None], tolerance), 2);
+    }
+
+    #[test]
+    fn test_choose_fallback() {
+        let latencies = [None, Some(300 * MS), Some(100 * MS)];
+        assert_eq!(choose(SelectMode::Fallback, 2, &latencies, MS), 1);
+        assert_eq!(choose(SelectMode::Fallback, 1, &[None, None], MS), 1);
+    }
+
+    #[tokio::test]
+    async fn test_probe_selects_working_candidate() {
+        let mut aps = AccessPoints::new();
+        let refusing = MockStreamOutboundFactory::refusing();
+        let working = MockStreamOutboundFactory::new();
+        let udp = MockDatagramSessionFactory::blackhole();
+        let udp = aps.hold(udp);
+        let candidates = vec![
+            Candidate {
+                name: "refusing".into(),
+                tcp_next: aps.hold(refusing) as _,
+                udp_next: udp.clone() as _,
+                probers: vec![],
+            },
+            Candidate {
+                name: "working".into(),
+                tcp_next: aps.hold(working.clone()) as _,
+                udp_next: udp as _,
+                probers: vec![],
+            },
+        ];
+        let url_test = UrlTest::new(
+            candidates,
+            Uri::from_static("http://example.com/generate_204"),
+            Duration::from_secs(60),
+            Duration::from_secs(5),
+            50 * MS,
+            SelectMode::Fastest,
+            None,
+            KillSwitch::default(),
+        );
+        assert_eq!(url_test.selected().name, "refusing");
+
+        let server = tokio::spawn(async move {
+            let mut conn = working.connected().await;
+            let mut buf = [0; 1024];
+            let _ = conn.peer.read(&mut buf).await.unwrap();
+            conn.peer
+                .write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+                .await
+                .unwrap();
+            conn
+        });
+        assert!(url_test.probe_all().await);
+        let conn = server.await.unwrap();
+        assert_eq!(conn.remote_peer.port, 80);
+
+        assert_eq!(url_test.selected().name, "working");
+        let results = url_test.results();
+        assert!(results[0].latency.is_none() && results[0].error.is_some());
+        assert!(results[1].latency.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_sticky_destination() {
+        let url_test = UrlTest::new(
+            vec![idle_candidate("a", &[]), idle_candidate("b", &[])],
+            Uri::from_static("http://example.com/generate_204"),
+            Duration::from_secs(60),
+            Duration::from_secs(5),
+            50 * MS,
+            SelectMode::Fastest,
+            Some(Sticky {
+                key: StickyKey::Destination,
+                ttl: 50 * MS,
+            }),
+            KillSwitch::default(),
+        );
+        let pinned = context("example.com:443");
+        assert_eq!(url_test.select_for(&pinned).name, "a");
+
+        url_test.selected.store(1, Ordering::Relaxed);
+        // Another port of the same host keeps the pinned candidate.
+        assert_eq!(url_test.select_for(&context("example.com:80")).name, "a");
+        assert_eq!(url_test.select_for(&context("example.org:443")).name, "b");
+
+        // A pin to a failing candidate is moved to the selected one.
+        url_test.results.lock().unwrap()[0].error = Some("timed out".into());
+        assert_eq!(url_test.select_for(&pinned).name, "b");
+        url_test.results.lock().unwrap()[0].error = None;
+        url_test.selected.store(0, Ordering::Relaxed);
+        assert_eq!(url_test.select_for(&pinned).name, "b");
+
+        // Pins expire when unused.
+        tokio::time::sleep(100 * MS).await;
+        assert_eq!(url_test.select_for(&pinned).name, "a");
+    }
+
+    #[tokio::test]
+    async fn test_failing_prober_demotes_candidate() {
+        let kill_switch = KillSwitch::default();
+        let url_test = UrlTest::new(
+            vec![idle_candidate("a", &["a-prober"]), idle_candidate("b", &[])],
+            Uri::from_static("http://example.com/generate_204"),
+            Duration::from_secs(60),
+            Duration::from_secs(5),
+            50 * MS,
+            SelectMode::Fallback,
+            None,
+            kill_switch.clone(),
+        );
+        for result in url_test.results.lock().unwrap().iter_mut() {
+            result.latency = Some(100 * MS);
+        }
+        assert!(url_test.reselect());
+        assert_eq!(url_test.selected().name, "a");
+
+        kill_switch.report_health("a-prober", false);
+        assert!(url_test.reselect());
+        assert_eq!(url_test.selected().name, "b");
+
+        kill_switch.report_health("a-prober", true);
+        assert!(url_test.reselect());
+        assert_eq!(url_test.selected().name, "a");
+    }
+
+    #[tokio::test]
+    async fn test_run_reports_to_kill_switch() {
+        let mut aps = AccessPoints::new();
+        let udp = aps.hold(MockDatagramSessionFactory::blackhole());
+        let kill_switch = KillSwitch::default();
+        let url_test = Arc::new(UrlTest::new(
+            vec![Candidate {
+                name: "refusing".into(),
+                tcp_next: aps.hold(MockStreamOutboundFactory::refusing()) as _,
+                udp_next: udp as _,
+                probers: vec![],
+            }],
+            Uri::from_static("http://example.com/generate_204"),
+            Duration::from_secs(60),
+            Duration::from_secs(5),
+            50 * MS,
+            SelectMode::Fastest,
+            None,
+            kill_switch.clone(),
+        ));
+        let task = tokio::spawn(url_test.run("ut".into(), EventBus::new()));
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while kill_switch.is_healthy("ut") {
+                tokio::time::sleep(10 * MS).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(kill_switch.status().unhealthy, ["ut"]);
+        task.abort();
+    }
+}
@@ -1,6 +1,7 @@
 use std::num::NonZeroUsize;
 use std::sync::Weak;
 use std::task::{ready, Context, Poll};
+use std::time::Duration;
 
 use async_trait::async_trait;
 use futures::{SinkExt, StreamExt};
@@ -21,6 +22,7 @@ use ws::{
 use super::h2::FlowAdapterConnector;
 use crate::flow::*;
 use crate::plugin::h2::TokioHyperExecutor;
+use crate::plugin::rotation::Rotation;
 
 struct WebSocketStream<S> {
     rx_buffer: Option<Buffer>,
@@ -37,25 +39,29 @@ enum H2ProbeState {
 }
 
 pub struct WebSocketStreamOutboundFactory {
-    pub host: Option<String>,
-    pub path: String,
+    /// When not set, the Host header is derived from the destination.
+    pub hosts: Option<Rotation<String>>,
+    pub paths: Rotation<String>,
     pub headers: HeaderMap<HeaderValue>,
     pub next: Weak<dyn StreamOutboundFactory>,
+    pub handshake_timeout: Duration,
     h2_probe_state: Mutex<H2ProbeState>,
 }
 
 impl WebSocketStreamOutboundFactory {
     pub fn new(
-        host: Option<String>,
-        path: String,
+        hosts: Option<Rotation<String>>,
+        paths: Rotation<String>,
         headers: HeaderMap<HeaderValue>,
         next: Weak<dyn StreamOutboundFactory>,
+        handshake_timeout: Duration,
     ) -> Self {
         Self {
-            host,
-            path,
+            hosts,
+            paths,
             headers,
             next,
+            handshake_timeout,
             h2_probe_state: Default::default(),
         }
     }
@@ -66,15 +72,17 @@ impl WebSocketStreamOutboundFactory {
         body: B,
         is_h2: bool,
     ) -> FlowResult<Request<B>> {
-        let host = match (&self.host, peer.port) {
-            (Some(_), _) => None,
-            (None, 443 | 80) => Some(peer.host.to_string()),
-            (None, _) => Some(peer.to_string()),
-        };
-        let host = if let Some(host) = self.host.as_ref() {
-            host.as_str()
-        } else {
-            host.as_deref().unwrap()
+        let derived_host;
+        let host = match (&self.hosts, peer.port) {
+            (Some(hosts), _) => hosts.pick().as_str(),
+            (None, 443 | 80) => {
+                derived_host = peer.host.to_string();
+                &derived_host
+            }
+            (None, _) => {
+                derived_host = peer.to_string();
+                &derived_host
+            }
         };
         let uri = Uri::builder()
             .scheme(if is_h2 {
@@ -84,7 +92,7 @@ impl WebSocketStreamOutboundFactory {
                 "ws"
             })
             .authority(host)
-            .path_and_query(&self.path)
+            .path_and_query(self.paths.pick().as_str())
             .build()
             .map_err(|_| FlowError::UnexpectedData)?;
 
@@ -140,15 +148,19 @@ impl WebSocketStreamOutboundFactory {
         context.application_layer_protocol.clear();
         let reader = StreamReader::new(4096, initial_res);
 
-        let (mut ws, res) = tokio_ws::client_async(
-            http_req,
-            CompatStream {
-                reader,
-                inner: lower,
-            },
-        )
-        .await
-        .map_err(ws_handshake_err_to_flow_err)?;
+        let abort = context.abort.signal();
+        let (mut ws, res) = with_flow_deadline(self.handshake_timeout, &abort, async {
+            tokio_ws::client_async(
+                http_req,
+                CompatStream {
+                    reader,
+                    inner: lower,
+                },
+            )
+            .await
+            .map_err(ws_handshake_err_to_flow_err)
+        })
+        .await?;
         if !initial_data.is_empty() {
             ws.send(WsMessage::Binary(initial_data))
                 .await
@@ -168,6 +180,7 @@ impl StreamOutboundFactory for WebSocketStreamOutboundFactory {
         context: &mut FlowContext,
         initial_data: &[u8],
     ) -> FlowResult<(Box<dyn Stream>, Buffer)> {
+        let abort = context.abort.signal();
         let res = loop {
             match {
                 let guard = self.h2_probe_state.lock().await;
@@ -181,10 +194,13 @@ impl StreamOutboundFactory for WebSocketStreamOutboundFactory {
                 H2ProbeState::Supported(client) => {
                     let h2_req =
                         self.create_upgrade_req(&context.remote_peer, Body::empty(), true)?;
-                    break client
-                        .request(h2_req)
-                        .await
-                        .map_err(|_| FlowError::UnexpectedData)?;
+                    break with_flow_deadline(self.handshake_timeout, &abort, async {
+                        client
+                            .request(h2_req)
+                            .await
+                            .map_err(|_| FlowError::UnexpectedData)
+                    })
+                    .await?;
                 }
                 H2ProbeState::Unknown => {}
             }
@@ -196,7 +212,20 @@ impl StreamOutboundFactory for WebSocketStreamOutboundFactory {
                 let client = hyper::Client::builder()
                     .executor(TokioHyperExecutor::new_current())
                     .build(FlowAdapterConnector { next });
-                let res = client.request(h2_req).await;
+                let res = match with_flow_deadline(self.handshake_timeout, &abort, async {
+                    Ok(client.request(h2_req).await)
+                })
+                .await
+                {
+                    Ok(res) => res,
+                    // A server that never answers the HTTP/2 request is most likely waiting for
+                    // an HTTP/1.1 request line instead.
+                    Err(FlowError::Io(e)) if e.kind() == std::io::ErrorKind::TimedOut => {
+                        *guard = H2ProbeState::NotSupported;
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                };
                 match res {
                     Ok(res) if res.version() == Version::HTTP_2 && res.status().is_success() => {
                         *guard = H2ProbeState::Supported(client);
@@ -218,9 +247,12 @@ impl StreamOutboundFactory for WebSocketStreamOutboundFactory {
         if !res.status().is_success() {
             return Err(FlowError::UnexpectedData);
         }
-        let upgraded = hyper::upgrade::on(res)
-            .await
-            .map_err(|_| FlowError::UnexpectedData)?;
+        let upgraded = with_flow_deadline(self.handshake_timeout, &abort, async {
+            hyper::upgrade::on(res)
+                .await
+                .map_err(|_| FlowError::UnexpectedData)
+        })
+        .await?;
         let mut ws = TokioWebSocketStream::from_raw_socket(upgraded, Role::Client, None).await;
         if !initial_data.is_empty() {
             ws.send(WsMessage::Binary(initial_data.to_vec()))
@@ -231,6 +263,114 @@ impl StreamOutboundFactory for WebSocketStreamOutboundFactory {
     }
 }
 
+#[async_trait]
+impl DatagramSessionFactory for WebSocketStreamOutboundFactory {
+    async fn bind(&self, mut context: Box<FlowContext>) -> FlowResult<Box<dyn DatagramSession>> {
+        // Every WebSocket binary message carries exactly one datagram, so that the
+        // message boundaries are preserved as v2ray does.
+        let (lower, _) = self.create_outbound(&mut context, &[]).await?;
+        Ok(Box::new(WebSocketDatagramSession {
+            remote_peer: context.remote_peer,
+            lower,
+            rx_state: DatagramRxState::AwaitingSize,
+            tx_buffer: None,
+            broken: false,
+        }))
+    }
+}
+
+enum DatagramRxState {
+    AwaitingSize,
+    AwaitingBuffer,
+}
+
+struct WebSocketDatagramSession {
+    remote_peer: DestinationAddr,
+    lower: Box<dyn Stream>,
+    rx_state: DatagramRxState,
+    tx_buffer: Option<Buffer>,
+    broken: bool,
+}
+
+impl DatagramSession for WebSocketDatagramSession {
+    fn poll_recv_from(&mut self, cx: &mut Context) -> Poll<Option<(DestinationAddr, Buffer)>> {
+        if self.broken {
+            return Poll::Ready(None);
+        }
+        loop {
+            match self.rx_state {
+                DatagramRxState::AwaitingSize => {
+                    let size = match self.lower.poll_request_size(cx) {
+                        Poll::Pending => {
+                            // Push out datagrams queued by `send_to`
+                            let _ = self.lower.poll_flush_tx(cx);
+                            return Poll::Pending;
+                        }
+                        Poll::Ready(Ok(size)) => size,
+                        Poll::Ready(Err(_)) => {
+                            self.broken = true;
+                            return Poll::Ready(None);
+                        }
+                    };
+                    let buf = Buffer::with_capacity(size.with_min_content(1500));
+                    if self.lower.commit_rx_buffer(buf).is_err() {
+                        self.broken = true;
+                        return Poll::Ready(None);
+                    }
+                    self.rx_state = DatagramRxState::AwaitingBuffer;
+                }
+                DatagramRxState::AwaitingBuffer => {
+                    let res = ready!(self.lower.poll_rx_buffer(cx));
+                    self.rx_state = DatagramRxState::AwaitingSize;
+                    return Poll::Ready(match res {
+                        Ok(buf) => Some((self.remote_peer.clone(), buf)),
+                        Err(_) => {
+                            self.broken = true;
+                            None
+                        }
+                    });
+                }
+            }
+        }
+    }
+
+    fn poll_send_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.broken || self.tx_buffer.is_some() {
+            return Poll::Ready(());
+        }
+        match ready!(self
+            .lower
+            .poll_tx_buffer(cx, NonZeroUsize::new(1500).unwrap()))
+        {
+            Ok(buf) => self.tx_buffer = Some(buf),
+            Err(_) => self.broken = true,
+        }
+        Poll::Ready(())
+    }
+
+    fn send_to(&mut self, remote_peer: DestinationAddr, buf: Buffer) {
+        // The WebSocket only carries datagrams of the peer it was opened for.
+        // Those to other peers are dropped silently, as a connected UDP socket
+        // would, keeping the tx buffer for the next datagram.
+        if self.broken || remote_peer != self.remote_peer {
+            return;
+        }
+        // Without a tx buffer from `poll_send_ready`, the WebSocket may not be
+        // ready for another message. Drop the datagram as a full socket would.
+        let Some(mut tx_buf) = self.tx_buffer.take() else {
+            return;
+        };
+        tx_buf.extend_from_slice(&buf);
+        if self.lower.commit_tx_buffer(tx_buf).is_err() {
+            self.broken = true;
+        }
+    }
+
+    fn poll_shutdown(&mut self, cx: &mut Context<'_>) -> Poll<FlowResult<()>> {
+        self.lower.poll_close_tx(cx)
+    }
+}
+
 fn ws_stream_err_to_flow_err(e: WsError) -> FlowError {
     match e {
         WsError::Io(e) => FlowError::Io(e),
@@ -317,3 +457,103 @@ impl<S: AsyncRead + AsyncWrite + Unpin + Send + 'static> Stream for WebSocketStr
             .map_err(ws_stream_err_to_flow_err)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use futures::future::poll_fn;
+
+    use super::*;
+    use crate::flow::testing::*;
+
+    #[tokio::test]
+    async fn test_datagram_send_follows_send_ready() {
+        let (client, server) = tokio::io::duplex(4096);
+        let (client, mut server) = futures::join!(
+            TokioWebSocketStream::from_raw_socket(client, Role::Client, None),
+            TokioWebSocketStream::from_raw_socket(server, Role::Server, None),
+        );
+        let mut session = WebSocketDatagramSession {
+            remote_peer: dest("127.0.0.1:53"),
+            lower: Box::new(WebSocketStream::new(client)),
+            rx_state: DatagramRxState::AwaitingSize,
+            tx_buffer: None,
+            broken: false,
+        };
+
+        // Not ready yet, hence dropped.
+        session.send_to(dest("127.0.0.1:53"), b"dropped".to_vec());
+        for datagram in [&b"a"[..], b"b"] {
+            poll_fn(|cx| session.poll_send_ready(cx)).await;
+            session.send_to(dest("127.0.0.1:53"), datagram.to_vec());
+        }
+        // Flushes the previous datagram.
+        poll_fn(|cx| session.poll_send_ready(cx)).await;
+        assert!(!session.broken);
+
+        for datagram in [&b"a"[..], b"b"] {
+            let msg = server.next().await.unwrap().unwrap();
+            assert_eq!(msg, WsMessage::Binary(datagram.to_vec()));
+        }
+        server.send(WsMessage::Binary(b"c".to_vec())).await.unwrap();
+        let received = poll_fn(|cx| session.poll_recv_from(cx)).await;
+        assert_eq!(received, Some((dest("127.0.0.1:53"), b"c".to_vec())));
+    }
+
+    #[tokio::test]
+    async fn test_datagram_to_other_peer_dropped() {
+        let (client, server) = tokio::io::duplex(4096);
+        let (client, mut server) = futures::join!(
+            TokioWebSocketStream::from_raw_socket(client, Role::Client, None),
+            TokioWebSocketStream::from_raw_socket(server, Role::Server, None),
+        );
+        let mut session = WebSocketDatagramSession {
+            remote_peer: dest("127.0.0.1:53"),
+            lower: Box::new(WebSocketStream::new(client)),
+            rx_state: DatagramRxState::AwaitingSize,
+            tx_buffer: None,
+            broken: false,
+        };
+
+        poll_fn(|cx| session.poll_send_ready(cx)).await;
+        session.send_to(dest("127.0.0.2:53"), b"dropped".to_vec());
+        // The tx buffer is still there for the expected peer.
+        session.send_to(dest("127.0.0.1:53"), b"a".to_vec());
+        poll_fn(|cx| session.poll_send_ready(cx)).await;
+        assert!(!session.broken);
+
+        let msg = server.next().await.unwrap().unwrap();
+        assert_eq!(msg, WsMessage::Binary(b"a".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn test_h2_probe_timeout_falls_back_to_http1() {
+        let next = MockStreamOutboundFactory::new();
+        let weak_next = Arc::downgrade(&next) as Weak<dyn StreamOutboundFactory>;
+        let factory = Arc::new(WebSocketStreamOutboundFactory::new(
+            None,
+            Rotation::new(vec!["/".into()], Default::default()),
+            HeaderMap::new(),
+            weak_next,
+            Duration::from_millis(200),
+        ));
+        let client = tokio::spawn({
+            let factory = factory.clone();
+            async move {
+                let mut context = context("example.com:80");
+                factory.create_outbound(&mut context, &[]).await.map(|_| ())
+            }
+        });
+
+        // The HTTP/2 probe is never answered.
+        let _silent = next.connected().await;
+        let h1 = next.connected().await;
+        let _server = tokio_ws::accept_async(h1.peer).await.unwrap();
+        client.await.unwrap().unwrap();
+        assert!(matches!(
+            *factory.h2_probe_state.lock().await,
+            H2ProbeState::NotSupported
+        ));
+    }
+}
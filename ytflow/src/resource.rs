@@ -1,3 +1,7 @@
+#[cfg(feature = "plugins")]
+pub mod fetch;
+pub mod maxmind;
+
 use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
 use std::future::Future;
@@ -10,6 +14,16 @@ use crate::data::{self, Connection};
 pub const RESOURCE_TYPE_GEOIP_COUNTRY: &str = "geoip-country";
 pub const RESOURCE_TYPE_SURGE_DOMAINSET: &str = "surge-domain-set";
 pub const RESOURCE_TYPE_QUANX_FILTER: &str = "quanx-filter";
+pub const RESOURCE_TYPE_CIDR_LIST: &str = "cidr-list";
+pub const RESOURCE_TYPE_CIDR_TRIE: &str = "cidr-trie";
+/// v2ray `geosite.dat`, domain lists of all categories in protobuf.
+pub const RESOURCE_TYPE_GEOSITE: &str = "geosite";
+/// A single domain list in the text format of v2fly domain-list-community.
+pub const RESOURCE_TYPE_DOMAIN_LIST: &str = "domain-list";
+/// A Clash rule provider of the `classical` behavior.
+pub const RESOURCE_TYPE_CLASH_RULE_PROVIDER: &str = "clash-rule-provider";
+/// PEM encoded CA certificates.
+pub const RESOURCE_TYPE_CA_BUNDLE: &str = "ca-bundle";
 
 #[derive(Debug, Error)]
 pub enum ResourceError {
@@ -0,0 +1,191 @@
+use std::sync::Arc;
+
+use base64::prelude::*;
+use http::header::{
+    HeaderName, AUTHORIZATION, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, LOCATION,
+    USER_AGENT,
+};
+use http::uri::Scheme;
+use http::{Request, StatusCode, Uri};
+use hyper::{Body, Client as HyperClient};
+use thiserror::Error;
+
+use crate::flow::*;
+use crate::plugin::h2::{FlowAdapterConnector, TokioHyperExecutor};
+use crate::plugin::socket::SocketOutboundFactory;
+use crate::plugin::system_resolver::SystemResolver;
+use crate::plugin::tls::SslStreamFactory;
+
+const MAX_REDIRECTS: usize = 5;
+
+#[derive(Debug, Error)]
+pub enum FetchError {
+    #[error("invalid URL")]
+    InvalidUrl,
+    #[error("too many redirects")]
+    TooManyRedirects,
+    #[error("HTTP error")]
+    Http(#[from] hyper::Error),
+    #[error("unexpected HTTP status {0}")]
+    UnexpectedStatus(u16),
+}
+
+pub type FetchResult<T> = Result<T, FetchError>;
+
+pub enum FetchResponse {
+    /// The remote content has not changed since the conditions supplied in the request.
+    NotModified,
+    Ok {
+        etag: Option<String>,
+        last_modified: Option<String>,
+        body: Vec<u8>,
+    },
+}
+
+/// A minimal HTTP client for retrieving resources, built upon the same outbound stack used by
+/// plugins. Requests go directly through the system resolver and sockets.
+pub struct ResourceFetcher {
+    plain_client: HyperClient<FlowAdapterConnector, Body>,
+    tls_client: HyperClient<FlowAdapterConnector, Body>,
+    _resolver: Arc<dyn Resolver>,
+    _outbounds: [Arc<dyn StreamOutboundFactory>; 2],
+}
+
+impl ResourceFetcher {
+    /// Must be called within a Tokio runtime.
+    pub fn new() -> Self {
+        let resolver: Arc<dyn Resolver> = Arc::new(SystemResolver::new());
+        let socket: Arc<dyn StreamOutboundFactory> = Arc::new(SocketOutboundFactory {
+            resolver: Arc::downgrade(&resolver),
+            bind_addr_v4: None,
+            bind_addr_v6: None,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            dscp: None,
+            proxy_protocol: None,
+            udp_connected: false,
+        });
+        let tls: Arc<dyn StreamOutboundFactory> = Arc::new(SslStreamFactory::new(
+            Arc::downgrade(&socket),
+            vec![],
+            false,
+            None,
+            DEFAULT_HANDSHAKE_TIMEOUT,
+        ));
+        let build_client = |next: &Arc<dyn StreamOutboundFactory>| {
+            HyperClient::builder()
+                .executor(TokioHyperExecutor::new_current())
+                .build(FlowAdapterConnector {
+                    next: Arc::downgrade(next),
+                })
+        };
+        Self {
+            plain_client: build_client(&socket),
+            tls_client: build_client(&tls),
+            _resolver: resolver,
+            _outbounds: [socket, tls],
+        }
+    }
+
+    /// Send a GET request to `url`, following redirects. If `etag` or `last_modified` from a
+    /// previous response is supplied, [`FetchResponse::NotModified`] may be returned.
+    pub async fn get(
+        &self,
+        url: &str,
+        etag: Option<&str>,
+        last_modified: Option<&str>,
+    ) -> FetchResult<FetchResponse> {
+        self.get_with_basic_auth(url, etag, last_modified, None)
+            .await
+    }
+
+    /// Like [`Self::get`], but authenticates to the host of `url` with HTTP basic auth. The
+    /// credentials are not sent to other hosts the request is redirected to.
+    pub async fn get_with_basic_auth(
+        &self,
+        url: &str,
+        etag: Option<&str>,
+        last_modified: Option<&str>,
+        basic_auth: Option<(&str, &str)>,
+    ) -> FetchResult<FetchResponse> {
+        let mut url: Uri = url.parse().map_err(|_| FetchError::InvalidUrl)?;
+        let auth_host = url.authority().cloned();
+        let auth_header = basic_auth.map(|(user, pass)| {
+            format!(
+                "Basic {}",
+                BASE64_STANDARD.encode(format!("{}:{}", user, pass))
+            )
+        });
+        for _ in 0..=MAX_REDIRECTS {
+            let client = match url.scheme() {
+                Some(s) if *s == Scheme::HTTPS => &self.tls_client,
+                Some(s) if *s == Scheme::HTTP => &self.plain_client,
+                _ => return Err(FetchError::InvalidUrl),
+            };
+            let mut req = Request::get(url.clone())
+                .header(USER_AGENT, concat!("ytflow/", env!("CARGO_PKG_VERSION")));
+            if let Some(etag) = etag {
+                req = req.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = last_modified {
+                req = req.header(IF_MODIFIED_SINCE, last_modified);
+            }
+            if let Some(auth_header) = auth_header.as_ref() {
+                if url.authority() == auth_host.as_ref() {
+                    req = req.header(AUTHORIZATION, auth_header);
+                }
+            }
+            let req = req
+                .body(Body::empty())
+                .map_err(|_| FetchError::InvalidUrl)?;
+            let res = client.request(req).await?;
+            let status = res.status();
+            if status == StatusCode::NOT_MODIFIED {
+                return Ok(FetchResponse::NotModified);
+            }
+            if status.is_redirection() {
+                let location = res
+                    .headers()
+                    .get(LOCATION)
+                    .and_then(|l| l.to_str().ok())
+                    .ok_or(FetchError::UnexpectedStatus(status.as_u16()))?;
+                url = resolve_redirect(&url, location)?;
+                continue;
+            }
+            if !status.is_success() {
+                return Err(FetchError::UnexpectedStatus(status.as_u16()));
+            }
+            let header_str = |name: HeaderName| {
+                res.headers()
+                    .get(name)
+                    .and_then(|v| v.to_str().ok())
+                    .map(|v| v.to_string())
+            };
+            let etag = header_str(ETAG);
+            let last_modified = header_str(LAST_MODIFIED);
+            let body = hyper::body::to_bytes(res.into_body()).await?.to_vec();
+            return Ok(FetchResponse::Ok {
+                etag,
+                last_modified,
+                body,
+            });
+        }
+        Err(FetchError::TooManyRedirects)
+    }
+}
+
+impl Default for ResourceFetcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn resolve_redirect(base: &Uri, location: &str) -> FetchResult<Uri> {
+    let location: Uri = location.parse().map_err(|_| FetchError::InvalidUrl)?;
+    if location.scheme().is_some() {
+        return Ok(location);
+    }
+    let mut parts = location.into_parts();
+    parts.scheme = base.scheme().cloned();
+    parts.authority = base.authority().cloned();
+    Uri::from_parts(parts).map_err(|_| FetchError::InvalidUrl)
+}
@@ -0,0 +1,77 @@
+use std::io::Read;
+
+use flate2::read::GzDecoder;
+
+use super::{ResourceError, ResourceResult};
+
+pub const DEFAULT_EDITION_ID: &str = "GeoLite2-Country";
+
+/// The permalink to the latest release of a MaxMind database edition, e.g. `GeoLite2-Country`.
+/// Requests must be authenticated by HTTP basic auth with the account ID and license key.
+pub fn permalink_url(edition_id: &str) -> String {
+    format!(
+        "https://download.maxmind.com/geoip/databases/{}/download?suffix=tar.gz",
+        edition_id
+    )
+}
+
+/// Extract the `.mmdb` database from the tar.gz envelope MaxMind databases are distributed in.
+pub fn extract_mmdb(archive: &[u8]) -> ResourceResult<Vec<u8>> {
+    let mut archive = tar::Archive::new(GzDecoder::new(archive));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let is_mmdb = entry
+            .path()?
+            .extension()
+            .map_or(false, |ext| ext.eq_ignore_ascii_case("mmdb"));
+        if !is_mmdb || !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let mut mmdb = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut mmdb)?;
+        return Ok(mmdb);
+    }
+    Err(ResourceError::InvalidData)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+
+    use super::*;
+
+    fn build_archive(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::fast()));
+        for (path, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, path, *content).unwrap();
+        }
+        let mut encoder = builder.into_inner().unwrap();
+        encoder.flush().unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_extract_mmdb() {
+        let archive = build_archive(&[
+            ("GeoLite2-Country_20240220/COPYRIGHT.txt", b"copyright"),
+            ("GeoLite2-Country_20240220/GeoLite2-Country.mmdb", b"mmdb"),
+        ]);
+        assert_eq!(extract_mmdb(&archive).unwrap(), b"mmdb");
+    }
+
+    #[test]
+    fn test_extract_mmdb_missing() {
+        let archive = build_archive(&[("LICENSE.txt", b"license")]);
+        assert!(matches!(
+            extract_mmdb(&archive),
+            Err(ResourceError::InvalidData)
+        ));
+    }
+}